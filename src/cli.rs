@@ -0,0 +1,67 @@
+use crate::repository::Storage;
+use crate::services::note_import::{self, NoteFileFormat, ParseNotesError};
+
+const USAGE: &str = "usage: backend-rs import-notes <file.csv|file.yaml>";
+
+/// Run the command named by `args` and return the exit code of the process.
+pub async fn run(storage: &Storage, args: &[String]) -> i32 {
+    match args {
+        [command, path] if command == "import-notes" => import_notes(storage, path).await,
+        _ => {
+            eprintln!("{USAGE}");
+            2
+        }
+    }
+}
+
+/// Import the notes of a CSV or YAML file, picked by its extension. Fails when
+/// any row could not be imported, the other rows being imported all the same.
+async fn import_notes(storage: &Storage, path: &str) -> i32 {
+    let Some(format) = NoteFileFormat::from_path(path) else {
+        eprintln!("{path}: the file should end with .csv, .yaml or .yml");
+        return 2;
+    };
+    let text = match tokio::fs::read_to_string(path).await {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return 1;
+        }
+    };
+    let rows = match note_import::parse_rows(format, &text) {
+        Ok(rows) => rows,
+        Err(ParseNotesError::InvalidFileError(message)) => {
+            eprintln!("{path}: {message}");
+            return 1;
+        }
+    };
+    let report = match note_import::import_notes(storage, rows).await {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{path}: the import failed: {err:?}");
+            return 1;
+        }
+    };
+
+    for title in &report.created_sections {
+        println!("created section {title}");
+    }
+    for title in &report.created_subsections {
+        println!("created subsection {title}");
+    }
+    println!(
+        "{} rows: {} notes created, {} updated, {} failed",
+        report.rows,
+        report.created_notes,
+        report.updated_notes,
+        report.errors.len()
+    );
+    for error in &report.errors {
+        eprintln!("{path}:{}: {}", error.line, error.message);
+    }
+    if report.errors.is_empty() {
+        0
+    } else {
+        1
+    }
+}
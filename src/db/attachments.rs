@@ -0,0 +1,418 @@
+use crate::db::lecture_notes::touch_notes;
+use crate::db::query::{Assignments, BindValues, Clauses, Filter, OrderBy, Value};
+use crate::db::OrAnd;
+use crate::error::Error;
+use loggit::{trace, warn};
+
+/// The form used to attach a new file or link to a note.
+#[derive(Default)]
+pub struct CreateAttachmentForm {
+    pub note_id: u32,
+    pub label: String,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    /// The uploaded file this attachment points to, if any.
+    pub file_id: Option<u32>,
+}
+
+/// The attachment record as stored in the database.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct AttachmentFromDb {
+    pub id: u32,
+    pub note_id: u32,
+    pub label: String,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub file_id: Option<u32>,
+    pub position: u32,
+}
+
+/// Get the maximum attachment position of a note.
+/// If the note has no attachment, returns None.
+pub async fn get_max_attachment_position_in_note(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    note_id: u32,
+) -> Option<u32> {
+    let query_str = "SELECT MAX(position) FROM note_attachments WHERE note_id = ?";
+    let query = sqlx::query_scalar(query_str).bind(note_id);
+    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
+    max.unwrap_or(None)
+}
+
+/// Struct to filter/select attachments.
+#[derive(Clone, Debug, Default)]
+pub struct GetAttachmentsForm {
+    pub id: Option<u32>,
+    pub note_id: Option<u32>,
+    /// Matches the attachments of any of these notes.
+    pub note_ids: Option<Vec<u32>>,
+    pub or_and: OrAnd,
+    /// Further conditions on the columns of the `note_attachments` table.
+    pub filter: Filter,
+    /// Sorted by note and position when empty.
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl GetAttachmentsForm {
+    pub(crate) fn to_clauses(&self) -> Clauses {
+        let fields = [
+            self.id.map(|id| Filter::eq("id", id)),
+            self.note_id.map(|note_id| Filter::eq("note_id", note_id)),
+            self.note_ids
+                .as_ref()
+                .map(|note_ids| Filter::is_in("note_id", note_ids.iter().copied())),
+        ];
+        let order_by = if self.order_by.is_empty() {
+            vec![OrderBy::asc("note_id"), OrderBy::asc("position")]
+        } else {
+            self.order_by.clone()
+        };
+        Clauses {
+            filter: Filter::combine(fields.into_iter().flatten().collect(), &self.or_and)
+                .and(self.filter.clone()),
+            order_by,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Errors that might occur when fetching attachments.
+#[derive(Debug)]
+pub enum GetAttachmentsError {
+    UnexpectedError(Error),
+}
+
+/// Fetch attachments based on the filtering form, ordered by note and position.
+pub async fn get_attachments(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetAttachmentsForm,
+) -> Result<Vec<AttachmentFromDb>, GetAttachmentsError> {
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!(
+        "SELECT id, note_id, label, url, mime_type, size, file_id, position \
+         FROM note_attachments{}",
+        clauses
+    );
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, AttachmentFromDb>(&query_str).bind_values(params);
+    query.fetch_all(pool).await.map_err(|err| {
+        warn!("{:?}", err);
+        GetAttachmentsError::UnexpectedError(err.into())
+    })
+}
+
+/// Error type when trying to get a single attachment.
+#[derive(Debug)]
+pub enum GetAttachmentError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+/// Get a single attachment (using LIMIT 1) based on the filtering form.
+pub async fn get_attachment(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetAttachmentsForm,
+) -> Result<AttachmentFromDb, GetAttachmentError> {
+    let attachments = get_attachments(
+        pool,
+        GetAttachmentsForm {
+            limit: Some(1),
+            ..form
+        },
+    )
+    .await;
+    match attachments {
+        Ok(mut list) => {
+            if list.is_empty() {
+                Err(GetAttachmentError::NotFoundError)
+            } else {
+                Ok(list.swap_remove(0))
+            }
+        }
+        Err(GetAttachmentsError::UnexpectedError(err)) => {
+            Err(GetAttachmentError::UnexpectedError(err))
+        }
+    }
+}
+
+/// The notes holding `attachments`.
+fn notes_of(attachments: &[AttachmentFromDb]) -> Filter {
+    Filter::is_in(
+        "id",
+        attachments.iter().map(|attachment| attachment.note_id),
+    )
+}
+
+/// Errors that might occur when creating an attachment.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CreateAttachmentError {
+    UnexpectedError(Error),
+    NoteNotFoundError,
+}
+
+/// Attach a file or link to a note, after the existing attachments.
+/// Returns the id of the created attachment.
+pub async fn create_attachment(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    attachment_form: CreateAttachmentForm,
+) -> Result<u32, CreateAttachmentError> {
+    let note = crate::db::lecture_notes::get_note(
+        pool,
+        crate::db::lecture_notes::GetNotesForm {
+            id: Some(attachment_form.note_id),
+            ..Default::default()
+        },
+    )
+    .await;
+    match note {
+        Ok(_) => {}
+        Err(crate::db::lecture_notes::GetNoteError::NotFoundError) => {
+            return Err(CreateAttachmentError::NoteNotFoundError)
+        }
+        Err(crate::db::lecture_notes::GetNoteError::UnexpectedError(err)) => {
+            return Err(CreateAttachmentError::UnexpectedError(err))
+        }
+    }
+
+    let next_pos = match get_max_attachment_position_in_note(pool, attachment_form.note_id).await {
+        Some(num) => num + 1,
+        None => 0,
+    };
+
+    let note_id = attachment_form.note_id;
+    let now = crate::db::now();
+    let res = sqlx::query(
+        "INSERT INTO note_attachments \
+         (note_id, label, url, mime_type, size, file_id, position, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(note_id)
+    .bind(attachment_form.label)
+    .bind(attachment_form.url)
+    .bind(attachment_form.mime_type)
+    .bind(attachment_form.size)
+    .bind(attachment_form.file_id)
+    .bind(next_pos)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await;
+    trace!("{:?}", res);
+    let res = match res {
+        Ok(done) => touch_notes(pool, Filter::eq("id", note_id))
+            .await
+            .map(|_| done),
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(done) => Ok(done.last_insert_id() as u32),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(CreateAttachmentError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// The form used to update one or more fields of an attachment.
+#[derive(Default)]
+pub struct UpdateAttachmentForm {
+    pub label: Option<String>,
+    pub url: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub position: Option<u32>,
+}
+
+impl UpdateAttachmentForm {
+    pub fn is_all_none(&self) -> bool {
+        self.label.is_none()
+            && self.url.is_none()
+            && self.mime_type.is_none()
+            && self.size.is_none()
+            && self.position.is_none()
+    }
+}
+
+/// Errors that might occur when updating attachments.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UpdateAttachmentsError {
+    UnexpectedError(Error),
+    NotFoundError,
+    NothingToUpdateError,
+}
+
+/// Update the attachments matched by `identified_by`.
+pub async fn update_attachments(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    attachment_form: UpdateAttachmentForm,
+    identified_by: GetAttachmentsForm,
+) -> Result<(), UpdateAttachmentsError> {
+    if attachment_form.is_all_none() {
+        return Err(UpdateAttachmentsError::NothingToUpdateError);
+    }
+    let attachments = get_attachments(pool, identified_by).await.map_err(
+        |GetAttachmentsError::UnexpectedError(err)| UpdateAttachmentsError::UnexpectedError(err),
+    )?;
+    if attachments.is_empty() {
+        return Err(UpdateAttachmentsError::NotFoundError);
+    }
+
+    let mut assignments = Assignments::default();
+    assignments.set_some("label", attachment_form.label);
+    assignments.set_some("url", attachment_form.url);
+    assignments.set_some("mime_type", attachment_form.mime_type);
+    assignments.set_some("size", attachment_form.size);
+    assignments.set_some("position", attachment_form.position);
+    assignments.set("updated_at", crate::db::now());
+
+    let mut params: Vec<Value> = Vec::new();
+    let set = assignments.to_sql(&mut params);
+    let filter = Filter::is_in("id", attachments.iter().map(|attachment| attachment.id));
+    let query_str = format!(
+        "UPDATE note_attachments SET {} WHERE {}",
+        set,
+        filter.to_sql(&mut params)
+    );
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
+
+    let res = match query.execute(pool).await {
+        Ok(_) => touch_notes(pool, notes_of(&attachments)).await,
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("{:?}", e);
+            Err(UpdateAttachmentsError::UnexpectedError(e.into()))
+        }
+    }
+}
+
+/// Error types for swapping two attachments.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwapAttachmentsError {
+    NotFoundError((Option<u32>, Option<u32>)),
+    UnexpectedError(Error),
+    CantSwapFromDifferentNotes,
+}
+
+/// Swap the positions of two attachments of the same note.
+pub async fn swap_attachments(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    ids: [u32; 2],
+) -> Result<(), SwapAttachmentsError> {
+    let attachment_1 = get_attachment(
+        pool,
+        GetAttachmentsForm {
+            id: Some(ids[0]),
+            ..Default::default()
+        },
+    )
+    .await;
+    let attachment_2 = get_attachment(
+        pool,
+        GetAttachmentsForm {
+            id: Some(ids[1]),
+            ..Default::default()
+        },
+    )
+    .await;
+    let mut ret_err_nf = (None, None);
+    if attachment_1.is_err() {
+        ret_err_nf.0 = Some(ids[0]);
+    }
+    if attachment_2.is_err() {
+        ret_err_nf.1 = Some(ids[1]);
+    }
+    if ret_err_nf.0.is_some() || ret_err_nf.1.is_some() {
+        return Err(SwapAttachmentsError::NotFoundError(ret_err_nf));
+    }
+    let attachment_1 = attachment_1.unwrap();
+    let attachment_2 = attachment_2.unwrap();
+    if attachment_1.note_id != attachment_2.note_id {
+        return Err(SwapAttachmentsError::CantSwapFromDifferentNotes);
+    }
+
+    let max_pos = get_max_attachment_position_in_note(pool, attachment_1.note_id)
+        .await
+        .unwrap_or(0)
+        + 1;
+    let now = crate::db::now();
+    let update_query = "UPDATE note_attachments SET position = ?, updated_at = ? WHERE id = ?";
+    let res: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        for (position, id) in [
+            (max_pos, attachment_1.id),
+            (attachment_1.position, attachment_2.id),
+            (attachment_2.position, attachment_1.id),
+        ] {
+            sqlx::query(update_query)
+                .bind(position)
+                .bind(now)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        touch_notes(pool, Filter::eq("id", attachment_1.note_id)).await
+    }
+    .await;
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        SwapAttachmentsError::UnexpectedError(err.into())
+    })
+}
+
+/// Error type for deleting attachments.
+#[derive(Debug)]
+pub enum DeleteAttachmentsError {
+    UnexpectedError(Error),
+}
+
+/// Delete attachments based on a filtering form.
+pub async fn delete_attachments(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetAttachmentsForm,
+) -> Result<(), DeleteAttachmentsError> {
+    let attachments = get_attachments(pool, form).await.map_err(
+        |GetAttachmentsError::UnexpectedError(err)| DeleteAttachmentsError::UnexpectedError(err),
+    )?;
+    if attachments.is_empty() {
+        return Ok(());
+    }
+
+    let mut params: Vec<Value> = Vec::new();
+    let filter = Filter::is_in("id", attachments.iter().map(|attachment| attachment.id));
+    let query_str = format!(
+        "DELETE FROM note_attachments WHERE {}",
+        filter.to_sql(&mut params)
+    );
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
+    let res = match query.execute(pool).await {
+        Ok(_) => touch_notes(pool, notes_of(&attachments)).await,
+        Err(err) => Err(err),
+    };
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        DeleteAttachmentsError::UnexpectedError(err.into())
+    })
+}
+
+/// Delete a single attachment (using LIMIT 1).
+pub async fn delete_attachment(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetAttachmentsForm,
+) -> Result<(), DeleteAttachmentsError> {
+    let new_form = GetAttachmentsForm {
+        limit: Some(1),
+        ..form
+    };
+    delete_attachments(pool, new_form).await
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+
+use crate::db::lecture_notes::{authors_to_json, NoteMetadata};
+use crate::db::query::{BindValues, Filter, Value};
+use crate::db::slugs::previous_slugs_to_json;
+use crate::error::Error;
+use loggit::{trace, warn};
+use sqlx::Row;
+
+/// Added to the positions of the rows the changes move, so that their final
+/// positions are free whatever order the rows are written in.
+pub(crate) const PARKED_POSITION_OFFSET: u32 = 1_000_000;
+
+/// A node a change hangs from or writes: one already stored, or the one created
+/// by the n-th `CreateNode` change of the same list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeRef {
+    Stored(u32),
+    Planned(usize),
+}
+
+/// A note a change writes: one already stored, or the one created by the n-th
+/// `CreateNote` change of the same list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteRef {
+    Stored(u32),
+    Planned(usize),
+}
+
+/// Every stored field of a node written by an import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedNode {
+    /// None for the root nodes.
+    pub parent: Option<NodeRef>,
+    pub kind: String,
+    pub title: String,
+    pub position: u32,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    pub slug: String,
+    pub previous_slugs: Vec<String>,
+}
+
+/// An attachment written by an import. The uploaded file it links to, if any, is
+/// found by content hash, as the file ids differ from one database to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedAttachment {
+    pub label: String,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub file_sha256: Option<String>,
+}
+
+/// Every stored field of a note written by an import, its tags and attachments
+/// included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedNote {
+    pub node: NodeRef,
+    pub name: String,
+    pub description: String,
+    pub url: String,
+    pub position: u32,
+    pub metadata: NoteMetadata,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    pub slug: String,
+    pub previous_slugs: Vec<String>,
+    pub tags: Vec<String>,
+    pub attachments: Vec<ImportedAttachment>,
+}
+
+/// One change made by an import or a batch. The updates and the deletions name
+/// the version the row holds before them, as left by the earlier changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogChange {
+    CreateNode(ImportedNode),
+    /// Moves the node under `parent` too.
+    UpdateNode(NodeRef, u32, ImportedNode),
+    /// The node has no children nor notes left by the earlier changes.
+    DeleteNode(NodeRef, u32),
+    CreateNote(ImportedNote),
+    /// Moves the note to `node` too.
+    UpdateNote(NoteRef, u32, ImportedNote),
+    DeleteNote(NoteRef, u32),
+}
+
+/// Error type for applying the changes of an import.
+#[derive(Debug)]
+pub enum ApplyCatalogChangesError {
+    UnexpectedError(Error),
+    /// The row written by the change at the index is missing or moved past the
+    /// version it names.
+    VersionConflictError(usize),
+}
+
+/// A stored row written by the changes, locked before any of them is made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LockedRow {
+    pub id: u32,
+    /// The version named by the first change writing the row.
+    pub version: u32,
+    /// The index of that change.
+    pub change: usize,
+}
+
+/// The stored nodes and notes written by the changes, keyed by table.
+pub(crate) fn locked_rows(changes: &[CatalogChange]) -> [(&'static str, Vec<LockedRow>); 2] {
+    let mut nodes: Vec<LockedRow> = Vec::new();
+    let mut notes: Vec<LockedRow> = Vec::new();
+    for (change, catalog_change) in changes.iter().enumerate() {
+        let (rows, id, version) = match catalog_change {
+            CatalogChange::UpdateNode(NodeRef::Stored(id), version, _)
+            | CatalogChange::DeleteNode(NodeRef::Stored(id), version) => {
+                (&mut nodes, *id, *version)
+            }
+            CatalogChange::UpdateNote(NoteRef::Stored(id), version, _)
+            | CatalogChange::DeleteNote(NoteRef::Stored(id), version) => {
+                (&mut notes, *id, *version)
+            }
+            _ => continue,
+        };
+        if !rows.iter().any(|row| row.id == id) {
+            rows.push(LockedRow {
+                id,
+                version,
+                change,
+            });
+        }
+    }
+    [("nodes", nodes), ("notes", notes)]
+}
+
+/// The first change writing a locked row that is missing from `stored` (the
+/// versions found, keyed by id) or held at another version.
+pub(crate) fn first_conflict(locked: &[LockedRow], stored: &HashMap<u32, u32>) -> Option<usize> {
+    locked
+        .iter()
+        .filter(|row| stored.get(&row.id) != Some(&row.version))
+        .map(|row| row.change)
+        .min()
+}
+
+/// Apply the changes of an import or a batch in one transaction: either all of
+/// them are made, or none. The changes run in order, each one seeing the rows
+/// left by the earlier ones, so a `CreateNode` comes before the changes hanging
+/// from it. The stored rows written are locked first, and every update and
+/// deletion checks the version it names. Returns the id of the row written by
+/// each change.
+pub async fn apply_catalog_changes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    changes: &[CatalogChange],
+) -> Result<Vec<u32>, ApplyCatalogChangesError> {
+    let res: Result<Result<Vec<u32>, usize>, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        let now = crate::db::now();
+
+        for (table, rows) in locked_rows(changes) {
+            if rows.is_empty() {
+                continue;
+            }
+            let mut params: Vec<Value> = Vec::new();
+            let filter = Filter::is_in("id", rows.iter().map(|row| row.id));
+            let query_str = format!(
+                "SELECT id, version FROM {table} WHERE {} FOR UPDATE",
+                filter.to_sql(&mut params)
+            );
+            trace!("{}", query_str);
+            let stored = sqlx::query(&query_str)
+                .bind_values(params)
+                .fetch_all(&mut *tx)
+                .await?
+                .iter()
+                .map(|row| Ok((row.try_get("id")?, row.try_get("version")?)))
+                .collect::<Result<HashMap<u32, u32>, sqlx::Error>>()?;
+            if let Some(change) = first_conflict(&rows, &stored) {
+                return Ok(Err(change));
+            }
+        }
+
+        for (table, ids) in [
+            (
+                "nodes",
+                changes
+                    .iter()
+                    .filter_map(|change| match change {
+                        CatalogChange::UpdateNode(NodeRef::Stored(id), _, _) => Some(*id),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "notes",
+                changes
+                    .iter()
+                    .filter_map(|change| match change {
+                        CatalogChange::UpdateNote(NoteRef::Stored(id), _, _) => Some(*id),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+        ] {
+            if ids.is_empty() {
+                continue;
+            }
+            let mut params: Vec<Value> = vec![Value::from(PARKED_POSITION_OFFSET)];
+            let filter = Filter::is_in("id", ids);
+            let query_str = format!(
+                "UPDATE {table} SET position = position + ? WHERE {}",
+                filter.to_sql(&mut params)
+            );
+            trace!("{}", query_str);
+            sqlx::query(&query_str)
+                .bind_values(params)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut written = Vec::with_capacity(changes.len());
+        let mut created_nodes: Vec<u32> = Vec::new();
+        let mut created_notes: Vec<u32> = Vec::new();
+        let node_id = |node: NodeRef, created_nodes: &[u32]| match node {
+            NodeRef::Stored(id) => Ok(id),
+            NodeRef::Planned(index) => created_nodes
+                .get(index)
+                .copied()
+                .ok_or(sqlx::Error::RowNotFound),
+        };
+        let note_id = |note: NoteRef, created_notes: &[u32]| match note {
+            NoteRef::Stored(id) => Ok(id),
+            NoteRef::Planned(index) => created_notes
+                .get(index)
+                .copied()
+                .ok_or(sqlx::Error::RowNotFound),
+        };
+        for (index, change) in changes.iter().enumerate() {
+            let id = match change {
+                CatalogChange::CreateNode(node) => {
+                    let parent_id = node
+                        .parent
+                        .map(|parent| node_id(parent, &created_nodes))
+                        .transpose()?;
+                    let done = sqlx::query(
+                        "INSERT INTO nodes (parent_id, kind, title, position, created_at, \
+                         updated_at, status, publish_at, slug, previous_slugs) \
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(parent_id)
+                    .bind(&node.kind)
+                    .bind(&node.title)
+                    .bind(node.position)
+                    .bind(now)
+                    .bind(now)
+                    .bind(&node.status)
+                    .bind(node.publish_at)
+                    .bind(&node.slug)
+                    .bind(previous_slugs_to_json(&node.previous_slugs))
+                    .execute(&mut *tx)
+                    .await?;
+                    let id = done.last_insert_id() as u32;
+                    created_nodes.push(id);
+                    id
+                }
+                CatalogChange::UpdateNode(node_ref, version, node) => {
+                    let id = node_id(*node_ref, &created_nodes)?;
+                    let parent_id = node
+                        .parent
+                        .map(|parent| node_id(parent, &created_nodes))
+                        .transpose()?;
+                    let done = sqlx::query(
+                        "UPDATE nodes SET parent_id = ?, kind = ?, title = ?, position = ?, \
+                         status = ?, publish_at = ?, slug = ?, previous_slugs = ?, \
+                         updated_at = ?, version = version + 1 WHERE id = ? AND version = ?",
+                    )
+                    .bind(parent_id)
+                    .bind(&node.kind)
+                    .bind(&node.title)
+                    .bind(node.position)
+                    .bind(&node.status)
+                    .bind(node.publish_at)
+                    .bind(&node.slug)
+                    .bind(previous_slugs_to_json(&node.previous_slugs))
+                    .bind(now)
+                    .bind(id)
+                    .bind(version)
+                    .execute(&mut *tx)
+                    .await?;
+                    if done.rows_affected() == 0 {
+                        return Ok(Err(index));
+                    }
+                    id
+                }
+                CatalogChange::DeleteNode(node_ref, version) => {
+                    let id = node_id(*node_ref, &created_nodes)?;
+                    let done = sqlx::query("DELETE FROM nodes WHERE id = ? AND version = ?")
+                        .bind(id)
+                        .bind(version)
+                        .execute(&mut *tx)
+                        .await?;
+                    if done.rows_affected() == 0 {
+                        return Ok(Err(index));
+                    }
+                    id
+                }
+                CatalogChange::CreateNote(note) => {
+                    let done = sqlx::query(
+                        "INSERT INTO notes (name, description, url, position, node_id, kind, \
+                         course_code, term, language, authors, license, created_at, updated_at, \
+                         status, publish_at, slug, previous_slugs) \
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(&note.name)
+                    .bind(&note.description)
+                    .bind(&note.url)
+                    .bind(note.position)
+                    .bind(node_id(note.node, &created_nodes)?)
+                    .bind(&note.metadata.kind)
+                    .bind(&note.metadata.course_code)
+                    .bind(&note.metadata.term)
+                    .bind(&note.metadata.language)
+                    .bind(authors_to_json(&note.metadata.authors))
+                    .bind(&note.metadata.license)
+                    .bind(now)
+                    .bind(now)
+                    .bind(&note.status)
+                    .bind(note.publish_at)
+                    .bind(&note.slug)
+                    .bind(previous_slugs_to_json(&note.previous_slugs))
+                    .execute(&mut *tx)
+                    .await?;
+                    let id = done.last_insert_id() as u32;
+                    replace_note_details(&mut tx, id, note, now).await?;
+                    created_notes.push(id);
+                    id
+                }
+                CatalogChange::UpdateNote(note_ref, version, note) => {
+                    let id = note_id(*note_ref, &created_notes)?;
+                    let done = sqlx::query(
+                        "UPDATE notes SET name = ?, description = ?, url = ?, position = ?, \
+                         node_id = ?, kind = ?, course_code = ?, term = ?, language = ?, \
+                         authors = ?, license = ?, status = ?, publish_at = ?, slug = ?, \
+                         previous_slugs = ?, updated_at = ?, version = version + 1 \
+                         WHERE id = ? AND version = ?",
+                    )
+                    .bind(&note.name)
+                    .bind(&note.description)
+                    .bind(&note.url)
+                    .bind(note.position)
+                    .bind(node_id(note.node, &created_nodes)?)
+                    .bind(&note.metadata.kind)
+                    .bind(&note.metadata.course_code)
+                    .bind(&note.metadata.term)
+                    .bind(&note.metadata.language)
+                    .bind(authors_to_json(&note.metadata.authors))
+                    .bind(&note.metadata.license)
+                    .bind(&note.status)
+                    .bind(note.publish_at)
+                    .bind(&note.slug)
+                    .bind(previous_slugs_to_json(&note.previous_slugs))
+                    .bind(now)
+                    .bind(id)
+                    .bind(version)
+                    .execute(&mut *tx)
+                    .await?;
+                    if done.rows_affected() == 0 {
+                        return Ok(Err(index));
+                    }
+                    replace_note_details(&mut tx, id, note, now).await?;
+                    id
+                }
+                CatalogChange::DeleteNote(note_ref, version) => {
+                    let id = note_id(*note_ref, &created_notes)?;
+                    let done = sqlx::query("DELETE FROM notes WHERE id = ? AND version = ?")
+                        .bind(id)
+                        .bind(version)
+                        .execute(&mut *tx)
+                        .await?;
+                    if done.rows_affected() == 0 {
+                        return Ok(Err(index));
+                    }
+                    id
+                }
+            };
+            written.push(id);
+        }
+        tx.commit().await?;
+        Ok(Ok(written))
+    }
+    .await;
+    match res {
+        Ok(Ok(written)) => Ok(written),
+        Ok(Err(change)) => Err(ApplyCatalogChangesError::VersionConflictError(change)),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(ApplyCatalogChangesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Replace the tags and the attachments of the note `note_id` by the imported
+/// ones. Missing tags are created. The attachments are only written again when
+/// they change, so that they keep their ids otherwise.
+async fn replace_note_details(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    note_id: u32,
+    note: &ImportedNote,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM note_tags WHERE note_id = ?")
+        .bind(note_id)
+        .execute(&mut **tx)
+        .await?;
+    for tag in &note.tags {
+        sqlx::query("INSERT IGNORE INTO tags (name, created_at, updated_at) VALUES (?, ?, ?)")
+            .bind(tag)
+            .bind(now)
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query(
+            "INSERT IGNORE INTO note_tags (note_id, tag_id) SELECT ?, id FROM tags WHERE name = ?",
+        )
+        .bind(note_id)
+        .bind(tag)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    let stored = sqlx::query(
+        "SELECT a.label, a.url, a.mime_type, a.size, f.sha256 FROM note_attachments a \
+         LEFT JOIN files f ON f.id = a.file_id WHERE a.note_id = ? ORDER BY a.position",
+    )
+    .bind(note_id)
+    .fetch_all(&mut **tx)
+    .await?
+    .iter()
+    .map(|row| {
+        Ok(ImportedAttachment {
+            label: row.try_get("label")?,
+            url: row.try_get("url")?,
+            mime_type: row.try_get("mime_type")?,
+            size: row.try_get("size")?,
+            file_sha256: row.try_get("sha256")?,
+        })
+    })
+    .collect::<Result<Vec<_>, sqlx::Error>>()?;
+    let unchanged = stored == note.attachments;
+    if unchanged {
+        return Ok(());
+    }
+    sqlx::query("DELETE FROM note_attachments WHERE note_id = ?")
+        .bind(note_id)
+        .execute(&mut **tx)
+        .await?;
+    for (position, attachment) in note.attachments.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO note_attachments (note_id, label, url, mime_type, size, file_id, \
+             position, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, (SELECT id FROM files WHERE sha256 = ?), ?, ?, ?)",
+        )
+        .bind(note_id)
+        .bind(&attachment.label)
+        .bind(&attachment.url)
+        .bind(&attachment.mime_type)
+        .bind(attachment.size)
+        .bind(&attachment.file_sha256)
+        .bind(position as u32)
+        .bind(now)
+        .bind(now)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
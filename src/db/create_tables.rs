@@ -1,3 +1,5 @@
+use crate::db::slugs::{missing_slugs, SlugRow};
+
 async fn create_users_table(pool: &sqlx::Pool<sqlx::MySql>) {
     let query_str = "\
         CREATE TABLE IF NOT EXISTS users(\
@@ -23,31 +25,29 @@ async fn create_sessions_table(pool: &sqlx::Pool<sqlx::MySql>) {
     let _ = sqlx::query(query_str).execute(pool).await;
 }
 
-async fn create_sections_table(pool: &sqlx::Pool<sqlx::MySql>) {
+async fn create_nodes_table(pool: &sqlx::Pool<sqlx::MySql>) {
     let query_str = "\
-         CREATE TABLE IF NOT EXISTS sections (\
+         CREATE TABLE IF NOT EXISTS nodes (\
              id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,\
+             parent_id INT UNSIGNED,\
+             kind VARCHAR(32) NOT NULL,\
              title VARCHAR(255) NOT NULL,\
-             position INT UNSIGNED NOT NULL UNIQUE\
+             position INT UNSIGNED NOT NULL,\
+             created_at BIGINT NOT NULL DEFAULT 0,\
+             updated_at BIGINT NOT NULL DEFAULT 0,\
+             version INT UNSIGNED NOT NULL DEFAULT 1,\
+             status VARCHAR(16) NOT NULL DEFAULT 'published',\
+             publish_at BIGINT,\
+             slug VARCHAR(255) NOT NULL DEFAULT '',\
+             previous_slugs TEXT,\
+             parent_key INT UNSIGNED AS (COALESCE(parent_id, 0)) STORED,\
+             FOREIGN KEY (parent_id) REFERENCES nodes(id),\
+             UNIQUE (parent_key, position)\
          );\
         ";
     let _ = sqlx::query(query_str).execute(pool).await;
 }
 
-async fn create_subsections_table(pool: &sqlx::Pool<sqlx::MySql>) {
-    let query_str = "\
-                     CREATE TABLE IF NOT EXISTS subsections ( \
-                         id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY, \
-                         title VARCHAR(255) NOT NULL, \
-                         position INT UNSIGNED NOT NULL, \
-                         section_id INT UNSIGNED NOT NULL, \
-                         FOREIGN KEY (section_id) REFERENCES sections(id), \
-                         UNIQUE (position, section_id) \
-                     ); \
-        ";
-    let _ = sqlx::query(query_str).execute(pool).await;
-}
-
 async fn create_notes_table(pool: &sqlx::Pool<sqlx::MySql>) {
     let query_str = "\
         CREATE TABLE IF NOT EXISTS notes (\
@@ -56,16 +56,308 @@ async fn create_notes_table(pool: &sqlx::Pool<sqlx::MySql>) {
             description TEXT NOT NULL,\
             url TEXT NOT NULL,\
             position INT UNSIGNED,\
-            section_id INT UNSIGNED,\
-            subsection_id INT UNSIGNED,\
-            FOREIGN KEY (section_id) REFERENCES sections(id),\
-            FOREIGN KEY (subsection_id) REFERENCES subsections(id),\
-            UNIQUE (position, subsection_id)\
+            node_id INT UNSIGNED,\
+            kind VARCHAR(32),\
+            course_code VARCHAR(64),\
+            term VARCHAR(64),\
+            language VARCHAR(16),\
+            authors TEXT,\
+            license VARCHAR(255),\
+            created_at BIGINT NOT NULL DEFAULT 0,\
+            updated_at BIGINT NOT NULL DEFAULT 0,\
+            version INT UNSIGNED NOT NULL DEFAULT 1,\
+            status VARCHAR(16) NOT NULL DEFAULT 'published',\
+            publish_at BIGINT,\
+            slug VARCHAR(255) NOT NULL DEFAULT '',\
+            previous_slugs TEXT,\
+            FOREIGN KEY (node_id) REFERENCES nodes(id),\
+            UNIQUE (position, node_id)\
         );\
         ";
     let _ = sqlx::query(query_str).execute(pool).await;
 }
 
+async fn create_tags_table(pool: &sqlx::Pool<sqlx::MySql>) {
+    let query_str = "\
+        CREATE TABLE IF NOT EXISTS tags (\
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,\
+            name VARCHAR(255) NOT NULL UNIQUE,\
+            created_at BIGINT NOT NULL DEFAULT 0,\
+            updated_at BIGINT NOT NULL DEFAULT 0\
+        );\
+        ";
+    let _ = sqlx::query(query_str).execute(pool).await;
+}
+
+async fn create_note_tags_table(pool: &sqlx::Pool<sqlx::MySql>) {
+    let query_str = "\
+        CREATE TABLE IF NOT EXISTS note_tags (\
+            note_id INT UNSIGNED NOT NULL,\
+            tag_id INT UNSIGNED NOT NULL,\
+            PRIMARY KEY (note_id, tag_id),\
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE,\
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE\
+        );\
+        ";
+    let _ = sqlx::query(query_str).execute(pool).await;
+}
+
+async fn create_files_table(pool: &sqlx::Pool<sqlx::MySql>) {
+    let query_str = "\
+        CREATE TABLE IF NOT EXISTS files (\
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,\
+            sha256 CHAR(64) NOT NULL UNIQUE,\
+            original_name VARCHAR(255) NOT NULL,\
+            mime_type VARCHAR(127) NOT NULL,\
+            size BIGINT UNSIGNED NOT NULL,\
+            created_at BIGINT NOT NULL DEFAULT 0,\
+            updated_at BIGINT NOT NULL DEFAULT 0\
+        );\
+        ";
+    let _ = sqlx::query(query_str).execute(pool).await;
+}
+
+async fn create_note_attachments_table(pool: &sqlx::Pool<sqlx::MySql>) {
+    let query_str = "\
+        CREATE TABLE IF NOT EXISTS note_attachments (\
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,\
+            note_id INT UNSIGNED NOT NULL,\
+            label VARCHAR(255) NOT NULL,\
+            url TEXT NOT NULL,\
+            mime_type VARCHAR(127),\
+            size BIGINT UNSIGNED,\
+            file_id INT UNSIGNED,\
+            position INT UNSIGNED NOT NULL,\
+            created_at BIGINT NOT NULL DEFAULT 0,\
+            updated_at BIGINT NOT NULL DEFAULT 0,\
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE,\
+            FOREIGN KEY (file_id) REFERENCES files(id),\
+            UNIQUE (note_id, position)\
+        );\
+        ";
+    let _ = sqlx::query(query_str).execute(pool).await;
+}
+
+async fn table_exists(pool: &sqlx::Pool<sqlx::MySql>, table_name: &str) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query_scalar::<_, i64>(
+        "\
+        SELECT COUNT(*) FROM INFORMATION_SCHEMA.TABLES \
+        WHERE TABLE_SCHEMA = DATABASE() \
+          AND TABLE_NAME = ?\
+        ",
+    )
+    .bind(table_name);
+    let count = query.fetch_one(pool).await?;
+    Ok(count > 0)
+}
+
+async fn column_exists(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    table_name: &str,
+    column_name: &str,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query_scalar::<_, i64>(
+        "\
+        SELECT COUNT(*) FROM INFORMATION_SCHEMA.COLUMNS \
+        WHERE TABLE_SCHEMA = DATABASE() \
+          AND TABLE_NAME = ? \
+          AND COLUMN_NAME = ?\
+        ",
+    )
+    .bind(table_name)
+    .bind(column_name);
+    let count = query.fetch_one(pool).await?;
+    Ok(count > 0)
+}
+
+/// The columns of `notes` pointing to the legacy tables.
+const LEGACY_NOTE_COLUMNS: [&str; 2] = ["section_id", "subsection_id"];
+
+/// Move the content of the legacy `sections`/`subsections` tables into `nodes`
+/// and attach every note to its node. The sections keep their ids and the
+/// subsections follow them, offset by the largest section id, so the old links
+/// still lead to the same content. Every step can run again after a failure, and
+/// the legacy tables are renamed by the last one, so the migration runs until it
+/// completes once. Returns true if something was migrated.
+pub async fn migrate_sections_to_nodes(pool: &sqlx::Pool<sqlx::MySql>) -> Result<bool, sqlx::Error> {
+    if !table_exists(pool, "sections").await? {
+        return Ok(false);
+    }
+
+    if !column_exists(pool, "notes", "node_id").await? {
+        sqlx::query(
+            "ALTER TABLE notes ADD COLUMN node_id INT UNSIGNED, \
+             ADD FOREIGN KEY (node_id) REFERENCES nodes(id)",
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    // The nodes stay empty until the copy commits.
+    let copied = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM nodes")
+        .fetch_one(pool)
+        .await?
+        > 0;
+    if !copied && column_exists(pool, "notes", "section_id").await? {
+        copy_legacy_nodes(pool).await?;
+    }
+
+    drop_legacy_note_columns(pool).await?;
+
+    let unique_position = sqlx::query_scalar::<_, i64>(
+        "\
+        SELECT COUNT(*) FROM INFORMATION_SCHEMA.STATISTICS \
+        WHERE TABLE_SCHEMA = DATABASE() \
+          AND TABLE_NAME = 'notes' \
+          AND COLUMN_NAME = 'node_id' \
+          AND NON_UNIQUE = 0\
+        ",
+    )
+    .fetch_one(pool)
+    .await?
+        > 0;
+    if !unique_position {
+        normalize_note_positions(pool).await?;
+        sqlx::query("ALTER TABLE notes ADD UNIQUE (position, node_id)")
+            .execute(pool)
+            .await?;
+    }
+
+    sqlx::query(
+        "RENAME TABLE sections TO legacy_sections, subsections TO legacy_subsections",
+    )
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+/// Insert a node per legacy section and subsection, numbering the siblings from
+/// 0 in their order, and point every note to the node it was in.
+async fn copy_legacy_nodes(pool: &sqlx::Pool<sqlx::MySql>) -> Result<(), sqlx::Error> {
+    let now = crate::db::now();
+    let mut tx = pool.begin().await?;
+    let sections = sqlx::query_as::<_, (u32, String)>(
+        "SELECT id, title FROM sections ORDER BY position, id",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let insert_query = "INSERT INTO nodes \
+                        (id, parent_id, kind, title, position, created_at, updated_at) \
+                        VALUES (?, ?, ?, ?, ?, ?, ?)";
+    for (position, (id, title)) in sections.iter().enumerate() {
+        sqlx::query(insert_query)
+            .bind(id)
+            .bind(None::<u32>)
+            .bind(crate::db::sections::SECTION_KIND)
+            .bind(title)
+            .bind(position as u32)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE notes SET node_id = ? WHERE subsection_id IS NULL AND section_id = ?")
+            .bind(id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let offset = sections.iter().map(|(id, _)| *id).max().unwrap_or_default();
+    let subsections = sqlx::query_as::<_, (u32, String, u32)>(
+        "SELECT id, title, section_id FROM subsections ORDER BY section_id, position, id",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut positions = std::collections::HashMap::new();
+    for (id, title, section_id) in subsections {
+        let node_id = offset + id;
+        let position = positions.entry(section_id).or_insert(0u32);
+        sqlx::query(insert_query)
+            .bind(node_id)
+            .bind(section_id)
+            .bind(crate::db::subsections::SUBSECTION_KIND)
+            .bind(title)
+            .bind(*position)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        *position += 1;
+        sqlx::query("UPDATE notes SET node_id = ? WHERE subsection_id = ?")
+            .bind(node_id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Drop the foreign keys and the indexes naming the legacy columns of `notes`,
+/// then the columns. Dropping the columns alone would leave the old
+/// `UNIQUE (position, subsection_id)` as a unique position over all notes.
+async fn drop_legacy_note_columns(pool: &sqlx::Pool<sqlx::MySql>) -> Result<(), sqlx::Error> {
+    let foreign_keys = sqlx::query_scalar::<_, String>(
+        "\
+        SELECT DISTINCT CONSTRAINT_NAME FROM INFORMATION_SCHEMA.KEY_COLUMN_USAGE \
+        WHERE TABLE_SCHEMA = DATABASE() \
+          AND TABLE_NAME = 'notes' \
+          AND COLUMN_NAME IN ('section_id', 'subsection_id') \
+          AND REFERENCED_TABLE_NAME IS NOT NULL\
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+    for name in foreign_keys {
+        let query_str = format!("ALTER TABLE notes DROP FOREIGN KEY `{}`", name);
+        sqlx::query(query_str.as_str()).execute(pool).await?;
+    }
+
+    let indexes = sqlx::query_scalar::<_, String>(
+        "\
+        SELECT DISTINCT INDEX_NAME FROM INFORMATION_SCHEMA.STATISTICS \
+        WHERE TABLE_SCHEMA = DATABASE() \
+          AND TABLE_NAME = 'notes' \
+          AND COLUMN_NAME IN ('section_id', 'subsection_id')\
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+    for name in indexes {
+        let query_str = format!("ALTER TABLE notes DROP INDEX `{}`", name);
+        sqlx::query(query_str.as_str()).execute(pool).await?;
+    }
+
+    for column in LEGACY_NOTE_COLUMNS {
+        if column_exists(pool, "notes", column).await? {
+            let query_str = format!("ALTER TABLE notes DROP COLUMN {}", column);
+            sqlx::query(query_str.as_str()).execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Number the notes of every node from 0 in their order, the legacy ones
+/// directly in a section sharing positions with the others.
+async fn normalize_note_positions(pool: &sqlx::Pool<sqlx::MySql>) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let notes = sqlx::query_as::<_, (u32, u32)>(
+        "SELECT id, node_id FROM notes WHERE node_id IS NOT NULL ORDER BY node_id, position, id",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut positions = std::collections::HashMap::new();
+    for (id, node_id) in notes {
+        let position = positions.entry(node_id).or_insert(0u32);
+        sqlx::query("UPDATE notes SET position = ? WHERE id = ?")
+            .bind(*position)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        *position += 1;
+    }
+    tx.commit().await
+}
+
 pub async fn notes_description_column_exists(
     pool: &sqlx::Pool<sqlx::MySql>,
 ) -> Result<bool, sqlx::Error> {
@@ -101,22 +393,214 @@ pub async fn ensure_notes_description_column_exists(
     ))
 }
 
+/// Metadata columns added to `notes` after the table was first released.
+const NOTES_METADATA_COLUMNS: [(&str, &str); 6] = [
+    ("kind", "VARCHAR(32)"),
+    ("course_code", "VARCHAR(64)"),
+    ("term", "VARCHAR(64)"),
+    ("language", "VARCHAR(16)"),
+    ("authors", "TEXT"),
+    ("license", "VARCHAR(255)"),
+];
+
+/// Add the note metadata columns missing from an existing `notes` table.
+/// Returns true if at least one column was added.
+pub async fn ensure_notes_metadata_columns_exist(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<bool, sqlx::Error> {
+    let mut added = false;
+    for (column, column_type) in NOTES_METADATA_COLUMNS {
+        if column_exists(pool, "notes", column).await? {
+            continue;
+        }
+        let query_str = format!("ALTER TABLE notes ADD COLUMN {} {}", column, column_type);
+        sqlx::query(query_str.as_str()).execute(pool).await?;
+        added = true;
+    }
+    Ok(added)
+}
+
+/// The content tables, which carry `created_at` and `updated_at` columns.
+pub(crate) const TIMESTAMPED_TABLES: [&str; 5] =
+    ["nodes", "notes", "tags", "files", "note_attachments"];
+
+/// Add the `created_at` and `updated_at` columns missing from the content tables.
+/// The rows already there get the current time, as their real dates are unknown.
+/// Returns true if at least one column was added.
+pub async fn ensure_timestamp_columns_exist(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<bool, sqlx::Error> {
+    let mut added = false;
+    for table in TIMESTAMPED_TABLES {
+        if !table_exists(pool, table).await? {
+            continue;
+        }
+        for column in ["created_at", "updated_at"] {
+            if column_exists(pool, table, column).await? {
+                continue;
+            }
+            let query_str = format!(
+                "ALTER TABLE {} ADD COLUMN {} BIGINT NOT NULL DEFAULT 0",
+                table, column
+            );
+            sqlx::query(query_str.as_str()).execute(pool).await?;
+            let query_str = format!("UPDATE {} SET {} = ?", table, column);
+            sqlx::query(query_str.as_str())
+                .bind(crate::db::now())
+                .execute(pool)
+                .await?;
+            added = true;
+        }
+    }
+    Ok(added)
+}
+
+/// The tables whose rows carry a `version` counter for optimistic concurrency.
+pub(crate) const VERSIONED_TABLES: [&str; 2] = ["nodes", "notes"];
+
+/// Add the `version` column missing from the versioned tables.
+/// Returns true if at least one column was added.
+pub async fn ensure_version_columns_exist(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<bool, sqlx::Error> {
+    let mut added = false;
+    for table in VERSIONED_TABLES {
+        if !table_exists(pool, table).await? || column_exists(pool, table, "version").await? {
+            continue;
+        }
+        let query_str = format!(
+            "ALTER TABLE {} ADD COLUMN version INT UNSIGNED NOT NULL DEFAULT 1",
+            table
+        );
+        sqlx::query(query_str.as_str()).execute(pool).await?;
+        added = true;
+    }
+    Ok(added)
+}
+
+/// The columns telling when the content of `VERSIONED_TABLES` is shown to the public.
+const PUBLICATION_COLUMNS: [(&str, &str); 2] = [
+    ("status", "VARCHAR(16) NOT NULL DEFAULT 'published'"),
+    ("publish_at", "BIGINT"),
+];
+
+/// Add the `status` and `publish_at` columns missing from the content tables.
+/// The rows already there stay published.
+/// Returns true if at least one column was added.
+pub async fn ensure_publication_columns_exist(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<bool, sqlx::Error> {
+    let mut added = false;
+    for table in VERSIONED_TABLES {
+        if !table_exists(pool, table).await? {
+            continue;
+        }
+        for (column, column_type) in PUBLICATION_COLUMNS {
+            if column_exists(pool, table, column).await? {
+                continue;
+            }
+            let query_str = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type);
+            sqlx::query(query_str.as_str()).execute(pool).await?;
+            added = true;
+        }
+    }
+    Ok(added)
+}
+
+/// The columns giving stable page names to the content of `VERSIONED_TABLES`.
+const SLUG_COLUMNS: [(&str, &str); 2] = [
+    ("slug", "VARCHAR(255) NOT NULL DEFAULT ''"),
+    ("previous_slugs", "TEXT"),
+];
+
+/// Add the `slug` and `previous_slugs` columns missing from the content tables.
+/// The rows already there get their slugs from `fill_missing_slugs`.
+/// Returns true if at least one column was added.
+pub async fn ensure_slug_columns_exist(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<bool, sqlx::Error> {
+    let mut added = false;
+    for table in VERSIONED_TABLES {
+        if !table_exists(pool, table).await? {
+            continue;
+        }
+        for (column, column_type) in SLUG_COLUMNS {
+            if column_exists(pool, table, column).await? {
+                continue;
+            }
+            let query_str = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type);
+            sqlx::query(query_str.as_str()).execute(pool).await?;
+            added = true;
+        }
+    }
+    Ok(added)
+}
+
+/// Give a slug derived from their title to the nodes and notes without one,
+/// so that the pages generated before slugs were stored keep their names.
+/// Returns the number of rows updated.
+pub async fn fill_missing_slugs(pool: &sqlx::Pool<sqlx::MySql>) -> Result<usize, sqlx::Error> {
+    let queries = [
+        (
+            "nodes",
+            "SELECT id, parent_id, title, slug FROM nodes ORDER BY parent_key, position, id",
+        ),
+        (
+            "notes",
+            "SELECT id, node_id, name, slug FROM notes ORDER BY node_id, position, id",
+        ),
+    ];
+    let mut filled = 0;
+    for (table, query_str) in queries {
+        let rows: Vec<(u32, Option<u32>, String, String)> =
+            sqlx::query_as(query_str).fetch_all(pool).await?;
+        let rows: Vec<SlugRow> = rows
+            .into_iter()
+            .map(|(id, parent_id, title, slug)| SlugRow {
+                id,
+                parent_id,
+                title,
+                slug,
+            })
+            .collect();
+        let update_str = format!("UPDATE {} SET slug = ? WHERE id = ?", table);
+        for (id, slug) in missing_slugs(&rows) {
+            sqlx::query(update_str.as_str())
+                .bind(slug)
+                .bind(id)
+                .execute(pool)
+                .await?;
+            filled += 1;
+        }
+    }
+    Ok(filled)
+}
+
 pub async fn create_required_tables(pool: &sqlx::Pool<sqlx::MySql>) {
     create_users_table(pool).await;
     create_sessions_table(pool).await;
-    create_sections_table(pool).await;
-    create_subsections_table(pool).await;
+    create_nodes_table(pool).await;
     create_notes_table(pool).await;
+    create_tags_table(pool).await;
+    create_note_tags_table(pool).await;
+    create_files_table(pool).await;
+    create_note_attachments_table(pool).await;
 }
 pub async fn drop_all_tables(pool: &sqlx::Pool<sqlx::MySql>) {
-    let query_strs = [
-        "DROP TABLE notes;",
-        "DROP TABLE subsections;",
-        "DROP TABLE sections;",
-        "DROP TABLE sessions;",
-        "DROP TABLE users;",
+    let table_names = [
+        "note_attachments",
+        "files",
+        "note_tags",
+        "tags",
+        "notes",
+        "nodes",
+        "legacy_subsections",
+        "legacy_sections",
+        "subsections",
+        "sections",
+        "sessions",
+        "users",
     ];
-    let table_names = ["notes", "subsections", "sections", "sessions", "users"];
     for table_name in table_names {
         let query_str = format!("DROP TABLE IF EXISTS {} ;", table_name);
         let _ = sqlx::query(query_str.as_str()).execute(pool).await;
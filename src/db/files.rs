@@ -0,0 +1,215 @@
+use crate::db::query::{BindValues, Clauses, Filter, OrderBy};
+use crate::db::OrAnd;
+use crate::error::Error;
+use loggit::{trace, warn};
+
+/// The form used to register a stored file.
+pub struct CreateFileForm {
+    pub sha256: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub size: u64,
+}
+
+/// The uploaded file record as stored in the database.
+/// The content itself lives in the file storage, addressed by `sha256`.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct FileFromDb {
+    pub id: u32,
+    pub sha256: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub size: u64,
+}
+
+/// Struct to filter/select files.
+#[derive(Clone, Debug, Default)]
+pub struct GetFilesForm {
+    pub id: Option<u32>,
+    pub sha256: Option<String>,
+    /// Matches any of these files.
+    pub ids: Option<Vec<u32>>,
+    pub or_and: OrAnd,
+    /// Further conditions on the columns of the `files` table.
+    pub filter: Filter,
+    /// Sorted by id when empty.
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl GetFilesForm {
+    pub(crate) fn to_clauses(&self) -> Clauses {
+        let fields = [
+            self.id.map(|id| Filter::eq("id", id)),
+            self.sha256.clone().map(|sha256| Filter::eq("sha256", sha256)),
+            self.ids
+                .as_ref()
+                .map(|ids| Filter::is_in("id", ids.iter().copied())),
+        ];
+        let order_by = if self.order_by.is_empty() {
+            vec![OrderBy::asc("id")]
+        } else {
+            self.order_by.clone()
+        };
+        Clauses {
+            filter: Filter::combine(fields.into_iter().flatten().collect(), &self.or_and)
+                .and(self.filter.clone()),
+            order_by,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Errors that might occur when fetching files.
+#[derive(Debug)]
+pub enum GetFilesError {
+    UnexpectedError(Error),
+}
+
+/// Fetch files based on the filtering form, ordered by id unless told otherwise.
+pub async fn get_files(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetFilesForm,
+) -> Result<Vec<FileFromDb>, GetFilesError> {
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!(
+        "SELECT id, sha256, original_name, mime_type, size FROM files{}",
+        clauses
+    );
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, FileFromDb>(&query_str).bind_values(params);
+    query.fetch_all(pool).await.map_err(|err| {
+        warn!("{:?}", err);
+        GetFilesError::UnexpectedError(err.into())
+    })
+}
+
+/// Error type when trying to get a single file.
+#[derive(Debug)]
+pub enum GetFileError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+/// Get a single file (using LIMIT 1) based on the filtering form.
+pub async fn get_file(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetFilesForm,
+) -> Result<FileFromDb, GetFileError> {
+    let files = get_files(
+        pool,
+        GetFilesForm {
+            limit: Some(1),
+            ..form
+        },
+    )
+    .await;
+    match files {
+        Ok(mut list) => {
+            if list.is_empty() {
+                Err(GetFileError::NotFoundError)
+            } else {
+                Ok(list.swap_remove(0))
+            }
+        }
+        Err(GetFilesError::UnexpectedError(err)) => Err(GetFileError::UnexpectedError(err)),
+    }
+}
+
+/// Errors that might occur when registering a file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CreateFileError {
+    UnexpectedError(Error),
+}
+
+/// Register a stored file. Files are deduplicated by content: if a file with
+/// the same hash already exists, its id is returned and nothing is inserted.
+/// Returns the id of the file and whether it was newly created.
+pub async fn create_file(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    file_form: CreateFileForm,
+) -> Result<(u32, bool), CreateFileError> {
+    let now = crate::db::now();
+    let res = sqlx::query(
+        "INSERT IGNORE INTO files (sha256, original_name, mime_type, size, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&file_form.sha256)
+    .bind(file_form.original_name)
+    .bind(file_form.mime_type)
+    .bind(file_form.size)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await;
+    trace!("{:?}", res);
+    match res {
+        Ok(done) if done.rows_affected() > 0 => Ok((done.last_insert_id() as u32, true)),
+        Ok(_) => get_file(
+            pool,
+            GetFilesForm {
+                sha256: Some(file_form.sha256),
+                ..Default::default()
+            },
+        )
+        .await
+        .map(|file| (file.id, false))
+        .map_err(|err| match err {
+            GetFileError::UnexpectedError(err) => CreateFileError::UnexpectedError(err),
+            GetFileError::NotFoundError => {
+                CreateFileError::UnexpectedError(Error::internal("the file was deleted meanwhile"))
+            }
+        }),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(CreateFileError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Error type for deleting a file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeleteFileError {
+    UnexpectedError(Error),
+    NotFoundError,
+    /// The file is still attached to a note.
+    InUseError,
+}
+
+/// Delete a file record. Files that are still attached to a note are kept.
+/// Returns the deleted record, so that its content can be removed from the storage.
+pub async fn delete_file(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    id: u32,
+) -> Result<FileFromDb, DeleteFileError> {
+    let file = get_file(
+        pool,
+        GetFilesForm {
+            id: Some(id),
+            ..Default::default()
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        GetFileError::NotFoundError => DeleteFileError::NotFoundError,
+        GetFileError::UnexpectedError(err) => DeleteFileError::UnexpectedError(err),
+    })?;
+
+    let res = sqlx::query("DELETE FROM files WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await;
+    trace!("{:?}", res);
+    match res {
+        Ok(_) => Ok(file),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            Err(DeleteFileError::InUseError)
+        }
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(DeleteFileError::UnexpectedError(err.into()))
+        }
+    }
+}
@@ -1,25 +1,124 @@
-use crate::db::{OrAnd, VecWrapper};
+use crate::db::nodes::{published_filter, published_nodes_condition, PUBLISHED};
+use crate::db::tags::{notes_with_tags_condition, TagMatch};
+use crate::db::query::{Assignments, BindValues, Clauses, Filter, OrderBy, Value};
+use crate::db::slugs::{previous_slugs_from_json, previous_slugs_to_json, slugify};
+use crate::db::{found_pair, LookupError, OrAnd, PairLookupError};
+use crate::error::Error;
 use loggit::{trace, warn};
 
 /// The form used to create a new note.
+#[derive(Default)]
 pub struct CreateNoteForm {
     pub name: String,
     pub description: String,
     pub url: String,
-    pub section_id: Option<u32>,
-    pub subsection_id: Option<u32>,
+    pub node_id: Option<u32>,
+    pub metadata: NoteMetadata,
+    /// `PUBLISHED` when None.
+    pub status: Option<String>,
+    /// Seconds since the Unix epoch before which the note stays hidden.
+    pub publish_at: Option<i64>,
+    /// The name slugified when None, see `slugs::slugify`.
+    pub slug: Option<String>,
+}
+
+/// Structured metadata of a note. Every field is optional.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoteMetadata {
+    /// One of lecture, exercise, exam, solution or cheatsheet.
+    pub kind: Option<String>,
+    pub course_code: Option<String>,
+    /// Academic term, e.g. "2024 Fall".
+    pub term: Option<String>,
+    /// BCP 47 language tag, e.g. "en" or "fr".
+    pub language: Option<String>,
+    pub authors: Vec<String>,
+    pub license: Option<String>,
+}
+
+/// Encode the authors list as stored in the `authors` JSON column.
+pub(crate) fn authors_to_json(authors: &[String]) -> String {
+    serde_json::to_string(authors).unwrap_or_else(|_| "[]".to_string())
 }
 
 /// The note record as stored in the database.
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default)]
+/// `section_id` and `subsection_id` are derived from `node_id` when the note
+/// hangs from a first or second level node, and are None deeper in the tree.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
 pub struct NoteFromDb {
     pub id: u32,
     pub name: String,
     pub description: String,
     pub url: String,
     pub position: u32,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    pub kind: Option<String>,
+    pub course_code: Option<String>,
+    pub term: Option<String>,
+    pub language: Option<String>,
+    /// JSON array of author names.
+    pub authors: String,
+    pub license: Option<String>,
+    /// Seconds since the Unix epoch, see `db::now`.
+    pub created_at: i64,
+    /// Also moved forward when the tags or the attachments of the note change.
+    pub updated_at: i64,
+    /// Incremented by every update and move of the note itself.
+    pub version: u32,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    /// The URL segment of the note, unique among the notes of its node.
+    pub slug: String,
+    /// JSON array of the slugs the note had before, oldest first.
+    pub previous_slugs: String,
+}
+
+impl NoteFromDb {
+    /// Decode the `authors` JSON column.
+    pub fn author_list(&self) -> Vec<String> {
+        serde_json::from_str(&self.authors).unwrap_or_default()
+    }
+
+    /// Decode the `previous_slugs` JSON column.
+    pub fn previous_slug_list(&self) -> Vec<String> {
+        previous_slugs_from_json(&self.previous_slugs)
+    }
+}
+
+/// Notes joined with their parent nodes to expose the section/subsection view.
+pub(crate) const NOTES_VIEW: &str = "\
+    SELECT notes.id, notes.name, COALESCE(notes.description, '') AS description, notes.url, \
+        notes.position, notes.node_id, notes.kind, notes.course_code, notes.term, \
+        notes.language, COALESCE(notes.authors, '[]') AS authors, notes.license, \
+        notes.created_at, notes.updated_at, notes.version, notes.status, notes.publish_at, \
+        notes.slug, COALESCE(notes.previous_slugs, '[]') AS previous_slugs, \
+        CASE \
+            WHEN parent.id IS NULL THEN NULL \
+            WHEN parent.parent_id IS NULL THEN parent.id \
+            WHEN grandparent.parent_id IS NULL THEN grandparent.id \
+            ELSE NULL \
+        END AS section_id, \
+        CASE \
+            WHEN parent.parent_id IS NOT NULL AND grandparent.parent_id IS NULL THEN parent.id \
+            ELSE NULL \
+        END AS subsection_id \
+    FROM notes \
+    LEFT JOIN nodes AS parent ON parent.id = notes.node_id \
+    LEFT JOIN nodes AS grandparent ON grandparent.id = parent.parent_id\
+    ";
+
+/// Get the maximum note position in a given node.
+/// If no note exists in that node, returns None.
+pub async fn get_max_note_position_in_node(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    node_id: u32,
+) -> Option<u32> {
+    let query_str = "SELECT MAX(position) FROM notes WHERE node_id = ?";
+    let query = sqlx::query_scalar(query_str).bind(node_id);
+    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
+    max.unwrap_or(None)
 }
 
 /// Get the maximum note position in a given subsection.
@@ -28,69 +127,98 @@ pub async fn get_max_note_position_in_subsection(
     pool: &sqlx::Pool<sqlx::MySql>,
     subsection_id: u32,
 ) -> Option<u32> {
-    let query_str = "SELECT MAX(position) FROM notes WHERE subsection_id = ?";
-    let query = sqlx::query_scalar(query_str).bind(subsection_id);
-    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
-    max.unwrap_or(None)
+    get_max_note_position_in_node(pool, subsection_id).await
 }
 
 /// Struct to filter/select notes.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct GetNotesForm {
     pub id: Option<u32>,
     pub name: Option<String>,
     pub url: Option<String>,
     pub position: Option<u32>,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    pub kind: Option<String>,
+    pub course_code: Option<String>,
+    pub term: Option<String>,
+    pub language: Option<String>,
+    /// Matches the notes listing this author among their authors.
+    pub author: Option<String>,
+    pub license: Option<String>,
+    /// Names of the tags the notes must carry, matched according to `tag_match`.
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Only the notes published at this time, along with the nodes they hang from.
+    pub published_at: Option<i64>,
     pub or_and: OrAnd,
+    /// Further conditions on the columns of the notes view, always combined with AND.
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
     pub limit: Option<u32>,
+    pub offset: Option<u32>,
 }
 
 impl GetNotesForm {
-    fn to_conditions_params(&self) -> (Vec<String>, Vec<VecWrapper>) {
-        let mut conditions: Vec<String> = Vec::new();
-        let mut params: Vec<VecWrapper> = Vec::new();
+    /// The conditions on plain columns, shared by every backend.
+    /// The `author` and `tags` conditions depend on how the backend stores them.
+    pub(crate) fn column_filters(&self) -> Vec<Filter> {
+        let int_columns = [
+            ("id", self.id),
+            ("position", self.position),
+            ("node_id", self.node_id),
+            ("section_id", self.section_id),
+            ("subsection_id", self.subsection_id),
+        ];
+        let text_columns = [
+            ("name", &self.name),
+            ("url", &self.url),
+            ("kind", &self.kind),
+            ("course_code", &self.course_code),
+            ("term", &self.term),
+            ("language", &self.language),
+            ("license", &self.license),
+        ];
+        let int_filters = int_columns
+            .into_iter()
+            .filter_map(|(column, value)| value.map(|value| Filter::eq(column, value)));
+        let text_filters = text_columns.into_iter().filter_map(|(column, value)| {
+            value.clone().map(|value| Filter::eq(column, value))
+        });
+        int_filters.chain(text_filters).collect()
+    }
 
-        if let Some(id) = self.id {
-            conditions.push("id = ?".to_string());
-            params.push(VecWrapper::Num(id));
-        }
-        if let Some(name) = &self.name {
-            conditions.push("name = ?".to_string());
-            params.push(VecWrapper::String(name.clone()));
-        }
-        if let Some(url) = &self.url {
-            conditions.push("url = ?".to_string());
-            params.push(VecWrapper::String(url.clone()));
+    /// The condition on the publication of the notes and of their nodes, always
+    /// combined with AND.
+    pub(crate) fn published_condition(&self) -> Filter {
+        match self.published_at {
+            Some(at) => published_filter(at).and(Filter::Or(vec![
+                Filter::IsNull("node_id"),
+                published_nodes_condition("node_id", at),
+            ])),
+            None => Filter::all(),
         }
-        if let Some(position) = self.position {
-            conditions.push("position = ?".to_string());
-            params.push(VecWrapper::Num(position));
-        }
-        if let Some(section_id) = self.section_id {
-            conditions.push("section_id = ?".to_string());
-            params.push(VecWrapper::Num(section_id));
-        }
-        if let Some(subsection_id) = self.subsection_id {
-            conditions.push("subsection_id = ?".to_string());
-            params.push(VecWrapper::Num(subsection_id));
-        }
-        (conditions, params)
     }
-}
 
-impl Default for GetNotesForm {
-    fn default() -> Self {
-        Self {
-            id: None,
-            name: None,
-            url: None,
-            position: None,
-            section_id: None,
-            subsection_id: None,
-            or_and: Default::default(),
-            limit: None,
+    fn to_clauses(&self) -> Clauses {
+        let mut filters = self.column_filters();
+        if let Some(author) = &self.author {
+            filters.push(Filter::Sql(
+                "JSON_CONTAINS(authors, JSON_QUOTE(?))".to_string(),
+                vec![author.clone().into()],
+            ));
+        }
+        if let Some(filter) = notes_with_tags_condition(&self.tags, &self.tag_match) {
+            filters.push(filter);
+        }
+        Clauses {
+            filter: Filter::combine(filters, &self.or_and)
+                .and(self.published_condition())
+                .and(self.filter.clone()),
+            order_by: self.order_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
         }
     }
 }
@@ -98,7 +226,7 @@ impl Default for GetNotesForm {
 /// Errors that might occur when fetching notes.
 #[derive(Debug)]
 pub enum GetNotesError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 /// Fetch notes based on the filtering form.
@@ -106,35 +234,34 @@ pub async fn get_notes(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetNotesForm,
 ) -> Result<Vec<NoteFromDb>, GetNotesError> {
-    let (conditions, params) = form.to_conditions_params();
-    let pre_query_str = format!(
-        "SELECT id, name, COALESCE(description, '') AS description, url, position, section_id, subsection_id FROM notes {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => format!("LIMIT {}", val),
-        }
-    );
-    let query_str = pre_query_str.as_str();
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!("SELECT * FROM ({}) AS notes_view{}", NOTES_VIEW, clauses);
     trace!("{}", query_str);
-    let mut query = sqlx::query_as::<_, NoteFromDb>(query_str);
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
+    let query = sqlx::query_as::<_, NoteFromDb>(&query_str).bind_values(params);
     let notes: Result<Vec<NoteFromDb>, sqlx::Error> = query.fetch_all(pool).await;
     match notes {
         Ok(notes) => Ok(notes),
         Err(err) => {
             warn!("{:?}", err);
-            Err(GetNotesError::UnexpectedError)
+            Err(GetNotesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Count the notes matching the filtering form, ignoring its ordering and paging.
+pub async fn count_notes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNotesForm,
+) -> Result<u64, GetNotesError> {
+    let (clauses, params) = form.to_clauses().without_paging().to_sql();
+    let query_str = format!("SELECT COUNT(*) FROM ({}) AS notes_view{}", NOTES_VIEW, clauses);
+    trace!("{}", query_str);
+    let query = sqlx::query_scalar::<_, i64>(&query_str).bind_values(params);
+    match query.fetch_one(pool).await {
+        Ok(count) => Ok(count as u64),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(GetNotesError::UnexpectedError(err.into()))
         }
     }
 }
@@ -142,10 +269,19 @@ pub async fn get_notes(
 /// Error type when trying to get a single note.
 #[derive(Debug)]
 pub enum GetNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
+impl LookupError for GetNoteError {
+    fn into_unexpected(self) -> Option<Error> {
+        match self {
+            GetNoteError::UnexpectedError(err) => Some(err),
+            GetNoteError::NotFoundError => None,
+        }
+    }
+}
+
 /// Get a single note (using LIMIT 1) based on the filtering form.
 pub async fn get_note(
     pool: &sqlx::Pool<sqlx::MySql>,
@@ -167,18 +303,18 @@ pub async fn get_note(
                 Ok(list.swap_remove(0))
             }
         }
-        Err(_) => Err(GetNoteError::UnexpectedError),
+        Err(GetNotesError::UnexpectedError(err)) => Err(GetNoteError::UnexpectedError(err)),
     }
 }
 
 /// Create a new note. Determines the next note position:
-/// if a subsection is provided, it uses that; otherwise, it uses a global max.
+/// if a node is provided, it uses the max inside that node; otherwise, it uses a global max.
 pub async fn create_note(
     pool: &sqlx::Pool<sqlx::MySql>,
     note_form: CreateNoteForm,
-) -> Result<(), ()> {
-    let next_pos = if let Some(subsec_id) = note_form.subsection_id {
-        match get_max_note_position_in_subsection(pool, subsec_id).await {
+) -> Result<(), Error> {
+    let next_pos = if let Some(node_id) = note_form.node_id {
+        match get_max_note_position_in_node(pool, node_id).await {
             Some(num) => num + 1,
             None => 0,
         }
@@ -192,32 +328,62 @@ pub async fn create_note(
         }
     };
 
+    let metadata = note_form.metadata;
+    let slug = note_form.slug.unwrap_or_else(|| slugify(&note_form.name));
+    let now = crate::db::now();
     let res = sqlx::query(
-        "INSERT INTO notes (name, description, url, position, section_id, subsection_id) VALUES (?, ?, ?, ?, ?, ?)",
+        "INSERT INTO notes (name, description, url, position, node_id, \
+         kind, course_code, term, language, authors, license, created_at, updated_at, \
+         status, publish_at, slug) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(note_form.name)
     .bind(note_form.description)
     .bind(note_form.url)
     .bind(next_pos)
-    .bind(note_form.section_id)
-    .bind(note_form.subsection_id)
+    .bind(note_form.node_id)
+    .bind(metadata.kind)
+    .bind(metadata.course_code)
+    .bind(metadata.term)
+    .bind(metadata.language)
+    .bind(authors_to_json(&metadata.authors))
+    .bind(metadata.license)
+    .bind(now)
+    .bind(now)
+    .bind(note_form.status.unwrap_or_else(|| PUBLISHED.to_string()))
+    .bind(note_form.publish_at)
+    .bind(slug)
     .execute(pool)
     .await;
     trace!("{:?}", res);
     match res {
         Ok(_) => Ok(()),
-        Err(_) => Err(()),
+        Err(err) => Err(err.into()),
     }
 }
 
 /// The form used to update one or more fields of a note.
+#[derive(Default)]
 pub struct UpdateNoteForm {
     pub name: Option<String>,
     pub description: Option<String>,
     pub url: Option<String>,
-    pub section_id: Option<u32>,
-    pub subsection_id: Option<u32>,
+    pub node_id: Option<u32>,
     pub position: Option<u32>,
+    /// `Some(None)` clears the metadata field, as for `course_code`, `term`,
+    /// `language` and `license`.
+    pub kind: Option<Option<String>>,
+    pub course_code: Option<Option<String>>,
+    pub term: Option<Option<String>>,
+    pub language: Option<Option<String>>,
+    pub authors: Option<Vec<String>>,
+    pub license: Option<Option<String>>,
+    pub status: Option<String>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    pub slug: Option<String>,
+    pub previous_slugs: Option<Vec<String>>,
+    /// Only update the notes still at this version.
+    pub version: Option<u32>,
 }
 
 impl UpdateNoteForm {
@@ -225,21 +391,32 @@ impl UpdateNoteForm {
         self.name.is_none()
             && self.description.is_none()
             && self.url.is_none()
-            && self.section_id.is_none()
-            && self.subsection_id.is_none()
+            && self.node_id.is_none()
             && self.position.is_none()
+            && self.kind.is_none()
+            && self.course_code.is_none()
+            && self.term.is_none()
+            && self.language.is_none()
+            && self.authors.is_none()
+            && self.license.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+            && self.slug.is_none()
+            && self.previous_slugs.is_none()
     }
 }
 
 /// Errors that might occur when updating notes.
 #[derive(Debug, PartialEq, Eq)]
 pub enum UpdateNotesError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    VersionConflictError,
 }
 
 /// Update notes based on the filtering form (`identified_by`).
+/// When `version` is given, the update is rejected if a note has moved past it.
 pub async fn update_notes(
     pool: &sqlx::Pool<sqlx::MySql>,
     note_form: UpdateNoteForm,
@@ -248,79 +425,72 @@ pub async fn update_notes(
     if note_form.is_all_none() {
         return Err(UpdateNotesError::NothingToUpdateError);
     }
-    let notes_q = get_notes(pool, identified_by.clone()).await;
-    if notes_q.is_err() {
-        return Err(UpdateNotesError::UnexpectedError);
+    let notes = get_notes(pool, identified_by)
+        .await
+        .map_err(|GetNotesError::UnexpectedError(err)| UpdateNotesError::UnexpectedError(err))?;
+    if notes.is_empty() {
+        return Err(UpdateNotesError::NotFoundError);
     }
-    if let Ok(notes) = notes_q {
-        if notes.is_empty() {
-            return Err(UpdateNotesError::NotFoundError);
+    if let Some(version) = note_form.version {
+        if notes.iter().any(|note| note.version != version) {
+            return Err(UpdateNotesError::VersionConflictError);
         }
     }
 
-    let mut update_columns: Vec<String> = Vec::new();
-    let mut update_params: Vec<VecWrapper> = Vec::new();
-
-    if let Some(name) = note_form.name {
-        update_columns.push("name = ?".to_string());
-        update_params.push(VecWrapper::String(name));
+    let mut assignments = Assignments::default();
+    assignments.set_some("name", note_form.name);
+    assignments.set_some("description", note_form.description);
+    assignments.set_some("url", note_form.url);
+    assignments.set_some("node_id", note_form.node_id);
+    assignments.set_some("position", note_form.position);
+    if let Some(kind) = note_form.kind {
+        assignments.set_or_null("kind", kind);
     }
-    if let Some(description) = note_form.description {
-        update_columns.push("description = ?".to_string());
-        update_params.push(VecWrapper::String(description));
+    if let Some(course_code) = note_form.course_code {
+        assignments.set_or_null("course_code", course_code);
     }
-    if let Some(url) = note_form.url {
-        update_columns.push("url = ?".to_string());
-        update_params.push(VecWrapper::String(url));
+    if let Some(term) = note_form.term {
+        assignments.set_or_null("term", term);
     }
-    if let Some(section_id) = note_form.section_id {
-        update_columns.push("section_id = ?".to_string());
-        update_params.push(VecWrapper::Num(section_id));
+    if let Some(language) = note_form.language {
+        assignments.set_or_null("language", language);
     }
-    if let Some(subsection_id) = note_form.subsection_id {
-        update_columns.push("subsection_id = ?".to_string());
-        update_params.push(VecWrapper::Num(subsection_id));
+    assignments.set_some("authors", note_form.authors.as_deref().map(authors_to_json));
+    if let Some(license) = note_form.license {
+        assignments.set_or_null("license", license);
     }
-    if let Some(position) = note_form.position {
-        update_columns.push("position = ?".to_string());
-        update_params.push(VecWrapper::Num(position));
+    assignments.set_some("status", note_form.status);
+    if let Some(publish_at) = note_form.publish_at {
+        assignments.set_or_null("publish_at", publish_at);
     }
-
-    let (conditions, params) = identified_by.to_conditions_params();
-
-    let pre_query_str = format!(
-        "UPDATE notes SET {} {} {}",
-        update_columns.join(", "),
-        if conditions.is_empty() { "" } else { "WHERE" },
-        conditions.join(match identified_by.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        })
+    assignments.set_some("slug", note_form.slug);
+    assignments.set_some(
+        "previous_slugs",
+        note_form.previous_slugs.as_deref().map(previous_slugs_to_json),
     );
-    let query_str = pre_query_str.as_str();
-    let mut query = sqlx::query(query_str);
-
-    for param in update_params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
+    assignments.set("updated_at", crate::db::now());
+    assignments.increment("version");
+
+    let mut params: Vec<Value> = Vec::new();
+    let set = assignments.to_sql(&mut params);
+    let mut filter = Filter::is_in("id", notes.iter().map(|note| note.id));
+    // Checked again in the UPDATE itself, in case a note changed since it was read.
+    if let Some(version) = note_form.version {
+        filter = filter.and(Filter::eq("version", version));
     }
+    let query_str = format!("UPDATE notes SET {} WHERE {}", set, filter.to_sql(&mut params));
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
 
     let res = query.execute(pool).await;
     match res {
+        Ok(done) if note_form.version.is_some() && (done.rows_affected() as usize) < notes.len() => {
+            Err(UpdateNotesError::VersionConflictError)
+        }
         Ok(_) => Ok(()),
         Err(e) => {
             warn!("{:?}", e);
-            Err(UpdateNotesError::UnexpectedError)
+            Err(UpdateNotesError::UnexpectedError(e.into()))
         }
     }
 }
@@ -329,15 +499,46 @@ pub async fn update_notes(
 #[derive(Debug, PartialEq, Eq)]
 pub enum SwapNotesError {
     NotFoundError((Option<u32>, Option<u32>)),
-    UnexpectedError,
+    UnexpectedError(Error),
     CantSwapFromDifferentSubsections,
+    /// A note is not at its expected version, nothing was swapped.
+    VersionConflictError,
 }
 
-/// Swap the positions of two notes identified by their IDs.
-/// If both notes have a non-null `subsection_id`, they must be the same.
+impl From<PairLookupError> for SwapNotesError {
+    fn from(err: PairLookupError) -> Self {
+        match err {
+            PairLookupError::UnexpectedError(err) => SwapNotesError::UnexpectedError(err),
+            PairLookupError::NotFoundError(ids) => SwapNotesError::NotFoundError(ids),
+        }
+    }
+}
+
+/// The notes to swap, checked alike by every storage before writing: both found,
+/// hanging from the same node and at their expected `versions`. The storages
+/// still write them only at these versions, for the swaps racing this check.
+pub(crate) fn check_swap(
+    ids: [u32; 2],
+    versions: [u32; 2],
+    lookups: [Result<NoteFromDb, GetNoteError>; 2],
+) -> Result<(NoteFromDb, NoteFromDb), SwapNotesError> {
+    let (note_1, note_2) = found_pair(ids, lookups)?;
+    if note_1.node_id != note_2.node_id {
+        return Err(SwapNotesError::CantSwapFromDifferentSubsections);
+    }
+    if [note_1.version, note_2.version] != versions {
+        return Err(SwapNotesError::VersionConflictError);
+    }
+    Ok((note_1, note_2))
+}
+
+/// Swap the positions of two notes identified by their IDs, in one transaction
+/// writing them only at their expected `versions`.
+/// Both notes must hang from the same node.
 pub async fn swap_notes(
     pool: &sqlx::Pool<sqlx::MySql>,
     ids: [u32; 2],
+    versions: [u32; 2],
 ) -> Result<(), SwapNotesError> {
     let note_1 = get_note(
         pool,
@@ -355,24 +556,7 @@ pub async fn swap_notes(
         },
     )
     .await;
-    let mut ret_err_nf = (None, None);
-    if note_1.is_err() {
-        ret_err_nf.0 = Some(ids[0]);
-    }
-    if note_2.is_err() {
-        ret_err_nf.1 = Some(ids[1]);
-    }
-    if ret_err_nf.0.is_some() || ret_err_nf.1.is_some() {
-        return Err(SwapNotesError::NotFoundError(ret_err_nf));
-    }
-    let note_1 = note_1.unwrap();
-    let note_2 = note_2.unwrap();
-    if note_1.subsection_id.is_some()
-        && note_2.subsection_id.is_some()
-        && note_1.subsection_id != note_2.subsection_id
-    {
-        return Err(SwapNotesError::CantSwapFromDifferentSubsections);
-    }
+    let (note_1, note_2) = check_swap(ids, versions, [note_1, note_2])?;
 
     // Use a temporary position that is higher than any existing one.
     let global_max_query = "SELECT MAX(position) FROM notes";
@@ -383,40 +567,71 @@ pub async fn swap_notes(
     let (note_1_id, note_1_pos) = (note_1.id, note_1.position);
     let (note_2_id, note_2_pos) = (note_2.id, note_2.position);
 
-    let update_query = "UPDATE notes SET position = ? WHERE id = ?";
-
-    let query = sqlx::query(update_query)
-        .bind(max_pos)
-        .bind(note_1_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapNotesError::UnexpectedError);
-    }
-    let query = sqlx::query(update_query)
-        .bind(note_1_pos)
-        .bind(note_2_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapNotesError::UnexpectedError);
+    let now = crate::db::now();
+    // Parking the first row on a free position does not count, each version moves once.
+    let park_query = "UPDATE notes SET position = ? WHERE id = ?";
+    let update_query = "UPDATE notes SET position = ?, updated_at = ?, version = version + 1 \
+        WHERE id = ? AND version = ?";
+
+    let res: Result<bool, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query(park_query)
+            .bind(max_pos)
+            .bind(note_1_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, id, version) in [
+            (note_1_pos, note_2_id, versions[1]),
+            (note_2_pos, note_1_id, versions[0]),
+        ] {
+            let done = sqlx::query(update_query)
+                .bind(position)
+                .bind(now)
+                .bind(id)
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+            if done.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+        tx.commit().await?;
+        Ok(true)
     }
-    let query = sqlx::query(update_query)
-        .bind(note_2_pos)
-        .bind(note_1_id)
-        .execute(pool)
-        .await;
-    query
-        .map_err(|err| {
+    .await;
+    match res {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(SwapNotesError::VersionConflictError),
+        Err(err) => {
             warn!("{:?}", err);
-            SwapNotesError::UnexpectedError
-        })
+            Err(SwapNotesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Move the `updated_at` of the notes matched by `filter` to now, after a change
+/// stored in another table, such as their tags or attachments. `executor` is the
+/// pool, or the transaction making that change.
+pub async fn touch_notes<'e>(
+    executor: impl sqlx::Executor<'e, Database = sqlx::MySql>,
+    filter: Filter,
+) -> Result<(), sqlx::Error> {
+    let mut params: Vec<Value> = vec![crate::db::now().into()];
+    let query_str = format!(
+        "UPDATE notes SET updated_at = ? WHERE {}",
+        filter.to_sql(&mut params)
+    );
+    trace!("{}", query_str);
+    sqlx::query(&query_str)
+        .bind_values(params)
+        .execute(executor)
+        .await
         .map(|_| ())
 }
 
 /// Error type for deleting notes.
 pub enum DeleteNotesError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 /// Delete notes based on a filtering form.
@@ -424,32 +639,20 @@ pub async fn delete_notes(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetNotesForm,
 ) -> Result<(), DeleteNotesError> {
-    let (conditions, params) = form.to_conditions_params();
-
-    let pre_query_str = format!(
-        "DELETE FROM notes {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => format!("LIMIT {}", val),
-        }
-    );
-    let query_str = pre_query_str.as_str();
-    trace!("{}", query_str);
-    let mut query = sqlx::query(query_str);
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
+    let notes = get_notes(pool, form)
+        .await
+        .map_err(|GetNotesError::UnexpectedError(err)| DeleteNotesError::UnexpectedError(err))?;
+    if notes.is_empty() {
+        return Ok(());
     }
+
+    let mut params: Vec<Value> = Vec::new();
+    let filter = Filter::is_in("id", notes.iter().map(|note| note.id));
+    let query_str = format!("DELETE FROM notes WHERE {}", filter.to_sql(&mut params));
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
     let res = query.execute(pool).await;
-    res.map_err(|_| DeleteNotesError::UnexpectedError)
+    res.map_err(|err| DeleteNotesError::UnexpectedError(err.into()))
         .map(|_| ())
 }
 
@@ -1,8 +1,15 @@
+pub mod attachments;
+pub mod catalog;
 pub mod create_tables;
+pub mod files;
 pub mod lecture_notes;
+pub mod nodes;
+pub mod query;
 pub mod sections;
 pub mod sessions;
+pub mod slugs;
 pub mod subsections;
+pub mod tags;
 pub mod users;
 
 use dotenvy::dotenv;
@@ -11,6 +18,8 @@ use std::{default, env};
 use sqlx::mysql::MySqlPoolOptions;
 use sqlx::Connection;
 
+use crate::error::Error;
+
 pub async fn establish_connection() -> Result<sqlx::Pool<sqlx::MySql>, sqlx::Error> {
     dotenv().ok();
 
@@ -32,10 +41,49 @@ pub async fn establish_connection_for_testing() -> Result<sqlx::Pool<sqlx::MySql
         .await
 }
 
-enum VecWrapper {
-    String(String),
-    Num(u32),
-    Bool(bool),
+/// The current time as stored in the `created_at` and `updated_at` columns:
+/// seconds since the Unix epoch.
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// The error of a lookup of a single row: the row is missing, or the query failed.
+pub(crate) trait LookupError {
+    /// The error of the failed query, None for a missing row.
+    fn into_unexpected(self) -> Option<Error>;
+}
+
+/// Why the two rows of an operation on a pair, such as a swap or a merge, are
+/// not both there.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PairLookupError {
+    UnexpectedError(Error),
+    /// The ids of the missing rows.
+    NotFoundError((Option<u32>, Option<u32>)),
+}
+
+/// The two rows of an operation on a pair, looked up by `ids`, as every storage
+/// checks them before writing: a failed lookup wins over the missing rows.
+pub(crate) fn found_pair<T, E: LookupError>(
+    ids: [u32; 2],
+    lookups: [Result<T, E>; 2],
+) -> Result<(T, T), PairLookupError> {
+    let [first, second] = lookups;
+    match (first, second) {
+        (Ok(first), Ok(second)) => Ok((first, second)),
+        (first, second) => {
+            let missing = (first.is_err().then_some(ids[0]), second.is_err().then_some(ids[1]));
+            for err in [first.err(), second.err()].into_iter().flatten() {
+                if let Some(err) = err.into_unexpected() {
+                    return Err(PairLookupError::UnexpectedError(err));
+                }
+            }
+            Err(PairLookupError::NotFoundError(missing))
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug)]
@@ -0,0 +1,626 @@
+use crate::db::query::{Assignments, BindValues, Clauses, Filter, OrderBy, Value};
+use crate::db::slugs::{previous_slugs_from_json, previous_slugs_to_json, slugify};
+use crate::db::{found_pair, LookupError, OrAnd, PairLookupError};
+use crate::error::Error;
+use loggit::{trace, warn};
+
+/// The status of the content shown to the public, the others being
+/// `draft` and `archived`.
+pub const PUBLISHED: &str = "published";
+
+/// The form used to create a new node of the content tree.
+#[derive(Default)]
+pub struct CreateNodeForm {
+    pub parent_id: Option<u32>,
+    pub kind: String,
+    pub title: String,
+    /// `PUBLISHED` when None.
+    pub status: Option<String>,
+    /// Seconds since the Unix epoch before which the node stays hidden.
+    pub publish_at: Option<i64>,
+    /// The title slugified when None, see `slugs::slugify`.
+    pub slug: Option<String>,
+}
+
+/// A node of the content tree as stored in the database.
+/// Root nodes have no parent; notes hang from nodes as leaves.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct NodeFromDb {
+    pub id: u32,
+    pub parent_id: Option<u32>,
+    pub kind: String,
+    pub title: String,
+    pub position: u32,
+    /// Seconds since the Unix epoch, see `db::now`.
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Incremented by every update and move of the node.
+    pub version: u32,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    /// The URL segment of the node, unique among its siblings.
+    pub slug: String,
+    /// JSON array of the slugs the node had before, oldest first.
+    pub previous_slugs: String,
+}
+
+impl NodeFromDb {
+    /// Decode the `previous_slugs` JSON column.
+    pub fn previous_slug_list(&self) -> Vec<String> {
+        previous_slugs_from_json(&self.previous_slugs)
+    }
+}
+
+/// The columns of `NodeFromDb`, in the `nodes` table.
+pub(crate) const NODE_COLUMNS: &str = "id, parent_id, kind, title, position, created_at, \
+    updated_at, version, status, publish_at, slug, COALESCE(previous_slugs, '[]') AS previous_slugs";
+
+/// Get the maximum position among the children of `parent_id`
+/// (or among the root nodes when `parent_id` is None).
+pub async fn get_max_position_in_parent(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    parent_id: Option<u32>,
+) -> Option<u32> {
+    let query_str = "SELECT MAX(position) FROM nodes WHERE parent_key = ?";
+    let query = sqlx::query_scalar(query_str).bind(parent_id.unwrap_or(0));
+    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
+    max.unwrap_or(None)
+}
+
+/// SQL condition matching the nodes located at `depth` (0 for the roots).
+pub(crate) fn depth_condition(depth: u32) -> String {
+    let mut condition = "parent_id IS NULL".to_string();
+    for level in 0..depth {
+        condition = format!(
+            "parent_id IN (SELECT id FROM (SELECT id FROM nodes WHERE {}) AS depth_{})",
+            condition, level
+        );
+    }
+    condition
+}
+
+/// The rows of a table with `status` and `publish_at` columns that are
+/// published at `at`.
+pub(crate) fn published_filter(at: i64) -> Filter {
+    Filter::eq("status", PUBLISHED).and(Filter::Or(vec![
+        Filter::IsNull("publish_at"),
+        Filter::Lte("publish_at", at.into()),
+    ]))
+}
+
+/// SQL condition matching the rows whose `column` is the id of a node published
+/// at `at` along with all its ancestors, so that hiding a node hides its subtree.
+pub(crate) fn published_nodes_condition(column: &str, at: i64) -> Filter {
+    let mut values = Vec::new();
+    let root = published_filter(at).to_sql(&mut values);
+    let child = published_filter(at)
+        .map_columns(&|column| match column {
+            "status" => "child.status",
+            "publish_at" => "child.publish_at",
+            column => column,
+        })
+        .to_sql(&mut values);
+    Filter::Sql(
+        format!(
+            "{} IN (WITH RECURSIVE published_nodes (id) AS (\
+                SELECT id FROM nodes WHERE parent_id IS NULL AND {} \
+                UNION ALL \
+                SELECT child.id FROM nodes AS child \
+                JOIN published_nodes ON child.parent_id = published_nodes.id WHERE {}\
+            ) SELECT id FROM published_nodes)",
+            column, root, child
+        ),
+        values,
+    )
+}
+
+/// Struct to filter/select nodes.
+/// `depth`, `is_root`, `published_at` and `filter` are always combined with AND,
+/// while the remaining fields are combined according to `or_and`.
+#[derive(Clone, Debug, Default)]
+pub struct GetNodesForm {
+    pub id: Option<u32>,
+    pub parent_id: Option<u32>,
+    pub kind: Option<String>,
+    pub title: Option<String>,
+    pub position: Option<u32>,
+    pub is_root: Option<bool>,
+    pub depth: Option<u32>,
+    /// Only the nodes published at this time, along with all their ancestors.
+    pub published_at: Option<i64>,
+    pub or_and: OrAnd,
+    /// Further conditions on the columns of the `nodes` table.
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl GetNodesForm {
+    /// The conditions of the form, shared by every backend.
+    pub(crate) fn to_filter(&self) -> Filter {
+        let fields = [
+            self.id.map(|id| Filter::eq("id", id)),
+            self.parent_id.map(|id| Filter::eq("parent_id", id)),
+            self.kind.clone().map(|kind| Filter::eq("kind", kind)),
+            self.title.clone().map(|title| Filter::eq("title", title)),
+            self.position.map(|position| Filter::eq("position", position)),
+        ];
+        let mut filter = Filter::combine(fields.into_iter().flatten().collect(), &self.or_and);
+
+        match self.is_root {
+            Some(true) => filter = filter.and(Filter::IsNull("parent_id")),
+            Some(false) => filter = filter.and(Filter::IsNotNull("parent_id")),
+            None => {}
+        }
+        if let Some(depth) = self.depth {
+            filter = filter.and(Filter::Sql(depth_condition(depth), Vec::new()));
+        }
+        if let Some(at) = self.published_at {
+            filter = filter.and(published_nodes_condition("id", at));
+        }
+        filter.and(self.filter.clone())
+    }
+
+    pub(crate) fn to_clauses(&self) -> Clauses {
+        Clauses {
+            filter: self.to_filter(),
+            order_by: self.order_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Errors that might occur when fetching nodes.
+#[derive(Debug)]
+pub enum GetNodesError {
+    UnexpectedError(Error),
+}
+
+/// Fetch nodes based on the filtering form.
+pub async fn get_nodes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNodesForm,
+) -> Result<Vec<NodeFromDb>, GetNodesError> {
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!("SELECT {} FROM nodes{}", NODE_COLUMNS, clauses);
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, NodeFromDb>(&query_str).bind_values(params);
+    match query.fetch_all(pool).await {
+        Ok(nodes) => Ok(nodes),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(GetNodesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Count the nodes matching the filtering form, ignoring its ordering and paging.
+pub async fn count_nodes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNodesForm,
+) -> Result<u64, GetNodesError> {
+    let (clauses, params) = form.to_clauses().without_paging().to_sql();
+    let query_str = format!("SELECT COUNT(*) FROM nodes{}", clauses);
+    trace!("{}", query_str);
+    let query = sqlx::query_scalar::<_, i64>(&query_str).bind_values(params);
+    match query.fetch_one(pool).await {
+        Ok(count) => Ok(count as u64),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(GetNodesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Error type when trying to get a single node.
+#[derive(Debug)]
+pub enum GetNodeError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+impl LookupError for GetNodeError {
+    fn into_unexpected(self) -> Option<Error> {
+        match self {
+            GetNodeError::UnexpectedError(err) => Some(err),
+            GetNodeError::NotFoundError => None,
+        }
+    }
+}
+
+/// Get a single node (using LIMIT 1) based on the filtering form.
+pub async fn get_node(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNodesForm,
+) -> Result<NodeFromDb, GetNodeError> {
+    let nodes = get_nodes(
+        pool,
+        GetNodesForm {
+            limit: Some(1),
+            ..form
+        },
+    )
+    .await;
+    match nodes {
+        Ok(mut list) => {
+            if list.is_empty() {
+                Err(GetNodeError::NotFoundError)
+            } else {
+                Ok(list.swap_remove(0))
+            }
+        }
+        Err(GetNodesError::UnexpectedError(err)) => Err(GetNodeError::UnexpectedError(err)),
+    }
+}
+
+/// Collect the ids of every ancestor of the node `id`, nearest first.
+pub async fn get_ancestor_ids(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    id: u32,
+) -> Result<Vec<u32>, GetNodeError> {
+    let mut ancestors = Vec::new();
+    let mut current = get_node(
+        pool,
+        GetNodesForm {
+            id: Some(id),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    while let Some(parent_id) = current.parent_id {
+        if ancestors.contains(&parent_id) {
+            break;
+        }
+        ancestors.push(parent_id);
+        current = get_node(
+            pool,
+            GetNodesForm {
+                id: Some(parent_id),
+                ..Default::default()
+            },
+        )
+        .await?;
+    }
+    Ok(ancestors)
+}
+
+/// Errors that might occur when creating a node.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CreateNodeError {
+    UnexpectedError(Error),
+    ParentNotFoundError,
+}
+
+/// Create a new node as the last child of its parent.
+/// Returns the id of the created node.
+pub async fn create_node(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    node_form: CreateNodeForm,
+) -> Result<u32, CreateNodeError> {
+    if let Some(parent_id) = node_form.parent_id {
+        let parent = get_node(
+            pool,
+            GetNodesForm {
+                id: Some(parent_id),
+                ..Default::default()
+            },
+        )
+        .await;
+        match parent {
+            Ok(_) => {}
+            Err(GetNodeError::NotFoundError) => return Err(CreateNodeError::ParentNotFoundError),
+            Err(GetNodeError::UnexpectedError(err)) => {
+                return Err(CreateNodeError::UnexpectedError(err))
+            }
+        }
+    }
+
+    let next_pos = match get_max_position_in_parent(pool, node_form.parent_id).await {
+        Some(num) => num + 1,
+        None => 0,
+    };
+
+    let slug = node_form
+        .slug
+        .unwrap_or_else(|| slugify(&node_form.title));
+    let now = crate::db::now();
+    let res = sqlx::query(
+        "INSERT INTO nodes (parent_id, kind, title, position, created_at, updated_at, \
+         status, publish_at, slug) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(node_form.parent_id)
+    .bind(node_form.kind)
+    .bind(node_form.title)
+    .bind(next_pos)
+    .bind(now)
+    .bind(now)
+    .bind(node_form.status.unwrap_or_else(|| PUBLISHED.to_string()))
+    .bind(node_form.publish_at)
+    .bind(slug)
+    .execute(pool)
+        .await;
+    trace!("{:?}", res);
+    match res {
+        Ok(done) => Ok(done.last_insert_id() as u32),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(CreateNodeError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// The form used to update one or more fields of a node.
+#[derive(Default)]
+pub struct UpdateNodeForm {
+    pub title: Option<String>,
+    pub kind: Option<String>,
+    pub parent_id: Option<u32>,
+    pub position: Option<u32>,
+    pub status: Option<String>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    pub slug: Option<String>,
+    pub previous_slugs: Option<Vec<String>>,
+    /// Only update the nodes still at this version.
+    pub version: Option<u32>,
+}
+
+impl UpdateNodeForm {
+    pub fn is_all_none(&self) -> bool {
+        self.title.is_none()
+            && self.kind.is_none()
+            && self.parent_id.is_none()
+            && self.position.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+            && self.slug.is_none()
+            && self.previous_slugs.is_none()
+    }
+}
+
+/// Errors that might occur when updating nodes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UpdateNodesError {
+    UnexpectedError(Error),
+    NotFoundError,
+    NothingToUpdateError,
+    CycleError,
+    VersionConflictError,
+}
+
+/// Update the nodes matched by `identified_by`.
+/// Re-parenting a node under itself or one of its descendants is rejected,
+/// and so is the update when `version` is given and a node has moved past it.
+pub async fn update_nodes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    node_form: UpdateNodeForm,
+    identified_by: GetNodesForm,
+) -> Result<(), UpdateNodesError> {
+    if node_form.is_all_none() {
+        return Err(UpdateNodesError::NothingToUpdateError);
+    }
+    let nodes = get_nodes(pool, identified_by)
+        .await
+        .map_err(|GetNodesError::UnexpectedError(err)| UpdateNodesError::UnexpectedError(err))?;
+    if nodes.is_empty() {
+        return Err(UpdateNodesError::NotFoundError);
+    }
+    if let Some(version) = node_form.version {
+        if nodes.iter().any(|node| node.version != version) {
+            return Err(UpdateNodesError::VersionConflictError);
+        }
+    }
+
+    if let Some(parent_id) = node_form.parent_id {
+        let ancestors = match get_ancestor_ids(pool, parent_id).await {
+            Ok(list) => list,
+            Err(GetNodeError::NotFoundError) => return Err(UpdateNodesError::NotFoundError),
+            Err(GetNodeError::UnexpectedError(err)) => {
+                return Err(UpdateNodesError::UnexpectedError(err))
+            }
+        };
+        if nodes
+            .iter()
+            .any(|node| node.id == parent_id || ancestors.contains(&node.id))
+        {
+            return Err(UpdateNodesError::CycleError);
+        }
+    }
+
+    let mut assignments = Assignments::default();
+    assignments.set_some("title", node_form.title);
+    assignments.set_some("kind", node_form.kind);
+    assignments.set_some("parent_id", node_form.parent_id);
+    assignments.set_some("position", node_form.position);
+    assignments.set_some("status", node_form.status);
+    if let Some(publish_at) = node_form.publish_at {
+        assignments.set_or_null("publish_at", publish_at);
+    }
+    assignments.set_some("slug", node_form.slug);
+    assignments.set_some(
+        "previous_slugs",
+        node_form.previous_slugs.as_deref().map(previous_slugs_to_json),
+    );
+    assignments.set("updated_at", crate::db::now());
+    assignments.increment("version");
+
+    let mut params: Vec<Value> = Vec::new();
+    let set = assignments.to_sql(&mut params);
+    let mut filter = Filter::is_in("id", nodes.iter().map(|node| node.id));
+    // Checked again in the UPDATE itself, in case a node changed since it was read.
+    if let Some(version) = node_form.version {
+        filter = filter.and(Filter::eq("version", version));
+    }
+    let query_str = format!("UPDATE nodes SET {} WHERE {}", set, filter.to_sql(&mut params));
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
+
+    let res = query.execute(pool).await;
+    match res {
+        Ok(done) if node_form.version.is_some() && (done.rows_affected() as usize) < nodes.len() => {
+            Err(UpdateNodesError::VersionConflictError)
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("{:?}", e);
+            Err(UpdateNodesError::UnexpectedError(e.into()))
+        }
+    }
+}
+
+/// Error types for swapping two nodes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwapNodesError {
+    NotFoundError((Option<u32>, Option<u32>)),
+    UnexpectedError(Error),
+    CantSwapFromDifferentParents,
+    /// A node is not at its expected version, nothing was swapped.
+    VersionConflictError,
+}
+
+impl From<PairLookupError> for SwapNodesError {
+    fn from(err: PairLookupError) -> Self {
+        match err {
+            PairLookupError::UnexpectedError(err) => SwapNodesError::UnexpectedError(err),
+            PairLookupError::NotFoundError(ids) => SwapNodesError::NotFoundError(ids),
+        }
+    }
+}
+
+/// The nodes to swap, checked alike by every storage before writing: both found,
+/// siblings and at their expected `versions`. The storages still write them only
+/// at these versions, for the swaps racing this check.
+pub(crate) fn check_swap(
+    ids: [u32; 2],
+    versions: [u32; 2],
+    lookups: [Result<NodeFromDb, GetNodeError>; 2],
+) -> Result<(NodeFromDb, NodeFromDb), SwapNodesError> {
+    let (node_1, node_2) = found_pair(ids, lookups)?;
+    if node_1.parent_id != node_2.parent_id {
+        return Err(SwapNodesError::CantSwapFromDifferentParents);
+    }
+    if [node_1.version, node_2.version] != versions {
+        return Err(SwapNodesError::VersionConflictError);
+    }
+    Ok((node_1, node_2))
+}
+
+/// Swap the positions of two sibling nodes, in one transaction writing them only
+/// at their expected `versions`.
+pub async fn swap_nodes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    ids: [u32; 2],
+    versions: [u32; 2],
+) -> Result<(), SwapNodesError> {
+    let node_1 = get_node(
+        pool,
+        GetNodesForm {
+            id: Some(ids[0]),
+            ..Default::default()
+        },
+    )
+    .await;
+    let node_2 = get_node(
+        pool,
+        GetNodesForm {
+            id: Some(ids[1]),
+            ..Default::default()
+        },
+    )
+    .await;
+    let (node_1, node_2) = check_swap(ids, versions, [node_1, node_2])?;
+
+    let max_pos = get_max_position_in_parent(pool, node_1.parent_id)
+        .await
+        .unwrap_or_default()
+        + 1;
+    let (node_1_id, node_1_pos) = (node_1.id, node_1.position);
+    let (node_2_id, node_2_pos) = (node_2.id, node_2.position);
+
+    let now = crate::db::now();
+    // Parking the first row on a free position does not count, each version moves once.
+    let park_query = "UPDATE nodes SET position = ? WHERE id = ?";
+    let update_query = "UPDATE nodes SET position = ?, updated_at = ?, version = version + 1 \
+        WHERE id = ? AND version = ?";
+
+    let res: Result<bool, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query(park_query)
+            .bind(max_pos)
+            .bind(node_1_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, id, version) in [
+            (node_1_pos, node_2_id, versions[1]),
+            (node_2_pos, node_1_id, versions[0]),
+        ] {
+            let done = sqlx::query(update_query)
+                .bind(position)
+                .bind(now)
+                .bind(id)
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+            if done.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+    .await;
+    match res {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(SwapNodesError::VersionConflictError),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(SwapNodesError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Error type for deleting nodes.
+pub enum DeleteNodesError {
+    UnexpectedError(Error),
+}
+
+/// Delete the nodes matched by the filtering form.
+/// Nodes that still have children or notes are protected by the foreign keys.
+pub async fn delete_nodes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNodesForm,
+) -> Result<(), DeleteNodesError> {
+    let nodes = get_nodes(pool, form)
+        .await
+        .map_err(|GetNodesError::UnexpectedError(err)| DeleteNodesError::UnexpectedError(err))?;
+    if nodes.is_empty() {
+        return Ok(());
+    }
+
+    let mut params: Vec<Value> = Vec::new();
+    let filter = Filter::is_in("id", nodes.iter().map(|node| node.id));
+    let query_str = format!("DELETE FROM nodes WHERE {}", filter.to_sql(&mut params));
+    trace!("{}", query_str);
+    let query = sqlx::query(&query_str).bind_values(params);
+    let res = query.execute(pool).await;
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        DeleteNodesError::UnexpectedError(err.into())
+    })
+    .map(|_| ())
+}
+
+/// Delete a single node (using LIMIT 1).
+pub async fn delete_node(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetNodesForm,
+) -> Result<(), DeleteNodesError> {
+    let new_form = GetNodesForm {
+        limit: Some(1),
+        ..form
+    };
+    delete_nodes(pool, new_form).await
+}
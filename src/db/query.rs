@@ -0,0 +1,606 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyArguments;
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::{Query, QueryAs, QueryScalar};
+use sqlx::{Any, MySql};
+
+/// The character escaping the wildcards of the LIKE patterns. Unlike `\`, it
+/// means the same in every SQL dialect.
+const LIKE_ESCAPE: char = '!';
+
+/// A value compared to a column or assigned to it.
+/// Values are always bound as query parameters, never written into the SQL.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i64),
+    Text(String),
+    Bool(bool),
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Int(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl PartialOrd for Value {
+    /// Values of different types cannot be compared.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// A condition on the rows of a table.
+/// Column names are `&'static str` so that they always come from the code,
+/// while the compared values are bound as parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Eq(&'static str, Value),
+    Ne(&'static str, Value),
+    /// The column equals one of the values (matches nothing when empty).
+    In(&'static str, Vec<Value>),
+    /// The column contains the text, wildcards included.
+    Contains(&'static str, String),
+    /// The column matches a LIKE pattern, using `%` and `_` as wildcards.
+    Like(&'static str, String),
+    Gt(&'static str, Value),
+    Gte(&'static str, Value),
+    Lt(&'static str, Value),
+    Lte(&'static str, Value),
+    IsNull(&'static str),
+    IsNotNull(&'static str),
+    /// Every filter matches (always true when empty).
+    And(Vec<Filter>),
+    /// Any filter matches (always false when empty).
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    /// A condition written by the db layer, such as a subquery, with its bound values.
+    /// It cannot be evaluated outside of a database.
+    Sql(String, Vec<Value>),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::all()
+    }
+}
+
+impl Filter {
+    /// The filter matching every row.
+    pub fn all() -> Self {
+        Filter::And(Vec::new())
+    }
+
+    /// The filter matching no row.
+    pub fn none() -> Self {
+        Filter::Or(Vec::new())
+    }
+
+    pub fn eq(column: &'static str, value: impl Into<Value>) -> Self {
+        Filter::Eq(column, value.into())
+    }
+
+    pub fn is_in<V: Into<Value>>(
+        column: &'static str,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        Filter::In(column, values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn contains(column: &'static str, text: impl Into<String>) -> Self {
+        Filter::Contains(column, text.into())
+    }
+
+    /// Whether this filter matches every row without any condition.
+    pub fn is_all(&self) -> bool {
+        match self {
+            Filter::And(filters) => filters.iter().all(Filter::is_all),
+            _ => false,
+        }
+    }
+
+    /// Both filters must match.
+    pub fn and(self, other: Filter) -> Self {
+        match (self, other) {
+            (filter, other) if other.is_all() => filter,
+            (filter, other) if filter.is_all() => other,
+            (Filter::And(mut filters), Filter::And(others)) => {
+                filters.extend(others);
+                Filter::And(filters)
+            }
+            (Filter::And(mut filters), other) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            (filter, other) => Filter::And(vec![filter, other]),
+        }
+    }
+
+    /// Combine the conditions of a filtering form according to its `or_and`.
+    /// No condition at all matches every row.
+    pub fn combine(filters: Vec<Filter>, or_and: &super::OrAnd) -> Self {
+        match or_and {
+            _ if filters.is_empty() => Filter::all(),
+            super::OrAnd::And => Filter::And(filters),
+            super::OrAnd::Or => Filter::Or(filters),
+        }
+    }
+
+    /// Rename the columns, for the views exposing a table under other names.
+    pub fn map_columns(self, rename: &impl Fn(&'static str) -> &'static str) -> Self {
+        let map_all = |filters: Vec<Filter>| {
+            filters
+                .into_iter()
+                .map(|filter| filter.map_columns(rename))
+                .collect()
+        };
+        match self {
+            Filter::Eq(column, value) => Filter::Eq(rename(column), value),
+            Filter::Ne(column, value) => Filter::Ne(rename(column), value),
+            Filter::In(column, values) => Filter::In(rename(column), values),
+            Filter::Contains(column, text) => Filter::Contains(rename(column), text),
+            Filter::Like(column, pattern) => Filter::Like(rename(column), pattern),
+            Filter::Gt(column, value) => Filter::Gt(rename(column), value),
+            Filter::Gte(column, value) => Filter::Gte(rename(column), value),
+            Filter::Lt(column, value) => Filter::Lt(rename(column), value),
+            Filter::Lte(column, value) => Filter::Lte(rename(column), value),
+            Filter::IsNull(column) => Filter::IsNull(rename(column)),
+            Filter::IsNotNull(column) => Filter::IsNotNull(rename(column)),
+            Filter::And(filters) => Filter::And(map_all(filters)),
+            Filter::Or(filters) => Filter::Or(map_all(filters)),
+            Filter::Not(filter) => Filter::Not(Box::new(filter.map_columns(rename))),
+            Filter::Sql(sql, values) => Filter::Sql(sql, values),
+        }
+    }
+
+    /// Write the condition with `?` placeholders, pushing the values to bind to `params`.
+    pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        let comparison = |column: &str, operator: &str, value: &Value, params: &mut Vec<Value>| {
+            params.push(value.clone());
+            format!("{} {} ?", column, operator)
+        };
+        let group = |filters: &[Filter], separator: &str, empty: &str, params: &mut Vec<Value>| {
+            if filters.is_empty() {
+                return empty.to_string();
+            }
+            let conditions = filters
+                .iter()
+                .map(|filter| filter.to_sql(params))
+                .collect::<Vec<_>>();
+            format!("({})", conditions.join(separator))
+        };
+        match self {
+            Filter::Eq(column, value) => comparison(column, "=", value, params),
+            Filter::Ne(column, value) => comparison(column, "<>", value, params),
+            Filter::Gt(column, value) => comparison(column, ">", value, params),
+            Filter::Gte(column, value) => comparison(column, ">=", value, params),
+            Filter::Lt(column, value) => comparison(column, "<", value, params),
+            Filter::Lte(column, value) => comparison(column, "<=", value, params),
+            Filter::In(_, values) if values.is_empty() => "1 = 0".to_string(),
+            Filter::In(column, values) => {
+                params.extend(values.iter().cloned());
+                format!("{} IN ({})", column, vec!["?"; values.len()].join(", "))
+            }
+            Filter::Contains(column, text) => {
+                params.push(Value::Text(format!("%{}%", escape_like(text))));
+                format!("{} LIKE ? ESCAPE '{}'", column, LIKE_ESCAPE)
+            }
+            Filter::Like(column, pattern) => {
+                params.push(Value::Text(pattern.clone()));
+                format!("{} LIKE ? ESCAPE '{}'", column, LIKE_ESCAPE)
+            }
+            Filter::IsNull(column) => format!("{} IS NULL", column),
+            Filter::IsNotNull(column) => format!("{} IS NOT NULL", column),
+            Filter::And(filters) => group(filters, " AND ", "1 = 1", params),
+            Filter::Or(filters) => group(filters, " OR ", "1 = 0", params),
+            Filter::Not(filter) => format!("NOT ({})", filter.to_sql(params)),
+            Filter::Sql(sql, values) => {
+                params.extend(values.iter().cloned());
+                format!("({})", sql)
+            }
+        }
+    }
+
+    /// Evaluate the filter on a row outside of a database, `column` giving the
+    /// value of a column (None for NULL). Like SQL, a comparison with NULL is
+    /// unknown (None), and only the rows evaluated to `Some(true)` match.
+    /// LIKE patterns are matched case-insensitively, as the default collations do.
+    pub fn evaluate(&self, column: &impl Fn(&str) -> Option<Value>) -> Option<bool> {
+        let compare = |name: &str, value: &Value, accept: fn(Ordering) -> bool| {
+            let current = column(name)?;
+            current.partial_cmp(value).map(accept)
+        };
+        match self {
+            Filter::Eq(name, value) => compare(name, value, Ordering::is_eq),
+            Filter::Ne(name, value) => compare(name, value, Ordering::is_ne),
+            Filter::Gt(name, value) => compare(name, value, Ordering::is_gt),
+            Filter::Gte(name, value) => compare(name, value, Ordering::is_ge),
+            Filter::Lt(name, value) => compare(name, value, Ordering::is_lt),
+            Filter::Lte(name, value) => compare(name, value, Ordering::is_le),
+            Filter::In(name, values) => {
+                let current = column(name)?;
+                Some(values.iter().any(|value| &current == value))
+            }
+            Filter::Contains(name, text) => match column(name)? {
+                Value::Text(current) => Some(
+                    current
+                        .to_lowercase()
+                        .contains(text.to_lowercase().as_str()),
+                ),
+                _ => None,
+            },
+            Filter::Like(name, pattern) => match column(name)? {
+                Value::Text(current) => Some(like_matches(
+                    &current.to_lowercase(),
+                    &pattern.to_lowercase(),
+                )),
+                _ => None,
+            },
+            Filter::IsNull(name) => Some(column(name).is_none()),
+            Filter::IsNotNull(name) => Some(column(name).is_some()),
+            Filter::And(filters) => {
+                let mut result = Some(true);
+                for filter in filters {
+                    match filter.evaluate(column) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => {}
+                    }
+                }
+                result
+            }
+            Filter::Or(filters) => {
+                let mut result = Some(false);
+                for filter in filters {
+                    match filter.evaluate(column) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result
+            }
+            Filter::Not(filter) => filter.evaluate(column).map(|matched| !matched),
+            Filter::Sql(_, _) => None,
+        }
+    }
+
+    /// Whether the row matches, see `evaluate`.
+    pub fn matches(&self, column: &impl Fn(&str) -> Option<Value>) -> bool {
+        self.evaluate(column) == Some(true)
+    }
+}
+
+/// Escape the LIKE wildcards of `text`, so that it is matched literally.
+pub fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == LIKE_ESCAPE || c == '%' || c == '_' {
+            escaped.push(LIKE_ESCAPE);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Match `text` against a LIKE `pattern` escaped with `LIKE_ESCAPE`.
+fn like_matches(text: &str, pattern: &str) -> bool {
+    enum Token {
+        Char(char),
+        AnyChar,
+        AnyString,
+    }
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            LIKE_ESCAPE => Token::Char(chars.next().unwrap_or(LIKE_ESCAPE)),
+            '%' => Token::AnyString,
+            '_' => Token::AnyChar,
+            c => Token::Char(c),
+        });
+    }
+    let text = text.chars().collect::<Vec<_>>();
+    // matched[i] tells whether the tokens seen so far can match text[..i].
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for token in tokens {
+        let mut next = vec![false; text.len() + 1];
+        for i in 0..=text.len() {
+            next[i] = match token {
+                Token::AnyString => matched[i] || (i > 0 && next[i - 1]),
+                Token::AnyChar => i > 0 && matched[i - 1],
+                Token::Char(c) => i > 0 && matched[i - 1] && text[i - 1] == c,
+            };
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Sort the rows by a column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderBy {
+    pub column: &'static str,
+    pub direction: Direction,
+}
+
+impl OrderBy {
+    pub fn asc(column: &'static str) -> Self {
+        Self {
+            column,
+            direction: Direction::Asc,
+        }
+    }
+
+    pub fn desc(column: &'static str) -> Self {
+        Self {
+            column,
+            direction: Direction::Desc,
+        }
+    }
+
+    /// Sort `rows` outside of a database, NULL coming first as in MySQL and SQLite.
+    pub fn sort<T>(
+        order_by: &[OrderBy],
+        rows: &mut [T],
+        column: impl Fn(&T, &str) -> Option<Value>,
+    ) {
+        if order_by.is_empty() {
+            return;
+        }
+        rows.sort_by(|a, b| {
+            for order in order_by {
+                let ordering = match (column(a, order.column), column(b, order.column)) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Less,
+                    (Some(_), None) => Ordering::Greater,
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                };
+                let ordering = match order.direction {
+                    Direction::Asc => ordering,
+                    Direction::Desc => ordering.reverse(),
+                };
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+}
+
+/// The clauses following the FROM of a SELECT: the filter, the ordering and the paging.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Clauses {
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl Clauses {
+    /// Write the clauses with `?` placeholders, returning the values to bind in order.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let mut sql = String::new();
+        if !self.filter.is_all() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.filter.to_sql(&mut params));
+        }
+        if !self.order_by.is_empty() {
+            let columns = self
+                .order_by
+                .iter()
+                .map(|order| match order.direction {
+                    Direction::Asc => format!("{} ASC", order.column),
+                    Direction::Desc => format!("{} DESC", order.column),
+                })
+                .collect::<Vec<_>>();
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&columns.join(", "));
+        }
+        // MySQL has no OFFSET without LIMIT, so an offset alone gets the largest limit.
+        match (self.limit, self.offset) {
+            (None, None) => {}
+            (limit, offset) => {
+                sql.push_str(" LIMIT ?");
+                params.push(limit.map(Value::from).unwrap_or(Value::Int(i64::MAX)));
+                if let Some(offset) = offset {
+                    sql.push_str(" OFFSET ?");
+                    params.push(offset.into());
+                }
+            }
+        }
+        (sql, params)
+    }
+
+    /// The same filter without the ordering and the paging, to count the matching rows.
+    pub fn without_paging(&self) -> Clauses {
+        Clauses {
+            filter: self.filter.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Apply the ordering and the paging to rows already filtered outside of a database.
+    pub fn page<T>(&self, mut rows: Vec<T>, column: impl Fn(&T, &str) -> Option<Value>) -> Vec<T> {
+        OrderBy::sort(&self.order_by, &mut rows, column);
+        rows.into_iter()
+            .skip(self.offset.unwrap_or(0) as usize)
+            .take(self.limit.map(|limit| limit as usize).unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Assignment {
+    To(Value),
+    Null,
+    Increment,
+}
+
+/// The `column = ?` assignments of an UPDATE.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assignments(Vec<(&'static str, Assignment)>);
+
+impl Assignments {
+    pub fn set(&mut self, column: &'static str, value: impl Into<Value>) {
+        self.0.push((column, Assignment::To(value.into())));
+    }
+
+    /// Assign the value when there is one.
+    pub fn set_some<V: Into<Value>>(&mut self, column: &'static str, value: Option<V>) {
+        if let Some(value) = value {
+            self.set(column, value);
+        }
+    }
+
+    /// Assign the value, or NULL when there is none.
+    pub fn set_or_null<V: Into<Value>>(&mut self, column: &'static str, value: Option<V>) {
+        match value {
+            Some(value) => self.set(column, value),
+            None => self.0.push((column, Assignment::Null)),
+        }
+    }
+
+    /// Add one to the current value of the column, as `column = column + 1`.
+    pub fn increment(&mut self, column: &'static str) {
+        self.0.push((column, Assignment::Increment));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Write the assignments, pushing the values to bind to `params`.
+    pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        self.0
+            .iter()
+            .map(|(column, assignment)| match assignment {
+                Assignment::To(value) => {
+                    params.push(value.clone());
+                    format!("{} = ?", column)
+                }
+                Assignment::Null => format!("{} = NULL", column),
+                Assignment::Increment => format!("{0} = {0} + 1", column),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Bind a list of values to a query, in order.
+pub trait BindValues: Sized {
+    fn bind_value(self, value: Value) -> Self;
+
+    fn bind_values(self, values: Vec<Value>) -> Self {
+        values.into_iter().fold(self, Self::bind_value)
+    }
+}
+
+impl<'q> BindValues for Query<'q, MySql, MySqlArguments> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(val),
+        }
+    }
+}
+
+impl<'q, O> BindValues for QueryAs<'q, MySql, O, MySqlArguments> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(val),
+        }
+    }
+}
+
+impl<'q, O> BindValues for QueryScalar<'q, MySql, O, MySqlArguments> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(val),
+        }
+    }
+}
+
+// The portable schema stores booleans as 0/1 integers.
+impl<'q> BindValues for Query<'q, Any, AnyArguments<'q>> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(i64::from(val)),
+        }
+    }
+}
+
+impl<'q, O> BindValues for QueryAs<'q, Any, O, AnyArguments<'q>> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(i64::from(val)),
+        }
+    }
+}
+
+impl<'q, O> BindValues for QueryScalar<'q, Any, O, AnyArguments<'q>> {
+    fn bind_value(self, value: Value) -> Self {
+        match value {
+            Value::Int(val) => self.bind(val),
+            Value::Text(val) => self.bind(val),
+            Value::Bool(val) => self.bind(i64::from(val)),
+        }
+    }
+}
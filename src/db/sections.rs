@@ -1,145 +1,168 @@
-use loggit::{trace, warn};
-use std::fmt::format;
+use crate::db::nodes::{self, GetNodesForm, NodeFromDb};
+use crate::db::query::{Filter, OrderBy};
+use crate::db::{found_pair, LookupError, OrAnd, PairLookupError};
+use crate::error::Error;
 
-use crate::db::{OrAnd, VecWrapper};
+/// The kind given to the root nodes created through the sections view.
+pub const SECTION_KIND: &str = "section";
 
+#[derive(Default)]
 pub struct CreateSectionForm {
     pub title: String,
+    /// `nodes::PUBLISHED` when None.
+    pub status: Option<String>,
+    pub publish_at: Option<i64>,
+    /// The title slugified when None.
+    pub slug: Option<String>,
 }
 
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default)]
+/// A section is a compatibility view over a root node of the content tree.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
 pub struct SectionFromDb {
     pub id: u32,
     pub title: String,
     pub position: u32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub version: u32,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    pub slug: String,
+    /// JSON array of the previous slugs, see `NodeFromDb::previous_slug_list`.
+    pub previous_slugs: String,
 }
 
-pub async fn get_max_position(pool: &sqlx::Pool<sqlx::MySql>) -> Option<u32> {
-    let pre_query_str = format!("SELECT MAX(position) FROM sections");
-    let query_str = pre_query_str.as_str();
-    let query = sqlx::query_scalar(query_str);
+impl From<NodeFromDb> for SectionFromDb {
+    fn from(value: NodeFromDb) -> Self {
+        Self {
+            id: value.id,
+            title: value.title,
+            position: value.position,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs: value.previous_slugs,
+        }
+    }
+}
 
-    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
-    max.unwrap_or(None)
+pub async fn get_max_position(pool: &sqlx::Pool<sqlx::MySql>) -> Option<u32> {
+    nodes::get_max_position_in_parent(pool, None).await
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct GetSectionsForm {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
+    /// Only the sections published at this time.
+    pub published_at: Option<i64>,
     pub or_and: OrAnd,
+    /// Further conditions on the `id`, `title` and `position` columns.
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
     pub limit: Option<u32>,
+    pub offset: Option<u32>,
 }
 
 impl GetSectionsForm {
-    fn to_conditions_params(&self) -> (Vec<String>, Vec<VecWrapper>) {
-        let mut conditions: Vec<String> = Vec::new();
-        let mut params: Vec<VecWrapper> = Vec::new();
-
-        if self.id.is_some() {
-            conditions.push("id = ?".to_string());
-            params.push(VecWrapper::Num(self.id.unwrap()));
-        }
-        if let Some(title) = &self.title {
-            conditions.push("title = ?".to_string());
-            params.push(VecWrapper::String(title.clone()));
-        }
-        if self.position.is_some() {
-            conditions.push("position = ?".to_string());
-            params.push(VecWrapper::Num(self.position.unwrap()));
-        }
-        (conditions, params)
-    }
-}
-
-impl Default for GetSectionsForm {
-    fn default() -> Self {
-        Self {
-            id: Default::default(),
-            title: Default::default(),
-            position: Default::default(),
-            or_and: Default::default(),
-            limit: None,
+    pub(crate) fn to_nodes_form(&self) -> GetNodesForm {
+        GetNodesForm {
+            id: self.id,
+            title: self.title.clone(),
+            position: self.position,
+            is_root: Some(true),
+            published_at: self.published_at,
+            or_and: self.or_and.clone(),
+            filter: self.filter.clone(),
+            order_by: self.order_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
+            ..Default::default()
         }
     }
 }
 
 pub enum GetSectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn get_sections(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSectionsForm,
 ) -> Result<Vec<SectionFromDb>, GetSectionsError> {
-    let (conditions, params) = form.to_conditions_params();
-    let pre_query_str = format!(
-        "SELECT * FROM sections {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => {
-                format!("LIMIT {}", val)
-            }
-        }
-    );
-    let query_str = pre_query_str.as_str();
-    trace!("{}", query_str);
-    let mut query = sqlx::query_as(query_str);
-
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
+    nodes::get_nodes(pool, form.to_nodes_form())
+        .await
+        .map(|list| list.into_iter().map(SectionFromDb::from).collect())
+        .map_err(|nodes::GetNodesError::UnexpectedError(err)| {
+            GetSectionsError::UnexpectedError(err)
+        })
+}
 
-    let sections: Vec<SectionFromDb> = query.fetch_all(pool).await.unwrap();
-    Ok(sections)
+pub async fn count_sections(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetSectionsForm,
+) -> Result<u64, GetSectionsError> {
+    nodes::count_nodes(pool, form.to_nodes_form())
+        .await
+        .map_err(|nodes::GetNodesError::UnexpectedError(err)| {
+            GetSectionsError::UnexpectedError(err)
+        })
 }
 
 pub async fn create_section(
     pool: &sqlx::Pool<sqlx::MySql>,
     section_form: CreateSectionForm,
-) -> Result<(), ()> {
-    let next_pos = match get_max_position(pool).await {
-        Some(num) => num + 1,
-        None => 0,
-    };
-
-    let res = sqlx::query!(
-        "INSERT INTO sections (title, position) VALUES (?, ?)",
-        section_form.title,
-        next_pos
+) -> Result<(), Error> {
+    nodes::create_node(
+        pool,
+        nodes::CreateNodeForm {
+            parent_id: None,
+            kind: SECTION_KIND.to_string(),
+            title: section_form.title,
+            status: section_form.status,
+            publish_at: section_form.publish_at,
+            slug: section_form.slug,
+        },
     )
-    .execute(pool)
-    .await;
-    match res {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
+    .await
+    .map(|_| ())
+    .map_err(|err| match err {
+        nodes::CreateNodeError::UnexpectedError(err) => err,
+        nodes::CreateNodeError::ParentNotFoundError => Error::internal("a section has no parent"),
+    })
 }
 
+#[derive(Default)]
 pub struct UpdateSectionForm {
     pub title: Option<String>,
+    pub status: Option<String>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    pub slug: Option<String>,
+    pub previous_slugs: Option<Vec<String>>,
+    /// Only update the sections still at this version.
+    pub version: Option<u32>,
 }
 impl UpdateSectionForm {
     pub fn is_all_none(&self) -> bool {
         self.title.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+            && self.slug.is_none()
+            && self.previous_slugs.is_none()
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum UpdateSectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    VersionConflictError,
 }
 
 pub async fn update_sections(
@@ -147,77 +170,54 @@ pub async fn update_sections(
     section_form: UpdateSectionForm,
     identified_by: GetSectionsForm,
 ) -> Result<(), UpdateSectionsError> {
-    //verify if there's no such section to update
-    if section_form.is_all_none() {
-        return Err(UpdateSectionsError::NothingToUpdateError);
-    }
-    let sections_q = get_sections(pool, identified_by.clone()).await;
-    if sections_q.is_err() {
-        return Err(UpdateSectionsError::UnexpectedError);
-    }
-    if let Ok(res) = sections_q {
-        if res.is_empty() {
-            return Err(UpdateSectionsError::NotFoundError);
-        }
-    }
-
-    //updating query
-    let mut update_columns: Vec<String> = Vec::new();
-    let mut update_params: Vec<VecWrapper> = Vec::new();
-    let mut conditions: Vec<String> = Vec::new();
-    let mut params: Vec<VecWrapper> = Vec::new();
-
-    if section_form.title.is_some() {
-        update_columns.push("title = ?".to_string());
-        update_params.push(VecWrapper::String(section_form.title.unwrap()));
-    }
-
-    let (conditions, params) = identified_by.to_conditions_params();
-
-    let pre_query_str = format!(
-        "UPDATE sections SET {} {} {}",
-        update_columns.join(", "),
-        if conditions.is_empty() { "" } else { "WHERE" },
-        conditions.join(match identified_by.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        })
-    );
-    let query_str = pre_query_str.as_str();
-
-    let mut query = sqlx::query(query_str);
-
-    for param in update_params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-    //trace!("{}", query);
-    let res = query.execute(pool).await;
+    let res = nodes::update_nodes(
+        pool,
+        nodes::UpdateNodeForm {
+            title: section_form.title,
+            kind: None,
+            parent_id: None,
+            position: None,
+            status: section_form.status,
+            publish_at: section_form.publish_at,
+            slug: section_form.slug,
+            previous_slugs: section_form.previous_slugs,
+            version: section_form.version,
+        },
+        identified_by.to_nodes_form(),
+    )
+    .await;
     match res {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            warn!("{:?}", e);
-            Err(UpdateSectionsError::UnexpectedError)
+        Ok(()) => Ok(()),
+        Err(nodes::UpdateNodesError::NotFoundError) => Err(UpdateSectionsError::NotFoundError),
+        Err(nodes::UpdateNodesError::NothingToUpdateError) => {
+            Err(UpdateSectionsError::NothingToUpdateError)
+        }
+        Err(nodes::UpdateNodesError::VersionConflictError) => {
+            Err(UpdateSectionsError::VersionConflictError)
         }
+        Err(nodes::UpdateNodesError::UnexpectedError(err)) => {
+            Err(UpdateSectionsError::UnexpectedError(err))
+        }
+        Err(nodes::UpdateNodesError::CycleError) => Err(UpdateSectionsError::UnexpectedError(
+            Error::internal("a section cannot be moved under itself"),
+        )),
     }
 }
 
 pub enum GetSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
+impl LookupError for GetSectionError {
+    fn into_unexpected(self) -> Option<Error> {
+        match self {
+            GetSectionError::UnexpectedError(err) => Some(err),
+            GetSectionError::NotFoundError => None,
+        }
+    }
+}
+
 pub async fn get_section(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSectionsForm,
@@ -239,7 +239,7 @@ pub async fn get_section(
             }
         }
         Err(err) => match err {
-            GetSectionsError::UnexpectedError => Err(GetSectionError::UnexpectedError),
+            GetSectionsError::UnexpectedError(err) => Err(GetSectionError::UnexpectedError(err)),
         },
     }
 }
@@ -247,12 +247,38 @@ pub async fn get_section(
 #[derive(Debug, PartialEq, Eq)]
 pub enum SwapSectionsError {
     NotFoundError((Option<u32>, Option<u32>)),
-    UnexpectedError,
+    UnexpectedError(Error),
+    /// A section is not at its expected version, nothing was swapped.
+    VersionConflictError,
+}
+
+impl From<PairLookupError> for SwapSectionsError {
+    fn from(err: PairLookupError) -> Self {
+        match err {
+            PairLookupError::UnexpectedError(err) => SwapSectionsError::UnexpectedError(err),
+            PairLookupError::NotFoundError(ids) => SwapSectionsError::NotFoundError(ids),
+        }
+    }
+}
+
+impl From<nodes::SwapNodesError> for SwapSectionsError {
+    fn from(err: nodes::SwapNodesError) -> Self {
+        match err {
+            nodes::SwapNodesError::NotFoundError(ids) => SwapSectionsError::NotFoundError(ids),
+            nodes::SwapNodesError::UnexpectedError(err) => SwapSectionsError::UnexpectedError(err),
+            nodes::SwapNodesError::VersionConflictError => SwapSectionsError::VersionConflictError,
+            nodes::SwapNodesError::CantSwapFromDifferentParents => {
+            SwapSectionsError::UnexpectedError(Error::internal("sections are always siblings"))
+            }
+        }
+    }
 }
 
+/// Swap the positions of two sections, written only at their expected `versions`.
 pub async fn swap_sections(
     pool: &sqlx::Pool<sqlx::MySql>,
     ids: [u32; 2],
+    versions: [u32; 2],
 ) -> Result<(), SwapSectionsError> {
     let section_1 = get_section(
         pool,
@@ -270,97 +296,25 @@ pub async fn swap_sections(
         },
     )
     .await;
-    let mut ret_err_nf = (None, None);
-    if section_1.is_err() {
-        ret_err_nf = (Some(ids[0]), ret_err_nf.1);
-    }
-    if section_2.is_err() {
-        ret_err_nf = (ret_err_nf.0, Some(ids[1]));
-    }
-    if ret_err_nf.0.is_some() || ret_err_nf.1.is_some() {
-        return Err(SwapSectionsError::NotFoundError(ret_err_nf));
-    }
-
-    let max_pos = get_max_position(pool).await.unwrap_or_default() + 1;
-    let section_1 = section_1.unwrap_or_default();
-    let section_2 = section_2.unwrap_or_default();
-    let (sec_1_id, sec_1_pos) = (section_1.id, section_1.position);
-    let (sec_2_id, sec_2_pos) = (section_2.id, section_2.position);
-
-    let pre_query_str = "\
-    UPDATE sections \
-    SET position = ? WHERE id =  ?\
-        "
-    .to_string();
-    let query = sqlx::query(&pre_query_str)
-        .bind(max_pos)
-        .bind(sec_1_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapSectionsError::UnexpectedError);
-    }
-    let query = sqlx::query(&pre_query_str)
-        .bind(sec_1_pos)
-        .bind(sec_2_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapSectionsError::UnexpectedError);
-    }
-    let query = sqlx::query(&pre_query_str)
-        .bind(sec_2_pos)
-        .bind(sec_1_id)
-        .execute(pool)
-        .await;
-    query
-        .map_err(|err| {
-            warn!("{:?}", err);
-            SwapSectionsError::UnexpectedError
-        })
-        .map(|_| ())
+    found_pair(ids, [section_1, section_2])?;
+    nodes::swap_nodes(pool, ids, versions)
+        .await
+        .map_err(SwapSectionsError::from)
 }
 
 pub enum DeleteSectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn delete_sections(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSectionsForm,
 ) -> Result<(), DeleteSectionsError> {
-    let (conditions, params) = form.to_conditions_params();
-
-    let pre_query_str = format!(
-        "DELETE FROM sections {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => {
-                format!("LIMIT {}", val)
-            }
-        }
-    );
-
-    let query_str = pre_query_str.as_str();
-    trace!("{}", query_str);
-    let mut query = sqlx::query(query_str);
-
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-
-    let res = query.execute(pool).await;
-    res.map_err(|_| DeleteSectionsError::UnexpectedError)
-        .map(|_| ())
+    nodes::delete_nodes(pool, form.to_nodes_form())
+        .await
+        .map_err(|nodes::DeleteNodesError::UnexpectedError(err)| {
+            DeleteSectionsError::UnexpectedError(err)
+        })
 }
 
 pub async fn delete_section(
@@ -1,6 +1,7 @@
+use crate::error::Error;
 use loggit::trace;
 
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq)]
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Clone)]
 pub struct SessionFromDb {
     pub id: u32,
     pub user_id: u32,
@@ -17,47 +18,47 @@ pub struct CreateSessionForm {
 pub async fn create_session(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: CreateSessionForm,
-) -> Result<(), ()> {
+) -> Result<(), Error> {
     let res = sqlx::query("INSERT INTO sessions (user_id, token, expires_at) VALUES (?, ?, ?)")
         .bind(form.user_id)
         .bind(form.token)
         .bind(form.expires_at)
         .execute(pool)
         .await;
-    res.map_err(|_| ()).map(|_| ())
+    res.map_err(Error::from).map(|_| ())
 }
 
 pub async fn get_session_by_token(
     pool: &sqlx::Pool<sqlx::MySql>,
     token: String,
-) -> Result<SessionFromDb, ()> {
+) -> Result<SessionFromDb, Error> {
     let query_str = "SELECT * FROM sessions WHERE token = ? LIMIT 1";
     trace!("{}", query_str);
     let res = sqlx::query_as::<_, SessionFromDb>(query_str)
         .bind(token)
         .fetch_one(pool)
         .await;
-    res.map_err(|_| ())
+    res.map_err(Error::from)
 }
 
 pub async fn delete_session_by_token(
     pool: &sqlx::Pool<sqlx::MySql>,
     token: String,
-) -> Result<(), ()> {
+) -> Result<(), Error> {
     let res = sqlx::query("DELETE FROM sessions WHERE token = ? LIMIT 1")
         .bind(token)
         .execute(pool)
         .await;
-    res.map_err(|_| ()).map(|_| ())
+    res.map_err(Error::from).map(|_| ())
 }
 
 pub async fn delete_sessions_by_user(
     pool: &sqlx::Pool<sqlx::MySql>,
     user_id: u32,
-) -> Result<(), ()> {
+) -> Result<(), Error> {
     let res = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
         .bind(user_id)
         .execute(pool)
         .await;
-    res.map_err(|_| ()).map(|_| ())
+    res.map_err(Error::from).map(|_| ())
 }
@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// How the letters outside ASCII end up in the slugs, set by `SLUG_STYLE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlugStyle {
+    /// Spelled with ASCII letters: `Лінійна алгебра` becomes `liniina-alhebra`.
+    #[default]
+    Transliterate,
+    /// Kept as they are, the generated pages percent-encoding them in their links.
+    Unicode,
+}
+
+impl SlugStyle {
+    /// The style picked by the `SLUG_STYLE` environment variable, `transliterate`
+    /// (the default) or `unicode`.
+    pub fn configured() -> Self {
+        match std::env::var("SLUG_STYLE").as_deref() {
+            Ok("unicode") => SlugStyle::Unicode,
+            _ => SlugStyle::Transliterate,
+        }
+    }
+}
+
+/// The ASCII spelling of a lowercase letter: Latin letters lose their
+/// diacritics, Cyrillic follows the Ukrainian national transliteration (with the
+/// Russian letters it lacks) and Greek follows ELOT 743. None for the other
+/// characters.
+///
+/// Accented letters are decomposed the way `search::fold_char` folds them, so
+/// only the letters without a canonical decomposition (or spelled apart from
+/// their base letter, as `й` and `ё`) are listed.
+fn transliterate(c: char) -> Option<&'static str> {
+    const LATIN: &str = "abcdefghijklmnopqrstuvwxyz";
+    let ascii = match c {
+        'a'..='z' => &LATIN[(c as usize - 'a' as usize)..][..1],
+        'đ' | 'ð' => "d",
+        'ħ' => "h",
+        'ı' => "i",
+        'ŀ' | 'ł' => "l",
+        'ø' => "o",
+        'ŧ' => "t",
+        'æ' => "ae",
+        'œ' => "oe",
+        'ß' => "ss",
+        'þ' => "th",
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' => "h",
+        'ґ' => "g",
+        'д' => "d",
+        'е' | 'э' => "e",
+        'є' => "ie",
+        'ё' => "io",
+        'ж' => "zh",
+        'з' => "z",
+        'и' => "y",
+        'і' => "i",
+        'й' => "i",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ы' => "y",
+        'ю' => "iu",
+        'я' => "ia",
+        'ь' | 'ъ' | 'ʼ' => "",
+        'α' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' => "e",
+        'ζ' => "z",
+        'η' => "i",
+        'θ' => "th",
+        'ι' => "i",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        'ω' => "o",
+        _ => return decomposed_base(c).and_then(transliterate),
+    };
+    Some(ascii)
+}
+
+/// The base letter of a letter with diacritics, None when `c` has no canonical
+/// decomposition into a letter followed by combining marks.
+fn decomposed_base(c: char) -> Option<char> {
+    let mut parts = Vec::new();
+    decompose_canonical(c, |part| parts.push(part));
+    match parts.split_first() {
+        Some((&base, marks))
+            if base != c && !marks.is_empty() && marks.iter().all(|&m| is_combining_mark(m)) =>
+        {
+            Some(base)
+        }
+        _ => None,
+    }
+}
+
+/// The Ukrainian iotated letters spelled differently at the start of a word.
+fn word_start_transliteration(c: char) -> Option<&'static str> {
+    match c {
+        'є' => Some("ye"),
+        'ї' => Some("yi"),
+        'й' => Some("y"),
+        'ю' => Some("yu"),
+        'я' => Some("ya"),
+        _ => None,
+    }
+}
+
+/// The URL segment derived from a title, in the configured `SlugStyle`.
+pub fn slugify(value: &str) -> String {
+    slugify_with(value, SlugStyle::configured())
+}
+
+/// The URL segment derived from a title: lowercase letters and digits separated
+/// by single dashes, spelled with ASCII letters unless `style` keeps Unicode.
+pub fn slugify_with(value: &str, style: SlugStyle) -> String {
+    let mut slug = String::new();
+    let mut previous_dash = false;
+
+    for ch in value.chars().flat_map(char::to_lowercase) {
+        // Apostrophes and combining accents, left by decomposed text, vanish
+        // rather than split words.
+        if matches!(ch, '\'' | '’' | 'ʼ' | '\u{300}'..='\u{36f}') {
+            continue;
+        }
+        let keep =
+            ch.is_ascii_alphanumeric() || (style == SlugStyle::Unicode && ch.is_alphanumeric());
+        let letters = if keep {
+            Some(ch.to_string())
+        } else if style == SlugStyle::Transliterate {
+            let word_start = slug.is_empty() || previous_dash;
+            word_start_transliteration(ch)
+                .filter(|_| word_start)
+                .or_else(|| transliterate(ch))
+                .map(str::to_string)
+        } else {
+            None
+        };
+        match letters {
+            Some(letters) if letters.is_empty() => {}
+            Some(letters) => {
+                slug.push_str(&letters);
+                previous_dash = false;
+            }
+            None if !previous_dash => {
+                slug.push('-');
+                previous_dash = true;
+            }
+            None => {}
+        }
+    }
+
+    let slug = slug.trim_matches('-').to_string();
+    if slug.is_empty() {
+        "note".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Whether `slug` is a valid slug, as `slugify` writes them.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty() && slugify(slug) == slug
+}
+
+/// `base`, or `base-2`, `base-3`, ... when it is already taken.
+pub fn unique_slug(base: &str, taken: &HashSet<String>) -> String {
+    let mut candidate = base.to_string();
+    let mut suffix = 2usize;
+    while taken.contains(&candidate) {
+        candidate = format!("{base}-{suffix}");
+        suffix += 1;
+    }
+    candidate
+}
+
+/// Why a requested slug can not be used.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SlugError {
+    /// Not a slug, see `is_valid_slug`.
+    InvalidSlugError,
+    /// Already used by a sibling.
+    SlugTakenError,
+}
+
+/// The slug of a new row among `siblings`: the requested one, or the title
+/// slugified with a suffix when another row already uses it.
+pub fn slug_for_new(
+    requested: Option<String>,
+    title: &str,
+    siblings: &HashSet<String>,
+) -> Result<String, SlugError> {
+    match requested {
+        Some(slug) if !is_valid_slug(&slug) => Err(SlugError::InvalidSlugError),
+        Some(slug) if siblings.contains(&slug) => Err(SlugError::SlugTakenError),
+        Some(slug) => Ok(slug),
+        None => Ok(unique_slug(&slugify(title), siblings)),
+    }
+}
+
+/// The new slug of a row along with the slugs it had before, which keep
+/// redirecting to it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SlugChange {
+    pub slug: String,
+    pub previous_slugs: Vec<String>,
+}
+
+/// What an update does to the slug `current` of a row among `siblings` (the row
+/// itself excluded). A row moved next to a sibling using its slug gets a suffix.
+/// None when the slug stays the same.
+pub fn slug_update(
+    current: &str,
+    previous_slugs: Vec<String>,
+    requested: Option<String>,
+    siblings: &HashSet<String>,
+) -> Result<Option<SlugChange>, SlugError> {
+    let slug = match requested {
+        Some(slug) if !is_valid_slug(&slug) => return Err(SlugError::InvalidSlugError),
+        Some(slug) if siblings.contains(&slug) => return Err(SlugError::SlugTakenError),
+        Some(slug) => slug,
+        None if siblings.contains(current) => unique_slug(current, siblings),
+        None => return Ok(None),
+    };
+    if slug == current {
+        return Ok(None);
+    }
+    let mut previous_slugs: Vec<String> = previous_slugs
+        .into_iter()
+        .filter(|previous| *previous != slug && previous != current)
+        .collect();
+    if !current.is_empty() {
+        previous_slugs.push(current.to_string());
+    }
+    Ok(Some(SlugChange {
+        slug,
+        previous_slugs,
+    }))
+}
+
+/// Encode the previous slugs as stored in the `previous_slugs` JSON column.
+pub(crate) fn previous_slugs_to_json(slugs: &[String]) -> String {
+    serde_json::to_string(slugs).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Decode the `previous_slugs` JSON column.
+pub fn previous_slugs_from_json(value: &str) -> Vec<String> {
+    serde_json::from_str(value).unwrap_or_default()
+}
+
+/// A row of `nodes` or `notes` without a slug yet, from the rows created before
+/// slugs were stored.
+pub(crate) struct SlugRow {
+    pub id: u32,
+    /// The parent node, the slugs being unique among siblings.
+    pub parent_id: Option<u32>,
+    pub title: String,
+    pub slug: String,
+}
+
+/// The slugs to give to the rows with an empty one, derived from their titles so
+/// that the generated page names stay the same. `rows` are in position order:
+/// the first of two rows with the same title keeps the bare slug.
+pub(crate) fn missing_slugs(rows: &[SlugRow]) -> Vec<(u32, String)> {
+    let mut taken: HashMap<Option<u32>, HashSet<String>> = HashMap::new();
+    for row in rows.iter().filter(|row| !row.slug.is_empty()) {
+        taken
+            .entry(row.parent_id)
+            .or_default()
+            .insert(row.slug.clone());
+    }
+    let mut assigned = Vec::new();
+    for row in rows.iter().filter(|row| row.slug.is_empty()) {
+        let siblings = taken.entry(row.parent_id).or_default();
+        let slug = unique_slug(&slugify(&row.title), siblings);
+        siblings.insert(slug.clone());
+        assigned.push((row.id, slug));
+    }
+    assigned
+}
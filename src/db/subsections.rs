@@ -1,159 +1,219 @@
-use crate::db::{OrAnd, VecWrapper};
+use crate::db::nodes::{self, GetNodesForm, NodeFromDb};
+use crate::db::query::{Filter, OrderBy};
+use crate::db::{found_pair, LookupError, OrAnd, PairLookupError};
+use crate::error::Error;
 
-use loggit::{trace, warn};
+/// The kind given to the second-level nodes created through the subsections view.
+pub const SUBSECTION_KIND: &str = "subsection";
 
+#[derive(Default)]
 pub struct CreateSubsectionForm {
     pub title: String,
     pub section_id: u32,
+    /// `nodes::PUBLISHED` when None.
+    pub status: Option<String>,
+    pub publish_at: Option<i64>,
+    /// The title slugified when None.
+    pub slug: Option<String>,
 }
 
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default)]
+/// A subsection is a compatibility view over a node whose parent is a root node.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
 pub struct SubsectionFromDb {
     pub id: u32,
     pub title: String,
     pub position: u32,
     pub section_id: u32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub version: u32,
+    pub status: String,
+    pub publish_at: Option<i64>,
+    pub slug: String,
+    /// JSON array of the previous slugs, see `NodeFromDb::previous_slug_list`.
+    pub previous_slugs: String,
+}
+
+impl From<NodeFromDb> for SubsectionFromDb {
+    fn from(value: NodeFromDb) -> Self {
+        Self {
+            id: value.id,
+            title: value.title,
+            position: value.position,
+            section_id: value.parent_id.unwrap_or_default(),
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs: value.previous_slugs,
+        }
+    }
 }
 
 pub async fn get_max_subsection_position_in_section(
     pool: &sqlx::Pool<sqlx::MySql>,
     section_id: u32,
 ) -> Option<u32> {
-    let pre_query_str = format!("SELECT MAX(position) FROM subsections WHERE section_id = ?");
-    let query_str = pre_query_str.as_str();
-    let query = sqlx::query_scalar(query_str).bind(section_id);
-
-    let max: Result<Option<u32>, sqlx::Error> = query.fetch_one(pool).await;
-    max.unwrap_or(None)
+    nodes::get_max_position_in_parent(pool, Some(section_id)).await
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct GetSubsectionsForm {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
     pub section_id: Option<u32>,
+    /// Only the subsections published at this time, along with their section.
+    pub published_at: Option<i64>,
     pub or_and: OrAnd,
+    /// Further conditions on the `id`, `title`, `position` and `section_id` columns.
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
     pub limit: Option<u32>,
+    pub offset: Option<u32>,
 }
 
-impl GetSubsectionsForm {
-    fn to_conditions_params(&self) -> (Vec<String>, Vec<VecWrapper>) {
-        let mut conditions: Vec<String> = Vec::new();
-        let mut params: Vec<VecWrapper> = Vec::new();
-
-        if let Some(id) = self.id {
-            conditions.push("id = ?".to_string());
-            params.push(VecWrapper::Num(id));
-        }
-        if let Some(title) = &self.title {
-            conditions.push("title = ?".to_string());
-            params.push(VecWrapper::String(title.clone()));
-        }
-        if let Some(position) = self.position {
-            conditions.push("position = ?".to_string());
-            params.push(VecWrapper::Num(position));
-        }
-        if let Some(section_id) = self.section_id {
-            conditions.push("section_id = ?".to_string());
-            params.push(VecWrapper::Num(section_id));
-        }
-        (conditions, params)
+/// Subsections are nodes, whose parent is their section.
+fn node_column(column: &'static str) -> &'static str {
+    match column {
+        "section_id" => "parent_id",
+        column => column,
     }
 }
 
-impl Default for GetSubsectionsForm {
-    fn default() -> Self {
-        Self {
-            id: Default::default(),
-            title: Default::default(),
-            position: Default::default(),
-            section_id: Default::default(),
-            or_and: Default::default(),
-            limit: None,
+impl GetSubsectionsForm {
+    pub(crate) fn to_nodes_form(&self) -> GetNodesForm {
+        GetNodesForm {
+            id: self.id,
+            parent_id: self.section_id,
+            title: self.title.clone(),
+            position: self.position,
+            depth: Some(1),
+            published_at: self.published_at,
+            or_and: self.or_and.clone(),
+            filter: self.filter.clone().map_columns(&node_column),
+            order_by: self
+                .order_by
+                .iter()
+                .map(|order| OrderBy {
+                    column: node_column(order.column),
+                    direction: order.direction,
+                })
+                .collect(),
+            limit: self.limit,
+            offset: self.offset,
+            ..Default::default()
         }
     }
 }
 
 #[derive(Debug)]
 pub enum GetSubsectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn get_subsections(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSubsectionsForm,
 ) -> Result<Vec<SubsectionFromDb>, GetSubsectionsError> {
-    let (conditions, params) = form.to_conditions_params();
-    let pre_query_str = format!(
-        "SELECT * FROM subsections {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => format!("LIMIT {}", val),
-        }
-    );
-    let query_str = pre_query_str.as_str();
-    trace!("{}", query_str);
-    let mut query = sqlx::query_as::<_, SubsectionFromDb>(query_str);
+    nodes::get_nodes(pool, form.to_nodes_form())
+        .await
+        .map(|list| list.into_iter().map(SubsectionFromDb::from).collect())
+        .map_err(|nodes::GetNodesError::UnexpectedError(err)| {
+            GetSubsectionsError::UnexpectedError(err)
+        })
+}
 
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
+pub async fn count_subsections(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetSubsectionsForm,
+) -> Result<u64, GetSubsectionsError> {
+    nodes::count_nodes(pool, form.to_nodes_form())
+        .await
+        .map_err(|nodes::GetNodesError::UnexpectedError(err)| {
+            GetSubsectionsError::UnexpectedError(err)
+        })
+}
 
-    let subsections: Vec<SubsectionFromDb> = query.fetch_all(pool).await.unwrap();
-    Ok(subsections)
+/// Check that `section_id` points to a root node, so that its children are subsections.
+async fn is_section(pool: &sqlx::Pool<sqlx::MySql>, section_id: u32) -> Result<bool, Error> {
+    let res = nodes::get_node(
+        pool,
+        GetNodesForm {
+            id: Some(section_id),
+            is_root: Some(true),
+            ..Default::default()
+        },
+    )
+    .await;
+    match res {
+        Ok(_) => Ok(true),
+        Err(nodes::GetNodeError::NotFoundError) => Ok(false),
+        Err(nodes::GetNodeError::UnexpectedError(err)) => Err(err),
+    }
 }
 
 pub async fn create_subsection(
     pool: &sqlx::Pool<sqlx::MySql>,
     subsection_form: CreateSubsectionForm,
-) -> Result<(), ()> {
-    let next_pos =
-        match get_max_subsection_position_in_section(pool, subsection_form.section_id).await {
-            Some(num) => num + 1,
-            None => 0,
-        };
+) -> Result<(), Error> {
+    if !is_section(pool, subsection_form.section_id).await? {
+        return Err(Error::not_found("no such section"));
+    }
 
-    let res = sqlx::query!(
-        "INSERT INTO subsections (title, position, section_id) VALUES (?, ?, ?)",
-        subsection_form.title,
-        next_pos,
-        subsection_form.section_id,
+    nodes::create_node(
+        pool,
+        nodes::CreateNodeForm {
+            parent_id: Some(subsection_form.section_id),
+            kind: SUBSECTION_KIND.to_string(),
+            title: subsection_form.title,
+            status: subsection_form.status,
+            publish_at: subsection_form.publish_at,
+            slug: subsection_form.slug,
+        },
     )
-    .execute(pool)
-    .await;
-    match res {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
+    .await
+    .map(|_| ())
+    .map_err(|err| match err {
+        nodes::CreateNodeError::UnexpectedError(err) => err,
+        nodes::CreateNodeError::ParentNotFoundError => Error::not_found("no such section"),
+    })
 }
 
+#[derive(Default)]
 pub struct UpdateSubsectionForm {
     pub title: Option<String>,
     pub section_id: Option<u32>,
     pub position: Option<u32>,
+    pub status: Option<String>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    pub slug: Option<String>,
+    pub previous_slugs: Option<Vec<String>>,
+    /// Only update the subsections still at this version.
+    pub version: Option<u32>,
 }
 
 impl UpdateSubsectionForm {
     pub fn is_all_none(&self) -> bool {
-        self.title.is_none() && self.section_id.is_none() && self.position.is_none()
+        self.title.is_none()
+            && self.section_id.is_none()
+            && self.position.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+            && self.slug.is_none()
+            && self.previous_slugs.is_none()
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum UpdateSubsectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    VersionConflictError,
 }
 
 pub async fn update_subsections(
@@ -164,79 +224,67 @@ pub async fn update_subsections(
     if subsection_form.is_all_none() {
         return Err(UpdateSubsectionsError::NothingToUpdateError);
     }
-    let subsections_q = get_subsections(pool, identified_by.clone()).await;
-    if subsections_q.is_err() {
-        return Err(UpdateSubsectionsError::UnexpectedError);
-    }
-    if let Ok(res) = subsections_q {
-        if res.is_empty() {
-            return Err(UpdateSubsectionsError::NotFoundError);
-        }
-    }
-
-    let mut update_columns: Vec<String> = Vec::new();
-    let mut update_params: Vec<VecWrapper> = Vec::new();
-
-    if let Some(title) = subsection_form.title {
-        update_columns.push("title = ?".to_string());
-        update_params.push(VecWrapper::String(title));
-    }
     if let Some(section_id) = subsection_form.section_id {
-        update_columns.push("section_id = ?".to_string());
-        update_params.push(VecWrapper::Num(section_id));
-    }
-    if let Some(position) = subsection_form.position {
-        update_columns.push("position = ?".to_string());
-        update_params.push(VecWrapper::Num(position));
-    }
-
-    let (conditions, params) = identified_by.to_conditions_params();
-
-    let pre_query_str = format!(
-        "UPDATE subsections SET {} {} {}",
-        update_columns.join(", "),
-        if conditions.is_empty() { "" } else { "WHERE" },
-        conditions.join(match identified_by.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        })
-    );
-    let query_str = pre_query_str.as_str();
-
-    let mut query = sqlx::query(query_str);
-
-    for param in update_params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
+        match is_section(pool, section_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(UpdateSubsectionsError::UnexpectedError(Error::not_found(
+                    "no such section",
+                )))
+            }
+            Err(err) => return Err(UpdateSubsectionsError::UnexpectedError(err)),
+        }
     }
 
-    let res = query.execute(pool).await;
+    let res = nodes::update_nodes(
+        pool,
+        nodes::UpdateNodeForm {
+            title: subsection_form.title,
+            kind: None,
+            parent_id: subsection_form.section_id,
+            position: subsection_form.position,
+            status: subsection_form.status,
+            publish_at: subsection_form.publish_at,
+            slug: subsection_form.slug,
+            previous_slugs: subsection_form.previous_slugs,
+            version: subsection_form.version,
+        },
+        identified_by.to_nodes_form(),
+    )
+    .await;
     match res {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            warn!("{:?}", e);
-            Err(UpdateSubsectionsError::UnexpectedError)
+        Ok(()) => Ok(()),
+        Err(nodes::UpdateNodesError::NotFoundError) => Err(UpdateSubsectionsError::NotFoundError),
+        Err(nodes::UpdateNodesError::NothingToUpdateError) => {
+            Err(UpdateSubsectionsError::NothingToUpdateError)
+        }
+        Err(nodes::UpdateNodesError::VersionConflictError) => {
+            Err(UpdateSubsectionsError::VersionConflictError)
         }
+        Err(nodes::UpdateNodesError::UnexpectedError(err)) => {
+            Err(UpdateSubsectionsError::UnexpectedError(err))
+        }
+        Err(nodes::UpdateNodesError::CycleError) => Err(UpdateSubsectionsError::UnexpectedError(
+            Error::internal("a section cannot hold itself"),
+        )),
     }
 }
 
 #[derive(Debug)]
 pub enum GetSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
+impl LookupError for GetSubsectionError {
+    fn into_unexpected(self) -> Option<Error> {
+        match self {
+            GetSubsectionError::UnexpectedError(err) => Some(err),
+            GetSubsectionError::NotFoundError => None,
+        }
+    }
+}
+
 pub async fn get_subsection(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSubsectionsForm,
@@ -257,20 +305,53 @@ pub async fn get_subsection(
                 Ok(val.swap_remove(0))
             }
         }
-        Err(_) => Err(GetSubsectionError::UnexpectedError),
+        Err(GetSubsectionsError::UnexpectedError(err)) => {
+            Err(GetSubsectionError::UnexpectedError(err))
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SwapSubsectionsError {
     NotFoundError((Option<u32>, Option<u32>)),
-    UnexpectedError,
+    UnexpectedError(Error),
     CantSwapFromDifferentSections,
+    /// A subsection is not at its expected version, nothing was swapped.
+    VersionConflictError,
 }
 
+impl From<PairLookupError> for SwapSubsectionsError {
+    fn from(err: PairLookupError) -> Self {
+        match err {
+            PairLookupError::UnexpectedError(err) => SwapSubsectionsError::UnexpectedError(err),
+            PairLookupError::NotFoundError(ids) => SwapSubsectionsError::NotFoundError(ids),
+        }
+    }
+}
+
+impl From<nodes::SwapNodesError> for SwapSubsectionsError {
+    fn from(err: nodes::SwapNodesError) -> Self {
+        match err {
+            nodes::SwapNodesError::NotFoundError(ids) => SwapSubsectionsError::NotFoundError(ids),
+            nodes::SwapNodesError::UnexpectedError(err) => {
+                SwapSubsectionsError::UnexpectedError(err)
+            }
+            nodes::SwapNodesError::VersionConflictError => {
+                SwapSubsectionsError::VersionConflictError
+            }
+            nodes::SwapNodesError::CantSwapFromDifferentParents => {
+            SwapSubsectionsError::CantSwapFromDifferentSections
+            }
+        }
+    }
+}
+
+/// Swap the positions of two subsections of a section, written only at their
+/// expected `versions`.
 pub async fn swap_subsections(
     pool: &sqlx::Pool<sqlx::MySql>,
     ids: [u32; 2],
+    versions: [u32; 2],
 ) -> Result<(), SwapSubsectionsError> {
     let subsection_1 = get_subsection(
         pool,
@@ -288,100 +369,26 @@ pub async fn swap_subsections(
         },
     )
     .await;
-    let mut ret_err_nf = (None, None);
-    if subsection_1.is_err() {
-        ret_err_nf = (Some(ids[0]), ret_err_nf.1);
-    }
-    if subsection_2.is_err() {
-        ret_err_nf = (ret_err_nf.0, Some(ids[1]));
-    }
-    if ret_err_nf.0.is_some() || ret_err_nf.1.is_some() {
-        return Err(SwapSubsectionsError::NotFoundError(ret_err_nf));
-    }
-
-    let subsection_1 = subsection_1.unwrap();
-    let subsection_2 = subsection_2.unwrap();
-    if subsection_1.section_id != subsection_2.section_id {
-        return Err(SwapSubsectionsError::CantSwapFromDifferentSections);
-    }
-
-    let max_pos = get_max_subsection_position_in_section(pool, subsection_1.section_id)
+    found_pair(ids, [subsection_1, subsection_2])?;
+    nodes::swap_nodes(pool, ids, versions)
         .await
-        .unwrap_or_default()
-        + 1;
-    let (sub_1_id, sub_1_pos) = (subsection_1.id, subsection_1.position);
-    let (sub_2_id, sub_2_pos) = (subsection_2.id, subsection_2.position);
-
-    let pre_query_str = "UPDATE subsections SET position = ? WHERE id = ?";
-    let query = sqlx::query(pre_query_str)
-        .bind(max_pos)
-        .bind(sub_1_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapSubsectionsError::UnexpectedError);
-    }
-    let query = sqlx::query(pre_query_str)
-        .bind(sub_1_pos)
-        .bind(sub_2_id)
-        .execute(pool)
-        .await;
-    if query.is_err() {
-        return Err(SwapSubsectionsError::UnexpectedError);
-    }
-    let query = sqlx::query(pre_query_str)
-        .bind(sub_2_pos)
-        .bind(sub_1_id)
-        .execute(pool)
-        .await;
-    query
-        .map_err(|err| {
-            warn!("{:?}", err);
-            SwapSubsectionsError::UnexpectedError
-        })
-        .map(|_| ())
+        .map_err(SwapSubsectionsError::from)
 }
 
 // deleting
 pub enum DeleteSubsectionsError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn delete_subsections(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetSubsectionsForm,
 ) -> Result<(), DeleteSubsectionsError> {
-    let (conditions, params) = form.to_conditions_params();
-    let pre_query_str = format!(
-        "DELETE FROM subsections {} {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        }),
-        match form.limit {
-            None => "".to_string(),
-            Some(val) => {
-                format!("LIMIT {}", val)
-            }
-        }
-    );
-
-    let query_str = pre_query_str.as_str();
-    trace!("{}", query_str);
-    let mut query = sqlx::query(query_str);
-
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-
-    let res = query.execute(pool).await;
-    res.map_err(|_| DeleteSubsectionsError::UnexpectedError)
-        .map(|_| ())
+    nodes::delete_nodes(pool, form.to_nodes_form())
+        .await
+        .map_err(|nodes::DeleteNodesError::UnexpectedError(err)| {
+            DeleteSubsectionsError::UnexpectedError(err)
+        })
 }
 
 pub async fn delete_subsection(
@@ -0,0 +1,508 @@
+use std::collections::BTreeSet;
+
+use crate::db::query::{BindValues, Clauses, Filter, OrderBy, Value};
+use crate::db::lecture_notes::touch_notes;
+use crate::db::{found_pair, LookupError, OrAnd, PairLookupError};
+use crate::error::Error;
+use loggit::{trace, warn};
+
+/// How a list of tags is matched against the tags of a note.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TagMatch {
+    /// The note has at least one of the tags.
+    #[default]
+    Any,
+    /// The note has every one of the tags.
+    All,
+}
+
+/// The tag record as stored in the database.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct TagFromDb {
+    pub id: u32,
+    pub name: String,
+}
+
+/// A tag together with the number of notes it is attached to.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct TagWithCountFromDb {
+    pub id: u32,
+    pub name: String,
+    pub note_count: u32,
+}
+
+/// A tag attached to a note.
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Default, Clone)]
+pub struct NoteTagFromDb {
+    pub note_id: u32,
+    pub tag_id: u32,
+    pub name: String,
+}
+
+/// Condition (on a notes `id` column) matching the notes tagged with `tags`,
+/// or None if `tags` is empty.
+pub(crate) fn notes_with_tags_condition(tags: &[String], tag_match: &TagMatch) -> Option<Filter> {
+    if tags.is_empty() {
+        return None;
+    }
+    // A tag named twice counts once, or no note would match them all.
+    let tags: BTreeSet<String> = tags.iter().cloned().collect();
+    let mut params: Vec<Value> = Vec::new();
+    let tag_names = Filter::is_in("tags.name", tags.iter().cloned()).to_sql(&mut params);
+    let condition = match tag_match {
+        TagMatch::Any => format!(
+            "id IN (SELECT note_tags.note_id FROM note_tags \
+             JOIN tags ON tags.id = note_tags.tag_id WHERE {})",
+            tag_names
+        ),
+        TagMatch::All => {
+            params.push(Value::from(tags.len() as u64));
+            format!(
+                "id IN (SELECT note_tags.note_id FROM note_tags \
+                 JOIN tags ON tags.id = note_tags.tag_id WHERE {} \
+                 GROUP BY note_tags.note_id HAVING COUNT(DISTINCT tags.id) = ?)",
+                tag_names
+            )
+        }
+    };
+    Some(Filter::Sql(condition, params))
+}
+
+/// Struct to filter/select tags.
+#[derive(Clone, Debug, Default)]
+pub struct GetTagsForm {
+    pub id: Option<u32>,
+    pub name: Option<String>,
+    pub or_and: OrAnd,
+    /// Further conditions on the `id` and `name` columns.
+    pub filter: Filter,
+    /// Sorted by name when empty.
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl GetTagsForm {
+    pub(crate) fn to_clauses(&self) -> Clauses {
+        let fields = [
+            self.id.map(|id| Filter::eq("id", id)),
+            self.name.clone().map(|name| Filter::eq("name", name)),
+        ];
+        let order_by = if self.order_by.is_empty() {
+            vec![OrderBy::asc("name")]
+        } else {
+            self.order_by.clone()
+        };
+        Clauses {
+            filter: Filter::combine(fields.into_iter().flatten().collect(), &self.or_and)
+                .and(self.filter.clone()),
+            order_by,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Errors that might occur when fetching tags.
+#[derive(Debug)]
+pub enum GetTagsError {
+    UnexpectedError(Error),
+}
+
+/// Fetch tags based on the filtering form, sorted by name unless told otherwise.
+pub async fn get_tags(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetTagsForm,
+) -> Result<Vec<TagFromDb>, GetTagsError> {
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!("SELECT id, name FROM tags{}", clauses);
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, TagFromDb>(&query_str).bind_values(params);
+    query.fetch_all(pool).await.map_err(|err| {
+        warn!("{:?}", err);
+        GetTagsError::UnexpectedError(err.into())
+    })
+}
+
+/// Error type when trying to get a single tag.
+#[derive(Debug)]
+pub enum GetTagError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+impl LookupError for GetTagError {
+    fn into_unexpected(self) -> Option<Error> {
+        match self {
+            GetTagError::UnexpectedError(err) => Some(err),
+            GetTagError::NotFoundError => None,
+        }
+    }
+}
+
+/// Get a single tag (using LIMIT 1) based on the filtering form.
+pub async fn get_tag(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetTagsForm,
+) -> Result<TagFromDb, GetTagError> {
+    let tags = get_tags(
+        pool,
+        GetTagsForm {
+            limit: Some(1),
+            ..form
+        },
+    )
+    .await;
+    match tags {
+        Ok(mut list) => {
+            if list.is_empty() {
+                Err(GetTagError::NotFoundError)
+            } else {
+                Ok(list.swap_remove(0))
+            }
+        }
+        Err(GetTagsError::UnexpectedError(err)) => Err(GetTagError::UnexpectedError(err)),
+    }
+}
+
+/// Fetch every tag with the number of notes it is attached to, sorted by name.
+pub async fn get_tags_with_counts(
+    pool: &sqlx::Pool<sqlx::MySql>,
+) -> Result<Vec<TagWithCountFromDb>, GetTagsError> {
+    let query_str = "\
+        SELECT tags.id, tags.name, CAST(COUNT(note_tags.note_id) AS UNSIGNED) AS note_count \
+        FROM tags LEFT JOIN note_tags ON note_tags.tag_id = tags.id \
+        GROUP BY tags.id, tags.name ORDER BY tags.name";
+    trace!("{}", query_str);
+    sqlx::query_as::<_, TagWithCountFromDb>(query_str)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| {
+            warn!("{:?}", err);
+            GetTagsError::UnexpectedError(err.into())
+        })
+}
+
+/// Fetch the tags attached to the given notes, sorted by tag name.
+pub async fn get_tags_of_notes(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    note_ids: &[u32],
+) -> Result<Vec<NoteTagFromDb>, GetTagsError> {
+    if note_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut params: Vec<Value> = Vec::new();
+    let filter = Filter::is_in("note_tags.note_id", note_ids.iter().copied());
+    let query_str = format!(
+        "SELECT note_tags.note_id, note_tags.tag_id, tags.name FROM note_tags \
+         JOIN tags ON tags.id = note_tags.tag_id WHERE {} ORDER BY tags.name",
+        filter.to_sql(&mut params)
+    );
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, NoteTagFromDb>(&query_str).bind_values(params);
+    query.fetch_all(pool).await.map_err(|err| {
+        warn!("{:?}", err);
+        GetTagsError::UnexpectedError(err.into())
+    })
+}
+
+/// Errors that might occur when creating a tag.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CreateTagError {
+    UnexpectedError(Error),
+    AlreadyExistsError,
+}
+
+fn is_duplicate_entry(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db_err) => db_err.is_unique_violation(),
+        _ => false,
+    }
+}
+
+/// The notes carrying the tag `tag_id`.
+fn notes_tagged_with(tag_id: u32) -> Filter {
+    Filter::Sql(
+        "id IN (SELECT note_id FROM note_tags WHERE tag_id = ?)".to_string(),
+        vec![tag_id.into()],
+    )
+}
+
+/// Create a new tag. Returns the id of the created tag.
+pub async fn create_tag(pool: &sqlx::Pool<sqlx::MySql>, name: String) -> Result<u32, CreateTagError> {
+    let now = crate::db::now();
+    let res = sqlx::query("INSERT INTO tags (name, created_at, updated_at) VALUES (?, ?, ?)")
+        .bind(name)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await;
+    trace!("{:?}", res);
+    match res {
+        Ok(done) => Ok(done.last_insert_id() as u32),
+        Err(err) if is_duplicate_entry(&err) => Err(CreateTagError::AlreadyExistsError),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(CreateTagError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Get the ids of the tags named `names`, creating the missing ones.
+pub async fn get_or_create_tags(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    names: &[String],
+) -> Result<Vec<u32>, CreateTagError> {
+    let mut ids = Vec::new();
+    for name in names {
+        let now = crate::db::now();
+        let res =
+            sqlx::query("INSERT IGNORE INTO tags (name, created_at, updated_at) VALUES (?, ?, ?)")
+                .bind(name)
+                .bind(now)
+                .bind(now)
+                .execute(pool)
+            .await;
+        if let Err(err) = res {
+            warn!("{:?}", err);
+            return Err(CreateTagError::UnexpectedError(err.into()));
+        }
+        let tag = get_tag(
+            pool,
+            GetTagsForm {
+                name: Some(name.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|err| match err {
+            GetTagError::UnexpectedError(err) => CreateTagError::UnexpectedError(err),
+            GetTagError::NotFoundError => {
+                CreateTagError::UnexpectedError(Error::internal("the tag was deleted meanwhile"))
+            }
+        })?;
+        ids.push(tag.id);
+    }
+    Ok(ids)
+}
+
+/// Errors that might occur when renaming a tag.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RenameTagError {
+    UnexpectedError(Error),
+    NotFoundError,
+    AlreadyExistsError,
+}
+
+/// Rename the tag `id` to `name`.
+pub async fn rename_tag(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    id: u32,
+    name: String,
+) -> Result<(), RenameTagError> {
+    let tag = get_tag(
+        pool,
+        GetTagsForm {
+            id: Some(id),
+            ..Default::default()
+        },
+    )
+    .await;
+    match tag {
+        Ok(_) => {}
+        Err(GetTagError::NotFoundError) => return Err(RenameTagError::NotFoundError),
+        Err(GetTagError::UnexpectedError(err)) => return Err(RenameTagError::UnexpectedError(err)),
+    }
+
+    let res = sqlx::query("UPDATE tags SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(crate::db::now())
+        .bind(id)
+        .execute(pool)
+        .await;
+    let res = match res {
+        Ok(_) => touch_notes(pool, notes_tagged_with(id)).await,
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) if is_duplicate_entry(&err) => Err(RenameTagError::AlreadyExistsError),
+        Err(err) => {
+            warn!("{:?}", err);
+            Err(RenameTagError::UnexpectedError(err.into()))
+        }
+    }
+}
+
+/// Errors that might occur when merging two tags.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MergeTagsError {
+    UnexpectedError(Error),
+    NotFoundError((Option<u32>, Option<u32>)),
+    SameTagError,
+}
+
+/// The tags to merge, checked alike by every storage before writing: two
+/// distinct tags, both found. A failed lookup wins over the missing tags.
+pub(crate) fn check_merge(
+    source_id: u32,
+    target_id: u32,
+    lookups: [Result<TagFromDb, GetTagError>; 2],
+) -> Result<(), MergeTagsError> {
+    if source_id == target_id {
+        return Err(MergeTagsError::SameTagError);
+    }
+    match found_pair([source_id, target_id], lookups) {
+        Ok(_) => Ok(()),
+        Err(PairLookupError::UnexpectedError(err)) => Err(MergeTagsError::UnexpectedError(err)),
+        Err(PairLookupError::NotFoundError(ids)) => Err(MergeTagsError::NotFoundError(ids)),
+    }
+}
+
+/// Merge the tag `source_id` into `target_id`: every note tagged with the source
+/// gets the target tag, then the source tag is deleted.
+pub async fn merge_tags(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    source_id: u32,
+    target_id: u32,
+) -> Result<(), MergeTagsError> {
+    let source = get_tag(
+        pool,
+        GetTagsForm {
+            id: Some(source_id),
+            ..Default::default()
+        },
+    )
+    .await;
+    let target = get_tag(
+        pool,
+        GetTagsForm {
+            id: Some(target_id),
+            ..Default::default()
+        },
+    )
+    .await;
+    check_merge(source_id, target_id, [source, target])?;
+
+    let res: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        touch_notes(&mut *tx, notes_tagged_with(source_id)).await?;
+        sqlx::query(
+            "INSERT IGNORE INTO note_tags (note_id, tag_id) \
+             SELECT note_id, ? FROM note_tags WHERE tag_id = ?",
+        )
+        .bind(target_id)
+        .bind(source_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM tags WHERE id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        MergeTagsError::UnexpectedError(err.into())
+    })
+}
+
+/// Error type for deleting tags.
+#[derive(Debug)]
+pub enum DeleteTagsError {
+    UnexpectedError(Error),
+}
+
+/// Delete the tag `id`; it is detached from every note.
+pub async fn delete_tag(pool: &sqlx::Pool<sqlx::MySql>, id: u32) -> Result<(), DeleteTagsError> {
+    let res = touch_notes(pool, notes_tagged_with(id)).await;
+    let res = match res {
+        Ok(()) => sqlx::query("DELETE FROM tags WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|_| ()),
+        Err(err) => Err(err),
+    };
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        DeleteTagsError::UnexpectedError(err.into())
+    })
+    .map(|_| ())
+}
+
+/// Errors that might occur when attaching tags to a note.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AttachTagsError {
+    UnexpectedError(Error),
+    NoteNotFoundError,
+}
+
+/// Attach the tags `tag_ids` to the note `note_id`. Tags already attached are kept.
+pub async fn attach_tags(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    note_id: u32,
+    tag_ids: &[u32],
+) -> Result<(), AttachTagsError> {
+    let note = crate::db::lecture_notes::get_note(
+        pool,
+        crate::db::lecture_notes::GetNotesForm {
+            id: Some(note_id),
+            ..Default::default()
+        },
+    )
+    .await;
+    match note {
+        Ok(_) => {}
+        Err(crate::db::lecture_notes::GetNoteError::NotFoundError) => {
+            return Err(AttachTagsError::NoteNotFoundError)
+        }
+        Err(crate::db::lecture_notes::GetNoteError::UnexpectedError(err)) => {
+            return Err(AttachTagsError::UnexpectedError(err))
+        }
+    }
+    if tag_ids.is_empty() {
+        return Ok(());
+    }
+
+    let pre_query_str = format!(
+        "INSERT IGNORE INTO note_tags (note_id, tag_id) VALUES {}",
+        vec!["(?, ?)"; tag_ids.len()].join(", ")
+    );
+    let query_str = pre_query_str.as_str();
+    trace!("{}", query_str);
+    let mut query = sqlx::query(query_str);
+    for tag_id in tag_ids {
+        query = query.bind(note_id).bind(tag_id);
+    }
+    let res = match query.execute(pool).await {
+        Ok(_) => touch_notes(pool, Filter::eq("id", note_id)).await,
+        Err(err) => Err(err),
+    };
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        AttachTagsError::UnexpectedError(err.into())
+    })
+}
+
+/// Detach the tag `tag_id` from the note `note_id`.
+pub async fn detach_tag(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    note_id: u32,
+    tag_id: u32,
+) -> Result<(), DeleteTagsError> {
+    let res = sqlx::query("DELETE FROM note_tags WHERE note_id = ? AND tag_id = ?")
+        .bind(note_id)
+        .bind(tag_id)
+        .execute(pool)
+        .await;
+    let res = match res {
+        Ok(_) => touch_notes(pool, Filter::eq("id", note_id)).await,
+        Err(err) => Err(err),
+    };
+    res.map_err(|err| {
+        warn!("{:?}", err);
+        DeleteTagsError::UnexpectedError(err.into())
+    })
+}
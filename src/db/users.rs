@@ -1,5 +1,6 @@
-use crate::db::VecWrapper;
-use loggit::trace;
+use crate::db::query::{BindValues, Clauses, Filter, OrderBy};
+use crate::error::Error;
+use loggit::{trace, warn};
 use sqlx::{mysql::MySqlRow, prelude::FromRow, Column, Executor, Row};
 
 use super::OrAnd;
@@ -10,7 +11,7 @@ pub struct CreateUserForm {
     pub is_admin: bool,
 }
 
-#[derive(FromRow, Debug, PartialEq, Eq)]
+#[derive(FromRow, Debug, PartialEq, Eq, Clone)]
 pub struct UserFromDb {
     pub id: u32,
     pub username: String,
@@ -21,7 +22,7 @@ pub struct UserFromDb {
 pub async fn create_user(
     pool: &sqlx::Pool<sqlx::MySql>,
     user_form: CreateUserForm,
-) -> Result<(), ()> {
+) -> Result<(), Error> {
     let res = sqlx::query!(
         "INSERT INTO users (username, password, is_admin) VALUES (?, ?, ?)",
         user_form.username,
@@ -32,7 +33,7 @@ pub async fn create_user(
     .await;
     match res {
         Ok(_) => Ok(()),
-        Err(_) => Err(()),
+        Err(err) => Err(err.into()),
     }
 }
 
@@ -42,65 +43,76 @@ pub struct GetUsersForm {
     pub username: Option<String>,
     pub password: Option<String>,
     pub or_and: OrAnd,
+    /// Further conditions on the columns of the `users` table.
+    pub filter: Filter,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
 }
 
 impl GetUsersForm {
     pub fn is_all_none(&self) -> bool {
-        self.id.is_none() && self.username.is_none() && self.password.is_none()
+        self.id.is_none() && self.username.is_none() && self.password.is_none() && self.filter.is_all()
+    }
+
+    /// The conditions of the form, shared by every backend.
+    pub(crate) fn to_clauses(&self) -> Clauses {
+        let fields = [
+            self.id.map(|id| Filter::eq("id", id)),
+            self.username.clone().map(|name| Filter::eq("username", name)),
+            self.password.clone().map(|hash| Filter::eq("password", hash)),
+        ];
+        Clauses {
+            filter: Filter::combine(fields.into_iter().flatten().collect(), &self.or_and)
+                .and(self.filter.clone()),
+            order_by: self.order_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
+        }
     }
 }
 
 pub enum GetUsersError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 pub async fn get_users(
     pool: &sqlx::Pool<sqlx::MySql>,
     form: GetUsersForm,
 ) -> Result<Vec<UserFromDb>, GetUsersError> {
-    let mut conditions: Vec<String> = Vec::new();
-    let mut params: Vec<VecWrapper> = Vec::new();
+    let (clauses, params) = form.to_clauses().to_sql();
+    let query_str = format!("SELECT * FROM users{}", clauses);
+    trace!("{}", query_str);
+    let query = sqlx::query_as::<_, UserFromDb>(&query_str).bind_values(params);
 
-    if form.id.is_some() {
-        conditions.push("id = ?".to_string());
-        params.push(VecWrapper::Num(form.id.unwrap()));
-    }
-    if form.username.is_some() {
-        conditions.push("username = ?".to_string());
-        params.push(VecWrapper::String(form.username.unwrap()));
-    }
-    if form.password.is_some() {
-        conditions.push("password = ?".to_string());
-        params.push(VecWrapper::String(form.password.unwrap()));
-    }
+    query.fetch_all(pool).await.map_err(|err| {
+        warn!("{:?}", err);
+        GetUsersError::UnexpectedError(err.into())
+    })
+}
 
-    let pre_query_str = format!(
-        "SELECT * FROM users {} {}",
-        if !conditions.is_empty() { "WHERE" } else { "" },
-        conditions.join(match form.or_and {
-            OrAnd::And => " AND ",
-            OrAnd::Or => " OR ",
-        })
-    );
-    let query_str = pre_query_str.as_str();
+pub async fn count_users(
+    pool: &sqlx::Pool<sqlx::MySql>,
+    form: GetUsersForm,
+) -> Result<u64, GetUsersError> {
+    let (clauses, params) = form.to_clauses().without_paging().to_sql();
+    let query_str = format!("SELECT COUNT(*) FROM users{}", clauses);
     trace!("{}", query_str);
-    let mut query = sqlx::query_as(query_str);
+    let query = sqlx::query_scalar::<_, i64>(&query_str).bind_values(params);
 
-    for param in params {
-        query = match param {
-            VecWrapper::String(val) => query.bind(val),
-            VecWrapper::Num(val) => query.bind(val),
-            VecWrapper::Bool(val) => query.bind(val),
-        };
-    }
-
-    let users: Vec<UserFromDb> = query.fetch_all(pool).await.unwrap();
-    Ok(users)
+    query
+        .fetch_one(pool)
+        .await
+        .map(|count| count as u64)
+        .map_err(|err| {
+            warn!("{:?}", err);
+            GetUsersError::UnexpectedError(err.into())
+        })
 }
 
 pub enum GetUserError {
     NoInfoToGetFromProvided,
     NoResults,
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn get_user(
@@ -110,11 +122,10 @@ pub async fn get_user(
     if form.is_all_none() {
         return Err(GetUserError::NoInfoToGetFromProvided);
     }
-    let res = get_users(pool, form).await;
-    if res.is_err() {
-        return Err(GetUserError::UnexpectedError);
-    }
-    let mut res = res.unwrap_or(Vec::new());
+    let mut res = match get_users(pool, form).await {
+        Ok(res) => res,
+        Err(GetUsersError::UnexpectedError(err)) => return Err(GetUserError::UnexpectedError(err)),
+    };
     if res.is_empty() {
         return Err(GetUserError::NoResults);
     }
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::value::RawValue;
+use utoipa::ToSchema;
+
+/// What went wrong, sent as the `code` of every error response. Clients match on
+/// these names, so a code is never renamed or reused once released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed, the message says how.
+    BadRequest,
+    /// A slug is not made of lowercase letters, digits and single dashes.
+    InvalidSlug,
+    /// The paging parameters cannot be used together, or with this list.
+    InvalidPage,
+    /// An update naming no field to change.
+    NothingToUpdate,
+    /// Fields break their rules, `details` names each with the reason.
+    ValidationFailed,
+    /// The request needs a session token.
+    Unauthorized,
+    /// The session does not allow the request.
+    Forbidden,
+    NotFound,
+    /// A name that must be unique is already used.
+    AlreadyExists,
+    /// The slug is already used by a sibling.
+    SlugTaken,
+    /// The resource is still referenced and cannot be deleted.
+    InUse,
+    /// The content moved past the version the update was made against.
+    VersionConflict,
+    /// A row with the same unique key already exists in the database.
+    DuplicateEntry,
+    /// A row refers to a missing row, or is still referred to by another.
+    ForeignKeyViolation,
+    /// A value is refused by a `NOT NULL` or `CHECK` constraint of the database.
+    ConstraintViolation,
+    /// The `If-Match` header names an outdated version.
+    PreconditionFailed,
+    /// The update names no version to check.
+    PreconditionRequired,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    /// The database cannot be reached.
+    DatabaseUnavailable,
+    /// Anything else, the details are only logged.
+    Internal,
+}
+
+/// A problem with one field of a request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The error of the storage, the services and the routes. It keeps the database
+/// error it comes from, which is logged but never sent to clients.
+#[derive(Debug)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Vec<FieldError>,
+    /// The current state of the content a stale update was made against, as the
+    /// GET routes answer it.
+    pub current: Option<Box<RawValue>>,
+    /// The message an internal error had before `context` replaced it.
+    detail: Option<String>,
+    source: Option<Box<sqlx::Error>>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: Vec::new(),
+            current: None,
+            detail: None,
+            source: None,
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn with_detail(mut self, field: impl Into<String>, message: impl Into<String>) -> Self {
+        self.details.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Attach the current state of the content, serialized as the GET routes do.
+    pub fn with_current<T: Serialize>(mut self, current: &T) -> Self {
+        self.current = serde_json::to_string(current)
+            .ok()
+            .and_then(|json| RawValue::from_string(json).ok());
+        self
+    }
+
+    /// Name the operation that failed. An internal error only tells that much to
+    /// clients and keeps its message for the logs, the others keep it after it.
+    pub fn context(mut self, operation: &str) -> Self {
+        match self.code {
+            ErrorCode::Internal => {
+                let message = std::mem::replace(&mut self.message, operation.to_string());
+                self.detail.get_or_insert(message);
+            }
+            _ => self.message = format!("{}: {}", operation, self.message),
+        }
+        self
+    }
+
+    /// The database error this error comes from.
+    pub fn database_error(&self) -> Option<&sqlx::Error> {
+        self.source.as_deref()
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(value: sqlx::Error) -> Self {
+        let (code, message) = match &value {
+            sqlx::Error::Database(err) => match err.kind() {
+                sqlx::error::ErrorKind::UniqueViolation => (
+                    ErrorCode::DuplicateEntry,
+                    "an entry with the same key already exists",
+                ),
+                sqlx::error::ErrorKind::ForeignKeyViolation => (
+                    ErrorCode::ForeignKeyViolation,
+                    "the entry refers to a missing entry or is still referred to",
+                ),
+                sqlx::error::ErrorKind::NotNullViolation
+                | sqlx::error::ErrorKind::CheckViolation => (
+                    ErrorCode::ConstraintViolation,
+                    "a value is refused by the database",
+                ),
+                _ => (ErrorCode::Internal, "unexpected database error"),
+            },
+            sqlx::Error::RowNotFound => (ErrorCode::NotFound, "not found"),
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => (
+                ErrorCode::DatabaseUnavailable,
+                "the database is unavailable",
+            ),
+            _ => (ErrorCode::Internal, "unexpected database error"),
+        };
+        Self {
+            source: Some(Box::new(value)),
+            ..Self::new(code, message)
+        }
+    }
+}
+
+/// Errors are equal when clients would see the same answer, whatever the cause.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code && self.message == other.message && self.details == other.details
+    }
+}
+
+impl Eq for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
@@ -18,6 +18,7 @@ pub async fn get_and_create_user_example() {
             username: None,
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await
@@ -31,6 +32,7 @@ pub async fn get_and_create_user_example() {
             username: Some("hey".to_string()),
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -54,6 +56,7 @@ pub async fn create_section_example() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -1,13 +1,16 @@
 use axum::http::header;
-use axum::http::Method;
+use axum::http::{HeaderName, Method};
 use loggit::{info, logger, Level};
 use pass_hashing::hash_password;
 use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
 
+pub mod cli;
 pub mod db;
+pub mod error;
 pub mod examples;
 pub mod pass_hashing;
+pub mod repository;
 pub mod routes;
 pub mod services;
 
@@ -16,23 +19,26 @@ mod tests;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    let pool: sqlx::Pool<sqlx::MySql>;
     logger::set_colorized(true);
     logger::set_level_formatting(Level::INFO, "<green>[{level}]<green>: {message}");
     logger::set_file("log.txt");
 
-    match db::establish_connection().await {
-        Ok(conn) => pool = conn,
-        Err(_) => {
-            panic!("an error occured")
+    let storage = match repository::establish_storage().await {
+        Ok(storage) => storage,
+        Err(err) => {
+            panic!("failed to connect to the database: {:?}", err)
         }
     };
-    info!("The connection was successfully established, checking tables");
-    db::create_tables::create_required_tables(&pool).await;
-    db::create_tables::ensure_notes_description_column_exists(&pool)
-        .await
-        .expect("failed to verify notes.description column");
-    info!("The tables were verified and the missing ones were successfully created");
+    info!(
+        "The connection to the {} database was established and its tables were verified",
+        storage.backend().name()
+    );
+
+    // `backend-rs <command> ...` runs a command against the database instead of the server.
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&storage, &args).await);
+    }
 
     let addr: SocketAddr = std::env::var("SERVER_ADDR")
         .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
@@ -45,18 +51,40 @@ async fn main() {
             Method::GET,
             Method::POST,
             Method::PUT,
+            Method::PATCH,
             Method::DELETE,
             Method::OPTIONS,
         ])
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]);
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+        ])
+        .expose_headers([
+            header::ETAG,
+            header::LAST_MODIFIED,
+            HeaderName::from_static("deprecation"),
+            HeaderName::from_static("sunset"),
+            header::LINK,
+        ]);
 
     let register_only_for_admin = std::env::var("REGISTER_ONLY_FOR_ADMIN")
         .unwrap_or_else(|_| "false".to_string())
         .eq_ignore_ascii_case("true");
 
+    let search = std::sync::Arc::new(services::search::SearchIndex::default());
+    match search.rebuild(&storage).await {
+        Ok(count) => info!("The search index was built with {} documents", count),
+        Err(err) => panic!("failed to build the search index: {:?}", err),
+    }
+
     let app = routes::router(routes::AppState {
-        pool,
+        storage,
         register_only_for_admin,
+        file_storage: std::sync::Arc::new(services::files::FileStorageConfig::from_env()),
+        search,
+        tree_cache: Default::default(),
     })
     .layer(cors);
     info!("Starting server on {}", addr);
@@ -0,0 +1,1938 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+
+use crate::db;
+use crate::db::attachments::AttachmentFromDb;
+use crate::db::catalog::{CatalogChange, ImportedAttachment, ImportedNote, NodeRef, NoteRef};
+use crate::db::files::FileFromDb;
+use crate::db::nodes::{GetNodesForm, NodeFromDb};
+use crate::db::query::{Clauses, OrderBy, Value};
+use crate::db::tags::{TagFromDb, TagMatch};
+use crate::db::OrAnd;
+use crate::error::{Error, ErrorCode};
+use crate::repository::{
+    AttachmentRepository, CatalogRepository, FileRepository, NodeRepository, NoteRepository,
+    SectionRepository, SessionRepository, SubsectionRepository, TagRepository, UserRepository,
+};
+
+/// A storage kept in memory, for tests that should not need a database.
+/// It enforces the same invariants as the SQL schemas: foreign keys between
+/// sessions and users, nodes and their parent, notes and their node, tags and
+/// attachments and their note, attachments and their file, and unique
+/// positions among siblings.
+#[derive(Default)]
+pub struct MemoryRepository {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default, Clone)]
+struct MemoryState {
+    users: Vec<db::users::UserFromDb>,
+    sessions: Vec<db::sessions::SessionFromDb>,
+    nodes: Vec<NodeFromDb>,
+    /// Notes as stored; `section_id` and `subsection_id` are derived on read.
+    notes: Vec<db::lecture_notes::NoteFromDb>,
+    tags: Vec<TagFromDb>,
+    /// The (note id, tag id) pairs.
+    note_tags: Vec<(u32, u32)>,
+    attachments: Vec<AttachmentFromDb>,
+    files: Vec<FileFromDb>,
+    last_user_id: u32,
+    last_session_id: u32,
+    last_node_id: u32,
+    last_note_id: u32,
+    last_tag_id: u32,
+    last_attachment_id: u32,
+    last_file_id: u32,
+}
+
+/// Combine the conditions of a filtering form, matching everything when there is none.
+fn matches(conditions: impl IntoIterator<Item = Option<bool>>, or_and: &OrAnd) -> bool {
+    let mut conditions = conditions.into_iter().flatten().peekable();
+    if conditions.peek().is_none() {
+        return true;
+    }
+    match or_and {
+        OrAnd::And => conditions.all(|condition| condition),
+        OrAnd::Or => conditions.any(|condition| condition),
+    }
+}
+
+/// Order and page rows that are already filtered.
+fn paged<T>(
+    rows: Vec<T>,
+    order_by: &[OrderBy],
+    limit: Option<u32>,
+    offset: Option<u32>,
+    column: impl Fn(&T, &str) -> Option<Value>,
+) -> Vec<T> {
+    let clauses = Clauses {
+        order_by: order_by.to_vec(),
+        limit,
+        offset,
+        ..Default::default()
+    };
+    clauses.page(rows, column)
+}
+
+/// The same form without the ordering and the paging, to count the matching nodes.
+fn unpaged_nodes_form(form: GetNodesForm) -> GetNodesForm {
+    GetNodesForm {
+        order_by: Vec::new(),
+        limit: None,
+        offset: None,
+        ..form
+    }
+}
+
+fn user_column(user: &db::users::UserFromDb, column: &str) -> Option<Value> {
+    match column {
+        "id" => Some(user.id.into()),
+        "username" => Some(user.username.clone().into()),
+        "password" => Some(user.password.clone().into()),
+        "is_admin" => Some(user.is_admin.into()),
+        _ => None,
+    }
+}
+
+fn node_column(node: &NodeFromDb, column: &str) -> Option<Value> {
+    match column {
+        "id" => Some(node.id.into()),
+        "parent_id" => node.parent_id.map(Value::from),
+        "kind" => Some(node.kind.clone().into()),
+        "title" => Some(node.title.clone().into()),
+        "position" => Some(node.position.into()),
+        "created_at" => Some(node.created_at.into()),
+        "updated_at" => Some(node.updated_at.into()),
+        "version" => Some(node.version.into()),
+        "status" => Some(node.status.clone().into()),
+        "publish_at" => node.publish_at.map(Value::from),
+        "slug" => Some(node.slug.clone().into()),
+        "previous_slugs" => Some(node.previous_slugs.clone().into()),
+        _ => None,
+    }
+}
+
+/// A column of the notes view.
+fn note_column(note: &db::lecture_notes::NoteFromDb, column: &str) -> Option<Value> {
+    let text = |value: &Option<String>| value.clone().map(Value::from);
+    match column {
+        "id" => Some(note.id.into()),
+        "name" => Some(note.name.clone().into()),
+        "description" => Some(note.description.clone().into()),
+        "url" => Some(note.url.clone().into()),
+        "position" => Some(note.position.into()),
+        "node_id" => note.node_id.map(Value::from),
+        "section_id" => note.section_id.map(Value::from),
+        "subsection_id" => note.subsection_id.map(Value::from),
+        "kind" => text(&note.kind),
+        "course_code" => text(&note.course_code),
+        "term" => text(&note.term),
+        "language" => text(&note.language),
+        "authors" => Some(note.authors.clone().into()),
+        "license" => text(&note.license),
+        "created_at" => Some(note.created_at.into()),
+        "updated_at" => Some(note.updated_at.into()),
+        "version" => Some(note.version.into()),
+        "status" => Some(note.status.clone().into()),
+        "publish_at" => note.publish_at.map(Value::from),
+        "slug" => Some(note.slug.clone().into()),
+        "previous_slugs" => Some(note.previous_slugs.clone().into()),
+        _ => None,
+    }
+}
+
+fn tag_column(tag: &TagFromDb, column: &str) -> Option<Value> {
+    match column {
+        "id" => Some(tag.id.into()),
+        "name" => Some(tag.name.clone().into()),
+        _ => None,
+    }
+}
+
+fn attachment_column(attachment: &AttachmentFromDb, column: &str) -> Option<Value> {
+    match column {
+        "id" => Some(attachment.id.into()),
+        "note_id" => Some(attachment.note_id.into()),
+        "label" => Some(attachment.label.clone().into()),
+        "url" => Some(attachment.url.clone().into()),
+        "mime_type" => attachment.mime_type.clone().map(Value::from),
+        "size" => attachment.size.map(Value::from),
+        "file_id" => attachment.file_id.map(Value::from),
+        "position" => Some(attachment.position.into()),
+        _ => None,
+    }
+}
+
+fn file_column(file: &FileFromDb, column: &str) -> Option<Value> {
+    match column {
+        "id" => Some(file.id.into()),
+        "sha256" => Some(file.sha256.clone().into()),
+        "original_name" => Some(file.original_name.clone().into()),
+        "mime_type" => Some(file.mime_type.clone().into()),
+        "size" => Some(file.size.into()),
+        _ => None,
+    }
+}
+
+/// Whether two records of `positions` share the same (parent, position) pair.
+fn has_duplicate_positions(positions: impl IntoIterator<Item = (Option<u32>, u32)>) -> bool {
+    let mut seen = HashSet::new();
+    positions.into_iter().any(|pair| !seen.insert(pair))
+}
+
+/// The error a unique position constraint of the SQL schemas would raise.
+fn duplicate_position_error() -> Error {
+    Error::new(
+        ErrorCode::DuplicateEntry,
+        "two siblings would share a position",
+    )
+}
+
+/// The error a foreign key to the nodes would raise.
+fn missing_node_error() -> Error {
+    Error::new(ErrorCode::ForeignKeyViolation, "no such node")
+}
+
+/// The error a foreign key to the nodes would raise when deleting a node.
+fn node_in_use_error() -> Error {
+    Error::new(
+        ErrorCode::ForeignKeyViolation,
+        "the node still holds nodes or notes",
+    )
+}
+
+impl MemoryRepository {
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        // A panic while holding the lock cannot leave a half-applied change,
+        // since every change is validated before being written.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MemoryState {
+    fn node(&self, id: u32) -> Option<&NodeFromDb> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    fn depth(&self, node: &NodeFromDb) -> u32 {
+        let mut depth = 0;
+        let mut parent_id = node.parent_id;
+        while let Some(id) = parent_id {
+            depth += 1;
+            parent_id = self.node(id).and_then(|parent| parent.parent_id);
+            if depth as usize > self.nodes.len() {
+                break;
+            }
+        }
+        depth
+    }
+
+    /// Whether the node and all its ancestors are published at `at`.
+    fn is_published(&self, node: &NodeFromDb, at: i64) -> bool {
+        let published = db::nodes::published_filter(at);
+        let mut current = Some(node);
+        let mut depth = 0;
+        while let Some(node) = current {
+            if !published.matches(&|column| node_column(node, column)) {
+                return false;
+            }
+            depth += 1;
+            if depth > self.nodes.len() {
+                break;
+            }
+            current = node.parent_id.and_then(|id| self.node(id));
+        }
+        true
+    }
+
+    fn get_nodes(&self, form: &GetNodesForm) -> Vec<NodeFromDb> {
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|node| match form.is_root {
+                Some(is_root) => node.parent_id.is_none() == is_root,
+                None => true,
+            })
+            .filter(|node| match form.depth {
+                Some(depth) => self.depth(node) == depth,
+                None => true,
+            })
+            .filter(|node| match form.published_at {
+                Some(at) => self.is_published(node, at),
+                None => true,
+            })
+            .filter(|node| {
+                matches(
+                    [
+                        form.id.map(|id| node.id == id),
+                        form.parent_id.map(|id| node.parent_id == Some(id)),
+                        form.kind.as_ref().map(|kind| &node.kind == kind),
+                        form.title.as_ref().map(|title| &node.title == title),
+                        form.position.map(|position| node.position == position),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|node| form.filter.matches(&|column| node_column(node, column)))
+            .cloned()
+            .collect();
+        paged(nodes, &form.order_by, form.limit, form.offset, node_column)
+    }
+
+    fn max_node_position(&self, parent_id: Option<u32>) -> Option<u32> {
+        self.nodes
+            .iter()
+            .filter(|node| node.parent_id == parent_id)
+            .map(|node| node.position)
+            .max()
+    }
+
+    fn create_node(
+        &mut self,
+        form: db::nodes::CreateNodeForm,
+    ) -> Result<u32, db::nodes::CreateNodeError> {
+        let parent_id = form.parent_id;
+        if let Some(parent_id) = parent_id {
+            if self.node(parent_id).is_none() {
+                return Err(db::nodes::CreateNodeError::ParentNotFoundError);
+            }
+        }
+        let position = self
+            .max_node_position(parent_id)
+            .map(|max| max + 1)
+            .unwrap_or(0);
+        self.last_node_id += 1;
+        let now = db::now();
+        let slug = form.slug.unwrap_or_else(|| db::slugs::slugify(&form.title));
+        self.nodes.push(NodeFromDb {
+            id: self.last_node_id,
+            parent_id,
+            kind: form.kind,
+            title: form.title,
+            position,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            status: form
+                .status
+                .unwrap_or_else(|| db::nodes::PUBLISHED.to_string()),
+            publish_at: form.publish_at,
+            slug,
+            previous_slugs: "[]".to_string(),
+        });
+        Ok(self.last_node_id)
+    }
+
+    fn ancestor_ids(&self, id: u32) -> Option<Vec<u32>> {
+        let mut ancestors = Vec::new();
+        let mut current = self.node(id)?;
+        while let Some(parent_id) = current.parent_id {
+            if ancestors.contains(&parent_id) {
+                break;
+            }
+            ancestors.push(parent_id);
+            current = self.node(parent_id)?;
+        }
+        Some(ancestors)
+    }
+
+    fn update_nodes(
+        &mut self,
+        form: db::nodes::UpdateNodeForm,
+        identified_by: &GetNodesForm,
+    ) -> Result<(), db::nodes::UpdateNodesError> {
+        if form.is_all_none() {
+            return Err(db::nodes::UpdateNodesError::NothingToUpdateError);
+        }
+        let matched = self.get_nodes(identified_by);
+        if matched.is_empty() {
+            return Err(db::nodes::UpdateNodesError::NotFoundError);
+        }
+        if let Some(version) = form.version {
+            if matched.iter().any(|node| node.version != version) {
+                return Err(db::nodes::UpdateNodesError::VersionConflictError);
+            }
+        }
+        let ids = matched.iter().map(|node| node.id).collect::<Vec<_>>();
+        if let Some(parent_id) = form.parent_id {
+            let ancestors = self
+                .ancestor_ids(parent_id)
+                .ok_or(db::nodes::UpdateNodesError::NotFoundError)?;
+            if ids
+                .iter()
+                .any(|id| *id == parent_id || ancestors.contains(id))
+            {
+                return Err(db::nodes::UpdateNodesError::CycleError);
+            }
+        }
+
+        let mut nodes = self.nodes.clone();
+        let now = db::now();
+        for node in nodes.iter_mut().filter(|node| ids.contains(&node.id)) {
+            node.updated_at = now;
+            node.version += 1;
+            if let Some(title) = &form.title {
+                node.title = title.clone();
+            }
+            if let Some(kind) = &form.kind {
+                node.kind = kind.clone();
+            }
+            if let Some(parent_id) = form.parent_id {
+                node.parent_id = Some(parent_id);
+            }
+            if let Some(position) = form.position {
+                node.position = position;
+            }
+            if let Some(status) = &form.status {
+                node.status = status.clone();
+            }
+            if let Some(publish_at) = form.publish_at {
+                node.publish_at = publish_at;
+            }
+            if let Some(slug) = &form.slug {
+                node.slug = slug.clone();
+            }
+            if let Some(previous_slugs) = &form.previous_slugs {
+                node.previous_slugs = db::slugs::previous_slugs_to_json(previous_slugs);
+            }
+        }
+        if has_duplicate_positions(nodes.iter().map(|node| (node.parent_id, node.position))) {
+            return Err(db::nodes::UpdateNodesError::UnexpectedError(
+                duplicate_position_error(),
+            ));
+        }
+        self.nodes = nodes;
+        Ok(())
+    }
+
+    fn swap_nodes(
+        &mut self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::nodes::SwapNodesError> {
+        let lookup = |id: u32| {
+            self.node(id)
+                .cloned()
+                .ok_or(db::nodes::GetNodeError::NotFoundError)
+        };
+        let (node_1, node_2) =
+            db::nodes::check_swap(ids, versions, [lookup(ids[0]), lookup(ids[1])])?;
+        let now = db::now();
+        for node in self.nodes.iter_mut() {
+            if node.id == node_1.id {
+                node.position = node_2.position;
+            } else if node.id == node_2.id {
+                node.position = node_1.position;
+            } else {
+                continue;
+            }
+            node.updated_at = now;
+            node.version += 1;
+        }
+        Ok(())
+    }
+
+    fn delete_nodes(&mut self, form: &GetNodesForm) -> Result<(), db::nodes::DeleteNodesError> {
+        let ids = self
+            .get_nodes(form)
+            .iter()
+            .map(|node| node.id)
+            .collect::<HashSet<_>>();
+        let has_children = self.nodes.iter().any(|node| {
+            !ids.contains(&node.id) && node.parent_id.is_some_and(|id| ids.contains(&id))
+        });
+        let has_notes = self
+            .notes
+            .iter()
+            .any(|note| note.node_id.is_some_and(|id| ids.contains(&id)));
+        if has_children || has_notes {
+            return Err(db::nodes::DeleteNodesError::UnexpectedError(
+                node_in_use_error(),
+            ));
+        }
+        self.nodes.retain(|node| !ids.contains(&node.id));
+        Ok(())
+    }
+
+    /// The note as exposed by the notes view, with its section and subsection.
+    fn note_view(&self, note: &db::lecture_notes::NoteFromDb) -> db::lecture_notes::NoteFromDb {
+        let parent = note.node_id.and_then(|id| self.node(id));
+        let grandparent = parent
+            .and_then(|parent| parent.parent_id)
+            .and_then(|id| self.node(id));
+        let (section_id, subsection_id) = match (parent, grandparent) {
+            (Some(parent), None) if parent.parent_id.is_none() => (Some(parent.id), None),
+            (Some(parent), Some(grandparent)) if grandparent.parent_id.is_none() => {
+                (Some(grandparent.id), Some(parent.id))
+            }
+            _ => (None, None),
+        };
+        db::lecture_notes::NoteFromDb {
+            section_id,
+            subsection_id,
+            ..note.clone()
+        }
+    }
+
+    fn get_notes(
+        &self,
+        form: &db::lecture_notes::GetNotesForm,
+    ) -> Vec<db::lecture_notes::NoteFromDb> {
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| self.note_view(note))
+            .filter(|note| {
+                matches(
+                    [
+                        form.id.map(|id| note.id == id),
+                        form.name.as_ref().map(|name| &note.name == name),
+                        form.url.as_ref().map(|url| &note.url == url),
+                        form.position.map(|position| note.position == position),
+                        form.node_id.map(|id| note.node_id == Some(id)),
+                        form.section_id.map(|id| note.section_id == Some(id)),
+                        form.subsection_id.map(|id| note.subsection_id == Some(id)),
+                        form.kind
+                            .as_ref()
+                            .map(|kind| note.kind.as_ref() == Some(kind)),
+                        form.course_code
+                            .as_ref()
+                            .map(|code| note.course_code.as_ref() == Some(code)),
+                        form.term
+                            .as_ref()
+                            .map(|term| note.term.as_ref() == Some(term)),
+                        form.language
+                            .as_ref()
+                            .map(|language| note.language.as_ref() == Some(language)),
+                        form.author
+                            .as_ref()
+                            .map(|author| note.author_list().contains(author)),
+                        form.license
+                            .as_ref()
+                            .map(|license| note.license.as_ref() == Some(license)),
+                        (!form.tags.is_empty())
+                            .then(|| self.has_tags(note.id, &form.tags, &form.tag_match)),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|note| match form.published_at {
+                Some(at) => {
+                    db::nodes::published_filter(at).matches(&|column| note_column(note, column))
+                        && note
+                            .node_id
+                            .and_then(|id| self.node(id))
+                            .is_none_or(|node| self.is_published(node, at))
+                }
+                None => true,
+            })
+            .filter(|note| form.filter.matches(&|column| note_column(note, column)))
+            .collect();
+        paged(notes, &form.order_by, form.limit, form.offset, note_column)
+    }
+
+    /// Whether the note `note_id` carries any or all of the tags named `names`.
+    fn has_tags(&self, note_id: u32, names: &[String], tag_match: &TagMatch) -> bool {
+        let tag_names = self.tag_names_of(note_id);
+        match tag_match {
+            TagMatch::Any => names.iter().any(|name| tag_names.contains(&name.as_str())),
+            TagMatch::All => names.iter().all(|name| tag_names.contains(&name.as_str())),
+        }
+    }
+
+    fn tag_names_of(&self, note_id: u32) -> Vec<&str> {
+        self.note_tags
+            .iter()
+            .filter(|(id, _)| *id == note_id)
+            .filter_map(|(_, tag_id)| self.tags.iter().find(|tag| tag.id == *tag_id))
+            .map(|tag| tag.name.as_str())
+            .collect()
+    }
+
+    fn note_exists(&self, note_id: u32) -> bool {
+        self.notes.iter().any(|note| note.id == note_id)
+    }
+
+    /// Move the `updated_at` of the notes `note_ids`, as every change of their
+    /// tags or attachments does.
+    fn touch_notes(&mut self, note_ids: &[u32]) {
+        let now = db::now();
+        for note in self
+            .notes
+            .iter_mut()
+            .filter(|note| note_ids.contains(&note.id))
+        {
+            note.updated_at = now;
+        }
+    }
+
+    fn notes_tagged_with(&self, tag_id: u32) -> Vec<u32> {
+        self.note_tags
+            .iter()
+            .filter(|(_, id)| *id == tag_id)
+            .map(|(note_id, _)| *note_id)
+            .collect()
+    }
+
+    /// The id of the tag named `name`, created when missing.
+    fn get_or_create_tag(&mut self, name: &str) -> u32 {
+        if let Some(tag) = self.tags.iter().find(|tag| tag.name == name) {
+            return tag.id;
+        }
+        self.last_tag_id += 1;
+        self.tags.push(TagFromDb {
+            id: self.last_tag_id,
+            name: name.to_string(),
+        });
+        self.last_tag_id
+    }
+
+    /// Remove the notes `ids`, their tags and attachments with them.
+    fn delete_notes_by_ids(&mut self, ids: &HashSet<u32>) {
+        self.notes.retain(|note| !ids.contains(&note.id));
+        self.note_tags.retain(|(note_id, _)| !ids.contains(note_id));
+        self.attachments
+            .retain(|attachment| !ids.contains(&attachment.note_id));
+    }
+
+    fn get_attachments(&self, form: &db::attachments::GetAttachmentsForm) -> Vec<AttachmentFromDb> {
+        let attachments = self
+            .attachments
+            .iter()
+            .filter(|attachment| {
+                matches(
+                    [
+                        form.id.map(|id| attachment.id == id),
+                        form.note_id.map(|id| attachment.note_id == id),
+                        form.note_ids
+                            .as_ref()
+                            .map(|ids| ids.contains(&attachment.note_id)),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|attachment| {
+                form.filter
+                    .matches(&|column| attachment_column(attachment, column))
+            })
+            .cloned()
+            .collect();
+        let order_by = if form.order_by.is_empty() {
+            vec![OrderBy::asc("note_id"), OrderBy::asc("position")]
+        } else {
+            form.order_by.clone()
+        };
+        paged(
+            attachments,
+            &order_by,
+            form.limit,
+            form.offset,
+            attachment_column,
+        )
+    }
+
+    fn attachments_have_duplicate_positions(attachments: &[AttachmentFromDb]) -> bool {
+        has_duplicate_positions(
+            attachments
+                .iter()
+                .map(|attachment| (Some(attachment.note_id), attachment.position)),
+        )
+    }
+
+    /// Replace the tags and the attachments of the note `note_id` by the imported
+    /// ones. The attachments are only written again when they change.
+    fn replace_note_details(&mut self, note_id: u32, note: &ImportedNote) {
+        self.note_tags.retain(|(id, _)| *id != note_id);
+        for name in &note.tags {
+            let tag_id = self.get_or_create_tag(name);
+            if !self.note_tags.contains(&(note_id, tag_id)) {
+                self.note_tags.push((note_id, tag_id));
+            }
+        }
+
+        let stored = self
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.note_id == note_id)
+            .map(|attachment| ImportedAttachment {
+                label: attachment.label.clone(),
+                url: attachment.url.clone(),
+                mime_type: attachment.mime_type.clone(),
+                size: attachment.size,
+                file_sha256: attachment.file_id.and_then(|id| {
+                    self.files
+                        .iter()
+                        .find(|file| file.id == id)
+                        .map(|file| file.sha256.clone())
+                }),
+            })
+            .collect::<Vec<_>>();
+        if stored == note.attachments {
+            return;
+        }
+        self.attachments
+            .retain(|attachment| attachment.note_id != note_id);
+        for (position, attachment) in note.attachments.iter().enumerate() {
+            let file_id = attachment.file_sha256.as_ref().and_then(|sha256| {
+                self.files
+                    .iter()
+                    .find(|file| &file.sha256 == sha256)
+                    .map(|file| file.id)
+            });
+            self.last_attachment_id += 1;
+            self.attachments.push(AttachmentFromDb {
+                id: self.last_attachment_id,
+                note_id,
+                label: attachment.label.clone(),
+                url: attachment.url.clone(),
+                mime_type: attachment.mime_type.clone(),
+                size: attachment.size,
+                file_id,
+                position: position as u32,
+            });
+        }
+    }
+
+    /// Apply one change of an import or a batch, returning the id of the row it
+    /// writes, or None when that row is missing or at another version than the
+    /// one named. Positions are only checked once every change is applied.
+    fn apply_catalog_change(
+        &mut self,
+        change: &CatalogChange,
+        created_nodes: &mut Vec<u32>,
+        created_notes: &mut Vec<u32>,
+        now: i64,
+    ) -> Result<Option<u32>, Error> {
+        let node_id = |node: NodeRef, created_nodes: &[u32]| match node {
+            NodeRef::Stored(id) => Ok(id),
+            NodeRef::Planned(index) => created_nodes
+                .get(index)
+                .copied()
+                .ok_or_else(|| Error::internal("the node is not created yet")),
+        };
+        let note_id = |note: NoteRef, created_notes: &[u32]| match note {
+            NoteRef::Stored(id) => Ok(id),
+            NoteRef::Planned(index) => created_notes
+                .get(index)
+                .copied()
+                .ok_or_else(|| Error::internal("the note is not created yet")),
+        };
+        match change {
+            CatalogChange::CreateNode(node) => {
+                let parent_id = node
+                    .parent
+                    .map(|parent| node_id(parent, created_nodes))
+                    .transpose()?;
+                if parent_id.is_some_and(|id| self.node(id).is_none()) {
+                    return Err(missing_node_error());
+                }
+                self.last_node_id += 1;
+                self.nodes.push(NodeFromDb {
+                    id: self.last_node_id,
+                    parent_id,
+                    kind: node.kind.clone(),
+                    title: node.title.clone(),
+                    position: node.position,
+                    created_at: now,
+                    updated_at: now,
+                    version: 1,
+                    status: node.status.clone(),
+                    publish_at: node.publish_at,
+                    slug: node.slug.clone(),
+                    previous_slugs: db::slugs::previous_slugs_to_json(&node.previous_slugs),
+                });
+                created_nodes.push(self.last_node_id);
+                Ok(Some(self.last_node_id))
+            }
+            CatalogChange::UpdateNode(node_ref, version, node) => {
+                let id = node_id(*node_ref, created_nodes)?;
+                let parent_id = node
+                    .parent
+                    .map(|parent| node_id(parent, created_nodes))
+                    .transpose()?;
+                if parent_id.is_some_and(|id| self.node(id).is_none()) {
+                    return Err(missing_node_error());
+                }
+                let Some(stored) = self
+                    .nodes
+                    .iter_mut()
+                    .find(|stored| stored.id == id && stored.version == *version)
+                else {
+                    return Ok(None);
+                };
+                stored.parent_id = parent_id;
+                stored.kind = node.kind.clone();
+                stored.title = node.title.clone();
+                stored.position = node.position;
+                stored.status = node.status.clone();
+                stored.publish_at = node.publish_at;
+                stored.slug = node.slug.clone();
+                stored.previous_slugs = db::slugs::previous_slugs_to_json(&node.previous_slugs);
+                stored.updated_at = now;
+                stored.version += 1;
+                Ok(Some(id))
+            }
+            CatalogChange::DeleteNode(node_ref, version) => {
+                let id = node_id(*node_ref, created_nodes)?;
+                if self.node(id).is_none_or(|node| node.version != *version) {
+                    return Ok(None);
+                }
+                let in_use = self.nodes.iter().any(|node| node.parent_id == Some(id))
+                    || self.notes.iter().any(|note| note.node_id == Some(id));
+                if in_use {
+                    return Err(node_in_use_error());
+                }
+                self.nodes.retain(|node| node.id != id);
+                Ok(Some(id))
+            }
+            CatalogChange::CreateNote(note) => {
+                let node_id = node_id(note.node, created_nodes)?;
+                if self.node(node_id).is_none() {
+                    return Err(missing_node_error());
+                }
+                self.last_note_id += 1;
+                let id = self.last_note_id;
+                let metadata = note.metadata.clone();
+                self.notes.push(db::lecture_notes::NoteFromDb {
+                    id,
+                    name: note.name.clone(),
+                    description: note.description.clone(),
+                    url: note.url.clone(),
+                    position: note.position,
+                    node_id: Some(node_id),
+                    section_id: None,
+                    subsection_id: None,
+                    kind: metadata.kind,
+                    course_code: metadata.course_code,
+                    term: metadata.term,
+                    language: metadata.language,
+                    authors: db::lecture_notes::authors_to_json(&metadata.authors),
+                    license: metadata.license,
+                    created_at: now,
+                    updated_at: now,
+                    version: 1,
+                    status: note.status.clone(),
+                    publish_at: note.publish_at,
+                    slug: note.slug.clone(),
+                    previous_slugs: db::slugs::previous_slugs_to_json(&note.previous_slugs),
+                });
+                self.replace_note_details(id, note);
+                created_notes.push(id);
+                Ok(Some(id))
+            }
+            CatalogChange::UpdateNote(note_ref, version, note) => {
+                let id = note_id(*note_ref, created_notes)?;
+                let node_id = node_id(note.node, created_nodes)?;
+                if self.node(node_id).is_none() {
+                    return Err(missing_node_error());
+                }
+                let Some(stored) = self
+                    .notes
+                    .iter_mut()
+                    .find(|stored| stored.id == id && stored.version == *version)
+                else {
+                    return Ok(None);
+                };
+                let metadata = note.metadata.clone();
+                stored.name = note.name.clone();
+                stored.description = note.description.clone();
+                stored.url = note.url.clone();
+                stored.position = note.position;
+                stored.node_id = Some(node_id);
+                stored.kind = metadata.kind;
+                stored.course_code = metadata.course_code;
+                stored.term = metadata.term;
+                stored.language = metadata.language;
+                stored.authors = db::lecture_notes::authors_to_json(&metadata.authors);
+                stored.license = metadata.license;
+                stored.status = note.status.clone();
+                stored.publish_at = note.publish_at;
+                stored.slug = note.slug.clone();
+                stored.previous_slugs = db::slugs::previous_slugs_to_json(&note.previous_slugs);
+                stored.updated_at = now;
+                stored.version += 1;
+                self.replace_note_details(id, note);
+                Ok(Some(id))
+            }
+            CatalogChange::DeleteNote(note_ref, version) => {
+                let id = note_id(*note_ref, created_notes)?;
+                let stored = self.notes.iter().find(|note| note.id == id);
+                if stored.is_none_or(|note| note.version != *version) {
+                    return Ok(None);
+                }
+                self.delete_notes_by_ids(&HashSet::from([id]));
+                Ok(Some(id))
+            }
+        }
+    }
+
+    fn notes_have_duplicate_positions(notes: &[db::lecture_notes::NoteFromDb]) -> bool {
+        has_duplicate_positions(
+            notes
+                .iter()
+                .filter(|note| note.node_id.is_some())
+                .map(|note| (note.node_id, note.position)),
+        )
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn create_user(&self, form: db::users::CreateUserForm) -> Result<(), Error> {
+        let mut state = self.state();
+        state.last_user_id += 1;
+        let id = state.last_user_id;
+        state.users.push(db::users::UserFromDb {
+            id,
+            username: form.username,
+            password: form.password,
+            is_admin: form.is_admin,
+        });
+        Ok(())
+    }
+
+    async fn get_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<Vec<db::users::UserFromDb>, db::users::GetUsersError> {
+        let state = self.state();
+        let users = state
+            .users
+            .iter()
+            .filter(|user| {
+                matches(
+                    [
+                        form.id.map(|id| user.id == id),
+                        form.username.as_ref().map(|name| &user.username == name),
+                        form.password.as_ref().map(|hash| &user.password == hash),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|user| form.filter.matches(&|column| user_column(user, column)))
+            .cloned()
+            .collect();
+        Ok(paged(
+            users,
+            &form.order_by,
+            form.limit,
+            form.offset,
+            user_column,
+        ))
+    }
+
+    async fn count_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<u64, db::users::GetUsersError> {
+        let users = self
+            .get_users(db::users::GetUsersForm {
+                order_by: Vec::new(),
+                limit: None,
+                offset: None,
+                ..form
+            })
+            .await?;
+        Ok(users.len() as u64)
+    }
+}
+
+#[async_trait]
+impl SessionRepository for MemoryRepository {
+    async fn create_session(&self, form: db::sessions::CreateSessionForm) -> Result<(), Error> {
+        let mut state = self.state();
+        let user_exists = state.users.iter().any(|user| user.id == form.user_id);
+        let token_taken = state
+            .sessions
+            .iter()
+            .any(|session| session.token == form.token);
+        if !user_exists {
+            return Err(Error::new(ErrorCode::ForeignKeyViolation, "no such user"));
+        }
+        if token_taken {
+            return Err(Error::new(
+                ErrorCode::DuplicateEntry,
+                "the token is already used",
+            ));
+        }
+        state.last_session_id += 1;
+        let id = state.last_session_id;
+        state.sessions.push(db::sessions::SessionFromDb {
+            id,
+            user_id: form.user_id,
+            token: form.token,
+            expires_at: form.expires_at,
+        });
+        Ok(())
+    }
+
+    async fn get_session_by_token(
+        &self,
+        token: String,
+    ) -> Result<db::sessions::SessionFromDb, Error> {
+        let state = self.state();
+        state
+            .sessions
+            .iter()
+            .find(|session| session.token == token)
+            .cloned()
+            .ok_or_else(|| Error::not_found("no such session"))
+    }
+
+    async fn delete_session_by_token(&self, token: String) -> Result<(), Error> {
+        self.state()
+            .sessions
+            .retain(|session| session.token != token);
+        Ok(())
+    }
+
+    async fn delete_sessions_by_user(&self, user_id: u32) -> Result<(), Error> {
+        self.state()
+            .sessions
+            .retain(|session| session.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SectionRepository for MemoryRepository {
+    async fn get_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<Vec<db::sections::SectionFromDb>, db::sections::GetSectionsError> {
+        let state = self.state();
+        Ok(state
+            .get_nodes(&form.to_nodes_form())
+            .into_iter()
+            .map(db::sections::SectionFromDb::from)
+            .collect())
+    }
+
+    async fn count_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<u64, db::sections::GetSectionsError> {
+        let form = unpaged_nodes_form(form.to_nodes_form());
+        Ok(self.state().get_nodes(&form).len() as u64)
+    }
+
+    async fn create_section(&self, form: db::sections::CreateSectionForm) -> Result<(), Error> {
+        self.state()
+            .create_node(db::nodes::CreateNodeForm {
+                parent_id: None,
+                kind: db::sections::SECTION_KIND.to_string(),
+                title: form.title,
+                status: form.status,
+                publish_at: form.publish_at,
+                slug: form.slug,
+            })
+            .map(|_| ())
+            .map_err(|err| match err {
+                db::nodes::CreateNodeError::UnexpectedError(err) => err,
+                db::nodes::CreateNodeError::ParentNotFoundError => {
+                    Error::internal("a section has no parent")
+                }
+            })
+    }
+
+    async fn update_sections(
+        &self,
+        form: db::sections::UpdateSectionForm,
+        identified_by: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::UpdateSectionsError> {
+        let res = self.state().update_nodes(
+            db::nodes::UpdateNodeForm {
+                title: form.title,
+                kind: None,
+                parent_id: None,
+                position: None,
+                status: form.status,
+                publish_at: form.publish_at,
+                slug: form.slug,
+                previous_slugs: form.previous_slugs,
+                version: form.version,
+            },
+            &identified_by.to_nodes_form(),
+        );
+        res.map_err(|err| match err {
+            db::nodes::UpdateNodesError::NotFoundError => {
+                db::sections::UpdateSectionsError::NotFoundError
+            }
+            db::nodes::UpdateNodesError::NothingToUpdateError => {
+                db::sections::UpdateSectionsError::NothingToUpdateError
+            }
+            db::nodes::UpdateNodesError::VersionConflictError => {
+                db::sections::UpdateSectionsError::VersionConflictError
+            }
+            db::nodes::UpdateNodesError::UnexpectedError(err) => {
+                db::sections::UpdateSectionsError::UnexpectedError(err)
+            }
+            db::nodes::UpdateNodesError::CycleError => {
+                db::sections::UpdateSectionsError::UnexpectedError(Error::internal(
+                    "a section cannot be moved under itself",
+                ))
+            }
+        })
+    }
+
+    async fn swap_sections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::sections::SwapSectionsError> {
+        let mut state = self.state();
+        let lookup = |id: u32| {
+            state
+                .node(id)
+                .filter(|node| node.parent_id.is_none())
+                .ok_or(db::sections::GetSectionError::NotFoundError)
+        };
+        db::found_pair(ids, [lookup(ids[0]), lookup(ids[1])])?;
+        state
+            .swap_nodes(ids, versions)
+            .map_err(db::sections::SwapSectionsError::from)
+    }
+
+    async fn delete_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::DeleteSectionsError> {
+        self.state().delete_nodes(&form.to_nodes_form()).map_err(
+            |db::nodes::DeleteNodesError::UnexpectedError(err)| {
+                db::sections::DeleteSectionsError::UnexpectedError(err)
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl SubsectionRepository for MemoryRepository {
+    async fn get_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<Vec<db::subsections::SubsectionFromDb>, db::subsections::GetSubsectionsError> {
+        let state = self.state();
+        Ok(state
+            .get_nodes(&form.to_nodes_form())
+            .into_iter()
+            .map(db::subsections::SubsectionFromDb::from)
+            .collect())
+    }
+
+    async fn count_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<u64, db::subsections::GetSubsectionsError> {
+        let form = unpaged_nodes_form(form.to_nodes_form());
+        Ok(self.state().get_nodes(&form).len() as u64)
+    }
+
+    async fn create_subsection(
+        &self,
+        form: db::subsections::CreateSubsectionForm,
+    ) -> Result<(), Error> {
+        let mut state = self.state();
+        let is_section = state
+            .node(form.section_id)
+            .is_some_and(|node| node.parent_id.is_none());
+        if !is_section {
+            return Err(Error::not_found("no such section"));
+        }
+        state
+            .create_node(db::nodes::CreateNodeForm {
+                parent_id: Some(form.section_id),
+                kind: db::subsections::SUBSECTION_KIND.to_string(),
+                title: form.title,
+                status: form.status,
+                publish_at: form.publish_at,
+                slug: form.slug,
+            })
+            .map(|_| ())
+            .map_err(|err| match err {
+                db::nodes::CreateNodeError::UnexpectedError(err) => err,
+                db::nodes::CreateNodeError::ParentNotFoundError => {
+                    Error::not_found("no such section")
+                }
+            })
+    }
+
+    async fn update_subsections(
+        &self,
+        form: db::subsections::UpdateSubsectionForm,
+        identified_by: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::UpdateSubsectionsError> {
+        let mut state = self.state();
+        if form.is_all_none() {
+            return Err(db::subsections::UpdateSubsectionsError::NothingToUpdateError);
+        }
+        if let Some(section_id) = form.section_id {
+            if state
+                .node(section_id)
+                .is_none_or(|node| node.parent_id.is_some())
+            {
+                return Err(db::subsections::UpdateSubsectionsError::UnexpectedError(
+                    Error::not_found("no such section"),
+                ));
+            }
+        }
+        let res = state.update_nodes(
+            db::nodes::UpdateNodeForm {
+                title: form.title,
+                kind: None,
+                parent_id: form.section_id,
+                position: form.position,
+                status: form.status,
+                publish_at: form.publish_at,
+                slug: form.slug,
+                previous_slugs: form.previous_slugs,
+                version: form.version,
+            },
+            &identified_by.to_nodes_form(),
+        );
+        res.map_err(|err| match err {
+            db::nodes::UpdateNodesError::NotFoundError => {
+                db::subsections::UpdateSubsectionsError::NotFoundError
+            }
+            db::nodes::UpdateNodesError::NothingToUpdateError => {
+                db::subsections::UpdateSubsectionsError::NothingToUpdateError
+            }
+            db::nodes::UpdateNodesError::VersionConflictError => {
+                db::subsections::UpdateSubsectionsError::VersionConflictError
+            }
+            db::nodes::UpdateNodesError::UnexpectedError(err) => {
+                db::subsections::UpdateSubsectionsError::UnexpectedError(err)
+            }
+            db::nodes::UpdateNodesError::CycleError => {
+                db::subsections::UpdateSubsectionsError::UnexpectedError(Error::internal(
+                    "a section cannot hold itself",
+                ))
+            }
+        })
+    }
+
+    async fn swap_subsections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::subsections::SwapSubsectionsError> {
+        let mut state = self.state();
+        let lookup = |id: u32| {
+            state
+                .node(id)
+                .filter(|node| state.depth(node) == 1)
+                .ok_or(db::subsections::GetSubsectionError::NotFoundError)
+        };
+        db::found_pair(ids, [lookup(ids[0]), lookup(ids[1])])?;
+        state
+            .swap_nodes(ids, versions)
+            .map_err(db::subsections::SwapSubsectionsError::from)
+    }
+
+    async fn delete_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::DeleteSubsectionsError> {
+        self.state().delete_nodes(&form.to_nodes_form()).map_err(
+            |db::nodes::DeleteNodesError::UnexpectedError(err)| {
+                db::subsections::DeleteSubsectionsError::UnexpectedError(err)
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl NoteRepository for MemoryRepository {
+    async fn get_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<Vec<db::lecture_notes::NoteFromDb>, db::lecture_notes::GetNotesError> {
+        Ok(self.state().get_notes(&form))
+    }
+
+    async fn count_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<u64, db::lecture_notes::GetNotesError> {
+        let form = db::lecture_notes::GetNotesForm {
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            ..form
+        };
+        Ok(self.state().get_notes(&form).len() as u64)
+    }
+
+    async fn create_note(&self, form: db::lecture_notes::CreateNoteForm) -> Result<(), Error> {
+        let mut state = self.state();
+        if let Some(node_id) = form.node_id {
+            if state.node(node_id).is_none() {
+                return Err(missing_node_error());
+            }
+        }
+        let position = state
+            .notes
+            .iter()
+            .filter(|note| form.node_id.is_none() || note.node_id == form.node_id)
+            .map(|note| note.position)
+            .max()
+            .map(|max| max + 1)
+            .unwrap_or(0);
+        state.last_note_id += 1;
+        let id = state.last_note_id;
+        let metadata = form.metadata;
+        let now = db::now();
+        let slug = form.slug.unwrap_or_else(|| db::slugs::slugify(&form.name));
+        state.notes.push(db::lecture_notes::NoteFromDb {
+            id,
+            name: form.name,
+            description: form.description,
+            url: form.url,
+            position,
+            node_id: form.node_id,
+            section_id: None,
+            subsection_id: None,
+            kind: metadata.kind,
+            course_code: metadata.course_code,
+            term: metadata.term,
+            language: metadata.language,
+            authors: db::lecture_notes::authors_to_json(&metadata.authors),
+            license: metadata.license,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            status: form
+                .status
+                .unwrap_or_else(|| db::nodes::PUBLISHED.to_string()),
+            publish_at: form.publish_at,
+            slug,
+            previous_slugs: "[]".to_string(),
+        });
+        Ok(())
+    }
+
+    async fn update_notes(
+        &self,
+        form: db::lecture_notes::UpdateNoteForm,
+        identified_by: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::UpdateNotesError> {
+        let mut state = self.state();
+        if form.is_all_none() {
+            return Err(db::lecture_notes::UpdateNotesError::NothingToUpdateError);
+        }
+        let matched = state.get_notes(&identified_by);
+        if matched.is_empty() {
+            return Err(db::lecture_notes::UpdateNotesError::NotFoundError);
+        }
+        if let Some(version) = form.version {
+            if matched.iter().any(|note| note.version != version) {
+                return Err(db::lecture_notes::UpdateNotesError::VersionConflictError);
+            }
+        }
+        let ids = matched.iter().map(|note| note.id).collect::<Vec<_>>();
+        if let Some(node_id) = form.node_id {
+            if state.node(node_id).is_none() {
+                return Err(db::lecture_notes::UpdateNotesError::UnexpectedError(
+                    missing_node_error(),
+                ));
+            }
+        }
+
+        let mut notes = state.notes.clone();
+        let now = db::now();
+        for note in notes.iter_mut().filter(|note| ids.contains(&note.id)) {
+            note.updated_at = now;
+            note.version += 1;
+            let text_fields = [
+                (&mut note.name, &form.name),
+                (&mut note.description, &form.description),
+                (&mut note.url, &form.url),
+            ];
+            for (field, value) in text_fields {
+                if let Some(value) = value {
+                    *field = value.clone();
+                }
+            }
+            let optional_fields = [
+                (&mut note.kind, &form.kind),
+                (&mut note.course_code, &form.course_code),
+                (&mut note.term, &form.term),
+                (&mut note.language, &form.language),
+                (&mut note.license, &form.license),
+            ];
+            for (field, value) in optional_fields {
+                if let Some(value) = value {
+                    *field = value.clone();
+                }
+            }
+            if let Some(authors) = &form.authors {
+                note.authors = db::lecture_notes::authors_to_json(authors);
+            }
+            if let Some(node_id) = form.node_id {
+                note.node_id = Some(node_id);
+            }
+            if let Some(position) = form.position {
+                note.position = position;
+            }
+            if let Some(status) = &form.status {
+                note.status = status.clone();
+            }
+            if let Some(publish_at) = form.publish_at {
+                note.publish_at = publish_at;
+            }
+            if let Some(slug) = &form.slug {
+                note.slug = slug.clone();
+            }
+            if let Some(previous_slugs) = &form.previous_slugs {
+                note.previous_slugs = db::slugs::previous_slugs_to_json(previous_slugs);
+            }
+        }
+        if MemoryState::notes_have_duplicate_positions(&notes) {
+            return Err(db::lecture_notes::UpdateNotesError::UnexpectedError(
+                duplicate_position_error(),
+            ));
+        }
+        state.notes = notes;
+        Ok(())
+    }
+
+    async fn swap_notes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::lecture_notes::SwapNotesError> {
+        let mut state = self.state();
+        let lookup = |id: u32| {
+            state
+                .notes
+                .iter()
+                .find(|note| note.id == id)
+                .cloned()
+                .ok_or(db::lecture_notes::GetNoteError::NotFoundError)
+        };
+        let (note_1, note_2) =
+            db::lecture_notes::check_swap(ids, versions, [lookup(ids[0]), lookup(ids[1])])?;
+        let now = db::now();
+        for note in state.notes.iter_mut() {
+            if note.id == note_1.id {
+                note.position = note_2.position;
+            } else if note.id == note_2.id {
+                note.position = note_1.position;
+            } else {
+                continue;
+            }
+            note.updated_at = now;
+            note.version += 1;
+        }
+        Ok(())
+    }
+
+    async fn delete_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::DeleteNotesError> {
+        let mut state = self.state();
+        let ids = state
+            .get_notes(&form)
+            .iter()
+            .map(|note| note.id)
+            .collect::<HashSet<_>>();
+        state.delete_notes_by_ids(&ids);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NodeRepository for MemoryRepository {
+    async fn get_nodes(
+        &self,
+        form: GetNodesForm,
+    ) -> Result<Vec<NodeFromDb>, db::nodes::GetNodesError> {
+        Ok(self.state().get_nodes(&form))
+    }
+
+    async fn create_node(
+        &self,
+        form: db::nodes::CreateNodeForm,
+    ) -> Result<u32, db::nodes::CreateNodeError> {
+        self.state().create_node(form)
+    }
+
+    async fn update_nodes(
+        &self,
+        form: db::nodes::UpdateNodeForm,
+        identified_by: GetNodesForm,
+    ) -> Result<(), db::nodes::UpdateNodesError> {
+        self.state().update_nodes(form, &identified_by)
+    }
+
+    async fn swap_nodes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::nodes::SwapNodesError> {
+        self.state().swap_nodes(ids, versions)
+    }
+
+    async fn delete_nodes(&self, form: GetNodesForm) -> Result<(), db::nodes::DeleteNodesError> {
+        self.state().delete_nodes(&form)
+    }
+}
+
+#[async_trait]
+impl TagRepository for MemoryRepository {
+    async fn get_tags(
+        &self,
+        form: db::tags::GetTagsForm,
+    ) -> Result<Vec<TagFromDb>, db::tags::GetTagsError> {
+        let state = self.state();
+        let tags = state
+            .tags
+            .iter()
+            .filter(|tag| {
+                matches(
+                    [
+                        form.id.map(|id| tag.id == id),
+                        form.name.as_ref().map(|name| &tag.name == name),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|tag| form.filter.matches(&|column| tag_column(tag, column)))
+            .cloned()
+            .collect();
+        let order_by = if form.order_by.is_empty() {
+            vec![OrderBy::asc("name")]
+        } else {
+            form.order_by
+        };
+        Ok(paged(tags, &order_by, form.limit, form.offset, tag_column))
+    }
+
+    async fn get_tags_with_counts(
+        &self,
+    ) -> Result<Vec<db::tags::TagWithCountFromDb>, db::tags::GetTagsError> {
+        let state = self.state();
+        let mut tags = state
+            .tags
+            .iter()
+            .map(|tag| db::tags::TagWithCountFromDb {
+                id: tag.id,
+                name: tag.name.clone(),
+                note_count: state.notes_tagged_with(tag.id).len() as u32,
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    async fn get_tags_of_notes(
+        &self,
+        note_ids: &[u32],
+    ) -> Result<Vec<db::tags::NoteTagFromDb>, db::tags::GetTagsError> {
+        let state = self.state();
+        let mut tags = state
+            .note_tags
+            .iter()
+            .filter(|(note_id, _)| note_ids.contains(note_id))
+            .filter_map(|(note_id, tag_id)| {
+                let tag = state.tags.iter().find(|tag| tag.id == *tag_id)?;
+                Some(db::tags::NoteTagFromDb {
+                    note_id: *note_id,
+                    tag_id: tag.id,
+                    name: tag.name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    async fn create_tag(&self, name: String) -> Result<u32, db::tags::CreateTagError> {
+        let mut state = self.state();
+        if state.tags.iter().any(|tag| tag.name == name) {
+            return Err(db::tags::CreateTagError::AlreadyExistsError);
+        }
+        Ok(state.get_or_create_tag(&name))
+    }
+
+    async fn get_or_create_tags(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<u32>, db::tags::CreateTagError> {
+        let mut state = self.state();
+        Ok(names
+            .iter()
+            .map(|name| state.get_or_create_tag(name))
+            .collect())
+    }
+
+    async fn rename_tag(&self, id: u32, name: String) -> Result<(), db::tags::RenameTagError> {
+        let mut state = self.state();
+        if !state.tags.iter().any(|tag| tag.id == id) {
+            return Err(db::tags::RenameTagError::NotFoundError);
+        }
+        if state
+            .tags
+            .iter()
+            .any(|tag| tag.id != id && tag.name == name)
+        {
+            return Err(db::tags::RenameTagError::AlreadyExistsError);
+        }
+        if let Some(tag) = state.tags.iter_mut().find(|tag| tag.id == id) {
+            tag.name = name;
+        }
+        let note_ids = state.notes_tagged_with(id);
+        state.touch_notes(&note_ids);
+        Ok(())
+    }
+
+    async fn merge_tags(
+        &self,
+        source_id: u32,
+        target_id: u32,
+    ) -> Result<(), db::tags::MergeTagsError> {
+        let mut state = self.state();
+        let lookup = |id: u32| {
+            state
+                .tags
+                .iter()
+                .find(|tag| tag.id == id)
+                .cloned()
+                .ok_or(db::tags::GetTagError::NotFoundError)
+        };
+        db::tags::check_merge(source_id, target_id, [lookup(source_id), lookup(target_id)])?;
+        let note_ids = state.notes_tagged_with(source_id);
+        state.touch_notes(&note_ids);
+        for note_id in note_ids {
+            if !state.note_tags.contains(&(note_id, target_id)) {
+                state.note_tags.push((note_id, target_id));
+            }
+        }
+        state.note_tags.retain(|(_, tag_id)| *tag_id != source_id);
+        state.tags.retain(|tag| tag.id != source_id);
+        Ok(())
+    }
+
+    async fn delete_tag(&self, id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        let mut state = self.state();
+        let note_ids = state.notes_tagged_with(id);
+        state.touch_notes(&note_ids);
+        state.note_tags.retain(|(_, tag_id)| *tag_id != id);
+        state.tags.retain(|tag| tag.id != id);
+        Ok(())
+    }
+
+    async fn attach_tags(
+        &self,
+        note_id: u32,
+        tag_ids: &[u32],
+    ) -> Result<(), db::tags::AttachTagsError> {
+        let mut state = self.state();
+        if !state.note_exists(note_id) {
+            return Err(db::tags::AttachTagsError::NoteNotFoundError);
+        }
+        if tag_ids
+            .iter()
+            .any(|id| !state.tags.iter().any(|tag| tag.id == *id))
+        {
+            return Err(db::tags::AttachTagsError::UnexpectedError(Error::new(
+                ErrorCode::ForeignKeyViolation,
+                "no such tag",
+            )));
+        }
+        if tag_ids.is_empty() {
+            return Ok(());
+        }
+        for tag_id in tag_ids {
+            if !state.note_tags.contains(&(note_id, *tag_id)) {
+                state.note_tags.push((note_id, *tag_id));
+            }
+        }
+        state.touch_notes(&[note_id]);
+        Ok(())
+    }
+
+    async fn detach_tag(&self, note_id: u32, tag_id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        let mut state = self.state();
+        state.note_tags.retain(|pair| *pair != (note_id, tag_id));
+        state.touch_notes(&[note_id]);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AttachmentRepository for MemoryRepository {
+    async fn get_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<Vec<AttachmentFromDb>, db::attachments::GetAttachmentsError> {
+        Ok(self.state().get_attachments(&form))
+    }
+
+    async fn create_attachment(
+        &self,
+        form: db::attachments::CreateAttachmentForm,
+    ) -> Result<u32, db::attachments::CreateAttachmentError> {
+        let mut state = self.state();
+        if !state.note_exists(form.note_id) {
+            return Err(db::attachments::CreateAttachmentError::NoteNotFoundError);
+        }
+        if let Some(file_id) = form.file_id {
+            if !state.files.iter().any(|file| file.id == file_id) {
+                return Err(db::attachments::CreateAttachmentError::UnexpectedError(
+                    Error::new(ErrorCode::ForeignKeyViolation, "no such file"),
+                ));
+            }
+        }
+        let position = state
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.note_id == form.note_id)
+            .map(|attachment| attachment.position)
+            .max()
+            .map(|max| max + 1)
+            .unwrap_or(0);
+        state.last_attachment_id += 1;
+        let id = state.last_attachment_id;
+        state.attachments.push(AttachmentFromDb {
+            id,
+            note_id: form.note_id,
+            label: form.label,
+            url: form.url,
+            mime_type: form.mime_type,
+            size: form.size,
+            file_id: form.file_id,
+            position,
+        });
+        state.touch_notes(&[form.note_id]);
+        Ok(id)
+    }
+
+    async fn update_attachments(
+        &self,
+        form: db::attachments::UpdateAttachmentForm,
+        identified_by: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::UpdateAttachmentsError> {
+        if form.is_all_none() {
+            return Err(db::attachments::UpdateAttachmentsError::NothingToUpdateError);
+        }
+        let mut state = self.state();
+        let matched = state.get_attachments(&identified_by);
+        if matched.is_empty() {
+            return Err(db::attachments::UpdateAttachmentsError::NotFoundError);
+        }
+        let ids = matched
+            .iter()
+            .map(|attachment| attachment.id)
+            .collect::<Vec<_>>();
+        let mut attachments = state.attachments.clone();
+        for attachment in attachments
+            .iter_mut()
+            .filter(|attachment| ids.contains(&attachment.id))
+        {
+            if let Some(label) = &form.label {
+                attachment.label = label.clone();
+            }
+            if let Some(url) = &form.url {
+                attachment.url = url.clone();
+            }
+            if let Some(mime_type) = &form.mime_type {
+                attachment.mime_type = Some(mime_type.clone());
+            }
+            if let Some(size) = form.size {
+                attachment.size = Some(size);
+            }
+            if let Some(position) = form.position {
+                attachment.position = position;
+            }
+        }
+        if MemoryState::attachments_have_duplicate_positions(&attachments) {
+            return Err(db::attachments::UpdateAttachmentsError::UnexpectedError(
+                duplicate_position_error(),
+            ));
+        }
+        state.attachments = attachments;
+        let note_ids = matched
+            .iter()
+            .map(|attachment| attachment.note_id)
+            .collect::<Vec<_>>();
+        state.touch_notes(&note_ids);
+        Ok(())
+    }
+
+    async fn swap_attachments(
+        &self,
+        ids: [u32; 2],
+    ) -> Result<(), db::attachments::SwapAttachmentsError> {
+        let mut state = self.state();
+        let find = |id: u32| {
+            state
+                .attachments
+                .iter()
+                .find(|attachment| attachment.id == id)
+                .cloned()
+        };
+        let (attachment_1, attachment_2) = match (find(ids[0]), find(ids[1])) {
+            (Some(attachment_1), Some(attachment_2)) => (attachment_1, attachment_2),
+            (attachment_1, attachment_2) => {
+                return Err(db::attachments::SwapAttachmentsError::NotFoundError((
+                    attachment_1.is_none().then_some(ids[0]),
+                    attachment_2.is_none().then_some(ids[1]),
+                )))
+            }
+        };
+        if attachment_1.note_id != attachment_2.note_id {
+            return Err(db::attachments::SwapAttachmentsError::CantSwapFromDifferentNotes);
+        }
+        for attachment in state.attachments.iter_mut() {
+            if attachment.id == attachment_1.id {
+                attachment.position = attachment_2.position;
+            } else if attachment.id == attachment_2.id {
+                attachment.position = attachment_1.position;
+            }
+        }
+        state.touch_notes(&[attachment_1.note_id]);
+        Ok(())
+    }
+
+    async fn delete_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::DeleteAttachmentsError> {
+        let mut state = self.state();
+        let matched = state.get_attachments(&form);
+        let ids = matched
+            .iter()
+            .map(|attachment| attachment.id)
+            .collect::<HashSet<_>>();
+        state
+            .attachments
+            .retain(|attachment| !ids.contains(&attachment.id));
+        let note_ids = matched
+            .iter()
+            .map(|attachment| attachment.note_id)
+            .collect::<Vec<_>>();
+        state.touch_notes(&note_ids);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FileRepository for MemoryRepository {
+    async fn get_files(
+        &self,
+        form: db::files::GetFilesForm,
+    ) -> Result<Vec<FileFromDb>, db::files::GetFilesError> {
+        let state = self.state();
+        let files = state
+            .files
+            .iter()
+            .filter(|file| {
+                matches(
+                    [
+                        form.id.map(|id| file.id == id),
+                        form.sha256.as_ref().map(|sha256| &file.sha256 == sha256),
+                        form.ids.as_ref().map(|ids| ids.contains(&file.id)),
+                    ],
+                    &form.or_and,
+                )
+            })
+            .filter(|file| form.filter.matches(&|column| file_column(file, column)))
+            .cloned()
+            .collect();
+        let order_by = if form.order_by.is_empty() {
+            vec![OrderBy::asc("id")]
+        } else {
+            form.order_by
+        };
+        Ok(paged(
+            files,
+            &order_by,
+            form.limit,
+            form.offset,
+            file_column,
+        ))
+    }
+
+    async fn create_file(
+        &self,
+        form: db::files::CreateFileForm,
+    ) -> Result<(u32, bool), db::files::CreateFileError> {
+        let mut state = self.state();
+        if let Some(file) = state.files.iter().find(|file| file.sha256 == form.sha256) {
+            return Ok((file.id, false));
+        }
+        state.last_file_id += 1;
+        let id = state.last_file_id;
+        state.files.push(FileFromDb {
+            id,
+            sha256: form.sha256,
+            original_name: form.original_name,
+            mime_type: form.mime_type,
+            size: form.size,
+        });
+        Ok((id, true))
+    }
+
+    async fn delete_file(&self, id: u32) -> Result<FileFromDb, db::files::DeleteFileError> {
+        let mut state = self.state();
+        let file = state
+            .files
+            .iter()
+            .find(|file| file.id == id)
+            .cloned()
+            .ok_or(db::files::DeleteFileError::NotFoundError)?;
+        if state
+            .attachments
+            .iter()
+            .any(|attachment| attachment.file_id == Some(id))
+        {
+            return Err(db::files::DeleteFileError::InUseError);
+        }
+        state.files.retain(|file| file.id != id);
+        Ok(file)
+    }
+}
+
+#[async_trait]
+impl CatalogRepository for MemoryRepository {
+    async fn apply_catalog_changes(
+        &self,
+        changes: &[CatalogChange],
+    ) -> Result<Vec<u32>, db::catalog::ApplyCatalogChangesError> {
+        let mut state = self.state();
+        // The changes are made on a copy, so that a failing one leaves nothing behind.
+        let mut changed = state.clone();
+        let now = db::now();
+        let mut created_nodes = Vec::new();
+        let mut created_notes = Vec::new();
+        let mut written = Vec::with_capacity(changes.len());
+        for (index, change) in changes.iter().enumerate() {
+            let id = changed
+                .apply_catalog_change(change, &mut created_nodes, &mut created_notes, now)
+                .map_err(db::catalog::ApplyCatalogChangesError::UnexpectedError)?
+                .ok_or(db::catalog::ApplyCatalogChangesError::VersionConflictError(
+                    index,
+                ))?;
+            written.push(id);
+        }
+        if has_duplicate_positions(
+            changed
+                .nodes
+                .iter()
+                .map(|node| (node.parent_id, node.position)),
+        ) || MemoryState::notes_have_duplicate_positions(&changed.notes)
+        {
+            return Err(db::catalog::ApplyCatalogChangesError::UnexpectedError(
+                duplicate_position_error(),
+            ));
+        }
+        *state = changed;
+        Ok(written)
+    }
+}
@@ -0,0 +1,592 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dotenvy::dotenv;
+
+use crate::db::attachments::{
+    AttachmentFromDb, CreateAttachmentError, CreateAttachmentForm, DeleteAttachmentsError,
+    GetAttachmentError, GetAttachmentsError, GetAttachmentsForm, SwapAttachmentsError,
+    UpdateAttachmentForm, UpdateAttachmentsError,
+};
+use crate::db::catalog::{ApplyCatalogChangesError, CatalogChange};
+use crate::db::files::{
+    CreateFileError, CreateFileForm, DeleteFileError, FileFromDb, GetFileError, GetFilesError,
+    GetFilesForm,
+};
+use crate::db::lecture_notes::{
+    CreateNoteForm, DeleteNotesError, GetNoteError, GetNotesError, GetNotesForm, NoteFromDb,
+    SwapNotesError, UpdateNoteForm, UpdateNotesError,
+};
+use crate::db::nodes::{
+    CreateNodeError, CreateNodeForm, DeleteNodesError, GetNodeError, GetNodesError, GetNodesForm,
+    NodeFromDb, SwapNodesError, UpdateNodeForm, UpdateNodesError,
+};
+use crate::db::sections::{
+    CreateSectionForm, DeleteSectionsError, GetSectionError, GetSectionsError, GetSectionsForm,
+    SectionFromDb, SwapSectionsError, UpdateSectionForm, UpdateSectionsError,
+};
+use crate::db::sessions::{CreateSessionForm, SessionFromDb};
+use crate::db::subsections::{
+    CreateSubsectionForm, DeleteSubsectionsError, GetSubsectionError, GetSubsectionsError,
+    GetSubsectionsForm, SubsectionFromDb, SwapSubsectionsError, UpdateSubsectionForm,
+    UpdateSubsectionsError,
+};
+use crate::db::tags::{
+    AttachTagsError, CreateTagError, DeleteTagsError, GetTagError, GetTagsError, GetTagsForm,
+    MergeTagsError, NoteTagFromDb, RenameTagError, TagFromDb, TagWithCountFromDb,
+};
+use crate::db::users::{CreateUserForm, GetUserError, GetUsersError, GetUsersForm, UserFromDb};
+use crate::error::Error;
+
+pub mod memory;
+pub mod mysql;
+pub mod sql;
+
+/// The database engines a storage can run on, picked from the `DATABASE_URL` scheme.
+/// `Memory` is never picked from a URL: it is only built by `Storage::memory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    MySql,
+    Postgres,
+    Sqlite,
+    Memory,
+}
+
+impl Backend {
+    pub fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split_once(':').map(|(scheme, _)| scheme)?;
+        match scheme.to_ascii_lowercase().as_str() {
+            "mysql" | "mariadb" => Some(Backend::MySql),
+            "postgres" | "postgresql" => Some(Backend::Postgres),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::MySql => "MySQL",
+            Backend::Postgres => "PostgreSQL",
+            Backend::Sqlite => "SQLite",
+            Backend::Memory => "in-memory",
+        }
+    }
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create_user(&self, form: CreateUserForm) -> Result<(), Error>;
+
+    async fn get_users(&self, form: GetUsersForm) -> Result<Vec<UserFromDb>, GetUsersError>;
+
+    /// Count the users matching the form, ignoring its ordering and paging.
+    async fn count_users(&self, form: GetUsersForm) -> Result<u64, GetUsersError>;
+
+    async fn get_user(&self, form: GetUsersForm) -> Result<UserFromDb, GetUserError> {
+        if form.is_all_none() {
+            return Err(GetUserError::NoInfoToGetFromProvided);
+        }
+        let mut users = self
+            .get_users(form)
+            .await
+            .map_err(|GetUsersError::UnexpectedError(err)| GetUserError::UnexpectedError(err))?;
+        if users.is_empty() {
+            return Err(GetUserError::NoResults);
+        }
+        Ok(users.swap_remove(0))
+    }
+}
+
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create_session(&self, form: CreateSessionForm) -> Result<(), Error>;
+
+    async fn get_session_by_token(&self, token: String) -> Result<SessionFromDb, Error>;
+
+    async fn delete_session_by_token(&self, token: String) -> Result<(), Error>;
+
+    async fn delete_sessions_by_user(&self, user_id: u32) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait SectionRepository: Send + Sync {
+    async fn get_sections(
+        &self,
+        form: GetSectionsForm,
+    ) -> Result<Vec<SectionFromDb>, GetSectionsError>;
+
+    /// Count the sections matching the form, ignoring its ordering and paging.
+    async fn count_sections(&self, form: GetSectionsForm) -> Result<u64, GetSectionsError>;
+
+    /// Get a single section (using LIMIT 1) based on the filtering form.
+    async fn get_section(&self, form: GetSectionsForm) -> Result<SectionFromDb, GetSectionError> {
+        let sections = self
+            .get_sections(GetSectionsForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetSectionsError::UnexpectedError(err)| {
+                GetSectionError::UnexpectedError(err)
+            })?;
+        sections
+            .into_iter()
+            .next()
+            .ok_or(GetSectionError::NotFoundError)
+    }
+
+    async fn create_section(&self, form: CreateSectionForm) -> Result<(), Error>;
+
+    async fn update_sections(
+        &self,
+        form: UpdateSectionForm,
+        identified_by: GetSectionsForm,
+    ) -> Result<(), UpdateSectionsError>;
+
+    /// Swap the positions of two sections, at their expected `versions`.
+    async fn swap_sections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), SwapSectionsError>;
+
+    async fn delete_sections(&self, form: GetSectionsForm) -> Result<(), DeleteSectionsError>;
+
+    /// Delete a single section (using LIMIT 1).
+    async fn delete_section(&self, form: GetSectionsForm) -> Result<(), DeleteSectionsError> {
+        self.delete_sections(GetSectionsForm {
+            limit: Some(1),
+            ..form
+        })
+        .await
+    }
+}
+
+#[async_trait]
+pub trait SubsectionRepository: Send + Sync {
+    async fn get_subsections(
+        &self,
+        form: GetSubsectionsForm,
+    ) -> Result<Vec<SubsectionFromDb>, GetSubsectionsError>;
+
+    /// Count the subsections matching the form, ignoring its ordering and paging.
+    async fn count_subsections(&self, form: GetSubsectionsForm)
+        -> Result<u64, GetSubsectionsError>;
+
+    /// Get a single subsection (using LIMIT 1) based on the filtering form.
+    async fn get_subsection(
+        &self,
+        form: GetSubsectionsForm,
+    ) -> Result<SubsectionFromDb, GetSubsectionError> {
+        let subsections = self
+            .get_subsections(GetSubsectionsForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetSubsectionsError::UnexpectedError(err)| {
+                GetSubsectionError::UnexpectedError(err)
+            })?;
+        subsections
+            .into_iter()
+            .next()
+            .ok_or(GetSubsectionError::NotFoundError)
+    }
+
+    /// Create a subsection as the last child of its section.
+    /// Fails when `section_id` does not point to a section.
+    async fn create_subsection(&self, form: CreateSubsectionForm) -> Result<(), Error>;
+
+    async fn update_subsections(
+        &self,
+        form: UpdateSubsectionForm,
+        identified_by: GetSubsectionsForm,
+    ) -> Result<(), UpdateSubsectionsError>;
+
+    /// Swap the positions of two subsections of a section, at their expected
+    /// `versions`.
+    async fn swap_subsections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), SwapSubsectionsError>;
+
+    async fn delete_subsections(
+        &self,
+        form: GetSubsectionsForm,
+    ) -> Result<(), DeleteSubsectionsError>;
+
+    /// Delete a single subsection (using LIMIT 1).
+    async fn delete_subsection(
+        &self,
+        form: GetSubsectionsForm,
+    ) -> Result<(), DeleteSubsectionsError> {
+        self.delete_subsections(GetSubsectionsForm {
+            limit: Some(1),
+            ..form
+        })
+        .await
+    }
+}
+
+#[async_trait]
+pub trait NoteRepository: Send + Sync {
+    async fn get_notes(&self, form: GetNotesForm) -> Result<Vec<NoteFromDb>, GetNotesError>;
+
+    /// Count the notes matching the form, ignoring its ordering and paging.
+    async fn count_notes(&self, form: GetNotesForm) -> Result<u64, GetNotesError>;
+
+    /// Get a single note (using LIMIT 1) based on the filtering form.
+    async fn get_note(&self, form: GetNotesForm) -> Result<NoteFromDb, GetNoteError> {
+        let notes = self
+            .get_notes(GetNotesForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetNotesError::UnexpectedError(err)| GetNoteError::UnexpectedError(err))?;
+        notes.into_iter().next().ok_or(GetNoteError::NotFoundError)
+    }
+
+    /// Create a note as the last one of its node.
+    async fn create_note(&self, form: CreateNoteForm) -> Result<(), Error>;
+
+    async fn update_notes(
+        &self,
+        form: UpdateNoteForm,
+        identified_by: GetNotesForm,
+    ) -> Result<(), UpdateNotesError>;
+
+    /// Swap the positions of two notes hanging from the same node, at their
+    /// expected `versions`.
+    async fn swap_notes(&self, ids: [u32; 2], versions: [u32; 2]) -> Result<(), SwapNotesError>;
+
+    async fn delete_notes(&self, form: GetNotesForm) -> Result<(), DeleteNotesError>;
+
+    /// Delete a single note (using LIMIT 1).
+    async fn delete_note(&self, form: GetNotesForm) -> Result<(), DeleteNotesError> {
+        self.delete_notes(GetNotesForm {
+            limit: Some(1),
+            ..form
+        })
+        .await
+    }
+}
+
+/// The content tree as a whole, whatever the depth of its nodes. Sections and
+/// subsections are the nodes of the first two levels.
+#[async_trait]
+pub trait NodeRepository: Send + Sync {
+    async fn get_nodes(&self, form: GetNodesForm) -> Result<Vec<NodeFromDb>, GetNodesError>;
+
+    /// Get a single node (using LIMIT 1) based on the filtering form.
+    async fn get_node(&self, form: GetNodesForm) -> Result<NodeFromDb, GetNodeError> {
+        let nodes = self
+            .get_nodes(GetNodesForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetNodesError::UnexpectedError(err)| GetNodeError::UnexpectedError(err))?;
+        nodes.into_iter().next().ok_or(GetNodeError::NotFoundError)
+    }
+
+    /// Create a node as the last child of its parent. Returns the id of the node.
+    async fn create_node(&self, form: CreateNodeForm) -> Result<u32, CreateNodeError>;
+
+    /// Update the nodes matched by `identified_by`, rejecting cycles.
+    async fn update_nodes(
+        &self,
+        form: UpdateNodeForm,
+        identified_by: GetNodesForm,
+    ) -> Result<(), UpdateNodesError>;
+
+    /// Swap the positions of two sibling nodes, at their expected `versions`.
+    async fn swap_nodes(&self, ids: [u32; 2], versions: [u32; 2]) -> Result<(), SwapNodesError>;
+
+    /// Nodes that still have children or notes are kept, as the foreign keys do.
+    async fn delete_nodes(&self, form: GetNodesForm) -> Result<(), DeleteNodesError>;
+
+    /// Delete a single node (using LIMIT 1).
+    async fn delete_node(&self, form: GetNodesForm) -> Result<(), DeleteNodesError> {
+        self.delete_nodes(GetNodesForm {
+            limit: Some(1),
+            ..form
+        })
+        .await
+    }
+}
+
+/// The tags and the notes they are attached to. Every change of the tags of a
+/// note moves its `updated_at`.
+#[async_trait]
+pub trait TagRepository: Send + Sync {
+    async fn get_tags(&self, form: GetTagsForm) -> Result<Vec<TagFromDb>, GetTagsError>;
+
+    /// Get a single tag (using LIMIT 1) based on the filtering form.
+    async fn get_tag(&self, form: GetTagsForm) -> Result<TagFromDb, GetTagError> {
+        let tags = self
+            .get_tags(GetTagsForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetTagsError::UnexpectedError(err)| GetTagError::UnexpectedError(err))?;
+        tags.into_iter().next().ok_or(GetTagError::NotFoundError)
+    }
+
+    /// Every tag with the number of notes it is attached to, sorted by name.
+    async fn get_tags_with_counts(&self) -> Result<Vec<TagWithCountFromDb>, GetTagsError>;
+
+    /// The tags attached to the given notes, sorted by tag name.
+    async fn get_tags_of_notes(&self, note_ids: &[u32])
+        -> Result<Vec<NoteTagFromDb>, GetTagsError>;
+
+    /// Returns the id of the created tag.
+    async fn create_tag(&self, name: String) -> Result<u32, CreateTagError>;
+
+    /// The ids of the tags named `names`, creating the missing ones.
+    async fn get_or_create_tags(&self, names: &[String]) -> Result<Vec<u32>, CreateTagError>;
+
+    async fn rename_tag(&self, id: u32, name: String) -> Result<(), RenameTagError>;
+
+    /// Every note tagged with the source gets the target tag, then the source
+    /// tag is deleted.
+    async fn merge_tags(&self, source_id: u32, target_id: u32) -> Result<(), MergeTagsError>;
+
+    /// The tag is detached from every note.
+    async fn delete_tag(&self, id: u32) -> Result<(), DeleteTagsError>;
+
+    /// Tags already attached are kept.
+    async fn attach_tags(&self, note_id: u32, tag_ids: &[u32]) -> Result<(), AttachTagsError>;
+
+    async fn detach_tag(&self, note_id: u32, tag_id: u32) -> Result<(), DeleteTagsError>;
+}
+
+/// The files and links attached to the notes, deleted along with their note.
+/// Every change of the attachments of a note moves its `updated_at`.
+#[async_trait]
+pub trait AttachmentRepository: Send + Sync {
+    async fn get_attachments(
+        &self,
+        form: GetAttachmentsForm,
+    ) -> Result<Vec<AttachmentFromDb>, GetAttachmentsError>;
+
+    /// Get a single attachment (using LIMIT 1) based on the filtering form.
+    async fn get_attachment(
+        &self,
+        form: GetAttachmentsForm,
+    ) -> Result<AttachmentFromDb, GetAttachmentError> {
+        let attachments = self
+            .get_attachments(GetAttachmentsForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetAttachmentsError::UnexpectedError(err)| {
+                GetAttachmentError::UnexpectedError(err)
+            })?;
+        attachments
+            .into_iter()
+            .next()
+            .ok_or(GetAttachmentError::NotFoundError)
+    }
+
+    /// Attach a file or link after the attachments of the note. Returns the id of
+    /// the attachment.
+    async fn create_attachment(
+        &self,
+        form: CreateAttachmentForm,
+    ) -> Result<u32, CreateAttachmentError>;
+
+    async fn update_attachments(
+        &self,
+        form: UpdateAttachmentForm,
+        identified_by: GetAttachmentsForm,
+    ) -> Result<(), UpdateAttachmentsError>;
+
+    /// Swap the positions of two attachments of the same note.
+    async fn swap_attachments(&self, ids: [u32; 2]) -> Result<(), SwapAttachmentsError>;
+
+    async fn delete_attachments(
+        &self,
+        form: GetAttachmentsForm,
+    ) -> Result<(), DeleteAttachmentsError>;
+
+    /// Delete a single attachment (using LIMIT 1).
+    async fn delete_attachment(
+        &self,
+        form: GetAttachmentsForm,
+    ) -> Result<(), DeleteAttachmentsError> {
+        self.delete_attachments(GetAttachmentsForm {
+            limit: Some(1),
+            ..form
+        })
+        .await
+    }
+}
+
+/// The records of the uploaded files, whose content lives in the file storage.
+#[async_trait]
+pub trait FileRepository: Send + Sync {
+    async fn get_files(&self, form: GetFilesForm) -> Result<Vec<FileFromDb>, GetFilesError>;
+
+    /// Get a single file (using LIMIT 1) based on the filtering form.
+    async fn get_file(&self, form: GetFilesForm) -> Result<FileFromDb, GetFileError> {
+        let files = self
+            .get_files(GetFilesForm {
+                limit: Some(1),
+                ..form
+            })
+            .await
+            .map_err(|GetFilesError::UnexpectedError(err)| GetFileError::UnexpectedError(err))?;
+        files.into_iter().next().ok_or(GetFileError::NotFoundError)
+    }
+
+    /// Register a stored file, deduplicated by content hash. Returns the id of the
+    /// file and whether it was newly created.
+    async fn create_file(&self, form: CreateFileForm) -> Result<(u32, bool), CreateFileError>;
+
+    /// Files still attached to a note are kept. Returns the deleted record, so
+    /// that its content can be removed from the storage.
+    async fn delete_file(&self, id: u32) -> Result<FileFromDb, DeleteFileError>;
+}
+
+/// The changes of the imports and the batches, made together.
+#[async_trait]
+pub trait CatalogRepository: Send + Sync {
+    /// Apply the changes in one transaction: either all of them are made, or none.
+    /// Returns the id of the row written by each change.
+    async fn apply_catalog_changes(
+        &self,
+        changes: &[CatalogChange],
+    ) -> Result<Vec<u32>, ApplyCatalogChangesError>;
+}
+
+/// The repositories backing the services, all sharing the same database.
+#[derive(Clone)]
+pub struct Storage {
+    pub users: Arc<dyn UserRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub sections: Arc<dyn SectionRepository>,
+    pub subsections: Arc<dyn SubsectionRepository>,
+    pub notes: Arc<dyn NoteRepository>,
+    pub nodes: Arc<dyn NodeRepository>,
+    pub tags: Arc<dyn TagRepository>,
+    pub attachments: Arc<dyn AttachmentRepository>,
+    pub files: Arc<dyn FileRepository>,
+    pub catalog: Arc<dyn CatalogRepository>,
+    backend: Backend,
+    /// Bumped by the services after every write of the content, shared by the clones.
+    content_generation: Arc<AtomicU64>,
+}
+
+impl Storage {
+    fn from_repository<R>(repository: Arc<R>, backend: Backend) -> Self
+    where
+        R: UserRepository
+            + SessionRepository
+            + SectionRepository
+            + SubsectionRepository
+            + NoteRepository
+            + NodeRepository
+            + TagRepository
+            + AttachmentRepository
+            + FileRepository
+            + CatalogRepository
+            + 'static,
+    {
+        Self {
+            users: repository.clone(),
+            sessions: repository.clone(),
+            sections: repository.clone(),
+            subsections: repository.clone(),
+            notes: repository.clone(),
+            nodes: repository.clone(),
+            tags: repository.clone(),
+            attachments: repository.clone(),
+            files: repository.clone(),
+            catalog: repository,
+            backend,
+            content_generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn mysql(repository: mysql::MySqlRepository) -> Self {
+        Self::from_repository(Arc::new(repository), Backend::MySql)
+    }
+
+    pub fn sql(repository: sql::SqlRepository) -> Self {
+        let backend = repository.backend();
+        Self::from_repository(Arc::new(repository), backend)
+    }
+
+    /// An empty storage kept in memory, mostly useful for tests.
+    pub fn memory() -> Self {
+        let repository = Arc::new(memory::MemoryRepository::default());
+        Self::from_repository(repository, Backend::Memory)
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Report a write of the sections, the notes, the tags or the attachments,
+    /// whether it succeeded or not, so that the views built from them are rebuilt.
+    pub fn content_changed(&self) {
+        self.content_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Changes with every write reported by `content_changed`.
+    pub fn content_generation(&self) -> u64 {
+        self.content_generation.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    MissingUrlError,
+    UnsupportedSchemeError(String),
+    ConnectionError(sqlx::Error),
+}
+
+/// Connect to the database pointed by `url`, picking the backend from its scheme.
+/// The missing tables are created and the pending migrations are run.
+pub async fn connect(url: &str) -> Result<Storage, ConnectError> {
+    match Backend::from_url(url) {
+        Some(Backend::MySql) => {
+            let pool = sqlx::mysql::MySqlPoolOptions::new()
+                .max_connections(5)
+                .connect(url)
+                .await
+                .map_err(ConnectError::ConnectionError)?;
+            let repository = mysql::MySqlRepository::new(pool);
+            repository
+                .create_required_tables()
+                .await
+                .map_err(ConnectError::ConnectionError)?;
+            Ok(Storage::mysql(repository))
+        }
+        Some(backend @ (Backend::Postgres | Backend::Sqlite)) => {
+            let repository = sql::SqlRepository::connect(backend, url)
+                .await
+                .map_err(ConnectError::ConnectionError)?;
+            repository
+                .create_required_tables()
+                .await
+                .map_err(ConnectError::ConnectionError)?;
+            Ok(Storage::sql(repository))
+        }
+        Some(Backend::Memory) | None => Err(ConnectError::UnsupportedSchemeError(
+            url.split(':').next().unwrap_or_default().to_string(),
+        )),
+    }
+}
+
+/// Connect to the database configured by `DATABASE_URL`.
+pub async fn establish_storage() -> Result<Storage, ConnectError> {
+    dotenv().ok();
+    let url = std::env::var("DATABASE_URL").map_err(|_| ConnectError::MissingUrlError)?;
+    connect(&url).await
+}
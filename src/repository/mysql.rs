@@ -0,0 +1,393 @@
+use async_trait::async_trait;
+use loggit::info;
+
+use crate::db;
+use crate::error::Error;
+use crate::repository::{
+    AttachmentRepository, CatalogRepository, FileRepository, NodeRepository, NoteRepository,
+    SectionRepository, SessionRepository, SubsectionRepository, TagRepository, UserRepository,
+};
+
+/// The MySQL storage, backed by the queries of the `db` module.
+pub struct MySqlRepository {
+    pool: sqlx::Pool<sqlx::MySql>,
+}
+
+impl MySqlRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::MySql>) -> Self {
+        Self { pool }
+    }
+
+    /// Create the missing tables and columns and run the pending migrations.
+    pub async fn create_required_tables(&self) -> Result<(), sqlx::Error> {
+        let pool = &self.pool;
+        db::create_tables::create_required_tables(pool).await;
+        db::create_tables::ensure_notes_description_column_exists(pool).await?;
+        db::create_tables::ensure_notes_metadata_columns_exist(pool).await?;
+        db::create_tables::ensure_timestamp_columns_exist(pool).await?;
+        db::create_tables::ensure_version_columns_exist(pool).await?;
+        db::create_tables::ensure_publication_columns_exist(pool).await?;
+        db::create_tables::ensure_slug_columns_exist(pool).await?;
+        if db::create_tables::migrate_sections_to_nodes(pool).await? {
+            info!("The legacy sections and subsections were migrated to nodes");
+        }
+        let filled = db::create_tables::fill_missing_slugs(pool).await?;
+        if filled > 0 {
+            info!("{} slugs were derived from the titles", filled);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for MySqlRepository {
+    async fn create_user(&self, form: db::users::CreateUserForm) -> Result<(), Error> {
+        db::users::create_user(&self.pool, form).await
+    }
+
+    async fn get_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<Vec<db::users::UserFromDb>, db::users::GetUsersError> {
+        db::users::get_users(&self.pool, form).await
+    }
+
+    async fn count_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<u64, db::users::GetUsersError> {
+        db::users::count_users(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl SessionRepository for MySqlRepository {
+    async fn create_session(&self, form: db::sessions::CreateSessionForm) -> Result<(), Error> {
+        db::sessions::create_session(&self.pool, form).await
+    }
+
+    async fn get_session_by_token(
+        &self,
+        token: String,
+    ) -> Result<db::sessions::SessionFromDb, Error> {
+        db::sessions::get_session_by_token(&self.pool, token).await
+    }
+
+    async fn delete_session_by_token(&self, token: String) -> Result<(), Error> {
+        db::sessions::delete_session_by_token(&self.pool, token).await
+    }
+
+    async fn delete_sessions_by_user(&self, user_id: u32) -> Result<(), Error> {
+        db::sessions::delete_sessions_by_user(&self.pool, user_id).await
+    }
+}
+
+#[async_trait]
+impl SectionRepository for MySqlRepository {
+    async fn get_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<Vec<db::sections::SectionFromDb>, db::sections::GetSectionsError> {
+        db::sections::get_sections(&self.pool, form).await
+    }
+
+    async fn count_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<u64, db::sections::GetSectionsError> {
+        db::sections::count_sections(&self.pool, form).await
+    }
+
+    async fn create_section(&self, form: db::sections::CreateSectionForm) -> Result<(), Error> {
+        db::sections::create_section(&self.pool, form).await
+    }
+
+    async fn update_sections(
+        &self,
+        form: db::sections::UpdateSectionForm,
+        identified_by: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::UpdateSectionsError> {
+        db::sections::update_sections(&self.pool, form, identified_by).await
+    }
+
+    async fn swap_sections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::sections::SwapSectionsError> {
+        db::sections::swap_sections(&self.pool, ids, versions).await
+    }
+
+    async fn delete_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::DeleteSectionsError> {
+        db::sections::delete_sections(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl SubsectionRepository for MySqlRepository {
+    async fn get_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<Vec<db::subsections::SubsectionFromDb>, db::subsections::GetSubsectionsError> {
+        db::subsections::get_subsections(&self.pool, form).await
+    }
+
+    async fn count_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<u64, db::subsections::GetSubsectionsError> {
+        db::subsections::count_subsections(&self.pool, form).await
+    }
+
+    async fn create_subsection(
+        &self,
+        form: db::subsections::CreateSubsectionForm,
+    ) -> Result<(), Error> {
+        db::subsections::create_subsection(&self.pool, form).await
+    }
+
+    async fn update_subsections(
+        &self,
+        form: db::subsections::UpdateSubsectionForm,
+        identified_by: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::UpdateSubsectionsError> {
+        db::subsections::update_subsections(&self.pool, form, identified_by).await
+    }
+
+    async fn swap_subsections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::subsections::SwapSubsectionsError> {
+        db::subsections::swap_subsections(&self.pool, ids, versions).await
+    }
+
+    async fn delete_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::DeleteSubsectionsError> {
+        db::subsections::delete_subsections(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl NoteRepository for MySqlRepository {
+    async fn get_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<Vec<db::lecture_notes::NoteFromDb>, db::lecture_notes::GetNotesError> {
+        db::lecture_notes::get_notes(&self.pool, form).await
+    }
+
+    async fn count_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<u64, db::lecture_notes::GetNotesError> {
+        db::lecture_notes::count_notes(&self.pool, form).await
+    }
+
+    async fn create_note(&self, form: db::lecture_notes::CreateNoteForm) -> Result<(), Error> {
+        db::lecture_notes::create_note(&self.pool, form).await
+    }
+
+    async fn update_notes(
+        &self,
+        form: db::lecture_notes::UpdateNoteForm,
+        identified_by: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::UpdateNotesError> {
+        db::lecture_notes::update_notes(&self.pool, form, identified_by).await
+    }
+
+    async fn swap_notes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::lecture_notes::SwapNotesError> {
+        db::lecture_notes::swap_notes(&self.pool, ids, versions).await
+    }
+
+    async fn delete_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::DeleteNotesError> {
+        db::lecture_notes::delete_notes(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl NodeRepository for MySqlRepository {
+    async fn get_nodes(
+        &self,
+        form: db::nodes::GetNodesForm,
+    ) -> Result<Vec<db::nodes::NodeFromDb>, db::nodes::GetNodesError> {
+        db::nodes::get_nodes(&self.pool, form).await
+    }
+
+    async fn create_node(
+        &self,
+        form: db::nodes::CreateNodeForm,
+    ) -> Result<u32, db::nodes::CreateNodeError> {
+        db::nodes::create_node(&self.pool, form).await
+    }
+
+    async fn update_nodes(
+        &self,
+        form: db::nodes::UpdateNodeForm,
+        identified_by: db::nodes::GetNodesForm,
+    ) -> Result<(), db::nodes::UpdateNodesError> {
+        db::nodes::update_nodes(&self.pool, form, identified_by).await
+    }
+
+    async fn swap_nodes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::nodes::SwapNodesError> {
+        db::nodes::swap_nodes(&self.pool, ids, versions).await
+    }
+
+    async fn delete_nodes(
+        &self,
+        form: db::nodes::GetNodesForm,
+    ) -> Result<(), db::nodes::DeleteNodesError> {
+        db::nodes::delete_nodes(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl TagRepository for MySqlRepository {
+    async fn get_tags(
+        &self,
+        form: db::tags::GetTagsForm,
+    ) -> Result<Vec<db::tags::TagFromDb>, db::tags::GetTagsError> {
+        db::tags::get_tags(&self.pool, form).await
+    }
+
+    async fn get_tags_with_counts(
+        &self,
+    ) -> Result<Vec<db::tags::TagWithCountFromDb>, db::tags::GetTagsError> {
+        db::tags::get_tags_with_counts(&self.pool).await
+    }
+
+    async fn get_tags_of_notes(
+        &self,
+        note_ids: &[u32],
+    ) -> Result<Vec<db::tags::NoteTagFromDb>, db::tags::GetTagsError> {
+        db::tags::get_tags_of_notes(&self.pool, note_ids).await
+    }
+
+    async fn create_tag(&self, name: String) -> Result<u32, db::tags::CreateTagError> {
+        db::tags::create_tag(&self.pool, name).await
+    }
+
+    async fn get_or_create_tags(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<u32>, db::tags::CreateTagError> {
+        db::tags::get_or_create_tags(&self.pool, names).await
+    }
+
+    async fn rename_tag(&self, id: u32, name: String) -> Result<(), db::tags::RenameTagError> {
+        db::tags::rename_tag(&self.pool, id, name).await
+    }
+
+    async fn merge_tags(
+        &self,
+        source_id: u32,
+        target_id: u32,
+    ) -> Result<(), db::tags::MergeTagsError> {
+        db::tags::merge_tags(&self.pool, source_id, target_id).await
+    }
+
+    async fn delete_tag(&self, id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        db::tags::delete_tag(&self.pool, id).await
+    }
+
+    async fn attach_tags(
+        &self,
+        note_id: u32,
+        tag_ids: &[u32],
+    ) -> Result<(), db::tags::AttachTagsError> {
+        db::tags::attach_tags(&self.pool, note_id, tag_ids).await
+    }
+
+    async fn detach_tag(&self, note_id: u32, tag_id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        db::tags::detach_tag(&self.pool, note_id, tag_id).await
+    }
+}
+
+#[async_trait]
+impl AttachmentRepository for MySqlRepository {
+    async fn get_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<Vec<db::attachments::AttachmentFromDb>, db::attachments::GetAttachmentsError> {
+        db::attachments::get_attachments(&self.pool, form).await
+    }
+
+    async fn create_attachment(
+        &self,
+        form: db::attachments::CreateAttachmentForm,
+    ) -> Result<u32, db::attachments::CreateAttachmentError> {
+        db::attachments::create_attachment(&self.pool, form).await
+    }
+
+    async fn update_attachments(
+        &self,
+        form: db::attachments::UpdateAttachmentForm,
+        identified_by: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::UpdateAttachmentsError> {
+        db::attachments::update_attachments(&self.pool, form, identified_by).await
+    }
+
+    async fn swap_attachments(
+        &self,
+        ids: [u32; 2],
+    ) -> Result<(), db::attachments::SwapAttachmentsError> {
+        db::attachments::swap_attachments(&self.pool, ids).await
+    }
+
+    async fn delete_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::DeleteAttachmentsError> {
+        db::attachments::delete_attachments(&self.pool, form).await
+    }
+}
+
+#[async_trait]
+impl FileRepository for MySqlRepository {
+    async fn get_files(
+        &self,
+        form: db::files::GetFilesForm,
+    ) -> Result<Vec<db::files::FileFromDb>, db::files::GetFilesError> {
+        db::files::get_files(&self.pool, form).await
+    }
+
+    async fn create_file(
+        &self,
+        form: db::files::CreateFileForm,
+    ) -> Result<(u32, bool), db::files::CreateFileError> {
+        db::files::create_file(&self.pool, form).await
+    }
+
+    async fn delete_file(
+        &self,
+        id: u32,
+    ) -> Result<db::files::FileFromDb, db::files::DeleteFileError> {
+        db::files::delete_file(&self.pool, id).await
+    }
+}
+
+#[async_trait]
+impl CatalogRepository for MySqlRepository {
+    async fn apply_catalog_changes(
+        &self,
+        changes: &[db::catalog::CatalogChange],
+    ) -> Result<Vec<u32>, db::catalog::ApplyCatalogChangesError> {
+        db::catalog::apply_catalog_changes(&self.pool, changes).await
+    }
+}
@@ -0,0 +1,2623 @@
+use async_trait::async_trait;
+use loggit::{trace, warn};
+use sqlx::any::AnyPoolOptions;
+use sqlx::Any;
+
+use crate::db;
+use crate::db::catalog::{CatalogChange, ImportedAttachment, ImportedNote, NodeRef, NoteRef};
+use crate::db::nodes::{GetNodesForm, NodeFromDb};
+use crate::db::query::{Assignments, BindValues, Clauses, Filter, Value};
+use crate::db::slugs::{missing_slugs, SlugRow};
+use crate::error::Error;
+use crate::repository::{
+    AttachmentRepository, Backend, CatalogRepository, FileRepository, NodeRepository,
+    NoteRepository, SectionRepository, SessionRepository, SubsectionRepository, TagRepository,
+    UserRepository,
+};
+
+/// The PostgreSQL and SQLite storage, sharing one portable dialect through `sqlx::Any`.
+/// Unsigned integers are stored as BIGINT and booleans as 0/1, since neither
+/// has a portable representation.
+pub struct SqlRepository {
+    pool: sqlx::Pool<Any>,
+    backend: Backend,
+}
+
+#[derive(sqlx::FromRow)]
+struct UserRow {
+    id: i64,
+    username: String,
+    password: String,
+    is_admin: i64,
+}
+
+impl From<UserRow> for db::users::UserFromDb {
+    fn from(value: UserRow) -> Self {
+        Self {
+            id: value.id as u32,
+            username: value.username,
+            password: value.password,
+            is_admin: value.is_admin != 0,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SessionRow {
+    id: i64,
+    user_id: i64,
+    token: String,
+    expires_at: i64,
+}
+
+impl From<SessionRow> for db::sessions::SessionFromDb {
+    fn from(value: SessionRow) -> Self {
+        Self {
+            id: value.id as u32,
+            user_id: value.user_id as u32,
+            token: value.token,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct NodeRow {
+    id: i64,
+    parent_id: Option<i64>,
+    kind: String,
+    title: String,
+    position: i64,
+    created_at: i64,
+    updated_at: i64,
+    version: i64,
+    status: String,
+    publish_at: Option<i64>,
+    slug: String,
+    previous_slugs: String,
+}
+
+impl From<NodeRow> for NodeFromDb {
+    fn from(value: NodeRow) -> Self {
+        Self {
+            id: value.id as u32,
+            parent_id: value.parent_id.map(|id| id as u32),
+            kind: value.kind,
+            title: value.title,
+            position: value.position as u32,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version as u32,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs: value.previous_slugs,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct NoteRow {
+    id: i64,
+    name: String,
+    description: String,
+    url: String,
+    position: i64,
+    node_id: Option<i64>,
+    section_id: Option<i64>,
+    subsection_id: Option<i64>,
+    kind: Option<String>,
+    course_code: Option<String>,
+    term: Option<String>,
+    language: Option<String>,
+    authors: String,
+    license: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+    version: i64,
+    status: String,
+    publish_at: Option<i64>,
+    slug: String,
+    previous_slugs: String,
+}
+
+impl From<NoteRow> for db::lecture_notes::NoteFromDb {
+    fn from(value: NoteRow) -> Self {
+        Self {
+            id: value.id as u32,
+            name: value.name,
+            description: value.description,
+            url: value.url,
+            position: value.position as u32,
+            node_id: value.node_id.map(|id| id as u32),
+            section_id: value.section_id.map(|id| id as u32),
+            subsection_id: value.subsection_id.map(|id| id as u32),
+            kind: value.kind,
+            course_code: value.course_code,
+            term: value.term,
+            language: value.language,
+            authors: value.authors,
+            license: value.license,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version as u32,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs: value.previous_slugs,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct TagRow {
+    id: i64,
+    name: String,
+}
+
+impl From<TagRow> for db::tags::TagFromDb {
+    fn from(value: TagRow) -> Self {
+        Self {
+            id: value.id as u32,
+            name: value.name,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct TagWithCountRow {
+    id: i64,
+    name: String,
+    note_count: i64,
+}
+
+impl From<TagWithCountRow> for db::tags::TagWithCountFromDb {
+    fn from(value: TagWithCountRow) -> Self {
+        Self {
+            id: value.id as u32,
+            name: value.name,
+            note_count: value.note_count as u32,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct NoteTagRow {
+    note_id: i64,
+    tag_id: i64,
+    name: String,
+}
+
+impl From<NoteTagRow> for db::tags::NoteTagFromDb {
+    fn from(value: NoteTagRow) -> Self {
+        Self {
+            note_id: value.note_id as u32,
+            tag_id: value.tag_id as u32,
+            name: value.name,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AttachmentRow {
+    id: i64,
+    note_id: i64,
+    label: String,
+    url: String,
+    mime_type: Option<String>,
+    size: Option<i64>,
+    file_id: Option<i64>,
+    position: i64,
+}
+
+impl From<AttachmentRow> for db::attachments::AttachmentFromDb {
+    fn from(value: AttachmentRow) -> Self {
+        Self {
+            id: value.id as u32,
+            note_id: value.note_id as u32,
+            label: value.label,
+            url: value.url,
+            mime_type: value.mime_type,
+            size: value.size.map(|size| size as u64),
+            file_id: value.file_id.map(|id| id as u32),
+            position: value.position as u32,
+        }
+    }
+}
+
+/// An attachment as compared with the imported ones, its file named by hash.
+#[derive(sqlx::FromRow)]
+struct StoredAttachmentRow {
+    label: String,
+    url: String,
+    mime_type: Option<String>,
+    size: Option<i64>,
+    sha256: Option<String>,
+}
+
+impl From<StoredAttachmentRow> for ImportedAttachment {
+    fn from(value: StoredAttachmentRow) -> Self {
+        Self {
+            label: value.label,
+            url: value.url,
+            mime_type: value.mime_type,
+            size: value.size.map(|size| size as u64),
+            file_sha256: value.sha256,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct FileRow {
+    id: i64,
+    sha256: String,
+    original_name: String,
+    mime_type: String,
+    size: i64,
+}
+
+impl From<FileRow> for db::files::FileFromDb {
+    fn from(value: FileRow) -> Self {
+        Self {
+            id: value.id as u32,
+            sha256: value.sha256,
+            original_name: value.original_name,
+            mime_type: value.mime_type,
+            size: value.size as u64,
+        }
+    }
+}
+
+/// The notes clauses of the portable schema, where the authors are a JSON text.
+fn notes_clauses(form: &db::lecture_notes::GetNotesForm) -> Clauses {
+    let mut filters = form.column_filters();
+    if let Some(author) = &form.author {
+        // The authors are stored as a JSON array, so look for the quoted name.
+        let quoted = serde_json::to_string(author).unwrap_or_default();
+        filters.push(Filter::contains("authors", quoted));
+    }
+    if let Some(filter) = db::tags::notes_with_tags_condition(&form.tags, &form.tag_match) {
+        filters.push(filter);
+    }
+    Clauses {
+        filter: Filter::combine(filters, &form.or_and)
+            .and(form.published_condition())
+            .and(form.filter.clone()),
+        order_by: form.order_by.clone(),
+        limit: form.limit,
+        offset: form.offset,
+    }
+}
+
+impl SqlRepository {
+    pub async fn connect(backend: Backend, url: &str) -> Result<Self, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let options = match backend {
+            // A single long-lived connection, so that `sqlite::memory:` keeps its content.
+            Backend::Sqlite => AnyPoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None),
+            Backend::MySql | Backend::Postgres | Backend::Memory => {
+                AnyPoolOptions::new().max_connections(5)
+            }
+        };
+        let pool = options.connect(url).await?;
+        Ok(Self { pool, backend })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Adapt a query written with `?` placeholders to the backend.
+    fn sql(&self, query: &str) -> String {
+        if self.backend != Backend::Postgres {
+            return query.to_string();
+        }
+        let mut adapted = String::with_capacity(query.len() + 8);
+        let mut index = 0;
+        for c in query.chars() {
+            if c == '?' {
+                index += 1;
+                adapted.push_str(&format!("${}", index));
+            } else {
+                adapted.push(c);
+            }
+        }
+        adapted
+    }
+
+    fn id_column(&self) -> &'static str {
+        match self.backend {
+            Backend::Postgres => "BIGSERIAL PRIMARY KEY",
+            Backend::Sqlite | Backend::MySql | Backend::Memory => {
+                "INTEGER PRIMARY KEY AUTOINCREMENT"
+            }
+        }
+    }
+
+    /// Create the tables that do not exist yet.
+    pub async fn create_required_tables(&self) -> Result<(), sqlx::Error> {
+        let id = self.id_column();
+        let statements = [
+            format!(
+                "CREATE TABLE IF NOT EXISTS users (\
+                    id {id}, \
+                    username TEXT NOT NULL, \
+                    password TEXT NOT NULL, \
+                    is_admin BIGINT NOT NULL DEFAULT 0\
+                )"
+            ),
+            format!(
+                "CREATE TABLE IF NOT EXISTS sessions (\
+                    id {id}, \
+                    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+                    token VARCHAR(128) NOT NULL UNIQUE, \
+                    expires_at BIGINT NOT NULL\
+                )"
+            ),
+            format!(
+                "CREATE TABLE IF NOT EXISTS nodes (\
+                    id {id}, \
+                    parent_id BIGINT REFERENCES nodes(id), \
+                    kind VARCHAR(32) NOT NULL, \
+                    title VARCHAR(255) NOT NULL, \
+                    position BIGINT NOT NULL, \
+                    created_at BIGINT NOT NULL DEFAULT 0, \
+                    updated_at BIGINT NOT NULL DEFAULT 0, \
+                    version BIGINT NOT NULL DEFAULT 1, \
+                    status VARCHAR(16) NOT NULL DEFAULT 'published', \
+                    publish_at BIGINT, \
+                    slug VARCHAR(255) NOT NULL DEFAULT '', \
+                    previous_slugs TEXT, \
+                    parent_key BIGINT GENERATED ALWAYS AS (COALESCE(parent_id, 0)) STORED\
+                )"
+            ),
+            "CREATE UNIQUE INDEX IF NOT EXISTS nodes_parent_position \
+                ON nodes (parent_key, position)"
+                .to_string(),
+            format!(
+                "CREATE TABLE IF NOT EXISTS notes (\
+                    id {id}, \
+                    name TEXT NOT NULL, \
+                    description TEXT NOT NULL, \
+                    url TEXT NOT NULL, \
+                    position BIGINT NOT NULL, \
+                    node_id BIGINT REFERENCES nodes(id), \
+                    kind VARCHAR(32), \
+                    course_code VARCHAR(64), \
+                    term VARCHAR(64), \
+                    language VARCHAR(16), \
+                    authors TEXT, \
+                    license VARCHAR(255), \
+                    created_at BIGINT NOT NULL DEFAULT 0, \
+                    updated_at BIGINT NOT NULL DEFAULT 0, \
+                    version BIGINT NOT NULL DEFAULT 1, \
+                    status VARCHAR(16) NOT NULL DEFAULT 'published', \
+                    publish_at BIGINT, \
+                    slug VARCHAR(255) NOT NULL DEFAULT '', \
+                    previous_slugs TEXT, \
+                    UNIQUE (position, node_id)\
+                )"
+            ),
+            format!(
+                "CREATE TABLE IF NOT EXISTS tags (\
+                    id {id}, \
+                    name VARCHAR(255) NOT NULL UNIQUE, \
+                    created_at BIGINT NOT NULL DEFAULT 0, \
+                    updated_at BIGINT NOT NULL DEFAULT 0\
+                )"
+            ),
+            "CREATE TABLE IF NOT EXISTS note_tags (\
+                note_id BIGINT NOT NULL REFERENCES notes(id) ON DELETE CASCADE, \
+                tag_id BIGINT NOT NULL REFERENCES tags(id) ON DELETE CASCADE, \
+                PRIMARY KEY (note_id, tag_id)\
+            )"
+            .to_string(),
+            format!(
+                "CREATE TABLE IF NOT EXISTS files (\
+                    id {id}, \
+                    sha256 CHAR(64) NOT NULL UNIQUE, \
+                    original_name VARCHAR(255) NOT NULL, \
+                    mime_type VARCHAR(255) NOT NULL, \
+                    size BIGINT NOT NULL, \
+                    created_at BIGINT NOT NULL DEFAULT 0, \
+                    updated_at BIGINT NOT NULL DEFAULT 0\
+                )"
+            ),
+            format!(
+                "CREATE TABLE IF NOT EXISTS note_attachments (\
+                    id {id}, \
+                    note_id BIGINT NOT NULL REFERENCES notes(id) ON DELETE CASCADE, \
+                    label VARCHAR(255) NOT NULL, \
+                    url TEXT NOT NULL, \
+                    mime_type VARCHAR(255), \
+                    size BIGINT, \
+                    file_id BIGINT REFERENCES files(id), \
+                    position BIGINT NOT NULL, \
+                    created_at BIGINT NOT NULL DEFAULT 0, \
+                    updated_at BIGINT NOT NULL DEFAULT 0, \
+                    UNIQUE (note_id, position)\
+                )"
+            ),
+        ];
+        for statement in statements {
+            trace!("{}", statement);
+            sqlx::query(&statement).execute(&self.pool).await?;
+        }
+        self.ensure_timestamp_columns_exist().await?;
+        self.ensure_version_columns_exist().await?;
+        self.ensure_publication_columns_exist().await?;
+        self.ensure_slug_columns_exist().await
+    }
+
+    /// Add the `created_at` and `updated_at` columns to the tables created before
+    /// they existed. The rows already there get the current time.
+    async fn ensure_timestamp_columns_exist(&self) -> Result<(), sqlx::Error> {
+        for table in ["nodes", "notes"] {
+            for column in ["created_at", "updated_at"] {
+                // Selecting the column is the portable way to know whether it exists.
+                let probe = format!("SELECT {} FROM {} LIMIT 1", column, table);
+                if sqlx::query(&probe).fetch_optional(&self.pool).await.is_ok() {
+                    continue;
+                }
+                let statement = format!(
+                    "ALTER TABLE {} ADD COLUMN {} BIGINT NOT NULL DEFAULT 0",
+                    table, column
+                );
+                trace!("{}", statement);
+                sqlx::query(&statement).execute(&self.pool).await?;
+                let statement = self.sql(&format!("UPDATE {} SET {} = ?", table, column));
+                sqlx::query(&statement)
+                    .bind(db::now())
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the `version` column to the tables created before it existed.
+    async fn ensure_version_columns_exist(&self) -> Result<(), sqlx::Error> {
+        for table in ["nodes", "notes"] {
+            let probe = format!("SELECT version FROM {} LIMIT 1", table);
+            if sqlx::query(&probe).fetch_optional(&self.pool).await.is_ok() {
+                continue;
+            }
+            let statement = format!(
+                "ALTER TABLE {} ADD COLUMN version BIGINT NOT NULL DEFAULT 1",
+                table
+            );
+            trace!("{}", statement);
+            sqlx::query(&statement).execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Add the `status` and `publish_at` columns to the tables created before they
+    /// existed, keeping the rows already there published.
+    async fn ensure_publication_columns_exist(&self) -> Result<(), sqlx::Error> {
+        let columns = [
+            ("status", "VARCHAR(16) NOT NULL DEFAULT 'published'"),
+            ("publish_at", "BIGINT"),
+        ];
+        for table in ["nodes", "notes"] {
+            for (column, column_type) in columns {
+                let probe = format!("SELECT {} FROM {} LIMIT 1", column, table);
+                if sqlx::query(&probe).fetch_optional(&self.pool).await.is_ok() {
+                    continue;
+                }
+                let statement = format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, column_type
+                );
+                trace!("{}", statement);
+                sqlx::query(&statement).execute(&self.pool).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the `slug` and `previous_slugs` columns to the tables created before they
+    /// existed, then give the rows without a slug one derived from their title.
+    async fn ensure_slug_columns_exist(&self) -> Result<(), sqlx::Error> {
+        let columns = [
+            ("slug", "VARCHAR(255) NOT NULL DEFAULT ''"),
+            ("previous_slugs", "TEXT"),
+        ];
+        for table in ["nodes", "notes"] {
+            for (column, column_type) in columns {
+                let probe = format!("SELECT {} FROM {} LIMIT 1", column, table);
+                if sqlx::query(&probe).fetch_optional(&self.pool).await.is_ok() {
+                    continue;
+                }
+                let statement = format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, column_type
+                );
+                trace!("{}", statement);
+                sqlx::query(&statement).execute(&self.pool).await?;
+            }
+        }
+
+        let queries = [
+            (
+                "nodes",
+                "SELECT id, parent_id, title, slug FROM nodes ORDER BY parent_key, position, id",
+            ),
+            (
+                "notes",
+                "SELECT id, node_id, name, slug FROM notes ORDER BY node_id, position, id",
+            ),
+        ];
+        for (table, query_str) in queries {
+            let rows: Vec<(i64, Option<i64>, String, String)> =
+                sqlx::query_as(query_str).fetch_all(&self.pool).await?;
+            let rows: Vec<SlugRow> = rows
+                .into_iter()
+                .map(|(id, parent_id, title, slug)| SlugRow {
+                    id: id as u32,
+                    parent_id: parent_id.map(|id| id as u32),
+                    title,
+                    slug,
+                })
+                .collect();
+            let statement = self.sql(&format!("UPDATE {} SET slug = ? WHERE id = ?", table));
+            for (id, slug) in missing_slugs(&rows) {
+                sqlx::query(&statement)
+                    .bind(slug)
+                    .bind(id as i64)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop every table, children first. Only meant for the tests.
+    pub async fn drop_all_tables(&self) -> Result<(), sqlx::Error> {
+        let tables = [
+            "sessions",
+            "users",
+            "note_tags",
+            "tags",
+            "note_attachments",
+            "files",
+            "notes",
+            "nodes",
+        ];
+        for table in tables {
+            sqlx::query(&format!("DROP TABLE IF EXISTS {}", table))
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn max_position(&self, query_str: &str, params: Vec<Value>) -> Option<u32> {
+        let query_str = self.sql(query_str);
+        let query = sqlx::query_scalar::<_, Option<i64>>(&query_str).bind_values(params);
+        match query.fetch_one(&self.pool).await {
+            Ok(max) => max.map(|max| max as u32),
+            Err(err) => {
+                warn!("{:?}", err);
+                None
+            }
+        }
+    }
+
+    async fn max_position_in_parent(&self, parent_id: Option<u32>) -> Option<u32> {
+        match parent_id {
+            Some(parent_id) => {
+                self.max_position(
+                    "SELECT MAX(position) FROM nodes WHERE parent_id = ?",
+                    vec![Value::from(parent_id)],
+                )
+                .await
+            }
+            None => {
+                self.max_position(
+                    "SELECT MAX(position) FROM nodes WHERE parent_id IS NULL",
+                    vec![],
+                )
+                .await
+            }
+        }
+    }
+
+    /// Count the rows of `from` matching the clauses, ignoring their ordering and paging.
+    async fn count(&self, from: &str, clauses: Clauses) -> Result<u64, Error> {
+        let (clauses, params) = clauses.without_paging().to_sql();
+        let query_str = self.sql(&format!("SELECT COUNT(*) FROM {}{}", from, clauses));
+        trace!("{}", query_str);
+        let query = sqlx::query_scalar::<_, i64>(&query_str).bind_values(params);
+        match query.fetch_one(&self.pool).await {
+            Ok(count) => Ok(count as u64),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    async fn get_ancestor_ids(&self, id: u32) -> Result<Vec<u32>, db::nodes::GetNodeError> {
+        let mut ancestors = Vec::new();
+        let mut current = self
+            .get_node(GetNodesForm {
+                id: Some(id),
+                ..Default::default()
+            })
+            .await?;
+        while let Some(parent_id) = current.parent_id {
+            if ancestors.contains(&parent_id) {
+                break;
+            }
+            ancestors.push(parent_id);
+            current = self
+                .get_node(GetNodesForm {
+                    id: Some(parent_id),
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Ok(ancestors)
+    }
+
+    /// Exchange two positions of `table` through `temp_pos` in one transaction, so
+    /// that a unique position constraint never sees a duplicate. Both rows get a new
+    /// `updated_at` and their `version` moves once. With `versions`, the rows are
+    /// only written at them, and false is returned when one moved past it.
+    async fn swap_positions(
+        &self,
+        table: &str,
+        temp_pos: u32,
+        first: (u32, u32),
+        second: (u32, u32),
+        versions: Option<[u32; 2]>,
+    ) -> Result<bool, Error> {
+        let park_query = self.sql(&format!("UPDATE {} SET position = ? WHERE id = ?", table));
+        let update_query = self.sql(&format!(
+            "UPDATE {} SET position = ?, updated_at = ?, version = version + 1 WHERE id = ?{}",
+            table,
+            if versions.is_some() {
+                " AND version = ?"
+            } else {
+                ""
+            }
+        ));
+        let now = db::now();
+        let res: Result<bool, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(&park_query)
+                .bind(i64::from(temp_pos))
+                .bind(i64::from(first.0))
+                .execute(&mut *tx)
+                .await?;
+            for (position, id, version) in [
+                (first.1, second.0, versions.map(|versions| versions[1])),
+                (second.1, first.0, versions.map(|versions| versions[0])),
+            ] {
+                let mut query = sqlx::query(&update_query)
+                    .bind(i64::from(position))
+                    .bind(now)
+                    .bind(i64::from(id));
+                if let Some(version) = version {
+                    query = query.bind(i64::from(version));
+                }
+                if query.execute(&mut *tx).await?.rows_affected() == 0 {
+                    return Ok(false);
+                }
+            }
+            tx.commit().await?;
+            Ok(true)
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            err.into()
+        })
+    }
+
+    async fn delete_by_ids(&self, table: &str, ids: Vec<u32>) -> Result<(), Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut params: Vec<Value> = Vec::new();
+        let filter = Filter::is_in("id", ids);
+        let query_str = self.sql(&format!(
+            "DELETE FROM {} WHERE {}",
+            table,
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let res = sqlx::query(&query_str)
+            .bind_values(params)
+            .execute(&self.pool)
+            .await;
+        res.map(|_| ()).map_err(|err| {
+            warn!("{:?}", err);
+            err.into()
+        })
+    }
+
+    /// Move the `updated_at` of the notes matching `filter`, as every change of
+    /// their tags or attachments does.
+    async fn touch_notes(
+        &self,
+        connection: &mut sqlx::AnyConnection,
+        filter: Filter,
+    ) -> Result<(), sqlx::Error> {
+        let mut params: Vec<Value> = vec![db::now().into()];
+        let query_str = self.sql(&format!(
+            "UPDATE notes SET updated_at = ? WHERE {}",
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        sqlx::query(&query_str)
+            .bind_values(params)
+            .execute(connection)
+            .await
+            .map(|_| ())
+    }
+
+    /// Check that the note `note_id` exists, for the changes of its tags and
+    /// attachments.
+    async fn note_exists(&self, note_id: u32) -> Result<bool, Error> {
+        let res = self
+            .get_note(db::lecture_notes::GetNotesForm {
+                id: Some(note_id),
+                ..Default::default()
+            })
+            .await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(db::lecture_notes::GetNoteError::NotFoundError) => Ok(false),
+            Err(db::lecture_notes::GetNoteError::UnexpectedError(err)) => Err(err),
+        }
+    }
+
+    /// Check that `section_id` points to a root node, so that its children are subsections.
+    async fn is_section(&self, section_id: u32) -> Result<bool, Error> {
+        let res = self
+            .get_node(GetNodesForm {
+                id: Some(section_id),
+                is_root: Some(true),
+                ..Default::default()
+            })
+            .await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(db::nodes::GetNodeError::NotFoundError) => Ok(false),
+            Err(db::nodes::GetNodeError::UnexpectedError(err)) => Err(err),
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqlRepository {
+    async fn create_user(&self, form: db::users::CreateUserForm) -> Result<(), Error> {
+        let query_str =
+            self.sql("INSERT INTO users (username, password, is_admin) VALUES (?, ?, ?)");
+        let res = sqlx::query(&query_str)
+            .bind(form.username)
+            .bind(form.password)
+            .bind(i64::from(form.is_admin))
+            .execute(&self.pool)
+            .await;
+        res.map(|_| ()).map_err(|err| {
+            warn!("{:?}", err);
+            err.into()
+        })
+    }
+
+    async fn get_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<Vec<db::users::UserFromDb>, db::users::GetUsersError> {
+        let (clauses, params) = form.to_clauses().to_sql();
+        let query_str = self.sql(&format!(
+            "SELECT id, username, password, is_admin FROM users{}",
+            clauses
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, UserRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows.into_iter().map(db::users::UserFromDb::from).collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::users::GetUsersError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn count_users(
+        &self,
+        form: db::users::GetUsersForm,
+    ) -> Result<u64, db::users::GetUsersError> {
+        self.count("users", form.to_clauses())
+            .await
+            .map_err(db::users::GetUsersError::UnexpectedError)
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SqlRepository {
+    async fn create_session(&self, form: db::sessions::CreateSessionForm) -> Result<(), Error> {
+        let query_str =
+            self.sql("INSERT INTO sessions (user_id, token, expires_at) VALUES (?, ?, ?)");
+        let res = sqlx::query(&query_str)
+            .bind(i64::from(form.user_id))
+            .bind(form.token)
+            .bind(form.expires_at)
+            .execute(&self.pool)
+            .await;
+        res.map(|_| ()).map_err(|err| {
+            warn!("{:?}", err);
+            err.into()
+        })
+    }
+
+    async fn get_session_by_token(
+        &self,
+        token: String,
+    ) -> Result<db::sessions::SessionFromDb, Error> {
+        let query_str =
+            self.sql("SELECT id, user_id, token, expires_at FROM sessions WHERE token = ? LIMIT 1");
+        trace!("{}", query_str);
+        let res = sqlx::query_as::<_, SessionRow>(&query_str)
+            .bind(token)
+            .fetch_one(&self.pool)
+            .await;
+        res.map(db::sessions::SessionFromDb::from)
+            .map_err(Error::from)
+    }
+
+    async fn delete_session_by_token(&self, token: String) -> Result<(), Error> {
+        let query_str = self.sql("DELETE FROM sessions WHERE token = ?");
+        let res = sqlx::query(&query_str)
+            .bind(token)
+            .execute(&self.pool)
+            .await;
+        res.map(|_| ()).map_err(Error::from)
+    }
+
+    async fn delete_sessions_by_user(&self, user_id: u32) -> Result<(), Error> {
+        let query_str = self.sql("DELETE FROM sessions WHERE user_id = ?");
+        let res = sqlx::query(&query_str)
+            .bind(i64::from(user_id))
+            .execute(&self.pool)
+            .await;
+        res.map(|_| ()).map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl SectionRepository for SqlRepository {
+    async fn get_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<Vec<db::sections::SectionFromDb>, db::sections::GetSectionsError> {
+        self.get_nodes(form.to_nodes_form())
+            .await
+            .map(|list| {
+                list.into_iter()
+                    .map(db::sections::SectionFromDb::from)
+                    .collect()
+            })
+            .map_err(|db::nodes::GetNodesError::UnexpectedError(err)| {
+                db::sections::GetSectionsError::UnexpectedError(err)
+            })
+    }
+
+    async fn count_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<u64, db::sections::GetSectionsError> {
+        self.count("nodes", form.to_nodes_form().to_clauses())
+            .await
+            .map_err(db::sections::GetSectionsError::UnexpectedError)
+    }
+
+    async fn create_section(&self, form: db::sections::CreateSectionForm) -> Result<(), Error> {
+        self.create_node(db::nodes::CreateNodeForm {
+            parent_id: None,
+            kind: db::sections::SECTION_KIND.to_string(),
+            title: form.title,
+            status: form.status,
+            publish_at: form.publish_at,
+            slug: form.slug,
+        })
+        .await
+        .map(|_| ())
+        .map_err(|err| match err {
+            db::nodes::CreateNodeError::UnexpectedError(err) => err,
+            db::nodes::CreateNodeError::ParentNotFoundError => {
+                Error::internal("a section has no parent")
+            }
+        })
+    }
+
+    async fn update_sections(
+        &self,
+        form: db::sections::UpdateSectionForm,
+        identified_by: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::UpdateSectionsError> {
+        let res = self
+            .update_nodes(
+                db::nodes::UpdateNodeForm {
+                    title: form.title,
+                    kind: None,
+                    parent_id: None,
+                    position: None,
+                    status: form.status,
+                    publish_at: form.publish_at,
+                    slug: form.slug,
+                    previous_slugs: form.previous_slugs,
+                    version: form.version,
+                },
+                identified_by.to_nodes_form(),
+            )
+            .await;
+        res.map_err(|err| match err {
+            db::nodes::UpdateNodesError::NotFoundError => {
+                db::sections::UpdateSectionsError::NotFoundError
+            }
+            db::nodes::UpdateNodesError::NothingToUpdateError => {
+                db::sections::UpdateSectionsError::NothingToUpdateError
+            }
+            db::nodes::UpdateNodesError::VersionConflictError => {
+                db::sections::UpdateSectionsError::VersionConflictError
+            }
+            db::nodes::UpdateNodesError::UnexpectedError(err) => {
+                db::sections::UpdateSectionsError::UnexpectedError(err)
+            }
+            db::nodes::UpdateNodesError::CycleError => {
+                db::sections::UpdateSectionsError::UnexpectedError(Error::internal(
+                    "a section cannot be moved under itself",
+                ))
+            }
+        })
+    }
+
+    async fn swap_sections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::sections::SwapSectionsError> {
+        let section_1 = self
+            .get_section(db::sections::GetSectionsForm {
+                id: Some(ids[0]),
+                ..Default::default()
+            })
+            .await;
+        let section_2 = self
+            .get_section(db::sections::GetSectionsForm {
+                id: Some(ids[1]),
+                ..Default::default()
+            })
+            .await;
+        db::found_pair(ids, [section_1, section_2])?;
+        self.swap_nodes(ids, versions)
+            .await
+            .map_err(db::sections::SwapSectionsError::from)
+    }
+
+    async fn delete_sections(
+        &self,
+        form: db::sections::GetSectionsForm,
+    ) -> Result<(), db::sections::DeleteSectionsError> {
+        self.delete_nodes(form.to_nodes_form()).await.map_err(
+            |db::nodes::DeleteNodesError::UnexpectedError(err)| {
+                db::sections::DeleteSectionsError::UnexpectedError(err)
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl SubsectionRepository for SqlRepository {
+    async fn get_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<Vec<db::subsections::SubsectionFromDb>, db::subsections::GetSubsectionsError> {
+        self.get_nodes(form.to_nodes_form())
+            .await
+            .map(|list| {
+                list.into_iter()
+                    .map(db::subsections::SubsectionFromDb::from)
+                    .collect()
+            })
+            .map_err(|db::nodes::GetNodesError::UnexpectedError(err)| {
+                db::subsections::GetSubsectionsError::UnexpectedError(err)
+            })
+    }
+
+    async fn count_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<u64, db::subsections::GetSubsectionsError> {
+        self.count("nodes", form.to_nodes_form().to_clauses())
+            .await
+            .map_err(db::subsections::GetSubsectionsError::UnexpectedError)
+    }
+
+    async fn create_subsection(
+        &self,
+        form: db::subsections::CreateSubsectionForm,
+    ) -> Result<(), Error> {
+        if !self.is_section(form.section_id).await? {
+            return Err(Error::not_found("no such section"));
+        }
+        self.create_node(db::nodes::CreateNodeForm {
+            parent_id: Some(form.section_id),
+            kind: db::subsections::SUBSECTION_KIND.to_string(),
+            title: form.title,
+            status: form.status,
+            publish_at: form.publish_at,
+            slug: form.slug,
+        })
+        .await
+        .map(|_| ())
+        .map_err(|err| match err {
+            db::nodes::CreateNodeError::UnexpectedError(err) => err,
+            db::nodes::CreateNodeError::ParentNotFoundError => Error::not_found("no such section"),
+        })
+    }
+
+    async fn update_subsections(
+        &self,
+        form: db::subsections::UpdateSubsectionForm,
+        identified_by: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::UpdateSubsectionsError> {
+        if form.is_all_none() {
+            return Err(db::subsections::UpdateSubsectionsError::NothingToUpdateError);
+        }
+        if let Some(section_id) = form.section_id {
+            match self.is_section(section_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(db::subsections::UpdateSubsectionsError::UnexpectedError(
+                        Error::not_found("no such section"),
+                    ))
+                }
+                Err(err) => {
+                    return Err(db::subsections::UpdateSubsectionsError::UnexpectedError(
+                        err,
+                    ))
+                }
+            }
+        }
+        let res = self
+            .update_nodes(
+                db::nodes::UpdateNodeForm {
+                    title: form.title,
+                    kind: None,
+                    parent_id: form.section_id,
+                    position: form.position,
+                    status: form.status,
+                    publish_at: form.publish_at,
+                    slug: form.slug,
+                    previous_slugs: form.previous_slugs,
+                    version: form.version,
+                },
+                identified_by.to_nodes_form(),
+            )
+            .await;
+        res.map_err(|err| match err {
+            db::nodes::UpdateNodesError::NotFoundError => {
+                db::subsections::UpdateSubsectionsError::NotFoundError
+            }
+            db::nodes::UpdateNodesError::NothingToUpdateError => {
+                db::subsections::UpdateSubsectionsError::NothingToUpdateError
+            }
+            db::nodes::UpdateNodesError::VersionConflictError => {
+                db::subsections::UpdateSubsectionsError::VersionConflictError
+            }
+            db::nodes::UpdateNodesError::UnexpectedError(err) => {
+                db::subsections::UpdateSubsectionsError::UnexpectedError(err)
+            }
+            db::nodes::UpdateNodesError::CycleError => {
+                db::subsections::UpdateSubsectionsError::UnexpectedError(Error::internal(
+                    "a section cannot hold itself",
+                ))
+            }
+        })
+    }
+
+    async fn swap_subsections(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::subsections::SwapSubsectionsError> {
+        let subsection_1 = self
+            .get_subsection(db::subsections::GetSubsectionsForm {
+                id: Some(ids[0]),
+                ..Default::default()
+            })
+            .await;
+        let subsection_2 = self
+            .get_subsection(db::subsections::GetSubsectionsForm {
+                id: Some(ids[1]),
+                ..Default::default()
+            })
+            .await;
+        db::found_pair(ids, [subsection_1, subsection_2])?;
+        self.swap_nodes(ids, versions)
+            .await
+            .map_err(db::subsections::SwapSubsectionsError::from)
+    }
+
+    async fn delete_subsections(
+        &self,
+        form: db::subsections::GetSubsectionsForm,
+    ) -> Result<(), db::subsections::DeleteSubsectionsError> {
+        self.delete_nodes(form.to_nodes_form()).await.map_err(
+            |db::nodes::DeleteNodesError::UnexpectedError(err)| {
+                db::subsections::DeleteSubsectionsError::UnexpectedError(err)
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl NoteRepository for SqlRepository {
+    async fn get_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<Vec<db::lecture_notes::NoteFromDb>, db::lecture_notes::GetNotesError> {
+        let (clauses, params) = notes_clauses(&form).to_sql();
+        let query_str = self.sql(&format!(
+            "SELECT * FROM ({}) AS notes_view{}",
+            db::lecture_notes::NOTES_VIEW,
+            clauses
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, NoteRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows
+                .into_iter()
+                .map(db::lecture_notes::NoteFromDb::from)
+                .collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::lecture_notes::GetNotesError::UnexpectedError(
+                    err.into(),
+                ))
+            }
+        }
+    }
+
+    async fn count_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<u64, db::lecture_notes::GetNotesError> {
+        let from = format!("({}) AS notes_view", db::lecture_notes::NOTES_VIEW);
+        self.count(&from, notes_clauses(&form))
+            .await
+            .map_err(db::lecture_notes::GetNotesError::UnexpectedError)
+    }
+
+    async fn create_note(&self, form: db::lecture_notes::CreateNoteForm) -> Result<(), Error> {
+        let max = match form.node_id {
+            Some(node_id) => {
+                self.max_position(
+                    "SELECT MAX(position) FROM notes WHERE node_id = ?",
+                    vec![Value::from(node_id)],
+                )
+                .await
+            }
+            None => {
+                self.max_position("SELECT MAX(position) FROM notes", vec![])
+                    .await
+            }
+        };
+        let next_pos = max.map(|num| num + 1).unwrap_or(0);
+
+        let metadata = form.metadata;
+        let now = db::now();
+        let query_str = self.sql(
+            "INSERT INTO notes (name, description, url, position, node_id, \
+             kind, course_code, term, language, authors, license, created_at, updated_at, \
+             status, publish_at, slug) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        );
+        let slug = form.slug.unwrap_or_else(|| db::slugs::slugify(&form.name));
+        let res = sqlx::query(&query_str)
+            .bind(form.name)
+            .bind(form.description)
+            .bind(form.url)
+            .bind(i64::from(next_pos))
+            .bind(form.node_id.map(i64::from))
+            .bind(metadata.kind)
+            .bind(metadata.course_code)
+            .bind(metadata.term)
+            .bind(metadata.language)
+            .bind(db::lecture_notes::authors_to_json(&metadata.authors))
+            .bind(metadata.license)
+            .bind(now)
+            .bind(now)
+            .bind(
+                form.status
+                    .unwrap_or_else(|| db::nodes::PUBLISHED.to_string()),
+            )
+            .bind(form.publish_at)
+            .bind(slug)
+            .execute(&self.pool)
+            .await;
+        trace!("{:?}", res);
+        res.map(|_| ()).map_err(|err| {
+            warn!("{:?}", err);
+            err.into()
+        })
+    }
+
+    async fn update_notes(
+        &self,
+        form: db::lecture_notes::UpdateNoteForm,
+        identified_by: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::UpdateNotesError> {
+        if form.is_all_none() {
+            return Err(db::lecture_notes::UpdateNotesError::NothingToUpdateError);
+        }
+        let notes = self.get_notes(identified_by).await.map_err(
+            |db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+                db::lecture_notes::UpdateNotesError::UnexpectedError(err)
+            },
+        )?;
+        if notes.is_empty() {
+            return Err(db::lecture_notes::UpdateNotesError::NotFoundError);
+        }
+        if let Some(version) = form.version {
+            if notes.iter().any(|note| note.version != version) {
+                return Err(db::lecture_notes::UpdateNotesError::VersionConflictError);
+            }
+        }
+
+        let mut assignments = Assignments::default();
+        assignments.set_some("name", form.name);
+        assignments.set_some("description", form.description);
+        assignments.set_some("url", form.url);
+        assignments.set_some("node_id", form.node_id);
+        assignments.set_some("position", form.position);
+        if let Some(kind) = form.kind {
+            assignments.set_or_null("kind", kind);
+        }
+        if let Some(course_code) = form.course_code {
+            assignments.set_or_null("course_code", course_code);
+        }
+        if let Some(term) = form.term {
+            assignments.set_or_null("term", term);
+        }
+        if let Some(language) = form.language {
+            assignments.set_or_null("language", language);
+        }
+        assignments.set_some(
+            "authors",
+            form.authors
+                .as_deref()
+                .map(db::lecture_notes::authors_to_json),
+        );
+        if let Some(license) = form.license {
+            assignments.set_or_null("license", license);
+        }
+        assignments.set_some("status", form.status);
+        if let Some(publish_at) = form.publish_at {
+            assignments.set_or_null("publish_at", publish_at);
+        }
+        assignments.set_some("slug", form.slug);
+        assignments.set_some(
+            "previous_slugs",
+            form.previous_slugs
+                .as_deref()
+                .map(db::slugs::previous_slugs_to_json),
+        );
+        assignments.set("updated_at", db::now());
+        assignments.increment("version");
+
+        let mut params: Vec<Value> = Vec::new();
+        let set = assignments.to_sql(&mut params);
+        let mut filter = Filter::is_in("id", notes.iter().map(|note| note.id));
+        if let Some(version) = form.version {
+            filter = filter.and(Filter::eq("version", version));
+        }
+        let query_str = self.sql(&format!(
+            "UPDATE notes SET {} WHERE {}",
+            set,
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let res = sqlx::query(&query_str)
+            .bind_values(params)
+            .execute(&self.pool)
+            .await;
+        match res {
+            Ok(done) if form.version.is_some() && (done.rows_affected() as usize) < notes.len() => {
+                Err(db::lecture_notes::UpdateNotesError::VersionConflictError)
+            }
+            Ok(_) => Ok(()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::lecture_notes::UpdateNotesError::UnexpectedError(
+                    err.into(),
+                ))
+            }
+        }
+    }
+
+    async fn swap_notes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::lecture_notes::SwapNotesError> {
+        let note_1 = self
+            .get_note(db::lecture_notes::GetNotesForm {
+                id: Some(ids[0]),
+                ..Default::default()
+            })
+            .await;
+        let note_2 = self
+            .get_note(db::lecture_notes::GetNotesForm {
+                id: Some(ids[1]),
+                ..Default::default()
+            })
+            .await;
+        let (note_1, note_2) = db::lecture_notes::check_swap(ids, versions, [note_1, note_2])?;
+        let max_pos = self
+            .max_position("SELECT MAX(position) FROM notes", vec![])
+            .await
+            .unwrap_or(0)
+            + 1;
+        let swapped = self
+            .swap_positions(
+                "notes",
+                max_pos,
+                (note_1.id, note_1.position),
+                (note_2.id, note_2.position),
+                Some(versions),
+            )
+            .await
+            .map_err(db::lecture_notes::SwapNotesError::UnexpectedError)?;
+        if !swapped {
+            return Err(db::lecture_notes::SwapNotesError::VersionConflictError);
+        }
+        Ok(())
+    }
+
+    async fn delete_notes(
+        &self,
+        form: db::lecture_notes::GetNotesForm,
+    ) -> Result<(), db::lecture_notes::DeleteNotesError> {
+        let notes = self.get_notes(form).await.map_err(
+            |db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+                db::lecture_notes::DeleteNotesError::UnexpectedError(err)
+            },
+        )?;
+        self.delete_by_ids("notes", notes.iter().map(|note| note.id).collect())
+            .await
+            .map_err(db::lecture_notes::DeleteNotesError::UnexpectedError)
+    }
+}
+
+#[async_trait]
+impl NodeRepository for SqlRepository {
+    async fn get_nodes(
+        &self,
+        form: GetNodesForm,
+    ) -> Result<Vec<NodeFromDb>, db::nodes::GetNodesError> {
+        let (clauses, params) = form.to_clauses().to_sql();
+        let query_str = self.sql(&format!(
+            "SELECT {} FROM nodes{}",
+            db::nodes::NODE_COLUMNS,
+            clauses
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, NodeRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows.into_iter().map(NodeFromDb::from).collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::nodes::GetNodesError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn create_node(
+        &self,
+        form: db::nodes::CreateNodeForm,
+    ) -> Result<u32, db::nodes::CreateNodeError> {
+        if let Some(parent_id) = form.parent_id {
+            match self
+                .get_node(GetNodesForm {
+                    id: Some(parent_id),
+                    ..Default::default()
+                })
+                .await
+            {
+                Ok(_) => {}
+                Err(db::nodes::GetNodeError::NotFoundError) => {
+                    return Err(db::nodes::CreateNodeError::ParentNotFoundError)
+                }
+                Err(db::nodes::GetNodeError::UnexpectedError(err)) => {
+                    return Err(db::nodes::CreateNodeError::UnexpectedError(err))
+                }
+            }
+        }
+        let next_pos = match self.max_position_in_parent(form.parent_id).await {
+            Some(num) => num + 1,
+            None => 0,
+        };
+        let now = db::now();
+        let query_str = self.sql(
+            "INSERT INTO nodes (parent_id, kind, title, position, created_at, updated_at, \
+             status, publish_at, slug) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        );
+        let slug = form.slug.unwrap_or_else(|| db::slugs::slugify(&form.title));
+        let res = sqlx::query_scalar::<_, i64>(&query_str)
+            .bind(form.parent_id.map(i64::from))
+            .bind(form.kind)
+            .bind(form.title)
+            .bind(i64::from(next_pos))
+            .bind(now)
+            .bind(now)
+            .bind(
+                form.status
+                    .unwrap_or_else(|| db::nodes::PUBLISHED.to_string()),
+            )
+            .bind(form.publish_at)
+            .bind(slug)
+            .fetch_one(&self.pool)
+            .await;
+        trace!("{:?}", res);
+        res.map(|id| id as u32).map_err(|err| {
+            warn!("{:?}", err);
+            db::nodes::CreateNodeError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn update_nodes(
+        &self,
+        form: db::nodes::UpdateNodeForm,
+        identified_by: GetNodesForm,
+    ) -> Result<(), db::nodes::UpdateNodesError> {
+        if form.is_all_none() {
+            return Err(db::nodes::UpdateNodesError::NothingToUpdateError);
+        }
+        let nodes = self.get_nodes(identified_by).await.map_err(
+            |db::nodes::GetNodesError::UnexpectedError(err)| {
+                db::nodes::UpdateNodesError::UnexpectedError(err)
+            },
+        )?;
+        if nodes.is_empty() {
+            return Err(db::nodes::UpdateNodesError::NotFoundError);
+        }
+        if let Some(version) = form.version {
+            if nodes.iter().any(|node| node.version != version) {
+                return Err(db::nodes::UpdateNodesError::VersionConflictError);
+            }
+        }
+        if let Some(parent_id) = form.parent_id {
+            let ancestors = match self.get_ancestor_ids(parent_id).await {
+                Ok(list) => list,
+                Err(db::nodes::GetNodeError::NotFoundError) => {
+                    return Err(db::nodes::UpdateNodesError::NotFoundError)
+                }
+                Err(db::nodes::GetNodeError::UnexpectedError(err)) => {
+                    return Err(db::nodes::UpdateNodesError::UnexpectedError(err))
+                }
+            };
+            if nodes
+                .iter()
+                .any(|node| node.id == parent_id || ancestors.contains(&node.id))
+            {
+                return Err(db::nodes::UpdateNodesError::CycleError);
+            }
+        }
+
+        let mut assignments = Assignments::default();
+        assignments.set_some("title", form.title);
+        assignments.set_some("kind", form.kind);
+        assignments.set_some("parent_id", form.parent_id);
+        assignments.set_some("position", form.position);
+        assignments.set_some("status", form.status);
+        if let Some(publish_at) = form.publish_at {
+            assignments.set_or_null("publish_at", publish_at);
+        }
+        assignments.set_some("slug", form.slug);
+        assignments.set_some(
+            "previous_slugs",
+            form.previous_slugs
+                .as_deref()
+                .map(db::slugs::previous_slugs_to_json),
+        );
+        assignments.set("updated_at", db::now());
+        assignments.increment("version");
+
+        let mut params: Vec<Value> = Vec::new();
+        let set = assignments.to_sql(&mut params);
+        let mut filter = Filter::is_in("id", nodes.iter().map(|node| node.id));
+        if let Some(version) = form.version {
+            filter = filter.and(Filter::eq("version", version));
+        }
+        let query_str = self.sql(&format!(
+            "UPDATE nodes SET {} WHERE {}",
+            set,
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let res = sqlx::query(&query_str)
+            .bind_values(params)
+            .execute(&self.pool)
+            .await;
+        match res {
+            Ok(done) if form.version.is_some() && (done.rows_affected() as usize) < nodes.len() => {
+                Err(db::nodes::UpdateNodesError::VersionConflictError)
+            }
+            Ok(_) => Ok(()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::nodes::UpdateNodesError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn swap_nodes(
+        &self,
+        ids: [u32; 2],
+        versions: [u32; 2],
+    ) -> Result<(), db::nodes::SwapNodesError> {
+        let node_1 = self
+            .get_node(GetNodesForm {
+                id: Some(ids[0]),
+                ..Default::default()
+            })
+            .await;
+        let node_2 = self
+            .get_node(GetNodesForm {
+                id: Some(ids[1]),
+                ..Default::default()
+            })
+            .await;
+        let (node_1, node_2) = db::nodes::check_swap(ids, versions, [node_1, node_2])?;
+        let max_pos = self
+            .max_position_in_parent(node_1.parent_id)
+            .await
+            .unwrap_or_default()
+            + 1;
+        let swapped = self
+            .swap_positions(
+                "nodes",
+                max_pos,
+                (node_1.id, node_1.position),
+                (node_2.id, node_2.position),
+                Some(versions),
+            )
+            .await
+            .map_err(db::nodes::SwapNodesError::UnexpectedError)?;
+        if !swapped {
+            return Err(db::nodes::SwapNodesError::VersionConflictError);
+        }
+        Ok(())
+    }
+
+    async fn delete_nodes(&self, form: GetNodesForm) -> Result<(), db::nodes::DeleteNodesError> {
+        let nodes = self.get_nodes(form).await.map_err(
+            |db::nodes::GetNodesError::UnexpectedError(err)| {
+                db::nodes::DeleteNodesError::UnexpectedError(err)
+            },
+        )?;
+        self.delete_by_ids("nodes", nodes.iter().map(|node| node.id).collect())
+            .await
+            .map_err(db::nodes::DeleteNodesError::UnexpectedError)
+    }
+}
+
+#[async_trait]
+impl TagRepository for SqlRepository {
+    async fn get_tags(
+        &self,
+        form: db::tags::GetTagsForm,
+    ) -> Result<Vec<db::tags::TagFromDb>, db::tags::GetTagsError> {
+        let (clauses, params) = form.to_clauses().to_sql();
+        let query_str = self.sql(&format!("SELECT id, name FROM tags{}", clauses));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, TagRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows.into_iter().map(db::tags::TagFromDb::from).collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::tags::GetTagsError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn get_tags_with_counts(
+        &self,
+    ) -> Result<Vec<db::tags::TagWithCountFromDb>, db::tags::GetTagsError> {
+        let query_str = "\
+            SELECT tags.id, tags.name, COUNT(note_tags.note_id) AS note_count \
+            FROM tags LEFT JOIN note_tags ON note_tags.tag_id = tags.id \
+            GROUP BY tags.id, tags.name ORDER BY tags.name";
+        trace!("{}", query_str);
+        match sqlx::query_as::<_, TagWithCountRow>(query_str)
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(rows) => Ok(rows
+                .into_iter()
+                .map(db::tags::TagWithCountFromDb::from)
+                .collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::tags::GetTagsError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn get_tags_of_notes(
+        &self,
+        note_ids: &[u32],
+    ) -> Result<Vec<db::tags::NoteTagFromDb>, db::tags::GetTagsError> {
+        if note_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut params: Vec<Value> = Vec::new();
+        let filter = Filter::is_in("note_tags.note_id", note_ids.iter().copied());
+        let query_str = self.sql(&format!(
+            "SELECT note_tags.note_id, note_tags.tag_id, tags.name FROM note_tags \
+             JOIN tags ON tags.id = note_tags.tag_id WHERE {} ORDER BY tags.name",
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, NoteTagRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows
+                .into_iter()
+                .map(db::tags::NoteTagFromDb::from)
+                .collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::tags::GetTagsError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn create_tag(&self, name: String) -> Result<u32, db::tags::CreateTagError> {
+        let now = db::now();
+        let query_str = self
+            .sql("INSERT INTO tags (name, created_at, updated_at) VALUES (?, ?, ?) RETURNING id");
+        let res = sqlx::query_scalar::<_, i64>(&query_str)
+            .bind(name)
+            .bind(now)
+            .bind(now)
+            .fetch_one(&self.pool)
+            .await;
+        trace!("{:?}", res);
+        match res {
+            Ok(id) => Ok(id as u32),
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                Err(db::tags::CreateTagError::AlreadyExistsError)
+            }
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::tags::CreateTagError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn get_or_create_tags(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<u32>, db::tags::CreateTagError> {
+        let insert_query = self.sql(
+            "INSERT INTO tags (name, created_at, updated_at) VALUES (?, ?, ?) \
+             ON CONFLICT (name) DO NOTHING",
+        );
+        let select_query = self.sql("SELECT id FROM tags WHERE name = ?");
+        let res: Result<Vec<u32>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let mut ids = Vec::new();
+            for name in names {
+                let now = db::now();
+                sqlx::query(&insert_query)
+                    .bind(name.as_str())
+                    .bind(now)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+                let id = sqlx::query_scalar::<_, i64>(&select_query)
+                    .bind(name.as_str())
+                    .fetch_one(&mut *tx)
+                    .await?;
+                ids.push(id as u32);
+            }
+            tx.commit().await?;
+            Ok(ids)
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::tags::CreateTagError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn rename_tag(&self, id: u32, name: String) -> Result<(), db::tags::RenameTagError> {
+        let tag = self
+            .get_tag(db::tags::GetTagsForm {
+                id: Some(id),
+                ..Default::default()
+            })
+            .await;
+        match tag {
+            Ok(_) => {}
+            Err(db::tags::GetTagError::NotFoundError) => {
+                return Err(db::tags::RenameTagError::NotFoundError)
+            }
+            Err(db::tags::GetTagError::UnexpectedError(err)) => {
+                return Err(db::tags::RenameTagError::UnexpectedError(err))
+            }
+        }
+
+        let query_str = self.sql("UPDATE tags SET name = ?, updated_at = ? WHERE id = ?");
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(&query_str)
+                .bind(name)
+                .bind(db::now())
+                .bind(i64::from(id))
+                .execute(&mut *tx)
+                .await?;
+            self.touch_notes(&mut tx, notes_tagged_with(id)).await?;
+            tx.commit().await
+        }
+        .await;
+        match res {
+            Ok(()) => Ok(()),
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                Err(db::tags::RenameTagError::AlreadyExistsError)
+            }
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::tags::RenameTagError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn merge_tags(
+        &self,
+        source_id: u32,
+        target_id: u32,
+    ) -> Result<(), db::tags::MergeTagsError> {
+        let source = self
+            .get_tag(db::tags::GetTagsForm {
+                id: Some(source_id),
+                ..Default::default()
+            })
+            .await;
+        let target = self
+            .get_tag(db::tags::GetTagsForm {
+                id: Some(target_id),
+                ..Default::default()
+            })
+            .await;
+        db::tags::check_merge(source_id, target_id, [source, target])?;
+
+        let notes_query = self.sql("SELECT note_id FROM note_tags WHERE tag_id = ?");
+        let insert_query = self.sql(
+            "INSERT INTO note_tags (note_id, tag_id) VALUES (?, ?) \
+             ON CONFLICT (note_id, tag_id) DO NOTHING",
+        );
+        let delete_query = self.sql("DELETE FROM tags WHERE id = ?");
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            self.touch_notes(&mut tx, notes_tagged_with(source_id))
+                .await?;
+            let note_ids = sqlx::query_scalar::<_, i64>(&notes_query)
+                .bind(i64::from(source_id))
+                .fetch_all(&mut *tx)
+                .await?;
+            for note_id in note_ids {
+                sqlx::query(&insert_query)
+                    .bind(note_id)
+                    .bind(i64::from(target_id))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(&delete_query)
+                .bind(i64::from(source_id))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::tags::MergeTagsError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn delete_tag(&self, id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        let query_str = self.sql("DELETE FROM tags WHERE id = ?");
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            self.touch_notes(&mut tx, notes_tagged_with(id)).await?;
+            sqlx::query(&query_str)
+                .bind(i64::from(id))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::tags::DeleteTagsError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn attach_tags(
+        &self,
+        note_id: u32,
+        tag_ids: &[u32],
+    ) -> Result<(), db::tags::AttachTagsError> {
+        match self.note_exists(note_id).await {
+            Ok(true) => {}
+            Ok(false) => return Err(db::tags::AttachTagsError::NoteNotFoundError),
+            Err(err) => return Err(db::tags::AttachTagsError::UnexpectedError(err)),
+        }
+        if tag_ids.is_empty() {
+            return Ok(());
+        }
+
+        let query_str = self.sql(
+            "INSERT INTO note_tags (note_id, tag_id) VALUES (?, ?) \
+             ON CONFLICT (note_id, tag_id) DO NOTHING",
+        );
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            for tag_id in tag_ids {
+                sqlx::query(&query_str)
+                    .bind(i64::from(note_id))
+                    .bind(i64::from(*tag_id))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            self.touch_notes(&mut tx, Filter::eq("id", note_id)).await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::tags::AttachTagsError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn detach_tag(&self, note_id: u32, tag_id: u32) -> Result<(), db::tags::DeleteTagsError> {
+        let query_str = self.sql("DELETE FROM note_tags WHERE note_id = ? AND tag_id = ?");
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(&query_str)
+                .bind(i64::from(note_id))
+                .bind(i64::from(tag_id))
+                .execute(&mut *tx)
+                .await?;
+            self.touch_notes(&mut tx, Filter::eq("id", note_id)).await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::tags::DeleteTagsError::UnexpectedError(err.into())
+        })
+    }
+}
+
+/// The notes carrying the tag `tag_id`.
+fn notes_tagged_with(tag_id: u32) -> Filter {
+    Filter::Sql(
+        "id IN (SELECT note_id FROM note_tags WHERE tag_id = ?)".to_string(),
+        vec![tag_id.into()],
+    )
+}
+
+/// The notes holding `attachments`.
+fn notes_of(attachments: &[db::attachments::AttachmentFromDb]) -> Filter {
+    Filter::is_in(
+        "id",
+        attachments.iter().map(|attachment| attachment.note_id),
+    )
+}
+
+#[async_trait]
+impl AttachmentRepository for SqlRepository {
+    async fn get_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<Vec<db::attachments::AttachmentFromDb>, db::attachments::GetAttachmentsError> {
+        let (clauses, params) = form.to_clauses().to_sql();
+        let query_str = self.sql(&format!(
+            "SELECT id, note_id, label, url, mime_type, size, file_id, position \
+             FROM note_attachments{}",
+            clauses
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, AttachmentRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows
+                .into_iter()
+                .map(db::attachments::AttachmentFromDb::from)
+                .collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::attachments::GetAttachmentsError::UnexpectedError(
+                    err.into(),
+                ))
+            }
+        }
+    }
+
+    async fn create_attachment(
+        &self,
+        form: db::attachments::CreateAttachmentForm,
+    ) -> Result<u32, db::attachments::CreateAttachmentError> {
+        match self.note_exists(form.note_id).await {
+            Ok(true) => {}
+            Ok(false) => return Err(db::attachments::CreateAttachmentError::NoteNotFoundError),
+            Err(err) => return Err(db::attachments::CreateAttachmentError::UnexpectedError(err)),
+        }
+        let next_pos = self
+            .max_position(
+                "SELECT MAX(position) FROM note_attachments WHERE note_id = ?",
+                vec![Value::from(form.note_id)],
+            )
+            .await
+            .map(|num| num + 1)
+            .unwrap_or(0);
+
+        let note_id = form.note_id;
+        let now = db::now();
+        let query_str = self.sql(
+            "INSERT INTO note_attachments \
+             (note_id, label, url, mime_type, size, file_id, position, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        );
+        let res: Result<i64, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let id = sqlx::query_scalar::<_, i64>(&query_str)
+                .bind(i64::from(note_id))
+                .bind(form.label)
+                .bind(form.url)
+                .bind(form.mime_type)
+                .bind(form.size.map(|size| size as i64))
+                .bind(form.file_id.map(i64::from))
+                .bind(i64::from(next_pos))
+                .bind(now)
+                .bind(now)
+                .fetch_one(&mut *tx)
+                .await?;
+            self.touch_notes(&mut tx, Filter::eq("id", note_id)).await?;
+            tx.commit().await?;
+            Ok(id)
+        }
+        .await;
+        trace!("{:?}", res);
+        res.map(|id| id as u32).map_err(|err| {
+            warn!("{:?}", err);
+            db::attachments::CreateAttachmentError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn update_attachments(
+        &self,
+        form: db::attachments::UpdateAttachmentForm,
+        identified_by: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::UpdateAttachmentsError> {
+        if form.is_all_none() {
+            return Err(db::attachments::UpdateAttachmentsError::NothingToUpdateError);
+        }
+        let attachments = self.get_attachments(identified_by).await.map_err(
+            |db::attachments::GetAttachmentsError::UnexpectedError(err)| {
+                db::attachments::UpdateAttachmentsError::UnexpectedError(err)
+            },
+        )?;
+        if attachments.is_empty() {
+            return Err(db::attachments::UpdateAttachmentsError::NotFoundError);
+        }
+
+        let mut assignments = Assignments::default();
+        assignments.set_some("label", form.label);
+        assignments.set_some("url", form.url);
+        assignments.set_some("mime_type", form.mime_type);
+        assignments.set_some("size", form.size);
+        assignments.set_some("position", form.position);
+        assignments.set("updated_at", db::now());
+
+        let mut params: Vec<Value> = Vec::new();
+        let set = assignments.to_sql(&mut params);
+        let filter = Filter::is_in("id", attachments.iter().map(|attachment| attachment.id));
+        let query_str = self.sql(&format!(
+            "UPDATE note_attachments SET {} WHERE {}",
+            set,
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(&query_str)
+                .bind_values(params)
+                .execute(&mut *tx)
+                .await?;
+            self.touch_notes(&mut tx, notes_of(&attachments)).await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::attachments::UpdateAttachmentsError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn swap_attachments(
+        &self,
+        ids: [u32; 2],
+    ) -> Result<(), db::attachments::SwapAttachmentsError> {
+        let attachment_1 = self
+            .get_attachment(db::attachments::GetAttachmentsForm {
+                id: Some(ids[0]),
+                ..Default::default()
+            })
+            .await;
+        let attachment_2 = self
+            .get_attachment(db::attachments::GetAttachmentsForm {
+                id: Some(ids[1]),
+                ..Default::default()
+            })
+            .await;
+        let (attachment_1, attachment_2) = match (attachment_1, attachment_2) {
+            (Ok(attachment_1), Ok(attachment_2)) => (attachment_1, attachment_2),
+            (attachment_1, attachment_2) => {
+                return Err(db::attachments::SwapAttachmentsError::NotFoundError((
+                    attachment_1.err().map(|_| ids[0]),
+                    attachment_2.err().map(|_| ids[1]),
+                )))
+            }
+        };
+        if attachment_1.note_id != attachment_2.note_id {
+            return Err(db::attachments::SwapAttachmentsError::CantSwapFromDifferentNotes);
+        }
+
+        let max_pos = self
+            .max_position(
+                "SELECT MAX(position) FROM note_attachments WHERE note_id = ?",
+                vec![Value::from(attachment_1.note_id)],
+            )
+            .await
+            .unwrap_or(0)
+            + 1;
+        let now = db::now();
+        let update_query =
+            self.sql("UPDATE note_attachments SET position = ?, updated_at = ? WHERE id = ?");
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            for (position, id) in [
+                (max_pos, attachment_1.id),
+                (attachment_1.position, attachment_2.id),
+                (attachment_2.position, attachment_1.id),
+            ] {
+                sqlx::query(&update_query)
+                    .bind(i64::from(position))
+                    .bind(now)
+                    .bind(i64::from(id))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            self.touch_notes(&mut tx, Filter::eq("id", attachment_1.note_id))
+                .await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::attachments::SwapAttachmentsError::UnexpectedError(err.into())
+        })
+    }
+
+    async fn delete_attachments(
+        &self,
+        form: db::attachments::GetAttachmentsForm,
+    ) -> Result<(), db::attachments::DeleteAttachmentsError> {
+        let attachments = self.get_attachments(form).await.map_err(
+            |db::attachments::GetAttachmentsError::UnexpectedError(err)| {
+                db::attachments::DeleteAttachmentsError::UnexpectedError(err)
+            },
+        )?;
+        if attachments.is_empty() {
+            return Ok(());
+        }
+
+        let mut params: Vec<Value> = Vec::new();
+        let filter = Filter::is_in("id", attachments.iter().map(|attachment| attachment.id));
+        let query_str = self.sql(&format!(
+            "DELETE FROM note_attachments WHERE {}",
+            filter.to_sql(&mut params)
+        ));
+        trace!("{}", query_str);
+        let res: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(&query_str)
+                .bind_values(params)
+                .execute(&mut *tx)
+                .await?;
+            self.touch_notes(&mut tx, notes_of(&attachments)).await?;
+            tx.commit().await
+        }
+        .await;
+        res.map_err(|err| {
+            warn!("{:?}", err);
+            db::attachments::DeleteAttachmentsError::UnexpectedError(err.into())
+        })
+    }
+}
+
+#[async_trait]
+impl FileRepository for SqlRepository {
+    async fn get_files(
+        &self,
+        form: db::files::GetFilesForm,
+    ) -> Result<Vec<db::files::FileFromDb>, db::files::GetFilesError> {
+        let (clauses, params) = form.to_clauses().to_sql();
+        let query_str = self.sql(&format!(
+            "SELECT id, sha256, original_name, mime_type, size FROM files{}",
+            clauses
+        ));
+        trace!("{}", query_str);
+        let query = sqlx::query_as::<_, FileRow>(&query_str).bind_values(params);
+        match query.fetch_all(&self.pool).await {
+            Ok(rows) => Ok(rows.into_iter().map(db::files::FileFromDb::from).collect()),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::files::GetFilesError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn create_file(
+        &self,
+        form: db::files::CreateFileForm,
+    ) -> Result<(u32, bool), db::files::CreateFileError> {
+        let now = db::now();
+        let query_str = self.sql(
+            "INSERT INTO files (sha256, original_name, mime_type, size, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (sha256) DO NOTHING RETURNING id",
+        );
+        let res = sqlx::query_scalar::<_, i64>(&query_str)
+            .bind(form.sha256.as_str())
+            .bind(form.original_name)
+            .bind(form.mime_type)
+            .bind(form.size as i64)
+            .bind(now)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await;
+        trace!("{:?}", res);
+        match res {
+            Ok(Some(id)) => Ok((id as u32, true)),
+            Ok(None) => self
+                .get_file(db::files::GetFilesForm {
+                    sha256: Some(form.sha256),
+                    ..Default::default()
+                })
+                .await
+                .map(|file| (file.id, false))
+                .map_err(|err| match err {
+                    db::files::GetFileError::UnexpectedError(err) => {
+                        db::files::CreateFileError::UnexpectedError(err)
+                    }
+                    db::files::GetFileError::NotFoundError => {
+                        db::files::CreateFileError::UnexpectedError(Error::internal(
+                            "the file was deleted meanwhile",
+                        ))
+                    }
+                }),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::files::CreateFileError::UnexpectedError(err.into()))
+            }
+        }
+    }
+
+    async fn delete_file(
+        &self,
+        id: u32,
+    ) -> Result<db::files::FileFromDb, db::files::DeleteFileError> {
+        let file = self
+            .get_file(db::files::GetFilesForm {
+                id: Some(id),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| match err {
+                db::files::GetFileError::NotFoundError => db::files::DeleteFileError::NotFoundError,
+                db::files::GetFileError::UnexpectedError(err) => {
+                    db::files::DeleteFileError::UnexpectedError(err)
+                }
+            })?;
+
+        let query_str = self.sql("DELETE FROM files WHERE id = ?");
+        let res = sqlx::query(&query_str)
+            .bind(i64::from(id))
+            .execute(&self.pool)
+            .await;
+        trace!("{:?}", res);
+        match res {
+            Ok(_) => Ok(file),
+            Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+                Err(db::files::DeleteFileError::InUseError)
+            }
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::files::DeleteFileError::UnexpectedError(err.into()))
+            }
+        }
+    }
+}
+
+impl SqlRepository {
+    /// Replace the tags and the attachments of the note `note_id` by the imported
+    /// ones, as `db::catalog` does for MySQL.
+    async fn replace_note_details(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Any>,
+        note_id: i64,
+        note: &ImportedNote,
+        now: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(&self.sql("DELETE FROM note_tags WHERE note_id = ?"))
+            .bind(note_id)
+            .execute(&mut **tx)
+            .await?;
+        let insert_tag = self.sql(
+            "INSERT INTO tags (name, created_at, updated_at) VALUES (?, ?, ?) \
+             ON CONFLICT (name) DO NOTHING",
+        );
+        let select_tag = self.sql("SELECT id FROM tags WHERE name = ?");
+        let insert_note_tag = self.sql(
+            "INSERT INTO note_tags (note_id, tag_id) VALUES (?, ?) \
+             ON CONFLICT (note_id, tag_id) DO NOTHING",
+        );
+        for tag in &note.tags {
+            sqlx::query(&insert_tag)
+                .bind(tag.as_str())
+                .bind(now)
+                .bind(now)
+                .execute(&mut **tx)
+                .await?;
+            let tag_id = sqlx::query_scalar::<_, i64>(&select_tag)
+                .bind(tag.as_str())
+                .fetch_one(&mut **tx)
+                .await?;
+            sqlx::query(&insert_note_tag)
+                .bind(note_id)
+                .bind(tag_id)
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        let stored = sqlx::query_as::<_, StoredAttachmentRow>(&self.sql(
+            "SELECT a.label, a.url, a.mime_type, a.size, f.sha256 FROM note_attachments a \
+             LEFT JOIN files f ON f.id = a.file_id WHERE a.note_id = ? ORDER BY a.position",
+        ))
+        .bind(note_id)
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(ImportedAttachment::from)
+        .collect::<Vec<_>>();
+        if stored == note.attachments {
+            return Ok(());
+        }
+        sqlx::query(&self.sql("DELETE FROM note_attachments WHERE note_id = ?"))
+            .bind(note_id)
+            .execute(&mut **tx)
+            .await?;
+        let select_file = self.sql("SELECT id FROM files WHERE sha256 = ?");
+        let insert_attachment = self.sql(
+            "INSERT INTO note_attachments (note_id, label, url, mime_type, size, file_id, \
+             position, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        );
+        for (position, attachment) in note.attachments.iter().enumerate() {
+            let file_id = match &attachment.file_sha256 {
+                Some(sha256) => {
+                    sqlx::query_scalar::<_, i64>(&select_file)
+                        .bind(sha256.as_str())
+                        .fetch_optional(&mut **tx)
+                        .await?
+                }
+                None => None,
+            };
+            sqlx::query(&insert_attachment)
+                .bind(note_id)
+                .bind(attachment.label.as_str())
+                .bind(attachment.url.as_str())
+                .bind(attachment.mime_type.clone())
+                .bind(attachment.size.map(|size| size as i64))
+                .bind(file_id)
+                .bind(position as i64)
+                .bind(now)
+                .bind(now)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CatalogRepository for SqlRepository {
+    async fn apply_catalog_changes(
+        &self,
+        changes: &[CatalogChange],
+    ) -> Result<Vec<u32>, db::catalog::ApplyCatalogChangesError> {
+        let res: Result<Result<Vec<u32>, usize>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let now = db::now();
+
+            // SQLite has no row locks, its write transactions hold the whole database.
+            let lock = match self.backend {
+                Backend::Postgres => " FOR UPDATE",
+                Backend::Sqlite | Backend::MySql | Backend::Memory => "",
+            };
+            for (table, rows) in db::catalog::locked_rows(changes) {
+                if rows.is_empty() {
+                    continue;
+                }
+                let mut params: Vec<Value> = Vec::new();
+                let filter = Filter::is_in("id", rows.iter().map(|row| row.id));
+                let query_str = self.sql(&format!(
+                    "SELECT id, version FROM {table} WHERE {}{lock}",
+                    filter.to_sql(&mut params)
+                ));
+                trace!("{}", query_str);
+                let stored: Vec<(i64, i64)> = sqlx::query_as(&query_str)
+                    .bind_values(params)
+                    .fetch_all(&mut *tx)
+                    .await?;
+                let stored = stored
+                    .into_iter()
+                    .map(|(id, version)| (id as u32, version as u32))
+                    .collect();
+                if let Some(change) = db::catalog::first_conflict(&rows, &stored) {
+                    return Ok(Err(change));
+                }
+            }
+
+            // The rows moved by the changes are parked first, so that their final
+            // positions are free whatever order they are written in.
+            for (table, ids) in [
+                (
+                    "nodes",
+                    changes
+                        .iter()
+                        .filter_map(|change| match change {
+                            CatalogChange::UpdateNode(NodeRef::Stored(id), _, _) => Some(*id),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                (
+                    "notes",
+                    changes
+                        .iter()
+                        .filter_map(|change| match change {
+                            CatalogChange::UpdateNote(NoteRef::Stored(id), _, _) => Some(*id),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            ] {
+                if ids.is_empty() {
+                    continue;
+                }
+                let mut params: Vec<Value> = vec![Value::from(db::catalog::PARKED_POSITION_OFFSET)];
+                let filter = Filter::is_in("id", ids);
+                let query_str = self.sql(&format!(
+                    "UPDATE {table} SET position = position + ? WHERE {}",
+                    filter.to_sql(&mut params)
+                ));
+                trace!("{}", query_str);
+                sqlx::query(&query_str)
+                    .bind_values(params)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            let insert_node = self.sql(
+                "INSERT INTO nodes (parent_id, kind, title, position, created_at, updated_at, \
+                 status, publish_at, slug, previous_slugs) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+            );
+            let update_node = self.sql(
+                "UPDATE nodes SET parent_id = ?, kind = ?, title = ?, position = ?, \
+                 status = ?, publish_at = ?, slug = ?, previous_slugs = ?, \
+                 updated_at = ?, version = version + 1 WHERE id = ? AND version = ?",
+            );
+            let delete_node = self.sql("DELETE FROM nodes WHERE id = ? AND version = ?");
+            let insert_note = self.sql(
+                "INSERT INTO notes (name, description, url, position, node_id, kind, \
+                 course_code, term, language, authors, license, created_at, updated_at, \
+                 status, publish_at, slug, previous_slugs) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+            );
+            let update_note = self.sql(
+                "UPDATE notes SET name = ?, description = ?, url = ?, position = ?, \
+                 node_id = ?, kind = ?, course_code = ?, term = ?, language = ?, \
+                 authors = ?, license = ?, status = ?, publish_at = ?, slug = ?, \
+                 previous_slugs = ?, updated_at = ?, version = version + 1 \
+                 WHERE id = ? AND version = ?",
+            );
+            let delete_note = self.sql("DELETE FROM notes WHERE id = ? AND version = ?");
+
+            let mut written = Vec::with_capacity(changes.len());
+            let mut created_nodes: Vec<u32> = Vec::new();
+            let mut created_notes: Vec<u32> = Vec::new();
+            let node_id = |node: NodeRef, created_nodes: &[u32]| match node {
+                NodeRef::Stored(id) => Ok(i64::from(id)),
+                NodeRef::Planned(index) => created_nodes
+                    .get(index)
+                    .map(|id| i64::from(*id))
+                    .ok_or(sqlx::Error::RowNotFound),
+            };
+            let note_id = |note: NoteRef, created_notes: &[u32]| match note {
+                NoteRef::Stored(id) => Ok(i64::from(id)),
+                NoteRef::Planned(index) => created_notes
+                    .get(index)
+                    .map(|id| i64::from(*id))
+                    .ok_or(sqlx::Error::RowNotFound),
+            };
+            for (index, change) in changes.iter().enumerate() {
+                let id = match change {
+                    CatalogChange::CreateNode(node) => {
+                        let parent_id = node
+                            .parent
+                            .map(|parent| node_id(parent, &created_nodes))
+                            .transpose()?;
+                        let id = sqlx::query_scalar::<_, i64>(&insert_node)
+                            .bind(parent_id)
+                            .bind(node.kind.as_str())
+                            .bind(node.title.as_str())
+                            .bind(i64::from(node.position))
+                            .bind(now)
+                            .bind(now)
+                            .bind(node.status.as_str())
+                            .bind(node.publish_at)
+                            .bind(node.slug.as_str())
+                            .bind(db::slugs::previous_slugs_to_json(&node.previous_slugs))
+                            .fetch_one(&mut *tx)
+                            .await?;
+                        created_nodes.push(id as u32);
+                        id
+                    }
+                    CatalogChange::UpdateNode(node_ref, version, node) => {
+                        let id = node_id(*node_ref, &created_nodes)?;
+                        let parent_id = node
+                            .parent
+                            .map(|parent| node_id(parent, &created_nodes))
+                            .transpose()?;
+                        let done = sqlx::query(&update_node)
+                            .bind(parent_id)
+                            .bind(node.kind.as_str())
+                            .bind(node.title.as_str())
+                            .bind(i64::from(node.position))
+                            .bind(node.status.as_str())
+                            .bind(node.publish_at)
+                            .bind(node.slug.as_str())
+                            .bind(db::slugs::previous_slugs_to_json(&node.previous_slugs))
+                            .bind(now)
+                            .bind(id)
+                            .bind(i64::from(*version))
+                            .execute(&mut *tx)
+                            .await?;
+                        if done.rows_affected() == 0 {
+                            return Ok(Err(index));
+                        }
+                        id
+                    }
+                    CatalogChange::DeleteNode(node_ref, version) => {
+                        let id = node_id(*node_ref, &created_nodes)?;
+                        let done = sqlx::query(&delete_node)
+                            .bind(id)
+                            .bind(i64::from(*version))
+                            .execute(&mut *tx)
+                            .await?;
+                        if done.rows_affected() == 0 {
+                            return Ok(Err(index));
+                        }
+                        id
+                    }
+                    CatalogChange::CreateNote(note) => {
+                        let metadata = &note.metadata;
+                        let id = sqlx::query_scalar::<_, i64>(&insert_note)
+                            .bind(note.name.as_str())
+                            .bind(note.description.as_str())
+                            .bind(note.url.as_str())
+                            .bind(i64::from(note.position))
+                            .bind(node_id(note.node, &created_nodes)?)
+                            .bind(metadata.kind.clone())
+                            .bind(metadata.course_code.clone())
+                            .bind(metadata.term.clone())
+                            .bind(metadata.language.clone())
+                            .bind(db::lecture_notes::authors_to_json(&metadata.authors))
+                            .bind(metadata.license.clone())
+                            .bind(now)
+                            .bind(now)
+                            .bind(note.status.as_str())
+                            .bind(note.publish_at)
+                            .bind(note.slug.as_str())
+                            .bind(db::slugs::previous_slugs_to_json(&note.previous_slugs))
+                            .fetch_one(&mut *tx)
+                            .await?;
+                        self.replace_note_details(&mut tx, id, note, now).await?;
+                        created_notes.push(id as u32);
+                        id
+                    }
+                    CatalogChange::UpdateNote(note_ref, version, note) => {
+                        let id = note_id(*note_ref, &created_notes)?;
+                        let metadata = &note.metadata;
+                        let done = sqlx::query(&update_note)
+                            .bind(note.name.as_str())
+                            .bind(note.description.as_str())
+                            .bind(note.url.as_str())
+                            .bind(i64::from(note.position))
+                            .bind(node_id(note.node, &created_nodes)?)
+                            .bind(metadata.kind.clone())
+                            .bind(metadata.course_code.clone())
+                            .bind(metadata.term.clone())
+                            .bind(metadata.language.clone())
+                            .bind(db::lecture_notes::authors_to_json(&metadata.authors))
+                            .bind(metadata.license.clone())
+                            .bind(note.status.as_str())
+                            .bind(note.publish_at)
+                            .bind(note.slug.as_str())
+                            .bind(db::slugs::previous_slugs_to_json(&note.previous_slugs))
+                            .bind(now)
+                            .bind(id)
+                            .bind(i64::from(*version))
+                            .execute(&mut *tx)
+                            .await?;
+                        if done.rows_affected() == 0 {
+                            return Ok(Err(index));
+                        }
+                        self.replace_note_details(&mut tx, id, note, now).await?;
+                        id
+                    }
+                    CatalogChange::DeleteNote(note_ref, version) => {
+                        let id = note_id(*note_ref, &created_notes)?;
+                        let done = sqlx::query(&delete_note)
+                            .bind(id)
+                            .bind(i64::from(*version))
+                            .execute(&mut *tx)
+                            .await?;
+                        if done.rows_affected() == 0 {
+                            return Ok(Err(index));
+                        }
+                        id
+                    }
+                };
+                written.push(id as u32);
+            }
+            tx.commit().await?;
+            Ok(Ok(written))
+        }
+        .await;
+        match res {
+            Ok(Ok(written)) => Ok(written),
+            Ok(Err(change)) => Err(db::catalog::ApplyCatalogChangesError::VersionConflictError(
+                change,
+            )),
+            Err(err) => {
+                warn!("{:?}", err);
+                Err(db::catalog::ApplyCatalogChangesError::UnexpectedError(
+                    err.into(),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::publishing::Viewer;
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{allowed_url, ValidJson, MAX_NAME_LENGTH};
+use crate::routes::AppState;
+use crate::services;
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CreateAttachmentRequest {
+    #[serde(default)]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub label: String,
+    #[validate(custom(function = allowed_url))]
+    pub url: Option<String>,
+    #[validate(length(max = 127))]
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub file_id: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct UpdateAttachmentRequest {
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub label: Option<String>,
+    #[validate(custom(function = allowed_url))]
+    pub url: Option<String>,
+    #[validate(length(max = 127))]
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct MoveAttachmentRequest {
+    pub first_id: u32,
+    pub second_id: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedAttachmentResponse {
+    pub id: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/{id}/attachments",
+    tag = "attachments",
+    params(("id" = u32, Path, description = "Note id")),
+    responses(
+        (status = 200, description = "The attachments of the note, in order", body = [services::attachments::AttachmentReturn]),
+        (status = 404, description = "No such note, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn list_note_attachments(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<services::attachments::AttachmentReturn>>, Error> {
+    match services::lecture_notes::get_note(&state.storage, id, viewer.visibility()).await {
+        Ok(_) => {}
+        Err(services::lecture_notes::GetNoteError::NotFoundError) => {
+            return Err(Error::not_found("note not found"))
+        }
+        Err(services::lecture_notes::GetNoteError::UnexpectedError(err)) => {
+            return Err(err.context("failed to fetch attachments"))
+        }
+    }
+    let attachments = services::attachments::get_attachments_of_note(&state.storage, id)
+        .await
+        .map_err(
+            |services::attachments::GetAttachmentsError::UnexpectedError(err)| {
+                err.context("failed to fetch attachments")
+            },
+        )?;
+    Ok(Json(attachments))
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/attachments",
+    tag = "attachments",
+    params(("id" = u32, Path, description = "Note id")),
+    request_body = CreateAttachmentRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The attachment was added last", body = CreatedAttachmentResponse),
+        (status = 400, description = "Empty label, or neither `url` nor `file_id`", body = ErrorResponse),
+        (status = 404, description = "No such note or file", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn create_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    ValidJson(payload): ValidJson<CreateAttachmentRequest>,
+) -> Result<(StatusCode, Json<CreatedAttachmentResponse>), Error> {
+    let attachment_id = services::attachments::create_attachment(
+        &state.storage,
+        id,
+        services::attachments::CreateAttachmentForm {
+            label: payload.label,
+            url: payload.url,
+            mime_type: payload.mime_type,
+            size: payload.size,
+            file_id: payload.file_id,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        services::attachments::CreateAttachmentError::NoteNotFoundError => {
+            Error::not_found("note not found")
+        }
+        services::attachments::CreateAttachmentError::FileNotFoundError => {
+            Error::not_found("file not found")
+        }
+        services::attachments::CreateAttachmentError::EmptyLabelError => {
+            Error::bad_request("attachment label cannot be empty")
+        }
+        services::attachments::CreateAttachmentError::EmptyUrlError => {
+            Error::bad_request("attachment needs either a url or a file_id")
+        }
+        services::attachments::CreateAttachmentError::UnexpectedError(err) => {
+            err.context("failed to create attachment")
+        }
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedAttachmentResponse { id: attachment_id }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/attachments/{id}",
+    tag = "attachments",
+    params(("id" = u32, Path, description = "Attachment id")),
+    responses(
+        (status = 200, description = "The attachment", body = services::attachments::AttachmentReturn),
+        (status = 404, description = "No such attachment", body = ErrorResponse),
+    )
+)]
+pub async fn get_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+) -> Result<Json<services::attachments::AttachmentReturn>, Error> {
+    let attachment = services::attachments::get_attachment(&state.storage, id)
+        .await
+        .map_err(|err| match err {
+            services::attachments::GetAttachmentError::NotFoundError => {
+                Error::not_found("attachment not found")
+            }
+            services::attachments::GetAttachmentError::UnexpectedError(err) => {
+                err.context("failed to fetch attachment")
+            }
+        })?;
+    Ok(Json(attachment))
+}
+
+#[utoipa::path(
+    put,
+    path = "/attachments/{id}",
+    tag = "attachments",
+    params(("id" = u32, Path, description = "Attachment id")),
+    request_body = UpdateAttachmentRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The attachment was updated", body = MessageResponse),
+        (status = 400, description = "Empty label or URL, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such attachment", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn update_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    ValidJson(payload): ValidJson<UpdateAttachmentRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::attachments::update_attachment(
+        &state.storage,
+        id,
+        services::attachments::UpdateAttachmentForm {
+            label: payload.label,
+            url: payload.url,
+            mime_type: payload.mime_type,
+            size: payload.size,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        services::attachments::UpdateAttachmentError::NotFoundError => {
+            Error::not_found("attachment not found")
+        }
+        services::attachments::UpdateAttachmentError::NothingToUpdateError => {
+            Error::new(ErrorCode::NothingToUpdate, "nothing to update")
+        }
+        services::attachments::UpdateAttachmentError::EmptyLabelError => {
+            Error::bad_request("attachment label cannot be empty")
+        }
+        services::attachments::UpdateAttachmentError::EmptyUrlError => {
+            Error::bad_request("attachment url cannot be empty")
+        }
+        services::attachments::UpdateAttachmentError::UnexpectedError(err) => {
+            err.context("failed to update attachment")
+        }
+    })?;
+    Ok(Json(MessageResponse {
+        message: "updated".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/attachments/{id}",
+    tag = "attachments",
+    params(("id" = u32, Path, description = "Attachment id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The attachment was deleted", body = MessageResponse))
+)]
+pub async fn delete_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::attachments::delete_attachment(&state.storage, id)
+        .await
+        .map_err(
+            |services::attachments::DeleteAttachmentError::UnexpectedError(err)| {
+                err.context("failed to delete attachment")
+            },
+        )?;
+    Ok(Json(MessageResponse {
+        message: "deleted".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/attachments/move",
+    tag = "attachments",
+    request_body = MoveAttachmentRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The two attachments swapped positions", body = MessageResponse),
+        (status = 400, description = "The attachments belong to different notes", body = ErrorResponse),
+        (status = 404, description = "No such attachment", body = ErrorResponse),
+    )
+)]
+pub async fn move_attachment(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<MoveAttachmentRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::attachments::move_attachment(&state.storage, [payload.first_id, payload.second_id])
+        .await
+        .map_err(|err| match err {
+            services::attachments::MoveAttachmentError::NotFoundError(_, _) => {
+                Error::not_found("attachment not found")
+            }
+            services::attachments::MoveAttachmentError::CantSwapFromDifferentNotes => {
+                Error::bad_request("cannot swap attachments of different notes")
+            }
+            services::attachments::MoveAttachmentError::UnexpectedError(err) => {
+                err.context("failed to move attachment")
+            }
+        })?;
+    Ok(Json(MessageResponse {
+        message: "moved".to_string(),
+    }))
+}
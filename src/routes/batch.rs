@@ -0,0 +1,318 @@
+use std::borrow::Cow;
+
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::publishing::nullable;
+use crate::routes::responses::ErrorResponse;
+use crate::routes::validation::{
+    allowed_url, invalid_field, not_blank, tag_names, ValidJson, MAX_NAME_LENGTH,
+};
+use crate::routes::AppState;
+use crate::services;
+use crate::services::batch::{
+    BatchAction, BatchEntity, BatchFields, BatchId, BatchOperation, BatchOperationError,
+    BatchResult,
+};
+use crate::services::publishing::Status;
+
+/// The most operations a batch holds.
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOp {
+    Create,
+    Update,
+    Delete,
+    /// Swap the positions of `first_id` and `second_id`.
+    Move,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[validate(schema(function = operation_count, skip_on_field_errors = false))]
+pub struct BatchRequest {
+    /// Made in order, each one seeing the rows as the earlier ones left them.
+    #[validate(nested)]
+    pub operations: Vec<BatchOperationRequest>,
+}
+
+fn operation_count(request: &BatchRequest) -> Result<(), ValidationError> {
+    let count = request.operations.len();
+    if count == 0 || count > MAX_BATCH_OPERATIONS {
+        let mut err = ValidationError::new("length").with_message(Cow::from(format!(
+            "must hold 1 to {MAX_BATCH_OPERATIONS} operations"
+        )));
+        err.add_param(Cow::from("field"), &"operations");
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// One operation of a batch. The ids are numbers for the stored rows, or the
+/// `ref` given to a row by an earlier `create` of the batch. An `update` is a
+/// merge patch: a missing field stays as it is, and `null` clears the fields
+/// that can be cleared.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct BatchOperationRequest {
+    pub op: BatchOp,
+    pub entity: BatchEntity,
+    /// Names the row a `create` makes, for the later operations.
+    #[serde(rename = "ref")]
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub reference: Option<String>,
+    /// The row an `update` or a `delete` acts on.
+    pub id: Option<BatchId>,
+    /// The `version` of the row, required by an `update` and a `delete`. A row
+    /// created by the batch starts at version 1.
+    pub version: Option<u32>,
+    pub first_id: Option<BatchId>,
+    pub second_id: Option<BatchId>,
+    /// The versions of the rows of a `move`, both required.
+    pub first_version: Option<u32>,
+    pub second_version: Option<u32>,
+    /// Sections, subsections and nodes.
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: Option<String>,
+    /// Any kind for the nodes, one of the note kinds for the notes.
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(min = 1, max = 32))]
+    pub kind: Option<Option<String>>,
+    /// The parent of a node.
+    pub parent_id: Option<BatchId>,
+    /// The section of a subsection or a note.
+    pub section_id: Option<BatchId>,
+    /// `null` moves a note up to its section.
+    #[serde(default, deserialize_with = "nullable")]
+    pub subsection_id: Option<Option<BatchId>>,
+    pub node_id: Option<BatchId>,
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub name: Option<String>,
+    /// `null` empties the description.
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[validate(custom(function = allowed_url))]
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 64))]
+    pub course_code: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 64))]
+    pub term: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 16))]
+    pub language: Option<Option<String>>,
+    /// `null` empties the list.
+    #[serde(default, deserialize_with = "nullable")]
+    pub authors: Option<Option<Vec<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub license: Option<Option<String>>,
+    /// Replaces the tags of a note.
+    #[validate(custom(function = tag_names))]
+    pub tags: Option<Vec<String>>,
+    pub status: Option<Status>,
+    /// `null` clears the publication time.
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+}
+
+/// The answer to an operation of the batch at `index` naming no version of
+/// its row in `field`.
+fn missing_version(index: usize, field: &str) -> Error {
+    Error::new(
+        ErrorCode::PreconditionRequired,
+        format!("operation {index} names no version to check, nothing was changed"),
+    )
+    .with_detail(format!("operations[{index}].{field}"), "is required")
+}
+
+impl BatchOperationRequest {
+    /// The operation at `index`, or the error naming the field it lacks or the
+    /// one it should not have.
+    fn into_operation(self, index: usize) -> Result<BatchOperation, Error> {
+        let invalid = |field: &str, message: &str| {
+            invalid_field(&format!("operations[{index}].{field}"), message)
+        };
+        let required = |field: &str| invalid(field, "is required");
+        if self.reference.is_some() && self.op != BatchOp::Create {
+            return Err(invalid("ref", "only names the row of a create"));
+        }
+        let action = match self.op {
+            BatchOp::Create => BatchAction::Create {
+                reference: self.reference,
+            },
+            BatchOp::Update => BatchAction::Update {
+                id: self.id.ok_or_else(|| required("id"))?,
+                version: self
+                    .version
+                    .ok_or_else(|| missing_version(index, "version"))?,
+            },
+            BatchOp::Delete => BatchAction::Delete {
+                id: self.id.ok_or_else(|| required("id"))?,
+                version: self
+                    .version
+                    .ok_or_else(|| missing_version(index, "version"))?,
+            },
+            BatchOp::Move => BatchAction::Move {
+                ids: [
+                    self.first_id.ok_or_else(|| required("first_id"))?,
+                    self.second_id.ok_or_else(|| required("second_id"))?,
+                ],
+                versions: [
+                    self.first_version
+                        .ok_or_else(|| missing_version(index, "first_version"))?,
+                    self.second_version
+                        .ok_or_else(|| missing_version(index, "second_version"))?,
+                ],
+            },
+        };
+        Ok(BatchOperation {
+            entity: self.entity,
+            action,
+            fields: BatchFields {
+                title: self.title,
+                kind: self.kind,
+                parent_id: self.parent_id,
+                section_id: self.section_id,
+                subsection_id: self.subsection_id,
+                node_id: self.node_id,
+                name: self.name,
+                description: self
+                    .description
+                    .map(|description| description.unwrap_or_default()),
+                url: self.url,
+                course_code: self.course_code,
+                term: self.term,
+                language: self.language,
+                authors: self.authors.map(|authors| authors.unwrap_or_default()),
+                license: self.license,
+                tags: self.tags,
+                status: self.status,
+                publish_at: self.publish_at,
+                slug: self.slug,
+            },
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// One per operation, in order.
+    pub results: Vec<BatchResult>,
+}
+
+/// The error of the operation at `index`, which nothing of the batch outlives.
+fn operation_error(index: usize, err: BatchOperationError) -> Error {
+    let (code, field, message) = match err {
+        BatchOperationError::NotFoundError(field) => {
+            (ErrorCode::NotFound, field, "names no such row")
+        }
+        BatchOperationError::DuplicateReferenceError => (
+            ErrorCode::ValidationFailed,
+            "ref",
+            "already names a row of the batch",
+        ),
+        BatchOperationError::MissingFieldError(field) => {
+            (ErrorCode::ValidationFailed, field, "is required")
+        }
+        BatchOperationError::UnexpectedFieldError(field) => (
+            ErrorCode::ValidationFailed,
+            field,
+            "does not apply to this operation",
+        ),
+        BatchOperationError::InvalidKindError => (
+            ErrorCode::ValidationFailed,
+            "kind",
+            "must be lecture, exercise, exam, solution or cheatsheet",
+        ),
+        BatchOperationError::InvalidSlugError => (
+            ErrorCode::InvalidSlug,
+            "slug",
+            "must be lowercase letters, digits and single dashes",
+        ),
+        BatchOperationError::SlugTakenError => {
+            (ErrorCode::SlugTaken, "slug", "is already used by a sibling")
+        }
+        BatchOperationError::ParentMismatchError => (
+            ErrorCode::ValidationFailed,
+            "subsection_id",
+            "the subsection is not in the section",
+        ),
+        BatchOperationError::NoSectionError => (
+            ErrorCode::ValidationFailed,
+            "subsection_id",
+            "the note has no section to move up to",
+        ),
+        BatchOperationError::CycleError => (
+            ErrorCode::ValidationFailed,
+            "parent_id",
+            "a node cannot be moved under itself or one of its descendants",
+        ),
+        BatchOperationError::InUseError => (ErrorCode::InUse, "id", "still has children or notes"),
+        BatchOperationError::CantSwapError => (
+            ErrorCode::ValidationFailed,
+            "second_id",
+            "cannot swap rows with different parents",
+        ),
+        BatchOperationError::VersionConflictError(field) => (
+            ErrorCode::VersionConflict,
+            field,
+            "the row moved past this version",
+        ),
+        BatchOperationError::NothingToUpdateError => {
+            (ErrorCode::NothingToUpdate, "id", "nothing to update")
+        }
+    };
+    Error::new(
+        code,
+        format!("operation {index} failed, nothing was changed"),
+    )
+    .with_detail(format!("operations[{index}].{field}"), message)
+}
+
+/// Make a list of operations on the sections, subsections, nodes and notes in
+/// one transaction: either all of them are made, or none.
+#[utoipa::path(
+    post,
+    path = "/batch",
+    tag = "batch",
+    request_body = BatchRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every operation was made, in order", body = BatchResponse),
+        (status = 400, description = "Invalid slug or nothing to update, nothing was changed", body = ErrorResponse),
+        (status = 404, description = "An operation names a missing row, nothing was changed", body = ErrorResponse),
+        (status = 409, description = "Version conflict, taken slug or node in use, nothing was changed", body = ErrorResponse),
+        (status = 422, description = "Invalid operation, nothing was changed", body = ErrorResponse),
+        (status = 428, description = "An update, a delete or a move names no version, nothing was changed", body = ErrorResponse),
+    )
+)]
+pub async fn run_batch(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<BatchRequest>,
+) -> Result<Json<BatchResponse>, Error> {
+    let operations = payload
+        .operations
+        .into_iter()
+        .enumerate()
+        .map(|(index, operation)| operation.into_operation(index))
+        .collect::<Result<Vec<_>, _>>()?;
+    let results = services::batch::run_batch(&state.storage, &operations)
+        .await
+        .map_err(|err| match err {
+            services::batch::RunBatchError::OperationError(index, err) => {
+                operation_error(index, err)
+            }
+            services::batch::RunBatchError::UnexpectedError(err) => {
+                err.context("failed to run the batch")
+            }
+        })?;
+    Ok(Json(BatchResponse { results }))
+}
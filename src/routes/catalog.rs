@@ -0,0 +1,118 @@
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::responses::ErrorResponse;
+use crate::routes::validation::ValidJson;
+use crate::routes::AppState;
+use crate::services;
+use crate::services::catalog::{Catalog, ImportMode, ImportReport};
+
+/// Largest catalog accepted by `POST /import`.
+pub const IMPORT_BODY_LIMIT: usize = 32 * 1024 * 1024;
+
+#[derive(Deserialize, IntoParams)]
+pub struct ImportQuery {
+    /// `merge` when missing.
+    #[serde(default)]
+    pub mode: ImportMode,
+    /// Only report what the import would create, update or delete.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Download the whole content tree as a versioned JSON catalog, drafts included.
+#[utoipa::path(
+    get,
+    path = "/export",
+    tag = "catalog",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The whole content tree, as an attachment", body = Catalog),
+    )
+)]
+pub async fn export_catalog(State(state): State<AppState>) -> Result<Response, Error> {
+    let catalog = services::catalog::export_catalog(&state.storage)
+        .await
+        .map_err(
+            |services::catalog::ExportCatalogError::UnexpectedError(err)| {
+                err.context("failed to export the catalog")
+            },
+        )?;
+    let file_name = format!("catalog-{}.json", catalog.exported_at);
+    let body = serde_json::to_vec(&catalog)
+        .map_err(|_| Error::internal("failed to serialize the catalog"))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{file_name}\""),
+        )
+        .body(Body::from(body))
+        .map_err(|_| Error::internal("failed to export the catalog"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/import",
+    tag = "catalog",
+    params(ImportQuery),
+    request_body = Catalog,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "What the import changed, or would change on a dry run", body = ImportReport),
+        (status = 400, description = "Not a catalog, unsupported version, or invalid slugs", body = ErrorResponse),
+        (status = 409, description = "The content changed during the import, nothing was changed", body = ErrorResponse),
+        (status = 422, description = "An item breaks the rules of its fields, named by its path", body = ErrorResponse),
+    )
+)]
+pub async fn import_catalog(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    ValidJson(catalog): ValidJson<Catalog>,
+) -> Result<Json<ImportReport>, Error> {
+    let report = services::catalog::import_catalog(
+        &state.storage,
+        services::catalog::ImportCatalogForm {
+            catalog,
+            mode: query.mode,
+            dry_run: query.dry_run,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        services::catalog::ImportCatalogError::UnsupportedFormatError => {
+            Error::bad_request(format!(
+                "not a catalog, the format should be {}",
+                services::catalog::CATALOG_FORMAT
+            ))
+        }
+        services::catalog::ImportCatalogError::UnsupportedVersionError(version) => {
+            Error::bad_request(format!(
+                "unsupported catalog version {version}, expected {}",
+                services::catalog::CATALOG_VERSION
+            ))
+        }
+        services::catalog::ImportCatalogError::InvalidSlugError(path) => {
+            Error::new(ErrorCode::InvalidSlug, format!("invalid slug at {path}"))
+        }
+        services::catalog::ImportCatalogError::DuplicateSlugError(path) => {
+            Error::bad_request(format!("two siblings use the slug at {path}"))
+        }
+        services::catalog::ImportCatalogError::VersionConflictError => Error::new(
+            ErrorCode::VersionConflict,
+            "the content changed during the import, nothing was changed",
+        ),
+        services::catalog::ImportCatalogError::UnexpectedError(err) => {
+            err.context("failed to import the catalog")
+        }
+    })?;
+    Ok(Json(report))
+}
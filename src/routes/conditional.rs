@@ -0,0 +1,298 @@
+use axum::body::Bytes;
+use axum::http::header::{
+    CONTENT_TYPE, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, ErrorCode};
+use crate::services;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// When the answered content last changed, in seconds since the Unix epoch.
+#[derive(Clone, Copy)]
+pub enum Modified {
+    /// A single row, answered with 304 when it is not newer than `If-Modified-Since`.
+    Row(i64),
+    /// Several rows: a deleted one leaves no timestamp behind, so only the `ETag`
+    /// can tell that the list is unchanged. `None` when the rows carry no timestamp.
+    List(Option<i64>),
+}
+
+/// The year, month and day of a count of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The count of days since 1970-01-01 of a date, the inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Format a timestamp as an HTTP date, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn http_date(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let seconds = timestamp.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Read an HTTP date in the IMF-fixdate format, the only one `http_date` writes.
+pub fn parse_http_date(value: &str) -> Option<i64> {
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day = day
+        .parse::<u32>()
+        .ok()
+        .filter(|day| (1..=31).contains(day))?;
+    let month = MONTHS.iter().position(|name| name == month)? as u32 + 1;
+    let year = year.parse::<i64>().ok()?;
+    let mut time = time.split(':').map(|part| part.parse::<i64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (time.next(), time.next(), time.next(), time.next())
+    else {
+        return None;
+    };
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// A strong entity tag derived from the answered bytes.
+pub(crate) fn entity_tag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex = digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` value names `etag`, with the weak comparison.
+fn none_match(header: &str, etag: &str) -> bool {
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str, modified: &Modified) -> bool {
+    // `If-Modified-Since` is ignored when `If-None-Match` is present.
+    if let Some(header) = headers.get(IF_NONE_MATCH) {
+        return header
+            .to_str()
+            .map(|header| none_match(header, etag))
+            .unwrap_or(false);
+    }
+    let Modified::Row(updated_at) = modified else {
+        return false;
+    };
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|header| header.to_str().ok())
+        .and_then(parse_http_date)
+        .is_some_and(|since| *updated_at <= since)
+}
+
+/// A JSON answer serialized once along with its `ETag`, to be sent many times.
+#[derive(Clone)]
+pub struct RenderedJson {
+    body: Bytes,
+    etag: String,
+    modified: Modified,
+}
+
+impl RenderedJson {
+    pub fn new<T: Serialize>(value: &T, modified: Modified) -> Result<Self, Error> {
+        let body = serde_json::to_vec(value)
+            .map_err(|_| Error::internal("failed to serialize the response"))?;
+        Ok(Self {
+            etag: entity_tag(&body),
+            body: Bytes::from(body),
+            modified,
+        })
+    }
+
+    /// Answer with the JSON along with its `ETag` and `Last-Modified`, or with an
+    /// empty 304 when the request's validators show the client already has it.
+    pub fn respond(&self, headers: &HeaderMap) -> Response {
+        let last_modified = match self.modified {
+            Modified::Row(updated_at) | Modified::List(Some(updated_at)) => Some(updated_at),
+            Modified::List(None) => None,
+        };
+
+        let mut response = if not_modified(headers, &self.etag, &self.modified) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            (
+                [(CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+                self.body.clone(),
+            )
+                .into_response()
+        };
+        let response_headers = response.headers_mut();
+        // The admins also see the unpublished content.
+        response_headers.insert(VARY, HeaderValue::from_static("Authorization, Cookie"));
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            response_headers.insert(ETAG, etag);
+        }
+        if let Some(date) = last_modified.and_then(|at| HeaderValue::from_str(&http_date(at)).ok())
+        {
+            response_headers.insert(LAST_MODIFIED, date);
+        }
+        response
+    }
+}
+
+/// Answer with `value` as JSON along with its `ETag` and `Last-Modified`, or with
+/// an empty 304 when the request's validators show the client already has it.
+pub fn conditional_json<T: Serialize>(
+    headers: &HeaderMap,
+    value: &T,
+    modified: Modified,
+) -> Response {
+    match RenderedJson::new(value, modified) {
+        Ok(rendered) => rendered.respond(headers),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Content whose rows carry a `version` counter, required by their updates.
+pub trait Versioned: Serialize {
+    fn version(&self) -> u32;
+}
+
+impl Versioned for services::nodes::NodeReturn {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Versioned for services::sections::SectionReturn {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Versioned for services::subsections::SubsectionReturn {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Versioned for services::lecture_notes::NoteReturn {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// Answer a stale update with the current state of the content, to merge with
+/// before trying again.
+pub fn conflict_error<T: Serialize>(code: ErrorCode, current: &T) -> Error {
+    Error::new(code, "the content was changed by another request").with_current(current)
+}
+
+/// The version an update was made against.
+pub struct ExpectedVersion {
+    pub version: u32,
+    /// `precondition_failed` (412) when the version came from `If-Match`,
+    /// `version_conflict` (409) when it came from the body.
+    pub conflict_code: ErrorCode,
+}
+
+impl ExpectedVersion {
+    pub fn conflict<T: Serialize>(&self, current: &T) -> Error {
+        conflict_error(self.conflict_code, current)
+    }
+}
+
+/// Whether an `If-Match` value names `etag`, with the strong comparison.
+fn any_match(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// Read the version an update expects, from the `If-Match` header holding the
+/// `ETag` of a previous GET, or else from the `version` field of the body.
+/// `current` loads the content as the GET answers it, and is only awaited for
+/// `If-Match`. Updates naming no version are refused with 428.
+pub async fn expected_version<T: Versioned>(
+    headers: &HeaderMap,
+    field: Option<u32>,
+    current: impl std::future::Future<Output = Result<T, Error>>,
+) -> Result<ExpectedVersion, Error> {
+    if let Some(header) = headers.get(IF_MATCH) {
+        let current = current.await?;
+        let etag = serde_json::to_vec(&current)
+            .map(|body| entity_tag(&body))
+            .unwrap_or_default();
+        let matches = header
+            .to_str()
+            .map(|header| any_match(header, &etag))
+            .unwrap_or(false);
+        if !matches {
+            return Err(conflict_error(ErrorCode::PreconditionFailed, &current));
+        }
+        return Ok(ExpectedVersion {
+            version: current.version(),
+            conflict_code: ErrorCode::PreconditionFailed,
+        });
+    }
+    match field {
+        Some(version) => Ok(ExpectedVersion {
+            version,
+            conflict_code: ErrorCode::VersionConflict,
+        }),
+        None => Err(Error::new(
+            ErrorCode::PreconditionRequired,
+            "updates need an If-Match header or a version field",
+        )),
+    }
+}
+
+/// The versions a move expects its two rows at, when both are given.
+pub fn expected_move_versions(first: Option<u32>, second: Option<u32>) -> Option<[u32; 2]> {
+    Some([first?, second?])
+}
+
+/// The answer to a move naming no versions.
+pub fn missing_move_versions() -> Error {
+    Error::new(
+        ErrorCode::PreconditionRequired,
+        "moves need the first_version and second_version fields",
+    )
+}
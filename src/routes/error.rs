@@ -0,0 +1,69 @@
+use axum::http::header::ETAG;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use loggit::{error, trace};
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::entity_tag;
+use crate::routes::responses::ErrorResponse;
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest
+            | ErrorCode::InvalidSlug
+            | ErrorCode::InvalidPage
+            | ErrorCode::NothingToUpdate => StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AlreadyExists
+            | ErrorCode::SlugTaken
+            | ErrorCode::InUse
+            | ErrorCode::VersionConflict
+            | ErrorCode::DuplicateEntry
+            | ErrorCode::ForeignKeyViolation
+            | ErrorCode::ConstraintViolation => StatusCode::CONFLICT,
+            ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ErrorCode::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The database error is only logged, the client gets the code and the message.
+/// A stale update also carries the `ETag` of the current state.
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = self.code.status();
+        if status.is_server_error() {
+            error!("{:?}: {}", self.code, self);
+        } else {
+            trace!("{:?}: {}", self.code, self);
+        }
+        let etag = self
+            .current
+            .as_ref()
+            .and_then(|current| HeaderValue::from_str(&entity_tag(current.get().as_bytes())).ok());
+        let mut response = (
+            status,
+            Json(ErrorResponse {
+                code: self.code,
+                error: self.message,
+                details: self.details,
+                current: self.current,
+            }),
+        )
+            .into_response();
+        if let Some(etag) = etag {
+            response.headers_mut().insert(ETAG, etag);
+        }
+        response
+    }
+}
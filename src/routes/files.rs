@@ -0,0 +1,232 @@
+use axum::body::Body;
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use utoipa::ToSchema;
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::AppState;
+use crate::services;
+
+/// Name of the multipart field holding the uploaded content.
+const FILE_FIELD_NAME: &str = "file";
+
+/// The multipart body of an upload, for the API documentation.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct UploadFileRequest {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+fn store_file_error(err: services::files::StoreFileError) -> Error {
+    match err {
+        services::files::StoreFileError::NotAllowedTypeError(mime_type) => Error::new(
+            ErrorCode::UnsupportedMediaType,
+            format!("files of type {mime_type} are not allowed"),
+        ),
+        services::files::StoreFileError::TooLargeError(max_size) => Error::new(
+            ErrorCode::PayloadTooLarge,
+            format!("files cannot be larger than {max_size} bytes"),
+        ),
+        services::files::StoreFileError::UnexpectedError(err) => {
+            err.context("failed to store file")
+        }
+    }
+}
+
+/// Stream the `file` field of a multipart body into the file storage.
+#[utoipa::path(
+    post,
+    path = "/files",
+    tag = "files",
+    request_body(content = UploadFileRequest, content_type = "multipart/form-data"),
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The file was stored, or was already", body = services::files::FileReturn),
+        (status = 400, description = "No `file` field", body = ErrorResponse),
+        (status = 413, description = "The file is too large", body = ErrorResponse),
+        (status = 415, description = "The type of the file is not allowed", body = ErrorResponse),
+    )
+)]
+pub async fn upload_file(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<services::files::FileReturn>), Error> {
+    let storage = &state.storage;
+    let invalid_body = |_| Error::bad_request("invalid multipart body");
+    while let Some(mut field) = multipart.next_field().await.map_err(invalid_body)? {
+        if field.name() != Some(FILE_FIELD_NAME) {
+            continue;
+        }
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        let content_type = field.content_type().map(str::to_string);
+        let mut upload = services::files::FileUpload::begin(
+            &state.file_storage,
+            &file_name,
+            content_type.as_deref(),
+        )
+        .await
+        .map_err(store_file_error)?;
+
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => upload.write(&chunk).await.map_err(store_file_error)?,
+                Ok(None) => break,
+                Err(err) => {
+                    upload.abort().await;
+                    return Err(invalid_body(err));
+                }
+            }
+        }
+
+        let file = upload.finish(storage).await.map_err(store_file_error)?;
+        return Ok((StatusCode::CREATED, Json(file)));
+    }
+    Err(Error::bad_request(format!(
+        "missing multipart field `{FILE_FIELD_NAME}`"
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/files",
+    tag = "files",
+    security(("bearer" = [])),
+    responses((status = 200, description = "The uploaded files", body = [services::files::FileReturn]))
+)]
+pub async fn list_files(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<services::files::FileReturn>>, Error> {
+    let files = services::files::get_files(&state.storage).await.map_err(
+        |services::files::GetFilesError::UnexpectedError(err)| err.context("failed to fetch files"),
+    )?;
+    Ok(Json(files))
+}
+
+/// Send the content of a file, or the part selected by a `Range` header.
+#[utoipa::path(
+    get,
+    path = "/files/{id}",
+    tag = "files",
+    params(
+        ("id" = u32, Path, description = "File id"),
+        ("Range" = Option<String>, Header, description = "A single `bytes=` range"),
+    ),
+    responses(
+        (status = 200, description = "The content of the file", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 206, description = "The requested range", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 404, description = "No such file", body = ErrorResponse),
+        (status = 416, description = "The range is outside the file", body = ErrorResponse),
+    )
+)]
+pub async fn download_file(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let file = services::files::get_file(&state.storage, id)
+        .await
+        .map_err(|err| match err {
+            services::files::GetFileError::NotFoundError => Error::not_found("file not found"),
+            services::files::GetFileError::UnexpectedError(err) => {
+                err.context("failed to fetch file")
+            }
+        })?;
+    let read_failed = |_| Error::internal("failed to read file");
+    let mut content = tokio::fs::File::open(state.file_storage.content_path(&file.sha256))
+        .await
+        .map_err(read_failed)?;
+
+    let range = services::files::parse_range(
+        headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok()),
+        file.size,
+    );
+    let range = match range {
+        Ok(range) => range,
+        Err(services::files::RangeError::NotSatisfiableError) => {
+            let mut response =
+                Error::new(ErrorCode::RangeNotSatisfiable, "range not satisfiable").into_response();
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", file.size)) {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            return Ok(response);
+        }
+    };
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, &file.mime_type)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "inline; filename=\"{}\"",
+                file.original_name.replace(['"', '\\', '\r', '\n'], "_")
+            ),
+        )
+        .header(header::ETAG, format!("\"{}\"", file.sha256));
+    let body = match range {
+        Some(range) => {
+            content
+                .seek(std::io::SeekFrom::Start(range.start))
+                .await
+                .map_err(read_failed)?;
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, range.length())
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", range.start, range.end, file.size),
+                );
+            Body::from_stream(ReaderStream::new(content.take(range.length())))
+        }
+        None => {
+            builder = builder
+                .status(StatusCode::OK)
+                .header(header::CONTENT_LENGTH, file.size);
+            Body::from_stream(ReaderStream::new(content))
+        }
+    };
+    builder
+        .body(body)
+        .map_err(|_| Error::internal("failed to read file"))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/files/{id}",
+    tag = "files",
+    params(("id" = u32, Path, description = "File id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The file was deleted", body = MessageResponse),
+        (status = 404, description = "No such file", body = ErrorResponse),
+        (status = 409, description = "The file is attached to a note", body = ErrorResponse),
+    )
+)]
+pub async fn delete_file(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::files::delete_file(&state.storage, &state.file_storage, id)
+        .await
+        .map_err(|err| match err {
+            services::files::DeleteFileError::NotFoundError => Error::not_found("file not found"),
+            services::files::DeleteFileError::InUseError => Error::new(
+                ErrorCode::InUse,
+                "file is attached to a note, delete the attachment first",
+            ),
+            services::files::DeleteFileError::UnexpectedError(err) => {
+                err.context("failed to delete file")
+            }
+        })?;
+    Ok(Json(MessageResponse {
+        message: "deleted".to_string(),
+    }))
+}
@@ -1,65 +1,223 @@
-use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use axum::extract::{OriginalUri, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
 
-use crate::routes::responses::{error_response, MessageResponse};
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::{
+    conditional_json, conflict_error, expected_move_versions, expected_version,
+    missing_move_versions, Modified,
+};
+use crate::routes::pagination::{page_response, PageQuery, PageResponse};
+use crate::routes::publishing::{nullable, Viewer};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{
+    allowed_url, field_error, invalid_field, not_blank, not_null, ValidJson, MAX_NAME_LENGTH,
+};
 use crate::routes::AppState;
 use crate::services;
+use crate::services::publishing::{Status, Visibility};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
+#[validate(schema(function = has_parent, skip_on_field_errors = false))]
 pub struct CreateNoteRequest {
+    #[validate(custom(function = not_blank))]
     pub name: String,
     pub description: Option<String>,
+    #[validate(custom(function = allowed_url))]
     pub url: String,
+    /// One of `node_id`, `section_id` and `subsection_id` is required. A
+    /// subsection given along with a section must be one of its own.
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub metadata: services::lecture_notes::NoteMetadata,
+    /// `published` when missing.
+    #[serde(default)]
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the note stays hidden.
+    pub publish_at: Option<i64>,
+    /// Names the generated pages, derived from the name when missing.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
 }
 
-#[derive(Deserialize)]
+fn has_parent(request: &CreateNoteRequest) -> Result<(), ValidationError> {
+    if request.node_id.is_none() && request.section_id.is_none() && request.subsection_id.is_none()
+    {
+        return Err(field_error(
+            "section_id",
+            "parent",
+            "a node, section or subsection is required",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct UpdateNoteRequest {
+    #[validate(custom(function = not_blank))]
     pub name: Option<String>,
     pub description: Option<String>,
+    #[validate(custom(function = allowed_url))]
     pub url: Option<String>,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
     pub position: Option<u32>,
+    pub kind: Option<services::lecture_notes::NoteKind>,
+    #[validate(length(max = 64))]
+    pub course_code: Option<String>,
+    #[validate(length(max = 64))]
+    pub term: Option<String>,
+    #[validate(length(max = 16))]
+    pub language: Option<String>,
+    pub authors: Option<Vec<String>>,
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub license: Option<String>,
+    pub status: Option<Status>,
+    /// `null` clears the publication time.
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the new one.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the update was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
+}
+
+/// A JSON Merge Patch of a note: a missing field stays as it is and `null`
+/// clears a nullable field. `subsection_id: null` moves the note up to its
+/// section, and a `section_id` given alone keeps the note in its subsection,
+/// which must then be one of that section.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct PatchNoteRequest {
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(custom(function = not_blank))]
+    pub name: Option<String>,
+    /// `null` empties the description.
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(custom(function = allowed_url))]
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub node_id: Option<u32>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub section_id: Option<u32>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub subsection_id: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub position: Option<u32>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub kind: Option<Option<services::lecture_notes::NoteKind>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 64))]
+    pub course_code: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 64))]
+    pub term: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 16))]
+    pub language: Option<Option<String>>,
+    /// `null` empties the list.
+    #[serde(default, deserialize_with = "nullable")]
+    pub authors: Option<Option<Vec<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub license: Option<Option<String>>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub status: Option<Status>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the patch was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct NoteQuery {
     pub id: Option<u32>,
     pub name: Option<String>,
     pub url: Option<String>,
     pub position: Option<u32>,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
-    pub limit: Option<u32>,
+    pub kind: Option<services::lecture_notes::NoteKind>,
+    pub course_code: Option<String>,
+    pub term: Option<String>,
+    pub language: Option<String>,
+    pub author: Option<String>,
+    pub license: Option<String>,
+    /// Comma-separated tag names.
+    pub tag: Option<String>,
+    /// `any` (default) or `all`.
+    pub tag_match: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct MoveNoteRequest {
     pub first_id: u32,
     pub second_id: u32,
+    pub first_version: Option<u32>,
+    pub second_version: Option<u32>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/notes",
+    tag = "notes",
+    request_body = CreateNoteRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The note was created last in its section or subsection", body = MessageResponse),
+        (status = 400, description = "Invalid slug", body = ErrorResponse),
+        (status = 409, description = "The slug is used by another note", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
 pub async fn create_note(
     State(state): State<AppState>,
-    Json(payload): Json<CreateNoteRequest>,
-) -> Result<(StatusCode, Json<MessageResponse>), Response> {
+    ValidJson(payload): ValidJson<CreateNoteRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), Error> {
     services::lecture_notes::create_note(
-        &state.pool,
+        &state.storage,
         services::lecture_notes::CreateNoteForm {
             name: payload.name,
             description: payload.description.unwrap_or_default(),
             url: payload.url,
+            node_id: payload.node_id,
             section_id: payload.section_id,
             subsection_id: payload.subsection_id,
+            metadata: payload.metadata,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
         },
     )
     .await
-    .map_err(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to create note"))?;
+    .map_err(|err| match err {
+        services::lecture_notes::CreateNoteError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::lecture_notes::CreateNoteError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another note")
+        }
+        services::lecture_notes::CreateNoteError::ParentMismatchError => {
+            invalid_field("subsection_id", "the subsection is not in the section")
+        }
+        services::lecture_notes::CreateNoteError::UnexpectedError(err) => {
+            err.context("failed to create note")
+        }
+    })?;
     Ok((
         StatusCode::CREATED,
         Json(MessageResponse {
@@ -68,71 +226,255 @@ pub async fn create_note(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/notes",
+    tag = "notes",
+    params(NoteQuery, PageQuery),
+    responses(
+        (status = 200, description = "A page of notes", body = PageResponse<services::lecture_notes::NoteReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid filter or paging parameters", body = ErrorResponse),
+    )
+)]
 pub async fn list_notes(
     State(state): State<AppState>,
+    viewer: Viewer,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(query): Query<NoteQuery>,
-) -> Result<Json<Vec<services::lecture_notes::NoteReturn>>, Response> {
+    Query(page_query): Query<PageQuery>,
+) -> Result<Response, Error> {
+    let tag_match = services::tags::parse_tag_match(query.tag_match.as_deref())
+        .ok_or_else(|| Error::bad_request("tag_match must be `any` or `all`"))?;
     let notes = services::lecture_notes::get_notes(
-        &state.pool,
+        &state.storage,
         services::lecture_notes::GetNotesForm {
             id: query.id,
             name: query.name,
             url: query.url,
             position: query.position,
+            node_id: query.node_id,
             section_id: query.section_id,
             subsection_id: query.subsection_id,
-            limit: query.limit,
+            kind: query.kind,
+            course_code: query.course_code,
+            term: query.term,
+            language: query.language,
+            author: query.author,
+            license: query.license,
+            tags: services::tags::split_tag_names(query.tag.as_deref()),
+            tag_match,
+            visibility: viewer.visibility(),
+            page: page_query.to_request()?,
         },
     )
     .await
-    .map_err(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to fetch notes"))?;
-    Ok(Json(notes))
+    .map_err(|err| match err {
+        services::lecture_notes::GetNotesError::PageError(err) => Error::from(err),
+        services::lecture_notes::GetNotesError::UnexpectedError(err) => {
+            err.context("failed to fetch notes")
+        }
+    })?;
+    let last_modified = notes.items.iter().map(|item| item.updated_at).max();
+    Ok(page_response(
+        &uri,
+        &headers,
+        &page_query,
+        notes,
+        last_modified,
+    ))
 }
 
-pub async fn get_note(
-    State(state): State<AppState>,
-    Path(id): Path<u32>,
-) -> Result<Json<services::lecture_notes::NoteReturn>, Response> {
-    let note = services::lecture_notes::get_note(&state.pool, id)
+async fn fetch_note(
+    state: &AppState,
+    id: u32,
+    visibility: Visibility,
+) -> Result<services::lecture_notes::NoteReturn, Error> {
+    services::lecture_notes::get_note(&state.storage, id, visibility)
         .await
         .map_err(|err| match err {
             services::lecture_notes::GetNoteError::NotFoundError => {
-                error_response(StatusCode::NOT_FOUND, "note not found")
+                Error::not_found("note not found")
             }
-            services::lecture_notes::GetNoteError::UnexpectedError => {
-                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to fetch note")
+            services::lecture_notes::GetNoteError::UnexpectedError(err) => {
+                err.context("failed to fetch note")
             }
-        })?;
-    Ok(Json(note))
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes/{id}",
+    tag = "notes",
+    params(("id" = u32, Path, description = "Note id")),
+    responses(
+        (status = 200, description = "The note", body = services::lecture_notes::NoteReturn),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such note, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn get_note(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let note = fetch_note(&state, id, viewer.visibility()).await?;
+    Ok(conditional_json(
+        &headers,
+        &note,
+        Modified::Row(note.updated_at),
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/notes/{id}",
+    tag = "notes",
+    params(
+        ("id" = u32, Path, description = "Note id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the note, instead of `version`"),
+    ),
+    request_body = UpdateNoteRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The note was updated", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such note", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current note is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current note is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
 pub async fn update_note(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-    Json(payload): Json<UpdateNoteRequest>,
-) -> Result<Json<MessageResponse>, Response> {
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<UpdateNoteRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    // A section given without a subsection has always moved the note to the
+    // section itself.
+    let subsection_id = match (payload.subsection_id, payload.section_id) {
+        (Some(subsection_id), _) => Some(Some(subsection_id)),
+        (None, Some(_)) => Some(None),
+        (None, None) => None,
+    };
+    let form = services::lecture_notes::UpdateNoteForm {
+        name: payload.name,
+        description: payload.description,
+        url: payload.url,
+        node_id: payload.node_id,
+        section_id: payload.section_id,
+        subsection_id,
+        position: payload.position,
+        kind: payload.kind.map(Some),
+        course_code: payload.course_code.map(Some),
+        term: payload.term.map(Some),
+        language: payload.language.map(Some),
+        authors: payload.authors,
+        license: payload.license.map(Some),
+        status: payload.status,
+        publish_at: payload.publish_at,
+        slug: payload.slug,
+        ..Default::default()
+    };
+    save_note(&state, id, &headers, payload.version, form).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/notes/{id}",
+    tag = "notes",
+    params(
+        ("id" = u32, Path, description = "Note id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the note, instead of `version`"),
+    ),
+    request_body(content = PatchNoteRequest, content_type = "application/merge-patch+json"),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The note was patched", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such note", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current note is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current note is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, a cleared required field or parents that do not agree, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
+pub async fn patch_note(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<PatchNoteRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let form = services::lecture_notes::UpdateNoteForm {
+        name: payload.name,
+        description: payload.description.map(Option::unwrap_or_default),
+        url: payload.url,
+        node_id: payload.node_id,
+        section_id: payload.section_id,
+        subsection_id: payload.subsection_id,
+        position: payload.position,
+        kind: payload.kind,
+        course_code: payload.course_code,
+        term: payload.term,
+        language: payload.language,
+        authors: payload.authors.map(Option::unwrap_or_default),
+        license: payload.license,
+        status: payload.status,
+        publish_at: payload.publish_at,
+        slug: payload.slug,
+        ..Default::default()
+    };
+    save_note(&state, id, &headers, payload.version, form).await
+}
+
+/// Apply an update or a patch made against the version named by `If-Match` or
+/// by the body.
+async fn save_note(
+    state: &AppState,
+    id: u32,
+    headers: &HeaderMap,
+    version: Option<u32>,
+    form: services::lecture_notes::UpdateNoteForm,
+) -> Result<Json<MessageResponse>, Error> {
+    let current = fetch_note(state, id, Visibility::Everything);
+    let expected = expected_version(headers, version, current).await?;
     services::lecture_notes::update_note(
-        &state.pool,
+        &state.storage,
         id,
         services::lecture_notes::UpdateNoteForm {
-            name: payload.name,
-            description: payload.description,
-            url: payload.url,
-            section_id: payload.section_id,
-            subsection_id: payload.subsection_id,
-            position: payload.position,
+            version: expected.version,
+            ..form
         },
     )
     .await
     .map_err(|err| match err {
         services::lecture_notes::UpdateNoteError::NotFoundError => {
-            error_response(StatusCode::NOT_FOUND, "note not found")
+            Error::not_found("note not found")
+        }
+        services::lecture_notes::UpdateNoteError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::lecture_notes::UpdateNoteError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another note")
+        }
+        services::lecture_notes::UpdateNoteError::ParentMismatchError => {
+            invalid_field("subsection_id", "the subsection is not in the section")
+        }
+        services::lecture_notes::UpdateNoteError::NoSectionError => {
+            invalid_field("subsection_id", "the note has no section to move up to")
         }
         services::lecture_notes::UpdateNoteError::NothingToUpdateError => {
-            error_response(StatusCode::BAD_REQUEST, "nothing to update")
+            Error::new(ErrorCode::NothingToUpdate, "nothing to update")
+        }
+        services::lecture_notes::UpdateNoteError::VersionConflictError(current) => {
+            expected.conflict(&current)
         }
-        services::lecture_notes::UpdateNoteError::UnexpectedError => {
-            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to update note")
+        services::lecture_notes::UpdateNoteError::UnexpectedError(err) => {
+            err.context("failed to update note")
         }
     })?;
     Ok(Json(MessageResponse {
@@ -140,59 +482,156 @@ pub async fn update_note(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/notes/{id}",
+    tag = "notes",
+    params(("id" = u32, Path, description = "Note id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The note was deleted", body = MessageResponse))
+)]
 pub async fn delete_note(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::lecture_notes::delete_note(&state.pool, id)
+) -> Result<Json<MessageResponse>, Error> {
+    services::lecture_notes::delete_note(&state.storage, id)
         .await
-        .map_err(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to delete note"))?;
+        .map_err(
+            |services::lecture_notes::DeleteNoteError::UnexpectedError(err)| {
+                err.context("failed to delete note")
+            },
+        )?;
     Ok(Json(MessageResponse {
         message: "deleted".to_string(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/notes/move",
+    tag = "notes",
+    request_body = MoveNoteRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The two notes swapped positions", body = MessageResponse),
+        (status = 400, description = "The notes hang from different nodes", body = ErrorResponse),
+        (status = 404, description = "No such note", body = ErrorResponse),
+        (status = 409, description = "Stale versions, the current notes are under `current`", body = ErrorResponse),
+        (status = 428, description = "The versions are missing", body = ErrorResponse),
+    )
+)]
 pub async fn move_note(
     State(state): State<AppState>,
-    Json(payload): Json<MoveNoteRequest>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::lecture_notes::move_note(&state.pool, [payload.first_id, payload.second_id])
-        .await
-        .map_err(|err| match err {
-            services::lecture_notes::MoveNoteError::NotFoundError(_, _) => {
-                error_response(StatusCode::NOT_FOUND, "note not found")
-            }
-            services::lecture_notes::MoveNoteError::CantSwapFromDifferentSubsections => {
-                error_response(
-                    StatusCode::BAD_REQUEST,
-                    "cannot swap notes from different subsections",
-                )
-            }
-            services::lecture_notes::MoveNoteError::UnexpectedError => {
-                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to move note")
-            }
-        })?;
+    ValidJson(payload): ValidJson<MoveNoteRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let versions = expected_move_versions(payload.first_version, payload.second_version)
+        .ok_or_else(missing_move_versions)?;
+    services::lecture_notes::move_note(
+        &state.storage,
+        [payload.first_id, payload.second_id],
+        versions,
+    )
+    .await
+    .map_err(|err| match err {
+        services::lecture_notes::MoveNoteError::NotFoundError(_, _) => {
+            Error::not_found("note not found")
+        }
+        services::lecture_notes::MoveNoteError::CantSwapFromDifferentSubsections => {
+            Error::bad_request("cannot swap notes from different subsections")
+        }
+        services::lecture_notes::MoveNoteError::VersionConflictError(current) => {
+            conflict_error(ErrorCode::VersionConflict, &current)
+        }
+        services::lecture_notes::MoveNoteError::UnexpectedError(err) => {
+            err.context("failed to move note")
+        }
+    })?;
     Ok(Json(MessageResponse {
         message: "moved".to_string(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/pages/generate",
+    tag = "pages",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The pages were written, the message counts them", body = MessageResponse),
+    )
+)]
 pub async fn generate_static_pages(
     State(state): State<AppState>,
-) -> Result<Json<MessageResponse>, Response> {
-    let result = services::static_pages::generate_static_pages(&state.pool)
+) -> Result<Json<MessageResponse>, Error> {
+    let result = services::static_pages::generate_static_pages(&state.storage, &state.file_storage)
         .await
-        .map_err(|_| {
-            error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to generate lecture notes pages",
-            )
+        .map_err(|err| {
+            Error::internal(format!("{err:?}")).context("failed to generate lecture notes pages")
         })?;
 
     Ok(Json(MessageResponse {
         message: format!(
-            "generated notes_pages/lecture_notes.html, notes_pages/lecture-notes.js, and {} note pages",
-            result.note_pages
+            "generated notes_pages/lecture_notes.html, notes_pages/lecture-notes.js, {} note pages, {} tag pages, {} files and {} redirects",
+            result.note_pages,
+            result.tag_pages,
+            result.files,
+            result.redirects
         ),
     }))
 }
+
+#[derive(Deserialize, IntoParams)]
+pub struct ImportNotesQuery {
+    /// `csv` or `yaml`, taken from the `Content-Type` when missing.
+    pub format: Option<services::note_import::NoteFileFormat>,
+}
+
+/// Import the notes of a CSV or YAML body, reporting the rows that failed.
+#[utoipa::path(
+    post,
+    path = "/notes/import",
+    tag = "notes",
+    params(ImportNotesQuery),
+    request_body(
+        description = "Rows naming the `section`, `subsection`, `name`, `description` and `url` of each note",
+        content((String = "text/csv"), (String = "application/yaml"))
+    ),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "What was imported, with the rows that failed", body = services::note_import::ImportNotesReport),
+        (status = 400, description = "The file cannot be read", body = ErrorResponse),
+        (status = 415, description = "Neither the `Content-Type` nor `format` names a supported format", body = ErrorResponse),
+    )
+)]
+pub async fn import_notes(
+    State(state): State<AppState>,
+    Query(query): Query<ImportNotesQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<services::note_import::ImportNotesReport>, Error> {
+    let format = query.format.or_else(|| {
+        headers
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(services::note_import::NoteFileFormat::from_content_type)
+    });
+    let Some(format) = format else {
+        return Err(Error::new(
+            ErrorCode::UnsupportedMediaType,
+            "send the notes as text/csv or application/yaml, or name the `format`",
+        ));
+    };
+    let rows = services::note_import::parse_rows(format, &body).map_err(|err| match err {
+        services::note_import::ParseNotesError::InvalidFileError(message) => {
+            Error::bad_request(message)
+        }
+    })?;
+    let report = services::note_import::import_notes(&state.storage, rows)
+        .await
+        .map_err(
+            |services::note_import::ImportNotesError::UnexpectedError(err)| {
+                err.context("failed to import the notes")
+            },
+        )?;
+    Ok(Json(report))
+}
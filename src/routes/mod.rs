@@ -1,19 +1,39 @@
 use axum::http::StatusCode;
+use axum::extract::DefaultBodyLimit;
 use axum::middleware;
-use axum::routing::{get, options, post, put};
+use axum::routing::{get, options};
 use axum::{response::Html, response::IntoResponse, response::Response, Router};
 use serde::Serialize;
-
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouterExt};
+use utoipa_axum::routes;
+
+use crate::error::{Error, ErrorCode};
+
+pub mod attachments;
+pub mod batch;
+pub mod catalog;
+pub mod conditional;
+pub mod error;
+pub mod files;
 pub mod lecture_notes;
+pub mod nodes;
+pub mod openapi;
+pub mod pagination;
+pub mod publishing;
 pub mod responses;
+pub mod search;
 pub mod sections;
 pub mod subsections;
+pub mod tags;
 pub mod users;
+pub mod validation;
 
 const LOGIN_HTML: &str = include_str!("../../web/login.html");
 const REGISTER_HTML: &str = include_str!("../../web/register.html");
 const ADMIN_HTML: &str = include_str!("../../web/admin.html");
 const STYLES_CSS: &str = include_str!("../../web/styles.css");
+const API_JS: &str = include_str!("../../web/api.js");
 const LOGIN_JS: &str = include_str!("../../web/login.js");
 const REGISTER_JS: &str = include_str!("../../web/register.js");
 const ADMIN_JS: &str = include_str!("../../web/admin.js");
@@ -22,22 +42,32 @@ const CREATE_USER_JS: &str = include_str!("../../web/create-user.js");
 
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: sqlx::Pool<sqlx::MySql>,
+    pub storage: crate::repository::Storage,
     pub register_only_for_admin: bool,
+    pub file_storage: std::sync::Arc<crate::services::files::FileStorageConfig>,
+    pub search: std::sync::Arc<crate::services::search::SearchIndex>,
+    /// The index served by `GET /`, dropped by every write of the content through
+    /// the services.
+    pub tree_cache: std::sync::Arc<crate::services::tree::TreeCache<conditional::RenderedJson>>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct RootNote {
     pub id: u32,
     pub name: String,
     pub description: String,
     pub url: String,
     pub position: u32,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    #[serde(flatten)]
+    pub metadata: crate::services::lecture_notes::NoteMetadata,
+    pub tags: Vec<String>,
+    pub attachments: Vec<crate::services::attachments::AttachmentReturn>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct RootSubsection {
     pub id: u32,
     pub title: String,
@@ -46,7 +76,7 @@ pub struct RootSubsection {
     pub notes: Vec<RootNote>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct RootSection {
     pub id: u32,
     pub title: String,
@@ -55,125 +85,108 @@ pub struct RootSection {
     pub notes: Vec<RootNote>,
 }
 
-#[derive(Serialize)]
-pub struct RootResponse {
-    pub sections: Vec<RootSection>,
-}
-
-async fn root_index(
-    axum::extract::State(state): axum::extract::State<AppState>,
-) -> Result<axum::Json<RootResponse>, Response> {
-    let sections = crate::services::sections::get_sections(
-        &state.pool,
-        crate::services::sections::GetSectionsForm {
-            id: None,
-            title: None,
-            position: None,
-            limit: None,
-        },
-    )
-    .await
-    .map_err(|_| responses::error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load"))?;
-
-    let subsections = crate::services::subsections::get_subsections(
-        &state.pool,
-        crate::services::subsections::GetSubsectionsForm {
-            id: None,
-            title: None,
-            position: None,
-            section_id: None,
-            limit: None,
-        },
-    )
-    .await
-    .map_err(|_| responses::error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load"))?;
-
-    let notes = crate::services::lecture_notes::get_notes(
-        &state.pool,
-        crate::services::lecture_notes::GetNotesForm {
-            id: None,
-            name: None,
-            url: None,
-            position: None,
-            section_id: None,
-            subsection_id: None,
-            limit: None,
-        },
-    )
-    .await
-    .map_err(|_| responses::error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to load"))?;
-
-    let mut subsections_by_section: std::collections::HashMap<u32, Vec<RootSubsection>> =
-        std::collections::HashMap::new();
-    let mut notes_by_subsection: std::collections::HashMap<u32, Vec<RootNote>> =
-        std::collections::HashMap::new();
-    let mut notes_by_section: std::collections::HashMap<u32, Vec<RootNote>> =
-        std::collections::HashMap::new();
-
-    for note in notes {
-        let note = RootNote {
+impl From<crate::services::lecture_notes::NoteReturn> for RootNote {
+    fn from(note: crate::services::lecture_notes::NoteReturn) -> Self {
+        Self {
             id: note.id,
             name: note.name,
             description: note.description,
             url: note.url,
             position: note.position,
+            node_id: note.node_id,
             section_id: note.section_id,
             subsection_id: note.subsection_id,
-        };
+            metadata: note.metadata,
+            tags: note.tags,
+            attachments: note.attachments,
+        }
+    }
+}
 
-        if let Some(sub_id) = note.subsection_id {
-            notes_by_subsection.entry(sub_id).or_default().push(note);
-        } else if let Some(sec_id) = note.section_id {
-            notes_by_section.entry(sec_id).or_default().push(note);
+impl From<crate::services::tree::SectionTree> for RootSection {
+    fn from(tree: crate::services::tree::SectionTree) -> Self {
+        Self {
+            id: tree.section.id,
+            title: tree.section.title,
+            position: tree.section.position,
+            subsections: tree
+                .subsections
+                .into_iter()
+                .map(|subsection| RootSubsection {
+                    id: subsection.subsection.id,
+                    title: subsection.subsection.title,
+                    position: subsection.subsection.position,
+                    section_id: subsection.subsection.section_id,
+                    notes: subsection.notes.into_iter().map(RootNote::from).collect(),
+                })
+                .collect(),
+            notes: tree.notes.into_iter().map(RootNote::from).collect(),
         }
     }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RootResponse {
+    pub sections: Vec<RootSection>,
+}
 
-    for subsection in subsections {
-        let mut sub_notes = notes_by_subsection
-            .remove(&subsection.id)
-            .unwrap_or_default();
-        sub_notes.sort_by_key(|note| note.position);
-
-        let item = RootSubsection {
-            id: subsection.id,
-            title: subsection.title,
-            position: subsection.position,
-            section_id: subsection.section_id,
-            notes: sub_notes,
-        };
-
-        subsections_by_section
-            .entry(subsection.section_id)
-            .or_default()
-            .push(item);
+/// The error of a section tree that failed to load, `not_found` naming what is missing.
+pub(crate) fn section_tree_error(
+    err: crate::services::tree::GetSectionTreesError,
+    not_found: &str,
+) -> Error {
+    match err {
+        crate::services::tree::GetSectionTreesError::NotFoundError => Error::not_found(not_found),
+        crate::services::tree::GetSectionTreesError::UnexpectedError(err) => {
+            err.context("failed to load")
+        }
     }
+}
 
-    let mut section_items: Vec<RootSection> = sections
-        .into_iter()
-        .map(|section| {
-            let mut section_subs = subsections_by_section
-                .remove(&section.id)
-                .unwrap_or_default();
-            section_subs.sort_by_key(|sub| sub.position);
-
-            let mut section_notes = notes_by_section.remove(&section.id).unwrap_or_default();
-            section_notes.sort_by_key(|note| note.position);
-
-            RootSection {
-                id: section.id,
-                title: section.title,
-                position: section.position,
-                subsections: section_subs,
-                notes: section_notes,
-            }
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "sections",
+    responses(
+        (status = 200, description = "The sections with their subsections and notes", body = RootResponse),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+    )
+)]
+async fn root_index(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    viewer: publishing::Viewer,
+    headers: axum::http::HeaderMap,
+) -> Result<Response, Error> {
+    let rendered = state
+        .tree_cache
+        .get(&state.storage, viewer.visibility(), |trees| {
+            let last_modified = trees
+                .iter()
+                .map(crate::services::tree::SectionTree::last_updated)
+                .max();
+            let response = RootResponse {
+                sections: trees.into_iter().map(RootSection::from).collect(),
+            };
+            conditional::RenderedJson::new(&response, conditional::Modified::List(last_modified))
         })
-        .collect();
-
-    section_items.sort_by_key(|section| section.position);
+        .await
+        .map_err(|err| section_tree_error(err, "section not found"))?;
+    Ok(rendered.respond(&headers))
+}
 
-    Ok(axum::Json(RootResponse {
-        sections: section_items,
-    }))
+#[utoipa::path(
+    get,
+    path = "/cache/stats",
+    tag = "sections",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "How often `GET /` was answered from its cache", body = crate::services::tree::TreeCacheStats),
+    )
+)]
+async fn tree_cache_stats(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> axum::Json<crate::services::tree::TreeCacheStats> {
+    axum::Json(state.tree_cache.stats())
 }
 
 async fn login_page() -> Html<&'static str> {
@@ -196,6 +209,14 @@ async fn styles_css() -> Response {
     ([(axum::http::header::CONTENT_TYPE, "text/css")], STYLES_CSS).into_response()
 }
 
+async fn api_js() -> Response {
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/javascript")],
+        API_JS,
+    )
+        .into_response()
+}
+
 async fn login_js() -> Response {
     (
         [(axum::http::header::CONTENT_TYPE, "application/javascript")],
@@ -228,19 +249,16 @@ async fn create_user_js() -> Response {
         .into_response()
 }
 
-async fn admin_guard(
-    axum::extract::State(state): axum::extract::State<AppState>,
-    req: axum::http::Request<axum::body::Body>,
-    next: middleware::Next,
-) -> Response {
-    let headers = req.headers();
+/// The session token of a request, from the `Authorization` header or the
+/// `session_token` cookie.
+pub(crate) fn session_token(headers: &axum::http::HeaderMap) -> Option<String> {
     let token = headers
         .get("authorization")
         .and_then(|val| val.to_str().ok())
         .and_then(|val| val.strip_prefix("Bearer "))
         .map(|val| val.to_string());
 
-    let token = match token {
+    match token {
         Some(value) => Some(value),
         None => headers
             .get(axum::http::header::COOKIE)
@@ -257,78 +275,185 @@ async fn admin_guard(
                     }
                 })
             }),
-    };
+    }
+}
 
-    let token = match token {
+async fn admin_guard(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> Response {
+    let token = match session_token(req.headers()) {
         Some(value) => value,
-        None => return responses::error_response(StatusCode::UNAUTHORIZED, "missing token"),
+        None => return Error::new(ErrorCode::Unauthorized, "missing token").into_response(),
     };
 
-    let auth_res = crate::services::users::authenticate_admin_by_token(&state.pool, token).await;
-    if auth_res.is_err() {
-        return responses::error_response(StatusCode::FORBIDDEN, "admin access required");
+    let auth_res = crate::services::users::authenticate_admin_by_token(&state.storage, token).await;
+    match auth_res {
+        Ok(_) => {}
+        Err(crate::services::users::AdminAuthError::NotAdmin) => {
+            return Error::new(ErrorCode::Forbidden, "admin access required").into_response()
+        }
+        Err(crate::services::users::AdminAuthError::UnexpectedError(err)) => {
+            return err.context("failed to check the session").into_response()
+        }
     }
 
     next.run(req).await
 }
 
+/// Mark a response of an unversioned path as deprecated, with the date it stops
+/// being served and the same path under `/api/v1`.
+async fn legacy_alias(
+    req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> Response {
+    let successor = match req.uri().path_and_query().map(|path| path.as_str()) {
+        None | Some("/") => API_V1_PATH.to_string(),
+        Some(path) => format!("{API_V1_PATH}{path}"),
+    };
+    let mut res = next.run(req).await;
+    let headers = res.headers_mut();
+    let values = [
+        ("deprecation", format!("@{LEGACY_DEPRECATED_AT}")),
+        ("sunset", conditional::http_date(LEGACY_SUNSET_AT)),
+        ("link", format!("<{successor}>; rel=\"successor-version\"")),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = axum::http::HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    res
+}
+
+/// Where the version 1 of the JSON API is mounted.
+pub const API_V1_PATH: &str = "/api/v1";
+
+/// When the unversioned paths of the API were deprecated, and when they stop
+/// being served, as Unix timestamps.
+pub const LEGACY_DEPRECATED_AT: i64 = 1_792_368_000;
+pub const LEGACY_SUNSET_AT: i64 = 1_808_092_800;
+
 pub fn router(state: AppState) -> Router {
-    let mut public_routes = Router::new()
-        .route("/", get(root_index))
+    let pages = Router::new()
         .route("/login", get(login_page))
         .route("/register", get(register_page))
         .route("/admin", get(admin_page))
         .route("/create-user.js", get(create_user_js))
         .route("/styles.css", get(styles_css))
+        .route("/api.js", get(api_js))
         .route("/login.js", get(login_js))
         .route("/register.js", get(register_js))
         .route("/admin.js", get(admin_js))
-        .route("/sections", get(sections::list_sections))
-        .route("/sections/{id}", get(sections::get_section))
-        .route("/subsections", get(subsections::list_subsections))
-        .route("/subsections/{id}", get(subsections::get_subsection))
-        .route("/notes", get(lecture_notes::list_notes))
-        .route("/notes/{id}", get(lecture_notes::get_note))
-        .route("/users/login", post(users::login));
-
-    let mut admin_routes = Router::new()
-        .route("/admin/create-user", get(create_user_page))
-        .route("/sections", post(sections::create_section))
-        .route(
-            "/sections/{id}",
-            put(sections::update_section).delete(sections::delete_section),
-        )
-        .route("/sections/move", post(sections::move_section))
-        .route("/subsections", post(subsections::create_subsection))
         .route(
-            "/subsections/{id}",
-            put(subsections::update_subsection).delete(subsections::delete_subsection),
-        )
-        .route("/subsections/move", post(subsections::move_subsection))
-        .route("/notes", post(lecture_notes::create_note))
-        .route(
-            "/notes/{id}",
-            put(lecture_notes::update_note).delete(lecture_notes::delete_note),
+            "/admin/create-user",
+            get(create_user_page)
+                .route_layer(middleware::from_fn_with_state(state.clone(), admin_guard)),
+        );
+
+    // The unversioned paths stay until the sunset, as aliases of version 1. The
+    // clone shares the handlers and the layers of the routes.
+    let (api, spec) = api_v1(&state).split_for_parts();
+    let legacy_routes = api.clone().route_layer(middleware::from_fn(legacy_alias));
+
+    Router::new()
+        .merge(pages)
+        .nest(API_V1_PATH, api)
+        .merge(legacy_routes)
+        .merge(
+            utoipa_swagger_ui::SwaggerUi::new(openapi::DOCS_PATH)
+                .url(openapi::SPEC_PATH, spec),
         )
-        .route("/notes/move", post(lecture_notes::move_note))
-        .route(
-            "/pages/generate",
-            post(lecture_notes::generate_static_pages),
+        .route("/{*path}", options(|| async { StatusCode::NO_CONTENT }))
+        .with_state(state)
+}
+
+/// Version 1 of the JSON API, with the request and response types of the handlers
+/// of this module. A later version gets a router of its own, nested next to this
+/// one, so that both can be served while the clients move over.
+fn api_v1(state: &AppState) -> OpenApiRouter<AppState> {
+    let mut public_routes = OpenApiRouter::with_openapi(openapi::ApiDoc::openapi())
+        .routes(routes!(root_index))
+        .routes(routes!(sections::list_sections))
+        .routes(routes!(sections::get_section))
+        .routes(routes!(sections::get_section_tree))
+        .routes(routes!(sections::list_section_subsections))
+        .routes(routes!(sections::list_section_notes))
+        .routes(routes!(subsections::list_subsections))
+        .routes(routes!(subsections::get_subsection))
+        .routes(routes!(subsections::list_subsection_notes))
+        .routes(routes!(lecture_notes::list_notes))
+        .routes(routes!(lecture_notes::get_note))
+        .routes(routes!(nodes::get_tree))
+        .routes(routes!(nodes::list_nodes))
+        .routes(routes!(nodes::get_node))
+        .routes(routes!(tags::list_tags))
+        .routes(routes!(search::search))
+        .routes(routes!(attachments::list_note_attachments))
+        .routes(routes!(attachments::get_attachment))
+        .routes(routes!(files::download_file))
+        .routes(routes!(users::login));
+
+    let mut admin_routes = OpenApiRouter::new()
+        .routes(routes!(sections::create_section))
+        .routes(routes!(
+            sections::update_section,
+            sections::patch_section,
+            sections::delete_section
+        ))
+        .routes(routes!(sections::move_section))
+        .routes(routes!(subsections::create_subsection))
+        .routes(routes!(
+            subsections::update_subsection,
+            subsections::patch_subsection,
+            subsections::delete_subsection
+        ))
+        .routes(routes!(subsections::move_subsection))
+        .routes(routes!(lecture_notes::create_note))
+        .routes(routes!(
+            lecture_notes::update_note,
+            lecture_notes::patch_note,
+            lecture_notes::delete_note
+        ))
+        .routes(routes!(lecture_notes::move_note))
+        .routes(routes!(lecture_notes::import_notes))
+        .routes(routes!(nodes::create_node))
+        .routes(routes!(nodes::update_node, nodes::delete_node))
+        .routes(routes!(nodes::move_node))
+        .routes(routes!(tags::create_tag))
+        .routes(routes!(tags::rename_tag, tags::delete_tag))
+        .routes(routes!(tags::merge_tags))
+        .routes(routes!(tags::tag_note))
+        .routes(routes!(tags::untag_note))
+        .routes(routes!(attachments::create_attachment))
+        .routes(routes!(
+            attachments::update_attachment,
+            attachments::delete_attachment
+        ))
+        .routes(routes!(attachments::move_attachment))
+        .routes(routes!(files::upload_file).layer(DefaultBodyLimit::disable()))
+        .routes(routes!(files::list_files))
+        .routes(routes!(files::delete_file))
+        .routes(routes!(lecture_notes::generate_static_pages))
+        .routes(routes!(search::rebuild_index))
+        .routes(routes!(catalog::export_catalog))
+        .routes(
+            routes!(catalog::import_catalog)
+                .layer(DefaultBodyLimit::max(catalog::IMPORT_BODY_LIMIT)),
         )
-        .route("/users", get(users::list_users));
+        .routes(routes!(batch::run_batch))
+        .routes(routes!(tree_cache_stats))
+        .routes(routes!(users::list_users));
 
     if state.register_only_for_admin {
-        admin_routes = admin_routes.route("/users/register", post(users::register));
+        admin_routes = admin_routes.routes(routes!(users::register));
     } else {
-        public_routes = public_routes.route("/users/register", post(users::register));
+        public_routes = public_routes.routes(routes!(users::register));
     }
 
     admin_routes =
         admin_routes.route_layer(middleware::from_fn_with_state(state.clone(), admin_guard));
 
-    Router::new()
-        .merge(public_routes)
-        .merge(admin_routes)
-        .route("/{*path}", options(|| async { StatusCode::NO_CONTENT }))
-        .with_state(state)
+    public_routes.merge(admin_routes)
 }
@@ -0,0 +1,370 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::{
+    conditional_json, conflict_error, expected_move_versions, expected_version,
+    missing_move_versions, Modified,
+};
+use crate::routes::publishing::{nullable, Viewer};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{not_blank, ValidJson, MAX_NAME_LENGTH};
+use crate::routes::AppState;
+use crate::services;
+use crate::services::publishing::{Status, Visibility};
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CreateNodeRequest {
+    pub parent_id: Option<u32>,
+    #[validate(length(min = 1, max = 32))]
+    pub kind: String,
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: String,
+    /// `published` when missing.
+    #[serde(default)]
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the node and its subtree stay hidden.
+    pub publish_at: Option<i64>,
+    /// Names the generated pages, derived from the title when missing.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct UpdateNodeRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: Option<String>,
+    #[validate(length(min = 1, max = 32))]
+    pub kind: Option<String>,
+    pub parent_id: Option<u32>,
+    pub position: Option<u32>,
+    pub status: Option<Status>,
+    /// `null` clears the publication time.
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the new one.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the update was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct NodeQuery {
+    pub id: Option<u32>,
+    pub parent_id: Option<u32>,
+    pub kind: Option<String>,
+    pub title: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct MoveNodeRequest {
+    pub first_id: u32,
+    pub second_id: u32,
+    pub first_version: Option<u32>,
+    pub second_version: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedNodeResponse {
+    pub id: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TreeResponse {
+    pub nodes: Vec<services::nodes::TreeNode>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/nodes",
+    tag = "nodes",
+    request_body = CreateNodeRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The node was created last under its parent", body = CreatedNodeResponse),
+        (status = 400, description = "Invalid slug", body = ErrorResponse),
+        (status = 404, description = "No such parent node", body = ErrorResponse),
+        (status = 409, description = "The slug is used by a sibling", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn create_node(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<CreateNodeRequest>,
+) -> Result<(StatusCode, Json<CreatedNodeResponse>), Error> {
+    let id = services::nodes::create_node(
+        &state.storage,
+        services::nodes::CreateNodeForm {
+            parent_id: payload.parent_id,
+            kind: payload.kind,
+            title: payload.title,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        services::nodes::CreateNodeError::ParentNotFoundError => {
+            Error::not_found("parent node not found")
+        }
+        services::nodes::CreateNodeError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::nodes::CreateNodeError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another node")
+        }
+        services::nodes::CreateNodeError::UnexpectedError(err) => {
+            err.context("failed to create node")
+        }
+    })?;
+    Ok((StatusCode::CREATED, Json(CreatedNodeResponse { id })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes",
+    tag = "nodes",
+    params(NodeQuery),
+    responses(
+        (status = 200, description = "The matching nodes", body = [services::nodes::NodeReturn]),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+    )
+)]
+pub async fn list_nodes(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(query): Query<NodeQuery>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let nodes = services::nodes::get_nodes(
+        &state.storage,
+        services::nodes::GetNodesForm {
+            id: query.id,
+            parent_id: query.parent_id,
+            kind: query.kind,
+            title: query.title,
+            limit: query.limit,
+            visibility: viewer.visibility(),
+        },
+    )
+    .await
+    .map_err(|services::nodes::GetNodesError::UnexpectedError(err)| {
+        err.context("failed to fetch nodes")
+    })?;
+    let last_modified = nodes.iter().map(|node| node.updated_at).max();
+    Ok(conditional_json(
+        &headers,
+        &nodes,
+        Modified::List(last_modified),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes/{id}",
+    tag = "nodes",
+    params(("id" = u32, Path, description = "Node id")),
+    responses(
+        (status = 200, description = "The node", body = services::nodes::NodeReturn),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such node, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn get_node(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let node = fetch_node(&state, id, viewer.visibility()).await?;
+    Ok(conditional_json(
+        &headers,
+        &node,
+        Modified::Row(node.updated_at),
+    ))
+}
+
+async fn fetch_node(
+    state: &AppState,
+    id: u32,
+    visibility: Visibility,
+) -> Result<services::nodes::NodeReturn, Error> {
+    services::nodes::get_node(&state.storage, id, visibility)
+        .await
+        .map_err(|err| match err {
+            services::nodes::GetNodeError::NotFoundError => Error::not_found("node not found"),
+            services::nodes::GetNodeError::UnexpectedError(err) => {
+                err.context("failed to fetch node")
+            }
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/tree",
+    tag = "nodes",
+    responses(
+        (status = 200, description = "The root nodes with their descendants and notes", body = TreeResponse),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+    )
+)]
+pub async fn get_tree(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let nodes = services::nodes::get_tree(&state.storage, viewer.visibility())
+        .await
+        .map_err(|services::nodes::GetTreeError::UnexpectedError(err)| {
+            err.context("failed to load")
+        })?;
+    let last_modified = nodes
+        .iter()
+        .map(services::nodes::TreeNode::last_updated)
+        .max();
+    Ok(conditional_json(
+        &headers,
+        &TreeResponse { nodes },
+        Modified::List(last_modified),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/nodes/{id}",
+    tag = "nodes",
+    params(
+        ("id" = u32, Path, description = "Node id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the node, instead of `version`"),
+    ),
+    request_body = UpdateNodeRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The node was updated", body = MessageResponse),
+        (status = 400, description = "Invalid slug, nothing to update, or a move under its own subtree", body = ErrorResponse),
+        (status = 404, description = "No such node", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current node is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current node is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
+pub async fn update_node(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<UpdateNodeRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let current = fetch_node(&state, id, Visibility::Everything);
+    let expected = expected_version(&headers, payload.version, current).await?;
+    services::nodes::update_node(
+        &state.storage,
+        id,
+        services::nodes::UpdateNodeForm {
+            title: payload.title,
+            kind: payload.kind,
+            parent_id: payload.parent_id,
+            position: payload.position,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
+            version: expected.version,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        services::nodes::UpdateNodeError::NotFoundError => Error::not_found("node not found"),
+        services::nodes::UpdateNodeError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::nodes::UpdateNodeError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another node")
+        }
+        services::nodes::UpdateNodeError::NothingToUpdateError => {
+            Error::new(ErrorCode::NothingToUpdate, "nothing to update")
+        }
+        services::nodes::UpdateNodeError::CycleError => {
+            Error::bad_request("a node cannot be moved under itself or one of its descendants")
+        }
+        services::nodes::UpdateNodeError::VersionConflictError(current) => {
+            expected.conflict(&current)
+        }
+        services::nodes::UpdateNodeError::UnexpectedError(err) => {
+            err.context("failed to update node")
+        }
+    })?;
+    Ok(Json(MessageResponse {
+        message: "updated".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/nodes/{id}",
+    tag = "nodes",
+    params(("id" = u32, Path, description = "Node id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The node and its subtree were deleted", body = MessageResponse))
+)]
+pub async fn delete_node(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::nodes::delete_node(&state.storage, id)
+        .await
+        .map_err(|services::nodes::DeleteNodeError::UnexpectedError(err)| {
+            err.context("failed to delete node")
+        })?;
+    Ok(Json(MessageResponse {
+        message: "deleted".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/nodes/move",
+    tag = "nodes",
+    request_body = MoveNodeRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The two nodes swapped positions", body = MessageResponse),
+        (status = 400, description = "The nodes have different parents", body = ErrorResponse),
+        (status = 404, description = "No such node", body = ErrorResponse),
+        (status = 409, description = "Stale versions, the current nodes are under `current`", body = ErrorResponse),
+        (status = 428, description = "The versions are missing", body = ErrorResponse),
+    )
+)]
+pub async fn move_node(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<MoveNodeRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let versions = expected_move_versions(payload.first_version, payload.second_version)
+        .ok_or_else(missing_move_versions)?;
+    services::nodes::move_node(
+        &state.storage,
+        [payload.first_id, payload.second_id],
+        versions,
+    )
+    .await
+    .map_err(|err| match err {
+        services::nodes::MoveNodeError::NotFoundError(_, _) => Error::not_found("node not found"),
+        services::nodes::MoveNodeError::CantSwapFromDifferentParents => {
+            Error::bad_request("cannot swap nodes with different parents")
+        }
+        services::nodes::MoveNodeError::VersionConflictError(current) => {
+            conflict_error(ErrorCode::VersionConflict, &current)
+        }
+        services::nodes::MoveNodeError::UnexpectedError(err) => err.context("failed to move node"),
+    })?;
+    Ok(Json(MessageResponse {
+        message: "moved".to_string(),
+    }))
+}
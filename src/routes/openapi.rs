@@ -0,0 +1,48 @@
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Where the OpenAPI document is served, and the Swagger UI browsing it.
+pub const SPEC_PATH: &str = "/openapi.json";
+pub const DOCS_PATH: &str = "/docs";
+
+/// The OpenAPI document of the API. Its paths, with the schemas of the types the
+/// handlers take and return, are added by `routes::api_v1` as it routes them.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Lecture Notes API",
+        description = "Sections, subsections and notes of lecture notes, with their tags, \
+                       attachments and files. The admin routes take the session token of \
+                       `/users/login` as a bearer token or the `session_token` cookie."
+    ),
+    servers((url = "/api/v1", description = "Version 1; the unversioned paths are deprecated")),
+    modifiers(&BearerToken),
+    tags(
+        (name = "sections", description = "The root level of the content"),
+        (name = "subsections", description = "The second level of the content"),
+        (name = "notes", description = "Lecture notes hanging from a section or subsection"),
+        (name = "nodes", description = "The content tree, of any depth"),
+        (name = "tags", description = "Labels shared by the notes"),
+        (name = "attachments", description = "Links and files attached to the notes"),
+        (name = "files", description = "Uploaded files"),
+        (name = "search", description = "Full-text search over the notes"),
+        (name = "catalog", description = "Export and import of the whole tree"),
+        (name = "batch", description = "Several tree changes in one transaction"),
+        (name = "pages", description = "Static pages generation"),
+        (name = "users", description = "Accounts and sessions"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer` scheme named by the `security` of the admin routes.
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
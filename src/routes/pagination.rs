@@ -0,0 +1,166 @@
+use axum::http::{HeaderMap, Uri};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::{conditional_json, Modified};
+use crate::services::pagination::{Cursor, Page, PageError, PageRequest, Sort};
+
+/// The paging parameters shared by the list endpoints.
+#[derive(Deserialize, IntoParams)]
+pub struct PageQuery {
+    /// `position`, `name`, `id` or `created_at`, prefixed with `-` to sort descending.
+    pub sort: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// `false` answers with the bare array of items, as before the envelope.
+    pub envelope: Option<bool>,
+}
+
+/// Returned when the `sort` or the `cursor` parameter cannot be read.
+pub enum InvalidPageQuery {
+    Sort,
+    Cursor,
+}
+
+impl From<InvalidPageQuery> for Error {
+    fn from(value: InvalidPageQuery) -> Self {
+        let message = match value {
+            InvalidPageQuery::Sort => {
+                "sort must be `position`, `name`, `id` or `created_at`, prefixed with `-` to sort descending"
+            }
+            InvalidPageQuery::Cursor => "invalid cursor",
+        };
+        Error::new(ErrorCode::InvalidPage, message)
+    }
+}
+
+impl PageQuery {
+    pub fn to_request(&self) -> Result<PageRequest, InvalidPageQuery> {
+        let sort = match self.sort.as_deref() {
+            Some(sort) => Some(Sort::parse(sort).ok_or(InvalidPageQuery::Sort)?),
+            None => None,
+        };
+        let cursor = match self.cursor.as_deref() {
+            Some(cursor) => Some(Cursor::decode(cursor).ok_or(InvalidPageQuery::Cursor)?),
+            None => None,
+        };
+        Ok(PageRequest {
+            sort,
+            limit: self.limit,
+            offset: self.offset,
+            cursor,
+        })
+    }
+}
+
+impl From<PageError> for Error {
+    fn from(value: PageError) -> Self {
+        let message = match value {
+            PageError::UnsupportedSortError(field) => {
+                format!("this list cannot be sorted by `{}`", field.as_str())
+            }
+            PageError::CursorWithOffsetError => "cursor and offset cannot be combined".to_string(),
+            PageError::CursorMismatchError => "the cursor was made for another sort".to_string(),
+        };
+        Error::new(ErrorCode::InvalidPage, message)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PageLinks {
+    #[serde(rename = "self")]
+    pub current: String,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PageResponse<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    pub next_cursor: Option<String>,
+    pub links: PageLinks,
+}
+
+/// `uri` with its `cursor` and `offset` parameters replaced by `params`.
+fn link(uri: &Uri, params: &[(&str, String)]) -> String {
+    let kept = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            key != "cursor" && key != "offset"
+        })
+        .map(str::to_string);
+    // Cursors are hex encoded and offsets are numbers, so neither needs escaping.
+    let added = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value));
+    let query = kept.chain(added).collect::<Vec<_>>().join("&");
+    if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), query)
+    }
+}
+
+/// Answer with the page in its envelope, or with the bare items when the
+/// request asked for `envelope=false`.
+/// Offset requests link to the neighbouring offsets, the others to the next cursor.
+/// `last_modified` is the latest `updated_at` of the items, when they have one.
+pub fn page_response<T: Serialize>(
+    uri: &Uri,
+    headers: &HeaderMap,
+    query: &PageQuery,
+    page: Page<T>,
+    last_modified: Option<i64>,
+) -> Response {
+    let modified = Modified::List(last_modified);
+    if query.envelope == Some(false) {
+        return conditional_json(headers, &page.items, modified);
+    }
+    let (next, prev) = match query.offset {
+        Some(offset) => {
+            let step = query.limit.unwrap_or(page.items.len() as u32);
+            let next = page
+                .next_cursor
+                .as_ref()
+                .map(|_| link(uri, &[("offset", (offset + step).to_string())]));
+            let prev = (offset > 0)
+                .then(|| link(uri, &[("offset", offset.saturating_sub(step).to_string())]));
+            (next, prev)
+        }
+        None => {
+            let next = page
+                .next_cursor
+                .as_ref()
+                .map(|cursor| link(uri, &[("cursor", cursor.clone())]));
+            (next, None)
+        }
+    };
+    let current = match uri.query() {
+        Some(params) => format!("{}?{}", uri.path(), params),
+        None => uri.path().to_string(),
+    };
+    let response = PageResponse {
+        items: page.items,
+        total: page.total,
+        limit: query.limit,
+        offset: query.offset,
+        next_cursor: page.next_cursor,
+        links: PageLinks {
+            current,
+            next,
+            prev,
+        },
+    };
+    conditional_json(headers, &response, modified)
+}
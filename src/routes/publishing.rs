@@ -0,0 +1,53 @@
+use std::convert::Infallible;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde::{Deserialize, Deserializer};
+
+use crate::routes::{session_token, AppState};
+use crate::services::publishing::Visibility;
+
+/// Who is reading the content: the admins see the drafts, the archived and the
+/// scheduled content, everyone else only what is published. A missing or invalid
+/// token reads as a public request rather than an error.
+pub struct Viewer {
+    pub is_admin: bool,
+}
+
+impl Viewer {
+    pub fn visibility(&self) -> Visibility {
+        match self.is_admin {
+            true => Visibility::Everything,
+            false => Visibility::Public,
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for Viewer {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let is_admin = match session_token(&parts.headers) {
+            Some(token) => {
+                crate::services::users::authenticate_admin_by_token(&state.storage, token)
+                    .await
+                    .is_ok()
+            }
+            None => false,
+        };
+        Ok(Viewer { is_admin })
+    }
+}
+
+/// Deserialize a field that can be cleared: a missing field is None, while
+/// `null` is `Some(None)`. To use along with `#[serde(default)]`.
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
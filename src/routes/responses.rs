@@ -1,24 +1,23 @@
-use axum::http::StatusCode;
-use axum::response::{IntoResponse, Response};
-use axum::Json;
 use serde::Serialize;
+use serde_json::value::RawValue;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+use crate::error::{ErrorCode, FieldError};
+
+#[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
+    pub code: ErrorCode,
+    /// The message, meant for people rather than for matching.
     pub error: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+    /// The current state of the content, answered to stale updates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub current: Option<Box<RawValue>>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct MessageResponse {
     pub message: String,
 }
-
-pub fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
-    (
-        status,
-        Json(ErrorResponse {
-            error: message.into(),
-        }),
-    )
-        .into_response()
-}
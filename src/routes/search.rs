@@ -0,0 +1,90 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use loggit::warn;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::Error;
+use crate::routes::publishing::Viewer;
+use crate::routes::responses::ErrorResponse;
+use crate::routes::AppState;
+use crate::services::search::{DocumentKind, RebuildIndexError, SearchForm, SearchHit};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+#[derive(Deserialize, IntoParams)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    /// `note`, `section` or `subsection`, to search only one kind of documents.
+    pub kind: Option<DocumentKind>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResponse {
+    pub query: String,
+    pub total: usize,
+    pub results: Vec<SearchHit>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RebuildResponse {
+    pub documents: usize,
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "The results, best first", body = SearchResponse),
+        (status = 400, description = "Empty `q`", body = ErrorResponse),
+    )
+)]
+pub async fn search(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, Error> {
+    let text = query.q.unwrap_or_default();
+    if text.trim().is_empty() {
+        return Err(Error::bad_request("the `q` parameter cannot be empty"));
+    }
+    if let Err(err) = state.search.refresh(&state.storage).await {
+        warn!(
+            "The search index could not be rebuilt, it may be stale: {:?}",
+            err
+        );
+    }
+    let results = state.search.search(&SearchForm {
+        query: text.clone(),
+        kind: query.kind,
+        visibility: viewer.visibility(),
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+        offset: query.offset.unwrap_or(0),
+    });
+    Ok(Json(SearchResponse {
+        query: text,
+        total: results.total,
+        results: results.hits,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/search/rebuild",
+    tag = "search",
+    security(("bearer" = [])),
+    responses((status = 200, description = "The index was rebuilt", body = RebuildResponse))
+)]
+pub async fn rebuild_index(State(state): State<AppState>) -> Result<Json<RebuildResponse>, Error> {
+    // The content may have been written by another process, such as `import-notes`.
+    state.storage.content_changed();
+    let documents = state.search.rebuild(&state.storage).await.map_err(
+        |RebuildIndexError::UnexpectedError(err)| err.context("failed to rebuild the search index"),
+    )?;
+    Ok(Json(RebuildResponse { documents }))
+}
@@ -1,53 +1,135 @@
-use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use axum::extract::{OriginalUri, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::routes::responses::{error_response, MessageResponse};
-use crate::routes::AppState;
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::{
+    conditional_json, conflict_error, expected_move_versions, expected_version,
+    missing_move_versions, Modified,
+};
+use crate::routes::pagination::{page_response, PageQuery, PageResponse};
+use crate::routes::publishing::{nullable, Viewer};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{not_blank, not_null, ValidJson, MAX_NAME_LENGTH};
+use crate::routes::{section_tree_error, AppState, RootSection};
 use crate::services;
+use crate::services::publishing::{Status, Visibility};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct CreateSectionRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
     pub title: String,
+    /// `published` when missing.
+    #[serde(default)]
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the section stays hidden.
+    pub publish_at: Option<i64>,
+    /// Names the generated pages, derived from the title when missing.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct UpdateSectionRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
     pub title: Option<String>,
+    pub status: Option<Status>,
+    /// `null` clears the publication time.
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the new one.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the update was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
 }
 
-#[derive(Deserialize)]
+/// A JSON Merge Patch of a section: a missing field stays as it is, and `null`
+/// only clears `publish_at`, the other fields cannot be cleared.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct PatchSectionRequest {
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub status: Option<Status>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the patch was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
+}
+
+impl From<PatchSectionRequest> for UpdateSectionRequest {
+    fn from(value: PatchSectionRequest) -> Self {
+        Self {
+            title: value.title,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            version: value.version,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
 pub struct SectionQuery {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
-    pub limit: Option<u32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct MoveSectionRequest {
     pub first_id: u32,
     pub second_id: u32,
+    pub first_version: Option<u32>,
+    pub second_version: Option<u32>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/sections",
+    tag = "sections",
+    request_body = CreateSectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The section was created last", body = MessageResponse),
+        (status = 400, description = "Invalid slug", body = ErrorResponse),
+        (status = 409, description = "The slug is used by another section", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
 pub async fn create_section(
     State(state): State<AppState>,
-    Json(payload): Json<CreateSectionRequest>,
-) -> Result<(StatusCode, Json<MessageResponse>), Response> {
+    ValidJson(payload): ValidJson<CreateSectionRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), Error> {
     services::sections::create_section(
-        &state.pool,
+        &state.storage,
         services::sections::CreateSectionForm {
             title: payload.title,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
         },
     )
     .await
-    .map_err(|_| {
-        error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to create section",
-        )
+    .map_err(|err| match err {
+        services::sections::CreateSectionError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::sections::CreateSectionError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another section")
+        }
+        services::sections::CreateSectionError::UnexpectedError(err) => {
+            err.context("failed to create section")
+        }
     })?;
     Ok((
         StatusCode::CREATED,
@@ -57,107 +139,346 @@ pub async fn create_section(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/sections",
+    tag = "sections",
+    params(SectionQuery, PageQuery),
+    responses(
+        (status = 200, description = "A page of sections", body = PageResponse<services::sections::SectionReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid paging parameters", body = ErrorResponse),
+    )
+)]
 pub async fn list_sections(
     State(state): State<AppState>,
+    viewer: Viewer,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(query): Query<SectionQuery>,
-) -> Result<Json<Vec<services::sections::SectionReturn>>, Response> {
+    Query(page_query): Query<PageQuery>,
+) -> Result<Response, Error> {
     let sections = services::sections::get_sections(
-        &state.pool,
+        &state.storage,
         services::sections::GetSectionsForm {
             id: query.id,
             title: query.title,
             position: query.position,
-            limit: query.limit,
+            visibility: viewer.visibility(),
+            page: page_query.to_request()?,
         },
     )
     .await
-    .map_err(|_| {
-        error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to fetch sections",
-        )
+    .map_err(|err| match err {
+        services::sections::GetSectionsError::PageError(err) => Error::from(err),
+        services::sections::GetSectionsError::UnexpectedError(err) => {
+            err.context("failed to fetch sections")
+        }
     })?;
-    Ok(Json(sections))
+    let last_modified = sections.items.iter().map(|item| item.updated_at).max();
+    Ok(page_response(
+        &uri,
+        &headers,
+        &page_query,
+        sections,
+        last_modified,
+    ))
 }
 
-pub async fn get_section(
-    State(state): State<AppState>,
-    Path(id): Path<u32>,
-) -> Result<Json<services::sections::SectionReturn>, Response> {
-    let section = services::sections::get_section(&state.pool, id)
+async fn fetch_section(
+    state: &AppState,
+    id: u32,
+    visibility: Visibility,
+) -> Result<services::sections::SectionReturn, Error> {
+    services::sections::get_section(&state.storage, id, visibility)
         .await
         .map_err(|err| match err {
             services::sections::GetSectionError::NotFoundError => {
-                error_response(StatusCode::NOT_FOUND, "section not found")
+                Error::not_found("section not found")
             }
-            services::sections::GetSectionError::UnexpectedError => {
-                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to fetch section")
+            services::sections::GetSectionError::UnexpectedError(err) => {
+                err.context("failed to fetch section")
             }
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/sections/{id}",
+    tag = "sections",
+    params(("id" = u32, Path, description = "Section id")),
+    responses(
+        (status = 200, description = "The section", body = services::sections::SectionReturn),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such section, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn get_section(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let section = fetch_section(&state, id, viewer.visibility()).await?;
+    Ok(conditional_json(
+        &headers,
+        &section,
+        Modified::Row(section.updated_at),
+    ))
+}
+
+async fn fetch_section_tree(
+    state: &AppState,
+    id: u32,
+    visibility: Visibility,
+) -> Result<services::tree::SectionTree, Error> {
+    services::tree::get_section_tree(&state.storage, id, visibility)
+        .await
+        .map_err(|err| section_tree_error(err, "section not found"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sections/{id}/tree",
+    tag = "sections",
+    params(("id" = u32, Path, description = "Section id")),
+    responses(
+        (status = 200, description = "The section with its subsections and notes, sorted by position", body = RootSection),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such section, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn get_section_tree(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let tree = fetch_section_tree(&state, id, viewer.visibility()).await?;
+    let last_modified = tree.last_updated();
+    Ok(conditional_json(
+        &headers,
+        &RootSection::from(tree),
+        Modified::List(Some(last_modified)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sections/{id}/subsections",
+    tag = "sections",
+    params(("id" = u32, Path, description = "Section id")),
+    responses(
+        (status = 200, description = "The subsections of the section, sorted by position", body = Vec<services::subsections::SubsectionReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such section, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn list_section_subsections(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let tree = fetch_section_tree(&state, id, viewer.visibility()).await?;
+    let subsections = tree
+        .subsections
+        .into_iter()
+        .map(|subsection| subsection.subsection)
+        .collect::<Vec<_>>();
+    let last_modified = subsections.iter().map(|item| item.updated_at).max();
+    Ok(conditional_json(
+        &headers,
+        &subsections,
+        Modified::List(last_modified),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sections/{id}/notes",
+    tag = "sections",
+    params(("id" = u32, Path, description = "Section id")),
+    responses(
+        (status = 200, description = "The notes directly under the section, outside its subsections, sorted by position", body = Vec<services::lecture_notes::NoteReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such section, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn list_section_notes(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    fetch_section(&state, id, viewer.visibility()).await?;
+    let notes = services::tree::get_node_notes(&state.storage, id, viewer.visibility())
+        .await
+        .map_err(|services::nodes::GetTreeError::UnexpectedError(err)| {
+            err.context("failed to fetch notes")
         })?;
-    Ok(Json(section))
+    let last_modified = notes.iter().map(|item| item.updated_at).max();
+    Ok(conditional_json(
+        &headers,
+        &notes,
+        Modified::List(last_modified),
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/sections/{id}",
+    tag = "sections",
+    params(
+        ("id" = u32, Path, description = "Section id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the section, instead of `version`"),
+    ),
+    request_body = UpdateSectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The section was updated", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such section", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current section is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current section is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
 pub async fn update_section(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-    Json(payload): Json<UpdateSectionRequest>,
-) -> Result<Json<MessageResponse>, Response> {
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<UpdateSectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let current = fetch_section(&state, id, Visibility::Everything);
+    let expected = expected_version(&headers, payload.version, current).await?;
     services::sections::update_section(
-        &state.pool,
+        &state.storage,
         id,
         services::sections::UpdateSectionForm {
             title: payload.title,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
+            version: expected.version,
         },
     )
     .await
     .map_err(|err| match err {
         services::sections::UpdateSectionError::NotFoundError => {
-            error_response(StatusCode::NOT_FOUND, "section not found")
+            Error::not_found("section not found")
+        }
+        services::sections::UpdateSectionError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::sections::UpdateSectionError::SlugTakenError => {
+            Error::new(ErrorCode::SlugTaken, "slug already used by another section")
         }
         services::sections::UpdateSectionError::NothingToUpdateError => {
-            error_response(StatusCode::BAD_REQUEST, "nothing to update")
+            Error::new(ErrorCode::NothingToUpdate, "nothing to update")
+        }
+        services::sections::UpdateSectionError::VersionConflictError(current) => {
+            expected.conflict(&current)
+        }
+        services::sections::UpdateSectionError::UnexpectedError(err) => {
+            err.context("failed to update section")
         }
-        services::sections::UpdateSectionError::UnexpectedError => error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to update section",
-        ),
     })?;
     Ok(Json(MessageResponse {
         message: "updated".to_string(),
     }))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/sections/{id}",
+    tag = "sections",
+    params(
+        ("id" = u32, Path, description = "Section id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the section, instead of `version`"),
+    ),
+    request_body(content = PatchSectionRequest, content_type = "application/merge-patch+json"),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The section was patched", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such section", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current section is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current section is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields or a cleared required field, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
+pub async fn patch_section(
+    state: State<AppState>,
+    id: Path<u32>,
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<PatchSectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    update_section(state, id, headers, ValidJson(payload.into())).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sections/{id}",
+    tag = "sections",
+    params(("id" = u32, Path, description = "Section id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The section was deleted", body = MessageResponse))
+)]
 pub async fn delete_section(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::sections::delete_section(&state.pool, id)
+) -> Result<Json<MessageResponse>, Error> {
+    services::sections::delete_section(&state.storage, id)
         .await
-        .map_err(|_| {
-            error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to delete section",
-            )
-        })?;
+        .map_err(
+            |services::sections::DeleteSectionError::UnexpectedError(err)| {
+                err.context("failed to delete section")
+            },
+        )?;
     Ok(Json(MessageResponse {
         message: "deleted".to_string(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/sections/move",
+    tag = "sections",
+    request_body = MoveSectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The two sections swapped positions", body = MessageResponse),
+        (status = 404, description = "No such section", body = ErrorResponse),
+        (status = 409, description = "Stale versions, the current sections are under `current`", body = ErrorResponse),
+        (status = 428, description = "The versions are missing", body = ErrorResponse),
+    )
+)]
 pub async fn move_section(
     State(state): State<AppState>,
-    Json(payload): Json<MoveSectionRequest>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::sections::move_section(&state.pool, [payload.first_id, payload.second_id])
-        .await
-        .map_err(|err| match err {
-            services::sections::MoveSectionError::NotFoundError(_, _) => {
-                error_response(StatusCode::NOT_FOUND, "section not found")
-            }
-            services::sections::MoveSectionError::UnexpectedError => {
-                error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to move section")
-            }
-        })?;
+    ValidJson(payload): ValidJson<MoveSectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let versions = expected_move_versions(payload.first_version, payload.second_version)
+        .ok_or_else(missing_move_versions)?;
+    services::sections::move_section(
+        &state.storage,
+        [payload.first_id, payload.second_id],
+        versions,
+    )
+    .await
+    .map_err(|err| match err {
+        services::sections::MoveSectionError::NotFoundError(_, _) => {
+            Error::not_found("section not found")
+        }
+        services::sections::MoveSectionError::VersionConflictError(current) => {
+            conflict_error(ErrorCode::VersionConflict, &current)
+        }
+        services::sections::MoveSectionError::UnexpectedError(err) => {
+            err.context("failed to move section")
+        }
+    })?;
     Ok(Json(MessageResponse {
         message: "moved".to_string(),
     }))
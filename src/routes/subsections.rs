@@ -1,58 +1,147 @@
-use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use axum::extract::{OriginalUri, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::routes::responses::{error_response, MessageResponse};
+use crate::error::{Error, ErrorCode};
+use crate::routes::conditional::{
+    conditional_json, conflict_error, expected_move_versions, expected_version,
+    missing_move_versions, Modified,
+};
+use crate::routes::pagination::{page_response, PageQuery, PageResponse};
+use crate::routes::publishing::{nullable, Viewer};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{not_blank, not_null, ValidJson, MAX_NAME_LENGTH};
 use crate::routes::AppState;
 use crate::services;
+use crate::services::publishing::{Status, Visibility};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct CreateSubsectionRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
     pub title: String,
     pub section_id: u32,
+    /// `published` when missing.
+    #[serde(default)]
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the subsection stays hidden.
+    pub publish_at: Option<i64>,
+    /// Names the generated pages, derived from the title when missing.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct UpdateSubsectionRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
     pub title: Option<String>,
     pub section_id: Option<u32>,
     pub position: Option<u32>,
+    pub status: Option<Status>,
+    /// `null` clears the publication time.
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the new one.
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the update was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
 }
 
-#[derive(Deserialize)]
+/// A JSON Merge Patch of a subsection: a missing field stays as it is, and `null`
+/// only clears `publish_at`, the other fields cannot be cleared.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct PatchSubsectionRequest {
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub section_id: Option<u32>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub position: Option<u32>,
+    #[serde(default, deserialize_with = "not_null")]
+    pub status: Option<Status>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub publish_at: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "not_null")]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    /// The `version` the patch was made against, when there is no `If-Match` header.
+    pub version: Option<u32>,
+}
+
+impl From<PatchSubsectionRequest> for UpdateSubsectionRequest {
+    fn from(value: PatchSubsectionRequest) -> Self {
+        Self {
+            title: value.title,
+            section_id: value.section_id,
+            position: value.position,
+            status: value.status,
+            publish_at: value.publish_at,
+            slug: value.slug,
+            version: value.version,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
 pub struct SubsectionQuery {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
     pub section_id: Option<u32>,
-    pub limit: Option<u32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct MoveSubsectionRequest {
     pub first_id: u32,
     pub second_id: u32,
+    pub first_version: Option<u32>,
+    pub second_version: Option<u32>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/subsections",
+    tag = "subsections",
+    request_body = CreateSubsectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The subsection was created last in its section", body = MessageResponse),
+        (status = 400, description = "Invalid slug", body = ErrorResponse),
+        (status = 409, description = "The slug is used by another subsection", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
 pub async fn create_subsection(
     State(state): State<AppState>,
-    Json(payload): Json<CreateSubsectionRequest>,
-) -> Result<(StatusCode, Json<MessageResponse>), Response> {
+    ValidJson(payload): ValidJson<CreateSubsectionRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), Error> {
     services::subsections::create_subsection(
-        &state.pool,
+        &state.storage,
         services::subsections::CreateSubsectionForm {
             title: payload.title,
             section_id: payload.section_id,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
         },
     )
     .await
-    .map_err(|_| {
-        error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to create subsection",
-        )
+    .map_err(|err| match err {
+        services::subsections::CreateSubsectionError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
+        }
+        services::subsections::CreateSubsectionError::SlugTakenError => Error::new(
+            ErrorCode::SlugTaken,
+            "slug already used by another subsection",
+        ),
+        services::subsections::CreateSubsectionError::UnexpectedError(err) => {
+            err.context("failed to create subsection")
+        }
     })?;
     Ok((
         StatusCode::CREATED,
@@ -62,118 +151,287 @@ pub async fn create_subsection(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/subsections",
+    tag = "subsections",
+    params(SubsectionQuery, PageQuery),
+    responses(
+        (status = 200, description = "A page of subsections", body = PageResponse<services::subsections::SubsectionReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid paging parameters", body = ErrorResponse),
+    )
+)]
 pub async fn list_subsections(
     State(state): State<AppState>,
+    viewer: Viewer,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(query): Query<SubsectionQuery>,
-) -> Result<Json<Vec<services::subsections::SubsectionReturn>>, Response> {
+    Query(page_query): Query<PageQuery>,
+) -> Result<Response, Error> {
     let subsections = services::subsections::get_subsections(
-        &state.pool,
+        &state.storage,
         services::subsections::GetSubsectionsForm {
             id: query.id,
             title: query.title,
             position: query.position,
             section_id: query.section_id,
-            limit: query.limit,
+            visibility: viewer.visibility(),
+            page: page_query.to_request()?,
         },
     )
     .await
-    .map_err(|_| {
-        error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to fetch subsections",
-        )
+    .map_err(|err| match err {
+        services::subsections::GetSubsectionsError::PageError(err) => Error::from(err),
+        services::subsections::GetSubsectionsError::UnexpectedError(err) => {
+            err.context("failed to fetch subsections")
+        }
     })?;
-    Ok(Json(subsections))
+    let last_modified = subsections.items.iter().map(|item| item.updated_at).max();
+    Ok(page_response(
+        &uri,
+        &headers,
+        &page_query,
+        subsections,
+        last_modified,
+    ))
 }
 
-pub async fn get_subsection(
-    State(state): State<AppState>,
-    Path(id): Path<u32>,
-) -> Result<Json<services::subsections::SubsectionReturn>, Response> {
-    let subsection = services::subsections::get_subsection(&state.pool, id)
+async fn fetch_subsection(
+    state: &AppState,
+    id: u32,
+    visibility: Visibility,
+) -> Result<services::subsections::SubsectionReturn, Error> {
+    services::subsections::get_subsection(&state.storage, id, visibility)
         .await
         .map_err(|err| match err {
             services::subsections::GetSubsectionError::NotFoundError => {
-                error_response(StatusCode::NOT_FOUND, "subsection not found")
+                Error::not_found("subsection not found")
+            }
+            services::subsections::GetSubsectionError::UnexpectedError(err) => {
+                err.context("failed to fetch subsection")
             }
-            services::subsections::GetSubsectionError::UnexpectedError => error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to fetch subsection",
-            ),
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/subsections/{id}",
+    tag = "subsections",
+    params(("id" = u32, Path, description = "Subsection id")),
+    responses(
+        (status = 200, description = "The subsection", body = services::subsections::SubsectionReturn),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such subsection, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn get_subsection(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let subsection = fetch_subsection(&state, id, viewer.visibility()).await?;
+    Ok(conditional_json(
+        &headers,
+        &subsection,
+        Modified::Row(subsection.updated_at),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/subsections/{id}/notes",
+    tag = "subsections",
+    params(("id" = u32, Path, description = "Subsection id")),
+    responses(
+        (status = 200, description = "The notes of the subsection, sorted by position", body = Vec<services::lecture_notes::NoteReturn>),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "No such subsection, or not published yet", body = ErrorResponse),
+    )
+)]
+pub async fn list_subsection_notes(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    fetch_subsection(&state, id, viewer.visibility()).await?;
+    let notes = services::tree::get_node_notes(&state.storage, id, viewer.visibility())
+        .await
+        .map_err(|services::nodes::GetTreeError::UnexpectedError(err)| {
+            err.context("failed to fetch notes")
         })?;
-    Ok(Json(subsection))
+    let last_modified = notes.iter().map(|item| item.updated_at).max();
+    Ok(conditional_json(
+        &headers,
+        &notes,
+        Modified::List(last_modified),
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/subsections/{id}",
+    tag = "subsections",
+    params(
+        ("id" = u32, Path, description = "Subsection id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the subsection, instead of `version`"),
+    ),
+    request_body = UpdateSubsectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The subsection was updated", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such subsection", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current subsection is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current subsection is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
 pub async fn update_subsection(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-    Json(payload): Json<UpdateSubsectionRequest>,
-) -> Result<Json<MessageResponse>, Response> {
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<UpdateSubsectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let current = fetch_subsection(&state, id, Visibility::Everything);
+    let expected = expected_version(&headers, payload.version, current).await?;
     services::subsections::update_subsection(
-        &state.pool,
+        &state.storage,
         id,
         services::subsections::UpdateSubsectionForm {
             title: payload.title,
             section_id: payload.section_id,
             position: payload.position,
+            status: payload.status,
+            publish_at: payload.publish_at,
+            slug: payload.slug,
+            version: expected.version,
         },
     )
     .await
     .map_err(|err| match err {
         services::subsections::UpdateSubsectionError::NotFoundError => {
-            error_response(StatusCode::NOT_FOUND, "subsection not found")
+            Error::not_found("subsection not found")
         }
-        services::subsections::UpdateSubsectionError::NothingToUpdateError => {
-            error_response(StatusCode::BAD_REQUEST, "nothing to update")
+        services::subsections::UpdateSubsectionError::InvalidSlugError => {
+            Error::new(ErrorCode::InvalidSlug, "invalid slug")
         }
-        services::subsections::UpdateSubsectionError::UnexpectedError => error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to update subsection",
+        services::subsections::UpdateSubsectionError::SlugTakenError => Error::new(
+            ErrorCode::SlugTaken,
+            "slug already used by another subsection",
         ),
+        services::subsections::UpdateSubsectionError::NothingToUpdateError => {
+            Error::new(ErrorCode::NothingToUpdate, "nothing to update")
+        }
+        services::subsections::UpdateSubsectionError::VersionConflictError(current) => {
+            expected.conflict(&current)
+        }
+        services::subsections::UpdateSubsectionError::UnexpectedError(err) => {
+            err.context("failed to update subsection")
+        }
     })?;
     Ok(Json(MessageResponse {
         message: "updated".to_string(),
     }))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/subsections/{id}",
+    tag = "subsections",
+    params(
+        ("id" = u32, Path, description = "Subsection id"),
+        ("If-Match" = Option<String>, Header, description = "The ETag of the subsection, instead of `version`"),
+    ),
+    request_body(content = PatchSubsectionRequest, content_type = "application/merge-patch+json"),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The subsection was patched", body = MessageResponse),
+        (status = 400, description = "Invalid slug, or nothing to update", body = ErrorResponse),
+        (status = 404, description = "No such subsection", body = ErrorResponse),
+        (status = 409, description = "Stale `version` or slug taken, the current subsection is under `current`", body = ErrorResponse),
+        (status = 412, description = "Stale `If-Match`, the current subsection is under `current`", body = ErrorResponse),
+        (status = 422, description = "Invalid fields or a cleared required field, listed under `details`", body = ErrorResponse),
+        (status = 428, description = "Neither `If-Match` nor `version` was given", body = ErrorResponse),
+    )
+)]
+pub async fn patch_subsection(
+    state: State<AppState>,
+    id: Path<u32>,
+    headers: HeaderMap,
+    ValidJson(payload): ValidJson<PatchSubsectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    update_subsection(state, id, headers, ValidJson(payload.into())).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/subsections/{id}",
+    tag = "subsections",
+    params(("id" = u32, Path, description = "Subsection id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The subsection was deleted", body = MessageResponse))
+)]
 pub async fn delete_subsection(
     State(state): State<AppState>,
     Path(id): Path<u32>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::subsections::delete_subsection(&state.pool, id)
+) -> Result<Json<MessageResponse>, Error> {
+    services::subsections::delete_subsection(&state.storage, id)
         .await
-        .map_err(|_| {
-            error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to delete subsection",
-            )
-        })?;
+        .map_err(
+            |services::subsections::DeleteSubsectionError::UnexpectedError(err)| {
+                err.context("failed to delete subsection")
+            },
+        )?;
     Ok(Json(MessageResponse {
         message: "deleted".to_string(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/subsections/move",
+    tag = "subsections",
+    request_body = MoveSubsectionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The two subsections swapped positions", body = MessageResponse),
+        (status = 400, description = "The subsections belong to different sections", body = ErrorResponse),
+        (status = 404, description = "No such subsection", body = ErrorResponse),
+        (status = 409, description = "Stale versions, the current subsections are under `current`", body = ErrorResponse),
+        (status = 428, description = "The versions are missing", body = ErrorResponse),
+    )
+)]
 pub async fn move_subsection(
     State(state): State<AppState>,
-    Json(payload): Json<MoveSubsectionRequest>,
-) -> Result<Json<MessageResponse>, Response> {
-    services::subsections::move_subsection(&state.pool, [payload.first_id, payload.second_id])
-        .await
-        .map_err(|err| match err {
-            services::subsections::MoveSubsectionError::NotFoundError(_, _) => {
-                error_response(StatusCode::NOT_FOUND, "subsection not found")
-            }
-            services::subsections::MoveSubsectionError::CantSwapFromDifferentSections => {
-                error_response(
-                    StatusCode::BAD_REQUEST,
-                    "cannot swap subsections from different sections",
-                )
-            }
-            services::subsections::MoveSubsectionError::UnexpectedError => error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to move subsection",
-            ),
-        })?;
+    ValidJson(payload): ValidJson<MoveSubsectionRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let versions = expected_move_versions(payload.first_version, payload.second_version)
+        .ok_or_else(missing_move_versions)?;
+    services::subsections::move_subsection(
+        &state.storage,
+        [payload.first_id, payload.second_id],
+        versions,
+    )
+    .await
+    .map_err(|err| match err {
+        services::subsections::MoveSubsectionError::NotFoundError(_, _) => {
+            Error::not_found("subsection not found")
+        }
+        services::subsections::MoveSubsectionError::CantSwapFromDifferentSections => {
+            Error::bad_request("cannot swap subsections from different sections")
+        }
+        services::subsections::MoveSubsectionError::VersionConflictError(current) => {
+            conflict_error(ErrorCode::VersionConflict, &current)
+        }
+        services::subsections::MoveSubsectionError::UnexpectedError(err) => {
+            err.context("failed to move subsection")
+        }
+    })?;
     Ok(Json(MessageResponse {
         message: "moved".to_string(),
     }))
@@ -0,0 +1,235 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::error::{Error, ErrorCode};
+use crate::routes::responses::{ErrorResponse, MessageResponse};
+use crate::routes::validation::{not_blank, tag_names, ValidJson, MAX_NAME_LENGTH};
+use crate::routes::AppState;
+use crate::services;
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct TagRequest {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub name: String,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct MergeTagsRequest {
+    pub source_id: u32,
+    pub target_id: u32,
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct TagNoteRequest {
+    #[validate(custom(function = tag_names))]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedTagResponse {
+    pub id: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/tags",
+    tag = "tags",
+    responses(
+        (status = 200, description = "The tags with their note counts", body = [services::tags::TagReturn]),
+    )
+)]
+pub async fn list_tags(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<services::tags::TagReturn>>, Error> {
+    let tags = services::tags::get_tags(&state.storage).await.map_err(
+        |services::tags::GetTagsError::UnexpectedError(err)| err.context("failed to fetch tags"),
+    )?;
+    Ok(Json(tags))
+}
+
+#[utoipa::path(
+    post,
+    path = "/tags",
+    tag = "tags",
+    request_body = TagRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The tag was created", body = CreatedTagResponse),
+        (status = 400, description = "Empty name", body = ErrorResponse),
+        (status = 409, description = "The tag already exists", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn create_tag(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<TagRequest>,
+) -> Result<(StatusCode, Json<CreatedTagResponse>), Error> {
+    let id = services::tags::create_tag(&state.storage, payload.name)
+        .await
+        .map_err(|err| match err {
+            services::tags::CreateTagError::EmptyNameError => {
+                Error::bad_request("tag name cannot be empty")
+            }
+            services::tags::CreateTagError::AlreadyExistsError => {
+                Error::new(ErrorCode::AlreadyExists, "tag already exists")
+            }
+            services::tags::CreateTagError::UnexpectedError(err) => {
+                err.context("failed to create tag")
+            }
+        })?;
+    Ok((StatusCode::CREATED, Json(CreatedTagResponse { id })))
+}
+
+#[utoipa::path(
+    put,
+    path = "/tags/{id}",
+    tag = "tags",
+    params(("id" = u32, Path, description = "Tag id")),
+    request_body = TagRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The tag was renamed", body = MessageResponse),
+        (status = 400, description = "Empty name", body = ErrorResponse),
+        (status = 404, description = "No such tag", body = ErrorResponse),
+        (status = 409, description = "Another tag has the name", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn rename_tag(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    ValidJson(payload): ValidJson<TagRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::tags::rename_tag(&state.storage, id, payload.name)
+        .await
+        .map_err(|err| match err {
+            services::tags::RenameTagError::NotFoundError => Error::not_found("tag not found"),
+            services::tags::RenameTagError::EmptyNameError => {
+                Error::bad_request("tag name cannot be empty")
+            }
+            services::tags::RenameTagError::AlreadyExistsError => Error::new(
+                ErrorCode::AlreadyExists,
+                "another tag already has this name, merge the tags instead",
+            ),
+            services::tags::RenameTagError::UnexpectedError(err) => {
+                err.context("failed to rename tag")
+            }
+        })?;
+    Ok(Json(MessageResponse {
+        message: "updated".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/tags/merge",
+    tag = "tags",
+    request_body = MergeTagsRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The notes of the source tag carry the target tag instead", body = MessageResponse),
+        (status = 400, description = "A tag cannot be merged into itself", body = ErrorResponse),
+        (status = 404, description = "No such tag", body = ErrorResponse),
+    )
+)]
+pub async fn merge_tags(
+    State(state): State<AppState>,
+    ValidJson(payload): ValidJson<MergeTagsRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::tags::merge_tags(&state.storage, payload.source_id, payload.target_id)
+        .await
+        .map_err(|err| match err {
+            services::tags::MergeTagsError::NotFoundError(_, _) => {
+                Error::not_found("tag not found")
+            }
+            services::tags::MergeTagsError::SameTagError => {
+                Error::bad_request("cannot merge a tag into itself")
+            }
+            services::tags::MergeTagsError::UnexpectedError(err) => {
+                err.context("failed to merge tags")
+            }
+        })?;
+    Ok(Json(MessageResponse {
+        message: "merged".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tags/{id}",
+    tag = "tags",
+    params(("id" = u32, Path, description = "Tag id")),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The tag was deleted", body = MessageResponse))
+)]
+pub async fn delete_tag(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::tags::delete_tag(&state.storage, id)
+        .await
+        .map_err(|services::tags::DeleteTagError::UnexpectedError(err)| {
+            err.context("failed to delete tag")
+        })?;
+    Ok(Json(MessageResponse {
+        message: "deleted".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/{id}/tags",
+    tag = "tags",
+    params(("id" = u32, Path, description = "Note id")),
+    request_body = TagNoteRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The note carries the tags, the missing ones were created", body = MessageResponse),
+        (status = 404, description = "No such note", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
+pub async fn tag_note(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    ValidJson(payload): ValidJson<TagNoteRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::tags::tag_note(&state.storage, id, payload.tags)
+        .await
+        .map_err(|err| match err {
+            services::tags::TagNoteError::NoteNotFoundError => Error::not_found("note not found"),
+            services::tags::TagNoteError::UnexpectedError(err) => err.context("failed to tag note"),
+        })?;
+    Ok(Json(MessageResponse {
+        message: "tagged".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/notes/{id}/tags/{tag_id}",
+    tag = "tags",
+    params(
+        ("id" = u32, Path, description = "Note id"),
+        ("tag_id" = u32, Path, description = "Tag id"),
+    ),
+    security(("bearer" = [])),
+    responses((status = 200, description = "The tag was taken off the note", body = MessageResponse))
+)]
+pub async fn untag_note(
+    State(state): State<AppState>,
+    Path((id, tag_id)): Path<(u32, u32)>,
+) -> Result<Json<MessageResponse>, Error> {
+    services::tags::untag_note(&state.storage, id, tag_id)
+        .await
+        .map_err(|services::tags::DeleteTagError::UnexpectedError(err)| {
+            err.context("failed to untag note")
+        })?;
+    Ok(Json(MessageResponse {
+        message: "untagged".to_string(),
+    }))
+}
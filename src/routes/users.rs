@@ -1,39 +1,59 @@
-use axum::extract::{Query, State};
-use axum::http::{header, StatusCode};
+use axum::extract::{OriginalUri, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::routes::responses::error_response;
+use crate::error::{Error, ErrorCode};
+use crate::routes::pagination::{page_response, PageQuery, PageResponse};
+use crate::routes::responses::ErrorResponse;
+use crate::routes::validation::{not_blank, ValidJson};
 use crate::routes::AppState;
 use crate::services;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct RegisterRequest {
+    #[validate(custom(function = not_blank))]
     pub username: String,
+    #[validate(custom(function = not_blank))]
     pub password: String,
     pub is_admin: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct LoginRequest {
+    #[validate(custom(function = not_blank))]
     pub username: String,
+    #[validate(custom(function = not_blank))]
     pub password: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct UsersQuery {
     pub id: Option<u32>,
     pub username: Option<String>,
-    pub limit: Option<u32>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/users/register",
+    tag = "users",
+    description = "Needs an admin session when `REGISTER_ONLY_FOR_ADMIN` is set.",
+    request_body = RegisterRequest,
+    security((), ("bearer" = [])),
+    responses(
+        (status = 201, description = "The user was created and signed in, the `session_token` cookie is set", body = services::users::AuthResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
 pub async fn register(
     State(state): State<AppState>,
-    Json(payload): Json<RegisterRequest>,
-) -> Result<Response, Response> {
+    ValidJson(payload): ValidJson<RegisterRequest>,
+) -> Result<Response, Error> {
     let auth = services::users::register(
-        &state.pool,
+        &state.storage,
         services::users::RegisterForm {
             username: payload.username,
             password: payload.password,
@@ -41,7 +61,9 @@ pub async fn register(
         },
     )
     .await
-    .map_err(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to register user"))?;
+    .map_err(|services::users::RegisterError::UnexpectedError(err)| {
+        err.context("failed to register user")
+    })?;
     Ok((
         StatusCode::CREATED,
         [(
@@ -53,12 +75,24 @@ pub async fn register(
         .into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/users/login",
+    tag = "users",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed in, the `session_token` cookie is set", body = services::users::AuthResponse),
+        (status = 401, description = "Wrong password", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, listed under `details`", body = ErrorResponse),
+    )
+)]
 pub async fn login(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>,
-) -> Result<Response, Response> {
+    ValidJson(payload): ValidJson<LoginRequest>,
+) -> Result<Response, Error> {
     let auth = services::users::login(
-        &state.pool,
+        &state.storage,
         services::users::LoginForm {
             username: payload.username,
             password: payload.password,
@@ -66,15 +100,11 @@ pub async fn login(
     )
     .await
     .map_err(|err| match err {
-        services::users::LoginError::NotFoundError => {
-            error_response(StatusCode::NOT_FOUND, "user not found")
-        }
+        services::users::LoginError::NotFoundError => Error::not_found("user not found"),
         services::users::LoginError::InvalidPassword => {
-            error_response(StatusCode::UNAUTHORIZED, "invalid password")
-        }
-        services::users::LoginError::UnexpectedError => {
-            error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to login")
+            Error::new(ErrorCode::Unauthorized, "invalid password")
         }
+        services::users::LoginError::UnexpectedError(err) => err.context("failed to login"),
     })?;
     Ok((
         StatusCode::OK,
@@ -87,19 +117,38 @@ pub async fn login(
         .into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(UsersQuery, PageQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "A page of users", body = PageResponse<services::users::UserReturn>),
+        (status = 400, description = "Invalid paging parameters", body = ErrorResponse),
+    )
+)]
 pub async fn list_users(
     State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(query): Query<UsersQuery>,
-) -> Result<Json<Vec<services::users::UserReturn>>, Response> {
+    Query(page_query): Query<PageQuery>,
+) -> Result<Response, Error> {
     let users = services::users::get_users(
-        &state.pool,
+        &state.storage,
         services::users::GetUsersForm {
             id: query.id,
             username: query.username,
-            limit: query.limit,
+            page: page_query.to_request()?,
         },
     )
     .await
-    .map_err(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "failed to fetch users"))?;
-    Ok(Json(users))
+    .map_err(|err| match err {
+        services::users::GetUsersError::PageError(err) => Error::from(err),
+        services::users::GetUsersError::UnexpectedError(err) => {
+            err.context("failed to fetch users")
+        }
+    })?;
+    Ok(page_response(&uri, &headers, &page_query, users, None))
 }
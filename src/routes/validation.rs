@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use axum::Json;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::error::{Error, ErrorCode, FieldError};
+
+/// The longest title, slug, label or tag name the `VARCHAR(255)` columns hold.
+pub const MAX_NAME_LENGTH: u64 = 255;
+
+/// The URL schemes notes and attachments may link to.
+const ALLOWED_URL_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// A JSON body checked by its `Validate` rules. A body breaking them is refused
+/// with 422 and one entry of `details` per broken rule.
+pub struct ValidJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(body) = Json::<Box<RawValue>>::from_request(req, state)
+            .await
+            .map_err(rejection_error)?;
+        let mut deserializer = serde_json::Deserializer::from_str(body.get());
+        let value: T = serde_path_to_error::deserialize(&mut deserializer).map_err(data_error)?;
+        value.validate().map_err(validation_error)?;
+        Ok(ValidJson(value))
+    }
+}
+
+fn rejection_error(rejection: JsonRejection) -> Error {
+    match rejection {
+        JsonRejection::MissingJsonContentType(err) => {
+            Error::new(ErrorCode::UnsupportedMediaType, err.body_text())
+        }
+        rejection => Error::bad_request(rejection.body_text()),
+    }
+}
+
+/// A body that could not be read as the request type, reported like a broken
+/// rule of the field it failed at: a missing field, or one of the wrong type.
+fn data_error(err: serde_path_to_error::Error<serde_json::Error>) -> Error {
+    let path = err.path().to_string();
+    let message = data_error_message(err.inner());
+    // serde only names a missing field in its message.
+    let missing_field = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.strip_suffix('`'));
+    match (missing_field, path.as_str()) {
+        (Some(field), ".") => invalid_field(field, "is required"),
+        (Some(field), path) => invalid_field(&format!("{path}.{field}"), "is required"),
+        (None, ".") => Error::new(
+            ErrorCode::ValidationFailed,
+            format!("Failed to deserialize the JSON body into the target type: {message}"),
+        ),
+        (None, path) => invalid_field(path, &message),
+    }
+}
+
+/// The message of a serde_json error, without the position it ends with.
+fn data_error_message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    match message.strip_suffix(&position) {
+        Some(message) => message.to_string(),
+        None => message,
+    }
+}
+
+/// One detail per broken rule, ordered by field. Nested structs are flattened
+/// into their parent in the request bodies, so their fields keep their names.
+pub fn validation_error(errors: ValidationErrors) -> Error {
+    let mut details = Vec::new();
+    collect_details(&errors, "", &mut details);
+    details.sort_by(|a, b| a.field.cmp(&b.field));
+    let mut error = Error::new(ErrorCode::ValidationFailed, "the request is invalid");
+    error.details = details;
+    error
+}
+
+fn collect_details(errors: &ValidationErrors, prefix: &str, details: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                for err in list {
+                    details.push(FieldError {
+                        field: field_name(prefix, field, err),
+                        message: message(err),
+                    });
+                }
+            }
+            ValidationErrorsKind::Struct(nested) => collect_details(nested, prefix, details),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_details(nested, &format!("{prefix}{field}[{index}]."), details);
+                }
+            }
+        }
+    }
+}
+
+/// Rules over several fields name the field to show the error next to.
+fn field_name(prefix: &str, field: &str, err: &ValidationError) -> String {
+    match err.params.get("field").and_then(|value| value.as_str()) {
+        Some(named) if field == "__all__" => format!("{prefix}{named}"),
+        _ => format!("{prefix}{field}"),
+    }
+}
+
+fn message(err: &ValidationError) -> String {
+    if let Some(message) = &err.message {
+        return message.to_string();
+    }
+    let param = |name: &str| err.params.get(name).and_then(|value| value.as_u64());
+    match err.code.as_ref() {
+        "length" => match (param("min"), param("max")) {
+            (Some(1), Some(max)) => format!("must be 1 to {max} characters long"),
+            (Some(min), Some(max)) => format!("must be {min} to {max} characters long"),
+            (None, Some(max)) => format!("must be at most {max} characters long"),
+            (Some(min), None) => format!("must be at least {min} characters long"),
+            (None, None) => "has an invalid length".to_string(),
+        },
+        "range" => "is out of range".to_string(),
+        code => format!("is invalid ({code})"),
+    }
+}
+
+/// Deserialize a field a merge patch may change but not clear: a missing field
+/// is None, while `null` is refused. To use along with `#[serde(default)]`.
+pub fn not_null<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match Option::<T>::deserialize(deserializer)? {
+        Some(value) => Ok(Some(value)),
+        None => Err(D::Error::custom("cannot be cleared")),
+    }
+}
+
+/// A required text holding more than whitespace.
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank").with_message(Cow::from("cannot be empty")));
+    }
+    Ok(())
+}
+
+/// An absolute URL whose scheme is allowed, so that no page links to
+/// `javascript:` or to a local file.
+pub fn allowed_url(value: &str) -> Result<(), ValidationError> {
+    let scheme = value
+        .split_once("://")
+        .map(|(scheme, rest)| (scheme.to_ascii_lowercase(), rest));
+    match scheme {
+        Some((scheme, rest))
+            if ALLOWED_URL_SCHEMES.contains(&scheme.as_str())
+                && !rest.is_empty()
+                && !rest.starts_with('/')
+                && !value.chars().any(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => {
+            Err(ValidationError::new("url").with_message(Cow::from("must be an http or https URL")))
+        }
+    }
+}
+
+/// Tag names, each holding more than whitespace and fitting their column.
+pub fn tag_names(names: &[String]) -> Result<(), ValidationError> {
+    let valid = names.iter().all(|name| {
+        let name = name.trim();
+        !name.is_empty() && name.chars().count() as u64 <= MAX_NAME_LENGTH
+    });
+    if valid {
+        return Ok(());
+    }
+    Err(
+        ValidationError::new("tag_names").with_message(Cow::from(format!(
+            "every tag must be 1 to {MAX_NAME_LENGTH} characters long"
+        ))),
+    )
+}
+
+/// A rule over several fields, reported next to `field`.
+pub fn field_error(
+    field: &'static str,
+    code: &'static str,
+    message: &'static str,
+) -> ValidationError {
+    let mut err = ValidationError::new(code).with_message(Cow::from(message));
+    err.add_param(Cow::from("field"), &field);
+    err
+}
+
+/// A rule only the services can check, such as one needing the database.
+pub fn invalid_field(field: &str, message: &str) -> Error {
+    let mut error = Error::new(ErrorCode::ValidationFailed, "the request is invalid");
+    error.details = vec![FieldError {
+        field: field.to_string(),
+        message: message.to_string(),
+    }];
+    error
+}
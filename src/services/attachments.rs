@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use crate::db;
+use crate::error::Error;
+use crate::repository::Storage;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Either `url` or `file_id` must be given. An attachment to an uploaded file
+/// takes its URL, MIME type and size from the file.
+pub struct CreateAttachmentForm {
+    pub label: String,
+    pub url: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub file_id: Option<u32>,
+}
+
+pub struct UpdateAttachmentForm {
+    pub label: Option<String>,
+    pub url: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct AttachmentReturn {
+    pub id: u32,
+    pub note_id: u32,
+    pub label: String,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub file_id: Option<u32>,
+    pub position: u32,
+}
+
+impl From<db::attachments::AttachmentFromDb> for AttachmentReturn {
+    fn from(value: db::attachments::AttachmentFromDb) -> Self {
+        Self {
+            id: value.id,
+            note_id: value.note_id,
+            label: value.label,
+            url: value.url,
+            mime_type: value.mime_type,
+            size: value.size,
+            file_id: value.file_id,
+            position: value.position,
+        }
+    }
+}
+
+/// Trim the MIME type, treating an empty value as unknown.
+fn clean_mime_type(mime_type: Option<String>) -> Option<String> {
+    mime_type
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty())
+}
+
+#[derive(Debug)]
+pub enum GetAttachmentsError {
+    UnexpectedError(Error),
+}
+
+pub async fn get_attachments_of_note(
+    storage: &Storage,
+    note_id: u32,
+) -> Result<Vec<AttachmentReturn>, GetAttachmentsError> {
+    storage
+        .attachments
+        .get_attachments(db::attachments::GetAttachmentsForm {
+            note_id: Some(note_id),
+            ..Default::default()
+        })
+        .await
+        .map(|list| list.into_iter().map(AttachmentReturn::from).collect())
+        .map_err(
+            |db::attachments::GetAttachmentsError::UnexpectedError(err)| {
+                GetAttachmentsError::UnexpectedError(err)
+            },
+        )
+}
+
+/// Attachments of the given notes, keyed by note id and ordered by position.
+pub async fn get_attachments_by_note(
+    storage: &Storage,
+    note_ids: &[u32],
+) -> Result<HashMap<u32, Vec<AttachmentReturn>>, GetAttachmentsError> {
+    let attachments = storage
+        .attachments
+        .get_attachments(db::attachments::GetAttachmentsForm {
+            note_ids: Some(note_ids.to_vec()),
+            ..Default::default()
+        })
+        .await
+        .map_err(
+            |db::attachments::GetAttachmentsError::UnexpectedError(err)| {
+                GetAttachmentsError::UnexpectedError(err)
+            },
+        )?;
+    let mut by_note: HashMap<u32, Vec<AttachmentReturn>> = HashMap::new();
+    for attachment in attachments {
+        by_note
+            .entry(attachment.note_id)
+            .or_default()
+            .push(AttachmentReturn::from(attachment));
+    }
+    Ok(by_note)
+}
+
+#[derive(Debug)]
+pub enum GetAttachmentError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+pub async fn get_attachment(
+    storage: &Storage,
+    id: u32,
+) -> Result<AttachmentReturn, GetAttachmentError> {
+    storage
+        .attachments
+        .get_attachment(db::attachments::GetAttachmentsForm {
+            id: Some(id),
+            ..Default::default()
+        })
+        .await
+        .map(AttachmentReturn::from)
+        .map_err(|err| match err {
+            db::attachments::GetAttachmentError::NotFoundError => GetAttachmentError::NotFoundError,
+            db::attachments::GetAttachmentError::UnexpectedError(err) => {
+                GetAttachmentError::UnexpectedError(err)
+            }
+        })
+}
+
+#[derive(Debug)]
+pub enum CreateAttachmentError {
+    UnexpectedError(Error),
+    NoteNotFoundError,
+    FileNotFoundError,
+    EmptyLabelError,
+    EmptyUrlError,
+}
+
+pub async fn create_attachment(
+    storage: &Storage,
+    note_id: u32,
+    form: CreateAttachmentForm,
+) -> Result<u32, CreateAttachmentError> {
+    let mut label = form.label.trim().to_string();
+    let (url, mime_type, size) = match form.file_id {
+        Some(file_id) => {
+            let file = crate::services::files::get_file(storage, file_id)
+                .await
+                .map_err(|err| match err {
+                    crate::services::files::GetFileError::NotFoundError => {
+                        CreateAttachmentError::FileNotFoundError
+                    }
+                    crate::services::files::GetFileError::UnexpectedError(err) => {
+                        CreateAttachmentError::UnexpectedError(err)
+                    }
+                })?;
+            if label.is_empty() {
+                label = file.original_name;
+            }
+            (file.url, Some(file.mime_type), Some(file.size))
+        }
+        None => {
+            let url = form.url.unwrap_or_default().trim().to_string();
+            if url.is_empty() {
+                return Err(CreateAttachmentError::EmptyUrlError);
+            }
+            (url, clean_mime_type(form.mime_type), form.size)
+        }
+    };
+    if label.is_empty() {
+        return Err(CreateAttachmentError::EmptyLabelError);
+    }
+    let res = storage
+        .attachments
+        .create_attachment(db::attachments::CreateAttachmentForm {
+            note_id,
+            label,
+            url,
+            mime_type,
+            size,
+            file_id: form.file_id,
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::attachments::CreateAttachmentError::NoteNotFoundError => {
+            CreateAttachmentError::NoteNotFoundError
+        }
+        db::attachments::CreateAttachmentError::UnexpectedError(err) => {
+            CreateAttachmentError::UnexpectedError(err)
+        }
+    })
+}
+
+#[derive(Debug)]
+pub enum UpdateAttachmentError {
+    UnexpectedError(Error),
+    NotFoundError,
+    NothingToUpdateError,
+    EmptyLabelError,
+    EmptyUrlError,
+}
+
+pub async fn update_attachment(
+    storage: &Storage,
+    id: u32,
+    form: UpdateAttachmentForm,
+) -> Result<(), UpdateAttachmentError> {
+    let label = form.label.map(|label| label.trim().to_string());
+    if label.as_deref() == Some("") {
+        return Err(UpdateAttachmentError::EmptyLabelError);
+    }
+    let url = form.url.map(|url| url.trim().to_string());
+    if url.as_deref() == Some("") {
+        return Err(UpdateAttachmentError::EmptyUrlError);
+    }
+    let res = storage
+        .attachments
+        .update_attachments(
+            db::attachments::UpdateAttachmentForm {
+                label,
+                url,
+                mime_type: form
+                    .mime_type
+                    .map(|value| value.trim().to_ascii_lowercase()),
+                size: form.size,
+                position: None,
+            },
+            db::attachments::GetAttachmentsForm {
+                id: Some(id),
+                ..Default::default()
+            },
+        )
+        .await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::attachments::UpdateAttachmentsError::NotFoundError => {
+            UpdateAttachmentError::NotFoundError
+        }
+        db::attachments::UpdateAttachmentsError::NothingToUpdateError => {
+            UpdateAttachmentError::NothingToUpdateError
+        }
+        db::attachments::UpdateAttachmentsError::UnexpectedError(err) => {
+            UpdateAttachmentError::UnexpectedError(err)
+        }
+    })
+}
+
+#[derive(Debug)]
+pub enum DeleteAttachmentError {
+    UnexpectedError(Error),
+}
+
+pub async fn delete_attachment(storage: &Storage, id: u32) -> Result<(), DeleteAttachmentError> {
+    let res = storage
+        .attachments
+        .delete_attachment(db::attachments::GetAttachmentsForm {
+            id: Some(id),
+            ..Default::default()
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(
+        |db::attachments::DeleteAttachmentsError::UnexpectedError(err)| {
+            DeleteAttachmentError::UnexpectedError(err)
+        },
+    )
+}
+
+#[derive(Debug)]
+pub enum MoveAttachmentError {
+    UnexpectedError(Error),
+    NotFoundError(Option<u32>, Option<u32>),
+    CantSwapFromDifferentNotes,
+}
+
+pub async fn move_attachment(storage: &Storage, ids: [u32; 2]) -> Result<(), MoveAttachmentError> {
+    let res = storage.attachments.swap_attachments(ids).await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::attachments::SwapAttachmentsError::NotFoundError(tuple) => {
+            MoveAttachmentError::NotFoundError(tuple.0, tuple.1)
+        }
+        db::attachments::SwapAttachmentsError::CantSwapFromDifferentNotes => {
+            MoveAttachmentError::CantSwapFromDifferentNotes
+        }
+        db::attachments::SwapAttachmentsError::UnexpectedError(err) => {
+            MoveAttachmentError::UnexpectedError(err)
+        }
+    })
+}
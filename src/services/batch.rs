@@ -0,0 +1,1005 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::db;
+use crate::db::catalog::{CatalogChange, ImportedNode, ImportedNote, NodeRef, NoteRef};
+use crate::db::slugs::SlugError;
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::catalog::{
+    export_catalog, stored_node, stored_note, CatalogNode, ExportCatalogError,
+};
+use crate::services::lecture_notes::NoteKind;
+use crate::services::publishing::Status;
+
+/// What an operation of a batch acts on.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchEntity {
+    /// A root node.
+    Section,
+    /// A node right below a section.
+    Subsection,
+    /// Any node of the tree.
+    Node,
+    Note,
+}
+
+/// A row named by an operation: a stored one by its id, or one created earlier
+/// in the same batch by the `ref` its `create` gave it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(untagged)]
+pub enum BatchId {
+    Id(u32),
+    Ref(String),
+}
+
+/// What an operation does. The versions are checked against the rows as the
+/// earlier operations of the batch left them, a row created by the batch
+/// starting at version 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchAction {
+    /// `reference` names the created row for the later operations.
+    Create {
+        reference: Option<String>,
+    },
+    Update {
+        id: BatchId,
+        version: u32,
+    },
+    /// A node must have no children nor notes left.
+    Delete {
+        id: BatchId,
+        version: u32,
+    },
+    /// Swap the positions of two siblings.
+    Move {
+        ids: [BatchId; 2],
+        versions: [u32; 2],
+    },
+}
+
+/// The fields set by a `create` or an `update`, None leaving them as they are.
+/// The sections and the subsections take a `title`, the nodes a `kind` too, and
+/// the notes a `name` and a `url`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchFields {
+    pub title: Option<String>,
+    /// Any kind for the nodes, a `NoteKind` for the notes. `Some(None)` clears
+    /// the kind of a note.
+    pub kind: Option<Option<String>>,
+    pub parent_id: Option<BatchId>,
+    pub section_id: Option<BatchId>,
+    /// `Some(None)` moves a note up to its section.
+    pub subsection_id: Option<Option<BatchId>>,
+    pub node_id: Option<BatchId>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub course_code: Option<Option<String>>,
+    pub term: Option<Option<String>>,
+    pub language: Option<Option<String>>,
+    pub authors: Option<Vec<String>>,
+    pub license: Option<Option<String>>,
+    /// Replace the tags of a note.
+    pub tags: Option<Vec<String>>,
+    pub status: Option<Status>,
+    pub publish_at: Option<Option<i64>>,
+    pub slug: Option<String>,
+}
+
+impl BatchFields {
+    /// The names of the fields set.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            ("title", self.title.is_some()),
+            ("kind", self.kind.is_some()),
+            ("parent_id", self.parent_id.is_some()),
+            ("section_id", self.section_id.is_some()),
+            ("subsection_id", self.subsection_id.is_some()),
+            ("node_id", self.node_id.is_some()),
+            ("name", self.name.is_some()),
+            ("description", self.description.is_some()),
+            ("url", self.url.is_some()),
+            ("course_code", self.course_code.is_some()),
+            ("term", self.term.is_some()),
+            ("language", self.language.is_some()),
+            ("authors", self.authors.is_some()),
+            ("license", self.license.is_some()),
+            ("tags", self.tags.is_some()),
+            ("status", self.status.is_some()),
+            ("publish_at", self.publish_at.is_some()),
+            ("slug", self.slug.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// The fields a `create` or an `update` of `entity` may set.
+fn allowed_fields(entity: BatchEntity) -> &'static [&'static str] {
+    match entity {
+        BatchEntity::Section => &["title", "status", "publish_at", "slug"],
+        BatchEntity::Subsection => &["title", "section_id", "status", "publish_at", "slug"],
+        BatchEntity::Node => &["title", "kind", "parent_id", "status", "publish_at", "slug"],
+        BatchEntity::Note => &[
+            "name",
+            "description",
+            "url",
+            "kind",
+            "node_id",
+            "section_id",
+            "subsection_id",
+            "course_code",
+            "term",
+            "language",
+            "authors",
+            "license",
+            "tags",
+            "status",
+            "publish_at",
+            "slug",
+        ],
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchOperation {
+    pub entity: BatchEntity,
+    pub action: BatchAction,
+    pub fields: BatchFields,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Created,
+    Updated,
+    Deleted,
+    Moved,
+}
+
+/// What an operation of a batch did.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct BatchResult {
+    /// The position of the operation in the batch, from 0.
+    pub index: usize,
+    pub status: BatchStatus,
+    pub entity: BatchEntity,
+    /// The row written, the first one for a move.
+    pub id: u32,
+    /// The second row of a move.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub second_id: Option<u32>,
+    /// The `ref` given to a created row.
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+/// Why an operation cannot be made. The fields named are the ones of the
+/// operation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchOperationError {
+    /// The row named by the field does not exist, is not of the expected entity,
+    /// or was deleted by an earlier operation.
+    NotFoundError(&'static str),
+    /// The `ref` already names a row created by an earlier operation.
+    DuplicateReferenceError,
+    MissingFieldError(&'static str),
+    /// The field does not apply to the entity, or to the action.
+    UnexpectedFieldError(&'static str),
+    /// Not one of the note kinds.
+    InvalidKindError,
+    InvalidSlugError,
+    SlugTakenError,
+    /// The subsection is not one of the section given along.
+    ParentMismatchError,
+    /// `subsection_id: null` on a note outside of any section.
+    NoSectionError,
+    /// A node moved under itself or one of its descendants.
+    CycleError,
+    /// The node still has children or notes.
+    InUseError,
+    /// The rows of a move have different parents.
+    CantSwapError,
+    /// The row named along with the field moved past the version it holds.
+    VersionConflictError(&'static str),
+    NothingToUpdateError,
+}
+
+impl From<SlugError> for BatchOperationError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => BatchOperationError::InvalidSlugError,
+            SlugError::SlugTakenError => BatchOperationError::SlugTakenError,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RunBatchError {
+    UnexpectedError(Error),
+    /// The operation at the index cannot be made, so none is.
+    OperationError(usize, BatchOperationError),
+}
+
+/// Make the operations in order, in one transaction: either all of them are
+/// made, or none. Each one sees the rows as the earlier ones left them, and can
+/// name the rows they created by their `ref`.
+pub async fn run_batch(
+    storage: &Storage,
+    operations: &[BatchOperation],
+) -> Result<Vec<BatchResult>, RunBatchError> {
+    let current = export_catalog(storage)
+        .await
+        .map_err(|ExportCatalogError::UnexpectedError(err)| RunBatchError::UnexpectedError(err))?;
+    let plan = plan_batch(&current.sections, operations)?;
+    let res = storage.catalog.apply_catalog_changes(&plan.changes).await;
+    storage.content_changed();
+    let written = res.map_err(|err| match err {
+        db::catalog::ApplyCatalogChangesError::UnexpectedError(err) => {
+            RunBatchError::UnexpectedError(err)
+        }
+        db::catalog::ApplyCatalogChangesError::VersionConflictError(change) => {
+            plan.conflict(change)
+        }
+    })?;
+    Ok(plan.results(&written))
+}
+
+/// The changes making a batch, along with what each operation does.
+#[derive(Debug)]
+pub struct BatchPlan {
+    pub changes: Vec<CatalogChange>,
+    results: Vec<PlannedResult>,
+}
+
+#[derive(Debug)]
+struct PlannedResult {
+    index: usize,
+    status: BatchStatus,
+    entity: BatchEntity,
+    /// The change writing the row, and the other row of a move.
+    change: usize,
+    second_change: Option<usize>,
+    reference: Option<String>,
+}
+
+impl BatchPlan {
+    /// The error of the operation making the change at `change`, whose row was
+    /// changed by someone else since the batch was planned.
+    fn conflict(&self, change: usize) -> RunBatchError {
+        let result = self
+            .results
+            .iter()
+            .find(|result| result.change.max(result.second_change.unwrap_or(0)) >= change);
+        match result {
+            Some(result) => {
+                let field = match (result.status, result.second_change) {
+                    (BatchStatus::Moved, Some(second)) if second == change => "second_version",
+                    (BatchStatus::Moved, _) => "first_version",
+                    _ => "version",
+                };
+                RunBatchError::OperationError(
+                    result.index,
+                    BatchOperationError::VersionConflictError(field),
+                )
+            }
+            None => RunBatchError::UnexpectedError(Error::internal(
+                "a change of the batch belongs to no operation",
+            )),
+        }
+    }
+
+    /// The results of the operations, given the id of the row written by each
+    /// change.
+    pub fn results(&self, written: &[u32]) -> Vec<BatchResult> {
+        let id = |change: usize| written.get(change).copied().unwrap_or_default();
+        self.results
+            .iter()
+            .map(|result| BatchResult {
+                index: result.index,
+                status: result.status,
+                entity: result.entity,
+                id: id(result.change),
+                second_id: result.second_change.map(id),
+                reference: result.reference.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Plan the operations against the `current` tree, read from the database.
+pub fn plan_batch(
+    current: &[CatalogNode],
+    operations: &[BatchOperation],
+) -> Result<BatchPlan, RunBatchError> {
+    let mut planner = BatchPlanner::default();
+    planner.load(None, current);
+    for (index, operation) in operations.iter().enumerate() {
+        let result = planner
+            .plan(index, operation)
+            .map_err(|err| RunBatchError::OperationError(index, err))?;
+        planner.results.push(result);
+    }
+    Ok(BatchPlan {
+        changes: planner.changes,
+        results: planner.results,
+    })
+}
+
+/// A row as the operations planned so far leave it.
+struct Planned<T> {
+    row: T,
+    version: u32,
+    /// A stored row not written yet, which the database keeps away from its
+    /// position until its first update.
+    parked: bool,
+}
+
+impl<T> Planned<T> {
+    fn stored(row: T, version: u32) -> Self {
+        Self {
+            row,
+            version,
+            parked: true,
+        }
+    }
+
+    fn created(row: T) -> Self {
+        Self {
+            row,
+            version: 1,
+            parked: false,
+        }
+    }
+
+    fn write(&mut self, row: T) {
+        self.row = row;
+        self.version += 1;
+        self.parked = false;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Reference {
+    Node(NodeRef),
+    Note(NoteRef),
+}
+
+#[derive(Default)]
+struct BatchPlanner {
+    nodes: HashMap<NodeRef, Planned<ImportedNode>>,
+    notes: HashMap<NoteRef, Planned<ImportedNote>>,
+    references: HashMap<String, Reference>,
+    changes: Vec<CatalogChange>,
+    created_nodes: usize,
+    created_notes: usize,
+    results: Vec<PlannedResult>,
+}
+
+fn check_version(
+    current: u32,
+    expected: u32,
+    field: &'static str,
+) -> Result<(), BatchOperationError> {
+    if current != expected {
+        return Err(BatchOperationError::VersionConflictError(field));
+    }
+    Ok(())
+}
+
+fn note_kind(kind: Option<String>) -> Result<Option<String>, BatchOperationError> {
+    kind.map(|kind| {
+        NoteKind::from_db(&kind)
+            .map(|kind| kind.as_str().to_string())
+            .ok_or(BatchOperationError::InvalidKindError)
+    })
+    .transpose()
+}
+
+fn note_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags.iter().map(|tag| tag.trim().to_string()).collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+impl BatchPlanner {
+    fn load(&mut self, parent: Option<NodeRef>, nodes: &[CatalogNode]) {
+        for node in nodes {
+            let Some(id) = node.id else {
+                continue;
+            };
+            let key = NodeRef::Stored(id);
+            self.nodes.insert(
+                key,
+                Planned::stored(stored_node(node, parent), node.version.unwrap_or_default()),
+            );
+            for note in &node.notes {
+                if let Some(note_id) = note.id {
+                    self.notes.insert(
+                        NoteRef::Stored(note_id),
+                        Planned::stored(stored_note(note, key), note.version.unwrap_or_default()),
+                    );
+                }
+            }
+            self.load(Some(key), &node.children);
+        }
+    }
+
+    fn push(&mut self, change: CatalogChange) -> usize {
+        self.changes.push(change);
+        self.changes.len() - 1
+    }
+
+    fn plan(
+        &mut self,
+        index: usize,
+        operation: &BatchOperation,
+    ) -> Result<PlannedResult, BatchOperationError> {
+        let entity = operation.entity;
+        let fields = &operation.fields;
+        let allowed = match operation.action {
+            BatchAction::Create { .. } | BatchAction::Update { .. } => allowed_fields(entity),
+            BatchAction::Delete { .. } | BatchAction::Move { .. } => &[],
+        };
+        if let Some(field) = fields
+            .names()
+            .into_iter()
+            .find(|name| !allowed.contains(name))
+        {
+            return Err(BatchOperationError::UnexpectedFieldError(field));
+        }
+        let result = |status, change, second_change, reference| PlannedResult {
+            index,
+            status,
+            entity,
+            change,
+            second_change,
+            reference,
+        };
+
+        Ok(match &operation.action {
+            BatchAction::Create { reference } => {
+                if reference
+                    .as_ref()
+                    .is_some_and(|reference| self.references.contains_key(reference))
+                {
+                    return Err(BatchOperationError::DuplicateReferenceError);
+                }
+                let (change, created) = match entity {
+                    BatchEntity::Note => self.create_note(fields)?,
+                    _ => self.create_node(entity, fields)?,
+                };
+                if let Some(reference) = reference {
+                    self.references.insert(reference.clone(), created);
+                }
+                result(BatchStatus::Created, change, None, reference.clone())
+            }
+            BatchAction::Update { id, version } => {
+                if fields.names().is_empty() {
+                    return Err(BatchOperationError::NothingToUpdateError);
+                }
+                let change = match entity {
+                    BatchEntity::Note => {
+                        let key = self.note_key(id, "id")?;
+                        check_version(self.notes[&key].version, *version, "version")?;
+                        self.update_note(key, fields)?
+                    }
+                    _ => {
+                        let key = self.entity_node(entity, id, "id")?;
+                        check_version(self.nodes[&key].version, *version, "version")?;
+                        self.update_node(entity, key, fields)?
+                    }
+                };
+                result(BatchStatus::Updated, change, None, None)
+            }
+            BatchAction::Delete { id, version } => {
+                let change = match entity {
+                    BatchEntity::Note => {
+                        let key = self.note_key(id, "id")?;
+                        check_version(self.notes[&key].version, *version, "version")?;
+                        self.notes.remove(&key);
+                        self.push(CatalogChange::DeleteNote(key, *version))
+                    }
+                    _ => {
+                        let key = self.entity_node(entity, id, "id")?;
+                        check_version(self.nodes[&key].version, *version, "version")?;
+                        let in_use = self.nodes.values().any(|node| node.row.parent == Some(key))
+                            || self.notes.values().any(|note| note.row.node == key);
+                        if in_use {
+                            return Err(BatchOperationError::InUseError);
+                        }
+                        self.nodes.remove(&key);
+                        self.push(CatalogChange::DeleteNode(key, *version))
+                    }
+                };
+                result(BatchStatus::Deleted, change, None, None)
+            }
+            BatchAction::Move { ids, versions } => {
+                let (first, second) = match entity {
+                    BatchEntity::Note => {
+                        let first = self.note_key(&ids[0], "first_id")?;
+                        let second = self.note_key(&ids[1], "second_id")?;
+                        check_version(self.notes[&first].version, versions[0], "first_version")?;
+                        check_version(self.notes[&second].version, versions[1], "second_version")?;
+                        self.swap_notes(first, second)?
+                    }
+                    _ => {
+                        let first = self.entity_node(entity, &ids[0], "first_id")?;
+                        let second = self.entity_node(entity, &ids[1], "second_id")?;
+                        check_version(self.nodes[&first].version, versions[0], "first_version")?;
+                        check_version(self.nodes[&second].version, versions[1], "second_version")?;
+                        self.swap_nodes(first, second)?
+                    }
+                };
+                result(BatchStatus::Moved, first, Some(second), None)
+            }
+        })
+    }
+
+    fn node_key(&self, id: &BatchId, field: &'static str) -> Result<NodeRef, BatchOperationError> {
+        let key = match id {
+            BatchId::Id(id) => NodeRef::Stored(*id),
+            BatchId::Ref(reference) => match self.references.get(reference) {
+                Some(Reference::Node(key)) => *key,
+                _ => return Err(BatchOperationError::NotFoundError(field)),
+            },
+        };
+        if !self.nodes.contains_key(&key) {
+            return Err(BatchOperationError::NotFoundError(field));
+        }
+        Ok(key)
+    }
+
+    fn note_key(&self, id: &BatchId, field: &'static str) -> Result<NoteRef, BatchOperationError> {
+        let key = match id {
+            BatchId::Id(id) => NoteRef::Stored(*id),
+            BatchId::Ref(reference) => match self.references.get(reference) {
+                Some(Reference::Note(key)) => *key,
+                _ => return Err(BatchOperationError::NotFoundError(field)),
+            },
+        };
+        if !self.notes.contains_key(&key) {
+            return Err(BatchOperationError::NotFoundError(field));
+        }
+        Ok(key)
+    }
+
+    fn parent(&self, key: NodeRef) -> Option<NodeRef> {
+        self.nodes.get(&key).and_then(|node| node.row.parent)
+    }
+
+    fn depth(&self, key: NodeRef) -> usize {
+        std::iter::successors(self.parent(key), |parent| self.parent(*parent)).count()
+    }
+
+    /// The node named by `id`, checked to be of `entity`.
+    fn entity_node(
+        &self,
+        entity: BatchEntity,
+        id: &BatchId,
+        field: &'static str,
+    ) -> Result<NodeRef, BatchOperationError> {
+        let key = self.node_key(id, field)?;
+        let matches = match entity {
+            BatchEntity::Section => self.depth(key) == 0,
+            BatchEntity::Subsection => self.depth(key) == 1,
+            BatchEntity::Node | BatchEntity::Note => true,
+        };
+        if !matches {
+            return Err(BatchOperationError::NotFoundError(field));
+        }
+        Ok(key)
+    }
+
+    /// Whether `key` is `ancestor` or one of its descendants.
+    fn is_within(&self, key: NodeRef, ancestor: NodeRef) -> bool {
+        std::iter::successors(Some(key), |node| self.parent(*node)).any(|node| node == ancestor)
+    }
+
+    fn next_node_position(&self, parent: Option<NodeRef>) -> u32 {
+        self.nodes
+            .values()
+            .filter(|node| node.row.parent == parent)
+            .map(|node| node.row.position + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn node_slugs(&self, parent: Option<NodeRef>, except: Option<NodeRef>) -> HashSet<String> {
+        self.nodes
+            .iter()
+            .filter(|(key, node)| node.row.parent == parent && Some(**key) != except)
+            .map(|(_, node)| node.row.slug.clone())
+            .collect()
+    }
+
+    fn next_note_position(&self, node: NodeRef) -> u32 {
+        self.notes
+            .values()
+            .filter(|note| note.row.node == node)
+            .map(|note| note.row.position + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn note_slugs(&self, node: NodeRef, except: Option<NoteRef>) -> HashSet<String> {
+        self.notes
+            .iter()
+            .filter(|(key, note)| note.row.node == node && Some(**key) != except)
+            .map(|(_, note)| note.row.slug.clone())
+            .collect()
+    }
+
+    /// The section and the subsection of a note hanging from `node`.
+    fn note_parents(&self, node: NodeRef) -> (Option<NodeRef>, Option<NodeRef>) {
+        match self.depth(node) {
+            0 => (Some(node), None),
+            1 => (self.parent(node), Some(node)),
+            _ => (None, None),
+        }
+    }
+
+    /// The subsection named by `subsection`, checked to be one of `section`.
+    fn subsection_of(
+        &self,
+        section: Option<&BatchId>,
+        subsection: &BatchId,
+    ) -> Result<NodeRef, BatchOperationError> {
+        let subsection = self.entity_node(BatchEntity::Subsection, subsection, "subsection_id")?;
+        if let Some(section) = section {
+            let section = self.entity_node(BatchEntity::Section, section, "section_id")?;
+            if self.parent(subsection) != Some(section) {
+                return Err(BatchOperationError::ParentMismatchError);
+            }
+        }
+        Ok(subsection)
+    }
+
+    fn create_node(
+        &mut self,
+        entity: BatchEntity,
+        fields: &BatchFields,
+    ) -> Result<(usize, Reference), BatchOperationError> {
+        let title = fields
+            .title
+            .clone()
+            .ok_or(BatchOperationError::MissingFieldError("title"))?;
+        let (parent, kind) = match entity {
+            BatchEntity::Section => (None, db::sections::SECTION_KIND.to_string()),
+            BatchEntity::Subsection => {
+                let section = fields
+                    .section_id
+                    .as_ref()
+                    .ok_or(BatchOperationError::MissingFieldError("section_id"))?;
+                (
+                    Some(self.entity_node(BatchEntity::Section, section, "section_id")?),
+                    db::subsections::SUBSECTION_KIND.to_string(),
+                )
+            }
+            BatchEntity::Node | BatchEntity::Note => {
+                let kind = fields
+                    .kind
+                    .clone()
+                    .flatten()
+                    .ok_or(BatchOperationError::MissingFieldError("kind"))?;
+                let parent = fields
+                    .parent_id
+                    .as_ref()
+                    .map(|id| self.node_key(id, "parent_id"))
+                    .transpose()?;
+                (parent, kind)
+            }
+        };
+        let slug =
+            db::slugs::slug_for_new(fields.slug.clone(), &title, &self.node_slugs(parent, None))?;
+        let row = ImportedNode {
+            parent,
+            kind,
+            title,
+            position: self.next_node_position(parent),
+            status: fields.status.unwrap_or_default().as_str().to_string(),
+            publish_at: fields.publish_at.flatten(),
+            slug,
+            previous_slugs: Vec::new(),
+        };
+        let key = NodeRef::Planned(self.created_nodes);
+        self.created_nodes += 1;
+        self.nodes.insert(key, Planned::created(row.clone()));
+        Ok((
+            self.push(CatalogChange::CreateNode(row)),
+            Reference::Node(key),
+        ))
+    }
+
+    fn update_node(
+        &mut self,
+        entity: BatchEntity,
+        key: NodeRef,
+        fields: &BatchFields,
+    ) -> Result<usize, BatchOperationError> {
+        let current = self.nodes[&key].row.clone();
+        let mut row = current.clone();
+        let parent = match entity {
+            BatchEntity::Subsection => fields
+                .section_id
+                .as_ref()
+                .map(|id| self.entity_node(BatchEntity::Section, id, "section_id"))
+                .transpose()?,
+            BatchEntity::Node => fields
+                .parent_id
+                .as_ref()
+                .map(|id| self.node_key(id, "parent_id"))
+                .transpose()?,
+            BatchEntity::Section | BatchEntity::Note => None,
+        };
+        if let Some(parent) = parent.filter(|parent| current.parent != Some(*parent)) {
+            if self.is_within(parent, key) {
+                return Err(BatchOperationError::CycleError);
+            }
+            row.parent = Some(parent);
+            row.position = self.next_node_position(Some(parent));
+        }
+        if let Some(title) = &fields.title {
+            row.title = title.clone();
+        }
+        if let Some(kind) = &fields.kind {
+            row.kind = kind
+                .clone()
+                .ok_or(BatchOperationError::MissingFieldError("kind"))?;
+        }
+        if let Some(status) = fields.status {
+            row.status = status.as_str().to_string();
+        }
+        if let Some(publish_at) = fields.publish_at {
+            row.publish_at = publish_at;
+        }
+        if fields.slug.is_some() || row.parent != current.parent {
+            let siblings = self.node_slugs(row.parent, Some(key));
+            if let Some(change) = db::slugs::slug_update(
+                &current.slug,
+                current.previous_slugs.clone(),
+                fields.slug.clone(),
+                &siblings,
+            )? {
+                row.slug = change.slug;
+                row.previous_slugs = change.previous_slugs;
+            }
+        }
+        Ok(self.write_node(key, row))
+    }
+
+    fn write_node(&mut self, key: NodeRef, row: ImportedNode) -> usize {
+        let version = self.nodes[&key].version;
+        if let Some(node) = self.nodes.get_mut(&key) {
+            node.write(row.clone());
+        }
+        self.push(CatalogChange::UpdateNode(key, version, row))
+    }
+
+    /// Swap the positions of two sibling nodes. The first one written takes the
+    /// place of a row still parked, or of none.
+    fn swap_nodes(
+        &mut self,
+        first: NodeRef,
+        second: NodeRef,
+    ) -> Result<(usize, usize), BatchOperationError> {
+        let (a, b) = (
+            self.nodes[&first].row.clone(),
+            self.nodes[&second].row.clone(),
+        );
+        if a.parent != b.parent {
+            return Err(BatchOperationError::CantSwapError);
+        }
+        let moved = |row: &ImportedNode, position| ImportedNode {
+            position,
+            ..row.clone()
+        };
+        if self.nodes[&second].parked {
+            let first_change = self.write_node(first, moved(&a, b.position));
+            let second_change = self.write_node(second, moved(&b, a.position));
+            return Ok((first_change, second_change));
+        }
+        if !self.nodes[&first].parked {
+            let free = self.next_node_position(a.parent);
+            self.write_node(first, moved(&a, free));
+        }
+        let second_change = self.write_node(second, moved(&b, a.position));
+        let first_change = self.write_node(first, moved(&a, b.position));
+        Ok((first_change, second_change))
+    }
+
+    fn create_note(
+        &mut self,
+        fields: &BatchFields,
+    ) -> Result<(usize, Reference), BatchOperationError> {
+        let name = fields
+            .name
+            .clone()
+            .ok_or(BatchOperationError::MissingFieldError("name"))?;
+        let url = fields
+            .url
+            .clone()
+            .ok_or(BatchOperationError::MissingFieldError("url"))?;
+        let node = match (&fields.node_id, &fields.section_id, &fields.subsection_id) {
+            (Some(id), _, _) => self.node_key(id, "node_id")?,
+            (None, section, Some(Some(subsection))) => {
+                self.subsection_of(section.as_ref(), subsection)?
+            }
+            (None, Some(section), _) => {
+                self.entity_node(BatchEntity::Section, section, "section_id")?
+            }
+            (None, None, _) => return Err(BatchOperationError::MissingFieldError("section_id")),
+        };
+        let slug =
+            db::slugs::slug_for_new(fields.slug.clone(), &name, &self.note_slugs(node, None))?;
+        let row = ImportedNote {
+            node,
+            name,
+            description: fields.description.clone().unwrap_or_default(),
+            url,
+            position: self.next_note_position(node),
+            metadata: db::lecture_notes::NoteMetadata {
+                kind: note_kind(fields.kind.clone().flatten())?,
+                course_code: fields.course_code.clone().flatten(),
+                term: fields.term.clone().flatten(),
+                language: fields.language.clone().flatten(),
+                authors: fields.authors.clone().unwrap_or_default(),
+                license: fields.license.clone().flatten(),
+            },
+            status: fields.status.unwrap_or_default().as_str().to_string(),
+            publish_at: fields.publish_at.flatten(),
+            slug,
+            previous_slugs: Vec::new(),
+            tags: note_tags(fields.tags.as_deref().unwrap_or_default()),
+            attachments: Vec::new(),
+        };
+        let key = NoteRef::Planned(self.created_notes);
+        self.created_notes += 1;
+        self.notes.insert(key, Planned::created(row.clone()));
+        Ok((
+            self.push(CatalogChange::CreateNote(row)),
+            Reference::Note(key),
+        ))
+    }
+
+    /// The node an update moves a note hanging from `current` to, as the
+    /// `PATCH` of a note does. None when the note stays where it is.
+    fn moved_note_node(
+        &self,
+        current: NodeRef,
+        fields: &BatchFields,
+    ) -> Result<Option<NodeRef>, BatchOperationError> {
+        let (current_section, current_subsection) = self.note_parents(current);
+        match (&fields.node_id, &fields.section_id, &fields.subsection_id) {
+            (Some(id), _, _) => self.node_key(id, "node_id").map(Some),
+            (None, None, None) => Ok(None),
+            (None, section, Some(Some(subsection))) => {
+                self.subsection_of(section.as_ref(), subsection).map(Some)
+            }
+            (None, Some(section), Some(None)) => self
+                .entity_node(BatchEntity::Section, section, "section_id")
+                .map(Some),
+            (None, None, Some(None)) => current_section
+                .map(Some)
+                .ok_or(BatchOperationError::NoSectionError),
+            (None, Some(section), None) => {
+                let section = self.entity_node(BatchEntity::Section, section, "section_id")?;
+                match current_subsection {
+                    Some(subsection) if self.parent(subsection) == Some(section) => Ok(None),
+                    Some(_) => Err(BatchOperationError::ParentMismatchError),
+                    None if current_section == Some(section) => Ok(None),
+                    None => Ok(Some(section)),
+                }
+            }
+        }
+    }
+
+    fn update_note(
+        &mut self,
+        key: NoteRef,
+        fields: &BatchFields,
+    ) -> Result<usize, BatchOperationError> {
+        let current = self.notes[&key].row.clone();
+        let mut row = current.clone();
+        if let Some(node) = self
+            .moved_note_node(current.node, fields)?
+            .filter(|node| *node != current.node)
+        {
+            row.node = node;
+            row.position = self.next_note_position(node);
+        }
+        if let Some(name) = &fields.name {
+            row.name = name.clone();
+        }
+        if let Some(description) = &fields.description {
+            row.description = description.clone();
+        }
+        if let Some(url) = &fields.url {
+            row.url = url.clone();
+        }
+        if let Some(kind) = &fields.kind {
+            row.metadata.kind = note_kind(kind.clone())?;
+        }
+        for (value, field) in [
+            (&fields.course_code, &mut row.metadata.course_code),
+            (&fields.term, &mut row.metadata.term),
+            (&fields.language, &mut row.metadata.language),
+            (&fields.license, &mut row.metadata.license),
+        ] {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        if let Some(authors) = &fields.authors {
+            row.metadata.authors = authors.clone();
+        }
+        if let Some(tags) = &fields.tags {
+            row.tags = note_tags(tags);
+        }
+        if let Some(status) = fields.status {
+            row.status = status.as_str().to_string();
+        }
+        if let Some(publish_at) = fields.publish_at {
+            row.publish_at = publish_at;
+        }
+        if fields.slug.is_some() || row.node != current.node {
+            let siblings = self.note_slugs(row.node, Some(key));
+            if let Some(change) = db::slugs::slug_update(
+                &current.slug,
+                current.previous_slugs.clone(),
+                fields.slug.clone(),
+                &siblings,
+            )? {
+                row.slug = change.slug;
+                row.previous_slugs = change.previous_slugs;
+            }
+        }
+        Ok(self.write_note(key, row))
+    }
+
+    fn write_note(&mut self, key: NoteRef, row: ImportedNote) -> usize {
+        let version = self.notes[&key].version;
+        if let Some(note) = self.notes.get_mut(&key) {
+            note.write(row.clone());
+        }
+        self.push(CatalogChange::UpdateNote(key, version, row))
+    }
+
+    /// Swap the positions of two notes of a node, as `swap_nodes` does.
+    fn swap_notes(
+        &mut self,
+        first: NoteRef,
+        second: NoteRef,
+    ) -> Result<(usize, usize), BatchOperationError> {
+        let (a, b) = (
+            self.notes[&first].row.clone(),
+            self.notes[&second].row.clone(),
+        );
+        if a.node != b.node {
+            return Err(BatchOperationError::CantSwapError);
+        }
+        let moved = |row: &ImportedNote, position| ImportedNote {
+            position,
+            ..row.clone()
+        };
+        if self.notes[&second].parked {
+            let first_change = self.write_note(first, moved(&a, b.position));
+            let second_change = self.write_note(second, moved(&b, a.position));
+            return Ok((first_change, second_change));
+        }
+        if !self.notes[&first].parked {
+            let free = self.next_note_position(a.node);
+            self.write_note(first, moved(&a, free));
+        }
+        let second_change = self.write_note(second, moved(&b, a.position));
+        let first_change = self.write_note(first, moved(&a, b.position));
+        Ok((first_change, second_change))
+    }
+}
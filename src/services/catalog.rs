@@ -0,0 +1,759 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::db;
+use crate::db::catalog::{
+    CatalogChange, ImportedAttachment, ImportedNode, ImportedNote, NodeRef, NoteRef,
+};
+use crate::db::slugs::{is_valid_slug, slugify};
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::routes::validation::{allowed_url, not_blank, tag_names, MAX_NAME_LENGTH};
+use crate::services::lecture_notes::{NoteMetadata, NoteReturn};
+use crate::services::nodes::TreeNode;
+use crate::services::publishing::{Status, Visibility};
+
+/// Written in the `format` field of every export and checked by the imports.
+pub const CATALOG_FORMAT: &str = "lecture-notes-catalog";
+
+/// The version of the catalog layout, bumped by every incompatible change.
+pub const CATALOG_VERSION: u32 = 1;
+
+/// The whole content tree, as exported and imported. The imported items follow
+/// the rules of the routes creating them.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Validate)]
+pub struct Catalog {
+    pub format: String,
+    pub version: u32,
+    /// Seconds since the Unix epoch.
+    #[serde(default)]
+    pub exported_at: i64,
+    /// The root nodes, the subsections and the deeper nodes being their `children`.
+    #[validate(nested)]
+    pub sections: Vec<CatalogNode>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema, Validate)]
+pub struct CatalogNode {
+    /// The stored node, for the catalogs read from the database.
+    #[serde(skip)]
+    pub id: Option<u32>,
+    #[serde(skip)]
+    pub version: Option<u32>,
+    /// `section` for the root nodes and `subsection` below when missing.
+    #[serde(default)]
+    #[validate(length(min = 1, max = 32))]
+    pub kind: Option<String>,
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub title: String,
+    /// Identifies the node among its siblings, derived from the title when missing.
+    #[serde(default)]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    #[serde(default)]
+    pub previous_slugs: Vec<String>,
+    /// Orders the siblings, the ties keeping the order of the file.
+    #[serde(default)]
+    pub position: u32,
+    #[serde(default)]
+    pub status: Status,
+    #[serde(default)]
+    pub publish_at: Option<i64>,
+    #[serde(default)]
+    #[schema(no_recursion)]
+    #[validate(nested)]
+    pub children: Vec<CatalogNode>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub notes: Vec<CatalogNote>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema, Validate)]
+pub struct CatalogNote {
+    /// The stored note, for the catalogs read from the database.
+    #[serde(skip)]
+    pub id: Option<u32>,
+    #[serde(skip)]
+    pub version: Option<u32>,
+    #[validate(custom(function = not_blank))]
+    pub name: String,
+    /// Identifies the note in its node, derived from the name when missing.
+    #[serde(default)]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub slug: Option<String>,
+    #[serde(default)]
+    pub previous_slugs: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[validate(custom(function = allowed_url))]
+    pub url: String,
+    /// Orders the notes of a node, the ties keeping the order of the file.
+    #[serde(default)]
+    pub position: u32,
+    #[serde(default)]
+    pub status: Status,
+    #[serde(default)]
+    pub publish_at: Option<i64>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub metadata: NoteMetadata,
+    #[serde(default)]
+    #[validate(custom(function = tag_names))]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub attachments: Vec<CatalogAttachment>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema, Validate)]
+pub struct CatalogAttachment {
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub label: String,
+    #[validate(custom(function = allowed_url))]
+    pub url: String,
+    #[serde(default)]
+    #[validate(length(max = 127))]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// The content hash of the uploaded file, linked again on import when the
+    /// target database has the same file.
+    #[serde(default)]
+    pub file_sha256: Option<String>,
+}
+
+fn catalog_note(note: NoteReturn, file_hashes: &HashMap<u32, String>) -> CatalogNote {
+    CatalogNote {
+        id: Some(note.id),
+        version: Some(note.version),
+        name: note.name,
+        slug: Some(note.slug),
+        previous_slugs: note.previous_slugs,
+        description: note.description,
+        url: note.url,
+        position: note.position,
+        status: note.status,
+        publish_at: note.publish_at,
+        metadata: note.metadata,
+        tags: note.tags,
+        attachments: note
+            .attachments
+            .into_iter()
+            .map(|attachment| CatalogAttachment {
+                file_sha256: attachment
+                    .file_id
+                    .and_then(|id| file_hashes.get(&id).cloned()),
+                label: attachment.label,
+                url: attachment.url,
+                mime_type: attachment.mime_type,
+                size: attachment.size,
+            })
+            .collect(),
+    }
+}
+
+fn catalog_node(node: TreeNode, file_hashes: &HashMap<u32, String>) -> CatalogNode {
+    CatalogNode {
+        id: Some(node.id),
+        version: Some(node.version),
+        kind: Some(node.kind),
+        title: node.title,
+        slug: Some(node.slug),
+        previous_slugs: node.previous_slugs,
+        position: node.position,
+        status: node.status,
+        publish_at: node.publish_at,
+        children: node
+            .children
+            .into_iter()
+            .map(|child| catalog_node(child, file_hashes))
+            .collect(),
+        notes: node
+            .notes
+            .into_iter()
+            .map(|note| catalog_note(note, file_hashes))
+            .collect(),
+    }
+}
+
+fn push_file_ids(node: &TreeNode, ids: &mut Vec<u32>) {
+    for note in &node.notes {
+        ids.extend(
+            note.attachments
+                .iter()
+                .filter_map(|attachment| attachment.file_id),
+        );
+    }
+    for child in &node.children {
+        push_file_ids(child, ids);
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportCatalogError {
+    UnexpectedError(Error),
+}
+
+/// Read the whole content tree, drafts and archived content included. The notes
+/// that are not attached to any node are left out.
+pub async fn export_catalog(storage: &Storage) -> Result<Catalog, ExportCatalogError> {
+    let tree = crate::services::nodes::get_tree(storage, Visibility::Everything)
+        .await
+        .map_err(
+            |crate::services::nodes::GetTreeError::UnexpectedError(err)| {
+                ExportCatalogError::UnexpectedError(err)
+            },
+        )?;
+
+    let mut file_ids = Vec::new();
+    for node in &tree {
+        push_file_ids(node, &mut file_ids);
+    }
+    let file_hashes = if file_ids.is_empty() {
+        HashMap::new()
+    } else {
+        storage
+            .files
+            .get_files(db::files::GetFilesForm {
+                ids: Some(file_ids),
+                ..Default::default()
+            })
+            .await
+            .map_err(|db::files::GetFilesError::UnexpectedError(err)| {
+                ExportCatalogError::UnexpectedError(err)
+            })?
+            .into_iter()
+            .map(|file| (file.id, file.sha256))
+            .collect()
+    };
+
+    Ok(Catalog {
+        format: CATALOG_FORMAT.to_string(),
+        version: CATALOG_VERSION,
+        exported_at: db::now(),
+        sections: tree
+            .into_iter()
+            .map(|node| catalog_node(node, &file_hashes))
+            .collect(),
+    })
+}
+
+/// What an import does with the stored content missing from the catalog.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Keep it, after the imported siblings.
+    #[default]
+    Merge,
+    /// Delete it, so the content ends up as in the catalog.
+    Replace,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportedKind {
+    Node,
+    Note,
+}
+
+/// A node or a note created, updated or deleted by an import.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct ImportedItem {
+    pub kind: ImportedKind,
+    /// The slugs leading to the item, joined by `/`.
+    pub path: String,
+    pub title: String,
+}
+
+/// What an import did, or would do for a dry run.
+#[derive(Serialize, Debug, Default, ToSchema)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub dry_run: bool,
+    pub created: Vec<ImportedItem>,
+    pub updated: Vec<ImportedItem>,
+    pub deleted: Vec<ImportedItem>,
+}
+
+#[derive(Debug)]
+pub enum ImportCatalogError {
+    UnexpectedError(Error),
+    UnsupportedFormatError,
+    UnsupportedVersionError(u32),
+    /// The path of the item with an invalid slug.
+    InvalidSlugError(String),
+    /// The path shared by two siblings of the catalog.
+    DuplicateSlugError(String),
+    /// A stored row was changed while the import was made.
+    VersionConflictError,
+}
+
+pub struct ImportCatalogForm {
+    pub catalog: Catalog,
+    pub mode: ImportMode,
+    /// Only report the changes.
+    pub dry_run: bool,
+}
+
+/// Import a catalog: the nodes and the notes are matched with the stored ones by
+/// slug (or by a previous slug, for the ones renamed since), the matching ones
+/// are updated and the others created. Every change is made in one transaction.
+pub async fn import_catalog(
+    storage: &Storage,
+    form: ImportCatalogForm,
+) -> Result<ImportReport, ImportCatalogError> {
+    if form.catalog.format != CATALOG_FORMAT {
+        return Err(ImportCatalogError::UnsupportedFormatError);
+    }
+    if form.catalog.version != CATALOG_VERSION {
+        return Err(ImportCatalogError::UnsupportedVersionError(
+            form.catalog.version,
+        ));
+    }
+    let current =
+        export_catalog(storage)
+            .await
+            .map_err(|ExportCatalogError::UnexpectedError(err)| {
+                ImportCatalogError::UnexpectedError(err)
+            })?;
+    let (mut changes, mut report) =
+        plan_import(&current.sections, &form.catalog.sections, form.mode)?;
+    report.dry_run = form.dry_run;
+    // The deletions free the slots of the rows taking their places.
+    changes.sort_by_key(|change| {
+        !matches!(
+            change,
+            CatalogChange::DeleteNode(..) | CatalogChange::DeleteNote(..)
+        )
+    });
+    if !form.dry_run && !changes.is_empty() {
+        let res = storage.catalog.apply_catalog_changes(&changes).await;
+        storage.content_changed();
+        res.map_err(|err| match err {
+            db::catalog::ApplyCatalogChangesError::UnexpectedError(err) => {
+                ImportCatalogError::UnexpectedError(err)
+            }
+            db::catalog::ApplyCatalogChangesError::VersionConflictError(_) => {
+                ImportCatalogError::VersionConflictError
+            }
+        })?;
+    }
+    Ok(report)
+}
+
+/// The changes turning the `current` tree, read from the database, into the
+/// `incoming` one, along with the report listing them.
+pub fn plan_import(
+    current: &[CatalogNode],
+    incoming: &[CatalogNode],
+    mode: ImportMode,
+) -> Result<(Vec<CatalogChange>, ImportReport), ImportCatalogError> {
+    let mut planner = ImportPlanner {
+        mode,
+        changes: Vec::new(),
+        created_nodes: 0,
+        report: ImportReport {
+            mode,
+            ..Default::default()
+        },
+    };
+    planner.plan_nodes(None, "", current, incoming)?;
+    Ok((planner.changes, planner.report))
+}
+
+fn join_path(parent_path: &str, slug: &str) -> String {
+    if parent_path.is_empty() {
+        slug.to_string()
+    } else {
+        format!("{parent_path}/{slug}")
+    }
+}
+
+/// The slug of an item of the catalog, checked against its siblings.
+fn checked_slug(
+    slug: &Option<String>,
+    title: &str,
+    parent_path: &str,
+    seen: &mut HashSet<String>,
+) -> Result<String, ImportCatalogError> {
+    let slug = slug.clone().unwrap_or_else(|| slugify(title));
+    if !is_valid_slug(&slug) {
+        return Err(ImportCatalogError::InvalidSlugError(join_path(
+            parent_path,
+            &slug,
+        )));
+    }
+    if !seen.insert(slug.clone()) {
+        return Err(ImportCatalogError::DuplicateSlugError(join_path(
+            parent_path,
+            &slug,
+        )));
+    }
+    Ok(slug)
+}
+
+/// The stored item an imported one stands for: the one with the same slug, or
+/// else one whose slug the imported item had before.
+fn find_stored<'a, T>(
+    stored: &'a [T],
+    claimed: &HashSet<u32>,
+    slug: &str,
+    previous_slugs: &[String],
+    stored_key: impl Fn(&T) -> (Option<u32>, &str),
+) -> Option<(u32, &'a T)> {
+    let free = |item: &&T| {
+        let (id, _) = stored_key(item);
+        id.is_some_and(|id| !claimed.contains(&id))
+    };
+    stored
+        .iter()
+        .filter(free)
+        .find(|item| stored_key(item).1 == slug)
+        .or_else(|| {
+            stored.iter().filter(free).find(|item| {
+                previous_slugs
+                    .iter()
+                    .any(|previous| previous == stored_key(item).1)
+            })
+        })
+        .and_then(|item| stored_key(item).0.map(|id| (id, item)))
+}
+
+/// The previous slugs of a matched item: the stored ones, the stored slug when
+/// it changes, then the imported ones.
+fn merged_previous_slugs(
+    stored_slug: &str,
+    stored_previous: &[String],
+    slug: &str,
+    incoming_previous: &[String],
+) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    let candidates = stored_previous
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once(stored_slug))
+        .chain(incoming_previous.iter().map(String::as_str));
+    for candidate in candidates {
+        if !candidate.is_empty() && candidate != slug && !merged.iter().any(|s| s == candidate) {
+            merged.push(candidate.to_string());
+        }
+    }
+    merged
+}
+
+/// The items of a catalog level sorted by position, the ties keeping the file order.
+fn by_position<T>(items: &[T], position: impl Fn(&T) -> u32) -> Vec<&T> {
+    let mut sorted = items.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|item| position(item));
+    sorted
+}
+
+fn default_kind(parent: Option<NodeRef>) -> String {
+    match parent {
+        None => db::sections::SECTION_KIND.to_string(),
+        Some(_) => db::subsections::SUBSECTION_KIND.to_string(),
+    }
+}
+
+fn stored_slug(slug: &Option<String>) -> &str {
+    slug.as_deref().unwrap_or_default()
+}
+
+fn imported_tags(tags: &[String]) -> Vec<String> {
+    let mut tags = tags.to_vec();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+fn imported_attachments(attachments: &[CatalogAttachment]) -> Vec<ImportedAttachment> {
+    attachments
+        .iter()
+        .map(|attachment| ImportedAttachment {
+            label: attachment.label.clone(),
+            url: attachment.url.clone(),
+            mime_type: attachment.mime_type.clone(),
+            size: attachment.size,
+            file_sha256: attachment.file_sha256.clone(),
+        })
+        .collect()
+}
+
+fn imported_node(
+    node: &CatalogNode,
+    parent: Option<NodeRef>,
+    position: u32,
+    slug: String,
+    previous_slugs: Vec<String>,
+) -> ImportedNode {
+    ImportedNode {
+        parent,
+        kind: node.kind.clone().unwrap_or_else(|| default_kind(parent)),
+        title: node.title.clone(),
+        position,
+        status: node.status.as_str().to_string(),
+        publish_at: node.publish_at,
+        slug,
+        previous_slugs,
+    }
+}
+
+fn imported_note(
+    note: &CatalogNote,
+    node: NodeRef,
+    position: u32,
+    slug: String,
+    previous_slugs: Vec<String>,
+) -> ImportedNote {
+    ImportedNote {
+        node,
+        name: note.name.clone(),
+        description: note.description.clone(),
+        url: note.url.clone(),
+        position,
+        metadata: note.metadata.clone().into(),
+        status: note.status.as_str().to_string(),
+        publish_at: note.publish_at,
+        slug,
+        previous_slugs,
+        tags: imported_tags(&note.tags),
+        attachments: imported_attachments(&note.attachments),
+    }
+}
+
+/// The stored item as an import would write it, to tell whether it changes.
+pub(crate) fn stored_node(node: &CatalogNode, parent: Option<NodeRef>) -> ImportedNode {
+    imported_node(
+        node,
+        parent,
+        node.position,
+        stored_slug(&node.slug).to_string(),
+        node.previous_slugs.clone(),
+    )
+}
+
+pub(crate) fn stored_note(note: &CatalogNote, node: NodeRef) -> ImportedNote {
+    imported_note(
+        note,
+        node,
+        note.position,
+        stored_slug(&note.slug).to_string(),
+        note.previous_slugs.clone(),
+    )
+}
+
+struct ImportPlanner {
+    mode: ImportMode,
+    changes: Vec<CatalogChange>,
+    /// The `CreateNode` changes so far, the next one being `NodeRef::Planned(created_nodes)`.
+    created_nodes: usize,
+    report: ImportReport,
+}
+
+impl ImportPlanner {
+    fn item(kind: ImportedKind, path: String, title: &str) -> ImportedItem {
+        ImportedItem {
+            kind,
+            path,
+            title: title.to_string(),
+        }
+    }
+
+    fn plan_nodes(
+        &mut self,
+        parent: Option<NodeRef>,
+        parent_path: &str,
+        stored: &[CatalogNode],
+        incoming: &[CatalogNode],
+    ) -> Result<(), ImportCatalogError> {
+        let mut seen = HashSet::new();
+        let mut claimed = HashSet::new();
+        let mut position = 0u32;
+        for node in by_position(incoming, |node| node.position) {
+            let slug = checked_slug(&node.slug, &node.title, parent_path, &mut seen)?;
+            let path = join_path(parent_path, &slug);
+            let item = Self::item(ImportedKind::Node, path.clone(), &node.title);
+            let matched = find_stored(stored, &claimed, &slug, &node.previous_slugs, |stored| {
+                (stored.id, stored_slug(&stored.slug))
+            });
+            let (node_ref, stored_children, stored_notes) = match matched {
+                Some((id, stored)) => {
+                    claimed.insert(id);
+                    let version = stored.version.unwrap_or_default();
+                    let previous_slugs = merged_previous_slugs(
+                        stored_slug(&stored.slug),
+                        &stored.previous_slugs,
+                        &slug,
+                        &node.previous_slugs,
+                    );
+                    let imported = imported_node(node, parent, position, slug, previous_slugs);
+                    if imported != stored_node(stored, parent) {
+                        self.changes.push(CatalogChange::UpdateNode(
+                            NodeRef::Stored(id),
+                            version,
+                            imported,
+                        ));
+                        self.report.updated.push(item);
+                    }
+                    (NodeRef::Stored(id), &stored.children[..], &stored.notes[..])
+                }
+                None => {
+                    let previous_slugs =
+                        merged_previous_slugs("", &[], &slug, &node.previous_slugs);
+                    let imported = imported_node(node, parent, position, slug, previous_slugs);
+                    self.changes.push(CatalogChange::CreateNode(imported));
+                    self.report.created.push(item);
+                    self.created_nodes += 1;
+                    (NodeRef::Planned(self.created_nodes - 1), &[][..], &[][..])
+                }
+            };
+            self.plan_notes(node_ref, &path, stored_notes, &node.notes)?;
+            self.plan_nodes(Some(node_ref), &path, stored_children, &node.children)?;
+            position += 1;
+        }
+
+        for stored in by_position(stored, |node| node.position) {
+            let Some(id) = stored.id.filter(|id| !claimed.contains(id)) else {
+                continue;
+            };
+            let version = stored.version.unwrap_or_default();
+            let path = join_path(parent_path, stored_slug(&stored.slug));
+            match self.mode {
+                ImportMode::Merge => {
+                    if stored.position != position {
+                        let mut moved = stored_node(stored, parent);
+                        moved.position = position;
+                        self.changes.push(CatalogChange::UpdateNode(
+                            NodeRef::Stored(id),
+                            version,
+                            moved,
+                        ));
+                        self.report.updated.push(Self::item(
+                            ImportedKind::Node,
+                            path,
+                            &stored.title,
+                        ));
+                    }
+                    position += 1;
+                }
+                ImportMode::Replace => self.delete_node(id, &path, stored),
+            }
+        }
+        Ok(())
+    }
+
+    fn plan_notes(
+        &mut self,
+        node: NodeRef,
+        node_path: &str,
+        stored: &[CatalogNote],
+        incoming: &[CatalogNote],
+    ) -> Result<(), ImportCatalogError> {
+        let mut seen = HashSet::new();
+        let mut claimed = HashSet::new();
+        let mut position = 0u32;
+        for note in by_position(incoming, |note| note.position) {
+            let slug = checked_slug(&note.slug, &note.name, node_path, &mut seen)?;
+            let item = Self::item(ImportedKind::Note, join_path(node_path, &slug), &note.name);
+            let matched = find_stored(stored, &claimed, &slug, &note.previous_slugs, |stored| {
+                (stored.id, stored_slug(&stored.slug))
+            });
+            match matched {
+                Some((id, stored)) => {
+                    claimed.insert(id);
+                    let version = stored.version.unwrap_or_default();
+                    let previous_slugs = merged_previous_slugs(
+                        stored_slug(&stored.slug),
+                        &stored.previous_slugs,
+                        &slug,
+                        &note.previous_slugs,
+                    );
+                    let imported = imported_note(note, node, position, slug, previous_slugs);
+                    if imported != stored_note(stored, node) {
+                        self.changes.push(CatalogChange::UpdateNote(
+                            NoteRef::Stored(id),
+                            version,
+                            imported,
+                        ));
+                        self.report.updated.push(item);
+                    }
+                }
+                None => {
+                    let previous_slugs =
+                        merged_previous_slugs("", &[], &slug, &note.previous_slugs);
+                    let imported = imported_note(note, node, position, slug, previous_slugs);
+                    self.changes.push(CatalogChange::CreateNote(imported));
+                    self.report.created.push(item);
+                }
+            }
+            position += 1;
+        }
+
+        for stored in by_position(stored, |note| note.position) {
+            let Some(id) = stored.id.filter(|id| !claimed.contains(id)) else {
+                continue;
+            };
+            let version = stored.version.unwrap_or_default();
+            let item = Self::item(
+                ImportedKind::Note,
+                join_path(node_path, stored_slug(&stored.slug)),
+                &stored.name,
+            );
+            match self.mode {
+                ImportMode::Merge => {
+                    if stored.position != position {
+                        let mut moved = stored_note(stored, node);
+                        moved.position = position;
+                        self.changes.push(CatalogChange::UpdateNote(
+                            NoteRef::Stored(id),
+                            version,
+                            moved,
+                        ));
+                        self.report.updated.push(item);
+                    }
+                    position += 1;
+                }
+                ImportMode::Replace => {
+                    self.changes
+                        .push(CatalogChange::DeleteNote(NoteRef::Stored(id), version));
+                    self.report.deleted.push(item);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete a stored node with its notes and descendants, the children first.
+    fn delete_node(&mut self, id: u32, path: &str, node: &CatalogNode) {
+        for note in &node.notes {
+            if let Some(note_id) = note.id {
+                self.changes.push(CatalogChange::DeleteNote(
+                    NoteRef::Stored(note_id),
+                    note.version.unwrap_or_default(),
+                ));
+                self.report.deleted.push(Self::item(
+                    ImportedKind::Note,
+                    join_path(path, stored_slug(&note.slug)),
+                    &note.name,
+                ));
+            }
+        }
+        for child in &node.children {
+            if let Some(child_id) = child.id {
+                self.delete_node(child_id, &join_path(path, stored_slug(&child.slug)), child);
+            }
+        }
+        self.changes.push(CatalogChange::DeleteNode(
+            NodeRef::Stored(id),
+            node.version.unwrap_or_default(),
+        ));
+        self.report.deleted.push(Self::item(
+            ImportedKind::Node,
+            path.to_string(),
+            &node.title,
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+use crate::db;
+use crate::error::Error;
+use crate::repository::Storage;
+
+/// Default maximum size of an uploaded file: 100 MiB.
+const DEFAULT_MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// MIME types accepted when `FILES_ALLOWED_MIME_TYPES` is not set.
+/// A trailing `/*` accepts every subtype.
+const DEFAULT_ALLOWED_MIME_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/zip",
+    "application/gzip",
+    "application/x-tar",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/x-tex",
+    "application/x-ipynb+json",
+    "text/*",
+    "image/*",
+    "audio/*",
+    "video/*",
+];
+
+/// Where and what files may be stored. Read from the environment:
+/// `FILES_STORAGE_PATH`, `FILES_MAX_SIZE_BYTES` and `FILES_ALLOWED_MIME_TYPES`
+/// (comma-separated).
+#[derive(Clone, Debug)]
+pub struct FileStorageConfig {
+    pub root: PathBuf,
+    pub max_size: u64,
+    pub allowed_mime_types: Vec<String>,
+}
+
+impl FileStorageConfig {
+    pub fn from_env() -> Self {
+        let root = std::env::var("FILES_STORAGE_PATH").unwrap_or_else(|_| "uploads".to_string());
+        let max_size = std::env::var("FILES_MAX_SIZE_BYTES")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let allowed_mime_types = match std::env::var("FILES_ALLOWED_MIME_TYPES") {
+            Ok(value) if !value.trim().is_empty() => value
+                .split(',')
+                .map(|mime_type| mime_type.trim().to_ascii_lowercase())
+                .filter(|mime_type| !mime_type.is_empty())
+                .collect(),
+            _ => DEFAULT_ALLOWED_MIME_TYPES
+                .iter()
+                .map(|mime_type| mime_type.to_string())
+                .collect(),
+        };
+        Self {
+            root: PathBuf::from(root),
+            max_size,
+            allowed_mime_types,
+        }
+    }
+
+    pub fn is_allowed(&self, mime_type: &str) -> bool {
+        self.allowed_mime_types
+            .iter()
+            .any(|allowed| match allowed.strip_suffix("/*") {
+                Some(prefix) => mime_type
+                    .split_once('/')
+                    .is_some_and(|(top_level, _)| top_level == prefix),
+                None => allowed == mime_type,
+            })
+    }
+
+    /// Location of the content with the given hash, sharded by its first two characters.
+    pub fn content_path(&self, sha256: &str) -> PathBuf {
+        self.root.join(&sha256[..2]).join(sha256)
+    }
+
+    fn tmp_dir(&self) -> PathBuf {
+        self.root.join("tmp")
+    }
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct FileReturn {
+    pub id: u32,
+    pub sha256: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Download URL of the file.
+    pub url: String,
+}
+
+/// Download URL of an uploaded file.
+pub fn file_url(id: u32) -> String {
+    format!("/files/{id}")
+}
+
+impl From<db::files::FileFromDb> for FileReturn {
+    fn from(value: db::files::FileFromDb) -> Self {
+        Self {
+            url: file_url(value.id),
+            id: value.id,
+            sha256: value.sha256,
+            original_name: value.original_name,
+            mime_type: value.mime_type,
+            size: value.size,
+        }
+    }
+}
+
+/// Guess a MIME type from the extension of a file name.
+pub fn guess_mime_type(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odp" => "application/vnd.oasis.opendocument.presentation",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "tex" => "application/x-tex",
+        "ipynb" => "application/x-ipynb+json",
+        "txt" | "md" => "text/plain",
+        "csv" => "text/csv",
+        "py" => "text/x-python",
+        "rs" => "text/x-rust",
+        "c" | "h" => "text/x-c",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+/// The MIME type of an upload: the declared one, or a guess from the file name
+/// when the client sent none or a generic one.
+pub fn resolve_mime_type(declared: Option<&str>, file_name: &str) -> String {
+    let declared = declared
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty() && value != "application/octet-stream");
+    declared
+        .or_else(|| guess_mime_type(file_name).map(str::to_string))
+        .unwrap_or_else(|| "application/octet-stream".to_string())
+}
+
+/// Only keep the last component of a client provided file name.
+fn clean_file_name(file_name: &str) -> String {
+    let name = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim();
+    if name.is_empty() {
+        "file".to_string()
+    } else {
+        name.chars().take(255).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreFileError {
+    UnexpectedError(Error),
+    NotAllowedTypeError(String),
+    TooLargeError(u64),
+}
+
+impl From<std::io::Error> for StoreFileError {
+    fn from(err: std::io::Error) -> Self {
+        StoreFileError::UnexpectedError(Error::internal(format!("the file storage failed: {err}")))
+    }
+}
+
+/// An upload being written to the storage. The content is streamed into a
+/// temporary file while its hash is computed, then moved to its content address.
+pub struct FileUpload<'a> {
+    config: &'a FileStorageConfig,
+    original_name: String,
+    mime_type: String,
+    tmp_path: PathBuf,
+    tmp_file: fs::File,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<'a> FileUpload<'a> {
+    /// Start an upload; fails right away when the MIME type is not allowed.
+    pub async fn begin(
+        config: &'a FileStorageConfig,
+        file_name: &str,
+        declared_mime_type: Option<&str>,
+    ) -> Result<FileUpload<'a>, StoreFileError> {
+        let mime_type = resolve_mime_type(declared_mime_type, file_name);
+        if !config.is_allowed(&mime_type) {
+            return Err(StoreFileError::NotAllowedTypeError(mime_type));
+        }
+        fs::create_dir_all(config.tmp_dir()).await?;
+        let tmp_path = config
+            .tmp_dir()
+            .join(format!("{:016x}", rand::random::<u64>()));
+        let tmp_file = fs::File::create(&tmp_path).await?;
+        Ok(FileUpload {
+            config,
+            original_name: clean_file_name(file_name),
+            mime_type,
+            tmp_path,
+            tmp_file,
+            hasher: Sha256::new(),
+            size: 0,
+        })
+    }
+
+    /// Append a chunk of the content. The upload is discarded when it grows over the limit.
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), StoreFileError> {
+        self.size += chunk.len() as u64;
+        if self.size > self.config.max_size {
+            let _ = fs::remove_file(&self.tmp_path).await;
+            return Err(StoreFileError::TooLargeError(self.config.max_size));
+        }
+        self.hasher.update(chunk);
+        if let Err(err) = self.tmp_file.write_all(chunk).await {
+            let _ = fs::remove_file(&self.tmp_path).await;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Discard the upload.
+    pub async fn abort(self) {
+        let _ = fs::remove_file(&self.tmp_path).await;
+    }
+
+    /// Move the content to its address and register the file. Uploading
+    /// content that is already stored returns the existing file.
+    pub async fn finish(mut self, storage: &Storage) -> Result<FileReturn, StoreFileError> {
+        self.tmp_file.flush().await?;
+        drop(self.tmp_file);
+        let sha256 = self
+            .hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        let content_path = self.config.content_path(&sha256);
+        if fs::try_exists(&content_path).await.unwrap_or(false) {
+            fs::remove_file(&self.tmp_path).await?;
+        } else {
+            if let Some(parent) = content_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::rename(&self.tmp_path, &content_path).await?;
+        }
+
+        let (id, _) = storage
+            .files
+            .create_file(db::files::CreateFileForm {
+                sha256: sha256.clone(),
+                original_name: self.original_name,
+                mime_type: self.mime_type,
+                size: self.size,
+            })
+            .await
+            .map_err(|db::files::CreateFileError::UnexpectedError(err)| {
+                StoreFileError::UnexpectedError(err)
+            })?;
+        get_file(storage, id).await.map_err(|err| match err {
+            GetFileError::UnexpectedError(err) => StoreFileError::UnexpectedError(err),
+            GetFileError::NotFoundError => {
+                StoreFileError::UnexpectedError(Error::internal("the file was deleted meanwhile"))
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum GetFilesError {
+    UnexpectedError(Error),
+}
+
+pub async fn get_files(storage: &Storage) -> Result<Vec<FileReturn>, GetFilesError> {
+    storage
+        .files
+        .get_files(Default::default())
+        .await
+        .map(|list| list.into_iter().map(FileReturn::from).collect())
+        .map_err(|db::files::GetFilesError::UnexpectedError(err)| {
+            GetFilesError::UnexpectedError(err)
+        })
+}
+
+/// The files with the given ids.
+pub async fn get_files_by_ids(
+    storage: &Storage,
+    ids: &[u32],
+) -> Result<Vec<FileReturn>, GetFilesError> {
+    storage
+        .files
+        .get_files(db::files::GetFilesForm {
+            ids: Some(ids.to_vec()),
+            ..Default::default()
+        })
+        .await
+        .map(|list| list.into_iter().map(FileReturn::from).collect())
+        .map_err(|db::files::GetFilesError::UnexpectedError(err)| {
+            GetFilesError::UnexpectedError(err)
+        })
+}
+
+#[derive(Debug)]
+pub enum GetFileError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+pub async fn get_file(storage: &Storage, id: u32) -> Result<FileReturn, GetFileError> {
+    storage
+        .files
+        .get_file(db::files::GetFilesForm {
+            id: Some(id),
+            ..Default::default()
+        })
+        .await
+        .map(FileReturn::from)
+        .map_err(|err| match err {
+            db::files::GetFileError::NotFoundError => GetFileError::NotFoundError,
+            db::files::GetFileError::UnexpectedError(err) => GetFileError::UnexpectedError(err),
+        })
+}
+
+#[derive(Debug)]
+pub enum DeleteFileError {
+    UnexpectedError(Error),
+    NotFoundError,
+    InUseError,
+}
+
+/// Delete a file and its content. Files attached to a note cannot be deleted.
+pub async fn delete_file(
+    storage: &Storage,
+    config: &FileStorageConfig,
+    id: u32,
+) -> Result<(), DeleteFileError> {
+    let file = storage
+        .files
+        .delete_file(id)
+        .await
+        .map_err(|err| match err {
+            db::files::DeleteFileError::NotFoundError => DeleteFileError::NotFoundError,
+            db::files::DeleteFileError::InUseError => DeleteFileError::InUseError,
+            db::files::DeleteFileError::UnexpectedError(err) => {
+                DeleteFileError::UnexpectedError(err)
+            }
+        })?;
+    match fs::remove_file(config.content_path(&file.sha256)).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(DeleteFileError::UnexpectedError(Error::internal(format!(
+            "the file storage failed: {err}"
+        )))),
+    }
+}
+
+/// A satisfiable byte range, both ends included.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeError {
+    /// The header is well formed but no byte of the file is selected.
+    NotSatisfiableError,
+}
+
+/// Parse a `Range` header for a file of `size` bytes.
+/// Returns `Ok(None)` when the whole file must be sent: no header, a unit
+/// other than bytes, a malformed value or several ranges.
+pub fn parse_range(header: Option<&str>, size: u64) -> Result<Option<ByteRange>, RangeError> {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // Suffix range: the last `end` bytes.
+        let Ok(suffix) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if suffix == 0 || size == 0 {
+            return Err(RangeError::NotSatisfiableError);
+        }
+        ByteRange {
+            start: size.saturating_sub(suffix),
+            end: size - 1,
+        }
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return Ok(None);
+        };
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return Ok(None),
+            }
+        };
+        if start >= size {
+            return Err(RangeError::NotSatisfiableError);
+        }
+        ByteRange {
+            start,
+            end: end.min(size - 1),
+        }
+    };
+    Ok(Some(range))
+}
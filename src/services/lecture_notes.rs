@@ -1,162 +1,612 @@
+use std::collections::HashSet;
+
 use crate::db;
-use serde::Serialize;
+use crate::db::query::{Filter, Value};
+use crate::db::slugs::SlugError;
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::pagination::{Page, PageError, PageRequest, Sort, SortField, Sortable};
+use crate::services::publishing::{Status, Visibility};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// What kind of learning resource a note is.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteKind {
+    Lecture,
+    Exercise,
+    Exam,
+    Solution,
+    Cheatsheet,
+}
+
+impl NoteKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteKind::Lecture => "lecture",
+            NoteKind::Exercise => "exercise",
+            NoteKind::Exam => "exam",
+            NoteKind::Solution => "solution",
+            NoteKind::Cheatsheet => "cheatsheet",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "lecture" => Some(NoteKind::Lecture),
+            "exercise" => Some(NoteKind::Exercise),
+            "exam" => Some(NoteKind::Exam),
+            "solution" => Some(NoteKind::Solution),
+            "cheatsheet" => Some(NoteKind::Cheatsheet),
+            _ => None,
+        }
+    }
+
+    /// Human readable label, used as the schema.org `learningResourceType`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            NoteKind::Lecture => "Lecture notes",
+            NoteKind::Exercise => "Exercise sheet",
+            NoteKind::Exam => "Exam",
+            NoteKind::Solution => "Solutions",
+            NoteKind::Cheatsheet => "Cheat sheet",
+        }
+    }
+}
+
+/// Structured metadata of a note, every field is optional.
+/// The lengths are the widths of the columns.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema, Validate)]
+pub struct NoteMetadata {
+    pub kind: Option<NoteKind>,
+    #[validate(length(max = 64))]
+    pub course_code: Option<String>,
+    #[validate(length(max = 64))]
+    pub term: Option<String>,
+    #[validate(length(max = 16))]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[validate(length(max = 255))]
+    pub license: Option<String>,
+}
+
+impl From<NoteMetadata> for db::lecture_notes::NoteMetadata {
+    fn from(value: NoteMetadata) -> Self {
+        Self {
+            kind: value.kind.map(|kind| kind.as_str().to_string()),
+            course_code: value.course_code,
+            term: value.term,
+            language: value.language,
+            authors: value.authors,
+            license: value.license,
+        }
+    }
+}
 
+#[derive(Default)]
 pub struct CreateNoteForm {
     pub name: String,
     pub description: String,
     pub url: String,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    pub metadata: NoteMetadata,
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the note stays hidden.
+    pub publish_at: Option<i64>,
+    /// The name slugified when None.
+    pub slug: Option<String>,
 }
 
+#[derive(Default)]
 pub struct UpdateNoteForm {
     pub name: Option<String>,
     pub description: Option<String>,
     pub url: Option<String>,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
-    pub subsection_id: Option<u32>,
+    /// `Some(None)` moves the note out of its subsection, to its section.
+    pub subsection_id: Option<Option<u32>>,
     pub position: Option<u32>,
+    /// `Some(None)` clears the metadata field, as for the fields below.
+    pub kind: Option<Option<NoteKind>>,
+    pub course_code: Option<Option<String>>,
+    pub term: Option<Option<String>>,
+    pub language: Option<Option<String>>,
+    pub authors: Option<Vec<String>>,
+    pub license: Option<Option<String>>,
+    pub status: Option<Status>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the note.
+    pub slug: Option<String>,
+    /// The version of the note the update was made against.
+    pub version: u32,
+}
+
+/// Sections and subsections are nodes of the content tree, so the most specific
+/// parent given wins.
+fn parent_node_id(
+    node_id: Option<u32>,
+    section_id: Option<u32>,
+    subsection_id: Option<u32>,
+) -> Option<u32> {
+    node_id.or(subsection_id).or(section_id)
+}
+
+/// Whether the subsection, when given along with a section, is one of its own.
+async fn parents_agree(
+    storage: &Storage,
+    section_id: Option<u32>,
+    subsection_id: Option<u32>,
+) -> Result<bool, Error> {
+    let (Some(section_id), Some(subsection_id)) = (section_id, subsection_id) else {
+        return Ok(true);
+    };
+    let res = crate::services::subsections::get_subsection(
+        storage,
+        subsection_id,
+        Visibility::Everything,
+    )
+    .await;
+    match res {
+        Ok(subsection) => Ok(subsection.section_id == section_id),
+        Err(crate::services::subsections::GetSubsectionError::NotFoundError) => Ok(false),
+        Err(crate::services::subsections::GetSubsectionError::UnexpectedError(err)) => Err(err),
+    }
+}
+
+/// Whether the node is a section, i.e. exists and has no parent.
+async fn is_section(storage: &Storage, id: u32) -> Result<bool, Error> {
+    let res = crate::services::sections::get_section(storage, id, Visibility::Everything).await;
+    match res {
+        Ok(_) => Ok(true),
+        Err(crate::services::sections::GetSectionError::NotFoundError) => Ok(false),
+        Err(crate::services::sections::GetSectionError::UnexpectedError(err)) => Err(err),
+    }
+}
+
+/// The node an update moves the note to, None when its parent stays. The
+/// parents given are merged into the current ones, which must still agree: a
+/// section given alone keeps the note in its subsection, which must then be one
+/// of that section, and a cleared subsection moves the note up to its section.
+async fn updated_node_id(
+    storage: &Storage,
+    id: u32,
+    form: &UpdateNoteForm,
+) -> Result<Option<u32>, UpdateNoteError> {
+    if form.node_id.is_some() {
+        return Ok(form.node_id);
+    }
+    let current = || async {
+        match get_note(storage, id, Visibility::Everything).await {
+            Ok(current) => Ok(current),
+            Err(GetNoteError::NotFoundError) => Err(UpdateNoteError::NotFoundError),
+            Err(GetNoteError::UnexpectedError(err)) => Err(UpdateNoteError::UnexpectedError(err)),
+        }
+    };
+    let (section_id, subsection_id) = match (form.section_id, form.subsection_id) {
+        (None, None) => return Ok(None),
+        (section_id, Some(Some(subsection_id))) => (section_id, subsection_id),
+        (Some(section_id), Some(None)) => return section_node_id(storage, section_id).await,
+        (None, Some(None)) => {
+            return match current().await?.section_id {
+                Some(section_id) => Ok(Some(section_id)),
+                None => Err(UpdateNoteError::NoSectionError),
+            }
+        }
+        (Some(section_id), None) => {
+            let current = current().await?;
+            match current.subsection_id {
+                Some(subsection_id) => (Some(section_id), subsection_id),
+                None if current.section_id == Some(section_id) => return Ok(None),
+                None => return section_node_id(storage, section_id).await,
+            }
+        }
+    };
+    if !parents_agree(storage, section_id, Some(subsection_id))
+        .await
+        .map_err(UpdateNoteError::UnexpectedError)?
+    {
+        return Err(UpdateNoteError::ParentMismatchError);
+    }
+    // A section given alone leaves the note where it is.
+    Ok(form.subsection_id.map(|_| subsection_id))
+}
+
+/// The section a note moves to directly, which must be a root node: a
+/// subsection given as the section would attach the note a level too deep.
+async fn section_node_id(
+    storage: &Storage,
+    section_id: u32,
+) -> Result<Option<u32>, UpdateNoteError> {
+    match is_section(storage, section_id).await {
+        Ok(true) => Ok(Some(section_id)),
+        Ok(false) => Err(UpdateNoteError::ParentMismatchError),
+        Err(err) => Err(UpdateNoteError::UnexpectedError(err)),
+    }
 }
 
+#[derive(Default)]
 pub struct GetNotesForm {
     pub id: Option<u32>,
     pub name: Option<String>,
     pub url: Option<String>,
     pub position: Option<u32>,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
-    pub limit: Option<u32>,
+    pub kind: Option<NoteKind>,
+    pub course_code: Option<String>,
+    pub term: Option<String>,
+    pub language: Option<String>,
+    pub author: Option<String>,
+    pub license: Option<String>,
+    pub tags: Vec<String>,
+    pub tag_match: db::tags::TagMatch,
+    pub visibility: Visibility,
+    pub page: PageRequest,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct NoteReturn {
     pub id: u32,
     pub name: String,
     pub description: String,
     pub url: String,
     pub position: u32,
+    pub node_id: Option<u32>,
     pub section_id: Option<u32>,
     pub subsection_id: Option<u32>,
+    #[serde(flatten)]
+    pub metadata: NoteMetadata,
+    pub tags: Vec<String>,
+    pub attachments: Vec<crate::services::attachments::AttachmentReturn>,
+    /// Seconds since the Unix epoch. `updated_at` also follows the tags and the attachments.
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Incremented by every update and move of the note, required by the next ones.
+    pub version: u32,
+    pub status: Status,
+    pub publish_at: Option<i64>,
+    /// Names the page of the note, unique among the notes of its node.
+    pub slug: String,
+    /// The slugs the note had before, oldest first.
+    pub previous_slugs: Vec<String>,
 }
 
 impl From<db::lecture_notes::NoteFromDb> for NoteReturn {
     fn from(value: db::lecture_notes::NoteFromDb) -> Self {
+        let previous_slugs = value.previous_slug_list();
+        let metadata = NoteMetadata {
+            kind: value.kind.as_deref().and_then(NoteKind::from_db),
+            authors: value.author_list(),
+            course_code: value.course_code,
+            term: value.term,
+            language: value.language,
+            license: value.license,
+        };
         Self {
             id: value.id,
             name: value.name,
             description: value.description,
             url: value.url,
             position: value.position,
+            node_id: value.node_id,
             section_id: value.section_id,
             subsection_id: value.subsection_id,
+            metadata,
+            tags: Vec::new(),
+            attachments: Vec::new(),
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: Status::from_db(&value.status),
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs,
+        }
+    }
+}
+
+impl Sortable for NoteReturn {
+    const DEFAULT_SORT: Sort = Sort::asc(SortField::Position);
+
+    fn sort_column(field: SortField) -> Option<&'static str> {
+        match field {
+            SortField::Position => Some("position"),
+            SortField::Name => Some("name"),
+            SortField::Id => Some("id"),
+            SortField::CreatedAt => Some("created_at"),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn sort_value(&self, field: SortField) -> Value {
+        match field {
+            SortField::Position => self.position.into(),
+            SortField::Name => self.name.clone().into(),
+            SortField::Id => self.id.into(),
+            SortField::CreatedAt => self.created_at.into(),
         }
     }
 }
 
+/// Fill in the tag names and the attachments of the notes.
+pub(crate) async fn with_details(
+    storage: &Storage,
+    mut notes: Vec<NoteReturn>,
+) -> Result<Vec<NoteReturn>, Error> {
+    let ids = notes.iter().map(|note| note.id).collect::<Vec<_>>();
+    let mut tags_by_note = crate::services::tags::get_tag_names_by_note(storage, &ids)
+        .await
+        .map_err(|crate::services::tags::GetTagsError::UnexpectedError(err)| err)?;
+    let mut attachments_by_note =
+        crate::services::attachments::get_attachments_by_note(storage, &ids)
+            .await
+            .map_err(
+                |crate::services::attachments::GetAttachmentsError::UnexpectedError(err)| err,
+            )?;
+    for note in notes.iter_mut() {
+        note.tags = tags_by_note.remove(&note.id).unwrap_or_default();
+        note.attachments = attachments_by_note.remove(&note.id).unwrap_or_default();
+    }
+    Ok(notes)
+}
+
+/// The slugs of the notes of `node_id` (or of the notes without a node), but the
+/// one with id `except`.
+async fn sibling_slugs(
+    storage: &Storage,
+    node_id: Option<u32>,
+    except: Option<u32>,
+) -> Result<HashSet<String>, Error> {
+    let notes = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            node_id,
+            filter: match node_id {
+                Some(_) => Filter::all(),
+                None => Filter::IsNull("node_id"),
+            },
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| err)?;
+    Ok(notes
+        .into_iter()
+        .filter(|note| Some(note.id) != except)
+        .map(|note| note.slug)
+        .collect())
+}
+
 #[derive(Debug)]
 pub enum CreateNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
+    InvalidSlugError,
+    SlugTakenError,
+    /// The subsection given is not in the section given.
+    ParentMismatchError,
 }
 
-pub async fn create_note(
-    pool: &sqlx::Pool<sqlx::MySql>,
-    form: CreateNoteForm,
-) -> Result<(), CreateNoteError> {
-    db::lecture_notes::create_note(
-        pool,
-        db::lecture_notes::CreateNoteForm {
+impl From<SlugError> for CreateNoteError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => CreateNoteError::InvalidSlugError,
+            SlugError::SlugTakenError => CreateNoteError::SlugTakenError,
+        }
+    }
+}
+
+pub async fn create_note(storage: &Storage, form: CreateNoteForm) -> Result<(), CreateNoteError> {
+    if !parents_agree(storage, form.section_id, form.subsection_id)
+        .await
+        .map_err(CreateNoteError::UnexpectedError)?
+    {
+        return Err(CreateNoteError::ParentMismatchError);
+    }
+    let node_id = parent_node_id(form.node_id, form.section_id, form.subsection_id);
+    let siblings = sibling_slugs(storage, node_id, None)
+        .await
+        .map_err(CreateNoteError::UnexpectedError)?;
+    let slug = db::slugs::slug_for_new(form.slug, &form.name, &siblings)?;
+    let res = storage
+        .notes
+        .create_note(db::lecture_notes::CreateNoteForm {
             name: form.name,
             description: form.description,
             url: form.url,
-            section_id: form.section_id,
-            subsection_id: form.subsection_id,
-        },
-    )
-    .await
-    .map_err(|_| CreateNoteError::UnexpectedError)
+            node_id,
+            metadata: form.metadata.into(),
+            status: Some(form.status.as_str().to_string()),
+            publish_at: form.publish_at,
+            slug: Some(slug),
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(CreateNoteError::UnexpectedError)
 }
 
 #[derive(Debug)]
 pub enum GetNotesError {
-    UnexpectedError,
+    PageError(PageError),
+    UnexpectedError(Error),
 }
 
 pub async fn get_notes(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: GetNotesForm,
-) -> Result<Vec<NoteReturn>, GetNotesError> {
-    let res = db::lecture_notes::get_notes(
-        pool,
-        db::lecture_notes::GetNotesForm {
-            id: form.id,
-            name: form.name,
-            url: form.url,
-            position: form.position,
-            section_id: form.section_id,
-            subsection_id: form.subsection_id,
-            limit: form.limit,
-            ..Default::default()
-        },
-    )
-    .await;
-    match res {
-        Ok(list) => Ok(list.into_iter().map(NoteReturn::from).collect()),
-        Err(db::lecture_notes::GetNotesError::UnexpectedError) => {
-            Err(GetNotesError::UnexpectedError)
-        }
-    }
+) -> Result<Page<NoteReturn>, GetNotesError> {
+    let clauses = form
+        .page
+        .clauses::<NoteReturn>()
+        .map_err(GetNotesError::PageError)?;
+    let filters = || db::lecture_notes::GetNotesForm {
+        id: form.id,
+        name: form.name.clone(),
+        url: form.url.clone(),
+        position: form.position,
+        node_id: form.node_id,
+        section_id: form.section_id,
+        subsection_id: form.subsection_id,
+        kind: form.kind.map(|kind| kind.as_str().to_string()),
+        course_code: form.course_code.clone(),
+        term: form.term.clone(),
+        language: form.language.clone(),
+        author: form.author.clone(),
+        license: form.license.clone(),
+        tags: form.tags.clone(),
+        tag_match: form.tag_match.clone(),
+        published_at: form.visibility.published_at(),
+        ..Default::default()
+    };
+    let list = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            filter: clauses.filter,
+            order_by: clauses.order_by,
+            limit: clauses.limit,
+            offset: clauses.offset,
+            ..filters()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+            GetNotesError::UnexpectedError(err)
+        })?;
+    let total = match form.page.known_total(list.len()) {
+        Some(total) => total,
+        None => storage.notes.count_notes(filters()).await.map_err(
+            |db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+                GetNotesError::UnexpectedError(err)
+            },
+        )?,
+    };
+    let list = list.into_iter().map(NoteReturn::from).collect();
+    let mut page = form.page.page(list, total);
+    page.items = with_details(storage, page.items)
+        .await
+        .map_err(GetNotesError::UnexpectedError)?;
+    Ok(page)
 }
 
 #[derive(Debug)]
 pub enum GetNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
-pub async fn get_note(pool: &sqlx::Pool<sqlx::MySql>, id: u32) -> Result<NoteReturn, GetNoteError> {
-    let res = db::lecture_notes::get_note(
-        pool,
-        db::lecture_notes::GetNotesForm {
+pub async fn get_note(
+    storage: &Storage,
+    id: u32,
+    visibility: Visibility,
+) -> Result<NoteReturn, GetNoteError> {
+    let res = storage
+        .notes
+        .get_note(db::lecture_notes::GetNotesForm {
             id: Some(id),
+            published_at: visibility.published_at(),
             ..Default::default()
-        },
-    )
-    .await;
+        })
+        .await;
     match res {
-        Ok(val) => Ok(NoteReturn::from(val)),
+        Ok(val) => with_details(storage, vec![NoteReturn::from(val)])
+            .await
+            .map(|mut list| list.swap_remove(0))
+            .map_err(GetNoteError::UnexpectedError),
         Err(db::lecture_notes::GetNoteError::NotFoundError) => Err(GetNoteError::NotFoundError),
-        Err(db::lecture_notes::GetNoteError::UnexpectedError) => Err(GetNoteError::UnexpectedError),
+        Err(db::lecture_notes::GetNoteError::UnexpectedError(err)) => {
+            Err(GetNoteError::UnexpectedError(err))
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum UpdateNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    InvalidSlugError,
+    SlugTakenError,
+    /// The subsection given, or the one of the note, is not in the section given.
+    ParentMismatchError,
+    /// The subsection was cleared but the note has no section to move up to.
+    NoSectionError,
+    /// The note moved past the expected version, it is returned as it is now.
+    VersionConflictError(Box<NoteReturn>),
+}
+
+impl From<SlugError> for UpdateNoteError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => UpdateNoteError::InvalidSlugError,
+            SlugError::SlugTakenError => UpdateNoteError::SlugTakenError,
+        }
+    }
 }
 
 pub async fn update_note(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     id: u32,
     form: UpdateNoteForm,
 ) -> Result<(), UpdateNoteError> {
-    let res = db::lecture_notes::update_notes(
-        pool,
-        db::lecture_notes::UpdateNoteForm {
-            name: form.name,
-            description: form.description,
-            url: form.url,
-            section_id: form.section_id,
-            subsection_id: form.subsection_id,
-            position: form.position,
-        },
-        db::lecture_notes::GetNotesForm {
-            id: Some(id),
-            ..Default::default()
-        },
-    )
-    .await;
+    let node_id = updated_node_id(storage, id, &form).await?;
+    // Moving to another node can clash with the slug of a note there too.
+    let slug_change = if form.slug.is_some() || node_id.is_some() {
+        let current = match get_note(storage, id, Visibility::Everything).await {
+            Ok(current) => current,
+            Err(GetNoteError::NotFoundError) => return Err(UpdateNoteError::NotFoundError),
+            Err(GetNoteError::UnexpectedError(err)) => {
+                return Err(UpdateNoteError::UnexpectedError(err))
+            }
+        };
+        let siblings = sibling_slugs(storage, node_id.or(current.node_id), Some(id))
+            .await
+            .map_err(UpdateNoteError::UnexpectedError)?;
+        db::slugs::slug_update(&current.slug, current.previous_slugs, form.slug, &siblings)?
+    } else {
+        None
+    };
+    let (slug, previous_slugs) = match slug_change {
+        Some(change) => (Some(change.slug), Some(change.previous_slugs)),
+        None => (None, None),
+    };
+    let res = storage
+        .notes
+        .update_notes(
+            db::lecture_notes::UpdateNoteForm {
+                name: form.name,
+                description: form.description,
+                url: form.url,
+                node_id,
+                position: form.position,
+                kind: form
+                    .kind
+                    .map(|kind| kind.map(|kind| kind.as_str().to_string())),
+                course_code: form.course_code,
+                term: form.term,
+                language: form.language,
+                authors: form.authors,
+                license: form.license,
+                status: form.status.map(|status| status.as_str().to_string()),
+                publish_at: form.publish_at,
+                slug,
+                previous_slugs,
+                version: Some(form.version),
+            },
+            db::lecture_notes::GetNotesForm {
+                id: Some(id),
+                ..Default::default()
+            },
+        )
+        .await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::lecture_notes::UpdateNotesError::NotFoundError) => {
@@ -165,38 +615,60 @@ pub async fn update_note(
         Err(db::lecture_notes::UpdateNotesError::NothingToUpdateError) => {
             Err(UpdateNoteError::NothingToUpdateError)
         }
-        Err(db::lecture_notes::UpdateNotesError::UnexpectedError) => {
-            Err(UpdateNoteError::UnexpectedError)
+        Err(db::lecture_notes::UpdateNotesError::VersionConflictError) => {
+            match get_note(storage, id, Visibility::Everything).await {
+                Ok(current) => Err(UpdateNoteError::VersionConflictError(Box::new(current))),
+                Err(GetNoteError::NotFoundError) => Err(UpdateNoteError::NotFoundError),
+                Err(GetNoteError::UnexpectedError(err)) => {
+                    Err(UpdateNoteError::UnexpectedError(err))
+                }
+            }
+        }
+        Err(db::lecture_notes::UpdateNotesError::UnexpectedError(err)) => {
+            Err(UpdateNoteError::UnexpectedError(err))
         }
     }
 }
 
 #[derive(Debug)]
 pub enum DeleteNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
-pub async fn delete_note(pool: &sqlx::Pool<sqlx::MySql>, id: u32) -> Result<(), DeleteNoteError> {
-    db::lecture_notes::delete_note(
-        pool,
-        db::lecture_notes::GetNotesForm {
+pub async fn delete_note(storage: &Storage, id: u32) -> Result<(), DeleteNoteError> {
+    let res = storage
+        .notes
+        .delete_note(db::lecture_notes::GetNotesForm {
             id: Some(id),
             ..Default::default()
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(
+        |db::lecture_notes::DeleteNotesError::UnexpectedError(err)| {
+            DeleteNoteError::UnexpectedError(err)
         },
     )
-    .await
-    .map_err(|_| DeleteNoteError::UnexpectedError)
 }
 
 #[derive(Debug)]
 pub enum MoveNoteError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError(Option<u32>, Option<u32>),
     CantSwapFromDifferentSubsections,
+    /// A note moved past its expected version, both are returned as they are now.
+    VersionConflictError(Vec<NoteReturn>),
 }
 
-pub async fn move_note(pool: &sqlx::Pool<sqlx::MySql>, ids: [u32; 2]) -> Result<(), MoveNoteError> {
-    let res = db::lecture_notes::swap_notes(pool, ids).await;
+/// Swap the positions of two notes of a node, written only at their expected
+/// `versions`.
+pub async fn move_note(
+    storage: &Storage,
+    ids: [u32; 2],
+    versions: [u32; 2],
+) -> Result<(), MoveNoteError> {
+    let res = storage.notes.swap_notes(ids, versions).await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::lecture_notes::SwapNotesError::NotFoundError(tuple)) => {
@@ -205,8 +677,27 @@ pub async fn move_note(pool: &sqlx::Pool<sqlx::MySql>, ids: [u32; 2]) -> Result<
         Err(db::lecture_notes::SwapNotesError::CantSwapFromDifferentSubsections) => {
             Err(MoveNoteError::CantSwapFromDifferentSubsections)
         }
-        Err(db::lecture_notes::SwapNotesError::UnexpectedError) => {
-            Err(MoveNoteError::UnexpectedError)
+        Err(db::lecture_notes::SwapNotesError::VersionConflictError) => {
+            let (first, second) = (
+                get_note(storage, ids[0], Visibility::Everything).await,
+                get_note(storage, ids[1], Visibility::Everything).await,
+            );
+            match (first, second) {
+                (Ok(first), Ok(second)) => {
+                    Err(MoveNoteError::VersionConflictError(vec![first, second]))
+                }
+                (Err(GetNoteError::UnexpectedError(err)), _)
+                | (_, Err(GetNoteError::UnexpectedError(err))) => {
+                    Err(MoveNoteError::UnexpectedError(err))
+                }
+                (first, second) => Err(MoveNoteError::NotFoundError(
+                    first.err().map(|_| ids[0]),
+                    second.err().map(|_| ids[1]),
+                )),
+            }
+        }
+        Err(db::lecture_notes::SwapNotesError::UnexpectedError(err)) => {
+            Err(MoveNoteError::UnexpectedError(err))
         }
     }
 }
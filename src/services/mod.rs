@@ -1,5 +1,16 @@
+pub mod attachments;
+pub mod batch;
+pub mod catalog;
+pub mod files;
 pub mod lecture_notes;
+pub mod nodes;
+pub mod note_import;
+pub mod pagination;
+pub mod publishing;
+pub mod search;
 pub mod sections;
 pub mod static_pages;
 pub mod subsections;
+pub mod tags;
+pub mod tree;
 pub mod users;
@@ -0,0 +1,432 @@
+use std::collections::HashSet;
+
+use crate::db;
+use crate::db::query::OrderBy;
+use crate::db::slugs::SlugError;
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::lecture_notes::NoteReturn;
+use crate::services::publishing::{Status, Visibility};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub struct CreateNodeForm {
+    pub parent_id: Option<u32>,
+    pub kind: String,
+    pub title: String,
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the node and its subtree stay hidden.
+    pub publish_at: Option<i64>,
+    /// The title slugified when None.
+    pub slug: Option<String>,
+}
+
+pub struct UpdateNodeForm {
+    pub title: Option<String>,
+    pub kind: Option<String>,
+    pub parent_id: Option<u32>,
+    pub position: Option<u32>,
+    pub status: Option<Status>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the node.
+    pub slug: Option<String>,
+    /// The version of the node the update was made against.
+    pub version: u32,
+}
+
+pub struct GetNodesForm {
+    pub id: Option<u32>,
+    pub parent_id: Option<u32>,
+    pub kind: Option<String>,
+    pub title: Option<String>,
+    pub limit: Option<u32>,
+    pub visibility: Visibility,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct NodeReturn {
+    pub id: u32,
+    pub parent_id: Option<u32>,
+    pub kind: String,
+    pub title: String,
+    pub position: u32,
+    /// Seconds since the Unix epoch.
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Incremented by every update and move of the node.
+    pub version: u32,
+    pub status: Status,
+    pub publish_at: Option<i64>,
+    /// Names the pages of the node, unique among its siblings.
+    pub slug: String,
+    /// The slugs the node had before, oldest first.
+    pub previous_slugs: Vec<String>,
+}
+
+impl From<db::nodes::NodeFromDb> for NodeReturn {
+    fn from(value: db::nodes::NodeFromDb) -> Self {
+        let previous_slugs = value.previous_slug_list();
+        Self {
+            id: value.id,
+            parent_id: value.parent_id,
+            kind: value.kind,
+            title: value.title,
+            position: value.position,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: Status::from_db(&value.status),
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs,
+        }
+    }
+}
+
+/// A node with its children and notes, both sorted by position.
+#[derive(Serialize, ToSchema)]
+pub struct TreeNode {
+    pub id: u32,
+    pub parent_id: Option<u32>,
+    pub kind: String,
+    pub title: String,
+    pub position: u32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub version: u32,
+    pub status: Status,
+    pub publish_at: Option<i64>,
+    pub slug: String,
+    pub previous_slugs: Vec<String>,
+    #[schema(no_recursion)]
+    pub children: Vec<TreeNode>,
+    pub notes: Vec<NoteReturn>,
+}
+
+impl TreeNode {
+    /// The latest `updated_at` of the node, its notes and its descendants.
+    pub fn last_updated(&self) -> i64 {
+        let notes = self.notes.iter().map(|note| note.updated_at);
+        let children = self.children.iter().map(TreeNode::last_updated);
+        notes.chain(children).fold(self.updated_at, i64::max)
+    }
+}
+
+/// The slugs of the children of `parent_id` (or of the root nodes), but the one
+/// with id `except`.
+async fn sibling_slugs(
+    storage: &Storage,
+    parent_id: Option<u32>,
+    except: Option<u32>,
+) -> Result<HashSet<String>, Error> {
+    let nodes = storage
+        .nodes
+        .get_nodes(db::nodes::GetNodesForm {
+            parent_id,
+            is_root: parent_id.is_none().then_some(true),
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::nodes::GetNodesError::UnexpectedError(err)| err)?;
+    Ok(nodes
+        .into_iter()
+        .filter(|node| Some(node.id) != except)
+        .map(|node| node.slug)
+        .collect())
+}
+
+#[derive(Debug)]
+pub enum CreateNodeError {
+    UnexpectedError(Error),
+    ParentNotFoundError,
+    InvalidSlugError,
+    SlugTakenError,
+}
+
+impl From<SlugError> for CreateNodeError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => CreateNodeError::InvalidSlugError,
+            SlugError::SlugTakenError => CreateNodeError::SlugTakenError,
+        }
+    }
+}
+
+pub async fn create_node(storage: &Storage, form: CreateNodeForm) -> Result<u32, CreateNodeError> {
+    let siblings = sibling_slugs(storage, form.parent_id, None)
+        .await
+        .map_err(CreateNodeError::UnexpectedError)?;
+    let slug = db::slugs::slug_for_new(form.slug, &form.title, &siblings)?;
+    let res = storage
+        .nodes
+        .create_node(db::nodes::CreateNodeForm {
+            parent_id: form.parent_id,
+            kind: form.kind,
+            title: form.title,
+            status: Some(form.status.as_str().to_string()),
+            publish_at: form.publish_at,
+            slug: Some(slug),
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::nodes::CreateNodeError::ParentNotFoundError => CreateNodeError::ParentNotFoundError,
+        db::nodes::CreateNodeError::UnexpectedError(err) => CreateNodeError::UnexpectedError(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum GetNodesError {
+    UnexpectedError(Error),
+}
+
+pub async fn get_nodes(
+    storage: &Storage,
+    form: GetNodesForm,
+) -> Result<Vec<NodeReturn>, GetNodesError> {
+    let res = storage
+        .nodes
+        .get_nodes(db::nodes::GetNodesForm {
+            id: form.id,
+            parent_id: form.parent_id,
+            kind: form.kind,
+            title: form.title,
+            limit: form.limit,
+            published_at: form.visibility.published_at(),
+            ..Default::default()
+        })
+        .await;
+    match res {
+        Ok(list) => Ok(list.into_iter().map(NodeReturn::from).collect()),
+        Err(db::nodes::GetNodesError::UnexpectedError(err)) => {
+            Err(GetNodesError::UnexpectedError(err))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GetNodeError {
+    UnexpectedError(Error),
+    NotFoundError,
+}
+
+pub async fn get_node(
+    storage: &Storage,
+    id: u32,
+    visibility: Visibility,
+) -> Result<NodeReturn, GetNodeError> {
+    let res = storage
+        .nodes
+        .get_node(db::nodes::GetNodesForm {
+            id: Some(id),
+            published_at: visibility.published_at(),
+            ..Default::default()
+        })
+        .await;
+    match res {
+        Ok(val) => Ok(NodeReturn::from(val)),
+        Err(db::nodes::GetNodeError::NotFoundError) => Err(GetNodeError::NotFoundError),
+        Err(db::nodes::GetNodeError::UnexpectedError(err)) => {
+            Err(GetNodeError::UnexpectedError(err))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UpdateNodeError {
+    UnexpectedError(Error),
+    NotFoundError,
+    NothingToUpdateError,
+    CycleError,
+    InvalidSlugError,
+    SlugTakenError,
+    /// The node moved past the expected version, it is returned as it is now.
+    VersionConflictError(NodeReturn),
+}
+
+impl From<SlugError> for UpdateNodeError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => UpdateNodeError::InvalidSlugError,
+            SlugError::SlugTakenError => UpdateNodeError::SlugTakenError,
+        }
+    }
+}
+
+/// Update a node. When the node is moved under another parent without an explicit
+/// position, it is appended after the last child of its new parent, and its slug
+/// gets a suffix if a sibling there already uses it.
+pub async fn update_node(
+    storage: &Storage,
+    id: u32,
+    form: UpdateNodeForm,
+) -> Result<(), UpdateNodeError> {
+    let slug_change = if form.slug.is_some() || form.parent_id.is_some() {
+        let current = match get_node(storage, id, Visibility::Everything).await {
+            Ok(current) => current,
+            Err(GetNodeError::NotFoundError) => return Err(UpdateNodeError::NotFoundError),
+            Err(GetNodeError::UnexpectedError(err)) => {
+                return Err(UpdateNodeError::UnexpectedError(err))
+            }
+        };
+        let siblings = sibling_slugs(storage, form.parent_id.or(current.parent_id), Some(id))
+            .await
+            .map_err(UpdateNodeError::UnexpectedError)?;
+        db::slugs::slug_update(&current.slug, current.previous_slugs, form.slug, &siblings)?
+    } else {
+        None
+    };
+    let (slug, previous_slugs) = match slug_change {
+        Some(change) => (Some(change.slug), Some(change.previous_slugs)),
+        None => (None, None),
+    };
+    let position = match (form.parent_id, form.position) {
+        (Some(parent_id), None) => Some(
+            next_position(storage, parent_id)
+                .await
+                .map_err(UpdateNodeError::UnexpectedError)?,
+        ),
+        (_, position) => position,
+    };
+    let res = storage
+        .nodes
+        .update_nodes(
+            db::nodes::UpdateNodeForm {
+                title: form.title,
+                kind: form.kind,
+                parent_id: form.parent_id,
+                position,
+                status: form.status.map(|status| status.as_str().to_string()),
+                publish_at: form.publish_at,
+                slug,
+                previous_slugs,
+                version: Some(form.version),
+            },
+            db::nodes::GetNodesForm {
+                id: Some(id),
+                ..Default::default()
+            },
+        )
+        .await;
+    storage.content_changed();
+    match res {
+        Ok(()) => Ok(()),
+        Err(db::nodes::UpdateNodesError::NotFoundError) => Err(UpdateNodeError::NotFoundError),
+        Err(db::nodes::UpdateNodesError::NothingToUpdateError) => {
+            Err(UpdateNodeError::NothingToUpdateError)
+        }
+        Err(db::nodes::UpdateNodesError::CycleError) => Err(UpdateNodeError::CycleError),
+        Err(db::nodes::UpdateNodesError::UnexpectedError(err)) => {
+            Err(UpdateNodeError::UnexpectedError(err))
+        }
+        Err(db::nodes::UpdateNodesError::VersionConflictError) => {
+            match get_node(storage, id, Visibility::Everything).await {
+                Ok(current) => Err(UpdateNodeError::VersionConflictError(current)),
+                Err(GetNodeError::NotFoundError) => Err(UpdateNodeError::NotFoundError),
+                Err(GetNodeError::UnexpectedError(err)) => {
+                    Err(UpdateNodeError::UnexpectedError(err))
+                }
+            }
+        }
+    }
+}
+
+/// The position after the last child of `parent_id`.
+async fn next_position(storage: &Storage, parent_id: u32) -> Result<u32, Error> {
+    let last = storage
+        .nodes
+        .get_nodes(db::nodes::GetNodesForm {
+            parent_id: Some(parent_id),
+            order_by: vec![OrderBy::desc("position")],
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::nodes::GetNodesError::UnexpectedError(err)| err)?;
+    Ok(last.first().map_or(0, |node| node.position + 1))
+}
+
+#[derive(Debug)]
+pub enum DeleteNodeError {
+    UnexpectedError(Error),
+}
+
+pub async fn delete_node(storage: &Storage, id: u32) -> Result<(), DeleteNodeError> {
+    let res = storage
+        .nodes
+        .delete_node(db::nodes::GetNodesForm {
+            id: Some(id),
+            ..Default::default()
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(|db::nodes::DeleteNodesError::UnexpectedError(err)| {
+        DeleteNodeError::UnexpectedError(err)
+    })
+}
+
+#[derive(Debug)]
+pub enum MoveNodeError {
+    UnexpectedError(Error),
+    NotFoundError(Option<u32>, Option<u32>),
+    CantSwapFromDifferentParents,
+    /// A node moved past its expected version, both are returned as they are now.
+    VersionConflictError(Vec<NodeReturn>),
+}
+
+/// Swap the positions of two sibling nodes, written only at their expected
+/// `versions`.
+pub async fn move_node(
+    storage: &Storage,
+    ids: [u32; 2],
+    versions: [u32; 2],
+) -> Result<(), MoveNodeError> {
+    let res = storage.nodes.swap_nodes(ids, versions).await;
+    storage.content_changed();
+    match res {
+        Ok(()) => Ok(()),
+        Err(db::nodes::SwapNodesError::NotFoundError(tuple)) => {
+            Err(MoveNodeError::NotFoundError(tuple.0, tuple.1))
+        }
+        Err(db::nodes::SwapNodesError::CantSwapFromDifferentParents) => {
+            Err(MoveNodeError::CantSwapFromDifferentParents)
+        }
+        Err(db::nodes::SwapNodesError::VersionConflictError) => {
+            let (first, second) = (
+                get_node(storage, ids[0], Visibility::Everything).await,
+                get_node(storage, ids[1], Visibility::Everything).await,
+            );
+            match (first, second) {
+                (Ok(first), Ok(second)) => {
+                    Err(MoveNodeError::VersionConflictError(vec![first, second]))
+                }
+                (Err(GetNodeError::UnexpectedError(err)), _)
+                | (_, Err(GetNodeError::UnexpectedError(err))) => {
+                    Err(MoveNodeError::UnexpectedError(err))
+                }
+                (first, second) => Err(MoveNodeError::NotFoundError(
+                    first.err().map(|_| ids[0]),
+                    second.err().map(|_| ids[1]),
+                )),
+            }
+        }
+        Err(db::nodes::SwapNodesError::UnexpectedError(err)) => {
+            Err(MoveNodeError::UnexpectedError(err))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GetTreeError {
+    UnexpectedError(Error),
+}
+
+/// Load the content tree visible with `visibility`: the root nodes with their
+/// descendants and notes. Notes that are not attached to any node are left out.
+pub async fn get_tree(
+    storage: &Storage,
+    visibility: Visibility,
+) -> Result<Vec<TreeNode>, GetTreeError> {
+    crate::services::tree::get_node_trees(storage, None, visibility).await
+}
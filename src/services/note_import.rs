@@ -0,0 +1,552 @@
+use std::str::Chars;
+
+use loggit::warn;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+use yaml_rust2::parser::Parser;
+use yaml_rust2::scanner::TScalarStyle;
+use yaml_rust2::{Event, ScanError};
+
+use crate::db;
+use crate::error::{Error, FieldError};
+use crate::repository::Storage;
+use crate::routes::validation::{allowed_url, not_blank, validation_error, MAX_NAME_LENGTH};
+use crate::services::lecture_notes::{self, CreateNoteForm, UpdateNoteForm};
+use crate::services::nodes::{self, CreateNodeForm};
+
+/// The columns a CSV file must name in its header, `subsection` and
+/// `description` being optional.
+const REQUIRED_COLUMNS: [&str; 3] = ["section", "name", "url"];
+
+/// The plain YAML scalars read as null, which leave their field out.
+const YAML_NULLS: [&str; 5] = ["", "~", "null", "Null", "NULL"];
+
+/// The formats the notes can be imported from.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteFileFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// A list of mappings.
+    Yaml,
+}
+
+impl NoteFileFormat {
+    /// The format of a file, from its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(NoteFileFormat::Csv),
+            "yaml" | "yml" => Some(NoteFileFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The format of a request body, from its `Content-Type`.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime.to_ascii_lowercase().as_str() {
+            "text/csv" => Some(NoteFileFormat::Csv),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(NoteFileFormat::Yaml)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One note to import, along with the section and subsection it goes under.
+/// It follows the rules of the routes creating them.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq, Validate)]
+pub struct NoteRow {
+    #[validate(custom(function = not_blank), length(max = MAX_NAME_LENGTH))]
+    pub section: String,
+    #[serde(default)]
+    #[validate(length(max = MAX_NAME_LENGTH))]
+    pub subsection: Option<String>,
+    #[validate(custom(function = not_blank))]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[validate(custom(function = allowed_url))]
+    pub url: String,
+}
+
+impl NoteRow {
+    /// The row with its texts trimmed, an empty subsection left out.
+    fn trimmed(&self) -> NoteRow {
+        NoteRow {
+            section: self.section.trim().to_string(),
+            subsection: self
+                .subsection
+                .as_deref()
+                .map(str::trim)
+                .filter(|subsection| !subsection.is_empty())
+                .map(str::to_string),
+            name: self.name.trim().to_string(),
+            description: self.description.trim().to_string(),
+            url: self.url.trim().to_string(),
+        }
+    }
+}
+
+/// A row of the file, or the reason it could not be read.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParsedRow {
+    /// The line the row starts at, from 1.
+    pub line: u64,
+    pub row: Result<NoteRow, String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseNotesError {
+    /// The file cannot be read as a whole, the message says why.
+    InvalidFileError(String),
+}
+
+/// Read the rows of a CSV or YAML file. A row that cannot be read is kept as an
+/// error, so the others can still be imported.
+pub fn parse_rows(format: NoteFileFormat, text: &str) -> Result<Vec<ParsedRow>, ParseNotesError> {
+    match format {
+        NoteFileFormat::Csv => parse_csv(text),
+        NoteFileFormat::Yaml => parse_yaml(text),
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<ParsedRow>, ParseNotesError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|err| ParseNotesError::InvalidFileError(err.to_string()))?
+        .clone();
+    for column in REQUIRED_COLUMNS {
+        if !headers.iter().any(|header| header == column) {
+            return Err(ParseNotesError::InvalidFileError(format!(
+                "the header has no `{column}` column"
+            )));
+        }
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let parsed = match record {
+            Ok(record) => ParsedRow {
+                line: record.position().map(|pos| pos.line()).unwrap_or_default(),
+                row: record
+                    .deserialize::<NoteRow>(Some(&headers))
+                    .map_err(|err| err.to_string()),
+            },
+            Err(err) => ParsedRow {
+                line: err.position().map(|pos| pos.line()).unwrap_or_default(),
+                row: Err(match err.kind() {
+                    csv::ErrorKind::UnequalLengths { len, .. } => format!(
+                        "the row has {len} fields while the header has {}",
+                        headers.len()
+                    ),
+                    _ => err.to_string(),
+                }),
+            },
+        };
+        rows.push(parsed);
+    }
+    Ok(rows)
+}
+
+/// The YAML file is a list of mappings, one per row, in any style. The events of
+/// the parser mark where each row starts. A syntax error ends the reading, the
+/// rows before it being kept.
+fn parse_yaml(text: &str) -> Result<Vec<ParsedRow>, ParseNotesError> {
+    let mut parser = Parser::new_from_str(text);
+    let file_error = |err: ScanError| ParseNotesError::InvalidFileError(yaml_error(&err));
+    loop {
+        match parser.next_token().map_err(file_error)?.0 {
+            Event::StreamStart | Event::DocumentStart => {}
+            Event::SequenceStart(..) => break,
+            Event::StreamEnd => return Ok(Vec::new()),
+            _ => {
+                return Err(ParseNotesError::InvalidFileError(
+                    "expected a list of rows".to_string(),
+                ))
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    loop {
+        let (line, res) = match parser.next_token() {
+            Ok((Event::SequenceEnd, _)) => break,
+            Ok((event, mark)) => (mark.line() as u64, read_yaml_row(&mut parser, event)),
+            Err(err) => (err.marker().line() as u64, Err(err)),
+        };
+        match res {
+            Ok(row) => rows.push(ParsedRow { line, row }),
+            Err(err) => {
+                rows.push(ParsedRow {
+                    line,
+                    row: Err(format!(
+                        "{}, the rows after it cannot be read",
+                        yaml_error(&err)
+                    )),
+                });
+                break;
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn yaml_error(err: &ScanError) -> String {
+    format!("{} at line {}", err.info(), err.marker().line())
+}
+
+/// Read the item starting with `first`, a mapping of texts for a valid row.
+fn read_yaml_row(
+    parser: &mut Parser<Chars>,
+    first: Event,
+) -> Result<Result<NoteRow, String>, ScanError> {
+    if !matches!(first, Event::MappingStart(..)) {
+        skip_yaml_node(parser, &first)?;
+        return Ok(Err("expected a mapping".to_string()));
+    }
+    let mut fields = serde_json::Map::new();
+    let mut error = None;
+    loop {
+        let key = match parser.next_token()?.0 {
+            Event::MappingEnd => break,
+            Event::Scalar(key, ..) => Some(key),
+            event => {
+                skip_yaml_node(parser, &event)?;
+                None
+            }
+        };
+        let value = match parser.next_token()?.0 {
+            Event::Scalar(value, TScalarStyle::Plain, ..)
+                if YAML_NULLS.contains(&value.as_str()) =>
+            {
+                continue
+            }
+            Event::Scalar(value, ..) => Some(value),
+            event => {
+                skip_yaml_node(parser, &event)?;
+                None
+            }
+        };
+        match (key, value) {
+            (Some(key), Some(value)) => {
+                fields.insert(key, serde_json::Value::String(value));
+            }
+            (Some(key), None) => {
+                error.get_or_insert(format!("the `{key}` is not a text"));
+            }
+            (None, _) => {
+                error.get_or_insert("the keys must be texts".to_string());
+            }
+        }
+    }
+    Ok(match error {
+        Some(message) => Err(message),
+        None => {
+            serde_json::from_value(serde_json::Value::Object(fields)).map_err(|err| err.to_string())
+        }
+    })
+}
+
+/// Skip the rest of the node starting with `first`, a sequence or a mapping.
+fn skip_yaml_node(parser: &mut Parser<Chars>, first: &Event) -> Result<(), ScanError> {
+    let mut depth = match first {
+        Event::SequenceStart(..) | Event::MappingStart(..) => 1,
+        _ => 0,
+    };
+    while depth > 0 {
+        match parser.next_token()?.0 {
+            Event::SequenceStart(..) | Event::MappingStart(..) => depth += 1,
+            Event::SequenceEnd | Event::MappingEnd => depth -= 1,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A row that was not imported.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+pub struct RowError {
+    pub line: u64,
+    pub message: String,
+    /// The fields breaking their rules, empty when the row failed otherwise.
+    pub details: Vec<FieldError>,
+}
+
+/// What an import did, row errors included.
+#[derive(Serialize, Debug, Default, PartialEq, Eq, ToSchema)]
+pub struct ImportNotesReport {
+    pub rows: usize,
+    /// The titles of the sections created, in order.
+    pub created_sections: Vec<String>,
+    /// The created subsections, as `section / subsection`.
+    pub created_subsections: Vec<String>,
+    pub created_notes: usize,
+    pub updated_notes: usize,
+    pub errors: Vec<RowError>,
+}
+
+#[derive(Debug)]
+pub enum ImportNotesError {
+    UnexpectedError(Error),
+}
+
+/// A section or subsection already stored or created by the import.
+struct KnownNode {
+    id: u32,
+    parent_id: Option<u32>,
+    title: String,
+}
+
+/// Import the rows in order. The sections and subsections are matched by title
+/// and created when missing, and a row whose URL is used by a note updates that
+/// note instead of creating another one. A failing row is reported and skipped,
+/// even when the storage failed on it.
+pub async fn import_notes(
+    storage: &Storage,
+    rows: Vec<ParsedRow>,
+) -> Result<ImportNotesReport, ImportNotesError> {
+    let mut known = known_nodes(storage).await?;
+    let mut report = ImportNotesReport {
+        rows: rows.len(),
+        ..Default::default()
+    };
+
+    for ParsedRow { line, row } in rows {
+        let res = match row {
+            Ok(row) => import_row(storage, &mut known, &mut report, row).await,
+            Err(message) => Err(RowFailure::Invalid(message)),
+        };
+        let (message, details) = match res {
+            Ok(()) => continue,
+            Err(RowFailure::Invalid(message)) => (message, Vec::new()),
+            Err(RowFailure::InvalidFields(details)) => {
+                let message = details
+                    .iter()
+                    .map(|detail| format!("the `{}` {}", detail.field, detail.message))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (message, details)
+            }
+            Err(RowFailure::Storage(err)) => {
+                warn!("The row at line {} could not be imported: {:?}", line, err);
+                ("the row could not be stored".to_string(), Vec::new())
+            }
+        };
+        report.errors.push(RowError {
+            line,
+            message,
+            details,
+        });
+    }
+    Ok(report)
+}
+async fn known_nodes(storage: &Storage) -> Result<Vec<KnownNode>, ImportNotesError> {
+    let sections = storage
+        .sections
+        .get_sections(Default::default())
+        .await
+        .map_err(|db::sections::GetSectionsError::UnexpectedError(err)| {
+            ImportNotesError::UnexpectedError(err)
+        })?;
+    let subsections = storage
+        .subsections
+        .get_subsections(Default::default())
+        .await
+        .map_err(
+            |db::subsections::GetSubsectionsError::UnexpectedError(err)| {
+                ImportNotesError::UnexpectedError(err)
+            },
+        )?;
+    Ok(sections
+        .into_iter()
+        .map(|section| KnownNode {
+            id: section.id,
+            parent_id: None,
+            title: section.title,
+        })
+        .chain(subsections.into_iter().map(|subsection| KnownNode {
+            id: subsection.id,
+            parent_id: Some(subsection.section_id),
+            title: subsection.title,
+        }))
+        .collect())
+}
+
+enum RowFailure {
+    /// The row is skipped, the message says why.
+    Invalid(String),
+    /// The row breaks the rules of these fields.
+    InvalidFields(Vec<FieldError>),
+    /// The storage failed on the row, which is skipped too.
+    Storage(Error),
+}
+
+async fn import_row(
+    storage: &Storage,
+    known: &mut Vec<KnownNode>,
+    report: &mut ImportNotesReport,
+    row: NoteRow,
+) -> Result<(), RowFailure> {
+    let row = row.trimmed();
+    if let Err(errors) = row.validate() {
+        return Err(RowFailure::InvalidFields(validation_error(errors).details));
+    }
+    let (section, name, url) = (row.section.as_str(), row.name.as_str(), row.url.as_str());
+
+    let section_id = match find_node(known, None, section) {
+        Some(id) => id,
+        None => {
+            let id = create_node(storage, known, None, section).await?;
+            report.created_sections.push(section.to_string());
+            id
+        }
+    };
+    let node_id = match row.subsection.as_deref() {
+        None => section_id,
+        Some(subsection) => match find_node(known, Some(section_id), subsection) {
+            Some(id) => id,
+            None => {
+                let id = create_node(storage, known, Some(section_id), subsection).await?;
+                report
+                    .created_subsections
+                    .push(format!("{section} / {subsection}"));
+                id
+            }
+        },
+    };
+
+    let existing = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            url: Some(url.to_string()),
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+            RowFailure::Storage(err)
+        })?;
+    match existing.into_iter().next() {
+        Some(note) => {
+            // A note moved to another node goes after the notes already there.
+            let position = if note.node_id == Some(node_id) {
+                None
+            } else {
+                Some(next_note_position(storage, node_id).await?)
+            };
+            lecture_notes::update_note(
+                storage,
+                note.id,
+                UpdateNoteForm {
+                    name: Some(name.to_string()),
+                    description: Some(row.description.clone()),
+                    node_id: Some(node_id),
+                    position,
+                    version: note.version,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|err| match err {
+                lecture_notes::UpdateNoteError::SlugTakenError => RowFailure::Invalid(
+                    "the note clashes with the slug of a note of the same node".to_string(),
+                ),
+                lecture_notes::UpdateNoteError::VersionConflictError(_) => {
+                    RowFailure::Invalid("the note was changed while it was imported".to_string())
+                }
+                lecture_notes::UpdateNoteError::UnexpectedError(err) => RowFailure::Storage(err),
+                _ => RowFailure::Invalid("the note could not be updated".to_string()),
+            })?;
+            report.updated_notes += 1;
+        }
+        None => {
+            lecture_notes::create_note(
+                storage,
+                CreateNoteForm {
+                    name: name.to_string(),
+                    description: row.description,
+                    url: url.to_string(),
+                    node_id: Some(node_id),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|err| match err {
+                lecture_notes::CreateNoteError::UnexpectedError(err) => RowFailure::Storage(err),
+                _ => RowFailure::Invalid(format!("no slug can be derived from `{name}`")),
+            })?;
+            report.created_notes += 1;
+        }
+    }
+    Ok(())
+}
+
+fn find_node(known: &[KnownNode], parent_id: Option<u32>, title: &str) -> Option<u32> {
+    known
+        .iter()
+        .find(|node| node.parent_id == parent_id && node.title == title)
+        .map(|node| node.id)
+}
+
+/// Create the section titled `title`, or the subsection of `parent_id`, and
+/// remember it for the later rows.
+async fn create_node(
+    storage: &Storage,
+    known: &mut Vec<KnownNode>,
+    parent_id: Option<u32>,
+    title: &str,
+) -> Result<u32, RowFailure> {
+    let kind = match parent_id {
+        None => db::sections::SECTION_KIND,
+        Some(_) => db::subsections::SUBSECTION_KIND,
+    };
+    let id = nodes::create_node(
+        storage,
+        CreateNodeForm {
+            parent_id,
+            kind: kind.to_string(),
+            title: title.to_string(),
+            status: Default::default(),
+            publish_at: None,
+            slug: None,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        nodes::CreateNodeError::UnexpectedError(err) => RowFailure::Storage(err),
+        nodes::CreateNodeError::ParentNotFoundError => {
+            RowFailure::Invalid(format!("the section of `{title}` was deleted"))
+        }
+        _ => RowFailure::Invalid(format!("no slug can be derived from `{title}`")),
+    })?;
+    known.push(KnownNode {
+        id,
+        parent_id,
+        title: title.to_string(),
+    });
+    Ok(id)
+}
+
+async fn next_note_position(storage: &Storage, node_id: u32) -> Result<u32, RowFailure> {
+    let notes = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            node_id: Some(node_id),
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+            RowFailure::Storage(err)
+        })?;
+    Ok(notes
+        .iter()
+        .map(|note| note.position + 1)
+        .max()
+        .unwrap_or_default())
+}
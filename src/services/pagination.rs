@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::query::{Clauses, Direction, Filter, OrderBy, Value};
+
+/// The fields a list can be sorted by. Each list maps them to its own columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Position,
+    Name,
+    Id,
+    CreatedAt,
+}
+
+impl SortField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortField::Position => "position",
+            SortField::Name => "name",
+            SortField::Id => "id",
+            SortField::CreatedAt => "created_at",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "position" => Some(SortField::Position),
+            "name" => Some(SortField::Name),
+            "id" => Some(SortField::Id),
+            "created_at" => Some(SortField::CreatedAt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub direction: Direction,
+}
+
+impl Sort {
+    pub const fn asc(field: SortField) -> Self {
+        Self {
+            field,
+            direction: Direction::Asc,
+        }
+    }
+
+    pub const fn desc(field: SortField) -> Self {
+        Self {
+            field,
+            direction: Direction::Desc,
+        }
+    }
+
+    /// Parse the `sort` query parameter: `name` sorts ascending, `-name` descending.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.strip_prefix('-') {
+            Some(field) => SortField::parse(field).map(Sort::desc),
+            None => SortField::parse(value).map(Sort::asc),
+        }
+    }
+
+    /// The `sort` query parameter selecting this sort.
+    pub fn to_param(&self) -> String {
+        match self.direction {
+            Direction::Asc => self.field.as_str().to_string(),
+            Direction::Desc => format!("-{}", self.field.as_str()),
+        }
+    }
+}
+
+/// A row of a list that can be sorted and paged.
+pub trait Sortable {
+    /// The sort used when the request gives none.
+    const DEFAULT_SORT: Sort;
+
+    /// The column holding `field`, None when the list cannot be sorted by it.
+    fn sort_column(field: SortField) -> Option<&'static str>;
+
+    fn id(&self) -> u32;
+
+    /// The value of the column holding `field`, written into the cursors.
+    fn sort_value(&self, field: SortField) -> Value;
+}
+
+/// The position of the last row of a page, from which the next page starts.
+/// Cursors are opaque to the clients: they are sent back as given.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// The `sort` parameter the cursor was made for.
+    pub sort: String,
+    pub value: Value,
+    pub id: u32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        json.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        if !value.len().is_multiple_of(2) || !value.is_ascii() {
+            return None;
+        }
+        let json = (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// The rows following the cursor in the order given by `column` and `direction`,
+    /// the id breaking the ties.
+    fn rows_after(&self, column: &'static str, direction: Direction) -> Filter {
+        let (after, id_after) = match direction {
+            Direction::Asc => (
+                Filter::Gt(column, self.value.clone()),
+                Filter::Gt("id", self.id.into()),
+            ),
+            Direction::Desc => (
+                Filter::Lt(column, self.value.clone()),
+                Filter::Lt("id", self.id.into()),
+            ),
+        };
+        if column == "id" {
+            return id_after;
+        }
+        Filter::Or(vec![
+            after,
+            Filter::And(vec![Filter::Eq(column, self.value.clone()), id_after]),
+        ])
+    }
+}
+
+/// How a list is sorted and which part of it is wanted.
+/// A page starts either at `offset` or right after `cursor`, never both.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageRequest {
+    /// None for the default sort of the list.
+    pub sort: Option<Sort>,
+    /// None for every remaining row.
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    pub cursor: Option<Cursor>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PageError {
+    UnsupportedSortError(SortField),
+    CursorWithOffsetError,
+    /// The cursor was made for another sort.
+    CursorMismatchError,
+}
+
+/// A page of a list.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The number of rows matching the filters, over every page.
+    pub total: u64,
+    /// The cursor of the next page, None on the last one.
+    pub next_cursor: Option<String>,
+}
+
+impl PageRequest {
+    pub fn sort_of<T: Sortable>(&self) -> Sort {
+        self.sort.unwrap_or(T::DEFAULT_SORT)
+    }
+
+    /// The ordering, the cursor condition and the paging of the rows to fetch.
+    /// One more row than the limit is fetched, to tell whether another page follows.
+    pub fn clauses<T: Sortable>(&self) -> Result<Clauses, PageError> {
+        let sort = self.sort_of::<T>();
+        let column =
+            T::sort_column(sort.field).ok_or(PageError::UnsupportedSortError(sort.field))?;
+        let order = |column| OrderBy {
+            column,
+            direction: sort.direction,
+        };
+        let mut order_by = vec![order(column)];
+        if column != "id" {
+            order_by.push(order("id"));
+        }
+
+        let filter = match &self.cursor {
+            Some(_) if self.offset.is_some() => return Err(PageError::CursorWithOffsetError),
+            Some(cursor) if cursor.sort != sort.to_param() => {
+                return Err(PageError::CursorMismatchError)
+            }
+            Some(cursor) => cursor.rows_after(column, sort.direction),
+            None => Filter::all(),
+        };
+        Ok(Clauses {
+            filter,
+            order_by,
+            limit: self.limit.map(|limit| limit.saturating_add(1)),
+            offset: self.offset,
+        })
+    }
+
+    /// The total when the fetched rows already tell it, so that it needs no count.
+    pub fn known_total(&self, fetched: usize) -> Option<u64> {
+        let complete = self.limit.is_none_or(|limit| fetched <= limit as usize);
+        (complete && self.offset.is_none() && self.cursor.is_none()).then_some(fetched as u64)
+    }
+
+    /// Cut the rows fetched with `clauses` down to the page.
+    pub fn page<T: Sortable>(&self, mut rows: Vec<T>, total: u64) -> Page<T> {
+        let mut next_cursor = None;
+        if let Some(limit) = self.limit {
+            if rows.len() > limit as usize {
+                rows.truncate(limit as usize);
+                let sort = self.sort_of::<T>();
+                next_cursor = rows.last().map(|row| {
+                    Cursor {
+                        sort: sort.to_param(),
+                        value: row.sort_value(sort.field),
+                        id: row.id(),
+                    }
+                    .encode()
+                });
+            }
+        }
+        Page {
+            items: rows,
+            total,
+            next_cursor,
+        }
+    }
+}
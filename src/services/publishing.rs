@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::db;
+
+/// The publication status of a section, a subsection or a note.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Prepared in advance, only shown to the admins.
+    Draft,
+    /// Shown to everyone, once its `publish_at` time (if any) has passed.
+    #[default]
+    Published,
+    /// Taken down, only shown to the admins.
+    Archived,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Published => db::nodes::PUBLISHED,
+            Status::Archived => "archived",
+        }
+    }
+
+    /// The status stored in the database. An unknown value is kept away from the
+    /// public, as a draft.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            db::nodes::PUBLISHED => Status::Published,
+            "archived" => Status::Archived,
+            _ => Status::Draft,
+        }
+    }
+}
+
+/// Which content a request gets to see.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visibility {
+    /// Only the content published by now, under published parents.
+    #[default]
+    Public,
+    /// Drafts, archived and scheduled content too, for the admins.
+    Everything,
+}
+
+impl Visibility {
+    /// The time the content has to be published at, None when everything is shown.
+    pub fn published_at(self) -> Option<i64> {
+        match self {
+            Visibility::Public => Some(db::now()),
+            Visibility::Everything => None,
+        }
+    }
+}
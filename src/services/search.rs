@@ -0,0 +1,614 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use utoipa::ToSchema;
+
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services;
+use crate::services::publishing::{Status, Visibility};
+
+/// What a search result points to.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentKind {
+    Note,
+    Section,
+    Subsection,
+}
+
+/// The searchable text of a note, a section or a subsection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Document {
+    pub kind: DocumentKind,
+    pub id: u32,
+    /// The note name, or the section and subsection title.
+    pub title: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// The titles of the section and subsection holding the document.
+    pub parents: Vec<String>,
+    pub url: Option<String>,
+    pub section_id: Option<u32>,
+    pub subsection_id: Option<u32>,
+    /// The time from which the document and its parents are all published, None
+    /// while one of them is a draft or archived.
+    pub published_from: Option<i64>,
+}
+
+type DocumentKey = (DocumentKind, u32);
+
+/// The fields of a document, ordered as in `FIELD_WEIGHTS`.
+const FIELD_COUNT: usize = 4;
+const TITLE: usize = 0;
+const TAGS: usize = 1;
+const PARENTS: usize = 2;
+const DESCRIPTION: usize = 3;
+/// How much a match weighs in each field: a word of the title says more
+/// about a note than a word of its description.
+const FIELD_WEIGHTS: [f64; FIELD_COUNT] = [3.0, 2.0, 1.5, 1.0];
+/// BM25 term saturation and length normalization.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// A query word matching the beginning of a longer word counts for less.
+const PREFIX_WEIGHT: f64 = 0.5;
+/// Query words shorter than this only match whole words.
+const MIN_PREFIX_LENGTH: usize = 3;
+/// The number of words shown around the first match of a snippet.
+const SNIPPET_WORDS_BEFORE: usize = 4;
+const SNIPPET_WORDS: usize = 16;
+
+/// When content with this status and publication time is published, under
+/// parents published from `parents`.
+fn published_from(status: Status, publish_at: Option<i64>, parents: Option<i64>) -> Option<i64> {
+    match status {
+        Status::Published => Some(publish_at.unwrap_or(i64::MIN).max(parents?)),
+        Status::Draft | Status::Archived => None,
+    }
+}
+
+impl Document {
+    fn is_visible(&self, visibility: Visibility) -> bool {
+        match visibility.published_at() {
+            Some(at) => self.published_from.is_some_and(|from| from <= at),
+            None => true,
+        }
+    }
+
+    fn fields(&self) -> [String; FIELD_COUNT] {
+        let mut fields: [String; FIELD_COUNT] = Default::default();
+        fields[TITLE] = self.title.clone();
+        fields[TAGS] = self.tags.join(" ");
+        fields[PARENTS] = self.parents.join(" ");
+        fields[DESCRIPTION] = self.description.clone();
+        fields
+    }
+}
+
+/// Fold a character for matching: lowercase, decomposed with its accents left
+/// out, and with the Ukrainian `ґ` matching `г`.
+fn fold_char(c: char, folded: &mut String) {
+    for c in c.to_lowercase() {
+        match c {
+            // Letters of their own rather than accented ones.
+            'й' | 'ї' => folded.push(c),
+            // Letters with no decomposition.
+            'ł' => folded.push('l'),
+            'ø' => folded.push('o'),
+            'đ' => folded.push('d'),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'ß' => folded.push_str("ss"),
+            'ґ' => folded.push('г'),
+            c => decompose_canonical(c, |c| {
+                if !is_combining_mark(c) {
+                    folded.push(c);
+                }
+            }),
+        }
+    }
+}
+
+/// Whether `c` belongs to a word. Apostrophes split words, so that the French
+/// `l'algèbre` and the Ukrainian `мʼята` match however the apostrophe is typed.
+fn is_word_char(c: char) -> bool {
+    (c.is_alphanumeric() && c != 'ʼ') || is_combining_mark(c)
+}
+
+/// The words of `text` with their byte ranges, folded for matching.
+fn words(text: &str) -> Vec<(usize, usize, String)> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, c) in text.char_indices() {
+        if is_word_char(c) {
+            let (_, folded) = current.get_or_insert_with(|| (index, String::new()));
+            fold_char(c, folded);
+        } else if let Some((start, folded)) = current.take() {
+            words.push((start, index, folded));
+        }
+    }
+    if let Some((start, folded)) = current {
+        words.push((start, text.len(), folded));
+    }
+    words.retain(|(_, _, folded)| !folded.is_empty());
+    words
+}
+
+/// The folded words of `text`, as they are indexed and queried.
+pub fn tokenize(text: &str) -> Vec<String> {
+    words(text).into_iter().map(|(_, _, word)| word).collect()
+}
+
+#[derive(Default)]
+struct Index {
+    documents: HashMap<DocumentKey, (Document, [u32; FIELD_COUNT])>,
+    /// The occurrences of each word, by document and field. Sorted, so that the
+    /// words starting with a prefix are next to each other.
+    postings: BTreeMap<String, HashMap<DocumentKey, [u32; FIELD_COUNT]>>,
+    /// The total number of words of each field, for the average lengths.
+    field_lengths: [u64; FIELD_COUNT],
+}
+
+impl Index {
+    fn insert(&mut self, document: Document) {
+        let key = (document.kind, document.id);
+        self.remove(key);
+        let mut lengths = [0; FIELD_COUNT];
+        for (field, text) in document.fields().iter().enumerate() {
+            for word in tokenize(text) {
+                lengths[field] += 1;
+                self.postings
+                    .entry(word)
+                    .or_default()
+                    .entry(key)
+                    .or_default()[field] += 1;
+            }
+            self.field_lengths[field] += lengths[field] as u64;
+        }
+        self.documents.insert(key, (document, lengths));
+    }
+
+    fn remove(&mut self, key: DocumentKey) {
+        let Some((document, lengths)) = self.documents.remove(&key) else {
+            return;
+        };
+        for (field, length) in lengths.iter().enumerate() {
+            self.field_lengths[field] -= *length as u64;
+        }
+        let words = document
+            .fields()
+            .iter()
+            .flat_map(|text| tokenize(text))
+            .collect::<HashSet<_>>();
+        for word in words {
+            if let Some(documents) = self.postings.get_mut(&word) {
+                documents.remove(&key);
+                if documents.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// The indexed words matching a query word, with the weight of the match.
+    fn matching_words<'a>(
+        &'a self,
+        query_word: &'a str,
+    ) -> impl Iterator<Item = (&'a String, f64)> {
+        let allow_prefix = query_word.chars().count() >= MIN_PREFIX_LENGTH;
+        self.postings
+            .range::<str, _>((
+                std::ops::Bound::Included(query_word),
+                std::ops::Bound::Unbounded,
+            ))
+            .map(|(word, _)| word)
+            .take_while(move |word| word.starts_with(query_word))
+            .filter_map(move |word| match word == query_word {
+                true => Some((word, 1.0)),
+                false if allow_prefix => Some((word, PREFIX_WEIGHT)),
+                false => None,
+            })
+    }
+
+    /// The BM25F score of every document matching all the query words.
+    fn score(&self, query_words: &[String]) -> HashMap<DocumentKey, f64> {
+        let count = self.documents.len().max(1) as f64;
+        let average_lengths = self
+            .field_lengths
+            .map(|length| (length as f64 / count).max(1.0));
+        let mut scores: Option<HashMap<DocumentKey, f64>> = None;
+        for query_word in query_words {
+            let mut word_scores: HashMap<DocumentKey, f64> = HashMap::new();
+            for (word, weight) in self.matching_words(query_word) {
+                let documents = &self.postings[word];
+                let frequency = documents.len() as f64;
+                let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+                for (key, occurrences) in documents {
+                    let lengths = &self.documents[key].1;
+                    let mut term_score = 0.0;
+                    for field in 0..FIELD_COUNT {
+                        let tf = occurrences[field] as f64;
+                        if tf == 0.0 {
+                            continue;
+                        }
+                        let norm = 1.0 - B + B * lengths[field] as f64 / average_lengths[field];
+                        term_score += FIELD_WEIGHTS[field] * tf * (K1 + 1.0) / (tf + K1 * norm);
+                    }
+                    let score = word_scores.entry(*key).or_default();
+                    *score = score.max(weight * idf * term_score);
+                }
+            }
+            // Every query word has to match the document.
+            scores = Some(match scores {
+                None => word_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(key, score)| Some((key, score + word_scores.get(&key)?)))
+                    .collect(),
+            });
+        }
+        scores.unwrap_or_default()
+    }
+}
+
+/// Whether the folded `word` is matched by one of the query words.
+fn is_match(word: &str, query_words: &[String]) -> bool {
+    query_words.iter().any(|query_word| {
+        word == query_word
+            || (query_word.chars().count() >= MIN_PREFIX_LENGTH
+                && word.starts_with(query_word.as_str()))
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A few words of `text` around its first match, HTML escaped, with the matching
+/// words wrapped in `<mark>`. None when nothing in `text` matches.
+pub fn snippet(text: &str, query_words: &[String]) -> Option<String> {
+    let words = words(text);
+    let first = words
+        .iter()
+        .position(|(_, _, word)| is_match(word, query_words))?;
+    let start = first.saturating_sub(SNIPPET_WORDS_BEFORE);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = words[start].0;
+    for (word_start, word_end, word) in &words[start..end] {
+        snippet.push_str(&escape_html(&text[position..*word_start]));
+        let original = escape_html(&text[*word_start..*word_end]);
+        if is_match(word, query_words) {
+            snippet.push_str(&format!("<mark>{}</mark>", original));
+        } else {
+            snippet.push_str(&original);
+        }
+        position = *word_end;
+    }
+    if end < words.len() {
+        snippet.push('…');
+    } else {
+        snippet.push_str(&escape_html(&text[position..]));
+    }
+    Some(snippet.trim().to_string())
+}
+
+#[derive(Default)]
+pub struct SearchForm {
+    pub query: String,
+    pub kind: Option<DocumentKind>,
+    /// Hidden documents are left out of the results.
+    pub visibility: Visibility,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SearchHit {
+    pub kind: DocumentKind,
+    pub id: u32,
+    pub title: String,
+    pub url: Option<String>,
+    pub section_id: Option<u32>,
+    pub subsection_id: Option<u32>,
+    pub score: f64,
+    /// Part of the best matching field, with the matches in `<mark>`.
+    pub snippet: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+/// An in-process full-text index over the notes, sections and subsections.
+/// It is filled by `rebuild`, and `refresh` builds it again once the services
+/// reported a write of the content through `Storage::content_changed`.
+#[derive(Default)]
+pub struct SearchIndex {
+    index: RwLock<Index>,
+    /// The content generation of the storage the index was built at, None
+    /// until it is built from a storage.
+    generation: Mutex<Option<u64>>,
+}
+
+#[derive(Debug)]
+pub enum RebuildIndexError {
+    UnexpectedError(Error),
+}
+
+impl SearchIndex {
+    fn read(&self) -> RwLockReadGuard<'_, Index> {
+        self.index.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Index> {
+        self.index.write().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, document: Document) {
+        self.write().insert(document);
+    }
+
+    pub fn remove(&self, kind: DocumentKind, id: u32) {
+        self.write().remove((kind, id));
+    }
+
+    /// Replace the whole content of the index.
+    pub fn replace(&self, documents: Vec<Document>) {
+        let mut index = Index::default();
+        for document in documents {
+            index.insert(document);
+        }
+        *self.write() = index;
+    }
+
+    pub fn search(&self, form: &SearchForm) -> SearchResults {
+        let mut query_words = tokenize(&form.query);
+        query_words.sort();
+        query_words.dedup();
+        if query_words.is_empty() {
+            return SearchResults {
+                total: 0,
+                hits: Vec::new(),
+            };
+        }
+
+        let index = self.read();
+        let mut scores = index
+            .score(&query_words)
+            .into_iter()
+            .filter(|((kind, _), _)| form.kind.is_none_or(|wanted| *kind == wanted))
+            .filter(|(key, _)| index.documents[key].0.is_visible(form.visibility))
+            .collect::<Vec<_>>();
+        scores.sort_by(|(a_key, a_score), (b_key, b_score)| {
+            b_score.total_cmp(a_score).then(a_key.cmp(b_key))
+        });
+
+        let total = scores.len();
+        let hits = scores
+            .into_iter()
+            .skip(form.offset)
+            .take(form.limit)
+            .map(|(key, score)| {
+                let document = &index.documents[&key].0;
+                let fields = document.fields();
+                let snippet = [DESCRIPTION, TITLE, TAGS, PARENTS]
+                    .into_iter()
+                    .find_map(|field| snippet(&fields[field], &query_words))
+                    .unwrap_or_else(|| escape_html(&document.title));
+                SearchHit {
+                    kind: document.kind,
+                    id: document.id,
+                    title: document.title.clone(),
+                    url: document.url.clone(),
+                    section_id: document.section_id,
+                    subsection_id: document.subsection_id,
+                    score,
+                    snippet,
+                }
+            })
+            .collect();
+        SearchResults { total, hits }
+    }
+
+    fn generation(&self) -> std::sync::MutexGuard<'_, Option<u64>> {
+        self.generation
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Rebuild the index when the content changed since it was built.
+    pub async fn refresh(&self, storage: &Storage) -> Result<(), RebuildIndexError> {
+        if *self.generation() == Some(storage.content_generation()) {
+            return Ok(());
+        }
+        self.rebuild(storage).await.map(|_| ())
+    }
+
+    /// Index the content of the storage again, from scratch.
+    pub async fn rebuild(&self, storage: &Storage) -> Result<usize, RebuildIndexError> {
+        // Read first, so that a write made while the content is loaded is not missed.
+        let generation = storage.content_generation();
+        let sections = services::sections::get_sections(
+            storage,
+            services::sections::GetSectionsForm {
+                id: None,
+                title: None,
+                position: None,
+                visibility: Visibility::Everything,
+                page: Default::default(),
+            },
+        )
+        .await
+        .map_err(|err| match err {
+            services::sections::GetSectionsError::UnexpectedError(err) => {
+                RebuildIndexError::UnexpectedError(err)
+            }
+            services::sections::GetSectionsError::PageError(_) => {
+                RebuildIndexError::UnexpectedError(Error::internal("the whole list is refused"))
+            }
+        })?
+        .items;
+        let subsections = services::subsections::get_subsections(
+            storage,
+            services::subsections::GetSubsectionsForm {
+                id: None,
+                title: None,
+                position: None,
+                section_id: None,
+                visibility: Visibility::Everything,
+                page: Default::default(),
+            },
+        )
+        .await
+        .map_err(|err| match err {
+            services::subsections::GetSubsectionsError::UnexpectedError(err) => {
+                RebuildIndexError::UnexpectedError(err)
+            }
+            services::subsections::GetSubsectionsError::PageError(_) => {
+                RebuildIndexError::UnexpectedError(Error::internal("the whole list is refused"))
+            }
+        })?
+        .items;
+        let notes = services::lecture_notes::get_notes(
+            storage,
+            services::lecture_notes::GetNotesForm {
+                id: None,
+                name: None,
+                url: None,
+                position: None,
+                node_id: None,
+                section_id: None,
+                subsection_id: None,
+                kind: None,
+                course_code: None,
+                term: None,
+                language: None,
+                author: None,
+                license: None,
+                tags: Vec::new(),
+                tag_match: Default::default(),
+                visibility: Visibility::Everything,
+                page: Default::default(),
+            },
+        )
+        .await
+        .map_err(|err| match err {
+            services::lecture_notes::GetNotesError::UnexpectedError(err) => {
+                RebuildIndexError::UnexpectedError(err)
+            }
+            services::lecture_notes::GetNotesError::PageError(_) => {
+                RebuildIndexError::UnexpectedError(Error::internal("the whole list is refused"))
+            }
+        })?
+        .items;
+
+        let titles = sections
+            .iter()
+            .map(|section| (section.id, section.title.clone()))
+            .chain(subsections.iter().map(|sub| (sub.id, sub.title.clone())))
+            .collect::<HashMap<_, _>>();
+        let parents = |ids: &[Option<u32>]| -> Vec<String> {
+            ids.iter()
+                .flatten()
+                .filter_map(|id| titles.get(id).cloned())
+                .collect()
+        };
+        let mut publication = HashMap::new();
+        for section in &sections {
+            let from = published_from(section.status, section.publish_at, Some(i64::MIN));
+            publication.insert(section.id, from);
+        }
+        for subsection in &subsections {
+            let section = publication.get(&subsection.section_id).copied().flatten();
+            let from = published_from(subsection.status, subsection.publish_at, section);
+            publication.insert(subsection.id, from);
+        }
+        let parents_published_from = |ids: &[Option<u32>]| -> Option<i64> {
+            ids.iter().flatten().try_fold(i64::MIN, |from, id| {
+                Some(from.max(publication.get(id).copied().flatten()?))
+            })
+        };
+
+        let mut documents = Vec::new();
+        for section in &sections {
+            documents.push(Document {
+                kind: DocumentKind::Section,
+                id: section.id,
+                title: section.title.clone(),
+                description: String::new(),
+                tags: Vec::new(),
+                parents: Vec::new(),
+                url: None,
+                section_id: None,
+                subsection_id: None,
+                published_from: publication[&section.id],
+            });
+        }
+        for subsection in &subsections {
+            documents.push(Document {
+                kind: DocumentKind::Subsection,
+                id: subsection.id,
+                title: subsection.title.clone(),
+                description: String::new(),
+                tags: Vec::new(),
+                parents: parents(&[Some(subsection.section_id)]),
+                url: None,
+                section_id: Some(subsection.section_id),
+                subsection_id: None,
+                published_from: publication[&subsection.id],
+            });
+        }
+        for note in notes {
+            let ids = [note.section_id, note.subsection_id];
+            let parents_from = parents_published_from(&ids);
+            documents.push(note_document(note, parents(&ids), parents_from));
+        }
+
+        let count = documents.len();
+        self.replace(documents);
+        *self.generation() = Some(generation);
+        Ok(count)
+    }
+}
+
+/// The document of a note, under parents published from `parents_from`.
+fn note_document(
+    note: services::lecture_notes::NoteReturn,
+    parents: Vec<String>,
+    parents_from: Option<i64>,
+) -> Document {
+    Document {
+        published_from: published_from(note.status, note.publish_at, parents_from),
+        kind: DocumentKind::Note,
+        id: note.id,
+        title: note.name,
+        description: note.description,
+        tags: note.tags,
+        parents,
+        url: Some(note.url),
+        section_id: note.section_id,
+        subsection_id: note.subsection_id,
+    }
+}
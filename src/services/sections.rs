@@ -1,128 +1,303 @@
+use std::collections::HashSet;
+
 use crate::db;
+use crate::db::query::Value;
+use crate::db::slugs::SlugError;
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::pagination::{Page, PageError, PageRequest, Sort, SortField, Sortable};
+use crate::services::publishing::{Status, Visibility};
 use serde::Serialize;
+use utoipa::ToSchema;
 
+#[derive(Default)]
 pub struct CreateSectionForm {
     pub title: String,
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the section stays hidden.
+    pub publish_at: Option<i64>,
+    /// The title slugified when None.
+    pub slug: Option<String>,
 }
 
+#[derive(Default)]
 pub struct UpdateSectionForm {
     pub title: Option<String>,
+    pub status: Option<Status>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the section.
+    pub slug: Option<String>,
+    /// The version of the section the update was made against.
+    pub version: u32,
 }
 
+#[derive(Default)]
 pub struct GetSectionsForm {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
-    pub limit: Option<u32>,
+    pub visibility: Visibility,
+    pub page: PageRequest,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct SectionReturn {
     pub id: u32,
     pub title: String,
     pub position: u32,
+    /// Seconds since the Unix epoch.
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Incremented by every update and move, required by the next ones.
+    pub version: u32,
+    pub status: Status,
+    pub publish_at: Option<i64>,
+    /// Names the pages of the section, unique among the sections.
+    pub slug: String,
+    /// The slugs the section had before, oldest first.
+    pub previous_slugs: Vec<String>,
 }
 
 impl From<db::sections::SectionFromDb> for SectionReturn {
     fn from(value: db::sections::SectionFromDb) -> Self {
+        let previous_slugs = db::slugs::previous_slugs_from_json(&value.previous_slugs);
         Self {
             id: value.id,
             title: value.title,
             position: value.position,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: Status::from_db(&value.status),
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs,
+        }
+    }
+}
+
+impl Sortable for SectionReturn {
+    const DEFAULT_SORT: Sort = Sort::asc(SortField::Position);
+
+    fn sort_column(field: SortField) -> Option<&'static str> {
+        match field {
+            SortField::Position => Some("position"),
+            SortField::Name => Some("title"),
+            SortField::Id => Some("id"),
+            SortField::CreatedAt => Some("created_at"),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn sort_value(&self, field: SortField) -> Value {
+        match field {
+            SortField::Position => self.position.into(),
+            SortField::Name => self.title.clone().into(),
+            SortField::Id => self.id.into(),
+            SortField::CreatedAt => self.created_at.into(),
         }
     }
 }
 
+/// The slugs of the sections, but the one with id `except`.
+async fn sibling_slugs(storage: &Storage, except: Option<u32>) -> Result<HashSet<String>, Error> {
+    let sections = storage
+        .sections
+        .get_sections(Default::default())
+        .await
+        .map_err(|db::sections::GetSectionsError::UnexpectedError(err)| err)?;
+    Ok(sections
+        .into_iter()
+        .filter(|section| Some(section.id) != except)
+        .map(|section| section.slug)
+        .collect())
+}
+
 #[derive(Debug)]
 pub enum CreateSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
+    InvalidSlugError,
+    SlugTakenError,
+}
+
+impl From<SlugError> for CreateSectionError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => CreateSectionError::InvalidSlugError,
+            SlugError::SlugTakenError => CreateSectionError::SlugTakenError,
+        }
+    }
 }
 
 pub async fn create_section(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: CreateSectionForm,
 ) -> Result<(), CreateSectionError> {
-    db::sections::create_section(pool, db::sections::CreateSectionForm { title: form.title })
+    let siblings = sibling_slugs(storage, None)
         .await
-        .map_err(|_| CreateSectionError::UnexpectedError)
+        .map_err(CreateSectionError::UnexpectedError)?;
+    let slug = db::slugs::slug_for_new(form.slug, &form.title, &siblings)?;
+    let res = storage
+        .sections
+        .create_section(db::sections::CreateSectionForm {
+            title: form.title,
+            status: Some(form.status.as_str().to_string()),
+            publish_at: form.publish_at,
+            slug: Some(slug),
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(CreateSectionError::UnexpectedError)
 }
 
 #[derive(Debug)]
 pub enum GetSectionsError {
-    UnexpectedError,
+    PageError(PageError),
+    UnexpectedError(Error),
 }
 
 pub async fn get_sections(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: GetSectionsForm,
-) -> Result<Vec<SectionReturn>, GetSectionsError> {
-    let res = db::sections::get_sections(
-        pool,
-        db::sections::GetSectionsForm {
-            id: form.id,
-            title: form.title,
-            position: form.position,
-            limit: form.limit,
-            ..Default::default()
-        },
-    )
-    .await;
-    match res {
-        Ok(list) => Ok(list.into_iter().map(SectionReturn::from).collect()),
-        Err(db::sections::GetSectionsError::UnexpectedError) => {
-            Err(GetSectionsError::UnexpectedError)
-        }
-    }
+) -> Result<Page<SectionReturn>, GetSectionsError> {
+    let clauses = form
+        .page
+        .clauses::<SectionReturn>()
+        .map_err(GetSectionsError::PageError)?;
+    let filters = || db::sections::GetSectionsForm {
+        id: form.id,
+        title: form.title.clone(),
+        position: form.position,
+        published_at: form.visibility.published_at(),
+        ..Default::default()
+    };
+    let list = storage
+        .sections
+        .get_sections(db::sections::GetSectionsForm {
+            filter: clauses.filter,
+            order_by: clauses.order_by,
+            limit: clauses.limit,
+            offset: clauses.offset,
+            ..filters()
+        })
+        .await
+        .map_err(|db::sections::GetSectionsError::UnexpectedError(err)| {
+            GetSectionsError::UnexpectedError(err)
+        })?;
+    let total = match form.page.known_total(list.len()) {
+        Some(total) => total,
+        None => storage.sections.count_sections(filters()).await.map_err(
+            |db::sections::GetSectionsError::UnexpectedError(err)| {
+                GetSectionsError::UnexpectedError(err)
+            },
+        )?,
+    };
+    let list = list.into_iter().map(SectionReturn::from).collect();
+    Ok(form.page.page(list, total))
 }
 
 #[derive(Debug)]
 pub enum GetSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
 pub async fn get_section(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     id: u32,
+    visibility: Visibility,
 ) -> Result<SectionReturn, GetSectionError> {
-    let res = db::sections::get_section(
-        pool,
-        db::sections::GetSectionsForm {
+    let res = storage
+        .sections
+        .get_section(db::sections::GetSectionsForm {
             id: Some(id),
+            published_at: visibility.published_at(),
             ..Default::default()
-        },
-    )
-    .await;
+        })
+        .await;
     match res {
         Ok(val) => Ok(SectionReturn::from(val)),
         Err(db::sections::GetSectionError::NotFoundError) => Err(GetSectionError::NotFoundError),
-        Err(db::sections::GetSectionError::UnexpectedError) => {
-            Err(GetSectionError::UnexpectedError)
+        Err(db::sections::GetSectionError::UnexpectedError(err)) => {
+            Err(GetSectionError::UnexpectedError(err))
         }
     }
 }
 
 #[derive(Debug)]
 pub enum UpdateSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    InvalidSlugError,
+    SlugTakenError,
+    /// The section moved past the expected version, it is returned as it is now.
+    VersionConflictError(SectionReturn),
+}
+
+impl From<SlugError> for UpdateSectionError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => UpdateSectionError::InvalidSlugError,
+            SlugError::SlugTakenError => UpdateSectionError::SlugTakenError,
+        }
+    }
 }
 
 pub async fn update_section(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     id: u32,
     form: UpdateSectionForm,
 ) -> Result<(), UpdateSectionError> {
-    let res = db::sections::update_sections(
-        pool,
-        db::sections::UpdateSectionForm { title: form.title },
-        db::sections::GetSectionsForm {
-            id: Some(id),
-            ..Default::default()
-        },
-    )
-    .await;
+    let slug_change = match form.slug {
+        Some(requested) => {
+            let current = match get_section(storage, id, Visibility::Everything).await {
+                Ok(current) => current,
+                Err(GetSectionError::NotFoundError) => {
+                    return Err(UpdateSectionError::NotFoundError)
+                }
+                Err(GetSectionError::UnexpectedError(err)) => {
+                    return Err(UpdateSectionError::UnexpectedError(err))
+                }
+            };
+            let siblings = sibling_slugs(storage, Some(id))
+                .await
+                .map_err(UpdateSectionError::UnexpectedError)?;
+            db::slugs::slug_update(
+                &current.slug,
+                current.previous_slugs,
+                Some(requested),
+                &siblings,
+            )?
+        }
+        None => None,
+    };
+    let (slug, previous_slugs) = match slug_change {
+        Some(change) => (Some(change.slug), Some(change.previous_slugs)),
+        None => (None, None),
+    };
+    let res = storage
+        .sections
+        .update_sections(
+            db::sections::UpdateSectionForm {
+                title: form.title,
+                status: form.status.map(|status| status.as_str().to_string()),
+                publish_at: form.publish_at,
+                slug,
+                previous_slugs,
+                version: Some(form.version),
+            },
+            db::sections::GetSectionsForm {
+                id: Some(id),
+                ..Default::default()
+            },
+        )
+        .await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::sections::UpdateSectionsError::NotFoundError) => {
@@ -131,50 +306,82 @@ pub async fn update_section(
         Err(db::sections::UpdateSectionsError::NothingToUpdateError) => {
             Err(UpdateSectionError::NothingToUpdateError)
         }
-        Err(db::sections::UpdateSectionsError::UnexpectedError) => {
-            Err(UpdateSectionError::UnexpectedError)
+        Err(db::sections::UpdateSectionsError::VersionConflictError) => {
+            match get_section(storage, id, Visibility::Everything).await {
+                Ok(current) => Err(UpdateSectionError::VersionConflictError(current)),
+                Err(GetSectionError::NotFoundError) => Err(UpdateSectionError::NotFoundError),
+                Err(GetSectionError::UnexpectedError(err)) => {
+                    Err(UpdateSectionError::UnexpectedError(err))
+                }
+            }
+        }
+        Err(db::sections::UpdateSectionsError::UnexpectedError(err)) => {
+            Err(UpdateSectionError::UnexpectedError(err))
         }
     }
 }
 
 #[derive(Debug)]
 pub enum DeleteSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
-pub async fn delete_section(
-    pool: &sqlx::Pool<sqlx::MySql>,
-    id: u32,
-) -> Result<(), DeleteSectionError> {
-    db::sections::delete_section(
-        pool,
-        db::sections::GetSectionsForm {
+pub async fn delete_section(storage: &Storage, id: u32) -> Result<(), DeleteSectionError> {
+    let res = storage
+        .sections
+        .delete_section(db::sections::GetSectionsForm {
             id: Some(id),
             ..Default::default()
-        },
-    )
-    .await
-    .map_err(|_| DeleteSectionError::UnexpectedError)
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(|db::sections::DeleteSectionsError::UnexpectedError(err)| {
+        DeleteSectionError::UnexpectedError(err)
+    })
 }
 
 #[derive(Debug)]
 pub enum MoveSectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError(Option<u32>, Option<u32>),
+    /// A section moved past its expected version, both are returned as they are now.
+    VersionConflictError(Vec<SectionReturn>),
 }
 
+/// Swap the positions of two sections, written only at their expected `versions`.
 pub async fn move_section(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     ids: [u32; 2],
+    versions: [u32; 2],
 ) -> Result<(), MoveSectionError> {
-    let res = db::sections::swap_sections(pool, ids).await;
+    let res = storage.sections.swap_sections(ids, versions).await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::sections::SwapSectionsError::NotFoundError(tuple)) => {
             Err(MoveSectionError::NotFoundError(tuple.0, tuple.1))
         }
-        Err(db::sections::SwapSectionsError::UnexpectedError) => {
-            Err(MoveSectionError::UnexpectedError)
+        Err(db::sections::SwapSectionsError::VersionConflictError) => {
+            let (first, second) = (
+                get_section(storage, ids[0], Visibility::Everything).await,
+                get_section(storage, ids[1], Visibility::Everything).await,
+            );
+            match (first, second) {
+                (Ok(first), Ok(second)) => {
+                    Err(MoveSectionError::VersionConflictError(vec![first, second]))
+                }
+                (Err(GetSectionError::UnexpectedError(err)), _)
+                | (_, Err(GetSectionError::UnexpectedError(err))) => {
+                    Err(MoveSectionError::UnexpectedError(err))
+                }
+                (first, second) => Err(MoveSectionError::NotFoundError(
+                    first.err().map(|_| ids[0]),
+                    second.err().map(|_| ids[1]),
+                )),
+            }
+        }
+        Err(db::sections::SwapSectionsError::UnexpectedError(err)) => {
+            Err(MoveSectionError::UnexpectedError(err))
         }
     }
 }
@@ -1,11 +1,24 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use serde_json::json;
 use tokio::fs;
 
+use crate::db::slugs::slugify;
+use crate::repository::Storage;
+use crate::services::attachments::AttachmentReturn;
+use crate::services::lecture_notes::NoteMetadata;
+
 const LECTURE_NOTES_TEMPLATE: &str = include_str!("../../templates/lecture_notes.html");
 const LECTURE_NOTES_JS_TEMPLATE: &str = include_str!("../../templates/lecture-notes.js");
 const NOTE_PAGE_TEMPLATE: &str = include_str!("../../templates/note_page.html");
+const TAG_PAGE_TEMPLATE: &str = include_str!("../../templates/tag_page.html");
+const REDIRECT_PAGE_TEMPLATE: &str = include_str!("../../templates/redirect_page.html");
+
+/// Directory, inside the notes directory, holding one page per tag.
+const TAGS_DIR_NAME: &str = "tags";
+
+/// Directory, inside the notes directory, holding the copies of the uploaded files.
+const FILES_DIR_NAME: &str = "files";
 
 const SITE_BASE_URL: &str = "https://korotenky.com";
 
@@ -13,35 +26,238 @@ struct GeneratedNote {
     name: String,
     description: String,
     url: String,
-    position: u32,
+    metadata: NoteMetadata,
+    tags: Vec<String>,
+    attachments: Vec<AttachmentReturn>,
+    slugs: Vec<String>,
+    /// Set by `assign_file_names`.
+    file_name: String,
+    /// The names the page had under the previous slugs of the note and its nodes.
+    previous_file_names: Vec<String>,
 }
 
-struct GeneratedSubsection {
+/// A node of the content tree; the root nodes are rendered as sections.
+struct GeneratedNode {
     title: String,
-    position: u32,
+    /// The current slug first, then the previous ones.
+    slugs: Vec<String>,
+    children: Vec<GeneratedNode>,
     notes: Vec<GeneratedNote>,
 }
 
-struct GeneratedSection {
-    title: String,
-    position: u32,
-    subsections: Vec<GeneratedSubsection>,
-    notes: Vec<GeneratedNote>,
+impl From<crate::services::lecture_notes::NoteReturn> for GeneratedNote {
+    fn from(note: crate::services::lecture_notes::NoteReturn) -> Self {
+        Self {
+            name: note.name,
+            description: note.description,
+            url: note.url,
+            metadata: note.metadata,
+            tags: note.tags,
+            attachments: note.attachments,
+            slugs: [vec![note.slug], note.previous_slugs].concat(),
+            file_name: String::new(),
+            previous_file_names: Vec::new(),
+        }
+    }
+}
+
+impl From<crate::services::nodes::TreeNode> for GeneratedNode {
+    fn from(value: crate::services::nodes::TreeNode) -> Self {
+        Self {
+            title: value.title,
+            slugs: [vec![value.slug], value.previous_slugs].concat(),
+            children: value.children.into_iter().map(GeneratedNode::from).collect(),
+            notes: value.notes.into_iter().map(GeneratedNote::from).collect(),
+        }
+    }
+}
+
+impl From<crate::services::tree::SectionTree> for GeneratedNode {
+    fn from(value: crate::services::tree::SectionTree) -> Self {
+        let subsections = value.subsections.into_iter().map(|subsection| Self {
+            title: subsection.subsection.title,
+            slugs: [
+                vec![subsection.subsection.slug],
+                subsection.subsection.previous_slugs,
+            ]
+            .concat(),
+            children: subsection.children.into_iter().map(GeneratedNode::from).collect(),
+            notes: subsection.notes.into_iter().map(GeneratedNote::from).collect(),
+        });
+        Self {
+            title: value.section.title,
+            slugs: [vec![value.section.slug], value.section.previous_slugs].concat(),
+            children: subsections.collect(),
+            notes: value.notes.into_iter().map(GeneratedNote::from).collect(),
+        }
+    }
+}
+
+impl GeneratedNode {
+    fn slug(&self) -> &str {
+        self.slugs.first().map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// Every combination of one slug per level, starting with the current slugs.
+fn slug_combinations(levels: &[&[String]]) -> Vec<Vec<String>> {
+    levels.iter().fold(vec![Vec::new()], |combinations, level| {
+        combinations
+            .iter()
+            .flat_map(|prefix| {
+                level.iter().map(move |slug| {
+                    let mut combination = prefix.clone();
+                    combination.push(slug.clone());
+                    combination
+                })
+            })
+            .collect()
+    })
+}
+
+/// Name the note pages after the slugs leading to them, and keep the names the
+/// pages had under any combination of the previous slugs.
+fn assign_file_names<'a>(node: &'a mut GeneratedNode, levels: &mut Vec<&'a [String]>) {
+    levels.push(&node.slugs);
+    for note in node.notes.iter_mut() {
+        let mut note_levels = levels.clone();
+        note_levels.push(&note.slugs);
+        let mut file_names = slug_combinations(&note_levels)
+            .into_iter()
+            .map(|mut slugs| {
+                let note_slug = slugs.pop().unwrap_or_default();
+                note_file_name(&slugs, &note_slug)
+            });
+        note.file_name = file_names.next().unwrap_or_default();
+        note.previous_file_names = file_names.collect();
+    }
+    for child in node.children.iter_mut() {
+        assign_file_names(child, levels);
+    }
+    levels.pop();
+}
+
+/// Give a suffix to the note pages whose name is already used by an earlier
+/// page of the run, as a note under a node slugged `section-notes` would be.
+/// The pages are visited in reading order, so the same page keeps the bare name
+/// on every run.
+fn resolve_file_name_collisions(data: &mut GeneratedLectureNotes) {
+    fn visit(node: &mut GeneratedNode, used: &mut HashSet<String>) {
+        for note in node.notes.iter_mut() {
+            if used.contains(&note.file_name) {
+                let stem = note.file_name.trim_end_matches(".html").to_string();
+                let mut suffix = 2usize;
+                while used.contains(&format!("{stem}-{suffix}.html")) {
+                    suffix += 1;
+                }
+                note.file_name = format!("{stem}-{suffix}.html");
+            }
+            used.insert(note.file_name.clone());
+        }
+        for child in node.children.iter_mut() {
+            visit(child, used);
+        }
+    }
+
+    let mut used = HashSet::new();
+    for section in data.sections.iter_mut() {
+        visit(section, &mut used);
+    }
 }
 
 struct GeneratedLectureNotes {
-    sections: Vec<GeneratedSection>,
+    sections: Vec<GeneratedNode>,
+}
+
+/// A note with the titles of the nodes leading to it, from the root section down.
+struct PlacedNote<'a> {
+    path: Vec<&'a str>,
+    note: &'a GeneratedNote,
+}
+
+/// The redirects from the previous names of the note pages to the current ones,
+/// keyed by previous name. A name that is still used by a page is left alone.
+fn note_redirects(placed: &[PlacedNote<'_>]) -> BTreeMap<String, String> {
+    let live = placed
+        .iter()
+        .map(|placed| placed.note.file_name.as_str())
+        .collect::<HashSet<_>>();
+    let mut redirects = BTreeMap::new();
+    for placed in placed {
+        for previous in &placed.note.previous_file_names {
+            if !live.contains(previous.as_str()) {
+                redirects
+                    .entry(previous.clone())
+                    .or_insert_with(|| placed.note.file_name.clone());
+            }
+        }
+    }
+    redirects
+}
+
+fn collect_notes<'a>(
+    node: &'a GeneratedNode,
+    path: &mut Vec<&'a str>,
+    placed: &mut Vec<PlacedNote<'a>>,
+) {
+    path.push(&node.title);
+    for note in &node.notes {
+        placed.push(PlacedNote {
+            path: path.clone(),
+            note,
+        });
+    }
+    for child in &node.children {
+        collect_notes(child, path, placed);
+    }
+    path.pop();
+}
+
+/// Every note of the tree in reading order: a node's own notes come before its children's.
+fn placed_notes(data: &GeneratedLectureNotes) -> Vec<PlacedNote<'_>> {
+    let mut placed = Vec::new();
+    for section in &data.sections {
+        collect_notes(section, &mut Vec::new(), &mut placed);
+    }
+    placed
+}
+
+/// File names of the tag pages, keyed by tag name.
+/// Slugs are assigned in tag name order, so colliding slugs are resolved the same way on every run.
+fn tag_page_files(placed: &[PlacedNote<'_>]) -> HashMap<String, String> {
+    let names = placed
+        .iter()
+        .flat_map(|placed| placed.note.tags.iter())
+        .collect::<BTreeSet<_>>();
+    let mut used = HashSet::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let base = slugify(name);
+            let mut candidate = base.clone();
+            let mut suffix = 2usize;
+            while used.contains(&candidate) {
+                candidate = format!("{base}-{suffix}");
+                suffix += 1;
+            }
+            used.insert(candidate.clone());
+            (name.clone(), format!("{candidate}.html"))
+        })
+        .collect()
 }
 
 pub struct GenerationSummary {
     pub note_pages: usize,
+    pub tag_pages: usize,
+    pub files: usize,
+    /// Redirect pages written for the previous names of the note pages.
+    pub redirects: usize,
 }
 
 #[derive(Debug)]
 pub enum GenerateStaticPagesError {
-    LoadSections,
-    LoadSubsections,
-    LoadNotes,
+    LoadTree,
+    LoadFiles,
     EnvVar(String),
     Io(std::io::Error),
 }
@@ -52,28 +268,6 @@ impl From<std::io::Error> for GenerateStaticPagesError {
     }
 }
 
-fn slugify(value: &str) -> String {
-    let mut slug = String::new();
-    let mut previous_dash = false;
-
-    for ch in value.chars() {
-        if ch.is_ascii_alphanumeric() {
-            slug.push(ch.to_ascii_lowercase());
-            previous_dash = false;
-        } else if !previous_dash {
-            slug.push('-');
-            previous_dash = true;
-        }
-    }
-
-    let slug = slug.trim_matches('-').to_string();
-    if slug.is_empty() {
-        "note".to_string()
-    } else {
-        slug
-    }
-}
-
 fn escape_html(value: &str) -> String {
     value
         .replace('&', "&amp;")
@@ -96,14 +290,15 @@ fn escape_json_for_html(value: String) -> String {
     value.replace("</", "<\\/")
 }
 
-fn note_file_name(section_title: &str, subsection_title: Option<&str>, note_name: &str) -> String {
-    let subsection_part = subsection_title.unwrap_or("section-notes");
-    format!(
-        "{}_{}_{}.html",
-        slugify(section_title),
-        slugify(subsection_part),
-        slugify(note_name)
-    )
+/// Build the note page file name from the slugs of the nodes leading to the note.
+/// Notes attached directly to a section keep the `section-notes` segment.
+fn note_file_name(node_slugs: &[String], note_slug: &str) -> String {
+    let mut parts = node_slugs.iter().map(String::as_str).collect::<Vec<_>>();
+    if parts.len() == 1 {
+        parts.push("section-notes");
+    }
+    parts.push(note_slug);
+    format!("{}.html", parts.join("_"))
 }
 
 fn hostname_from_url(value: &str) -> String {
@@ -138,16 +333,14 @@ fn unique_id(prefix: &str, title: &str, index: usize, used: &mut HashSet<String>
     candidate
 }
 
-fn display_note_description(
-    raw_description: &str,
-    section_title: &str,
-    subsection_title: Option<&str>,
-) -> String {
+fn display_note_description(raw_description: &str, path: &[&str]) -> String {
     let trimmed = raw_description.trim();
     if !trimmed.is_empty() {
         return trimmed.to_string();
     }
 
+    let section_title = path.first().copied().unwrap_or_default();
+    let subsection_title = if path.len() > 1 { path.last() } else { None };
     match subsection_title {
         Some(subsection_title) => {
             format!("Lecture note for {subsection_title} in the {section_title} section.")
@@ -156,8 +349,36 @@ fn display_note_description(
     }
 }
 
+/// A file name as written in a link, with the bytes outside the unreserved URL
+/// characters percent-encoded. Only Unicode slugs need it.
+fn url_file_name(file_name: &str) -> String {
+    file_name
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Path of a note page on the site.
+fn note_page_path(file_name: &str) -> String {
+    format!("/notes_pages/{}", url_file_name(file_name))
+}
+
 fn note_page_absolute_url(file_name: &str) -> String {
-    format!("{SITE_BASE_URL}/notes_pages/{file_name}")
+    format!("{SITE_BASE_URL}{}", note_page_path(file_name))
+}
+
+/// Absolute URL of a link found on a note page; relative links point inside the notes directory.
+fn note_link_absolute_url(url: &str) -> String {
+    if url.contains("://") || url.starts_with('/') {
+        url.to_string()
+    } else {
+        note_page_absolute_url(url)
+    }
 }
 
 fn lecture_notes_absolute_url() -> String {
@@ -204,13 +425,49 @@ fn subsection_html(
     )
 }
 
+fn node_notes_html(node: &GeneratedNode, notes_dir_name: &str) -> Vec<String> {
+    node.notes
+        .iter()
+        .enumerate()
+        .map(|(index, note)| {
+            let href = format!("{notes_dir_name}/{}", url_file_name(&note.file_name));
+            note_item_html(note, index + 1, &href)
+        })
+        .collect()
+}
+
+/// Render the descendants of a section as a flat list of subsection blocks.
+/// Nodes below the second level are labelled with their path from the section.
+fn push_child_nodes_html<'a>(
+    node: &'a GeneratedNode,
+    path: &mut Vec<&'a GeneratedNode>,
+    used_ids: &mut HashSet<String>,
+    notes_dir_name: &str,
+    parts: &mut Vec<String>,
+) {
+    for (child_index, child) in node.children.iter().enumerate() {
+        path.push(child);
+        let slugs = path.iter().map(|node| node.slug()).collect::<Vec<_>>();
+        let subsection_id = unique_id("subsection", &slugs.join(" "), child_index, used_ids);
+        let titles = path[1..].iter().map(|node| node.title.as_str()).collect::<Vec<_>>();
+        parts.push(subsection_html(
+            &subsection_id,
+            &titles.join(" · "),
+            node_notes_html(child, notes_dir_name),
+            child.notes.len(),
+        ));
+        push_child_nodes_html(child, path, used_ids, notes_dir_name, parts);
+        path.pop();
+    }
+}
+
 fn build_lecture_notes_markup(data: &GeneratedLectureNotes, notes_dir_name: &str) -> (String, String) {
     let mut toc_items = Vec::new();
     let mut sections_html = Vec::new();
     let mut used_ids = HashSet::new();
 
     for (section_index, section) in data.sections.iter().enumerate() {
-        let section_id = unique_id("section", &section.title, section_index, &mut used_ids);
+        let section_id = unique_id("section", section.slug(), section_index, &mut used_ids);
         toc_items.push(format!(
             "<li><button type=\"button\" data-target-id=\"{}\" aria-current=\"false\">{}</button></li>",
             escape_html(&section_id),
@@ -225,47 +482,21 @@ fn build_lecture_notes_markup(data: &GeneratedLectureNotes, notes_dir_name: &str
         )];
 
         if !section.notes.is_empty() {
-            let notes_html = section
-                .notes
-                .iter()
-                .enumerate()
-                .map(|(index, note)| {
-                    let file = note_file_name(&section.title, None, &note.name);
-                    note_item_html(note, index + 1, &format!("{notes_dir_name}/{file}"))
-                })
-                .collect::<Vec<_>>();
             section_parts.push(subsection_html(
                 &format!("{section_id}-section-notes"),
                 "Section notes",
-                notes_html,
+                node_notes_html(section, notes_dir_name),
                 section.notes.len(),
             ));
         }
 
-        for (subsection_index, subsection) in section.subsections.iter().enumerate() {
-            let subsection_id = unique_id(
-                "subsection",
-                &format!("{} {}", section.title, subsection.title),
-                subsection_index,
-                &mut used_ids,
-            );
-            let notes_html = subsection
-                .notes
-                .iter()
-                .enumerate()
-                .map(|(index, note)| {
-                    let file = note_file_name(&section.title, Some(&subsection.title), &note.name);
-                    note_item_html(note, index + 1, &format!("{notes_dir_name}/{file}"))
-                })
-                .collect::<Vec<_>>();
-
-            section_parts.push(subsection_html(
-                &subsection_id,
-                &subsection.title,
-                notes_html,
-                subsection.notes.len(),
-            ));
-        }
+        push_child_nodes_html(
+            section,
+            &mut vec![section],
+            &mut used_ids,
+            notes_dir_name,
+            &mut section_parts,
+        );
 
         sections_html.push(format!(
             "<section class=\"section\" id=\"{}\" aria-labelledby=\"{}-title\">{}</section>",
@@ -278,6 +509,15 @@ fn build_lecture_notes_markup(data: &GeneratedLectureNotes, notes_dir_name: &str
     (toc_items.join(""), sections_html.join(""))
 }
 
+fn push_node_keywords(node: &GeneratedNode, keywords: &mut Vec<String>) {
+    if !keywords.iter().any(|keyword| keyword == &node.title) {
+        keywords.push(node.title.clone());
+    }
+    for child in &node.children {
+        push_node_keywords(child, keywords);
+    }
+}
+
 fn lecture_notes_keywords(data: &GeneratedLectureNotes) -> String {
     let mut keywords = vec![
         "lecture notes".to_string(),
@@ -287,55 +527,26 @@ fn lecture_notes_keywords(data: &GeneratedLectureNotes) -> String {
     ];
 
     for section in &data.sections {
-        if !keywords.iter().any(|keyword| keyword == &section.title) {
-            keywords.push(section.title.clone());
-        }
-
-        for subsection in &section.subsections {
-            if !keywords.iter().any(|keyword| keyword == &subsection.title) {
-                keywords.push(subsection.title.clone());
-            }
-        }
+        push_node_keywords(section, &mut keywords);
     }
 
     keywords.join(", ")
 }
 
 fn lecture_notes_structured_data(data: &GeneratedLectureNotes) -> String {
-    let mut position = 1usize;
-    let mut items = Vec::new();
-
-    for section in &data.sections {
-        for note in &section.notes {
-            let file_name = note_file_name(&section.title, None, &note.name);
-            items.push(json!({
+    let items = placed_notes(data)
+        .iter()
+        .enumerate()
+        .map(|(index, placed)| {
+            json!({
                 "@type": "ListItem",
-                "position": position,
-                "url": note_page_absolute_url(&file_name),
-                "name": note.name,
-                "description": display_note_description(&note.description, &section.title, None),
-            }));
-            position += 1;
-        }
-
-        for subsection in &section.subsections {
-            for note in &subsection.notes {
-                let file_name = note_file_name(&section.title, Some(&subsection.title), &note.name);
-                items.push(json!({
-                    "@type": "ListItem",
-                    "position": position,
-                    "url": note_page_absolute_url(&file_name),
-                    "name": note.name,
-                    "description": display_note_description(
-                        &note.description,
-                        &section.title,
-                        Some(&subsection.title),
-                    ),
-                }));
-                position += 1;
-            }
-        }
-    }
+                "position": index + 1,
+                "url": note_page_absolute_url(&placed.note.file_name),
+                "name": placed.note.name,
+                "description": display_note_description(&placed.note.description, &placed.path),
+            })
+        })
+        .collect::<Vec<_>>();
 
     escape_json_for_html(
         json!({
@@ -360,135 +571,294 @@ fn lecture_notes_structured_data(data: &GeneratedLectureNotes) -> String {
     )
 }
 
-fn note_keywords(section_title: &str, subsection_title: Option<&str>, note_name: &str) -> String {
-    let mut keywords = vec![
-        note_name.to_string(),
-        section_title.to_string(),
-        "lecture note".to_string(),
-        "study material".to_string(),
-        "pdf".to_string(),
-    ];
+fn note_tags_html(tags: &[String], tag_files: &HashMap<String, String>) -> String {
+    if tags.is_empty() {
+        return "".to_string();
+    }
+    let chips = tags
+        .iter()
+        .map(|tag| {
+            format!(
+                "<a class=\"single-note-chip\" href=\"{TAGS_DIR_NAME}/{}\" rel=\"tag\">{}</a>",
+                escape_html(&url_file_name(
+                    tag_files.get(tag).map(String::as_str).unwrap_or_default()
+                )),
+                escape_html(tag)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "<div class=\"single-note-chips\" aria-label=\"Tags\">{}</div>",
+        chips.join("")
+    )
+}
 
-    if let Some(subsection_title) = subsection_title {
-        keywords.push(subsection_title.to_string());
+/// The stored MIME type of an attachment, if any.
+fn attachment_mime_type(attachment: &AttachmentReturn) -> Option<&str> {
+    attachment
+        .mime_type
+        .as_deref()
+        .map(str::trim)
+        .filter(|mime_type| !mime_type.is_empty())
+}
+
+/// Font Awesome icon matching the MIME type of an attachment; links without one get a link icon.
+fn attachment_icon(mime_type: Option<&str>) -> &'static str {
+    let Some(mime_type) = mime_type else {
+        return "fa-link";
+    };
+    match mime_type {
+        "application/pdf" => "fa-file-pdf",
+        "application/vnd.ms-powerpoint"
+        | "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+        | "application/vnd.oasis.opendocument.presentation" => "fa-file-powerpoint",
+        "application/msword"
+        | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        | "application/vnd.oasis.opendocument.text" => "fa-file-word",
+        "application/zip"
+        | "application/gzip"
+        | "application/x-tar"
+        | "application/x-7z-compressed"
+        | "application/vnd.rar" => "fa-file-zipper",
+        "text/html" => "fa-link",
+        "application/json" | "application/x-tex" | "application/x-ipynb+json" => "fa-file-code",
+        _ if mime_type.starts_with("video/") => "fa-file-video",
+        _ if mime_type.starts_with("audio/") => "fa-file-audio",
+        _ if mime_type.starts_with("image/") => "fa-file-image",
+        _ if mime_type.starts_with("text/x-") => "fa-file-code",
+        _ if mime_type.starts_with("text/") => "fa-file-lines",
+        _ => "fa-file",
     }
+}
 
-    keywords.push("Yehor Korotenko".to_string());
-    keywords.join(", ")
+/// Human readable attachment size, e.g. `2.4 MB`.
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
 }
 
-pub async fn generate_static_pages(
-    pool: &sqlx::Pool<sqlx::MySql>,
-) -> Result<GenerationSummary, GenerateStaticPagesError> {
-    let sections = crate::services::sections::get_sections(
-        pool,
-        crate::services::sections::GetSectionsForm {
-            id: None,
-            title: None,
-            position: None,
-            limit: None,
-        },
-    )
-    .await
-    .map_err(|_| GenerateStaticPagesError::LoadSections)?;
-
-    let subsections = crate::services::subsections::get_subsections(
-        pool,
-        crate::services::subsections::GetSubsectionsForm {
-            id: None,
-            title: None,
-            position: None,
-            section_id: None,
-            limit: None,
-        },
-    )
-    .await
-    .map_err(|_| GenerateStaticPagesError::LoadSubsections)?;
-
-    let notes = crate::services::lecture_notes::get_notes(
-        pool,
-        crate::services::lecture_notes::GetNotesForm {
-            id: None,
-            name: None,
-            url: None,
-            position: None,
-            section_id: None,
-            subsection_id: None,
-            limit: None,
-        },
+fn note_attachments_html(attachments: &[AttachmentReturn]) -> String {
+    if attachments.is_empty() {
+        return "".to_string();
+    }
+    let items = attachments
+        .iter()
+        .map(|attachment| {
+            let size = attachment
+                .size
+                .map(|size| {
+                    format!(
+                        "<span class=\"single-note-attachment-size\">{}</span>",
+                        format_size(size)
+                    )
+                })
+                .unwrap_or_default();
+            format!(
+                "<li><a class=\"single-note-attachment\" href=\"{}\" target=\"_blank\" rel=\"noopener noreferrer\"><i class=\"fas {}\" aria-hidden=\"true\"></i><span>{}</span></a>{}</li>",
+                escape_html(&attachment.url),
+                attachment_icon(attachment_mime_type(attachment)),
+                escape_html(&attachment.label),
+                size
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "<ul class=\"single-note-attachments\" aria-label=\"Attachments\">{}</ul>",
+        items.join("")
     )
-    .await
-    .map_err(|_| GenerateStaticPagesError::LoadNotes)?;
-
-    let mut notes_by_subsection: HashMap<u32, Vec<GeneratedNote>> = HashMap::new();
-    let mut notes_by_section: HashMap<u32, Vec<GeneratedNote>> = HashMap::new();
+}
 
-    for note in notes {
-        let generated_note = GeneratedNote {
-            name: note.name,
-            description: note.description,
-            url: note.url,
-            position: note.position,
-        };
-
-        if let Some(subsection_id) = note.subsection_id {
-            notes_by_subsection
-                .entry(subsection_id)
-                .or_default()
-                .push(generated_note);
-        } else if let Some(section_id) = note.section_id {
-            notes_by_section
-                .entry(section_id)
-                .or_default()
-                .push(generated_note);
+/// `encodingFormat` of a note: the distinct MIME types of its attachments, or null when unknown.
+fn note_encoding_format(attachments: &[AttachmentReturn]) -> serde_json::Value {
+    let mut formats: Vec<&str> = Vec::new();
+    for mime_type in attachments.iter().filter_map(attachment_mime_type) {
+        if !formats.contains(&mime_type) {
+            formats.push(mime_type);
         }
     }
+    match formats.len() {
+        0 => serde_json::Value::Null,
+        1 => json!(formats[0]),
+        _ => json!(formats),
+    }
+}
 
-    let mut subsections_by_section: HashMap<u32, Vec<GeneratedSubsection>> = HashMap::new();
-
-    for subsection in subsections {
-        let mut subsection_notes = notes_by_subsection
-            .remove(&subsection.id)
-            .unwrap_or_default();
-        subsection_notes.sort_by_key(|note| note.position);
+/// schema.org `MediaObject` entries for the note attachments, or null when there is none.
+fn note_attachments_structured_data(attachments: &[AttachmentReturn]) -> serde_json::Value {
+    if attachments.is_empty() {
+        return serde_json::Value::Null;
+    }
+    json!(attachments
+        .iter()
+        .map(|attachment| json!({
+            "@type": "MediaObject",
+            "name": attachment.label,
+            "contentUrl": note_link_absolute_url(&attachment.url),
+            "encodingFormat": attachment_mime_type(attachment),
+            "contentSize": attachment.size.map(format_size),
+        }))
+        .collect::<Vec<_>>())
+}
 
-        let generated_subsection = GeneratedSubsection {
-            title: subsection.title,
-            position: subsection.position,
-            notes: subsection_notes,
-        };
+/// schema.org `Person` entries for the note authors, or null when none is known.
+fn note_authors_structured_data(authors: &[String]) -> serde_json::Value {
+    if authors.is_empty() {
+        return serde_json::Value::Null;
+    }
+    json!(authors
+        .iter()
+        .map(|author| json!({ "@type": "Person", "name": author }))
+        .collect::<Vec<_>>())
+}
 
-        subsections_by_section
-            .entry(subsection.section_id)
-            .or_default()
-            .push(generated_subsection);
+fn optional_json(value: Option<&str>) -> serde_json::Value {
+    match value.map(str::trim) {
+        Some(value) if !value.is_empty() => json!(value),
+        _ => serde_json::Value::Null,
     }
+}
 
-    let mut generated_sections: Vec<GeneratedSection> = sections
+/// Extra chips shown next to the section on a note page: kind, course code and term.
+fn note_meta_extra_html(metadata: &NoteMetadata) -> String {
+    let labels = [
+        metadata.kind.map(|kind| kind.label().to_string()),
+        metadata.course_code.clone(),
+        metadata.term.clone(),
+    ];
+    labels
         .into_iter()
-        .map(|section| {
-            let mut section_subsections = subsections_by_section
-                .remove(&section.id)
-                .unwrap_or_default();
-            section_subsections.sort_by_key(|subsection| subsection.position);
+        .flatten()
+        .filter(|label| !label.trim().is_empty())
+        .map(|label| format!("<span class=\"single-note-tag\">{}</span>", escape_html(&label)))
+        .collect::<Vec<_>>()
+        .join("")
+}
 
-            let mut section_notes = notes_by_section.remove(&section.id).unwrap_or_default();
-            section_notes.sort_by_key(|note| note.position);
+fn note_keywords(path: &[&str], note: &GeneratedNote) -> String {
+    let note_name = note.name.as_str();
+    let mut keywords = vec![
+        note_name.to_string(),
+        path.first().copied().unwrap_or_default().to_string(),
+        "lecture note".to_string(),
+        "study material".to_string(),
+        "pdf".to_string(),
+    ];
 
-            GeneratedSection {
-                title: section.title,
-                position: section.position,
-                subsections: section_subsections,
-                notes: section_notes,
+    for title in path.iter().skip(1) {
+        keywords.push(title.to_string());
+    }
+    keywords.extend(note.tags.iter().cloned());
+    if let Some(course_code) = &note.metadata.course_code {
+        keywords.push(course_code.clone());
+    }
+    keywords.extend(note.metadata.authors.iter().cloned());
+    keywords.join(", ")
+}
+
+fn push_node_file_ids(node: &GeneratedNode, ids: &mut BTreeSet<u32>) {
+    for note in &node.notes {
+        ids.extend(note.attachments.iter().filter_map(|attachment| attachment.file_id));
+    }
+    for child in &node.children {
+        push_node_file_ids(child, ids);
+    }
+}
+
+fn rewrite_node_file_urls(node: &mut GeneratedNode, file_urls: &HashMap<u32, String>) {
+    for note in node.notes.iter_mut() {
+        for attachment in note.attachments.iter_mut() {
+            if let Some(url) = attachment.file_id.and_then(|id| file_urls.get(&id)) {
+                attachment.url = url.clone();
             }
-        })
-        .collect();
+        }
+    }
+    for child in node.children.iter_mut() {
+        rewrite_node_file_urls(child, file_urls);
+    }
+}
 
-    generated_sections.sort_by_key(|section| section.position);
+/// Name of the copy of an uploaded file: its hash, followed by the extension of its original name.
+fn copied_file_name(file: &crate::services::files::FileReturn) -> String {
+    let extension = std::path::Path::new(&file.original_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+        .filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()));
+    match extension {
+        Some(extension) => format!("{}.{extension}", file.sha256),
+        None => file.sha256.clone(),
+    }
+}
 
-    let data = GeneratedLectureNotes {
-        sections: generated_sections,
+/// Copy the uploaded files attached to the notes into the notes directory and
+/// point the attachments to the copies. Files missing from the storage keep
+/// their download URL. Returns the number of available files.
+async fn copy_attached_files(
+    storage: &Storage,
+    file_storage: &crate::services::files::FileStorageConfig,
+    data: &mut GeneratedLectureNotes,
+    notes_dir: &str,
+) -> Result<usize, GenerateStaticPagesError> {
+    let mut ids = BTreeSet::new();
+    for section in &data.sections {
+        push_node_file_ids(section, &mut ids);
+    }
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let ids = ids.into_iter().collect::<Vec<_>>();
+    let files = crate::services::files::get_files_by_ids(storage, &ids)
+        .await
+        .map_err(|_| GenerateStaticPagesError::LoadFiles)?;
+
+    let files_dir = format!("{notes_dir}/{FILES_DIR_NAME}");
+    fs::create_dir_all(&files_dir).await?;
+    let mut file_urls = HashMap::new();
+    for file in files {
+        let file_name = copied_file_name(&file);
+        let output_path = format!("{files_dir}/{file_name}");
+        let up_to_date = fs::metadata(&output_path)
+            .await
+            .is_ok_and(|metadata| metadata.len() == file.size);
+        if !up_to_date {
+            if let Err(err) = fs::copy(file_storage.content_path(&file.sha256), &output_path).await {
+                loggit::warn!("could not copy file {}: {:?}", file.id, err);
+                continue;
+            }
+        }
+        file_urls.insert(file.id, format!("{FILES_DIR_NAME}/{file_name}"));
+    }
+    for section in data.sections.iter_mut() {
+        rewrite_node_file_urls(section, &file_urls);
+    }
+    Ok(file_urls.len())
+}
+
+pub async fn generate_static_pages(
+    storage: &Storage,
+    file_storage: &crate::services::files::FileStorageConfig,
+) -> Result<GenerationSummary, GenerateStaticPagesError> {
+    let visibility = crate::services::publishing::Visibility::Public;
+    let tree = crate::services::tree::get_section_trees(storage, None, visibility)
+        .await
+        .map_err(|_| GenerateStaticPagesError::LoadTree)?;
+
+    let mut data = GeneratedLectureNotes {
+        sections: tree.into_iter().map(GeneratedNode::from).collect(),
     };
+    for section in data.sections.iter_mut() {
+        assign_file_names(section, &mut Vec::new());
+    }
+    resolve_file_name_collisions(&mut data);
 
     let lecture_notes_html_path = std::env::var("LECTURE_NOTES_HTML_PATH")
         .map_err(|_| GenerateStaticPagesError::EnvVar("LECTURE_NOTES_HTML_PATH".to_string()))?;
@@ -496,11 +866,14 @@ pub async fn generate_static_pages(
         .map_err(|_| GenerateStaticPagesError::EnvVar("NOTES_DIRECTORY_PATH".to_string()))?;
     let styles_css_path = std::env::var("STYLES_CSS_PATH")
         .map_err(|_| GenerateStaticPagesError::EnvVar("STYLES_CSS_PATH".to_string()))?;
+    let redirects_file_path = std::env::var("REDIRECTS_FILE_PATH")
+        .unwrap_or_else(|_| format!("{notes_dir}/_redirects"));
 
     fs::create_dir_all(&notes_dir).await?;
     if let Some(parent) = std::path::Path::new(&lecture_notes_html_path).parent() {
         fs::create_dir_all(parent).await?;
     }
+    let files = copy_attached_files(storage, file_storage, &mut data, &notes_dir).await?;
 
     let notes_dir_name = std::path::Path::new(&notes_dir)
         .file_name()
@@ -525,70 +898,225 @@ pub async fn generate_static_pages(
     let lecture_notes_js_output = format!("{notes_dir}/lecture-notes.js");
     fs::write(lecture_notes_js_output, LECTURE_NOTES_JS_TEMPLATE).await?;
 
+    let placed = placed_notes(&data);
+    let tag_files = tag_page_files(&placed);
+
     let mut note_pages = 0usize;
-    for section in &data.sections {
-        for note in &section.notes {
-            write_note_page(note, &section.title, None, &notes_dir, &styles_css_path).await?;
-            note_pages += 1;
-        }
+    for placed in &placed {
+        write_note_page(
+            placed.note,
+            &placed.path,
+            &tag_files,
+            &notes_dir,
+            &styles_css_path,
+        )
+        .await?;
+        note_pages += 1;
+    }
 
-        for subsection in &section.subsections {
-            for note in &subsection.notes {
-                write_note_page(note, &section.title, Some(&subsection.title), &notes_dir, &styles_css_path).await?;
-                note_pages += 1;
-            }
-        }
+    let redirects = note_redirects(&placed);
+    write_redirects(&redirects, &notes_dir, &redirects_file_path).await?;
+
+    let tags_dir = format!("{notes_dir}/{TAGS_DIR_NAME}");
+    fs::create_dir_all(&tags_dir).await?;
+    let nested_styles_css_path = nested_asset_path(&styles_css_path);
+    for (tag, file_name) in &tag_files {
+        let tagged = placed
+            .iter()
+            .filter(|placed| placed.note.tags.contains(tag))
+            .collect::<Vec<_>>();
+        write_tag_page(tag, file_name, &tagged, &tags_dir, &nested_styles_css_path).await?;
+    }
+
+    Ok(GenerationSummary {
+        note_pages,
+        tag_pages: tag_files.len(),
+        files,
+        redirects: redirects.len(),
+    })
+}
+
+/// Write a page sending the visitors and the crawlers of each previous note page
+/// name to the current one, along with a `_redirects` file for the static hosts
+/// that can answer with a permanent redirect instead.
+async fn write_redirects(
+    redirects: &BTreeMap<String, String>,
+    notes_dir: &str,
+    redirects_file_path: &str,
+) -> Result<(), GenerateStaticPagesError> {
+    let mut lines = Vec::new();
+    for (previous, current) in redirects {
+        let html = REDIRECT_PAGE_TEMPLATE
+            .replace("{{TARGET_NAME}}", &escape_html(current))
+            .replace("{{TARGET_FILE_NAME}}", &escape_html(&url_file_name(current)))
+            .replace("{{TARGET_URL}}", &escape_html(&note_page_absolute_url(current)));
+        fs::write(format!("{notes_dir}/{previous}"), html).await?;
+        lines.push(format!(
+            "{} {} 301\n",
+            note_page_path(previous),
+            note_page_path(current)
+        ));
     }
+    if let Some(parent) = std::path::Path::new(redirects_file_path).parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(redirects_file_path, lines.concat()).await?;
+    Ok(())
+}
+
+/// Asset path as seen from a page one directory below the note pages.
+/// Absolute paths and URLs are kept as they are.
+fn nested_asset_path(path: &str) -> String {
+    if path.starts_with('/') || path.contains("://") {
+        path.to_string()
+    } else {
+        format!("../{path}")
+    }
+}
+
+fn tag_page_absolute_url(file_name: &str) -> String {
+    format!(
+        "{SITE_BASE_URL}/notes_pages/{TAGS_DIR_NAME}/{}",
+        url_file_name(file_name)
+    )
+}
+
+async fn write_tag_page(
+    tag: &str,
+    file_name: &str,
+    notes: &[&PlacedNote<'_>],
+    tags_dir: &str,
+    styles_css_path: &str,
+) -> Result<(), GenerateStaticPagesError> {
+    let notes_html = notes
+        .iter()
+        .enumerate()
+        .map(|(index, placed)| {
+            let href = format!("../{}", url_file_name(&placed.note.file_name));
+            note_item_html(placed.note, index + 1, &href)
+        })
+        .collect::<Vec<_>>();
+    let items = notes
+        .iter()
+        .enumerate()
+        .map(|(index, placed)| {
+            json!({
+                "@type": "ListItem",
+                "position": index + 1,
+                "url": note_page_absolute_url(&placed.note.file_name),
+                "name": placed.note.name,
+            })
+        })
+        .collect::<Vec<_>>();
+    let structured_data = escape_json_for_html(
+        json!({
+            "@context": "https://schema.org",
+            "@type": "CollectionPage",
+            "name": format!("{tag} — Lecture Notes"),
+            "url": tag_page_absolute_url(file_name),
+            "mainEntity": {
+                "@type": "ItemList",
+                "numberOfItems": items.len(),
+                "itemListElement": items,
+            }
+        })
+        .to_string(),
+    );
+
+    let html = TAG_PAGE_TEMPLATE
+        .replace("{{TAG_NAME}}", &escape_html(tag))
+        .replace("{{TAG_FILE_NAME}}", &escape_html(&url_file_name(file_name)))
+        .replace(
+            "{{TAG_NOTE_COUNT}}",
+            &format!(
+                "{} {}",
+                notes.len(),
+                if notes.len() == 1 { "note" } else { "notes" }
+            ),
+        )
+        .replace("{{TAG_NOTES_HTML}}", &notes_html.join(""))
+        .replace("{{TAG_STRUCTURED_DATA}}", &structured_data)
+        .replace("{{STYLES_CSS_PATH}}", styles_css_path);
 
-    Ok(GenerationSummary { note_pages })
+    fs::write(format!("{tags_dir}/{file_name}"), html).await?;
+    Ok(())
 }
 
 async fn write_note_page(
     note: &GeneratedNote,
-    section_title: &str,
-    subsection_title: Option<&str>,
+    path: &[&str],
+    tag_files: &HashMap<String, String>,
     notes_dir: &str,
     styles_css_path: &str,
 ) -> Result<(), GenerateStaticPagesError> {
-    let file_name = note_file_name(section_title, subsection_title, &note.name);
+    let file_name = &note.file_name;
     let output_path = format!("{notes_dir}/{file_name}");
 
-    let meta_primary = subsection_title.unwrap_or(section_title);
+    let section_title = path.first().copied().unwrap_or_default();
+    let meta_primary = path.last().copied().unwrap_or_default();
     let meta_secondary = format!("Section: {section_title}");
-    let description = display_note_description(&note.description, section_title, subsection_title);
+    let about = if path.len() > 1 {
+        path.to_vec()
+    } else {
+        vec![section_title, "section notes"]
+    };
+    let description = display_note_description(&note.description, path);
     let structured_data = escape_json_for_html(
         json!({
             "@context": "https://schema.org",
             "@type": "LearningResource",
             "name": note.name,
             "description": description,
-            "url": note_page_absolute_url(&file_name),
+            "url": note_page_absolute_url(file_name),
             "isAccessibleForFree": true,
             "educationalUse": "study reference",
-            "author": {
-                "@type": "Person",
-                "name": "Yehor Korotenko",
-                "url": SITE_BASE_URL,
+            "author": note_authors_structured_data(&note.metadata.authors),
+            "learningResourceType": note.metadata.kind.map(|kind| kind.label()),
+            "inLanguage": optional_json(note.metadata.language.as_deref()),
+            "license": optional_json(note.metadata.license.as_deref()),
+            "isPartOf": match optional_json(note.metadata.course_code.as_deref()) {
+                serde_json::Value::Null => serde_json::Value::Null,
+                course_code => json!({
+                    "@type": "Course",
+                    "name": section_title,
+                    "courseCode": course_code,
+                }),
             },
-            "about": [
-                section_title,
-                subsection_title.unwrap_or("section notes"),
-            ],
+            "temporalCoverage": optional_json(note.metadata.term.as_deref()),
+            "about": about,
             "sameAs": if note.url.trim().is_empty() {
                 serde_json::Value::Null
             } else {
                 json!(note.url)
             },
-            "encodingFormat": if note.url.trim_end_matches('/').to_lowercase().ends_with(".pdf") {
-                serde_json::Value::String("application/pdf".to_string())
-            } else {
-                serde_json::Value::Null
-            },
+            "encodingFormat": note_encoding_format(&note.attachments),
+            "associatedMedia": note_attachments_structured_data(&note.attachments),
         })
         .to_string(),
     );
 
+    let authors = note.metadata.authors.join(", ");
+    let (author_html, author_meta) = if authors.is_empty() {
+        ("".to_string(), "".to_string())
+    } else {
+        (
+            format!("<p class=\"single-note-author\">by {}</p>", escape_html(&authors)),
+            format!("<meta name=\"author\" content=\"{}\" />", escape_html(&authors)),
+        )
+    };
+    let language = note
+        .metadata
+        .language
+        .as_deref()
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .unwrap_or("en");
+
     let html = NOTE_PAGE_TEMPLATE
+        .replace("{{NOTE_LANGUAGE}}", &escape_html(language))
+        .replace("{{NOTE_AUTHOR_HTML}}", &author_html)
+        .replace("{{NOTE_AUTHOR_META}}", &author_meta)
+        .replace("{{NOTE_META_EXTRA}}", &note_meta_extra_html(&note.metadata))
         .replace("{{NOTE_TITLE}}", &escape_html(&note.name))
         .replace(
             "{{NOTE_DESCRIPTION_META}}",
@@ -596,16 +1124,18 @@ async fn write_note_page(
         )
         .replace("{{NOTE_DESCRIPTION_BODY}}", &escape_html(&description))
         .replace("{{NOTE_URL}}", &escape_html(&note.url))
-        .replace("{{NOTE_FILE_NAME}}", &escape_html(&file_name))
+        .replace("{{NOTE_FILE_NAME}}", &escape_html(&url_file_name(file_name)))
         .replace("{{NOTE_META_PRIMARY}}", &escape_html(meta_primary))
         .replace("{{NOTE_META_SECONDARY}}", &escape_html(&meta_secondary))
+        .replace("{{NOTE_SECTION_NAME}}", &escape_html(meta_primary))
         .replace(
-            "{{NOTE_SECTION_NAME}}",
-            &escape_html(subsection_title.unwrap_or(section_title)),
+            "{{NOTE_KEYWORDS}}",
+            &escape_html(&note_keywords(path, note)),
         )
+        .replace("{{NOTE_TAGS_HTML}}", &note_tags_html(&note.tags, tag_files))
         .replace(
-            "{{NOTE_KEYWORDS}}",
-            &escape_html(&note_keywords(section_title, subsection_title, &note.name)),
+            "{{NOTE_ATTACHMENTS_HTML}}",
+            &note_attachments_html(&note.attachments),
         )
         .replace("{{NOTE_STRUCTURED_DATA}}", &structured_data)
         .replace("{{STYLES_CSS_PATH}}", styles_css_path);
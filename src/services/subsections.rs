@@ -1,147 +1,324 @@
+use std::collections::HashSet;
+
 use crate::db;
+use crate::db::query::Value;
+use crate::db::slugs::SlugError;
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::pagination::{Page, PageError, PageRequest, Sort, SortField, Sortable};
+use crate::services::publishing::{Status, Visibility};
 use serde::Serialize;
+use utoipa::ToSchema;
 
+#[derive(Default)]
 pub struct CreateSubsectionForm {
     pub title: String,
     pub section_id: u32,
+    pub status: Status,
+    /// Seconds since the Unix epoch before which the subsection stays hidden.
+    pub publish_at: Option<i64>,
+    /// The title slugified when None.
+    pub slug: Option<String>,
 }
 
+#[derive(Default)]
 pub struct UpdateSubsectionForm {
     pub title: Option<String>,
     pub section_id: Option<u32>,
     pub position: Option<u32>,
+    pub status: Option<Status>,
+    /// `Some(None)` clears the publication time.
+    pub publish_at: Option<Option<i64>>,
+    /// The previous slug keeps redirecting to the subsection.
+    pub slug: Option<String>,
+    /// The version of the subsection the update was made against.
+    pub version: u32,
 }
 
+#[derive(Default)]
 pub struct GetSubsectionsForm {
     pub id: Option<u32>,
     pub title: Option<String>,
     pub position: Option<u32>,
     pub section_id: Option<u32>,
-    pub limit: Option<u32>,
+    pub visibility: Visibility,
+    pub page: PageRequest,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct SubsectionReturn {
     pub id: u32,
     pub title: String,
     pub position: u32,
     pub section_id: u32,
+    /// Seconds since the Unix epoch.
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Incremented by every update and move, required by the next ones.
+    pub version: u32,
+    pub status: Status,
+    pub publish_at: Option<i64>,
+    /// Names the pages of the subsection, unique within its section.
+    pub slug: String,
+    /// The slugs the subsection had before, oldest first.
+    pub previous_slugs: Vec<String>,
 }
 
 impl From<db::subsections::SubsectionFromDb> for SubsectionReturn {
     fn from(value: db::subsections::SubsectionFromDb) -> Self {
+        let previous_slugs = db::slugs::previous_slugs_from_json(&value.previous_slugs);
         Self {
             id: value.id,
             title: value.title,
             position: value.position,
             section_id: value.section_id,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            version: value.version,
+            status: Status::from_db(&value.status),
+            publish_at: value.publish_at,
+            slug: value.slug,
+            previous_slugs,
+        }
+    }
+}
+
+impl Sortable for SubsectionReturn {
+    const DEFAULT_SORT: Sort = Sort::asc(SortField::Position);
+
+    fn sort_column(field: SortField) -> Option<&'static str> {
+        match field {
+            SortField::Position => Some("position"),
+            SortField::Name => Some("title"),
+            SortField::Id => Some("id"),
+            SortField::CreatedAt => Some("created_at"),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn sort_value(&self, field: SortField) -> Value {
+        match field {
+            SortField::Position => self.position.into(),
+            SortField::Name => self.title.clone().into(),
+            SortField::Id => self.id.into(),
+            SortField::CreatedAt => self.created_at.into(),
         }
     }
 }
 
+/// The slugs of the subsections of `section_id`, but the one with id `except`.
+async fn sibling_slugs(
+    storage: &Storage,
+    section_id: u32,
+    except: Option<u32>,
+) -> Result<HashSet<String>, Error> {
+    let subsections = storage
+        .subsections
+        .get_subsections(db::subsections::GetSubsectionsForm {
+            section_id: Some(section_id),
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::subsections::GetSubsectionsError::UnexpectedError(err)| err)?;
+    Ok(subsections
+        .into_iter()
+        .filter(|subsection| Some(subsection.id) != except)
+        .map(|subsection| subsection.slug)
+        .collect())
+}
+
 #[derive(Debug)]
 pub enum CreateSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
+    InvalidSlugError,
+    SlugTakenError,
+}
+
+impl From<SlugError> for CreateSubsectionError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => CreateSubsectionError::InvalidSlugError,
+            SlugError::SlugTakenError => CreateSubsectionError::SlugTakenError,
+        }
+    }
 }
 
 pub async fn create_subsection(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: CreateSubsectionForm,
 ) -> Result<(), CreateSubsectionError> {
-    db::subsections::create_subsection(
-        pool,
-        db::subsections::CreateSubsectionForm {
+    let siblings = sibling_slugs(storage, form.section_id, None)
+        .await
+        .map_err(CreateSubsectionError::UnexpectedError)?;
+    let slug = db::slugs::slug_for_new(form.slug, &form.title, &siblings)?;
+    let res = storage
+        .subsections
+        .create_subsection(db::subsections::CreateSubsectionForm {
             title: form.title,
             section_id: form.section_id,
-        },
-    )
-    .await
-    .map_err(|_| CreateSubsectionError::UnexpectedError)
+            status: Some(form.status.as_str().to_string()),
+            publish_at: form.publish_at,
+            slug: Some(slug),
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(CreateSubsectionError::UnexpectedError)
 }
 
 #[derive(Debug)]
 pub enum GetSubsectionsError {
-    UnexpectedError,
+    PageError(PageError),
+    UnexpectedError(Error),
 }
 
 pub async fn get_subsections(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: GetSubsectionsForm,
-) -> Result<Vec<SubsectionReturn>, GetSubsectionsError> {
-    let res = db::subsections::get_subsections(
-        pool,
-        db::subsections::GetSubsectionsForm {
-            id: form.id,
-            title: form.title,
-            position: form.position,
-            section_id: form.section_id,
-            limit: form.limit,
-            ..Default::default()
-        },
-    )
-    .await;
-    match res {
-        Ok(list) => Ok(list.into_iter().map(SubsectionReturn::from).collect()),
-        Err(db::subsections::GetSubsectionsError::UnexpectedError) => {
-            Err(GetSubsectionsError::UnexpectedError)
-        }
-    }
+) -> Result<Page<SubsectionReturn>, GetSubsectionsError> {
+    let clauses = form
+        .page
+        .clauses::<SubsectionReturn>()
+        .map_err(GetSubsectionsError::PageError)?;
+    let filters = || db::subsections::GetSubsectionsForm {
+        id: form.id,
+        title: form.title.clone(),
+        position: form.position,
+        section_id: form.section_id,
+        published_at: form.visibility.published_at(),
+        ..Default::default()
+    };
+    let list = storage
+        .subsections
+        .get_subsections(db::subsections::GetSubsectionsForm {
+            filter: clauses.filter,
+            order_by: clauses.order_by,
+            limit: clauses.limit,
+            offset: clauses.offset,
+            ..filters()
+        })
+        .await
+        .map_err(
+            |db::subsections::GetSubsectionsError::UnexpectedError(err)| {
+                GetSubsectionsError::UnexpectedError(err)
+            },
+        )?;
+    let total = match form.page.known_total(list.len()) {
+        Some(total) => total,
+        None => storage
+            .subsections
+            .count_subsections(filters())
+            .await
+            .map_err(
+                |db::subsections::GetSubsectionsError::UnexpectedError(err)| {
+                    GetSubsectionsError::UnexpectedError(err)
+                },
+            )?,
+    };
+    let list = list.into_iter().map(SubsectionReturn::from).collect();
+    Ok(form.page.page(list, total))
 }
 
 #[derive(Debug)]
 pub enum GetSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
 }
 
 pub async fn get_subsection(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     id: u32,
+    visibility: Visibility,
 ) -> Result<SubsectionReturn, GetSubsectionError> {
-    let res = db::subsections::get_subsection(
-        pool,
-        db::subsections::GetSubsectionsForm {
+    let res = storage
+        .subsections
+        .get_subsection(db::subsections::GetSubsectionsForm {
             id: Some(id),
+            published_at: visibility.published_at(),
             ..Default::default()
-        },
-    )
-    .await;
+        })
+        .await;
     match res {
         Ok(val) => Ok(SubsectionReturn::from(val)),
         Err(db::subsections::GetSubsectionError::NotFoundError) => {
             Err(GetSubsectionError::NotFoundError)
         }
-        Err(db::subsections::GetSubsectionError::UnexpectedError) => {
-            Err(GetSubsectionError::UnexpectedError)
+        Err(db::subsections::GetSubsectionError::UnexpectedError(err)) => {
+            Err(GetSubsectionError::UnexpectedError(err))
         }
     }
 }
 
 #[derive(Debug)]
 pub enum UpdateSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError,
     NothingToUpdateError,
+    InvalidSlugError,
+    SlugTakenError,
+    /// The subsection moved past the expected version, it is returned as it is now.
+    VersionConflictError(SubsectionReturn),
+}
+
+impl From<SlugError> for UpdateSubsectionError {
+    fn from(value: SlugError) -> Self {
+        match value {
+            SlugError::InvalidSlugError => UpdateSubsectionError::InvalidSlugError,
+            SlugError::SlugTakenError => UpdateSubsectionError::SlugTakenError,
+        }
+    }
 }
 
 pub async fn update_subsection(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     id: u32,
     form: UpdateSubsectionForm,
 ) -> Result<(), UpdateSubsectionError> {
-    let res = db::subsections::update_subsections(
-        pool,
-        db::subsections::UpdateSubsectionForm {
-            title: form.title,
-            section_id: form.section_id,
-            position: form.position,
-        },
-        db::subsections::GetSubsectionsForm {
-            id: Some(id),
-            ..Default::default()
-        },
-    )
-    .await;
+    // Moving to another section can clash with the slug of a subsection there too.
+    let slug_change = if form.slug.is_some() || form.section_id.is_some() {
+        let current = match get_subsection(storage, id, Visibility::Everything).await {
+            Ok(current) => current,
+            Err(GetSubsectionError::NotFoundError) => {
+                return Err(UpdateSubsectionError::NotFoundError)
+            }
+            Err(GetSubsectionError::UnexpectedError(err)) => {
+                return Err(UpdateSubsectionError::UnexpectedError(err))
+            }
+        };
+        let section_id = form.section_id.unwrap_or(current.section_id);
+        let siblings = sibling_slugs(storage, section_id, Some(id))
+            .await
+            .map_err(UpdateSubsectionError::UnexpectedError)?;
+        db::slugs::slug_update(&current.slug, current.previous_slugs, form.slug, &siblings)?
+    } else {
+        None
+    };
+    let (slug, previous_slugs) = match slug_change {
+        Some(change) => (Some(change.slug), Some(change.previous_slugs)),
+        None => (None, None),
+    };
+    let res = storage
+        .subsections
+        .update_subsections(
+            db::subsections::UpdateSubsectionForm {
+                title: form.title,
+                section_id: form.section_id,
+                position: form.position,
+                status: form.status.map(|status| status.as_str().to_string()),
+                publish_at: form.publish_at,
+                slug,
+                previous_slugs,
+                version: Some(form.version),
+            },
+            db::subsections::GetSubsectionsForm {
+                id: Some(id),
+                ..Default::default()
+            },
+        )
+        .await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::subsections::UpdateSubsectionsError::NotFoundError) => {
@@ -150,44 +327,60 @@ pub async fn update_subsection(
         Err(db::subsections::UpdateSubsectionsError::NothingToUpdateError) => {
             Err(UpdateSubsectionError::NothingToUpdateError)
         }
-        Err(db::subsections::UpdateSubsectionsError::UnexpectedError) => {
-            Err(UpdateSubsectionError::UnexpectedError)
+        Err(db::subsections::UpdateSubsectionsError::VersionConflictError) => {
+            match get_subsection(storage, id, Visibility::Everything).await {
+                Ok(current) => Err(UpdateSubsectionError::VersionConflictError(current)),
+                Err(GetSubsectionError::NotFoundError) => Err(UpdateSubsectionError::NotFoundError),
+                Err(GetSubsectionError::UnexpectedError(err)) => {
+                    Err(UpdateSubsectionError::UnexpectedError(err))
+                }
+            }
+        }
+        Err(db::subsections::UpdateSubsectionsError::UnexpectedError(err)) => {
+            Err(UpdateSubsectionError::UnexpectedError(err))
         }
     }
 }
 
 #[derive(Debug)]
 pub enum DeleteSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
-pub async fn delete_subsection(
-    pool: &sqlx::Pool<sqlx::MySql>,
-    id: u32,
-) -> Result<(), DeleteSubsectionError> {
-    db::subsections::delete_subsection(
-        pool,
-        db::subsections::GetSubsectionsForm {
+pub async fn delete_subsection(storage: &Storage, id: u32) -> Result<(), DeleteSubsectionError> {
+    let res = storage
+        .subsections
+        .delete_subsection(db::subsections::GetSubsectionsForm {
             id: Some(id),
             ..Default::default()
+        })
+        .await;
+    storage.content_changed();
+    res.map_err(
+        |db::subsections::DeleteSubsectionsError::UnexpectedError(err)| {
+            DeleteSubsectionError::UnexpectedError(err)
         },
     )
-    .await
-    .map_err(|_| DeleteSubsectionError::UnexpectedError)
 }
 
 #[derive(Debug)]
 pub enum MoveSubsectionError {
-    UnexpectedError,
+    UnexpectedError(Error),
     NotFoundError(Option<u32>, Option<u32>),
     CantSwapFromDifferentSections,
+    /// A subsection moved past its expected version, both are returned as they are now.
+    VersionConflictError(Vec<SubsectionReturn>),
 }
 
+/// Swap the positions of two subsections of a section, written only at their
+/// expected `versions`.
 pub async fn move_subsection(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     ids: [u32; 2],
+    versions: [u32; 2],
 ) -> Result<(), MoveSubsectionError> {
-    let res = db::subsections::swap_subsections(pool, ids).await;
+    let res = storage.subsections.swap_subsections(ids, versions).await;
+    storage.content_changed();
     match res {
         Ok(()) => Ok(()),
         Err(db::subsections::SwapSubsectionsError::NotFoundError(tuple)) => {
@@ -196,8 +389,27 @@ pub async fn move_subsection(
         Err(db::subsections::SwapSubsectionsError::CantSwapFromDifferentSections) => {
             Err(MoveSubsectionError::CantSwapFromDifferentSections)
         }
-        Err(db::subsections::SwapSubsectionsError::UnexpectedError) => {
-            Err(MoveSubsectionError::UnexpectedError)
+        Err(db::subsections::SwapSubsectionsError::VersionConflictError) => {
+            let (first, second) = (
+                get_subsection(storage, ids[0], Visibility::Everything).await,
+                get_subsection(storage, ids[1], Visibility::Everything).await,
+            );
+            match (first, second) {
+                (Ok(first), Ok(second)) => Err(MoveSubsectionError::VersionConflictError(vec![
+                    first, second,
+                ])),
+                (Err(GetSubsectionError::UnexpectedError(err)), _)
+                | (_, Err(GetSubsectionError::UnexpectedError(err))) => {
+                    Err(MoveSubsectionError::UnexpectedError(err))
+                }
+                (first, second) => Err(MoveSubsectionError::NotFoundError(
+                    first.err().map(|_| ids[0]),
+                    second.err().map(|_| ids[1]),
+                )),
+            }
+        }
+        Err(db::subsections::SwapSubsectionsError::UnexpectedError(err)) => {
+            Err(MoveSubsectionError::UnexpectedError(err))
         }
     }
 }
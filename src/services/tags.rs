@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use crate::db;
+use crate::error::Error;
+use crate::repository::Storage;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct TagReturn {
+    pub id: u32,
+    pub name: String,
+    pub note_count: u32,
+}
+
+impl From<db::tags::TagWithCountFromDb> for TagReturn {
+    fn from(value: db::tags::TagWithCountFromDb) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            note_count: value.note_count,
+        }
+    }
+}
+
+/// Parse the `tag_match` query value; `any` is the default.
+pub fn parse_tag_match(value: Option<&str>) -> Option<db::tags::TagMatch> {
+    match value {
+        None | Some("any") => Some(db::tags::TagMatch::Any),
+        Some("all") => Some(db::tags::TagMatch::All),
+        Some(_) => None,
+    }
+}
+
+/// Split a comma-separated list of tag names, dropping empty entries.
+pub fn split_tag_names(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Trim the tag names and drop the empty and duplicated ones.
+fn clean_tag_names(names: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim().to_string();
+        if !name.is_empty() && !cleaned.contains(&name) {
+            cleaned.push(name);
+        }
+    }
+    cleaned
+}
+
+#[derive(Debug)]
+pub enum GetTagsError {
+    UnexpectedError(Error),
+}
+
+pub async fn get_tags(storage: &Storage) -> Result<Vec<TagReturn>, GetTagsError> {
+    storage
+        .tags
+        .get_tags_with_counts()
+        .await
+        .map(|list| list.into_iter().map(TagReturn::from).collect())
+        .map_err(|db::tags::GetTagsError::UnexpectedError(err)| GetTagsError::UnexpectedError(err))
+}
+
+/// Tag names of the given notes, keyed by note id and sorted by name.
+pub async fn get_tag_names_by_note(
+    storage: &Storage,
+    note_ids: &[u32],
+) -> Result<HashMap<u32, Vec<String>>, GetTagsError> {
+    let note_tags = storage.tags.get_tags_of_notes(note_ids).await.map_err(
+        |db::tags::GetTagsError::UnexpectedError(err)| GetTagsError::UnexpectedError(err),
+    )?;
+    let mut by_note: HashMap<u32, Vec<String>> = HashMap::new();
+    for note_tag in note_tags {
+        by_note.entry(note_tag.note_id).or_default().push(note_tag.name);
+    }
+    Ok(by_note)
+}
+
+#[derive(Debug)]
+pub enum CreateTagError {
+    UnexpectedError(Error),
+    AlreadyExistsError,
+    EmptyNameError,
+}
+
+pub async fn create_tag(storage: &Storage, name: String) -> Result<u32, CreateTagError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(CreateTagError::EmptyNameError);
+    }
+    let res = storage.tags.create_tag(name).await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::tags::CreateTagError::AlreadyExistsError => CreateTagError::AlreadyExistsError,
+        db::tags::CreateTagError::UnexpectedError(err) => CreateTagError::UnexpectedError(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum RenameTagError {
+    UnexpectedError(Error),
+    NotFoundError,
+    AlreadyExistsError,
+    EmptyNameError,
+}
+
+pub async fn rename_tag(
+    storage: &Storage,
+    id: u32,
+    name: String,
+) -> Result<(), RenameTagError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(RenameTagError::EmptyNameError);
+    }
+    let res = storage.tags.rename_tag(id, name).await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::tags::RenameTagError::NotFoundError => RenameTagError::NotFoundError,
+        db::tags::RenameTagError::AlreadyExistsError => RenameTagError::AlreadyExistsError,
+        db::tags::RenameTagError::UnexpectedError(err) => RenameTagError::UnexpectedError(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum MergeTagsError {
+    UnexpectedError(Error),
+    NotFoundError(Option<u32>, Option<u32>),
+    SameTagError,
+}
+
+pub async fn merge_tags(
+    storage: &Storage,
+    source_id: u32,
+    target_id: u32,
+) -> Result<(), MergeTagsError> {
+    let res = storage.tags.merge_tags(source_id, target_id).await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::tags::MergeTagsError::NotFoundError(tuple) => {
+            MergeTagsError::NotFoundError(tuple.0, tuple.1)
+        }
+        db::tags::MergeTagsError::SameTagError => MergeTagsError::SameTagError,
+        db::tags::MergeTagsError::UnexpectedError(err) => MergeTagsError::UnexpectedError(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum DeleteTagError {
+    UnexpectedError(Error),
+}
+
+pub async fn delete_tag(storage: &Storage, id: u32) -> Result<(), DeleteTagError> {
+    let res = storage.tags.delete_tag(id).await;
+    storage.content_changed();
+    res.map_err(
+        |db::tags::DeleteTagsError::UnexpectedError(err)| DeleteTagError::UnexpectedError(err),
+    )
+}
+
+#[derive(Debug)]
+pub enum TagNoteError {
+    UnexpectedError(Error),
+    NoteNotFoundError,
+}
+
+/// Attach the tags named `names` to a note, creating the tags that do not exist yet.
+pub async fn tag_note(
+    storage: &Storage,
+    note_id: u32,
+    names: Vec<String>,
+) -> Result<(), TagNoteError> {
+    let note = storage
+        .notes
+        .get_note(db::lecture_notes::GetNotesForm {
+            id: Some(note_id),
+            ..Default::default()
+        })
+        .await;
+    match note {
+        Ok(_) => {}
+        Err(db::lecture_notes::GetNoteError::NotFoundError) => {
+            return Err(TagNoteError::NoteNotFoundError)
+        }
+        Err(db::lecture_notes::GetNoteError::UnexpectedError(err)) => {
+            return Err(TagNoteError::UnexpectedError(err))
+        }
+    }
+    let tag_ids = storage
+        .tags
+        .get_or_create_tags(&clean_tag_names(names))
+        .await
+        .map_err(|err| match err {
+            db::tags::CreateTagError::UnexpectedError(err) => TagNoteError::UnexpectedError(err),
+            db::tags::CreateTagError::AlreadyExistsError => {
+                TagNoteError::UnexpectedError(Error::internal("the tag was created meanwhile"))
+            }
+        })?;
+    let res = storage.tags.attach_tags(note_id, &tag_ids).await;
+    storage.content_changed();
+    res.map_err(|err| match err {
+        db::tags::AttachTagsError::NoteNotFoundError => TagNoteError::NoteNotFoundError,
+        db::tags::AttachTagsError::UnexpectedError(err) => TagNoteError::UnexpectedError(err),
+    })
+}
+
+pub async fn untag_note(
+    storage: &Storage,
+    note_id: u32,
+    tag_id: u32,
+) -> Result<(), DeleteTagError> {
+    let res = storage.tags.detach_tag(note_id, tag_id).await;
+    storage.content_changed();
+    res.map_err(
+        |db::tags::DeleteTagsError::UnexpectedError(err)| DeleteTagError::UnexpectedError(err),
+    )
+}
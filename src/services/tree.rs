@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::db;
+use crate::db::query::{Filter, OrderBy};
+use crate::error::Error;
+use crate::repository::Storage;
+use crate::services::lecture_notes::NoteReturn;
+use crate::services::nodes::{GetTreeError, TreeNode};
+use crate::services::publishing::{Status, Visibility};
+use crate::services::sections::SectionReturn;
+use crate::services::subsections::SubsectionReturn;
+
+/// Rows grouped by the row they hang from, each group sorted by position. Every
+/// view of the content tree is assembled from these groups.
+pub struct Children<T> {
+    groups: HashMap<Option<u32>, Vec<T>>,
+}
+
+impl<T> Children<T> {
+    pub fn new(
+        rows: impl IntoIterator<Item = T>,
+        parent: impl Fn(&T) -> Option<u32>,
+        position: impl Fn(&T) -> u32,
+    ) -> Self {
+        let mut groups: HashMap<Option<u32>, Vec<T>> = HashMap::new();
+        for row in rows {
+            groups.entry(parent(&row)).or_default().push(row);
+        }
+        for group in groups.values_mut() {
+            group.sort_by_key(&position);
+        }
+        Self { groups }
+    }
+
+    /// The rows hanging from `parent`, or the ones hanging from nothing for None.
+    pub fn take(&mut self, parent: Option<u32>) -> Vec<T> {
+        self.groups.remove(&parent).unwrap_or_default()
+    }
+}
+
+/// A subsection with its notes, sorted by position.
+pub struct SubsectionTree {
+    pub subsection: SubsectionReturn,
+    pub notes: Vec<NoteReturn>,
+    /// The nodes under the subsection, which only the generated pages show.
+    pub children: Vec<TreeNode>,
+}
+
+/// A section with its subsections and the notes directly under it, sorted by
+/// position.
+pub struct SectionTree {
+    pub section: SectionReturn,
+    pub subsections: Vec<SubsectionTree>,
+    pub notes: Vec<NoteReturn>,
+}
+
+impl SectionTree {
+    /// The latest `updated_at` of the section, its subsections and its notes.
+    pub fn last_updated(&self) -> i64 {
+        let subsections = self.subsections.iter().flat_map(|subsection| {
+            let notes = subsection.notes.iter().map(|note| note.updated_at);
+            notes.chain([subsection.subsection.updated_at])
+        });
+        let notes = self.notes.iter().map(|note| note.updated_at);
+        subsections
+            .chain(notes)
+            .fold(self.section.updated_at, i64::max)
+    }
+}
+
+impl From<TreeNode> for SectionTree {
+    /// The root node as a section, its children as subsections.
+    fn from(node: TreeNode) -> Self {
+        let section_id = node.id;
+        let subsections = node
+            .children
+            .into_iter()
+            .map(|child| SubsectionTree {
+                subsection: SubsectionReturn {
+                    id: child.id,
+                    title: child.title,
+                    position: child.position,
+                    section_id,
+                    created_at: child.created_at,
+                    updated_at: child.updated_at,
+                    version: child.version,
+                    status: child.status,
+                    publish_at: child.publish_at,
+                    slug: child.slug,
+                    previous_slugs: child.previous_slugs,
+                },
+                notes: child.notes,
+                children: child.children,
+            })
+            .collect();
+        Self {
+            section: SectionReturn {
+                id: node.id,
+                title: node.title,
+                position: node.position,
+                created_at: node.created_at,
+                updated_at: node.updated_at,
+                version: node.version,
+                status: node.status,
+                publish_at: node.publish_at,
+                slug: node.slug,
+                previous_slugs: node.previous_slugs,
+            },
+            subsections,
+            notes: node.notes,
+        }
+    }
+}
+
+fn assemble_nodes(
+    parent_id: Option<u32>,
+    nodes: &mut Children<db::nodes::NodeFromDb>,
+    notes: &mut Children<NoteReturn>,
+) -> Vec<TreeNode> {
+    nodes
+        .take(parent_id)
+        .into_iter()
+        .map(|node| {
+            let previous_slugs = node.previous_slug_list();
+            TreeNode {
+                id: node.id,
+                parent_id: node.parent_id,
+                children: assemble_nodes(Some(node.id), nodes, notes),
+                notes: notes.take(Some(node.id)),
+                kind: node.kind,
+                title: node.title,
+                position: node.position,
+                created_at: node.created_at,
+                updated_at: node.updated_at,
+                version: node.version,
+                status: Status::from_db(&node.status),
+                publish_at: node.publish_at,
+                slug: node.slug,
+                previous_slugs,
+            }
+        })
+        .collect()
+}
+
+/// The ids of the root node `root_id` and of its descendants.
+fn subtree_ids(nodes: &[db::nodes::NodeFromDb], root_id: u32) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for node in nodes {
+        if let Some(parent_id) = node.parent_id {
+            children.entry(parent_id).or_default().push(node.id);
+        }
+    }
+    let mut ids = vec![root_id];
+    let mut index = 0;
+    while let Some(id) = ids.get(index).copied() {
+        ids.extend(children.remove(&id).unwrap_or_default());
+        index += 1;
+    }
+    ids
+}
+
+/// Load the root nodes visible with `visibility`, or only the one of `root_id`,
+/// with their descendants and notes, everything sorted by position. Notes that
+/// are not attached to any node are left out. Every view of the content tree is
+/// built from this one.
+pub async fn get_node_trees(
+    storage: &Storage,
+    root_id: Option<u32>,
+    visibility: Visibility,
+) -> Result<Vec<TreeNode>, GetTreeError> {
+    let published_at = visibility.published_at();
+    let nodes = storage
+        .nodes
+        .get_nodes(db::nodes::GetNodesForm {
+            published_at,
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::nodes::GetNodesError::UnexpectedError(err)| {
+            GetTreeError::UnexpectedError(err)
+        })?;
+    let filter = match root_id {
+        Some(id) => Filter::is_in("node_id", subtree_ids(&nodes, id)),
+        None => Filter::all(),
+    };
+    let notes = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            published_at,
+            filter,
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+            GetTreeError::UnexpectedError(err)
+        })?;
+    let notes = crate::services::lecture_notes::with_details(
+        storage,
+        notes.into_iter().map(NoteReturn::from).collect(),
+    )
+    .await
+    .map_err(GetTreeError::UnexpectedError)?;
+
+    let mut nodes = Children::new(nodes, |node| node.parent_id, |node| node.position);
+    let mut notes = Children::new(
+        notes.into_iter().filter(|note| note.node_id.is_some()),
+        |note| note.node_id,
+        |note| note.position,
+    );
+    let mut trees = assemble_nodes(None, &mut nodes, &mut notes);
+    if let Some(id) = root_id {
+        trees.retain(|tree| tree.id == id);
+    }
+    Ok(trees)
+}
+
+/// The notes hanging from the node of `node_id`, sorted by position, read without
+/// the rest of the tree. The node itself is not checked.
+pub async fn get_node_notes(
+    storage: &Storage,
+    node_id: u32,
+    visibility: Visibility,
+) -> Result<Vec<NoteReturn>, GetTreeError> {
+    let notes = storage
+        .notes
+        .get_notes(db::lecture_notes::GetNotesForm {
+            node_id: Some(node_id),
+            published_at: visibility.published_at(),
+            order_by: vec![OrderBy::asc("position"), OrderBy::asc("id")],
+            ..Default::default()
+        })
+        .await
+        .map_err(|db::lecture_notes::GetNotesError::UnexpectedError(err)| {
+            GetTreeError::UnexpectedError(err)
+        })?;
+    crate::services::lecture_notes::with_details(
+        storage,
+        notes.into_iter().map(NoteReturn::from).collect(),
+    )
+    .await
+    .map_err(GetTreeError::UnexpectedError)
+}
+
+#[derive(Debug)]
+pub enum GetSectionTreesError {
+    /// The section asked for does not exist, or is hidden from the viewer.
+    NotFoundError,
+    UnexpectedError(Error),
+}
+
+/// Load the sections visible with `visibility`, or only the one of `section_id`,
+/// with their subsections and notes.
+pub async fn get_section_trees(
+    storage: &Storage,
+    section_id: Option<u32>,
+    visibility: Visibility,
+) -> Result<Vec<SectionTree>, GetSectionTreesError> {
+    let trees = get_node_trees(storage, section_id, visibility)
+        .await
+        .map_err(|GetTreeError::UnexpectedError(err)| GetSectionTreesError::UnexpectedError(err))?;
+    if section_id.is_some() && trees.is_empty() {
+        return Err(GetSectionTreesError::NotFoundError);
+    }
+    Ok(trees.into_iter().map(SectionTree::from).collect())
+}
+
+/// The section of `id` with its subsections and notes.
+pub async fn get_section_tree(
+    storage: &Storage,
+    id: u32,
+    visibility: Visibility,
+) -> Result<SectionTree, GetSectionTreesError> {
+    get_section_trees(storage, Some(id), visibility)
+        .await?
+        .pop()
+        .ok_or(GetSectionTreesError::NotFoundError)
+}
+
+/// The earliest `publish_at` after `now` in the trees, when a row scheduled for
+/// later comes out.
+pub fn next_publication(trees: &[SectionTree], now: i64) -> Option<i64> {
+    let mut times = Vec::new();
+    for tree in trees {
+        times.push(tree.section.publish_at);
+        times.extend(tree.notes.iter().map(|note| note.publish_at));
+        for subsection in &tree.subsections {
+            times.push(subsection.subsection.publish_at);
+            times.extend(subsection.notes.iter().map(|note| note.publish_at));
+        }
+    }
+    times.into_iter().flatten().filter(|at| *at > now).min()
+}
+
+/// How often `TreeCache::get` was answered from the cache.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+pub struct TreeCacheStats {
+    pub hits: u64,
+    /// The requests that loaded and rendered the trees.
+    pub misses: u64,
+}
+
+struct CachedTrees<T> {
+    /// The content generation of the storage the trees were read at: they are
+    /// stale once it moved on.
+    generation: u64,
+    public: Option<T>,
+    everything: Option<T>,
+    /// When a scheduled row comes out, leaving the public tree stale.
+    public_expires_at: Option<i64>,
+}
+
+/// The sections trees rendered for each visibility, kept until the content changes
+/// or a scheduled row comes out. `T` is the rendered form, cheap to clone.
+pub struct TreeCache<T> {
+    trees: Mutex<CachedTrees<T>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<T> Default for TreeCache<T> {
+    fn default() -> Self {
+        Self {
+            trees: Mutex::new(CachedTrees {
+                generation: 0,
+                public: None,
+                everything: None,
+                public_expires_at: None,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: Clone> TreeCache<T> {
+    fn lock(&self) -> MutexGuard<'_, CachedTrees<T>> {
+        self.trees.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn stats(&self) -> TreeCacheStats {
+        TreeCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn cached(&self, visibility: Visibility, generation: u64, now: i64) -> Option<T> {
+        let trees = self.lock();
+        if trees.generation != generation {
+            return None;
+        }
+        match visibility {
+            Visibility::Public if trees.public_expires_at.is_none_or(|at| now < at) => {
+                trees.public.clone()
+            }
+            Visibility::Public => None,
+            Visibility::Everything => trees.everything.clone(),
+        }
+    }
+
+    /// The trees seen with `visibility`, as rendered by `render`. A miss loads and
+    /// renders both visibilities, the public one expiring with the next scheduled
+    /// row, and keeps them unless the content changed in the meantime. Every write
+    /// reported to `storage.content_changed` drops them.
+    pub async fn get(
+        &self,
+        storage: &Storage,
+        visibility: Visibility,
+        render: impl Fn(Vec<SectionTree>) -> Result<T, Error>,
+    ) -> Result<T, GetSectionTreesError> {
+        let now = db::now();
+        let generation = storage.content_generation();
+        if let Some(rendered) = self.cached(visibility, generation, now) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(rendered);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let everything = get_section_trees(storage, None, Visibility::Everything).await?;
+        let public_expires_at = next_publication(&everything, now);
+        let everything = render(everything).map_err(GetSectionTreesError::UnexpectedError)?;
+        let public = get_section_trees(storage, None, Visibility::Public).await?;
+        let public = render(public).map_err(GetSectionTreesError::UnexpectedError)?;
+
+        let mut trees = self.lock();
+        if storage.content_generation() == generation {
+            trees.generation = generation;
+            trees.public = Some(public.clone());
+            trees.everything = Some(everything.clone());
+            trees.public_expires_at = public_expires_at;
+        }
+        Ok(match visibility {
+            Visibility::Public => public,
+            Visibility::Everything => everything,
+        })
+    }
+}
@@ -1,8 +1,13 @@
 use crate::db;
+use crate::db::query::Value;
+use crate::error::{Error, ErrorCode};
 use crate::pass_hashing::hash_password;
+use crate::repository::Storage;
+use crate::services::pagination::{Page, PageError, PageRequest, Sort, SortField, Sortable};
 use rand::RngCore;
 use serde::Serialize;
 use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
 
 pub struct LoginForm {
     pub username: String,
@@ -18,10 +23,10 @@ pub struct RegisterForm {
 pub struct GetUsersForm {
     pub id: Option<u32>,
     pub username: Option<String>,
-    pub limit: Option<u32>,
+    pub page: PageRequest,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct UserReturn {
     pub id: u32,
     pub username: String,
@@ -38,38 +43,78 @@ impl From<crate::db::users::UserFromDb> for UserReturn {
     }
 }
 
+impl Sortable for UserReturn {
+    const DEFAULT_SORT: Sort = Sort::asc(SortField::Id);
+
+    fn sort_column(field: SortField) -> Option<&'static str> {
+        match field {
+            SortField::Name => Some("username"),
+            // Ids grow with every new user, so they follow the creation order.
+            SortField::Id | SortField::CreatedAt => Some("id"),
+            SortField::Position => None,
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn sort_value(&self, field: SortField) -> Value {
+        match field {
+            SortField::Name => self.username.clone().into(),
+            SortField::Id | SortField::CreatedAt | SortField::Position => self.id.into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum GetUsersError {
-    UnexpectedError,
+    PageError(PageError),
+    UnexpectedError(Error),
 }
 
 pub async fn get_users(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: GetUsersForm,
-) -> Result<Vec<UserReturn>, GetUsersError> {
-    let users_req = db::users::get_users(
-        pool,
-        db::users::GetUsersForm {
-            id: form.id,
-            username: form.username,
-            password: None,
-            ..Default::default()
-        },
-    )
-    .await;
-
-    match users_req {
-        Ok(res) => Ok(res.into_iter().map(UserReturn::from).collect()),
-        Err(db::users::GetUsersError::UnexpectedError) => Err(GetUsersError::UnexpectedError),
-    }
+) -> Result<Page<UserReturn>, GetUsersError> {
+    let clauses = form
+        .page
+        .clauses::<UserReturn>()
+        .map_err(GetUsersError::PageError)?;
+    let filters = || db::users::GetUsersForm {
+        id: form.id,
+        username: form.username.clone(),
+        ..Default::default()
+    };
+    let users = storage
+        .users
+        .get_users(db::users::GetUsersForm {
+            filter: clauses.filter,
+            order_by: clauses.order_by,
+            limit: clauses.limit,
+            offset: clauses.offset,
+            ..filters()
+        })
+        .await
+        .map_err(|db::users::GetUsersError::UnexpectedError(err)| {
+            GetUsersError::UnexpectedError(err)
+        })?;
+    let total = match form.page.known_total(users.len()) {
+        Some(total) => total,
+        None => storage.users.count_users(filters()).await.map_err(
+            |db::users::GetUsersError::UnexpectedError(err)| GetUsersError::UnexpectedError(err),
+        )?,
+    };
+    let users = users.into_iter().map(UserReturn::from).collect();
+    Ok(form.page.page(users, total))
 }
 
 #[derive(Debug)]
 pub enum RegisterError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct AuthResponse {
     pub user: UserReturn,
     pub token: String,
@@ -94,44 +139,44 @@ fn generate_token() -> String {
 }
 
 pub async fn register(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     form: RegisterForm,
 ) -> Result<AuthResponse, RegisterError> {
     let username = form.username;
     let hashed = hash_password(form.password);
-    db::users::create_user(
-        pool,
-        db::users::CreateUserForm {
+    storage
+        .users
+        .create_user(db::users::CreateUserForm {
             username: username.clone(),
             password: hashed,
             is_admin: form.is_admin,
-        },
-    )
-    .await
-    .map_err(|_| RegisterError::UnexpectedError)?;
-
-    let user = db::users::get_user(
-        pool,
-        db::users::GetUsersForm {
+        })
+        .await
+        .map_err(RegisterError::UnexpectedError)?;
+
+    let user = storage
+        .users
+        .get_user(db::users::GetUsersForm {
             username: Some(username),
             ..Default::default()
-        },
-    )
-    .await
-    .map_err(|_| RegisterError::UnexpectedError)?;
+        })
+        .await
+        .map_err(|err| match err {
+            db::users::GetUserError::UnexpectedError(err) => RegisterError::UnexpectedError(err),
+            _ => RegisterError::UnexpectedError(Error::internal("the user was deleted meanwhile")),
+        })?;
 
     let token = generate_token();
     let expires_at = now_unix() + 60 * 60 * 24 * 7;
-    db::sessions::create_session(
-        pool,
-        db::sessions::CreateSessionForm {
+    storage
+        .sessions
+        .create_session(db::sessions::CreateSessionForm {
             user_id: user.id,
             token: token.clone(),
             expires_at,
-        },
-    )
-    .await
-    .map_err(|_| RegisterError::UnexpectedError)?;
+        })
+        .await
+        .map_err(RegisterError::UnexpectedError)?;
 
     Ok(AuthResponse {
         user: UserReturn::from(user),
@@ -144,28 +189,28 @@ pub async fn register(
 pub enum LoginError {
     NotFoundError,
     InvalidPassword,
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
-pub async fn login(
-    pool: &sqlx::Pool<sqlx::MySql>,
-    form: LoginForm,
-) -> Result<AuthResponse, LoginError> {
-    let user = db::users::get_user(
-        pool,
-        db::users::GetUsersForm {
+pub async fn login(storage: &Storage, form: LoginForm) -> Result<AuthResponse, LoginError> {
+    let user = storage
+        .users
+        .get_user(db::users::GetUsersForm {
             username: Some(form.username),
             ..Default::default()
-        },
-    )
-    .await;
+        })
+        .await;
     let user = match user {
         Ok(val) => val,
         Err(db::users::GetUserError::NoResults) => return Err(LoginError::NotFoundError),
         Err(db::users::GetUserError::NoInfoToGetFromProvided) => {
-            return Err(LoginError::UnexpectedError)
+            return Err(LoginError::UnexpectedError(Error::internal(
+                "no username to log in",
+            )))
+        }
+        Err(db::users::GetUserError::UnexpectedError(err)) => {
+            return Err(LoginError::UnexpectedError(err))
         }
-        Err(db::users::GetUserError::UnexpectedError) => return Err(LoginError::UnexpectedError),
     };
 
     let hashed = hash_password(form.password);
@@ -175,16 +220,15 @@ pub async fn login(
 
     let token = generate_token();
     let expires_at = now_unix() + 60 * 60 * 24 * 7;
-    db::sessions::create_session(
-        pool,
-        db::sessions::CreateSessionForm {
+    storage
+        .sessions
+        .create_session(db::sessions::CreateSessionForm {
             user_id: user.id,
             token: token.clone(),
             expires_at,
-        },
-    )
-    .await
-    .map_err(|_| LoginError::UnexpectedError)?;
+        })
+        .await
+        .map_err(LoginError::UnexpectedError)?;
 
     Ok(AuthResponse {
         user: UserReturn::from(user),
@@ -193,34 +237,53 @@ pub async fn login(
     })
 }
 
+/// An unknown token is refused like an expired one, while a failing storage is
+/// told apart.
+fn session_error(err: Error) -> Error {
+    match err.code {
+        ErrorCode::NotFound => Error::new(ErrorCode::Unauthorized, "no such session"),
+        _ => err,
+    }
+}
+
 #[derive(Debug)]
 pub enum AdminAuthError {
     NotAdmin,
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn authenticate_admin_by_token(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     token: String,
 ) -> Result<UserReturn, AdminAuthError> {
-    let session = db::sessions::get_session_by_token(pool, token)
+    let session = storage
+        .sessions
+        .get_session_by_token(token)
         .await
-        .map_err(|_| AdminAuthError::UnexpectedError)?;
+        .map_err(|err| AdminAuthError::UnexpectedError(session_error(err)))?;
 
     if session.expires_at <= now_unix() {
-        let _ = db::sessions::delete_session_by_token(pool, session.token).await;
-        return Err(AdminAuthError::UnexpectedError);
+        let _ = storage
+            .sessions
+            .delete_session_by_token(session.token)
+            .await;
+        return Err(AdminAuthError::UnexpectedError(Error::new(
+            ErrorCode::Unauthorized,
+            "the session has expired",
+        )));
     }
 
-    let user = db::users::get_user(
-        pool,
-        db::users::GetUsersForm {
+    let user = storage
+        .users
+        .get_user(db::users::GetUsersForm {
             id: Some(session.user_id),
             ..Default::default()
-        },
-    )
-    .await
-    .map_err(|_| AdminAuthError::UnexpectedError)?;
+        })
+        .await
+        .map_err(|err| match err {
+            db::users::GetUserError::UnexpectedError(err) => AdminAuthError::UnexpectedError(err),
+            _ => AdminAuthError::UnexpectedError(Error::internal("the session has no user")),
+        })?;
 
     if !user.is_admin {
         return Err(AdminAuthError::NotAdmin);
@@ -231,31 +294,41 @@ pub async fn authenticate_admin_by_token(
 
 #[derive(Debug)]
 pub enum UserAuthError {
-    UnexpectedError,
+    UnexpectedError(Error),
 }
 
 pub async fn authenticate_user_by_token(
-    pool: &sqlx::Pool<sqlx::MySql>,
+    storage: &Storage,
     token: String,
 ) -> Result<UserReturn, UserAuthError> {
-    let session = db::sessions::get_session_by_token(pool, token)
+    let session = storage
+        .sessions
+        .get_session_by_token(token)
         .await
-        .map_err(|_| UserAuthError::UnexpectedError)?;
+        .map_err(|err| UserAuthError::UnexpectedError(session_error(err)))?;
 
     if session.expires_at <= now_unix() {
-        let _ = db::sessions::delete_session_by_token(pool, session.token).await;
-        return Err(UserAuthError::UnexpectedError);
+        let _ = storage
+            .sessions
+            .delete_session_by_token(session.token)
+            .await;
+        return Err(UserAuthError::UnexpectedError(Error::new(
+            ErrorCode::Unauthorized,
+            "the session has expired",
+        )));
     }
 
-    let user = db::users::get_user(
-        pool,
-        db::users::GetUsersForm {
+    let user = storage
+        .users
+        .get_user(db::users::GetUsersForm {
             id: Some(session.user_id),
             ..Default::default()
-        },
-    )
-    .await
-    .map_err(|_| UserAuthError::UnexpectedError)?;
+        })
+        .await
+        .map_err(|err| match err {
+            db::users::GetUserError::UnexpectedError(err) => UserAuthError::UnexpectedError(err),
+            _ => UserAuthError::UnexpectedError(Error::internal("the session has no user")),
+        })?;
 
     Ok(UserReturn::from(user))
 }
@@ -0,0 +1,211 @@
+use crate::db::{
+    self,
+    attachments::{
+        create_attachment, delete_attachment, get_attachment, get_attachments, swap_attachments,
+        update_attachments, CreateAttachmentError, CreateAttachmentForm, GetAttachmentsForm,
+        SwapAttachmentsError, UpdateAttachmentForm, UpdateAttachmentsError,
+    },
+    lecture_notes::{create_note, delete_notes, CreateNoteForm, GetNotesForm},
+};
+
+async fn create_notes(pool: &sqlx::Pool<sqlx::MySql>, count: u32) {
+    for index in 1..=count {
+        let _ = create_note(
+            pool,
+            CreateNoteForm {
+                name: format!("Note {}", index),
+                description: format!("Description {}", index),
+                url: format!("http://note{}.com", index),
+                node_id: None,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+}
+
+fn attachment_form(note_id: u32, label: &str, mime_type: Option<&str>) -> CreateAttachmentForm {
+    CreateAttachmentForm {
+        note_id,
+        label: label.to_string(),
+        url: format!("http://files.com/{}", label.to_lowercase()),
+        mime_type: mime_type.map(|value| value.to_string()),
+        size: None,
+        file_id: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn create_and_get_attachments_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    create_notes(&pool, 2).await;
+
+    let pdf = create_attachment(&pool, attachment_form(1, "PDF", Some("application/pdf")))
+        .await
+        .unwrap();
+    let slides = create_attachment(&pool, attachment_form(1, "Slides", None))
+        .await
+        .unwrap();
+    let recording = create_attachment(
+        &pool,
+        CreateAttachmentForm {
+            size: Some(5_000_000_000),
+            ..attachment_form(2, "Recording", Some("video/mp4"))
+        },
+    )
+    .await
+    .unwrap();
+
+    // Attaching to a missing note fails.
+    let res = create_attachment(&pool, attachment_form(90, "Ghost", None)).await;
+    assert_eq!(res.unwrap_err(), CreateAttachmentError::NoteNotFoundError);
+
+    // Attachments are appended per note.
+    let note_1 = get_attachments(
+        &pool,
+        GetAttachmentsForm {
+            note_id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        note_1
+            .iter()
+            .map(|a| (a.id, a.position))
+            .collect::<Vec<_>>(),
+        vec![(pdf, 0), (slides, 1)]
+    );
+    assert_eq!(note_1[0].mime_type.as_deref(), Some("application/pdf"));
+    assert_eq!(note_1[1].mime_type, None);
+
+    let res = get_attachment(
+        &pool,
+        GetAttachmentsForm {
+            id: Some(recording),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.position, 0);
+    assert_eq!(res.size, Some(5_000_000_000));
+
+    let res = get_attachments(
+        &pool,
+        GetAttachmentsForm {
+            note_ids: Some(vec![1, 2]),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.len(), 3);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn update_move_and_delete_attachments_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    create_notes(&pool, 2).await;
+    let pdf = create_attachment(&pool, attachment_form(1, "PDF", None))
+        .await
+        .unwrap();
+    let slides = create_attachment(&pool, attachment_form(1, "Slides", None))
+        .await
+        .unwrap();
+    let other = create_attachment(&pool, attachment_form(2, "Code", None))
+        .await
+        .unwrap();
+
+    let by_id = |id: u32| GetAttachmentsForm {
+        id: Some(id),
+        ..Default::default()
+    };
+
+    let res = update_attachments(&pool, UpdateAttachmentForm::default(), by_id(pdf)).await;
+    assert_eq!(
+        res.unwrap_err(),
+        UpdateAttachmentsError::NothingToUpdateError
+    );
+    let res = update_attachments(
+        &pool,
+        UpdateAttachmentForm {
+            label: Some("Missing".to_string()),
+            ..Default::default()
+        },
+        by_id(90),
+    )
+    .await;
+    assert_eq!(res.unwrap_err(), UpdateAttachmentsError::NotFoundError);
+
+    update_attachments(
+        &pool,
+        UpdateAttachmentForm {
+            label: Some("Lecture PDF".to_string()),
+            mime_type: Some("application/pdf".to_string()),
+            size: Some(1024),
+            ..Default::default()
+        },
+        by_id(pdf),
+    )
+    .await
+    .unwrap();
+    let res = get_attachment(&pool, by_id(pdf)).await.unwrap();
+    assert_eq!(res.label, "Lecture PDF");
+    assert_eq!(res.mime_type.as_deref(), Some("application/pdf"));
+    assert_eq!(res.size, Some(1024));
+    assert_eq!(res.url, "http://files.com/pdf");
+
+    // Reordering only works inside one note.
+    swap_attachments(&pool, [pdf, slides]).await.unwrap();
+    assert_eq!(get_attachment(&pool, by_id(pdf)).await.unwrap().position, 1);
+    assert_eq!(
+        get_attachment(&pool, by_id(slides)).await.unwrap().position,
+        0
+    );
+    let res = swap_attachments(&pool, [pdf, other]).await;
+    assert_eq!(
+        res.unwrap_err(),
+        SwapAttachmentsError::CantSwapFromDifferentNotes
+    );
+    let res = swap_attachments(&pool, [pdf, 90]).await;
+    assert_eq!(
+        res.unwrap_err(),
+        SwapAttachmentsError::NotFoundError((None, Some(90)))
+    );
+
+    delete_attachment(&pool, by_id(slides)).await.unwrap();
+    assert!(get_attachment(&pool, by_id(slides)).await.is_err());
+
+    // Deleting a note removes its attachments.
+    let res = delete_notes(
+        &pool,
+        GetNotesForm {
+            id: Some(2),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    assert!(get_attachment(&pool, by_id(other)).await.is_err());
+    assert!(get_attachment(&pool, by_id(pdf)).await.is_ok());
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
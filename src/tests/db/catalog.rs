@@ -0,0 +1,208 @@
+use crate::db::{
+    self,
+    lecture_notes::{create_note, get_notes, CreateNoteForm, GetNotesForm},
+    nodes::{create_node, get_nodes, CreateNodeForm, GetNodesForm},
+};
+use crate::repository::mysql::MySqlRepository;
+use crate::repository::Storage;
+use crate::services::batch::{
+    run_batch, BatchAction, BatchEntity, BatchFields, BatchId, BatchOperation, BatchOperationError,
+    RunBatchError,
+};
+use crate::services::catalog::{export_catalog, import_catalog, ImportCatalogForm, ImportMode};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn export_and_import_catalog_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+    let storage = Storage::mysql(MySqlRepository::new(pool.clone()));
+
+    let section = create_node(
+        &pool,
+        CreateNodeForm {
+            kind: "section".to_string(),
+            title: "Algebra".to_string(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    for name in ["Groups", "Rings"] {
+        let res = create_note(
+            &pool,
+            CreateNoteForm {
+                name: name.to_string(),
+                url: format!("{name}.pdf"),
+                node_id: Some(section),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+
+    let mut catalog = export_catalog(&storage).await.unwrap();
+    assert_eq!(catalog.sections.len(), 1);
+    assert_eq!(catalog.sections[0].notes.len(), 2);
+
+    // Drop `rings`, put `fields` first and add a second section.
+    let mut notes = catalog.sections[0].notes.clone();
+    notes.truncate(1);
+    notes[0].position = 1;
+    let mut fields = notes[0].clone();
+    fields.name = "Fields".to_string();
+    fields.slug = None;
+    fields.position = 0;
+    notes.push(fields);
+    catalog.sections[0].notes = notes;
+    let mut analysis = catalog.sections[0].clone();
+    analysis.title = "Analysis".to_string();
+    analysis.slug = None;
+    analysis.notes.clear();
+    catalog.sections.push(analysis);
+
+    let form = |catalog, dry_run| ImportCatalogForm {
+        catalog,
+        mode: ImportMode::Replace,
+        dry_run,
+    };
+    let report = import_catalog(&storage, form(catalog.clone(), true))
+        .await
+        .unwrap();
+    assert_eq!(report.created.len(), 2);
+    assert_eq!(report.deleted.len(), 1);
+    // A dry run changes nothing.
+    let stored = get_notes(&pool, GetNotesForm::default()).await.unwrap();
+    assert_eq!(stored.len(), 2);
+
+    let report = import_catalog(&storage, form(catalog, false))
+        .await
+        .unwrap();
+    assert!(!report.dry_run);
+    let names = get_notes(
+        &pool,
+        GetNotesForm {
+            node_id: Some(section),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|note| (note.name, note.position))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![("Fields".to_string(), 0), ("Groups".to_string(), 1)]
+    );
+    let nodes = get_nodes(&pool, GetNodesForm::default()).await.unwrap();
+    assert_eq!(nodes.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn run_batch_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+    let storage = Storage::mysql(MySqlRepository::new(pool.clone()));
+
+    let section = |title: &str, reference: &str| BatchOperation {
+        entity: BatchEntity::Section,
+        action: BatchAction::Create {
+            reference: Some(reference.to_string()),
+        },
+        fields: BatchFields {
+            title: Some(title.to_string()),
+            ..Default::default()
+        },
+    };
+    let note = |name: &str, section: &str| BatchOperation {
+        entity: BatchEntity::Note,
+        action: BatchAction::Create { reference: None },
+        fields: BatchFields {
+            name: Some(name.to_string()),
+            url: Some(format!("https://example.com/{name}.pdf")),
+            section_id: Some(BatchId::Ref(section.to_string())),
+            ..Default::default()
+        },
+    };
+    let results = run_batch(
+        &storage,
+        &[
+            section("Algebra", "algebra"),
+            note("Groups", "algebra"),
+            note("Rings", "algebra"),
+        ],
+    )
+    .await
+    .unwrap();
+    let section_id = results[0].id;
+    let groups = results[1].id;
+    let rings = results[2].id;
+
+    // The second operation fails, so the first one is rolled back.
+    let rename = BatchOperation {
+        entity: BatchEntity::Note,
+        action: BatchAction::Update {
+            id: BatchId::Id(groups),
+            version: 1,
+        },
+        fields: BatchFields {
+            name: Some("Group theory".to_string()),
+            ..Default::default()
+        },
+    };
+    let delete_section = BatchOperation {
+        entity: BatchEntity::Section,
+        action: BatchAction::Delete {
+            id: BatchId::Id(section_id),
+            version: 1,
+        },
+        fields: BatchFields::default(),
+    };
+    let res = run_batch(&storage, &[rename.clone(), delete_section]).await;
+    assert!(matches!(
+        res,
+        Err(RunBatchError::OperationError(
+            1,
+            BatchOperationError::InUseError
+        ))
+    ));
+
+    let swap = BatchOperation {
+        entity: BatchEntity::Note,
+        action: BatchAction::Move {
+            ids: [BatchId::Id(groups), BatchId::Id(rings)],
+            // The rename is the first update of `groups`.
+            versions: [2, 1],
+        },
+        fields: BatchFields::default(),
+    };
+    let results = run_batch(&storage, &[rename, swap]).await.unwrap();
+    assert_eq!(results[1].second_id, Some(rings));
+    let names = get_notes(
+        &pool,
+        GetNotesForm {
+            node_id: Some(section_id),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|note| (note.name, note.position))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![("Rings".to_string(), 0), ("Group theory".to_string(), 1)]
+    );
+}
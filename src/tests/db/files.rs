@@ -0,0 +1,116 @@
+use crate::db::{
+    self,
+    attachments::{create_attachment, delete_attachment, CreateAttachmentForm, GetAttachmentsForm},
+    files::{create_file, delete_file, get_files, CreateFileForm, DeleteFileError, GetFilesForm},
+    lecture_notes::{create_note, CreateNoteForm},
+};
+
+fn file_form(sha256: &str, original_name: &str) -> CreateFileForm {
+    CreateFileForm {
+        sha256: sha256.repeat(64 / sha256.len()),
+        original_name: original_name.to_string(),
+        mime_type: "application/pdf".to_string(),
+        size: 2048,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn create_files_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    let (first, created) = create_file(&pool, file_form("a", "lecture.pdf"))
+        .await
+        .unwrap();
+    assert!(created);
+    let (second, created) = create_file(&pool, file_form("b", "slides.pdf"))
+        .await
+        .unwrap();
+    assert!(created);
+    assert_ne!(first, second);
+
+    // The same content is stored once, whatever its name.
+    let (again, created) = create_file(&pool, file_form("a", "copy.pdf"))
+        .await
+        .unwrap();
+    assert!(!created);
+    assert_eq!(again, first);
+
+    let files = get_files(&pool, GetFilesForm::default()).await.unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].original_name, "lecture.pdf");
+    assert_eq!(files[0].size, 2048);
+
+    let res = get_files(
+        &pool,
+        GetFilesForm {
+            sha256: Some("b".repeat(64)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(res[0].id, second);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn delete_attached_file_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    let _ = create_note(
+        &pool,
+        CreateNoteForm {
+            name: "Note".to_string(),
+            url: "http://note.com".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let (file_id, _) = create_file(&pool, file_form("c", "lecture.pdf"))
+        .await
+        .unwrap();
+    let attachment = create_attachment(
+        &pool,
+        CreateAttachmentForm {
+            note_id: 1,
+            label: "PDF".to_string(),
+            url: format!("/files/{}", file_id),
+            file_id: Some(file_id),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    // A file attached to a note is kept.
+    let res = delete_file(&pool, file_id).await;
+    assert_eq!(res.unwrap_err(), DeleteFileError::InUseError);
+
+    let _ = delete_attachment(
+        &pool,
+        GetAttachmentsForm {
+            id: Some(attachment),
+            ..Default::default()
+        },
+    )
+    .await;
+    let res = delete_file(&pool, file_id).await.unwrap();
+    assert_eq!(res.sha256, "c".repeat(64));
+    let res = delete_file(&pool, file_id).await;
+    assert_eq!(res.unwrap_err(), DeleteFileError::NotFoundError);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
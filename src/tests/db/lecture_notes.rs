@@ -2,8 +2,8 @@ use crate::db::{
     self,
     lecture_notes::{
         create_note, delete_note, delete_notes, get_max_note_position_in_subsection, get_note,
-        get_notes, swap_notes, update_notes, CreateNoteForm, SwapNotesError, UpdateNoteForm,
-        UpdateNotesError,
+        get_notes, swap_notes, update_notes, CreateNoteForm, GetNotesForm, NoteMetadata,
+        SwapNotesError, UpdateNoteForm, UpdateNotesError,
     },
     sections::CreateSectionForm,
     subsections::CreateSubsectionForm,
@@ -20,11 +20,13 @@ pub async fn create_note_test() {
     db::create_tables::drop_all_tables(&pool).await;
     db::create_tables::create_required_tables(&pool).await;
 
-    // Create a section and a subsection for note attachment.
+    // Create a section and a subsection for note attachment. Sections and subsections
+    // share the node id space, so the subsection gets id 2.
     let _ = db::sections::create_section(
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -33,6 +35,7 @@ pub async fn create_note_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -42,26 +45,26 @@ pub async fn create_note_test() {
     assert!(notes.is_ok());
     assert_eq!(notes.unwrap().len(), 0);
 
-    // For subsection 1, max note position should be None.
-    let max_pos = get_max_note_position_in_subsection(&pool, 1).await;
+    // For subsection 2, max note position should be None.
+    let max_pos = get_max_note_position_in_subsection(&pool, 2).await;
     assert!(max_pos.is_none());
 
-    // Create the first note in subsection 1.
+    // Create the first note in subsection 2.
     let res = create_note(
         &pool,
         CreateNoteForm {
             name: "Note 1".to_string(),
             description: "Description 1".to_string(),
             url: "http://note1.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
     assert!(res.is_ok());
 
-    // Now max position for subsection 1 should be 0.
-    let max_pos = get_max_note_position_in_subsection(&pool, 1).await;
+    // Now max position for subsection 2 should be 0.
+    let max_pos = get_max_note_position_in_subsection(&pool, 2).await;
     assert_eq!(max_pos, Some(0));
 
     // Create a second note in the same subsection.
@@ -71,15 +74,15 @@ pub async fn create_note_test() {
             name: "Note 2".to_string(),
             description: "Description 2".to_string(),
             url: "http://note2.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
     assert!(res.is_ok());
 
     // Now max position should be 1.
-    let max_pos = get_max_note_position_in_subsection(&pool, 1).await;
+    let max_pos = get_max_note_position_in_subsection(&pool, 2).await;
     assert_eq!(max_pos, Some(1));
 
     // Create a note without specifying a subsection (global note).
@@ -89,8 +92,8 @@ pub async fn create_note_test() {
             name: "Global Note".to_string(),
             description: "Global description".to_string(),
             url: "http://globalnote.com".to_string(),
-            section_id: None,
-            subsection_id: None,
+            node_id: None,
+            ..Default::default()
         },
     )
     .await;
@@ -135,6 +138,7 @@ pub async fn update_note_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -143,6 +147,7 @@ pub async fn update_note_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -152,8 +157,8 @@ pub async fn update_note_test() {
             name: "Note 1".to_string(),
             description: "Description 1".to_string(),
             url: "http://note1.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -163,8 +168,8 @@ pub async fn update_note_test() {
             name: "Note 2".to_string(),
             description: "Description 2".to_string(),
             url: "http://note2.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -176,9 +181,9 @@ pub async fn update_note_test() {
             name: Some("Updated Note".to_string()),
             description: None,
             url: None,
-            section_id: None,
-            subsection_id: None,
+            node_id: None,
             position: None,
+            ..Default::default()
         },
         crate::db::lecture_notes::GetNotesForm {
             id: Some(90),
@@ -195,9 +200,9 @@ pub async fn update_note_test() {
             name: None,
             description: None,
             url: None,
-            section_id: None,
-            subsection_id: None,
+            node_id: None,
             position: None,
+            ..Default::default()
         },
         crate::db::lecture_notes::GetNotesForm {
             id: Some(1),
@@ -214,9 +219,9 @@ pub async fn update_note_test() {
             name: Some("Updated Note 1".to_string()),
             description: Some("Updated description 1".to_string()),
             url: None,
-            section_id: None,
-            subsection_id: None,
+            node_id: None,
             position: None,
+            ..Default::default()
         },
         crate::db::lecture_notes::GetNotesForm {
             id: Some(1),
@@ -247,9 +252,9 @@ pub async fn update_note_test() {
             name: None,
             description: None,
             url: Some("http://updatedurl.com".to_string()),
-            section_id: None,
-            subsection_id: None,
+            node_id: None,
             position: None,
+            ..Default::default()
         },
         crate::db::lecture_notes::GetNotesForm {
             section_id: Some(1),
@@ -291,6 +296,7 @@ pub async fn swap_notes_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -299,6 +305,7 @@ pub async fn swap_notes_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -308,8 +315,8 @@ pub async fn swap_notes_test() {
             name: "Note 1".to_string(),
             description: "Description 1".to_string(),
             url: "http://note1.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -319,8 +326,8 @@ pub async fn swap_notes_test() {
             name: "Note 2".to_string(),
             description: "Description 2".to_string(),
             url: "http://note2.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -329,7 +336,7 @@ pub async fn swap_notes_test() {
     let notes = get_notes(
         &pool,
         crate::db::lecture_notes::GetNotesForm {
-            subsection_id: Some(1),
+            subsection_id: Some(2),
             ..Default::default()
         },
     )
@@ -341,14 +348,14 @@ pub async fn swap_notes_test() {
     assert_eq!(note2.position, 1);
 
     // Test error cases for swap: non-existent note ID.
-    let res = swap_notes(&pool, [1, 90]).await;
+    let res = swap_notes(&pool, [1, 90], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapNotesError::NotFoundError((None, Some(90)))
     );
 
-    let res = swap_notes(&pool, [90, 1]).await;
+    let res = swap_notes(&pool, [90, 1], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
@@ -360,6 +367,7 @@ pub async fn swap_notes_test() {
         &pool,
         CreateSectionForm {
             title: "Section 2".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -367,7 +375,8 @@ pub async fn swap_notes_test() {
         &pool,
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
-            section_id: 2,
+            section_id: 4,
+            ..Default::default()
         },
     )
     .await;
@@ -377,29 +386,31 @@ pub async fn swap_notes_test() {
             name: "Note 3".to_string(),
             description: "Description 3".to_string(),
             url: "http://note3.com".to_string(),
-            section_id: Some(2),
-            subsection_id: Some(2),
+            node_id: Some(5),
+            ..Default::default()
         },
     )
     .await;
 
-    // Attempt to swap note 1 (subsection 1) with note 3 (subsection 2).
-    let res = swap_notes(&pool, [1, 3]).await;
+    // Attempt to swap note 1 (subsection 2) with note 3 (subsection 5).
+    let res = swap_notes(&pool, [1, 3], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapNotesError::CantSwapFromDifferentSubsections
     );
 
-    // Now perform a valid swap within subsection 1.
-    let res = swap_notes(&pool, [1, 2]).await;
+    // Now perform a valid swap within subsection 2.
+    let res = swap_notes(&pool, [1, 2], [1, 2]).await;
+    assert_eq!(res, Err(SwapNotesError::VersionConflictError));
+    let res = swap_notes(&pool, [1, 2], [1, 1]).await;
     assert!(res.is_ok());
 
     // Verify that positions have been swapped.
     let notes = get_notes(
         &pool,
         crate::db::lecture_notes::GetNotesForm {
-            subsection_id: Some(1),
+            subsection_id: Some(2),
             ..Default::default()
         },
     )
@@ -429,6 +440,7 @@ pub async fn delete_notes_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -437,6 +449,7 @@ pub async fn delete_notes_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -446,8 +459,8 @@ pub async fn delete_notes_test() {
             name: "Note 1".to_string(),
             description: "Description 1".to_string(),
             url: "http://note1.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -457,8 +470,8 @@ pub async fn delete_notes_test() {
             name: "Note 2".to_string(),
             description: "Description 2".to_string(),
             url: "http://note2.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -496,6 +509,7 @@ pub async fn delete_one_note_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -504,6 +518,7 @@ pub async fn delete_one_note_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -513,8 +528,8 @@ pub async fn delete_one_note_test() {
             name: "Note 1".to_string(),
             description: "Description 1".to_string(),
             url: "http://note1.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -524,8 +539,8 @@ pub async fn delete_one_note_test() {
             name: "Note 2".to_string(),
             description: "Description 2".to_string(),
             url: "http://note2.com".to_string(),
-            section_id: Some(1),
-            subsection_id: Some(1),
+            node_id: Some(2),
+            ..Default::default()
         },
     )
     .await;
@@ -546,3 +561,138 @@ pub async fn delete_one_note_test() {
 
     db::create_tables::drop_all_tables(&pool).await;
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn note_metadata_test() {
+    let pool = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    // Setup fresh tables.
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    // A note with full metadata and one without any.
+    let res = create_note(
+        &pool,
+        CreateNoteForm {
+            name: "Exam 2024".to_string(),
+            description: "Final exam".to_string(),
+            url: "http://exam.com".to_string(),
+            node_id: None,
+            metadata: NoteMetadata {
+                kind: Some("exam".to_string()),
+                course_code: Some("MATH101".to_string()),
+                term: Some("2024 Fall".to_string()),
+                language: Some("fr".to_string()),
+                authors: vec!["Ada Lovelace".to_string(), "Alan Turing".to_string()],
+                license: Some("CC-BY-4.0".to_string()),
+            },
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = create_note(
+        &pool,
+        CreateNoteForm {
+            name: "Plain note".to_string(),
+            description: "".to_string(),
+            url: "http://plain.com".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+
+    let note = get_note(
+        &pool,
+        GetNotesForm {
+            id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(note.kind, Some("exam".to_string()));
+    assert_eq!(note.course_code, Some("MATH101".to_string()));
+    assert_eq!(note.language, Some("fr".to_string()));
+    assert_eq!(
+        note.author_list(),
+        vec!["Ada Lovelace".to_string(), "Alan Turing".to_string()]
+    );
+
+    let plain = get_note(
+        &pool,
+        GetNotesForm {
+            id: Some(2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(plain.kind, None);
+    assert!(plain.author_list().is_empty());
+
+    // Filter by kind and by a single author.
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            kind: Some("exam".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 1);
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            author: Some("Alan Turing".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].id, 1);
+
+    // Update the authors and the term of the plain note.
+    let res = update_notes(
+        &pool,
+        UpdateNoteForm {
+            term: Some(Some("2025 Spring".to_string())),
+            authors: Some(vec!["Alan Turing".to_string()]),
+            ..Default::default()
+        },
+        GetNotesForm {
+            id: Some(2),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            author: Some("Alan Turing".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            term: Some("2025 Spring".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 1);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
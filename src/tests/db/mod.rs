@@ -1,4 +1,11 @@
+mod attachments;
+mod catalog;
+mod files;
 mod lecture_notes;
+mod nodes;
+mod query;
 mod sections;
+mod slugs;
 mod subsections;
+mod tags;
 mod users;
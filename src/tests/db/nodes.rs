@@ -0,0 +1,338 @@
+use crate::db::{
+    self,
+    lecture_notes::{create_note, get_notes, CreateNoteForm, GetNotesForm},
+    nodes::{
+        create_node, delete_node, get_node, get_nodes, swap_nodes, update_nodes, CreateNodeError,
+        CreateNodeForm, GetNodesForm, SwapNodesError, UpdateNodeForm, UpdateNodesError,
+    },
+    sections::get_sections,
+    subsections::get_subsections,
+};
+
+fn node_form(parent_id: Option<u32>, kind: &str, title: &str) -> CreateNodeForm {
+    CreateNodeForm {
+        parent_id,
+        kind: kind.to_string(),
+        title: title.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn create_nested_nodes_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    // Build a course -> module -> lesson -> topic chain.
+    let course = create_node(&pool, node_form(None, "course", "Course")).await.unwrap();
+    let module = create_node(&pool, node_form(Some(course), "module", "Module"))
+        .await
+        .unwrap();
+    let lesson = create_node(&pool, node_form(Some(module), "lesson", "Lesson 1"))
+        .await
+        .unwrap();
+    let lesson_2 = create_node(&pool, node_form(Some(module), "lesson", "Lesson 2"))
+        .await
+        .unwrap();
+    let topic = create_node(&pool, node_form(Some(lesson), "topic", "Topic"))
+        .await
+        .unwrap();
+
+    // A parent that does not exist is rejected.
+    let res = create_node(&pool, node_form(Some(90), "topic", "Orphan")).await;
+    assert!(matches!(res, Err(CreateNodeError::ParentNotFoundError)));
+
+    // Children are numbered per parent.
+    let node = get_node(
+        &pool,
+        GetNodesForm {
+            id: Some(lesson_2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(node.parent_id, Some(module));
+    assert_eq!(node.position, 1);
+
+    let node = get_node(
+        &pool,
+        GetNodesForm {
+            id: Some(topic),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(node.kind, "topic".to_string());
+    assert_eq!(node.position, 0);
+
+    // Filtering by depth only returns the nodes of that level.
+    let lessons = get_nodes(
+        &pool,
+        GetNodesForm {
+            depth: Some(2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(lessons.len(), 2);
+
+    // The compatibility views only see the first two levels.
+    let sections = get_sections(&pool, Default::default()).await;
+    assert_eq!(sections.ok().map(|list| list.len()), Some(1));
+    let subsections = get_subsections(&pool, Default::default()).await.unwrap();
+    assert_eq!(subsections.len(), 1);
+    assert_eq!(subsections[0].section_id, course);
+
+    // Notes can hang from any depth.
+    let res = create_note(
+        &pool,
+        CreateNoteForm {
+            name: "Deep Note".to_string(),
+            description: "Description".to_string(),
+            url: "http://deep.com".to_string(),
+            node_id: Some(topic),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            node_id: Some(topic),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].section_id, Some(course));
+    assert_eq!(notes[0].subsection_id, Some(module));
+
+    // Nodes that still have children are protected from deletion.
+    let res = delete_node(
+        &pool,
+        GetNodesForm {
+            id: Some(module),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_err());
+
+    // Leaves can be deleted.
+    let res = delete_node(
+        &pool,
+        GetNodesForm {
+            id: Some(lesson_2),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let nodes = get_nodes(&pool, Default::default()).await.unwrap();
+    assert_eq!(nodes.len(), 4);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn move_nodes_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    let root = create_node(&pool, node_form(None, "course", "Root")).await.unwrap();
+    let child_a = create_node(&pool, node_form(Some(root), "module", "A"))
+        .await
+        .unwrap();
+    let child_b = create_node(&pool, node_form(Some(root), "module", "B"))
+        .await
+        .unwrap();
+    let grandchild = create_node(&pool, node_form(Some(child_a), "lesson", "A.1"))
+        .await
+        .unwrap();
+
+    // A node cannot become its own descendant.
+    let res = update_nodes(
+        &pool,
+        UpdateNodeForm {
+            title: None,
+            kind: None,
+            parent_id: Some(grandchild),
+            position: None,
+            version: None,
+            ..Default::default()
+        },
+        GetNodesForm {
+            id: Some(child_a),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(res.unwrap_err(), UpdateNodesError::CycleError);
+
+    // Nodes with different parents cannot be swapped.
+    let res = swap_nodes(&pool, [child_b, grandchild], [1, 1]).await;
+    assert_eq!(
+        res.unwrap_err(),
+        SwapNodesError::CantSwapFromDifferentParents
+    );
+
+    // Siblings are only swapped at their expected versions.
+    let res = swap_nodes(&pool, [child_a, child_b], [1, 2]).await;
+    assert_eq!(res.unwrap_err(), SwapNodesError::VersionConflictError);
+    let res = swap_nodes(&pool, [child_a, child_b], [1, 1]).await;
+    assert!(res.is_ok());
+    let children = get_nodes(
+        &pool,
+        GetNodesForm {
+            parent_id: Some(root),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let a = children.iter().find(|n| n.id == child_a).unwrap();
+    let b = children.iter().find(|n| n.id == child_b).unwrap();
+    assert_eq!(a.position, 1);
+    assert_eq!(b.position, 0);
+
+    // Re-parenting a node under another branch is allowed.
+    let res = update_nodes(
+        &pool,
+        UpdateNodeForm {
+            title: None,
+            kind: None,
+            parent_id: Some(child_b),
+            position: Some(0),
+            version: None,
+            ..Default::default()
+        },
+        GetNodesForm {
+            id: Some(grandchild),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let node = get_node(
+        &pool,
+        GetNodesForm {
+            id: Some(grandchild),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(node.parent_id, Some(child_b));
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn migrate_legacy_sections_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+    db::create_tables::drop_all_tables(&pool).await;
+
+    // The tables as they were before the nodes.
+    for query_str in [
+        "CREATE TABLE sections (id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY, \
+         title VARCHAR(255) NOT NULL, position INT UNSIGNED NOT NULL UNIQUE)",
+        "CREATE TABLE subsections (id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY, \
+         title VARCHAR(255) NOT NULL, position INT UNSIGNED NOT NULL, \
+         section_id INT UNSIGNED NOT NULL, FOREIGN KEY (section_id) REFERENCES sections(id), \
+         UNIQUE (position, section_id))",
+        "CREATE TABLE notes (id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY, name TEXT NOT NULL, \
+         description TEXT NOT NULL, url TEXT NOT NULL, position INT UNSIGNED, \
+         section_id INT UNSIGNED, subsection_id INT UNSIGNED, \
+         FOREIGN KEY (section_id) REFERENCES sections(id), \
+         FOREIGN KEY (subsection_id) REFERENCES subsections(id), \
+         UNIQUE (position, subsection_id))",
+        "INSERT INTO sections (id, title, position) VALUES (1, 'Algebra', 1), (2, 'Analysis', 0)",
+        "INSERT INTO subsections (id, title, position, section_id) VALUES (1, 'Rings', 0, 1)",
+        // The notes directly in a section could share their positions.
+        "INSERT INTO notes (name, description, url, position, section_id, subsection_id) VALUES \
+         ('Groups', '', 'https://example.com/groups.pdf', 0, 1, NULL), \
+         ('Fields', '', 'https://example.com/fields.pdf', 0, 1, NULL), \
+         ('Ideals', '', 'https://example.com/ideals.pdf', 0, 1, 1)",
+    ] {
+        sqlx::query(query_str).execute(&pool).await.unwrap();
+    }
+
+    let repository = crate::repository::mysql::MySqlRepository::new(pool.clone());
+    repository.create_required_tables().await.unwrap();
+    // Nothing is left to migrate on the next start.
+    repository.create_required_tables().await.unwrap();
+
+    // The sections keep their ids, the subsections follow the largest one.
+    let mut sections = get_sections(&pool, Default::default())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|section| (section.id, section.title, section.position))
+        .collect::<Vec<_>>();
+    sections.sort();
+    assert_eq!(
+        sections,
+        vec![(1, "Algebra".to_string(), 1), (2, "Analysis".to_string(), 0)]
+    );
+    let subsections = get_subsections(&pool, Default::default())
+        .await
+        .unwrap_or_default();
+    assert_eq!(subsections.len(), 1);
+    assert_eq!((subsections[0].id, subsections[0].section_id), (3, 1));
+
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            node_id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|note| (note.name, note.position))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        vec![("Groups".to_string(), 0), ("Fields".to_string(), 1)]
+    );
+    let notes = get_notes(
+        &pool,
+        GetNotesForm {
+            node_id: Some(3),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes[0].name, "Ideals");
+
+    let legacy_columns = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM INFORMATION_SCHEMA.COLUMNS WHERE TABLE_SCHEMA = DATABASE() \
+         AND TABLE_NAME = 'notes' AND COLUMN_NAME IN ('section_id', 'subsection_id')",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(legacy_columns, 0);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
@@ -0,0 +1,167 @@
+use crate::db::{
+    query::{escape_like, Clauses, Filter, OrderBy, Value},
+    OrAnd,
+};
+
+fn row(id: i64, name: Option<&str>) -> impl Fn(&str) -> Option<Value> + '_ {
+    move |column| match column {
+        "id" => Some(Value::Int(id)),
+        "name" => name.map(Value::from),
+        _ => None,
+    }
+}
+
+#[test]
+fn filter_to_sql_test() {
+    let mut params = Vec::new();
+    let filter = Filter::And(vec![
+        Filter::eq("id", 3_u32),
+        Filter::is_in("parent_id", [1_u32, 2]),
+        Filter::Or(vec![
+            Filter::contains("title", "50%_off"),
+            Filter::IsNull("title"),
+        ]),
+        Filter::Not(Box::new(Filter::Gte("position", Value::Int(10)))),
+    ]);
+
+    assert_eq!(
+        filter.to_sql(&mut params),
+        "(id = ? AND parent_id IN (?, ?) AND (title LIKE ? ESCAPE '!' OR title IS NULL) AND NOT (position >= ?))"
+    );
+    assert_eq!(
+        params,
+        vec![
+            Value::Int(3),
+            Value::Int(1),
+            Value::Int(2),
+            Value::Text("%50!%!_off%".to_string()),
+            Value::Int(10),
+        ]
+    );
+
+    let mut params = Vec::new();
+    assert_eq!(
+        Filter::is_in("id", Vec::<u32>::new()).to_sql(&mut params),
+        "1 = 0"
+    );
+    assert_eq!(Filter::all().to_sql(&mut params), "1 = 1");
+    assert_eq!(Filter::none().to_sql(&mut params), "1 = 0");
+    assert!(params.is_empty());
+}
+
+#[test]
+fn filter_combine_test() {
+    let filters = vec![Filter::eq("id", 1_u32), Filter::eq("name", "a")];
+
+    assert_eq!(
+        Filter::combine(filters.clone(), &OrAnd::And),
+        Filter::And(filters.clone())
+    );
+    assert_eq!(
+        Filter::combine(filters.clone(), &OrAnd::Or),
+        Filter::Or(filters)
+    );
+    assert!(Filter::combine(Vec::new(), &OrAnd::Or).is_all());
+    assert_eq!(
+        Filter::all().and(Filter::eq("id", 1_u32)),
+        Filter::eq("id", 1_u32)
+    );
+    assert_eq!(
+        Filter::eq("section_id", 1_u32).map_columns(&|column| match column {
+            "section_id" => "parent_id",
+            column => column,
+        }),
+        Filter::eq("parent_id", 1_u32)
+    );
+}
+
+#[test]
+fn clauses_to_sql_test() {
+    assert_eq!(Clauses::default().to_sql(), (String::new(), Vec::new()));
+
+    let clauses = Clauses {
+        filter: Filter::eq("name", "x' OR 1 = 1 --"),
+        order_by: vec![OrderBy::asc("position"), OrderBy::desc("id")],
+        limit: Some(20),
+        offset: Some(40),
+    };
+    let (sql, params) = clauses.to_sql();
+
+    // Values, limit and offset included, are bound and never written into the SQL.
+    assert_eq!(
+        sql,
+        " WHERE name = ? ORDER BY position ASC, id DESC LIMIT ? OFFSET ?"
+    );
+    assert_eq!(
+        params,
+        vec![
+            Value::Text("x' OR 1 = 1 --".to_string()),
+            Value::Int(20),
+            Value::Int(40),
+        ]
+    );
+
+    let (sql, params) = Clauses {
+        offset: Some(5),
+        ..Default::default()
+    }
+    .to_sql();
+    assert_eq!(sql, " LIMIT ? OFFSET ?");
+    assert_eq!(params, vec![Value::Int(i64::MAX), Value::Int(5)]);
+}
+
+#[test]
+fn escape_like_test() {
+    assert_eq!(escape_like("plain"), "plain");
+    assert_eq!(escape_like("100%"), "100!%");
+    assert_eq!(escape_like("a_b!c"), "a!_b!!c");
+}
+
+#[test]
+fn filter_evaluate_test() {
+    let named = row(1, Some("Linear Algebra"));
+    let unnamed = row(2, None);
+
+    assert!(Filter::eq("id", 1_u32).matches(&named));
+    assert!(Filter::is_in("id", [2_u32, 3]).matches(&unnamed));
+    assert!(Filter::contains("name", "ALGEBRA").matches(&named));
+    assert!(Filter::Like("name", "linear%".to_string()).matches(&named));
+    assert!(!Filter::Like("name", "%!%".to_string()).matches(&named));
+    assert!(Filter::Lt("id", Value::Int(2)).matches(&named));
+    assert!(Filter::IsNull("name").matches(&unnamed));
+
+    // A comparison with NULL is unknown, and so is its negation.
+    assert_eq!(Filter::eq("name", "x").evaluate(&unnamed), None);
+    assert!(!Filter::Not(Box::new(Filter::eq("name", "x"))).matches(&unnamed));
+    assert_eq!(
+        Filter::Or(vec![Filter::eq("name", "x"), Filter::eq("id", 2_u32)]).evaluate(&unnamed),
+        Some(true)
+    );
+    assert_eq!(
+        Filter::And(vec![Filter::eq("name", "x"), Filter::eq("id", 3_u32)]).evaluate(&unnamed),
+        Some(false)
+    );
+    assert!(!Filter::none().matches(&named));
+    assert!(Filter::all().matches(&named));
+}
+
+#[test]
+fn clauses_page_test() {
+    let rows = vec![(1, Some("b")), (2, None), (3, Some("a")), (4, Some("b"))];
+    let column = |row: &(i64, Option<&str>), column: &str| self::row(row.0, row.1)(column);
+
+    let clauses = Clauses {
+        order_by: vec![OrderBy::asc("name"), OrderBy::desc("id")],
+        limit: Some(2),
+        offset: Some(1),
+        ..Default::default()
+    };
+    let ids = clauses
+        .page(rows, column)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    // NULL first, then "a", then the two "b" by descending id.
+    assert_eq!(ids, vec![3, 4]);
+}
@@ -36,6 +36,7 @@ pub async fn create_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -59,7 +60,8 @@ pub async fn create_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
 
@@ -67,6 +69,7 @@ pub async fn create_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -90,7 +93,8 @@ pub async fn create_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -98,7 +102,8 @@ pub async fn create_section_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
     db::create_tables::drop_all_tables(&pool).await;
@@ -124,6 +129,7 @@ pub async fn get_max_position_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -136,6 +142,7 @@ pub async fn get_max_position_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -178,6 +185,7 @@ pub async fn update_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -201,7 +209,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
 
@@ -209,6 +218,7 @@ pub async fn update_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -232,7 +242,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -240,7 +251,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -250,6 +262,8 @@ pub async fn update_section_test() {
         &pool,
         UpdateSectionForm {
             title: Some("update_title_for_1".to_string()),
+            version: None,
+            ..Default::default()
         },
         GetSectionsForm {
             id: Some(90),
@@ -264,7 +278,11 @@ pub async fn update_section_test() {
     // all none
     let res = db::sections::update_sections(
         &pool,
-        UpdateSectionForm { title: None },
+        UpdateSectionForm {
+            title: None,
+            version: None,
+            ..Default::default()
+        },
         GetSectionsForm {
             id: None,
             title: None,
@@ -280,6 +298,8 @@ pub async fn update_section_test() {
         &pool,
         UpdateSectionForm {
             title: Some("update_title_for_1".to_string()),
+            version: None,
+            ..Default::default()
         },
         GetSectionsForm {
             id: Some(1),
@@ -311,7 +331,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "update_title_for_1".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -319,7 +340,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -329,6 +351,8 @@ pub async fn update_section_test() {
         &pool,
         UpdateSectionForm {
             title: Some("same_title?".to_string()),
+            version: None,
+            ..Default::default()
         },
         GetSectionsForm {
             id: None,
@@ -360,7 +384,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "same_title?".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -368,7 +393,8 @@ pub async fn update_section_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "same_title?".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -406,6 +432,7 @@ pub async fn limit_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -414,6 +441,7 @@ pub async fn limit_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -439,7 +467,8 @@ pub async fn limit_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -447,7 +476,8 @@ pub async fn limit_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -472,7 +502,8 @@ pub async fn limit_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -480,7 +511,8 @@ pub async fn limit_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -505,7 +537,8 @@ pub async fn limit_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     db::create_tables::drop_all_tables(&pool).await;
@@ -528,6 +561,7 @@ pub async fn swap_sections() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -536,6 +570,7 @@ pub async fn swap_sections() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -560,7 +595,8 @@ pub async fn swap_sections() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -568,26 +604,27 @@ pub async fn swap_sections() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
     //checking errors
-    let res = db::sections::swap_sections(&pool, [1, 14]).await;
+    let res = db::sections::swap_sections(&pool, [1, 14], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapSectionsError::NotFoundError((None, Some(14)))
     );
 
-    let res = db::sections::swap_sections(&pool, [14, 1]).await;
+    let res = db::sections::swap_sections(&pool, [14, 1], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapSectionsError::NotFoundError((Some(14), None))
     );
 
-    let res = db::sections::swap_sections(&pool, [14, 15]).await;
+    let res = db::sections::swap_sections(&pool, [14, 15], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
@@ -595,7 +632,10 @@ pub async fn swap_sections() {
     );
 
     //verify the sections' positions are really swapped
-    let res = db::sections::swap_sections(&pool, [1, 2]).await;
+    let versions = [sections_vec[0].version, sections_vec[1].version];
+    let res = db::sections::swap_sections(&pool, [1, 2], [versions[0] + 1, versions[1]]).await;
+    assert_eq!(res.unwrap_err(), SwapSectionsError::VersionConflictError);
+    let res = db::sections::swap_sections(&pool, [1, 2], versions).await;
     assert!(res.is_ok());
     let sections = db::sections::get_sections(
         &pool,
@@ -616,7 +656,8 @@ pub async fn swap_sections() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -624,7 +665,8 @@ pub async fn swap_sections() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[1].clone()
         }
     );
     db::create_tables::drop_all_tables(&pool).await;
@@ -647,6 +689,7 @@ pub async fn delete_all_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -655,6 +698,7 @@ pub async fn delete_all_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -679,7 +723,8 @@ pub async fn delete_all_sections_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -687,7 +732,8 @@ pub async fn delete_all_sections_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -730,6 +776,7 @@ pub async fn delete_one_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -738,6 +785,7 @@ pub async fn delete_one_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -762,7 +810,8 @@ pub async fn delete_one_sections_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -770,7 +819,8 @@ pub async fn delete_one_sections_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -820,6 +870,7 @@ pub async fn delete_concrete_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -828,6 +879,7 @@ pub async fn delete_concrete_sections_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -852,7 +904,8 @@ pub async fn delete_concrete_sections_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -860,7 +913,8 @@ pub async fn delete_concrete_sections_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -895,7 +949,8 @@ pub async fn delete_concrete_sections_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[0].clone()
         }
     );
     db::create_tables::drop_all_tables(&pool).await;
@@ -918,6 +973,7 @@ pub async fn delete_one_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "new_title_0".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -926,6 +982,7 @@ pub async fn delete_one_section_test() {
         &pool,
         db::sections::CreateSectionForm {
             title: "title haha".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -950,7 +1007,8 @@ pub async fn delete_one_section_test() {
         db::sections::SectionFromDb {
             id: 1,
             title: "new_title_0".to_string(),
-            position: 0
+            position: 0,
+            ..sections_vec[0].clone()
         }
     );
     assert_eq!(
@@ -958,7 +1016,8 @@ pub async fn delete_one_section_test() {
         db::sections::SectionFromDb {
             id: 2,
             title: "title haha".to_string(),
-            position: 1
+            position: 1,
+            ..sections_vec[1].clone()
         }
     );
 
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+use crate::db::slugs::{missing_slugs, slug_update, slugify_with, SlugRow, SlugStyle};
+
+#[test]
+fn transliterated_slugs_test() {
+    let slugify = |value| slugify_with(value, SlugStyle::Transliterate);
+    assert_eq!(slugify("Linear Algebra 2"), "linear-algebra-2");
+    assert_eq!(slugify("Лінійна алгебра"), "liniina-alhebra");
+    assert_eq!(
+        slugify("Юридична освіта, її зміст"),
+        "yurydychna-osvita-yii-zmist"
+    );
+    assert_eq!(slugify("Щільність ґрунту"), "shchilnist-gruntu");
+    assert_eq!(slugify("Théorème de l'Hôpital"), "theoreme-de-lhopital");
+    assert_eq!(slugify("Łódź Straße"), "lodz-strasse");
+    assert_eq!(slugify("Θεωρία Ομάδων"), "theoria-omadon");
+    assert_eq!(slugify("Ǎrvíztűrő Ștefan"), "arvizturo-stefan");
+    assert_eq!(slugify("Ὀδύσσεια"), "odysseia");
+    // Decomposed accents vanish too.
+    assert_eq!(slugify("Ecole\u{301}"), "ecole");
+    assert_eq!(slugify("数学"), "note");
+}
+
+#[test]
+fn unicode_slugs_test() {
+    let slugify = |value| slugify_with(value, SlugStyle::Unicode);
+    assert_eq!(slugify("Лінійна Алгебра"), "лінійна-алгебра");
+    assert_eq!(slugify("Théorème: 1"), "théorème-1");
+    assert_eq!(slugify("数学"), "数学");
+}
+
+#[test]
+fn slug_collisions_test() {
+    let rows = [("Алгебра", None), ("Algebra", None), ("Алгебра", Some(1))]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (title, parent_id))| SlugRow {
+            id: index as u32 + 1,
+            parent_id,
+            title: title.to_string(),
+            slug: String::new(),
+        })
+        .collect::<Vec<_>>();
+    let assigned = missing_slugs(&rows);
+    assert_eq!(
+        assigned,
+        vec![
+            (1, "alhebra".to_string()),
+            (2, "algebra".to_string()),
+            (3, "alhebra".to_string())
+        ]
+    );
+
+    let siblings = HashSet::from(["algebra".to_string()]);
+    let change = slug_update("alhebra", Vec::new(), None, &siblings);
+    assert!(matches!(change, Ok(None)));
+    let siblings = HashSet::from(["alhebra".to_string()]);
+    let change = slug_update("alhebra", Vec::new(), None, &siblings)
+        .ok()
+        .flatten()
+        .map(|change| (change.slug, change.previous_slugs));
+    assert_eq!(
+        change,
+        Some(("alhebra-2".to_string(), vec!["alhebra".to_string()]))
+    );
+}
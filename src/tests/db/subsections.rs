@@ -24,6 +24,7 @@ pub async fn create_subsection_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -50,6 +51,7 @@ pub async fn create_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -65,6 +67,7 @@ pub async fn create_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -91,19 +94,21 @@ pub async fn create_subsection_test() {
     assert_eq!(
         subs_vec[0],
         SubsectionFromDb {
-            id: 1,
+            id: 2,
             title: "Subsection A".to_string(),
             position: 0,
             section_id: 1,
+            ..subs_vec[0].clone()
         }
     );
     assert_eq!(
         subs_vec[1],
         SubsectionFromDb {
-            id: 2,
+            id: 3,
             title: "Subsection B".to_string(),
             position: 1,
             section_id: 1,
+            ..subs_vec[1].clone()
         }
     );
 
@@ -125,6 +130,7 @@ pub async fn update_subsection_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -133,6 +139,7 @@ pub async fn update_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -141,6 +148,7 @@ pub async fn update_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -152,6 +160,8 @@ pub async fn update_subsection_test() {
             title: Some("Updated A".to_string()),
             section_id: None,
             position: None,
+            version: None,
+            ..Default::default()
         },
         GetSubsectionsForm {
             id: Some(90),
@@ -168,9 +178,11 @@ pub async fn update_subsection_test() {
             title: None,
             section_id: None,
             position: None,
+            version: None,
+            ..Default::default()
         },
         GetSubsectionsForm {
-            id: Some(1),
+            id: Some(2),
             ..Default::default()
         },
     )
@@ -180,16 +192,18 @@ pub async fn update_subsection_test() {
         UpdateSubsectionsError::NothingToUpdateError
     );
 
-    // Update the title of subsection with id 1
+    // Update the title of subsection with id 2 (section 1 holds id 1)
     let res = update_subsections(
         &pool,
         UpdateSubsectionForm {
             title: Some("Updated A".to_string()),
             section_id: None,
             position: None,
+            version: None,
+            ..Default::default()
         },
         GetSubsectionsForm {
-            id: Some(1),
+            id: Some(2),
             ..Default::default()
         },
     )
@@ -200,7 +214,7 @@ pub async fn update_subsection_test() {
     let sub = get_subsection(
         &pool,
         GetSubsectionsForm {
-            id: Some(1),
+            id: Some(2),
             ..Default::default()
         },
     )
@@ -216,6 +230,8 @@ pub async fn update_subsection_test() {
             title: Some("Global Update".to_string()),
             section_id: None,
             position: None,
+            version: None,
+            ..Default::default()
         },
         GetSubsectionsForm {
             section_id: Some(1),
@@ -256,6 +272,7 @@ pub async fn swap_subsections_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -264,6 +281,7 @@ pub async fn swap_subsections_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -272,6 +290,7 @@ pub async fn swap_subsections_test() {
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -287,27 +306,27 @@ pub async fn swap_subsections_test() {
     .await;
     assert!(subs.is_ok());
     let subs_vec = subs.unwrap();
-    let sub_a = subs_vec.iter().find(|s| s.id == 1).unwrap();
-    let sub_b = subs_vec.iter().find(|s| s.id == 2).unwrap();
+    let sub_a = subs_vec.iter().find(|s| s.id == 2).unwrap();
+    let sub_b = subs_vec.iter().find(|s| s.id == 3).unwrap();
     assert_eq!(sub_a.position, 0);
     assert_eq!(sub_b.position, 1);
 
     // Test error cases for swap: non-existent id(s)
-    let res = swap_subsections(&pool, [1, 90]).await;
+    let res = swap_subsections(&pool, [2, 90], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapSubsectionsError::NotFoundError((None, Some(90)))
     );
 
-    let res = swap_subsections(&pool, [90, 1]).await;
+    let res = swap_subsections(&pool, [90, 2], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapSubsectionsError::NotFoundError((Some(90), None))
     );
 
-    let res = swap_subsections(&pool, [90, 91]).await;
+    let res = swap_subsections(&pool, [90, 91], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
@@ -319,6 +338,7 @@ pub async fn swap_subsections_test() {
         &pool,
         CreateSectionForm {
             title: "Section 2".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -326,21 +346,27 @@ pub async fn swap_subsections_test() {
         &pool,
         CreateSubsectionForm {
             title: "Subsection C".to_string(),
-            section_id: 2,
+            section_id: 4,
+            ..Default::default()
         },
     )
     .await;
 
-    // Attempt to swap subsection id 1 (section 1) with subsection id 3 (section 2)
-    let res = swap_subsections(&pool, [1, 3]).await;
+    // Attempt to swap subsection id 2 (section 1) with subsection id 5 (section 4)
+    let res = swap_subsections(&pool, [2, 5], [1, 1]).await;
     assert!(res.is_err());
     assert_eq!(
         res.unwrap_err(),
         SwapSubsectionsError::CantSwapFromDifferentSections
     );
 
-    // Now perform a valid swap within section 1
-    let res = swap_subsections(&pool, [1, 2]).await;
+    // Now perform a valid swap within section 1, at the current versions
+    let res = swap_subsections(&pool, [2, 3], [1, 2]).await;
+    assert_eq!(
+        res.unwrap_err(),
+        SwapSubsectionsError::VersionConflictError
+    );
+    let res = swap_subsections(&pool, [2, 3], [1, 1]).await;
     assert!(res.is_ok());
 
     // Verify that positions have been swapped:
@@ -354,8 +380,8 @@ pub async fn swap_subsections_test() {
     .await;
     assert!(subs.is_ok());
     let subs_vec = subs.unwrap();
-    let sub_a = subs_vec.iter().find(|s| s.id == 1).unwrap();
-    let sub_b = subs_vec.iter().find(|s| s.id == 2).unwrap();
+    let sub_a = subs_vec.iter().find(|s| s.id == 2).unwrap();
+    let sub_b = subs_vec.iter().find(|s| s.id == 3).unwrap();
     // Originally, sub_a.position was 0 and sub_b.position was 1.
     // After swapping, sub_a should have position 1 and sub_b position 0.
     assert_eq!(sub_a.position, 1);
@@ -379,6 +405,7 @@ pub async fn delete_subsections_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -387,6 +414,7 @@ pub async fn delete_subsections_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -395,6 +423,7 @@ pub async fn delete_subsections_test() {
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -431,6 +460,7 @@ pub async fn delete_one_subsection_test() {
         &pool,
         CreateSectionForm {
             title: "Section 1".to_string(),
+            ..Default::default()
         },
     )
     .await;
@@ -439,6 +469,7 @@ pub async fn delete_one_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection A".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
@@ -447,6 +478,7 @@ pub async fn delete_one_subsection_test() {
         CreateSubsectionForm {
             title: "Subsection B".to_string(),
             section_id: 1,
+            ..Default::default()
         },
     )
     .await;
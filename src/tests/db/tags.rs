@@ -0,0 +1,178 @@
+use crate::db::{
+    self,
+    lecture_notes::{create_note, delete_notes, get_notes, CreateNoteForm, GetNotesForm},
+    tags::{
+        attach_tags, create_tag, delete_tag, detach_tag, get_or_create_tags, get_tags,
+        get_tags_of_notes, get_tags_with_counts, merge_tags, rename_tag, AttachTagsError,
+        CreateTagError, GetTagsForm, MergeTagsError, RenameTagError, TagMatch,
+    },
+};
+
+async fn create_notes(pool: &sqlx::Pool<sqlx::MySql>, count: u32) {
+    for index in 1..=count {
+        let _ = create_note(
+            pool,
+            CreateNoteForm {
+                name: format!("Note {}", index),
+                description: format!("Description {}", index),
+                url: format!("http://note{}.com", index),
+                node_id: None,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn create_and_rename_tags_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    let algebra = create_tag(&pool, "algebra".to_string()).await.unwrap();
+    let analysis = create_tag(&pool, "analysis".to_string()).await.unwrap();
+
+    // Tag names are unique.
+    let res = create_tag(&pool, "algebra".to_string()).await;
+    assert_eq!(res.unwrap_err(), CreateTagError::AlreadyExistsError);
+
+    // Renaming onto an existing name is rejected, renaming a missing tag too.
+    let res = rename_tag(&pool, analysis, "algebra".to_string()).await;
+    assert_eq!(res.unwrap_err(), RenameTagError::AlreadyExistsError);
+    let res = rename_tag(&pool, 90, "geometry".to_string()).await;
+    assert_eq!(res.unwrap_err(), RenameTagError::NotFoundError);
+
+    let res = rename_tag(&pool, analysis, "calculus".to_string()).await;
+    assert!(res.is_ok());
+
+    // get_or_create_tags reuses existing tags and creates the missing ones.
+    let ids = get_or_create_tags(&pool, &["calculus".to_string(), "logic".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(ids[0], analysis);
+
+    let tags = get_tags(&pool, GetTagsForm::default()).await.unwrap();
+    let names = tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["algebra", "calculus", "logic"]);
+    assert_eq!(tags[0].id, algebra);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn filter_notes_by_tags_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    create_notes(&pool, 3).await;
+    let algebra = create_tag(&pool, "algebra".to_string()).await.unwrap();
+    let exam = create_tag(&pool, "exam".to_string()).await.unwrap();
+
+    // Tagging a missing note is rejected.
+    let res = attach_tags(&pool, 90, &[algebra]).await;
+    assert_eq!(res.unwrap_err(), AttachTagsError::NoteNotFoundError);
+
+    // Note 1: algebra + exam, note 2: algebra, note 3: no tags.
+    assert!(attach_tags(&pool, 1, &[algebra, exam]).await.is_ok());
+    assert!(attach_tags(&pool, 2, &[algebra]).await.is_ok());
+    // Attaching twice is harmless.
+    assert!(attach_tags(&pool, 2, &[algebra]).await.is_ok());
+
+    let tags = vec!["algebra".to_string(), "exam".to_string()];
+    let any = get_notes(
+        &pool,
+        GetNotesForm {
+            tags: tags.clone(),
+            tag_match: TagMatch::Any,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let mut any_ids = any.iter().map(|note| note.id).collect::<Vec<_>>();
+    any_ids.sort();
+    assert_eq!(any_ids, vec![1, 2]);
+
+    let all = get_notes(
+        &pool,
+        GetNotesForm {
+            tags,
+            tag_match: TagMatch::All,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].id, 1);
+
+    // Counts include unused tags.
+    let counts = get_tags_with_counts(&pool).await.unwrap();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[0].note_count, 2);
+    assert_eq!(counts[1].note_count, 1);
+
+    let note_tags = get_tags_of_notes(&pool, &[1, 2, 3]).await.unwrap();
+    assert_eq!(note_tags.len(), 3);
+
+    assert!(detach_tag(&pool, 1, exam).await.is_ok());
+    let counts = get_tags_with_counts(&pool).await.unwrap();
+    assert_eq!(counts[1].note_count, 0);
+
+    // Deleting notes detaches their tags.
+    assert!(delete_notes(&pool, Default::default()).await.is_ok());
+    let counts = get_tags_with_counts(&pool).await.unwrap();
+    assert_eq!(counts[0].note_count, 0);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+pub async fn merge_and_delete_tags_test() {
+    let pool: sqlx::Pool<sqlx::MySql> = match db::establish_connection_for_testing().await {
+        Ok(conn) => conn,
+        Err(_) => panic!("An error occurred"),
+    };
+
+    db::create_tables::drop_all_tables(&pool).await;
+    db::create_tables::create_required_tables(&pool).await;
+
+    create_notes(&pool, 2).await;
+    let maths = create_tag(&pool, "maths".to_string()).await.unwrap();
+    let math = create_tag(&pool, "math".to_string()).await.unwrap();
+    assert!(attach_tags(&pool, 1, &[maths, math]).await.is_ok());
+    assert!(attach_tags(&pool, 2, &[maths]).await.is_ok());
+
+    let res = merge_tags(&pool, maths, maths).await;
+    assert_eq!(res.unwrap_err(), MergeTagsError::SameTagError);
+    let res = merge_tags(&pool, maths, 90).await;
+    assert_eq!(
+        res.unwrap_err(),
+        MergeTagsError::NotFoundError((None, Some(90)))
+    );
+
+    // Merging keeps a single link for notes that had both tags.
+    assert!(merge_tags(&pool, maths, math).await.is_ok());
+    let counts = get_tags_with_counts(&pool).await.unwrap();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].id, math);
+    assert_eq!(counts[0].note_count, 2);
+
+    assert!(delete_tag(&pool, math).await.is_ok());
+    let note_tags = get_tags_of_notes(&pool, &[1, 2]).await.unwrap();
+    assert!(note_tags.is_empty());
+    let notes = get_notes(&pool, Default::default()).await.unwrap();
+    assert_eq!(notes.len(), 2);
+
+    db::create_tables::drop_all_tables(&pool).await;
+}
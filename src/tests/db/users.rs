@@ -20,6 +20,7 @@ pub async fn get_users_test() {
             username: None,
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -44,6 +45,7 @@ pub async fn get_users_test() {
             username: None,
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -69,6 +71,7 @@ pub async fn get_users_test() {
             username: None,
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -102,6 +105,7 @@ pub async fn get_users_test() {
             username: Some("dobb".to_string()),
             password: Some("pass2".to_string()),
             or_and: db::OrAnd::Or,
+            ..Default::default()
         },
     )
     .await;
@@ -136,6 +140,7 @@ pub async fn get_users_test() {
             username: Some("dobb".to_string()),
             password: Some("pass2".to_string()),
             or_and: db::OrAnd::And,
+            ..Default::default()
         },
     )
     .await;
@@ -152,6 +157,7 @@ pub async fn get_users_test() {
             username: Some("dobb".to_string()),
             password: Some("pass1".to_string()),
             or_and: db::OrAnd::And,
+            ..Default::default()
         },
     )
     .await;
@@ -193,6 +199,7 @@ pub async fn get_user_test() {
             username: Some("hey".to_string()),
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -215,6 +222,7 @@ pub async fn get_user_test() {
             username: Some("ivgap04".to_string()),
             password: None,
             or_and: Default::default(),
+            ..Default::default()
         },
     )
     .await;
@@ -0,0 +1,40 @@
+use crate::error::{Error, ErrorCode};
+
+#[test]
+fn database_error_codes_test() {
+    let err = Error::from(sqlx::Error::RowNotFound);
+    assert_eq!(err.code, ErrorCode::NotFound);
+    let err = Error::from(sqlx::Error::PoolTimedOut);
+    assert_eq!(err.code, ErrorCode::DatabaseUnavailable);
+    assert!(matches!(
+        err.database_error(),
+        Some(sqlx::Error::PoolTimedOut)
+    ));
+    let err = Error::from(sqlx::Error::ColumnNotFound("title".to_string()));
+    assert_eq!(err.code, ErrorCode::Internal);
+    assert_eq!(err.message, "unexpected database error");
+}
+
+#[test]
+fn error_context_test() {
+    // Clients only learn which operation failed, the logs also get the cause.
+    let err = Error::from(sqlx::Error::ColumnNotFound("title".to_string()))
+        .context("failed to fetch sections");
+    assert_eq!(err.message, "failed to fetch sections");
+    assert_eq!(
+        err.to_string(),
+        "failed to fetch sections: unexpected database error: \
+         no column found for name: title"
+    );
+
+    let err = Error::from(sqlx::Error::PoolTimedOut).context("failed to fetch sections");
+    assert_eq!(err.code, ErrorCode::DatabaseUnavailable);
+    assert_eq!(
+        err.message,
+        "failed to fetch sections: the database is unavailable"
+    );
+
+    let err = Error::bad_request("invalid body").with_detail("title", "cannot be empty");
+    assert_eq!(err.details.len(), 1);
+    assert_eq!(err.details[0].field, "title");
+}
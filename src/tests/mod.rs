@@ -1,6 +1,10 @@
 use super::pass_hashing::hash_password;
 mod common;
 mod db;
+mod error;
+mod repository;
+mod routes;
+mod services;
 
 #[test]
 fn test_hashing() {
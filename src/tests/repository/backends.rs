@@ -0,0 +1,161 @@
+use crate::db::{lecture_notes, nodes, sections, subsections, tags};
+use crate::repository::Storage;
+
+use super::sqlite::sqlite_storage;
+
+/// The storages whose rules must agree: every backend but MySQL, which needs a
+/// server.
+async fn storages() -> Vec<Storage> {
+    vec![Storage::memory(), sqlite_storage().await]
+}
+
+/// Sections 1 and 2, subsections 3 and 4 in section 1 and 5 in section 2, notes
+/// 1 and 2 in subsection 3 and note 3 in subsection 4.
+async fn fill(storage: &Storage) {
+    for title in ["first", "second"] {
+        let res = storage
+            .sections
+            .create_section(sections::CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+    for (title, section_id) in [("a", 1), ("b", 1), ("c", 2)] {
+        let res = storage
+            .subsections
+            .create_subsection(subsections::CreateSubsectionForm {
+                title: title.to_string(),
+                section_id,
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+    for (name, node_id) in [("x", 3), ("y", 3), ("z", 4)] {
+        let res = storage
+            .notes
+            .create_note(lecture_notes::CreateNoteForm {
+                name: name.to_string(),
+                url: format!("{}.pdf", name),
+                node_id: Some(node_id),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+}
+
+#[tokio::test]
+pub async fn swap_rules_test() {
+    for storage in storages().await {
+        fill(&storage).await;
+        let backend = storage.backend().name();
+
+        let res = storage.sections.swap_sections([1, 9], [1, 1]).await;
+        let expected = sections::SwapSectionsError::NotFoundError((None, Some(9)));
+        assert_eq!(res, Err(expected), "{backend}");
+        // A subsection is no section.
+        let res = storage.sections.swap_sections([3, 1], [1, 1]).await;
+        let expected = sections::SwapSectionsError::NotFoundError((Some(3), None));
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.sections.swap_sections([1, 2], [1, 2]).await;
+        let expected = sections::SwapSectionsError::VersionConflictError;
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.sections.swap_sections([1, 2], [1, 1]).await;
+        assert_eq!(res, Ok(()), "{backend}");
+
+        let res = storage.subsections.swap_subsections([3, 5], [1, 1]).await;
+        let expected = subsections::SwapSubsectionsError::CantSwapFromDifferentSections;
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.subsections.swap_subsections([3, 4], [2, 1]).await;
+        let expected = subsections::SwapSubsectionsError::VersionConflictError;
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.subsections.swap_subsections([3, 4], [1, 1]).await;
+        assert_eq!(res, Ok(()), "{backend}");
+
+        let res = storage.nodes.swap_nodes([8, 9], [1, 1]).await;
+        let expected = nodes::SwapNodesError::NotFoundError((Some(8), Some(9)));
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.nodes.swap_nodes([1, 3], [2, 2]).await;
+        let expected = nodes::SwapNodesError::CantSwapFromDifferentParents;
+        assert_eq!(res, Err(expected), "{backend}");
+        // The first swap moved both sections once.
+        let res = storage.nodes.swap_nodes([1, 2], [1, 1]).await;
+        assert_eq!(
+            res,
+            Err(nodes::SwapNodesError::VersionConflictError),
+            "{backend}"
+        );
+        let res = storage.nodes.swap_nodes([1, 2], [2, 2]).await;
+        assert_eq!(res, Ok(()), "{backend}");
+
+        let res = storage.notes.swap_notes([1, 9], [1, 1]).await;
+        let expected = lecture_notes::SwapNotesError::NotFoundError((None, Some(9)));
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.notes.swap_notes([1, 3], [1, 1]).await;
+        let expected = lecture_notes::SwapNotesError::CantSwapFromDifferentSubsections;
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.notes.swap_notes([1, 2], [1, 2]).await;
+        let expected = lecture_notes::SwapNotesError::VersionConflictError;
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.notes.swap_notes([1, 2], [1, 1]).await;
+        assert_eq!(res, Ok(()), "{backend}");
+        let notes = storage
+            .notes
+            .get_notes(lecture_notes::GetNotesForm {
+                node_id: Some(3),
+                ..Default::default()
+            })
+            .await
+            .unwrap_or_default();
+        let moved = notes
+            .into_iter()
+            .map(|note| (note.id, note.position, note.version))
+            .collect::<Vec<_>>();
+        assert_eq!(moved, vec![(1, 1, 2), (2, 0, 2)], "{backend}");
+    }
+}
+
+#[tokio::test]
+pub async fn merge_tags_rules_test() {
+    for storage in storages().await {
+        fill(&storage).await;
+        let backend = storage.backend().name();
+        let ids = storage
+            .tags
+            .get_or_create_tags(&["rust".to_string(), "sql".to_string()])
+            .await;
+        assert_eq!(ids, Ok(vec![1, 2]), "{backend}");
+        assert_eq!(
+            storage.tags.attach_tags(1, &[1, 2]).await,
+            Ok(()),
+            "{backend}"
+        );
+        assert_eq!(storage.tags.attach_tags(2, &[2]).await, Ok(()), "{backend}");
+
+        let res = storage.tags.merge_tags(1, 1).await;
+        assert_eq!(res, Err(tags::MergeTagsError::SameTagError), "{backend}");
+        let res = storage.tags.merge_tags(9, 1).await;
+        let expected = tags::MergeTagsError::NotFoundError((Some(9), None));
+        assert_eq!(res, Err(expected), "{backend}");
+        let res = storage.tags.merge_tags(8, 9).await;
+        let expected = tags::MergeTagsError::NotFoundError((Some(8), Some(9)));
+        assert_eq!(res, Err(expected), "{backend}");
+
+        assert_eq!(storage.tags.merge_tags(2, 1).await, Ok(()), "{backend}");
+        let counts = storage
+            .tags
+            .get_tags_with_counts()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tag| (tag.name, tag.note_count))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![("rust".to_string(), 2)], "{backend}");
+        let res = storage.tags.merge_tags(2, 1).await;
+        let expected = tags::MergeTagsError::NotFoundError((Some(2), None));
+        assert_eq!(res, Err(expected), "{backend}");
+    }
+}
@@ -0,0 +1,871 @@
+use crate::db::catalog::{
+    ApplyCatalogChangesError, CatalogChange, ImportedAttachment, ImportedNode, ImportedNote,
+    NodeRef, NoteRef,
+};
+use crate::db::{
+    attachments, files, lecture_notes, nodes, sections, sessions, subsections, tags, users,
+};
+use crate::error::ErrorCode;
+use crate::repository::{self, Backend, Storage};
+
+pub(super) async fn sqlite_storage() -> Storage {
+    match repository::connect("sqlite::memory:").await {
+        Ok(storage) => storage,
+        Err(err) => panic!("failed to open the SQLite storage: {:?}", err),
+    }
+}
+
+#[tokio::test]
+pub async fn sqlite_users_and_sessions_test() {
+    let storage = sqlite_storage().await;
+    assert_eq!(storage.backend(), Backend::Sqlite);
+
+    let res = storage
+        .users
+        .create_user(users::CreateUserForm {
+            username: "admin".to_string(),
+            password: "hash".to_string(),
+            is_admin: true,
+        })
+        .await;
+    assert!(res.is_ok());
+
+    let user = storage
+        .users
+        .get_user(users::GetUsersForm {
+            username: Some("admin".to_string()),
+            ..Default::default()
+        })
+        .await;
+    let user = match user {
+        Ok(user) => user,
+        Err(_) => panic!("the user was not found"),
+    };
+    assert_eq!(
+        user,
+        users::UserFromDb {
+            id: 1,
+            username: "admin".to_string(),
+            password: "hash".to_string(),
+            is_admin: true,
+        }
+    );
+
+    let res = storage
+        .sessions
+        .create_session(sessions::CreateSessionForm {
+            user_id: user.id,
+            token: "token".to_string(),
+            expires_at: 42,
+        })
+        .await;
+    assert!(res.is_ok());
+    let session = storage
+        .sessions
+        .get_session_by_token("token".to_string())
+        .await;
+    assert_eq!(
+        session,
+        Ok(sessions::SessionFromDb {
+            id: 1,
+            user_id: 1,
+            token: "token".to_string(),
+            expires_at: 42,
+        })
+    );
+    // The unique token is reported as such, not as a failing database.
+    let res = storage
+        .sessions
+        .create_session(sessions::CreateSessionForm {
+            user_id: user.id,
+            token: "token".to_string(),
+            expires_at: 43,
+        })
+        .await;
+    assert_eq!(res.map_err(|err| err.code), Err(ErrorCode::DuplicateEntry));
+
+    let res = storage.sessions.delete_sessions_by_user(user.id).await;
+    assert!(res.is_ok());
+    let session = storage
+        .sessions
+        .get_session_by_token("token".to_string())
+        .await;
+    assert_eq!(session.map_err(|err| err.code), Err(ErrorCode::NotFound));
+}
+
+#[tokio::test]
+pub async fn sqlite_sections_and_subsections_test() {
+    let storage = sqlite_storage().await;
+
+    for title in ["first", "second"] {
+        let res = storage
+            .sections
+            .create_section(sections::CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+    let res = storage
+        .subsections
+        .create_subsection(subsections::CreateSubsectionForm {
+            title: "child".to_string(),
+            section_id: 1,
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+    // Subsections can only hang from sections.
+    let res = storage
+        .subsections
+        .create_subsection(subsections::CreateSubsectionForm {
+            title: "grandchild".to_string(),
+            section_id: 3,
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_err());
+
+    let sections = storage.sections.get_sections(Default::default()).await;
+    let titles = sections
+        .unwrap_or_default()
+        .into_iter()
+        .map(|section| (section.id, section.title, section.position))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        titles,
+        vec![(1, "first".to_string(), 0), (2, "second".to_string(), 1)]
+    );
+
+    // A stale version leaves both sections where they are.
+    let res = storage.sections.swap_sections([1, 2], [1, 2]).await;
+    assert_eq!(res, Err(sections::SwapSectionsError::VersionConflictError));
+    let res = storage.sections.swap_sections([1, 2], [1, 1]).await;
+    assert_eq!(res, Ok(()));
+    let first = storage
+        .sections
+        .get_section(sections::GetSectionsForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(first.map(|section| section.position).ok(), Some(1));
+    let res = storage.sections.swap_sections([1, 9], [2, 1]).await;
+    assert_eq!(
+        res,
+        Err(sections::SwapSectionsError::NotFoundError((None, Some(9))))
+    );
+
+    let res = storage
+        .subsections
+        .update_subsections(
+            subsections::UpdateSubsectionForm {
+                title: None,
+                section_id: Some(2),
+                position: None,
+                version: None,
+                ..Default::default()
+            },
+            subsections::GetSubsectionsForm {
+                id: Some(3),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert_eq!(res, Ok(()));
+    let subsection = storage
+        .subsections
+        .get_subsection(subsections::GetSubsectionsForm {
+            id: Some(3),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(
+        subsection.map(|subsection| subsection.section_id).ok(),
+        Some(2)
+    );
+
+    // A section that still has subsections is protected by the foreign key.
+    let res = storage
+        .sections
+        .delete_section(sections::GetSectionsForm {
+            id: Some(2),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+pub async fn sqlite_notes_test() {
+    let storage = sqlite_storage().await;
+
+    let _ = storage
+        .sections
+        .create_section(sections::CreateSectionForm {
+            title: "section".to_string(),
+            ..Default::default()
+        })
+        .await;
+    let _ = storage
+        .subsections
+        .create_subsection(subsections::CreateSubsectionForm {
+            title: "subsection".to_string(),
+            section_id: 1,
+            ..Default::default()
+        })
+        .await;
+    for (name, authors) in [("a", vec!["Ada 100%"]), ("b", vec!["Ada", "Alan"])] {
+        let res = storage
+            .notes
+            .create_note(lecture_notes::CreateNoteForm {
+                name: name.to_string(),
+                url: format!("{}.pdf", name),
+                node_id: Some(2),
+                metadata: lecture_notes::NoteMetadata {
+                    authors: authors.into_iter().map(str::to_string).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+
+    let notes = storage
+        .notes
+        .get_notes(lecture_notes::GetNotesForm {
+            section_id: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap_or_default();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0].subsection_id, Some(2));
+    assert_eq!(notes[1].position, 1);
+    assert!(notes[0].created_at > 0);
+    assert_eq!(notes[0].created_at, notes[0].updated_at);
+
+    let notes = storage
+        .notes
+        .get_notes(lecture_notes::GetNotesForm {
+            author: Some("Ada".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_or_default();
+    assert_eq!(
+        notes.into_iter().map(|note| note.name).collect::<Vec<_>>(),
+        vec!["b".to_string()]
+    );
+
+    let res = storage.notes.swap_notes([1, 2], [1, 1]).await;
+    assert_eq!(res, Ok(()));
+    // The versions moved with the swap, so a second one at them is rolled back.
+    let res = storage.notes.swap_notes([1, 2], [2, 1]).await;
+    assert_eq!(
+        res,
+        Err(lecture_notes::SwapNotesError::VersionConflictError)
+    );
+    let note = storage
+        .notes
+        .get_note(lecture_notes::GetNotesForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(note.map(|note| note.position).ok(), Some(1));
+
+    let res = storage
+        .notes
+        .update_notes(
+            lecture_notes::UpdateNoteForm {
+                name: Some("renamed".to_string()),
+                authors: Some(vec!["Grace".to_string()]),
+                ..Default::default()
+            },
+            lecture_notes::GetNotesForm {
+                id: Some(2),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert_eq!(res, Ok(()));
+    let note = storage
+        .notes
+        .get_note(lecture_notes::GetNotesForm {
+            id: Some(2),
+            ..Default::default()
+        })
+        .await;
+    let note = match note {
+        Ok(note) => note,
+        Err(_) => panic!("the note was not found"),
+    };
+    assert_eq!(note.name, "renamed");
+    assert_eq!(note.author_list(), vec!["Grace".to_string()]);
+    // Moved once by the swap and once by the update.
+    assert_eq!(note.version, 3);
+    let res = storage
+        .notes
+        .update_notes(
+            lecture_notes::UpdateNoteForm {
+                name: Some("stale".to_string()),
+                version: Some(2),
+                ..Default::default()
+            },
+            lecture_notes::GetNotesForm {
+                id: Some(2),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert_eq!(
+        res,
+        Err(lecture_notes::UpdateNotesError::VersionConflictError)
+    );
+
+    let res = storage
+        .notes
+        .delete_note(lecture_notes::GetNotesForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+    let notes = storage
+        .notes
+        .get_notes(Default::default())
+        .await
+        .unwrap_or_default();
+    assert_eq!(notes.len(), 1);
+}
+
+#[tokio::test]
+pub async fn sqlite_paging_test() {
+    use crate::services::pagination::{Cursor, PageRequest, Sort, SortField};
+    use crate::services::sections::{get_sections, GetSectionsForm};
+
+    let storage = sqlite_storage().await;
+    for title in ["b", "c", "a", "c"] {
+        let _ = storage
+            .sections
+            .create_section(sections::CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .await;
+    }
+    let count = storage
+        .sections
+        .count_sections(sections::GetSectionsForm {
+            title: Some("c".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert!(matches!(count, Ok(2)));
+
+    // Walk the pages by cursor, the ids breaking the tie between the two "c".
+    let mut page_request = PageRequest {
+        sort: Some(Sort::desc(SortField::Name)),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let mut ids = Vec::new();
+    loop {
+        let page = get_sections(
+            &storage,
+            GetSectionsForm {
+                id: None,
+                title: None,
+                position: None,
+                page: page_request.clone(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 4);
+        ids.extend(page.items.iter().map(|section| section.id));
+        match page.next_cursor {
+            Some(cursor) => page_request.cursor = Cursor::decode(&cursor),
+            None => break,
+        }
+    }
+    assert_eq!(ids, vec![4, 2, 1, 3]);
+}
+
+#[tokio::test]
+pub async fn sqlite_publication_test() {
+    let storage = sqlite_storage().await;
+    let now = crate::db::now();
+
+    let _ = storage
+        .sections
+        .create_section(sections::CreateSectionForm {
+            title: "published".to_string(),
+            ..Default::default()
+        })
+        .await;
+    let _ = storage
+        .subsections
+        .create_subsection(subsections::CreateSubsectionForm {
+            title: "scheduled".to_string(),
+            section_id: 1,
+            publish_at: Some(now + 60),
+            ..Default::default()
+        })
+        .await;
+    for (name, node_id, status) in [
+        ("shown", 1, None),
+        ("draft", 1, Some("draft")),
+        ("later", 2, None),
+    ] {
+        let res = storage
+            .notes
+            .create_note(lecture_notes::CreateNoteForm {
+                name: name.to_string(),
+                url: format!("{}.pdf", name),
+                node_id: Some(node_id),
+                status: status.map(str::to_string),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+
+    let published = |at| lecture_notes::GetNotesForm {
+        published_at: Some(at),
+        ..Default::default()
+    };
+    let names = |notes: Vec<lecture_notes::NoteFromDb>| {
+        notes.into_iter().map(|note| note.name).collect::<Vec<_>>()
+    };
+    let notes = storage.notes.get_notes(published(now)).await;
+    assert_eq!(names(notes.unwrap_or_default()), vec!["shown"]);
+    // The note under the subsection shows up along with it.
+    let notes = storage.notes.get_notes(published(now + 60)).await;
+    assert_eq!(names(notes.unwrap_or_default()), vec!["shown", "later"]);
+    let count = storage.notes.count_notes(published(now)).await;
+    assert!(matches!(count, Ok(1)));
+
+    let subsections = storage
+        .subsections
+        .get_subsections(subsections::GetSubsectionsForm {
+            published_at: Some(now),
+            ..Default::default()
+        })
+        .await;
+    assert!(subsections.unwrap_or_default().is_empty());
+
+    // Drafting the section hides everything under it.
+    let res = storage
+        .sections
+        .update_sections(
+            sections::UpdateSectionForm {
+                status: Some("draft".to_string()),
+                ..Default::default()
+            },
+            sections::GetSectionsForm {
+                id: Some(1),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(res.is_ok());
+    let notes = storage.notes.get_notes(published(now + 60)).await;
+    assert!(notes.unwrap_or_default().is_empty());
+    let section = storage
+        .sections
+        .get_section(sections::GetSectionsForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(
+        section.map(|section| section.status).ok().as_deref(),
+        Some("draft")
+    );
+}
+
+#[tokio::test]
+pub async fn sqlite_slugs_test() {
+    let storage = sqlite_storage().await;
+
+    // A section created without a slug gets its title slugified.
+    let _ = storage
+        .sections
+        .create_section(sections::CreateSectionForm {
+            title: "Linear Algebra".to_string(),
+            ..Default::default()
+        })
+        .await;
+    let res = storage
+        .notes
+        .create_note(lecture_notes::CreateNoteForm {
+            name: "Vector spaces".to_string(),
+            url: "spaces.pdf".to_string(),
+            node_id: Some(1),
+            slug: Some("spaces".to_string()),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+
+    let res = storage
+        .sections
+        .update_sections(
+            sections::UpdateSectionForm {
+                slug: Some("algebra".to_string()),
+                previous_slugs: Some(vec!["linear-algebra".to_string()]),
+                ..Default::default()
+            },
+            sections::GetSectionsForm {
+                id: Some(1),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(res.is_ok());
+    let section = match storage
+        .sections
+        .get_section(sections::GetSectionsForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await
+    {
+        Ok(section) => section,
+        Err(_) => panic!("the section was not found"),
+    };
+    assert_eq!(section.slug, "algebra");
+    assert_eq!(section.previous_slugs, r#"["linear-algebra"]"#);
+
+    let note = match storage
+        .notes
+        .get_note(lecture_notes::GetNotesForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await
+    {
+        Ok(note) => note,
+        Err(_) => panic!("the note was not found"),
+    };
+    assert_eq!(note.slug, "spaces");
+    assert!(note.previous_slug_list().is_empty());
+}
+
+/// A section holding the notes `names`, with ids from 1.
+async fn sqlite_storage_with_notes(names: &[&str]) -> Storage {
+    let storage = sqlite_storage().await;
+    let _ = storage
+        .sections
+        .create_section(sections::CreateSectionForm {
+            title: "section".to_string(),
+            ..Default::default()
+        })
+        .await;
+    for name in names {
+        let res = storage
+            .notes
+            .create_note(lecture_notes::CreateNoteForm {
+                name: name.to_string(),
+                url: format!("{}.pdf", name),
+                node_id: Some(1),
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+    storage
+}
+
+#[tokio::test]
+pub async fn sqlite_tags_test() {
+    let storage = sqlite_storage_with_notes(&["a", "b"]).await;
+
+    let ids = storage
+        .tags
+        .get_or_create_tags(&["rust".to_string(), "sql".to_string()])
+        .await;
+    assert_eq!(ids, Ok(vec![1, 2]));
+    let res = storage.tags.create_tag("rust".to_string()).await;
+    assert_eq!(res, Err(tags::CreateTagError::AlreadyExistsError));
+    assert_eq!(storage.tags.attach_tags(1, &[1, 2]).await, Ok(()));
+    assert_eq!(storage.tags.attach_tags(2, &[1, 1]).await, Ok(()));
+    let res = storage.tags.attach_tags(3, &[1]).await;
+    assert_eq!(res, Err(tags::AttachTagsError::NoteNotFoundError));
+
+    let names = |tags: Vec<tags::NoteTagFromDb>| {
+        tags.into_iter()
+            .map(|tag| (tag.note_id, tag.name))
+            .collect::<Vec<_>>()
+    };
+    let of_notes = storage
+        .tags
+        .get_tags_of_notes(&[1, 2])
+        .await
+        .map(names)
+        .unwrap_or_default();
+    assert_eq!(of_notes.len(), 3);
+    assert_eq!(of_notes[2], (1, "sql".to_string()));
+
+    // A tag named twice counts once.
+    let cases = [
+        (["rust", "sql"], tags::TagMatch::Any, 2),
+        (["rust", "sql"], tags::TagMatch::All, 1),
+        (["rust", "rust"], tags::TagMatch::All, 2),
+    ];
+    for (names, tag_match, expected) in cases {
+        let notes = storage
+            .notes
+            .get_notes(lecture_notes::GetNotesForm {
+                tags: names.map(str::to_string).to_vec(),
+                tag_match,
+                ..Default::default()
+            })
+            .await
+            .unwrap_or_default();
+        assert_eq!(notes.len(), expected);
+    }
+
+    let res = storage.tags.merge_tags(2, 1).await;
+    assert_eq!(res, Ok(()));
+    let counts = storage
+        .tags
+        .get_tags_with_counts()
+        .await
+        .unwrap_or_default();
+    assert_eq!(
+        counts
+            .into_iter()
+            .map(|tag| (tag.name, tag.note_count))
+            .collect::<Vec<_>>(),
+        vec![("rust".to_string(), 2)]
+    );
+    let res = storage.tags.merge_tags(2, 1).await;
+    assert_eq!(
+        res,
+        Err(tags::MergeTagsError::NotFoundError((Some(2), None)))
+    );
+
+    assert_eq!(storage.tags.rename_tag(1, "rs".to_string()).await, Ok(()));
+    assert!(storage.tags.detach_tag(1, 1).await.is_ok());
+    // Deleting a note detaches its tags.
+    let res = storage
+        .notes
+        .delete_note(lecture_notes::GetNotesForm {
+            id: Some(2),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+    let counts = storage
+        .tags
+        .get_tags_with_counts()
+        .await
+        .unwrap_or_default();
+    assert_eq!(counts[0].note_count, 0);
+}
+
+#[tokio::test]
+pub async fn sqlite_attachments_and_files_test() {
+    let storage = sqlite_storage_with_notes(&["a"]).await;
+
+    let file = files::CreateFileForm {
+        sha256: "0".repeat(64),
+        original_name: "a.pdf".to_string(),
+        mime_type: "application/pdf".to_string(),
+        size: 42,
+    };
+    assert_eq!(storage.files.create_file(file).await, Ok((1, true)));
+    let again = files::CreateFileForm {
+        sha256: "0".repeat(64),
+        original_name: "copy.pdf".to_string(),
+        mime_type: "application/pdf".to_string(),
+        size: 42,
+    };
+    assert_eq!(storage.files.create_file(again).await, Ok((1, false)));
+
+    for (label, file_id) in [("slides", Some(1)), ("video", None)] {
+        let res = storage
+            .attachments
+            .create_attachment(attachments::CreateAttachmentForm {
+                note_id: 1,
+                label: label.to_string(),
+                url: format!("https://example.com/{}", label),
+                file_id,
+                ..Default::default()
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+    let res = storage
+        .attachments
+        .create_attachment(attachments::CreateAttachmentForm {
+            note_id: 2,
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(
+        res,
+        Err(attachments::CreateAttachmentError::NoteNotFoundError)
+    );
+
+    assert_eq!(storage.attachments.swap_attachments([1, 2]).await, Ok(()));
+    let labels = storage
+        .attachments
+        .get_attachments(attachments::GetAttachmentsForm {
+            note_id: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|attachment| attachment.label)
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["video".to_string(), "slides".to_string()]);
+
+    let res = storage.files.delete_file(1).await;
+    assert_eq!(res, Err(files::DeleteFileError::InUseError));
+    let res = storage
+        .attachments
+        .delete_attachment(attachments::GetAttachmentsForm {
+            id: Some(1),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+    let res = storage.files.delete_file(1).await;
+    assert_eq!(res.map(|file| file.original_name), Ok("a.pdf".to_string()));
+}
+
+#[tokio::test]
+pub async fn sqlite_catalog_changes_test() {
+    let storage = sqlite_storage().await;
+    let node = ImportedNode {
+        parent: None,
+        kind: sections::SECTION_KIND.to_string(),
+        title: "section".to_string(),
+        position: 0,
+        status: nodes::PUBLISHED.to_string(),
+        publish_at: None,
+        slug: "section".to_string(),
+        previous_slugs: Vec::new(),
+    };
+    let note = ImportedNote {
+        node: NodeRef::Planned(0),
+        name: "note".to_string(),
+        description: String::new(),
+        url: "note.pdf".to_string(),
+        position: 0,
+        metadata: Default::default(),
+        status: nodes::PUBLISHED.to_string(),
+        publish_at: None,
+        slug: "note".to_string(),
+        previous_slugs: Vec::new(),
+        tags: vec!["rust".to_string()],
+        attachments: vec![ImportedAttachment {
+            label: "slides".to_string(),
+            url: "https://example.com/slides".to_string(),
+            mime_type: None,
+            size: None,
+            file_sha256: None,
+        }],
+    };
+    let changes = [
+        CatalogChange::CreateNode(node.clone()),
+        CatalogChange::CreateNote(note.clone()),
+    ];
+    let res = storage.catalog.apply_catalog_changes(&changes).await;
+    assert_eq!(res.ok(), Some(vec![1, 1]));
+    let tags = storage
+        .tags
+        .get_tags_of_notes(&[1])
+        .await
+        .unwrap_or_default();
+    assert_eq!(tags.len(), 1);
+
+    // A change naming another version than the stored one writes nothing.
+    let changes = [
+        CatalogChange::UpdateNode(NodeRef::Stored(1), 1, node.clone()),
+        CatalogChange::DeleteNote(NoteRef::Stored(1), 2),
+    ];
+    let res = storage.catalog.apply_catalog_changes(&changes).await;
+    assert!(matches!(
+        res,
+        Err(ApplyCatalogChangesError::VersionConflictError(1))
+    ));
+    let note_count = storage
+        .notes
+        .count_notes(Default::default())
+        .await
+        .unwrap_or_default();
+    assert_eq!(note_count, 1);
+
+    // A failing change leaves nothing behind.
+    let changes = [
+        CatalogChange::CreateNode(ImportedNode {
+            title: "other".to_string(),
+            position: 1,
+            ..node.clone()
+        }),
+        CatalogChange::CreateNote(ImportedNote {
+            node: NodeRef::Stored(1),
+            ..note
+        }),
+    ];
+    let res = storage.catalog.apply_catalog_changes(&changes).await;
+    assert!(res.is_err());
+    let count = storage
+        .sections
+        .count_sections(Default::default())
+        .await
+        .unwrap_or_default();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+pub async fn sqlite_section_tree_test() {
+    use crate::services::publishing::Visibility;
+    use crate::services::tree::{get_node_notes, get_section_trees};
+
+    let storage = sqlite_storage_with_notes(&["a", "b"]).await;
+    let res = storage
+        .sections
+        .create_section(sections::CreateSectionForm {
+            title: "other".to_string(),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+    let res = storage
+        .notes
+        .create_note(lecture_notes::CreateNoteForm {
+            name: "c".to_string(),
+            url: "c.pdf".to_string(),
+            node_id: Some(2),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+
+    let names = |notes: &[crate::services::lecture_notes::NoteReturn]| {
+        notes
+            .iter()
+            .map(|note| note.name.clone())
+            .collect::<Vec<_>>()
+    };
+    let trees = get_section_trees(&storage, Some(1), Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!(trees.len(), 1);
+    assert_eq!(names(&trees[0].notes), ["a", "b"]);
+    let notes = get_node_notes(&storage, 2, Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!(names(&notes), ["c"]);
+}
@@ -0,0 +1,1300 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::db::{sessions, users};
+use crate::repository::Storage;
+use crate::routes::conditional::{http_date, parse_http_date};
+use crate::routes::{router, AppState};
+use crate::services::files::FileStorageConfig;
+use crate::services::search::SearchIndex;
+
+const ADMIN_TOKEN: &str = "admin-token";
+
+/// A router over an empty in-memory storage, with an admin session already open.
+async fn test_app() -> Router {
+    let storage = Storage::memory();
+    let _ = storage
+        .users
+        .create_user(users::CreateUserForm {
+            username: "admin".to_string(),
+            password: "hash".to_string(),
+            is_admin: true,
+        })
+        .await;
+    let expires_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        + 60 * 60;
+    let _ = storage
+        .sessions
+        .create_session(sessions::CreateSessionForm {
+            user_id: 1,
+            token: ADMIN_TOKEN.to_string(),
+            expires_at,
+        })
+        .await;
+
+    router(AppState {
+        storage,
+        register_only_for_admin: true,
+        file_storage: Arc::new(FileStorageConfig {
+            root: std::env::temp_dir().join("backend-rs-route-tests"),
+            max_size: 1024,
+            allowed_mime_types: Vec::new(),
+        }),
+        search: Arc::new(SearchIndex::default()),
+        tree_cache: Default::default(),
+    })
+}
+
+async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    send_with(app, method, uri, &[], body).await
+}
+
+/// `send` with extra headers.
+async fn send_with(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN_TOKEN));
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    let body = match body {
+        Some(body) => {
+            if !headers.iter().any(|(name, _)| name == header::CONTENT_TYPE) {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+            }
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+#[tokio::test]
+async fn content_routes_test() {
+    let app = test_app().await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/sections",
+        Some(json!({"title": "Maths"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let body = json!({"title": "Algebra", "section_id": 1});
+    let (status, _) = send(&app, Method::POST, "/subsections", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let body = json!({
+        "name": "Groups",
+        "url": "https://example.com/groups.pdf",
+        "subsection_id": 2,
+        "kind": "lecture",
+        "authors": ["Ada"],
+    });
+    let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, note) = send(&app, Method::GET, "/notes/1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(note["section_id"], json!(1));
+    assert_eq!(note["subsection_id"], json!(2));
+    assert_eq!(note["authors"], json!(["Ada"]));
+
+    let (status, index) = send(&app, Method::GET, "/", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(index["sections"][0]["title"], json!("Maths"));
+    assert_eq!(
+        index["sections"][0]["subsections"][0]["notes"][0]["name"],
+        json!("Groups")
+    );
+
+    let (status, _) = send(&app, Method::GET, "/sections/9", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let body = json!({"version": 1});
+    let (status, _) = send(&app, Method::PUT, "/sections/1", Some(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn section_tree_routes_test() {
+    let app = test_app().await;
+    for (uri, body) in [
+        ("/sections", json!({"title": "Maths"})),
+        ("/subsections", json!({"title": "Algebra", "section_id": 1})),
+        (
+            "/subsections",
+            json!({"title": "Drafts", "section_id": 1, "status": "draft"}),
+        ),
+        (
+            "/notes",
+            json!({"name": "Groups", "url": "https://example.com/groups.pdf", "subsection_id": 2}),
+        ),
+        (
+            "/notes",
+            json!({"name": "Rings", "url": "https://example.com/rings.pdf", "subsection_id": 2}),
+        ),
+        (
+            "/notes",
+            json!({"name": "Syllabus", "url": "https://example.com/syllabus.pdf", "section_id": 1}),
+        ),
+    ] {
+        let (status, _) = send(&app, Method::POST, uri, Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let body = json!({"first_id": 1, "second_id": 2, "first_version": 1, "second_version": 1});
+    let (status, _) = send(&app, Method::POST, "/notes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let names = |notes: &Value| {
+        notes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|note| note["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let (status, tree) = get_public(&app, "/sections/1/tree").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tree["title"], json!("Maths"));
+    assert_eq!(tree["subsections"].as_array().unwrap().len(), 1);
+    assert_eq!(names(&tree["subsections"][0]["notes"]), ["Rings", "Groups"]);
+    assert_eq!(names(&tree["notes"]), ["Syllabus"]);
+    // The tree is what the index holds for the section.
+    let (_, index) = get_public(&app, "/").await;
+    assert_eq!(index["sections"][0], tree);
+
+    let (status, subsections) = get_public(&app, "/sections/1/subsections").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(subsections.as_array().unwrap().len(), 1);
+    assert_eq!(subsections[0]["slug"], json!("algebra"));
+    let (_, subsections) = send(&app, Method::GET, "/sections/1/subsections", None).await;
+    assert_eq!(subsections.as_array().unwrap().len(), 2);
+
+    let (status, notes) = get_public(&app, "/subsections/2/notes").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(names(&notes), ["Rings", "Groups"]);
+    let (_, notes) = get_public(&app, "/sections/1/notes").await;
+    assert_eq!(names(&notes), ["Syllabus"]);
+
+    let response = get_with(&app, "/sections/1/tree", &[]).await;
+    let etag = header_value(&response, header::ETAG);
+    let response = get_with(&app, "/sections/1/tree", &[(header::IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    for uri in [
+        "/sections/9/tree",
+        "/sections/9/subsections",
+        "/sections/9/notes",
+        "/subsections/9/notes",
+        "/subsections/3/notes",
+    ] {
+        let (status, _) = get_public(&app, uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+    }
+    let (status, _) = send(&app, Method::GET, "/subsections/3/notes", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_routes_require_a_session_test() {
+    let app = test_app().await;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/sections")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"title": "Maths"}).to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let (status, users) = send(&app, Method::GET, "/users", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(users["items"][0]["username"], json!("admin"));
+}
+
+#[tokio::test]
+async fn tags_attachments_and_catalog_routes_test() {
+    let app = test_app().await;
+    let batch = json!({"operations": [
+        {"op": "create", "entity": "section", "ref": "maths", "title": "Maths"},
+        {"op": "create", "entity": "note", "section_id": "maths", "name": "Groups",
+            "url": "https://example.com/groups.pdf"},
+    ]});
+    let (status, _) = send(&app, Method::POST, "/batch", Some(batch)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let body = json!({"tags": ["algebra", "exam"]});
+    let (status, _) = send(&app, Method::POST, "/notes/1/tags", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = json!({"label": "Slides", "url": "https://example.com/slides.pdf"});
+    let (status, _) = send(&app, Method::POST, "/notes/1/attachments", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, tags) = send(&app, Method::GET, "/tags", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tags[0]["name"], json!("algebra"));
+    assert_eq!(tags[0]["note_count"], json!(1));
+    let (_, note) = send(&app, Method::GET, "/notes/1", None).await;
+    assert_eq!(note["tags"], json!(["algebra", "exam"]));
+    assert_eq!(note["attachments"][0]["label"], json!("Slides"));
+
+    let (status, tree) = send(&app, Method::GET, "/tree", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        tree["nodes"][0]["notes"][0]["tags"],
+        json!(["algebra", "exam"])
+    );
+    let (status, catalog) = send(&app, Method::GET, "/export", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(catalog["sections"][0]["title"], json!("Maths"));
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/import?mode=replace&dry_run=true",
+        Some(catalog.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The imported items follow the rules of the routes creating them.
+    let mut invalid = catalog;
+    invalid["sections"][0]["notes"][0]["url"] = json!("javascript:alert(1)");
+    invalid["sections"][0]["title"] = json!(" ");
+    let (status, body) = send(&app, Method::POST, "/import", Some(invalid)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["details"],
+        json!([
+            {"field": "sections[0].notes[0].url", "message": "must be an http or https URL"},
+            {"field": "sections[0].title", "message": "cannot be empty"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn batch_validation_test() {
+    let app = test_app().await;
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/batch",
+        Some(json!({"operations": []})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"][0]["field"], json!("operations"));
+
+    // A body that cannot be read names the field it failed at, by its path.
+    let (status, body) = send(&app, Method::POST, "/batch", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["details"],
+        json!([{"field": "operations", "message": "is required"}])
+    );
+    let operations = json!({"operations": [{"entity": "note"}]});
+    let (status, body) = send(&app, Method::POST, "/batch", Some(operations)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["details"],
+        json!([{"field": "operations[0].op", "message": "is required"}])
+    );
+
+    // The rules of the fields apply to every operation, which is named by its index.
+    let operations = json!({"operations": [
+        {"op": "create", "entity": "section", "ref": "maths", "title": "Maths"},
+        {"op": "create", "entity": "note", "section_id": "maths", "name": " ", "url": "x"},
+    ]});
+    let (status, body) = send(&app, Method::POST, "/batch", Some(operations)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["details"],
+        json!([
+            {"field": "operations[1].name", "message": "cannot be empty"},
+            {"field": "operations[1].url", "message": "must be an http or https URL"},
+        ])
+    );
+
+    let update = json!({"operations": [{"op": "update", "entity": "note", "name": "Groups"}]});
+    let (status, body) = send(&app, Method::POST, "/batch", Some(update)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["details"],
+        json!([{"field": "operations[0].id", "message": "is required"}])
+    );
+
+    // The writes to stored rows name the versions they were read at.
+    let update = json!({"operations": [
+        {"op": "update", "entity": "note", "id": 1, "name": "Groups"},
+    ]});
+    let (status, body) = send(&app, Method::POST, "/batch", Some(update)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(
+        body["details"],
+        json!([{"field": "operations[0].version", "message": "is required"}])
+    );
+}
+
+#[tokio::test]
+async fn list_pagination_test() {
+    let app = test_app().await;
+    for title in ["Physics", "Algebra", "Chemistry"] {
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/sections",
+            Some(json!({"title": title})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, page) = send(&app, Method::GET, "/sections?sort=name&limit=2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], json!(3));
+    assert_eq!(page["items"][0]["title"], json!("Algebra"));
+    assert_eq!(page["items"][1]["title"], json!("Chemistry"));
+    assert_eq!(page["links"]["self"], json!("/sections?sort=name&limit=2"));
+    let cursor = page["next_cursor"].as_str().unwrap().to_string();
+    assert_eq!(
+        page["links"]["next"],
+        json!(format!("/sections?sort=name&limit=2&cursor={}", cursor))
+    );
+
+    let next = page["links"]["next"].as_str().unwrap().to_string();
+    let (status, page) = send(&app, Method::GET, &next, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"][0]["title"], json!("Physics"));
+    assert_eq!(page["next_cursor"], Value::Null);
+    assert_eq!(page["links"]["next"], Value::Null);
+
+    let (status, page) = send(
+        &app,
+        Method::GET,
+        "/sections?sort=-id&limit=1&offset=1",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"][0]["title"], json!("Algebra"));
+    assert_eq!(
+        page["links"]["next"],
+        json!("/sections?sort=-id&limit=1&offset=2")
+    );
+    assert_eq!(
+        page["links"]["prev"],
+        json!("/sections?sort=-id&limit=1&offset=0")
+    );
+
+    // The bare array answered before the envelope existed.
+    let (status, list) = send(&app, Method::GET, "/sections?envelope=false", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(list.as_array().map(Vec::len), Some(3));
+    assert_eq!(list[0]["title"], json!("Physics"));
+
+    for uri in [
+        "/sections?sort=title",
+        "/users?sort=position",
+        "/notes?cursor=nothex",
+        &format!("/sections?cursor={}&offset=1", cursor),
+        &format!("/sections?sort=-name&cursor={}", cursor),
+    ] {
+        let (status, body) = send(&app, Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        assert!(body["error"].is_string());
+    }
+}
+
+/// Percent-encode a query parameter, for the non-ASCII searches.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn search_routes_test() {
+    let app = test_app().await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/sections",
+        Some(json!({"title": "Mathématiques"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    for (name, description) in [
+        ("Théorème de Pythagore", "Démonstration et exercices"),
+        ("Лінійна алгебра", "Конспект лекцій з лінійної алгебри"),
+    ] {
+        let body = json!({
+            "name": name,
+            "description": description,
+            "url": "https://example.com/notes.pdf",
+            "section_id": 1,
+        });
+        let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let uri = format!("/search?q={}", encode("theoreme"));
+    let (status, found) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(found["total"], json!(1));
+    assert_eq!(found["results"][0]["kind"], json!("note"));
+    assert_eq!(found["results"][0]["id"], json!(1));
+    assert_eq!(
+        found["results"][0]["snippet"],
+        json!("<mark>Théorème</mark> de Pythagore")
+    );
+
+    let uri = format!("/search?q={}", encode("ЛІНІЙН"));
+    let (_, found) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(found["total"], json!(1));
+    assert_eq!(
+        found["results"][0]["snippet"],
+        json!("Конспект лекцій з <mark>лінійної</mark> алгебри")
+    );
+
+    // The parent section title is searchable too.
+    let uri = format!("/search?q={}&kind=note", encode("mathematiques"));
+    let (_, found) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(found["total"], json!(2));
+    let (_, found) = send(&app, Method::GET, "/search?q=mathematiques", None).await;
+    assert_eq!(found["total"], json!(3));
+    assert_eq!(found["results"][0]["kind"], json!("section"));
+
+    // Updates and deletions reach the index.
+    let body = json!({"description": "Preuve géométrique", "version": 1});
+    let (status, _) = send(&app, Method::PUT, "/notes/1", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, found) = send(&app, Method::GET, "/search?q=geometrique", None).await;
+    assert_eq!(found["results"][0]["id"], json!(1));
+    let (_, found) = send(&app, Method::GET, "/search?q=exercices", None).await;
+    assert_eq!(found["total"], json!(0));
+    let (status, _) = send(&app, Method::DELETE, "/notes/1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, found) = send(&app, Method::GET, "/search?q=pythagore", None).await;
+    assert_eq!(found["total"], json!(0));
+
+    let (status, rebuilt) = send(&app, Method::POST, "/search/rebuild", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rebuilt["documents"], json!(2));
+
+    let (status, _) = send(&app, Method::GET, "/search?q=%20", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+/// A GET request with extra headers, answered with the raw response.
+async fn get_with(
+    app: &Router,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+) -> axum::response::Response {
+    let mut request = Request::builder().method(Method::GET).uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+fn header_value(response: &axum::response::Response, name: header::HeaderName) -> String {
+    response.headers()[name].to_str().unwrap().to_string()
+}
+
+#[test]
+fn http_date_test() {
+    assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(http_date(951_782_400), "Tue, 29 Feb 2000 00:00:00 GMT");
+    assert_eq!(
+        parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(784_111_777)
+    );
+    assert_eq!(
+        parse_http_date(&http_date(1_800_000_000)),
+        Some(1_800_000_000)
+    );
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+}
+
+#[tokio::test]
+async fn conditional_requests_test() {
+    let app = test_app().await;
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/sections",
+        Some(json!({"title": "Maths"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let body = json!({"name": "Groups", "url": "https://example.com/groups.pdf", "section_id": 1});
+    let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, note) = send(&app, Method::GET, "/notes/1", None).await;
+    assert!(note["created_at"].as_i64().unwrap() > 0);
+    assert_eq!(note["created_at"], note["updated_at"]);
+
+    let response = get_with(&app, "/notes/1", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = header_value(&response, header::ETAG);
+    let last_modified = header_value(&response, header::LAST_MODIFIED);
+    assert_eq!(parse_http_date(&last_modified), note["updated_at"].as_i64());
+
+    let response = get_with(&app, "/notes/1", &[(header::IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(header_value(&response, header::ETAG), etag);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(bytes.is_empty());
+    let weak = format!("\"other\", W/{}", etag);
+    let response = get_with(&app, "/notes/1", &[(header::IF_NONE_MATCH, &weak)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = get_with(
+        &app,
+        "/notes/1",
+        &[(header::IF_MODIFIED_SINCE, &last_modified)],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    let earlier = http_date(note["updated_at"].as_i64().unwrap() - 60);
+    let response = get_with(&app, "/notes/1", &[(header::IF_MODIFIED_SINCE, &earlier)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // `If-None-Match` takes precedence over `If-Modified-Since`.
+    let response = get_with(
+        &app,
+        "/notes/1",
+        &[
+            (header::IF_NONE_MATCH, "\"other\""),
+            (header::IF_MODIFIED_SINCE, &last_modified),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Lists only revalidate through the ETag.
+    let response = get_with(&app, "/notes", &[]).await;
+    let list_etag = header_value(&response, header::ETAG);
+    assert_eq!(
+        header_value(&response, header::LAST_MODIFIED),
+        last_modified
+    );
+    let response = get_with(&app, "/notes", &[(header::IF_NONE_MATCH, &list_etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    let response = get_with(
+        &app,
+        "/notes",
+        &[(header::IF_MODIFIED_SINCE, &last_modified)],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get_with(&app, "/", &[]).await;
+    let index_etag = header_value(&response, header::ETAG);
+    let response = get_with(&app, "/", &[(header::IF_NONE_MATCH, &index_etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        "/notes/1",
+        Some(json!({"name": "Rings", "version": 1})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    for (uri, etag) in [
+        ("/notes/1", &etag),
+        ("/notes", &list_etag),
+        ("/", &index_etag),
+    ] {
+        let response = get_with(&app, uri, &[(header::IF_NONE_MATCH, etag)]).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert_ne!(&header_value(&response, header::ETAG), etag);
+    }
+}
+
+#[tokio::test]
+async fn optimistic_concurrency_test() {
+    let app = test_app().await;
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/sections",
+        Some(json!({"title": "Maths"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    for name in ["Groups", "Rings"] {
+        let body = json!({"name": name, "url": "https://example.com/a.pdf", "section_id": 1});
+        let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (_, note) = send(&app, Method::GET, "/notes/1", None).await;
+    assert_eq!(note["version"], json!(1));
+    let (status, error) = send(&app, Method::PUT, "/notes/1", Some(json!({"name": "A"}))).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(error["code"], json!("precondition_required"));
+
+    // Through `If-Match`, with the ETag of a previous GET.
+    let etag = header_value(&get_with(&app, "/notes/1", &[]).await, header::ETAG);
+    let if_match = [(header::IF_MATCH, etag.as_str())];
+    let body = json!({"name": "Groupes"});
+    let (status, _) = send_with(&app, Method::PUT, "/notes/1", &if_match, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = json!({"name": "Gruppen"});
+    let (status, stale) = send_with(&app, Method::PUT, "/notes/1", &if_match, Some(body)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(stale["code"], json!("precondition_failed"));
+    assert_eq!(stale["current"]["name"], json!("Groupes"));
+    assert_eq!(stale["current"]["version"], json!(2));
+
+    // Through the `version` field.
+    let body = json!({"name": "Gruppen", "version": 1});
+    let (status, stale) = send(&app, Method::PUT, "/notes/1", Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(stale["code"], json!("version_conflict"));
+    assert_eq!(stale["current"]["version"], json!(2));
+    let body = json!({"name": "Gruppen", "version": 2});
+    let (status, _) = send(&app, Method::PUT, "/notes/1", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let body = json!({"title": "Mathématiques"});
+    let (status, _) = send_with(
+        &app,
+        Method::PUT,
+        "/sections/1",
+        &[(header::IF_MATCH, "*")],
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, section) = send(&app, Method::GET, "/sections/1", None).await;
+    assert_eq!(section["version"], json!(2));
+
+    // Moves name the versions of both notes.
+    let body = json!({"first_id": 1, "second_id": 2});
+    let (status, _) = send(&app, Method::POST, "/notes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    let body = json!({"first_id": 1, "second_id": 2, "first_version": 1, "second_version": 1});
+    let (status, stale) = send(&app, Method::POST, "/notes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(stale["current"][0]["version"], json!(3));
+    assert_eq!(stale["current"][1]["version"], json!(1));
+    let body = json!({"first_id": 1, "second_id": 2, "first_version": 3, "second_version": 1});
+    let (status, _) = send(&app, Method::POST, "/notes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, moved) = send(&app, Method::GET, "/notes/2", None).await;
+    assert_eq!(moved["position"], json!(0));
+    assert_eq!(moved["version"], json!(2));
+
+    // The node routes reach the same rows, under the same rules.
+    let (status, _) = send(&app, Method::PUT, "/nodes/1", Some(json!({"title": "A"}))).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    let body = json!({"title": "Algèbre", "version": 1});
+    let (status, stale) = send(&app, Method::PUT, "/nodes/1", Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(stale["current"]["title"], json!("Mathématiques"));
+    let body = json!({"title": "Physics"});
+    let (status, _) = send(&app, Method::POST, "/sections", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let body = json!({"first_id": 1, "second_id": 2});
+    let (status, _) = send(&app, Method::POST, "/nodes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    let body = json!({"first_id": 1, "second_id": 2, "first_version": 1, "second_version": 1});
+    let (status, stale) = send(&app, Method::POST, "/nodes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(stale["current"][0]["version"], json!(2));
+    let body = json!({"first_id": 1, "second_id": 2, "first_version": 2, "second_version": 1});
+    let (status, _) = send(&app, Method::POST, "/nodes/move", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, section) = send(&app, Method::GET, "/sections/1", None).await;
+    assert_eq!((&section["position"], &section["version"]), (&json!(1), &json!(3)));
+}
+
+#[tokio::test]
+async fn slugs_test() {
+    let app = test_app().await;
+    for title in ["Maths", "Maths"] {
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/sections",
+            Some(json!({"title": title})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let (_, section) = send(&app, Method::GET, "/sections/2", None).await;
+    assert_eq!(section["slug"], json!("maths-2"));
+
+    let body = json!({"slug": "maths", "version": 1});
+    let (status, error) = send(&app, Method::PUT, "/sections/2", Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["code"], json!("slug_taken"));
+    let body = json!({"slug": "Maths II", "version": 1});
+    let (status, error) = send(&app, Method::PUT, "/sections/2", Some(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], json!("invalid_slug"));
+    let body = json!({"slug": "maths-ii", "version": 1});
+    let (status, _) = send(&app, Method::PUT, "/sections/2", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, section) = send(&app, Method::GET, "/sections/2", None).await;
+    assert_eq!(section["slug"], json!("maths-ii"));
+    assert_eq!(section["previous_slugs"], json!(["maths-2"]));
+}
+
+#[tokio::test]
+async fn error_codes_test() {
+    let app = test_app().await;
+
+    let (status, error) = send(&app, Method::GET, "/sections/9", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        error,
+        json!({"code": "not_found", "error": "section not found"})
+    );
+    let (status, error) = send(&app, Method::GET, "/sections?sort=size", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], json!("invalid_page"));
+
+    // An unknown session is told apart from a session without the admin role.
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri("/sections/1")
+        .header(header::AUTHORIZATION, "Bearer unknown")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let error = serde_json::from_slice::<Value>(&bytes).unwrap();
+    assert_eq!(error["code"], json!("unauthorized"));
+}
+
+#[tokio::test]
+async fn validation_test() {
+    let app = test_app().await;
+
+    let body = json!({"name": " ", "url": "ftp://example.com/notes.pdf"});
+    let (status, error) = send(&app, Method::POST, "/notes", Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], json!("validation_failed"));
+    assert_eq!(
+        error["details"],
+        json!([
+            {"field": "name", "message": "cannot be empty"},
+            {"field": "section_id", "message": "a node, section or subsection is required"},
+            {"field": "url", "message": "must be an http or https URL"},
+        ])
+    );
+
+    let body = json!({"title": "x".repeat(256)});
+    let (status, error) = send(&app, Method::POST, "/sections", Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["details"][0]["field"], json!("title"));
+    let body = json!({"title": "Nothing", "section_id": "one"});
+    let (status, error) = send(&app, Method::POST, "/subsections", Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], json!("validation_failed"));
+
+    for (uri, body) in [
+        ("/sections", json!({"title": "Maths"})),
+        ("/subsections", json!({"title": "Algebra", "section_id": 1})),
+        ("/sections", json!({"title": "Physics"})),
+    ] {
+        let (status, _) = send(&app, Method::POST, uri, Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    // The subsection 2 is in the section 1, not in the section 3.
+    let body = json!({
+        "name": "Groups",
+        "url": "https://example.com/groups.pdf",
+        "section_id": 3,
+        "subsection_id": 2,
+    });
+    let (status, error) = send(&app, Method::POST, "/notes", Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["details"],
+        json!([{"field": "subsection_id", "message": "the subsection is not in the section"}])
+    );
+}
+
+#[tokio::test]
+async fn merge_patch_test() {
+    let app = test_app().await;
+    let merge_patch = [(header::CONTENT_TYPE, "application/merge-patch+json")];
+
+    for (uri, body) in [
+        (
+            "/sections",
+            json!({"title": "Maths", "publish_at": 4102444800i64}),
+        ),
+        ("/subsections", json!({"title": "Algebra", "section_id": 1})),
+    ] {
+        let (status, _) = send(&app, Method::POST, uri, Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let body = json!({
+        "name": "Groups",
+        "url": "https://example.com/groups.pdf",
+        "subsection_id": 2,
+        "kind": "lecture",
+        "course_code": "MATH101",
+    });
+    let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // `null` takes the note out of its subsection and clears its metadata.
+    let body = json!({"subsection_id": null, "course_code": null, "version": 1});
+    let (status, _) = send_with(&app, Method::PATCH, "/notes/1", &merge_patch, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, note) = send(&app, Method::GET, "/notes/1", None).await;
+    assert_eq!(note["section_id"], json!(1));
+    assert_eq!(note["subsection_id"], Value::Null);
+    assert_eq!(note["course_code"], Value::Null);
+    assert_eq!(note["kind"], json!("lecture"));
+
+    // Required fields cannot be cleared.
+    let body = json!({"name": null, "version": 2});
+    let (status, error) =
+        send_with(&app, Method::PATCH, "/notes/1", &merge_patch, Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["details"],
+        json!([{"field": "name", "message": "cannot be cleared"}])
+    );
+
+    // A field of the wrong type is named, without the position in the body.
+    let body = json!({"name": 7, "version": 2});
+    let (status, error) =
+        send_with(&app, Method::PATCH, "/notes/1", &merge_patch, Some(body)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error["details"],
+        json!([{"field": "name", "message": "invalid type: integer `7`, expected a string"}])
+    );
+
+    let body = json!({"publish_at": null, "version": 1});
+    let (status, _) = send_with(&app, Method::PATCH, "/sections/1", &merge_patch, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, section) = send(&app, Method::GET, "/sections/1", None).await;
+    assert_eq!(section["publish_at"], Value::Null);
+    let body = json!({"title": "Linear algebra", "version": 1});
+    let (status, _) = send_with(
+        &app,
+        Method::PATCH,
+        "/subsections/2",
+        &merge_patch,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// A GET request without a session, answered as to the public.
+async fn get_public(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let response = get_with(app, uri, &[]).await;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn publication_test() {
+    let app = test_app().await;
+    let next_year = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        + 365 * 24 * 60 * 60;
+
+    for body in [
+        json!({"title": "Maths"}),
+        json!({"title": "Physics", "status": "draft"}),
+        json!({"title": "Algebra", "section_id": 1, "publish_at": next_year}),
+    ] {
+        let uri = match body.get("section_id") {
+            Some(_) => "/subsections",
+            None => "/sections",
+        };
+        let (status, _) = send(&app, Method::POST, uri, Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    for (name, parent) in [
+        ("Groups", json!({"section_id": 1})),
+        ("Rings", json!({"subsection_id": 3})),
+    ] {
+        let mut body = json!({"name": name, "url": "https://example.com/notes.pdf"});
+        body.as_object_mut()
+            .unwrap()
+            .extend(parent.as_object().unwrap().clone());
+        let (status, _) = send(&app, Method::POST, "/notes", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (_, sections) = get_public(&app, "/sections").await;
+    assert_eq!(sections["total"], json!(1));
+    let (status, _) = get_public(&app, "/sections/2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, section) = send(&app, Method::GET, "/sections/2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(section["status"], json!("draft"));
+
+    let (_, index) = get_public(&app, "/").await;
+    assert_eq!(index["sections"].as_array().map(Vec::len), Some(1));
+    assert_eq!(index["sections"][0]["subsections"], json!([]));
+    let (_, notes) = get_public(&app, "/notes").await;
+    assert_eq!(notes["total"], json!(1));
+    let (_, notes) = send(&app, Method::GET, "/notes", None).await;
+    assert_eq!(notes["total"], json!(2));
+    let (_, found) = get_public(&app, "/search?q=rings").await;
+    assert_eq!(found["total"], json!(0));
+    let (_, found) = send(&app, Method::GET, "/search?q=rings", None).await;
+    assert_eq!(found["total"], json!(1));
+
+    // Clearing the publication time publishes the subsection and its note.
+    let body = json!({"publish_at": null, "version": 1});
+    let (status, _) = send(&app, Method::PUT, "/subsections/3", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, subsection) = get_public(&app, "/subsections/3").await;
+    assert_eq!(subsection["publish_at"], json!(null));
+    let (_, found) = get_public(&app, "/search?q=rings").await;
+    assert_eq!(found["total"], json!(1));
+    let (_, notes) = get_public(&app, "/notes").await;
+    assert_eq!(notes["total"], json!(2));
+}
+
+#[tokio::test]
+async fn import_notes_test() {
+    let app = test_app().await;
+    let import = |content_type: &'static str, uri: &'static str, body: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN_TOKEN))
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (
+                status,
+                serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+            )
+        }
+    };
+
+    let csv = "section,subsection,name,url\n\
+               Algebra,Rings,Ideals,https://example.com/ideals.pdf\n\
+               Algebra,,,https://example.com/x.pdf\n";
+    let (status, report) = import("text/csv", "/notes/import", csv).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["created_sections"], json!(["Algebra"]));
+    assert_eq!(report["created_notes"], json!(1));
+    assert_eq!(
+        report["errors"],
+        json!([{
+            "line": 3,
+            "message": "the `name` cannot be empty",
+            "details": [{"field": "name", "message": "cannot be empty"}],
+        }])
+    );
+    // The search index follows the import.
+    let (_, found) = send(&app, Method::GET, "/search?q=ideals", None).await;
+    assert_eq!(found["total"], json!(1));
+
+    let yaml = "- section: Algebra\n  subsection: Rings\n  name: Prime ideals\n  \
+                url: https://example.com/ideals.pdf\n";
+    let (status, report) = import("text/plain", "/notes/import?format=yaml", yaml).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["updated_notes"], json!(1));
+
+    let (status, _) = import("text/plain", "/notes/import", yaml).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let (status, _) = import("text/csv", "/notes/import", "name,url\nA,a.pdf\n").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+/// Every operation of the served document reaches a handler of the router, which
+/// the document is built from.
+#[tokio::test]
+async fn openapi_covers_router_test() {
+    let app = test_app().await;
+    let (_, spec) = get_public(&app, "/openapi.json").await;
+    let mut operations = 0;
+    let mut unrouted = Vec::new();
+    for (path, item) in spec["paths"].as_object().unwrap() {
+        // The path parameters are all ids, and the root is served without a slash.
+        let uri = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "1"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let uri = format!("/api/v1{}", uri.trim_end_matches('/'));
+        for method in ["get", "post", "put", "patch", "delete"] {
+            if item.get(method).is_none() {
+                continue;
+            }
+            operations += 1;
+            let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+            let (status, body) = send(&app, method.clone(), &uri, None).await;
+            // The router answers an unknown route with an empty 404, and an unknown
+            // method with a 405.
+            if status == StatusCode::METHOD_NOT_ALLOWED
+                || (status == StatusCode::NOT_FOUND && body.is_null())
+            {
+                unrouted.push(format!("{method} {path}"));
+            }
+        }
+    }
+    assert!(operations > 50);
+    assert!(
+        unrouted.is_empty(),
+        "documented but not routed: {unrouted:?}"
+    );
+}
+
+#[tokio::test]
+async fn openapi_routes_test() {
+    let app = test_app().await;
+    let (status, spec) = get_public(&app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(spec["paths"]["/notes/{id}"]["put"].is_object());
+    assert!(spec["components"]["schemas"]["CreateNoteRequest"].is_object());
+    assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+    assert_eq!(spec["servers"][0]["url"], "/api/v1");
+
+    let response = get_with(&app, "/docs/", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(header_value(&response, header::CONTENT_TYPE).starts_with("text/html"));
+}
+
+#[tokio::test]
+async fn api_versions_test() {
+    use crate::routes::{LEGACY_DEPRECATED_AT, LEGACY_SUNSET_AT};
+
+    let app = test_app().await;
+    let response = get_with(&app, "/api/v1/sections?limit=5", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("deprecation").is_none());
+    let response = get_with(&app, "/api/v1", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // The guard covers the admin routes of both paths.
+    let response = get_with(&app, "/api/v1/users", &[]).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::GET, "/api/v1/users", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let response = get_with(&app, "/sections?limit=5", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header_value(&response, header::HeaderName::from_static("deprecation")),
+        format!("@{LEGACY_DEPRECATED_AT}")
+    );
+    assert_eq!(
+        header_value(&response, header::HeaderName::from_static("sunset")),
+        http_date(LEGACY_SUNSET_AT)
+    );
+    assert_eq!(
+        header_value(&response, header::LINK),
+        "</api/v1/sections?limit=5>; rel=\"successor-version\""
+    );
+    let response = get_with(&app, "/users", &[]).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().get("deprecation").is_some());
+    let response = get_with(&app, "/", &[]).await;
+    assert_eq!(
+        header_value(&response, header::LINK),
+        "</api/v1>; rel=\"successor-version\""
+    );
+
+    // The pages of the admin UI are not versioned.
+    let response = get_with(&app, "/login", &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("deprecation").is_none());
+}
+
+/// The public index, checked against the uncached trees of its sections.
+async fn fresh_index(app: &Router) -> Value {
+    let (status, index) = get_public(app, "/").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, listed) = get_public(app, "/sections").await;
+    let sections = index["sections"].as_array().unwrap();
+    assert_eq!(json!(sections.len()), listed["total"]);
+    for section in sections {
+        let (_, tree) = get_public(app, &format!("/sections/{}/tree", section["id"])).await;
+        assert_eq!(section, &tree);
+    }
+    index
+}
+
+#[tokio::test]
+async fn tree_cache_test() {
+    let app = test_app().await;
+    let writes = [
+        (Method::POST, "/sections", json!({"title": "Maths"})),
+        (
+            Method::POST,
+            "/sections",
+            json!({"title": "Drafts", "status": "draft"}),
+        ),
+        (
+            Method::POST,
+            "/subsections",
+            json!({"title": "Algebra", "section_id": 1}),
+        ),
+        (
+            Method::POST,
+            "/notes",
+            json!({"name": "Groups", "url": "https://example.com/groups.pdf", "subsection_id": 3}),
+        ),
+        (
+            Method::PUT,
+            "/sections/1",
+            json!({"title": "Mathematics", "version": 1}),
+        ),
+        // Refused, the version is stale.
+        (
+            Method::PUT,
+            "/sections/1",
+            json!({"title": "Maths", "version": 1}),
+        ),
+        (
+            Method::PUT,
+            "/sections/2",
+            json!({"status": "published", "version": 1}),
+        ),
+        (Method::DELETE, "/notes/1", json!(null)),
+    ];
+    let mut indexes = Vec::new();
+    for (method, uri, body) in writes {
+        let body = (!body.is_null()).then_some(body);
+        let (status, _) = send(&app, method, uri, body).await;
+        assert!(status.is_success() || status == StatusCode::CONFLICT);
+        // The first read after a write loads the trees, the next ones hit the cache.
+        let index = fresh_index(&app).await;
+        let response = get_with(&app, "/", &[]).await;
+        let etag = header_value(&response, header::ETAG);
+        let response = get_with(&app, "/", &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        indexes.push(index);
+    }
+
+    let titles = |index: &Value| {
+        index["sections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|section| section["title"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles(&indexes[1]), ["Maths"]);
+    assert_eq!(
+        indexes[3]["sections"][0]["subsections"][0]["notes"][0]["name"],
+        json!("Groups")
+    );
+    assert_eq!(titles(&indexes[5]), ["Mathematics"]);
+    assert_eq!(titles(&indexes[6]), ["Mathematics", "Drafts"]);
+    assert_eq!(
+        indexes[7]["sections"][0]["subsections"][0]["notes"],
+        json!([])
+    );
+    // The admins get their own tree from the same cache.
+    let (_, index) = send(&app, Method::GET, "/", None).await;
+    assert_eq!(index, indexes[7]);
+
+    let (status, stats) = send(&app, Method::GET, "/cache/stats", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stats, json!({"hits": 8 * 2 + 1, "misses": 8}));
+    let (status, _) = get_public(&app, "/cache/stats").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn tree_cache_concurrency_test() {
+    let app = test_app().await;
+    let writers = (0..8).map(|index| {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let body = json!({"title": format!("Section {index}")});
+            let (status, _) = send(&app, Method::POST, "/sections", Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        })
+    });
+    let readers = (0..32).map(|_| {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let (status, _) = get_public(&app, "/").await;
+            assert_eq!(status, StatusCode::OK);
+        })
+    });
+    let tasks = writers.chain(readers).collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // No read racing a write left a stale index behind.
+    let index = fresh_index(&app).await;
+    assert_eq!(index["sections"].as_array().unwrap().len(), 8);
+}
@@ -0,0 +1,64 @@
+use crate::repository::Storage;
+use crate::services::attachments::{
+    create_attachment, get_attachments_of_note, CreateAttachmentError, CreateAttachmentForm,
+};
+use crate::services::lecture_notes::{create_note, get_note, CreateNoteForm};
+use crate::services::publishing::Visibility;
+use crate::services::sections::{create_section, CreateSectionForm};
+
+async fn storage_with_note() -> Storage {
+    let storage = Storage::memory();
+    let _ = create_section(
+        &storage,
+        CreateSectionForm {
+            title: "section".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let _ = create_note(
+        &storage,
+        CreateNoteForm {
+            name: "note".to_string(),
+            url: "https://example.com/note.pdf".to_string(),
+            section_id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    storage
+}
+
+#[tokio::test]
+async fn attachments_test() {
+    let storage = storage_with_note().await;
+    let form = |label: &str| CreateAttachmentForm {
+        label: label.to_string(),
+        url: Some(format!("https://example.com/{label}.pdf")),
+        mime_type: Some(" Application/PDF ".to_string()),
+        size: None,
+        file_id: None,
+    };
+
+    assert!(create_attachment(&storage, 1, form("slides")).await.is_ok());
+    assert!(create_attachment(&storage, 1, form("sheet")).await.is_ok());
+    assert!(matches!(
+        create_attachment(&storage, 9, form("slides")).await,
+        Err(CreateAttachmentError::NoteNotFoundError)
+    ));
+    assert!(matches!(
+        create_attachment(&storage, 1, form(" ")).await,
+        Err(CreateAttachmentError::EmptyLabelError)
+    ));
+
+    let attachments = get_attachments_of_note(&storage, 1).await.unwrap();
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachments[0].mime_type.as_deref(), Some("application/pdf"));
+    let note = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    let labels = note
+        .attachments
+        .iter()
+        .map(|attachment| attachment.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["slides", "sheet"]);
+}
@@ -0,0 +1,331 @@
+use crate::db::catalog::{CatalogChange, NodeRef, NoteRef};
+use crate::services::batch::{
+    plan_batch, BatchAction, BatchEntity, BatchFields, BatchId, BatchOperation,
+    BatchOperationError, BatchResult, BatchStatus, RunBatchError,
+};
+use crate::services::catalog::{CatalogNode, CatalogNote};
+
+fn node(id: u32, slug: &str, position: u32) -> CatalogNode {
+    CatalogNode {
+        id: Some(id),
+        version: Some(1),
+        kind: Some("section".to_string()),
+        title: slug.to_uppercase(),
+        slug: Some(slug.to_string()),
+        position,
+        ..Default::default()
+    }
+}
+
+fn note(id: u32, slug: &str, position: u32) -> CatalogNote {
+    CatalogNote {
+        id: Some(id),
+        version: Some(1),
+        name: slug.to_uppercase(),
+        slug: Some(slug.to_string()),
+        url: format!("https://example.com/{slug}.pdf"),
+        position,
+        ..Default::default()
+    }
+}
+
+/// The stored tree: `algebra` with the notes `groups` and `rings`, then `analysis`.
+fn stored() -> Vec<CatalogNode> {
+    let mut algebra = node(1, "algebra", 0);
+    algebra.notes = vec![note(10, "groups", 0), note(11, "rings", 1)];
+    vec![algebra, node(2, "analysis", 1)]
+}
+
+fn create(entity: BatchEntity, reference: Option<&str>, fields: BatchFields) -> BatchOperation {
+    BatchOperation {
+        entity,
+        action: BatchAction::Create {
+            reference: reference.map(str::to_string),
+        },
+        fields,
+    }
+}
+
+fn reference(name: &str) -> BatchId {
+    BatchId::Ref(name.to_string())
+}
+
+fn failure(operations: &[BatchOperation]) -> (usize, BatchOperationError) {
+    match plan_batch(&stored(), operations) {
+        Err(RunBatchError::OperationError(index, err)) => (index, err),
+        other => panic!("unexpected plan {:?}", other.map(|plan| plan.changes)),
+    }
+}
+
+#[test]
+fn plan_batch_test() {
+    let operations = [
+        create(
+            BatchEntity::Section,
+            Some("geometry"),
+            BatchFields {
+                title: Some("Geometry".to_string()),
+                ..Default::default()
+            },
+        ),
+        create(
+            BatchEntity::Subsection,
+            Some("plane"),
+            BatchFields {
+                title: Some("Plane".to_string()),
+                section_id: Some(reference("geometry")),
+                ..Default::default()
+            },
+        ),
+        create(
+            BatchEntity::Note,
+            Some("triangles"),
+            BatchFields {
+                name: Some("Triangles".to_string()),
+                url: Some("https://example.com/triangles.pdf".to_string()),
+                subsection_id: Some(Some(reference("plane"))),
+                ..Default::default()
+            },
+        ),
+        BatchOperation {
+            entity: BatchEntity::Note,
+            action: BatchAction::Update {
+                id: reference("triangles"),
+                version: 1,
+            },
+            fields: BatchFields {
+                tags: Some(vec!["shapes".to_string()]),
+                ..Default::default()
+            },
+        },
+        BatchOperation {
+            entity: BatchEntity::Note,
+            action: BatchAction::Move {
+                ids: [BatchId::Id(10), BatchId::Id(11)],
+                versions: [1, 1],
+            },
+            fields: BatchFields::default(),
+        },
+        BatchOperation {
+            entity: BatchEntity::Section,
+            action: BatchAction::Delete {
+                id: BatchId::Id(2),
+                version: 1,
+            },
+            fields: BatchFields::default(),
+        },
+    ];
+    let plan = plan_batch(&stored(), &operations).unwrap();
+
+    let summary = plan
+        .changes
+        .iter()
+        .map(|change| match change {
+            CatalogChange::CreateNode(node) => format!("create {} at {}", node.slug, node.position),
+            CatalogChange::CreateNote(note) => format!("create {} at {}", note.slug, note.position),
+            CatalogChange::UpdateNote(NoteRef::Stored(id), _, note) => {
+                format!("update {id} at {}", note.position)
+            }
+            other => format!("{:?}", other)
+                .split('(')
+                .next()
+                .unwrap()
+                .to_string(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            "create geometry at 2",
+            "create plane at 0",
+            "create triangles at 0",
+            "UpdateNote",
+            "update 10 at 1",
+            "update 11 at 0",
+            "DeleteNode",
+        ]
+    );
+    // The later operations hang from the rows created before them.
+    match &plan.changes[2] {
+        CatalogChange::CreateNote(note) => assert_eq!(note.node, NodeRef::Planned(1)),
+        other => panic!("unexpected change {:?}", other),
+    }
+    match &plan.changes[3] {
+        CatalogChange::UpdateNote(NoteRef::Planned(0), _, note) => {
+            assert_eq!(note.name, "Triangles");
+            assert_eq!(note.tags, vec!["shapes"]);
+        }
+        other => panic!("unexpected change {:?}", other),
+    }
+    assert_eq!(
+        plan.changes[6],
+        CatalogChange::DeleteNode(NodeRef::Stored(2), 1)
+    );
+
+    let results = plan.results(&[20, 21, 30, 30, 10, 11, 2]);
+    assert_eq!(
+        results[0],
+        BatchResult {
+            index: 0,
+            status: BatchStatus::Created,
+            entity: BatchEntity::Section,
+            id: 20,
+            second_id: None,
+            reference: Some("geometry".to_string()),
+        }
+    );
+    assert_eq!(results[3].status, BatchStatus::Updated);
+    assert_eq!(results[3].id, 30);
+    assert_eq!((results[4].id, results[4].second_id), (10, Some(11)));
+    assert_eq!(
+        (results[5].status, results[5].id),
+        (BatchStatus::Deleted, 2)
+    );
+}
+
+#[test]
+fn swap_created_rows_test() {
+    let created = |name: &str| {
+        create(
+            BatchEntity::Note,
+            Some(name),
+            BatchFields {
+                name: Some(name.to_string()),
+                url: Some(format!("https://example.com/{name}.pdf")),
+                section_id: Some(BatchId::Id(2)),
+                ..Default::default()
+            },
+        )
+    };
+    let operations = [
+        created("limits"),
+        created("series"),
+        BatchOperation {
+            entity: BatchEntity::Note,
+            action: BatchAction::Move {
+                ids: [reference("limits"), reference("series")],
+                versions: [1, 1],
+            },
+            fields: BatchFields::default(),
+        },
+    ];
+    let plan = plan_batch(&stored(), &operations).unwrap();
+    // Neither row is parked, so the first one steps aside before the swap.
+    let positions = plan
+        .changes
+        .iter()
+        .map(|change| match change {
+            CatalogChange::CreateNote(note) => (None, note.position),
+            CatalogChange::UpdateNote(NoteRef::Planned(index), _, note) => {
+                (Some(*index), note.position)
+            }
+            other => panic!("unexpected change {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        positions,
+        vec![
+            (None, 0),
+            (None, 1),
+            (Some(0), 2),
+            (Some(1), 0),
+            (Some(0), 1)
+        ]
+    );
+}
+
+#[test]
+fn invalid_batch_test() {
+    let delete_algebra = BatchOperation {
+        entity: BatchEntity::Section,
+        action: BatchAction::Delete {
+            id: BatchId::Id(1),
+            version: 1,
+        },
+        fields: BatchFields::default(),
+    };
+    assert_eq!(
+        failure(&[delete_algebra]),
+        (0, BatchOperationError::InUseError)
+    );
+
+    let section = |slug: &str| BatchFields {
+        title: Some("Geometry".to_string()),
+        slug: Some(slug.to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        failure(&[create(BatchEntity::Section, None, section("analysis"))]),
+        (0, BatchOperationError::SlugTakenError)
+    );
+    assert_eq!(
+        failure(&[
+            create(BatchEntity::Section, Some("new"), section("geometry")),
+            create(BatchEntity::Section, Some("new"), section("topology")),
+        ]),
+        (1, BatchOperationError::DuplicateReferenceError)
+    );
+
+    // A ref names a row of the right entity, created by an earlier operation.
+    let subsection = |section_id: BatchId| BatchFields {
+        title: Some("Plane".to_string()),
+        section_id: Some(section_id),
+        ..Default::default()
+    };
+    assert_eq!(
+        failure(&[create(
+            BatchEntity::Subsection,
+            None,
+            subsection(reference("geometry"))
+        )]),
+        (0, BatchOperationError::NotFoundError("section_id"))
+    );
+    assert_eq!(
+        failure(&[
+            create(
+                BatchEntity::Subsection,
+                Some("plane"),
+                subsection(BatchId::Id(1))
+            ),
+            create(
+                BatchEntity::Subsection,
+                None,
+                subsection(reference("plane"))
+            ),
+        ]),
+        (1, BatchOperationError::NotFoundError("section_id"))
+    );
+
+    let update_groups = |version, fields| BatchOperation {
+        entity: BatchEntity::Note,
+        action: BatchAction::Update {
+            id: BatchId::Id(10),
+            version,
+        },
+        fields,
+    };
+    let rename = BatchFields {
+        name: Some("Groups".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        failure(&[update_groups(3, rename.clone())]),
+        (0, BatchOperationError::VersionConflictError("version"))
+    );
+    // The versions follow the earlier updates of the batch.
+    assert_eq!(
+        failure(&[
+            update_groups(1, rename.clone()),
+            update_groups(1, rename.clone()),
+        ]),
+        (1, BatchOperationError::VersionConflictError("version"))
+    );
+    assert_eq!(
+        failure(&[update_groups(1, section("groups"))]),
+        (0, BatchOperationError::UnexpectedFieldError("title"))
+    );
+    assert_eq!(
+        failure(&[update_groups(1, BatchFields::default())]),
+        (0, BatchOperationError::NothingToUpdateError)
+    );
+}
@@ -0,0 +1,179 @@
+use crate::db::catalog::{CatalogChange, NodeRef, NoteRef};
+use crate::services::catalog::{
+    plan_import, Catalog, CatalogNode, CatalogNote, ImportCatalogError, ImportMode, ImportedKind,
+};
+
+fn node(id: Option<u32>, slug: &str, position: u32) -> CatalogNode {
+    CatalogNode {
+        id,
+        kind: Some("section".to_string()),
+        title: slug.to_uppercase(),
+        slug: Some(slug.to_string()),
+        position,
+        ..Default::default()
+    }
+}
+
+fn note(id: Option<u32>, slug: &str, position: u32) -> CatalogNote {
+    CatalogNote {
+        id,
+        name: slug.to_uppercase(),
+        slug: Some(slug.to_string()),
+        url: format!("{slug}.pdf"),
+        position,
+        ..Default::default()
+    }
+}
+
+/// The stored tree: `algebra` with the notes `groups` and `rings`, then `analysis`,
+/// all at their first version.
+fn stored() -> Vec<CatalogNode> {
+    let stored_node = |id, slug, position| CatalogNode {
+        version: Some(1),
+        ..node(Some(id), slug, position)
+    };
+    let stored_note = |id, slug, position| CatalogNote {
+        version: Some(1),
+        ..note(Some(id), slug, position)
+    };
+    let mut algebra = stored_node(1, "algebra", 0);
+    algebra.notes = vec![stored_note(10, "groups", 0), stored_note(11, "rings", 1)];
+    vec![algebra, stored_node(2, "analysis", 1)]
+}
+
+fn paths(items: &[crate::services::catalog::ImportedItem]) -> Vec<&str> {
+    items.iter().map(|item| item.path.as_str()).collect()
+}
+
+#[test]
+fn import_unchanged_catalog_test() {
+    let incoming = stored()
+        .into_iter()
+        .map(|mut node| {
+            node.id = None;
+            node
+        })
+        .collect::<Vec<_>>();
+    let (changes, report) = plan_import(&stored(), &incoming, ImportMode::Replace).unwrap();
+    assert!(changes.is_empty());
+    assert!(report.created.is_empty() && report.updated.is_empty() && report.deleted.is_empty());
+}
+
+#[test]
+fn merge_import_test() {
+    let mut algebra = node(None, "algebra", 0);
+    algebra.title = "Abstract algebra".to_string();
+    // `rings` keeps its place after the imported notes.
+    algebra.notes = vec![note(None, "fields", 0), note(None, "groups", 1)];
+    let mut topology = node(None, "topology", 1);
+    topology.notes = vec![note(None, "spaces", 0)];
+
+    let (changes, report) =
+        plan_import(&stored(), &[algebra, topology], ImportMode::Merge).unwrap();
+    assert_eq!(
+        paths(&report.created),
+        vec!["algebra/fields", "topology", "topology/spaces"]
+    );
+    assert_eq!(
+        paths(&report.updated),
+        vec!["algebra", "algebra/groups", "algebra/rings", "analysis"]
+    );
+    assert!(report.deleted.is_empty());
+
+    let positions = changes
+        .iter()
+        .filter_map(|change| match change {
+            CatalogChange::UpdateNote(NoteRef::Stored(id), _, note) => Some((*id, note.position)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(positions, vec![(10, 1), (11, 2)]);
+    // The note of the new node hangs from it.
+    let spaces = changes.iter().find_map(|change| match change {
+        CatalogChange::CreateNote(note) if note.slug == "spaces" => Some(note.node),
+        _ => None,
+    });
+    assert_eq!(spaces, Some(NodeRef::Planned(0)));
+}
+
+#[test]
+fn replace_import_test() {
+    // `algebra` was renamed `algebra-i` where the catalog comes from.
+    let mut algebra = node(None, "algebra-i", 0);
+    algebra.title = "ALGEBRA".to_string();
+    algebra.previous_slugs = vec!["algebra".to_string()];
+    algebra.notes = vec![note(None, "groups", 0)];
+
+    let (changes, report) = plan_import(&stored(), &[algebra], ImportMode::Replace).unwrap();
+    assert!(report.created.is_empty());
+    assert_eq!(paths(&report.updated), vec!["algebra-i"]);
+    assert_eq!(paths(&report.deleted), vec!["algebra-i/rings", "analysis"]);
+    assert_eq!(report.deleted[1].kind, ImportedKind::Node);
+    assert!(changes.contains(&CatalogChange::DeleteNote(NoteRef::Stored(11), 1)));
+    assert!(changes.contains(&CatalogChange::DeleteNode(NodeRef::Stored(2), 1)));
+    match changes.first() {
+        Some(CatalogChange::UpdateNode(NodeRef::Stored(1), 1, node)) => {
+            assert_eq!(node.slug, "algebra-i");
+            assert_eq!(node.previous_slugs, vec!["algebra"]);
+        }
+        other => panic!("unexpected change {:?}", other),
+    }
+}
+
+#[test]
+fn invalid_import_test() {
+    let res = plan_import(
+        &stored(),
+        &[node(None, "algebra", 0), node(None, "algebra", 1)],
+        ImportMode::Merge,
+    );
+    assert!(matches!(res, Err(ImportCatalogError::DuplicateSlugError(path)) if path == "algebra"));
+
+    let mut algebra = node(None, "algebra", 0);
+    algebra.notes = vec![note(None, "Not a slug", 0)];
+    let res = plan_import(&stored(), &[algebra], ImportMode::Merge);
+    assert!(
+        matches!(res, Err(ImportCatalogError::InvalidSlugError(path)) if path == "algebra/Not a slug")
+    );
+}
+
+#[test]
+fn catalog_json_test() {
+    let catalog: Catalog = serde_json::from_str(
+        r#"{
+            "format": "lecture-notes-catalog",
+            "version": 1,
+            "sections": [{
+                "title": "Linear Algebra",
+                "children": [{"title": "Matrices", "status": "draft"}],
+                "notes": [{"name": "Intro", "url": "intro.pdf", "kind": "lecture", "tags": ["exam"]}]
+            }]
+        }"#,
+    )
+    .unwrap();
+    let (changes, report) = plan_import(&[], &catalog.sections, ImportMode::Merge).unwrap();
+    assert_eq!(
+        paths(&report.created),
+        vec![
+            "linear-algebra",
+            "linear-algebra/intro",
+            "linear-algebra/matrices"
+        ]
+    );
+    match &changes[..] {
+        [CatalogChange::CreateNode(section), CatalogChange::CreateNote(note), CatalogChange::CreateNode(matrices)] =>
+        {
+            assert_eq!(section.kind, "section");
+            assert_eq!(note.metadata.kind.as_deref(), Some("lecture"));
+            assert_eq!(note.tags, vec!["exam"]);
+            assert_eq!(matrices.kind, "subsection");
+            assert_eq!(matrices.status, "draft");
+            assert_eq!(matrices.parent, Some(NodeRef::Planned(0)));
+        }
+        other => panic!("unexpected changes {:?}", other),
+    }
+
+    let exported = serde_json::to_value(&catalog.sections[0].notes[0]).unwrap();
+    assert_eq!(exported["kind"], "lecture");
+    assert!(exported.get("id").is_none());
+}
@@ -0,0 +1,62 @@
+use crate::services::files::{
+    parse_range, resolve_mime_type, ByteRange, FileStorageConfig, RangeError,
+};
+
+#[test]
+fn parse_range_test() {
+    let range = |start, end| Ok(Some(ByteRange { start, end }));
+
+    assert_eq!(parse_range(None, 100), Ok(None));
+    assert_eq!(parse_range(Some("bytes=0-9"), 100), range(0, 9));
+    assert_eq!(parse_range(Some("bytes=90-"), 100), range(90, 99));
+    assert_eq!(parse_range(Some("bytes=90-500"), 100), range(90, 99));
+    assert_eq!(parse_range(Some("bytes=-10"), 100), range(90, 99));
+    assert_eq!(parse_range(Some("bytes=-500"), 100), range(0, 99));
+
+    // Unsupported or malformed ranges fall back to the whole file.
+    assert_eq!(parse_range(Some("items=0-9"), 100), Ok(None));
+    assert_eq!(parse_range(Some("bytes=0-9,20-29"), 100), Ok(None));
+    assert_eq!(parse_range(Some("bytes=9-0"), 100), Ok(None));
+    assert_eq!(parse_range(Some("bytes=a-b"), 100), Ok(None));
+
+    assert_eq!(
+        parse_range(Some("bytes=100-"), 100),
+        Err(RangeError::NotSatisfiableError)
+    );
+    assert_eq!(
+        parse_range(Some("bytes=-0"), 100),
+        Err(RangeError::NotSatisfiableError)
+    );
+}
+
+#[test]
+fn mime_type_test() {
+    assert_eq!(
+        resolve_mime_type(Some("application/pdf; charset=binary"), "a.bin"),
+        "application/pdf"
+    );
+    assert_eq!(
+        resolve_mime_type(Some("application/octet-stream"), "slides.PPTX"),
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+    );
+    assert_eq!(
+        resolve_mime_type(None, "noextension"),
+        "application/octet-stream"
+    );
+
+    let config = FileStorageConfig {
+        root: "uploads".into(),
+        max_size: 10,
+        allowed_mime_types: vec!["application/pdf".to_string(), "video/*".to_string()],
+    };
+    assert!(config.is_allowed("application/pdf"));
+    assert!(config.is_allowed("video/mp4"));
+    assert!(!config.is_allowed("videos/mp4"));
+    assert!(!config.is_allowed("application/zip"));
+    assert_eq!(
+        config.content_path(&"ab".repeat(32)),
+        std::path::Path::new("uploads")
+            .join("ab")
+            .join("ab".repeat(32))
+    );
+}
@@ -0,0 +1,330 @@
+use crate::repository::Storage;
+use crate::services::lecture_notes::{
+    create_note, get_note, get_notes, move_note, update_note, CreateNoteForm, GetNoteError,
+    GetNotesForm, MoveNoteError, NoteKind, NoteMetadata, UpdateNoteError, UpdateNoteForm,
+};
+use crate::services::publishing::{Status, Visibility};
+use crate::services::sections::{
+    create_section, update_section, CreateSectionForm, UpdateSectionForm,
+};
+use crate::services::subsections::{create_subsection, CreateSubsectionForm};
+
+fn notes_form() -> GetNotesForm {
+    GetNotesForm {
+        id: None,
+        name: None,
+        url: None,
+        position: None,
+        node_id: None,
+        section_id: None,
+        subsection_id: None,
+        kind: None,
+        course_code: None,
+        term: None,
+        language: None,
+        author: None,
+        license: None,
+        tags: Vec::new(),
+        tag_match: Default::default(),
+        page: Default::default(),
+        ..Default::default()
+    }
+}
+
+fn note_form(name: &str, section_id: Option<u32>, subsection_id: Option<u32>) -> CreateNoteForm {
+    CreateNoteForm {
+        name: name.to_string(),
+        description: String::new(),
+        url: format!("https://example.com/{}.pdf", name),
+        node_id: None,
+        section_id,
+        subsection_id,
+        metadata: NoteMetadata::default(),
+        ..Default::default()
+    }
+}
+
+async fn storage_with_tree() -> Storage {
+    let storage = Storage::memory();
+    let _ = create_section(
+        &storage,
+        CreateSectionForm {
+            title: "section".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    for title in ["first", "second"] {
+        let _ = create_subsection(
+            &storage,
+            CreateSubsectionForm {
+                title: title.to_string(),
+                section_id: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+    storage
+}
+
+#[tokio::test]
+async fn notes_service_test() {
+    let storage = storage_with_tree().await;
+
+    let res = create_note(&storage, note_form("intro", Some(1), None)).await;
+    assert!(res.is_ok());
+    let res = create_note(
+        &storage,
+        CreateNoteForm {
+            metadata: NoteMetadata {
+                kind: Some(NoteKind::Exam),
+                authors: vec!["Ada".to_string()],
+                ..Default::default()
+            },
+            ..note_form("exam", None, Some(2))
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = create_note(&storage, note_form("sheet", None, Some(2))).await;
+    assert!(res.is_ok());
+    let res = create_note(&storage, note_form("other", None, Some(3))).await;
+    assert!(res.is_ok());
+    // The node foreign key is enforced.
+    let res = create_note(&storage, note_form("orphan", None, Some(99))).await;
+    assert!(res.is_err());
+
+    let intro = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    assert_eq!((intro.section_id, intro.subsection_id), (Some(1), None));
+    let exam = get_note(&storage, 2, Visibility::Everything).await.unwrap();
+    assert_eq!((exam.section_id, exam.subsection_id), (Some(1), Some(2)));
+    assert_eq!(exam.metadata.kind, Some(NoteKind::Exam));
+
+    let notes = get_notes(
+        &storage,
+        GetNotesForm {
+            section_id: Some(1),
+            ..notes_form()
+        },
+    )
+    .await
+    .unwrap()
+    .items;
+    assert_eq!(notes.len(), 4);
+    let notes = get_notes(
+        &storage,
+        GetNotesForm {
+            author: Some("Ada".to_string()),
+            ..notes_form()
+        },
+    )
+    .await
+    .unwrap()
+    .items;
+    assert_eq!(
+        notes.into_iter().map(|note| note.name).collect::<Vec<_>>(),
+        vec!["exam".to_string()]
+    );
+
+    let res = move_note(&storage, [2, 3], [1, 1]).await;
+    assert!(res.is_ok());
+    assert_eq!(
+        get_note(&storage, 2, Visibility::Everything)
+            .await
+            .unwrap()
+            .position,
+        1
+    );
+    let res = move_note(&storage, [2, 3], [1, 1]).await;
+    assert!(matches!(res, Err(MoveNoteError::VersionConflictError(_))));
+    let res = move_note(&storage, [2, 4], [2, 1]).await;
+    assert!(matches!(
+        res,
+        Err(MoveNoteError::CantSwapFromDifferentSubsections)
+    ));
+
+    let res = update_note(
+        &storage,
+        4,
+        UpdateNoteForm {
+            name: None,
+            description: None,
+            url: None,
+            node_id: None,
+            section_id: None,
+            subsection_id: Some(Some(2)),
+            position: Some(0),
+            kind: None,
+            course_code: None,
+            term: None,
+            language: None,
+            authors: None,
+            license: None,
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    // Position 0 of the second subsection is already taken.
+    assert!(res.is_err());
+    assert_eq!(
+        get_note(&storage, 4, Visibility::Everything)
+            .await
+            .unwrap()
+            .subsection_id,
+        Some(3)
+    );
+
+    let res = get_note(&storage, 42, Visibility::Everything).await;
+    assert!(matches!(res, Err(GetNoteError::NotFoundError)));
+}
+
+#[tokio::test]
+async fn publication_test() {
+    let storage = storage_with_tree().await;
+    let tomorrow = crate::db::now() + 24 * 60 * 60;
+    let _ = create_section(
+        &storage,
+        CreateSectionForm {
+            title: "draft section".to_string(),
+            status: Status::Draft,
+            ..Default::default()
+        },
+    )
+    .await;
+    let notes = [
+        note_form("published", Some(1), None),
+        CreateNoteForm {
+            status: Status::Draft,
+            ..note_form("draft", Some(1), None)
+        },
+        CreateNoteForm {
+            status: Status::Archived,
+            ..note_form("archived", None, Some(2))
+        },
+        CreateNoteForm {
+            publish_at: Some(tomorrow),
+            ..note_form("scheduled", None, Some(3))
+        },
+        // Hidden along with its section.
+        note_form("under draft", Some(4), None),
+    ];
+    for form in notes {
+        assert!(create_note(&storage, form).await.is_ok());
+    }
+
+    let names = |visibility| {
+        let storage = &storage;
+        async move {
+            let form = GetNotesForm {
+                visibility,
+                ..notes_form()
+            };
+            let page = get_notes(storage, form).await.unwrap();
+            page.items
+                .into_iter()
+                .map(|note| note.name)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(names(Visibility::Public).await, vec!["published"]);
+    assert_eq!(names(Visibility::Everything).await.len(), 5);
+
+    let draft = get_note(&storage, 2, Visibility::Public).await;
+    assert!(matches!(draft, Err(GetNoteError::NotFoundError)));
+    let draft = get_note(&storage, 2, Visibility::Everything).await.unwrap();
+    assert_eq!(draft.status, Status::Draft);
+
+    // Publishing the section shows its note, and a past time shows the scheduled one.
+    let res = update_section(
+        &storage,
+        4,
+        UpdateSectionForm {
+            status: Some(Status::Published),
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let res = update_note(
+        &storage,
+        4,
+        UpdateNoteForm {
+            publish_at: Some(Some(crate::db::now() - 1)),
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    assert_eq!(
+        names(Visibility::Public).await,
+        vec!["published", "scheduled", "under draft"]
+    );
+}
+
+#[tokio::test]
+async fn merge_update_test() {
+    let storage = storage_with_tree().await;
+    let _ = create_section(
+        &storage,
+        CreateSectionForm {
+            title: "other".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let form = CreateNoteForm {
+        metadata: NoteMetadata {
+            course_code: Some("MATH101".to_string()),
+            ..Default::default()
+        },
+        ..note_form("groups", None, Some(2))
+    };
+    assert!(create_note(&storage, form).await.is_ok());
+
+    // The subsection 2 is not in the section 4, so the note cannot stay in it.
+    let form = UpdateNoteForm {
+        section_id: Some(4),
+        version: 1,
+        ..Default::default()
+    };
+    let res = update_note(&storage, 1, form).await;
+    assert!(matches!(res, Err(UpdateNoteError::ParentMismatchError)));
+    // Its own section leaves it in its subsection.
+    let form = UpdateNoteForm {
+        section_id: Some(1),
+        name: Some("rings".to_string()),
+        version: 1,
+        ..Default::default()
+    };
+    assert!(update_note(&storage, 1, form).await.is_ok());
+    let note = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    assert_eq!((note.section_id, note.subsection_id), (Some(1), Some(2)));
+
+    // Clearing the subsection moves the note up to the section.
+    let form = UpdateNoteForm {
+        subsection_id: Some(None),
+        course_code: Some(None),
+        version: 2,
+        ..Default::default()
+    };
+    assert!(update_note(&storage, 1, form).await.is_ok());
+    let note = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    assert_eq!((note.section_id, note.subsection_id), (Some(1), None));
+    assert_eq!(note.metadata.course_code, None);
+
+    // A subsection is not a section, with the subsection cleared or not.
+    for subsection_id in [Some(None), None] {
+        let form = UpdateNoteForm {
+            section_id: Some(2),
+            subsection_id,
+            version: 3,
+            ..Default::default()
+        };
+        let res = update_note(&storage, 1, form).await;
+        assert!(matches!(res, Err(UpdateNoteError::ParentMismatchError)));
+    }
+}
@@ -0,0 +1,12 @@
+mod attachments;
+mod batch;
+mod catalog;
+mod files;
+mod lecture_notes;
+mod note_import;
+mod pagination;
+mod search;
+mod sections;
+mod tags;
+mod tree;
+mod users;
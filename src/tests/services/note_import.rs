@@ -0,0 +1,254 @@
+use crate::error::FieldError;
+use crate::repository::Storage;
+use crate::services::lecture_notes::{create_note, get_notes, CreateNoteForm, GetNotesForm};
+use crate::services::note_import::{
+    import_notes, parse_rows, ImportNotesReport, NoteFileFormat, NoteRow, ParseNotesError, RowError,
+};
+use crate::services::publishing::Visibility;
+use crate::services::sections::{create_section, get_sections, CreateSectionForm, GetSectionsForm};
+use crate::services::subsections::{get_subsections, GetSubsectionsForm};
+
+fn row(section: &str, subsection: Option<&str>, name: &str, url: &str) -> NoteRow {
+    NoteRow {
+        section: section.to_string(),
+        subsection: subsection.map(str::to_string),
+        name: name.to_string(),
+        description: String::new(),
+        url: url.to_string(),
+    }
+}
+
+#[test]
+fn parse_csv_test() {
+    let text = "section,subsection,name,description,url\n\
+                Algebra,,Groups,Definitions,groups.pdf\n\
+                Algebra,Rings,Ideals,,ideals.pdf\n\
+                Algebra,Rings\n\
+                \"Analysis, I\",Limits,Sequences,,sequences.pdf\n";
+    let rows = parse_rows(NoteFileFormat::Csv, text).unwrap();
+    let lines = rows.iter().map(|parsed| parsed.line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![2, 3, 4, 5]);
+    let mut groups = row("Algebra", None, "Groups", "groups.pdf");
+    groups.description = "Definitions".to_string();
+    assert_eq!(rows[0].row, Ok(groups));
+    assert_eq!(
+        rows[1].row,
+        Ok(row("Algebra", Some("Rings"), "Ideals", "ideals.pdf"))
+    );
+    assert!(rows[2].row.is_err());
+    assert_eq!(
+        rows[3].row,
+        Ok(row(
+            "Analysis, I",
+            Some("Limits"),
+            "Sequences",
+            "sequences.pdf"
+        ))
+    );
+
+    // The optional columns can be left out, the required ones cannot.
+    let rows = parse_rows(NoteFileFormat::Csv, "url,name,section\na.pdf,A,S\n").unwrap();
+    assert_eq!(rows[0].row, Ok(row("S", None, "A", "a.pdf")));
+    let res = parse_rows(NoteFileFormat::Csv, "section,name\nS,A\n");
+    assert!(matches!(res, Err(ParseNotesError::InvalidFileError(_))));
+}
+
+#[test]
+fn parse_yaml_test() {
+    let text = "# Spring term\n\
+                ---\n\
+                - section: Algebra\n\
+                \x20 name: Groups\n\
+                \x20 url: groups.pdf\n\
+                - section: Algebra\n\
+                \x20 subsection: Rings\n\
+                \x20 name: Ideals\n\
+                \x20 description: >\n\
+                \x20   Prime and maximal ideals\n\
+                \x20 url: ideals.pdf\n\
+                - section: Algebra\n\
+                \x20 name: Modules\n\
+                - section: Algebra\n\
+                \x20 name: [Fields\n\
+                - section: Algebra\n";
+    let rows = parse_rows(NoteFileFormat::Yaml, text).unwrap();
+    let lines = rows.iter().map(|parsed| parsed.line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![3, 6, 12, 14]);
+    assert_eq!(
+        rows[0].row,
+        Ok(row("Algebra", None, "Groups", "groups.pdf"))
+    );
+    let ideals = rows[1].row.as_ref().unwrap();
+    assert_eq!(ideals.subsection.as_deref(), Some("Rings"));
+    assert_eq!(ideals.description, "Prime and maximal ideals\n");
+    // The missing url, then the unclosed flow sequence ending the reading.
+    assert!(rows[2].row.as_ref().unwrap_err().contains("url"));
+    assert!(rows[3]
+        .row
+        .as_ref()
+        .unwrap_err()
+        .ends_with("the rows after it cannot be read"));
+
+    // Indented and flow style lists keep the lines of their rows.
+    let text = "\n  - section: Algebra\n    name: Groups\n    url: groups.pdf\n\
+                \x20 - {section: Algebra, subsection: ~, name: Rings, url: rings.pdf}\n";
+    let rows = parse_rows(NoteFileFormat::Yaml, text).unwrap();
+    let lines = rows.iter().map(|parsed| parsed.line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![2, 5]);
+    assert_eq!(rows[1].row, Ok(row("Algebra", None, "Rings", "rings.pdf")));
+    let text = "[{section: Algebra, name: Groups, url: groups.pdf},\n {section: Algebra}]\n";
+    let rows = parse_rows(NoteFileFormat::Yaml, text).unwrap();
+    let lines = rows.iter().map(|parsed| parsed.line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![1, 2]);
+    assert_eq!(
+        rows[0].row,
+        Ok(row("Algebra", None, "Groups", "groups.pdf"))
+    );
+
+    let res = parse_rows(NoteFileFormat::Yaml, "rows:\n  - section: Algebra\n");
+    assert!(matches!(res, Err(ParseNotesError::InvalidFileError(_))));
+}
+
+#[test]
+fn note_file_format_test() {
+    assert_eq!(
+        NoteFileFormat::from_path("notes.CSV"),
+        Some(NoteFileFormat::Csv)
+    );
+    assert_eq!(
+        NoteFileFormat::from_path("a/b.yml"),
+        Some(NoteFileFormat::Yaml)
+    );
+    assert_eq!(NoteFileFormat::from_path("notes.json"), None);
+    assert_eq!(
+        NoteFileFormat::from_content_type("text/csv; charset=utf-8"),
+        Some(NoteFileFormat::Csv)
+    );
+    assert_eq!(
+        NoteFileFormat::from_content_type("application/yaml"),
+        Some(NoteFileFormat::Yaml)
+    );
+    assert_eq!(NoteFileFormat::from_content_type("application/json"), None);
+}
+
+#[tokio::test]
+async fn import_notes_test() {
+    let storage = Storage::memory();
+    create_section(
+        &storage,
+        CreateSectionForm {
+            title: "Algebra".to_string(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    create_note(
+        &storage,
+        CreateNoteForm {
+            name: "Old groups".to_string(),
+            url: "https://example.com/groups.pdf".to_string(),
+            section_id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let text = "section,subsection,name,description,url\n\
+                Analysis,,Limits,,https://example.com/limits.pdf\n\
+                Algebra,Rings,Groups,Updated,https://example.com/groups.pdf\n\
+                Algebra,Rings,,,https://example.com/nameless.pdf\n\
+                Algebra,Rings,Ideals,,https://example.com/ideals.pdf\n\
+                Geometry,Plane,Lines,,https://example.com/lines.pdf\n\
+                Geometry,Plane,Script,,javascript:alert(1)\n";
+    let rows = parse_rows(NoteFileFormat::Csv, text).unwrap();
+    let report = import_notes(&storage, rows).await.unwrap();
+    assert_eq!(
+        report,
+        ImportNotesReport {
+            rows: 6,
+            created_sections: vec!["Analysis".to_string(), "Geometry".to_string()],
+            created_subsections: vec![
+                "Algebra / Rings".to_string(),
+                "Geometry / Plane".to_string()
+            ],
+            created_notes: 3,
+            updated_notes: 1,
+            errors: vec![
+                RowError {
+                    line: 4,
+                    message: "the `name` cannot be empty".to_string(),
+                    details: vec![FieldError {
+                        field: "name".to_string(),
+                        message: "cannot be empty".to_string(),
+                    }],
+                },
+                RowError {
+                    line: 7,
+                    message: "the `url` must be an http or https URL".to_string(),
+                    details: vec![FieldError {
+                        field: "url".to_string(),
+                        message: "must be an http or https URL".to_string(),
+                    }],
+                }
+            ],
+        }
+    );
+
+    let sections = get_sections(&storage, GetSectionsForm::default())
+        .await
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|section| (section.title, section.position))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sections,
+        vec![
+            ("Algebra".to_string(), 0),
+            ("Analysis".to_string(), 1),
+            ("Geometry".to_string(), 2)
+        ]
+    );
+    let rings = get_subsections(
+        &storage,
+        GetSubsectionsForm {
+            title: Some("Rings".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap()
+    .items;
+    assert_eq!(rings.len(), 1);
+    // The note matched by URL moved under the new subsection, before the new note.
+    let notes = get_notes(
+        &storage,
+        GetNotesForm {
+            subsection_id: Some(rings[0].id),
+            visibility: Visibility::Everything,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap()
+    .items
+    .into_iter()
+    .map(|note| (note.id, note.name, note.description))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        vec![
+            (1, "Groups".to_string(), "Updated".to_string()),
+            (3, "Ideals".to_string(), String::new())
+        ]
+    );
+
+    // Importing the same file again only updates the notes.
+    let rows = parse_rows(NoteFileFormat::Csv, text).unwrap();
+    let report = import_notes(&storage, rows).await.unwrap();
+    assert!(report.created_sections.is_empty());
+    assert!(report.created_subsections.is_empty());
+    assert_eq!((report.created_notes, report.updated_notes), (0, 4));
+}
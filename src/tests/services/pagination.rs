@@ -0,0 +1,182 @@
+use crate::db::query::{Direction, Filter, OrderBy, Value};
+use crate::repository::Storage;
+use crate::services::pagination::{Cursor, PageError, PageRequest, Sort, SortField};
+use crate::services::sections::{
+    create_section, get_sections, CreateSectionForm, GetSectionsError, GetSectionsForm,
+    SectionReturn,
+};
+use crate::services::users::UserReturn;
+
+#[test]
+fn sort_parse_test() {
+    assert_eq!(Sort::parse("name"), Some(Sort::asc(SortField::Name)));
+    assert_eq!(
+        Sort::parse("-created_at"),
+        Some(Sort::desc(SortField::CreatedAt))
+    );
+    assert_eq!(Sort::parse("title"), None);
+    assert_eq!(Sort::parse("--id"), None);
+    assert_eq!(Sort::desc(SortField::Position).to_param(), "-position");
+}
+
+#[test]
+fn cursor_encoding_test() {
+    let cursor = Cursor {
+        sort: "-name".to_string(),
+        value: Value::Text("Algebra & \"Groups\"".to_string()),
+        id: 7,
+    };
+    let encoded = cursor.encode();
+
+    assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(Cursor::decode(&encoded), Some(cursor));
+    assert_eq!(Cursor::decode("zz"), None);
+    assert_eq!(Cursor::decode("7b7"), None);
+    assert_eq!(Cursor::decode("7b7d"), None);
+}
+
+#[test]
+fn page_request_clauses_test() {
+    let request = PageRequest {
+        sort: Some(Sort::desc(SortField::Name)),
+        limit: Some(10),
+        cursor: Some(Cursor {
+            sort: "-name".to_string(),
+            value: "m".into(),
+            id: 4,
+        }),
+        ..Default::default()
+    };
+    let clauses = request.clauses::<SectionReturn>().unwrap();
+
+    // One more row than asked, to know whether another page follows.
+    assert_eq!(clauses.limit, Some(11));
+    assert_eq!(
+        clauses.order_by,
+        vec![OrderBy::desc("title"), OrderBy::desc("id")]
+    );
+    assert_eq!(
+        clauses.filter,
+        Filter::Or(vec![
+            Filter::Lt("title", "m".into()),
+            Filter::And(vec![
+                Filter::Eq("title", "m".into()),
+                Filter::Lt("id", 4_u32.into())
+            ]),
+        ])
+    );
+
+    let default = PageRequest::default().clauses::<UserReturn>().unwrap();
+    assert_eq!(default.order_by[0].direction, Direction::Asc);
+    assert_eq!(default.order_by, vec![OrderBy::asc("id")]);
+
+    let by_position = PageRequest {
+        sort: Some(Sort::asc(SortField::Position)),
+        ..Default::default()
+    };
+    assert_eq!(
+        by_position.clauses::<UserReturn>(),
+        Err(PageError::UnsupportedSortError(SortField::Position))
+    );
+    let mismatch = PageRequest {
+        cursor: request.cursor.clone(),
+        ..Default::default()
+    };
+    assert_eq!(
+        mismatch.clauses::<SectionReturn>(),
+        Err(PageError::CursorMismatchError)
+    );
+    let both = PageRequest {
+        offset: Some(10),
+        ..request
+    };
+    assert_eq!(
+        both.clauses::<SectionReturn>(),
+        Err(PageError::CursorWithOffsetError)
+    );
+}
+
+fn sections_page(page: PageRequest) -> GetSectionsForm {
+    GetSectionsForm {
+        id: None,
+        title: None,
+        position: None,
+        page,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn sections_pages_test() {
+    let storage = Storage::memory();
+    for title in ["delta", "alpha", "echo", "charlie", "bravo"] {
+        let res = create_section(
+            &storage,
+            CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+
+    let mut request = PageRequest {
+        sort: Some(Sort::asc(SortField::Name)),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let mut titles = Vec::new();
+    loop {
+        let page = get_sections(&storage, sections_page(request.clone()))
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        titles.extend(page.items.into_iter().map(|section| section.title));
+        match page.next_cursor {
+            Some(cursor) => request.cursor = Cursor::decode(&cursor),
+            None => break,
+        }
+    }
+    assert_eq!(titles, vec!["alpha", "bravo", "charlie", "delta", "echo"]);
+
+    let page = get_sections(
+        &storage,
+        sections_page(PageRequest {
+            sort: Some(Sort::desc(SortField::Position)),
+            limit: Some(2),
+            offset: Some(3),
+            ..Default::default()
+        }),
+    )
+    .await
+    .unwrap();
+    let titles = page
+        .items
+        .iter()
+        .map(|section| section.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["alpha", "delta"]);
+    assert_eq!(page.total, 5);
+    assert_eq!(page.next_cursor, None);
+
+    let res = get_sections(
+        &storage,
+        sections_page(PageRequest {
+            cursor: Cursor::decode(
+                &Cursor {
+                    sort: "name".to_string(),
+                    value: "bravo".into(),
+                    id: 5,
+                }
+                .encode(),
+            ),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert!(matches!(
+        res,
+        Err(GetSectionsError::PageError(PageError::CursorMismatchError))
+    ));
+}
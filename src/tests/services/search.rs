@@ -0,0 +1,152 @@
+use crate::services::publishing::Status;
+use crate::services::search::{snippet, tokenize, Document, DocumentKind, SearchForm, SearchIndex};
+use crate::services::sections::{
+    create_section, update_section, CreateSectionForm, UpdateSectionForm,
+};
+
+fn note(id: u32, title: &str, description: &str, tags: &[&str], parents: &[&str]) -> Document {
+    Document {
+        kind: DocumentKind::Note,
+        id,
+        title: title.to_string(),
+        description: description.to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        parents: parents.iter().map(|parent| parent.to_string()).collect(),
+        url: Some(format!("https://example.com/{}.pdf", id)),
+        section_id: None,
+        subsection_id: None,
+        published_from: Some(i64::MIN),
+    }
+}
+
+fn search(index: &SearchIndex, query: &str) -> Vec<u32> {
+    index
+        .search(&SearchForm {
+            query: query.to_string(),
+            kind: None,
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        })
+        .hits
+        .into_iter()
+        .map(|hit| hit.id)
+        .collect()
+}
+
+#[test]
+fn tokenize_test() {
+    assert_eq!(
+        tokenize("Théorème de l'Hôpital, ŒUVRES!"),
+        vec!["theoreme", "de", "l", "hopital", "oeuvres"]
+    );
+    // Decomposed accents fold like the composed ones.
+    assert_eq!(tokenize("Cafe\u{301} Straße"), vec!["cafe", "strasse"]);
+    assert_eq!(
+        tokenize("Erdős, Tűz, Știință, Ångström"),
+        vec!["erdos", "tuz", "stiinta", "angstrom"]
+    );
+    assert_eq!(
+        tokenize("Ґрунтознавство, мʼята та Її"),
+        vec!["грунтознавство", "м", "ята", "та", "її"]
+    );
+    assert!(tokenize(" -- ").is_empty());
+}
+
+#[test]
+fn snippet_test() {
+    let query = tokenize("groupe");
+    let text = "Une introduction aux <groupes> finis : définitions, exemples et \
+                premières propriétés des sous-groupes, suivie d'exercices corrigés";
+    let shown = snippet(text, &query).unwrap();
+    assert!(shown.starts_with("Une introduction aux &lt;<mark>groupes</mark>&gt;"));
+    assert!(shown.contains("sous-<mark>groupes</mark>"));
+    assert!(shown.ends_with('…'));
+
+    assert_eq!(snippet("Rien à voir", &query), None);
+}
+
+#[test]
+fn search_ranking_test() {
+    let index = SearchIndex::default();
+    index.replace(vec![
+        note(
+            1,
+            "Algèbre linéaire",
+            "Espaces vectoriels",
+            &["maths"],
+            &["Mathématiques"],
+        ),
+        note(
+            2,
+            "Analyse",
+            "Suites, séries et un peu d'algèbre",
+            &[],
+            &["Mathématiques"],
+        ),
+        note(
+            3,
+            "Лінійна алгебра",
+            "Векторні простори",
+            &["ґратки"],
+            &["Математика"],
+        ),
+        note(4, "Mechanics", "Newton's laws", &["algebra"], &["Physics"]),
+    ]);
+    assert_eq!(index.len(), 4);
+
+    // A match in the title outranks one in the description.
+    assert_eq!(search(&index, "algebre"), vec![1, 2]);
+    assert_eq!(search(&index, "ALGÈBRE maths"), vec![1]);
+    assert_eq!(search(&index, "алгебра"), vec![3]);
+    assert_eq!(search(&index, "гратки"), vec![3]);
+    // Prefixes match from three letters on.
+    assert_eq!(search(&index, "vecto"), vec![1]);
+    assert_eq!(search(&index, "al"), Vec::<u32>::new());
+    assert_eq!(search(&index, "mathematiques analyse"), vec![2]);
+    assert_eq!(search(&index, "physics algebra"), vec![4]);
+
+    index.insert(note(2, "Analyse réelle", "Intégrales", &[], &[]));
+    assert_eq!(search(&index, "algebre"), vec![1]);
+    index.remove(DocumentKind::Note, 1);
+    assert_eq!(search(&index, "algebre"), Vec::<u32>::new());
+    assert_eq!(index.len(), 3);
+
+    let results = index.search(&SearchForm {
+        query: "Інтеграли intégrales".to_string(),
+        kind: Some(DocumentKind::Section),
+        limit: 10,
+        offset: 0,
+        ..Default::default()
+    });
+    assert_eq!(results.total, 0);
+}
+
+#[tokio::test]
+async fn refresh_test() {
+    let storage = crate::repository::Storage::memory();
+    let index = SearchIndex::default();
+    let section = CreateSectionForm {
+        title: "Algèbre".to_string(),
+        status: Status::Published,
+        publish_at: None,
+        slug: None,
+    };
+    assert!(create_section(&storage, section).await.is_ok());
+    assert!(index.refresh(&storage).await.is_ok());
+    assert_eq!(search(&index, "algebre"), vec![1]);
+
+    // A write through the services is found by the next refresh.
+    let form = UpdateSectionForm {
+        title: Some("Géométrie".to_string()),
+        status: None,
+        publish_at: None,
+        slug: None,
+        version: 1,
+    };
+    assert!(update_section(&storage, 1, form).await.is_ok());
+    assert_eq!(search(&index, "geometrie"), Vec::<u32>::new());
+    assert!(index.refresh(&storage).await.is_ok());
+    assert_eq!(search(&index, "geometrie"), vec![1]);
+    assert_eq!(search(&index, "algebre"), Vec::<u32>::new());
+}
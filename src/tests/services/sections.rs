@@ -0,0 +1,249 @@
+use crate::repository::Storage;
+use crate::services::publishing::Visibility;
+use crate::services::sections::{
+    create_section, delete_section, get_section, get_sections, move_section, update_section,
+    CreateSectionError, CreateSectionForm, GetSectionError, GetSectionsForm, MoveSectionError,
+    UpdateSectionError, UpdateSectionForm,
+};
+use crate::services::subsections::{
+    create_subsection, get_subsection, move_subsection, update_subsection, CreateSubsectionForm,
+    MoveSubsectionError, UpdateSubsectionForm,
+};
+
+fn all_sections() -> GetSectionsForm {
+    GetSectionsForm {
+        id: None,
+        title: None,
+        position: None,
+        page: Default::default(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn sections_service_test() {
+    let storage = Storage::memory();
+
+    for title in ["first", "second", "third"] {
+        let res = create_section(
+            &storage,
+            CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    let sections = get_sections(&storage, all_sections()).await.unwrap().items;
+    let positions = sections
+        .iter()
+        .map(|section| (section.id, section.position))
+        .collect::<Vec<_>>();
+    assert_eq!(positions, vec![(1, 0), (2, 1), (3, 2)]);
+
+    let res = move_section(&storage, [1, 3], [1, 1]).await;
+    assert!(res.is_ok());
+    let first = get_section(&storage, 1, Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!(first.position, 2);
+    assert_eq!(first.version, 2);
+    // The versions read before the move are stale now.
+    let res = move_section(&storage, [1, 3], [1, 1]).await;
+    match res {
+        Err(MoveSectionError::VersionConflictError(current)) => {
+            let versions = current
+                .iter()
+                .map(|section| section.version)
+                .collect::<Vec<_>>();
+            assert_eq!(versions, vec![2, 2]);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    let res = move_section(&storage, [1, 7], [2, 1]).await;
+    assert!(matches!(
+        res,
+        Err(MoveSectionError::NotFoundError(None, Some(7)))
+    ));
+
+    let res = update_section(
+        &storage,
+        2,
+        UpdateSectionForm {
+            title: None,
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(matches!(res, Err(UpdateSectionError::NothingToUpdateError)));
+    let res = update_section(
+        &storage,
+        9,
+        UpdateSectionForm {
+            title: Some("missing".to_string()),
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(matches!(res, Err(UpdateSectionError::NotFoundError)));
+
+    let rename = |version| UpdateSectionForm {
+        title: Some("renamed".to_string()),
+        version,
+        ..Default::default()
+    };
+    assert!(update_section(&storage, 2, rename(1)).await.is_ok());
+    match update_section(&storage, 2, rename(1)).await {
+        Err(UpdateSectionError::VersionConflictError(current)) => {
+            assert_eq!(current.title, "renamed");
+            assert_eq!(current.version, 2);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let res = delete_section(&storage, 2).await;
+    assert!(res.is_ok());
+    let res = get_section(&storage, 2, Visibility::Everything).await;
+    assert!(matches!(res, Err(GetSectionError::NotFoundError)));
+}
+
+#[tokio::test]
+async fn subsections_service_test() {
+    let storage = Storage::memory();
+
+    for title in ["first", "second"] {
+        let _ = create_section(
+            &storage,
+            CreateSectionForm {
+                title: title.to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+    for section_id in [1, 1, 2] {
+        let res = create_subsection(
+            &storage,
+            CreateSubsectionForm {
+                title: format!("child of {}", section_id),
+                section_id,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    // The parent foreign key is enforced.
+    let res = create_subsection(
+        &storage,
+        CreateSubsectionForm {
+            title: "orphan".to_string(),
+            section_id: 42,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_err());
+
+    let res = move_subsection(&storage, [3, 4], [1, 1]).await;
+    assert!(res.is_ok());
+    let res = move_subsection(&storage, [3, 5], [2, 1]).await;
+    assert!(matches!(
+        res,
+        Err(MoveSubsectionError::CantSwapFromDifferentSections)
+    ));
+
+    // Positions stay unique among the children of a section.
+    let res = update_subsection(
+        &storage,
+        3,
+        UpdateSubsectionForm {
+            title: None,
+            section_id: None,
+            position: Some(0),
+            version: 2,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_err());
+
+    let res = update_subsection(
+        &storage,
+        5,
+        UpdateSubsectionForm {
+            title: None,
+            section_id: Some(1),
+            position: Some(2),
+            version: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_ok());
+    let moved = get_subsection(&storage, 5, Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!((moved.section_id, moved.position), (1, 2));
+
+    // A section with children cannot be deleted.
+    let res = delete_section(&storage, 1).await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn section_slugs_service_test() {
+    let storage = Storage::memory();
+
+    for _ in 0..2 {
+        let res = create_section(
+            &storage,
+            CreateSectionForm {
+                title: "Linear Algebra".to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+    let slugs = get_sections(&storage, all_sections())
+        .await
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|section| section.slug)
+        .collect::<Vec<_>>();
+    assert_eq!(slugs, vec!["linear-algebra", "linear-algebra-2"]);
+
+    let create = |slug: &str| CreateSectionForm {
+        title: "Analysis".to_string(),
+        slug: Some(slug.to_string()),
+        ..Default::default()
+    };
+    let res = create_section(&storage, create("linear-algebra")).await;
+    assert!(matches!(res, Err(CreateSectionError::SlugTakenError)));
+    let res = create_section(&storage, create("Not a slug")).await;
+    assert!(matches!(res, Err(CreateSectionError::InvalidSlugError)));
+
+    let reslug = |slug: &str, version| UpdateSectionForm {
+        slug: Some(slug.to_string()),
+        version,
+        ..Default::default()
+    };
+    let res = update_section(&storage, 2, reslug("linear-algebra", 1)).await;
+    assert!(matches!(res, Err(UpdateSectionError::SlugTakenError)));
+    assert!(update_section(&storage, 2, reslug("algebra", 1))
+        .await
+        .is_ok());
+    assert!(update_section(&storage, 2, reslug("algebra-ii", 2))
+        .await
+        .is_ok());
+    let section = get_section(&storage, 2, Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!(section.slug, "algebra-ii");
+    assert_eq!(section.previous_slugs, vec!["linear-algebra-2", "algebra"]);
+}
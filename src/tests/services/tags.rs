@@ -0,0 +1,66 @@
+use crate::repository::Storage;
+use crate::services::lecture_notes::{create_note, get_note, CreateNoteForm};
+use crate::services::publishing::Visibility;
+use crate::services::sections::{create_section, CreateSectionForm};
+use crate::services::tags::{
+    get_tags, merge_tags, tag_note, untag_note, MergeTagsError, TagNoteError,
+};
+
+async fn storage_with_note() -> Storage {
+    let storage = Storage::memory();
+    let _ = create_section(
+        &storage,
+        CreateSectionForm {
+            title: "section".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let _ = create_note(
+        &storage,
+        CreateNoteForm {
+            name: "note".to_string(),
+            url: "https://example.com/note.pdf".to_string(),
+            section_id: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    storage
+}
+
+#[tokio::test]
+async fn tag_note_test() {
+    let storage = storage_with_note().await;
+
+    let names = vec![
+        " exam ".to_string(),
+        "algebra".to_string(),
+        "exam".to_string(),
+    ];
+    assert!(tag_note(&storage, 1, names).await.is_ok());
+    let note = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    assert_eq!(note.tags, ["algebra", "exam"]);
+    assert!(matches!(
+        tag_note(&storage, 9, vec!["exam".to_string()]).await,
+        Err(TagNoteError::NoteNotFoundError)
+    ));
+
+    // Merging moves the notes of the source tag to the target and drops the source.
+    let tags = get_tags(&storage).await.unwrap();
+    let id = |name: &str| tags.iter().find(|tag| tag.name == name).unwrap().id;
+    assert!(merge_tags(&storage, id("exam"), id("algebra"))
+        .await
+        .is_ok());
+    assert!(matches!(
+        merge_tags(&storage, id("algebra"), id("algebra")).await,
+        Err(MergeTagsError::SameTagError)
+    ));
+    let tags = get_tags(&storage).await.unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].note_count, 1);
+
+    assert!(untag_note(&storage, 1, tags[0].id).await.is_ok());
+    let note = get_note(&storage, 1, Visibility::Everything).await.unwrap();
+    assert!(note.tags.is_empty());
+}
@@ -0,0 +1,174 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::db;
+use crate::repository::Storage;
+use crate::services::lecture_notes::{create_note, CreateNoteForm};
+use crate::services::nodes::{create_node, CreateNodeForm};
+use crate::services::publishing::{Status, Visibility};
+use crate::services::sections::{create_section, CreateSectionForm};
+use crate::services::tree::{
+    get_node_notes, get_section_trees, next_publication, Children, GetSectionTreesError, TreeCache,
+    TreeCacheStats,
+};
+
+#[test]
+fn children_test() {
+    // (id, parent, position)
+    let rows = [(1, None, 1), (2, None, 0), (3, Some(1), 2), (4, Some(1), 0)];
+    let mut children = Children::new(rows, |row| row.1, |row| row.2);
+    let ids = |rows: Vec<(u32, Option<u32>, u32)>| rows.iter().map(|row| row.0).collect::<Vec<_>>();
+
+    assert_eq!(ids(children.take(None)), [2, 1]);
+    assert_eq!(ids(children.take(Some(1))), [4, 3]);
+    // Each group is taken once.
+    assert!(children.take(Some(1)).is_empty());
+    assert!(children.take(Some(2)).is_empty());
+}
+
+async fn add_section(storage: &Storage, title: &str, publish_at: Option<i64>) {
+    create_section(
+        storage,
+        CreateSectionForm {
+            title: title.to_string(),
+            status: Status::Published,
+            publish_at,
+            slug: None,
+        },
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn tree_cache_test() {
+    let storage = Storage::memory();
+    add_section(&storage, "Maths", None).await;
+    let next_year = db::now() + 365 * 24 * 60 * 60;
+    add_section(&storage, "Physics", Some(next_year)).await;
+
+    let renders = AtomicUsize::new(0);
+    let titles = |trees: Vec<crate::services::tree::SectionTree>| {
+        renders.fetch_add(1, Ordering::Relaxed);
+        Ok(trees
+            .into_iter()
+            .map(|tree| tree.section.title)
+            .collect::<Vec<_>>())
+    };
+    let cache = TreeCache::default();
+
+    // A miss renders both visibilities, so the admins then hit the cache too.
+    let public = cache
+        .get(&storage, Visibility::Public, titles)
+        .await
+        .unwrap();
+    assert_eq!(public, ["Maths"]);
+    let everything = cache
+        .get(&storage, Visibility::Everything, titles)
+        .await
+        .unwrap();
+    assert_eq!(everything, ["Maths", "Physics"]);
+    assert_eq!(renders.load(Ordering::Relaxed), 2);
+    assert_eq!(cache.stats(), TreeCacheStats { hits: 1, misses: 1 });
+
+    // A write through the services drops the trees.
+    add_section(&storage, "Biology", None).await;
+    let public = cache
+        .get(&storage, Visibility::Public, titles)
+        .await
+        .unwrap();
+    assert_eq!(public, ["Maths", "Biology"]);
+    assert_eq!(cache.stats(), TreeCacheStats { hits: 1, misses: 2 });
+
+    // A change reported while the trees are loaded keeps them from being cached.
+    let changing = |trees: Vec<crate::services::tree::SectionTree>| {
+        storage.content_changed();
+        titles(trees)
+    };
+    storage.content_changed();
+    cache
+        .get(&storage, Visibility::Public, changing)
+        .await
+        .unwrap();
+    cache
+        .get(&storage, Visibility::Public, titles)
+        .await
+        .unwrap();
+    cache
+        .get(&storage, Visibility::Everything, titles)
+        .await
+        .unwrap();
+    assert_eq!(cache.stats(), TreeCacheStats { hits: 2, misses: 4 });
+
+    // The public trees go stale when the scheduled section comes out.
+    let trees = get_section_trees(&storage, None, Visibility::Everything)
+        .await
+        .unwrap();
+    assert_eq!(next_publication(&trees, db::now()), Some(next_year));
+    assert_eq!(next_publication(&trees, next_year), None);
+}
+
+async fn add_node(storage: &Storage, parent_id: u32, title: &str) -> u32 {
+    create_node(
+        storage,
+        CreateNodeForm {
+            parent_id: Some(parent_id),
+            kind: "topic".to_string(),
+            title: title.to_string(),
+            status: Status::Published,
+            publish_at: None,
+            slug: None,
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn add_note(storage: &Storage, node_id: u32, name: &str) {
+    create_note(
+        storage,
+        CreateNoteForm {
+            name: name.to_string(),
+            url: format!("https://example.com/{name}.pdf"),
+            node_id: Some(node_id),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn section_trees_test() {
+    let storage = Storage::memory();
+    add_section(&storage, "Maths", None).await;
+    add_section(&storage, "Physics", None).await;
+    let algebra = add_node(&storage, 1, "Algebra").await;
+    let groups = add_node(&storage, algebra, "Groups").await;
+    add_note(&storage, groups, "lagrange").await;
+    add_note(&storage, 2, "optics").await;
+    add_note(&storage, 2, "mechanics").await;
+
+    // The nodes under a subsection come along, with their notes.
+    let trees = get_section_trees(&storage, Some(1), Visibility::Public)
+        .await
+        .unwrap();
+    assert_eq!(trees.len(), 1);
+    let subsection = &trees[0].subsections[0];
+    assert_eq!(subsection.subsection.title, "Algebra");
+    assert!(subsection.notes.is_empty());
+    assert_eq!(subsection.children[0].title, "Groups");
+    assert_eq!(subsection.children[0].notes[0].name, "lagrange");
+
+    // Only the roots are sections.
+    let res = get_section_trees(&storage, Some(algebra), Visibility::Public).await;
+    assert!(matches!(res, Err(GetSectionTreesError::NotFoundError)));
+
+    let notes = get_node_notes(&storage, 2, Visibility::Public)
+        .await
+        .unwrap();
+    let names = notes
+        .iter()
+        .map(|note| note.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["optics", "mechanics"]);
+}
@@ -0,0 +1,110 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::{sessions, users};
+use crate::error::ErrorCode;
+use crate::repository::Storage;
+use crate::services::users::{
+    authenticate_admin_by_token, authenticate_user_by_token, get_users, AdminAuthError,
+    GetUsersForm,
+};
+
+fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+async fn create_user_with_session(
+    storage: &Storage,
+    username: &str,
+    is_admin: bool,
+    expires_at: i64,
+) {
+    let _ = storage
+        .users
+        .create_user(users::CreateUserForm {
+            username: username.to_string(),
+            password: "hash".to_string(),
+            is_admin,
+        })
+        .await;
+    let user = storage
+        .users
+        .get_user(users::GetUsersForm {
+            username: Some(username.to_string()),
+            ..Default::default()
+        })
+        .await;
+    let user = match user {
+        Ok(user) => user,
+        Err(_) => panic!("the user was not found"),
+    };
+    let res = storage
+        .sessions
+        .create_session(sessions::CreateSessionForm {
+            user_id: user.id,
+            token: format!("{}-token", username),
+            expires_at,
+        })
+        .await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn authenticate_by_token_test() {
+    let storage = Storage::memory();
+    let tomorrow = now_unix() + 24 * 60 * 60;
+    create_user_with_session(&storage, "admin", true, tomorrow).await;
+    create_user_with_session(&storage, "reader", false, tomorrow).await;
+    create_user_with_session(&storage, "expired", true, now_unix() - 1).await;
+
+    let admin = authenticate_admin_by_token(&storage, "admin-token".to_string()).await;
+    assert_eq!(
+        admin.map(|user| user.username).ok(),
+        Some("admin".to_string())
+    );
+    let res = authenticate_admin_by_token(&storage, "reader-token".to_string()).await;
+    assert!(matches!(res, Err(AdminAuthError::NotAdmin)));
+    let res = authenticate_user_by_token(&storage, "reader-token".to_string()).await;
+    assert!(res.is_ok());
+    let res = authenticate_admin_by_token(&storage, "unknown".to_string()).await;
+    assert!(matches!(
+        res,
+        Err(AdminAuthError::UnexpectedError(err)) if err.code == ErrorCode::Unauthorized
+    ));
+
+    // Expired sessions are rejected and removed.
+    let res = authenticate_user_by_token(&storage, "expired-token".to_string()).await;
+    assert!(res.is_err());
+    let session = storage
+        .sessions
+        .get_session_by_token("expired-token".to_string())
+        .await;
+    assert!(session.is_err());
+
+    // Sessions can only belong to existing users.
+    let res = storage
+        .sessions
+        .create_session(sessions::CreateSessionForm {
+            user_id: 42,
+            token: "dangling".to_string(),
+            expires_at: tomorrow,
+        })
+        .await;
+    assert!(res.is_err());
+
+    let users = get_users(
+        &storage,
+        GetUsersForm {
+            id: None,
+            username: Some("reader".to_string()),
+            page: Default::default(),
+        },
+    )
+    .await
+    .unwrap()
+    .items;
+    assert_eq!(users.len(), 1);
+    assert!(!users[0].is_admin);
+}
@@ -28,6 +28,7 @@ const generatePagesBtn = document.getElementById('generatePagesBtn');
 const sectionForm = document.getElementById('sectionForm');
 const subsectionForm = document.getElementById('subsectionForm');
 const noteForm = document.getElementById('noteForm');
+const importForm = document.getElementById('importForm');
 
 const subsectionSection = document.getElementById('subsectionSection');
 const noteParent = document.getElementById('noteParent');
@@ -58,21 +59,124 @@ function authHeaders() {
 }
 
 async function apiFetch(path, options = {}) {
+  const isForm = options.body instanceof FormData;
   const headers = {
-    'Content-Type': 'application/json',
+    ...(isForm ? {} : { 'Content-Type': 'application/json' }),
     ...authHeaders(),
     ...(options.headers || {}),
   };
 
-  const res = await fetch(`${apiBase}${path}`, { ...options, headers });
+  const res = await fetch(`${apiBase}${API_PATH}${path}`, { ...options, headers });
   if (!res.ok) {
-    const message = await res.text();
-    throw new Error(message || 'Request failed');
+    const text = await res.text();
+    let body = null;
+    try {
+      body = JSON.parse(text);
+    } catch {
+      // Not every failure comes from the API, a proxy may answer in plain text.
+    }
+    const details = (body && body.details) || [];
+    const message = body && body.error
+      ? [body.error, ...details.map((detail) => `${detail.field}: ${detail.message}`)].join('\n')
+      : text;
+    const err = new Error(message || 'Request failed');
+    err.status = res.status;
+    err.body = body;
+    throw err;
   }
   if (res.status === 204) return null;
   return res.json();
 }
 
+// Remove the field errors shown by a previous submit of the form.
+function clearFieldErrors(form) {
+  form.querySelectorAll('.field-error').forEach((el) => el.remove());
+  form.querySelectorAll('[aria-invalid]').forEach((el) => el.removeAttribute('aria-invalid'));
+}
+
+// Show the field errors of a refused request next to the inputs they concern,
+// `inputs` maps the request fields to the ids of the inputs. Returns whether
+// every error found its input.
+function showFieldErrors(form, err, inputs) {
+  clearFieldErrors(form);
+  const details = (err.body && err.body.details) || [];
+  let placed = 0;
+  details.forEach((detail) => {
+    const input = inputs[detail.field] && document.getElementById(inputs[detail.field]);
+    if (!input) return;
+    const message = document.createElement('span');
+    message.className = 'field-error';
+    message.textContent = detail.message;
+    input.setAttribute('aria-invalid', 'true');
+    input.insertAdjacentElement('afterend', message);
+    placed += 1;
+  });
+  return details.length > 0 && placed === details.length;
+}
+
+// Send an update made against a version, reloading when it turned out stale.
+async function versionedUpdate(path, options) {
+  try {
+    await apiFetch(path, options);
+  } catch (err) {
+    // A slug used by a sibling is a 409 too, but without the current version.
+    if (err.status === 409 && !(err.body && err.body.current)) {
+      setStatus(err.message);
+      return;
+    }
+    if (err.status !== 409 && err.status !== 412) throw err;
+    await loadAll();
+    setStatus('Changed by someone else in the meantime, reloaded the latest version');
+    return;
+  }
+  await loadAll();
+}
+
+// The publication state shown next to a title, empty once the item is public.
+function publicationLabel(item) {
+  if (item.status !== 'published') return ` · ${item.status}`;
+  if (item.publish_at && item.publish_at * 1000 > Date.now()) {
+    return ` · scheduled for ${new Date(item.publish_at * 1000).toLocaleString()}`;
+  }
+  return '';
+}
+
+// An input editing the slug naming the generated pages of the item.
+function buildSlugInput(item) {
+  const input = document.createElement('input');
+  input.value = item.slug || '';
+  input.placeholder = 'Slug';
+  input.title = item.previous_slugs && item.previous_slugs.length
+    ? `Redirected from: ${item.previous_slugs.join(', ')}`
+    : 'Names the generated pages';
+  return input;
+}
+
+// The slug to send on save, left out when unchanged.
+function changedSlug(item, input) {
+  const slug = input.value.trim();
+  return slug && slug !== item.slug ? slug : undefined;
+}
+
+// A status picker saving the new status of the item at `path` right away.
+function buildStatusSelect(item, path) {
+  const select = document.createElement('select');
+  ['draft', 'published', 'archived'].forEach((status) => {
+    const option = document.createElement('option');
+    option.value = status;
+    option.textContent = status;
+    select.appendChild(option);
+  });
+  select.value = item.status;
+  select.addEventListener('change', async () => {
+    await versionedUpdate(path, {
+      method: 'PUT',
+      body: JSON.stringify({ status: select.value, version: item.version }),
+    });
+  });
+  return select;
+}
+
 function sortByPosition(items) {
   return [...items].sort((a, b) => a.position - b.position);
 }
@@ -122,11 +226,12 @@ function render() {
 
     const title = document.createElement('div');
     title.className = 'item-title';
-    title.textContent = `${section.title} (pos ${section.position})`;
+    title.textContent = `${section.title} (pos ${section.position})${publicationLabel(section)}`;
 
     const editInput = document.createElement('input');
     editInput.value = section.title;
     editInput.placeholder = 'Edit title';
+    const slugInput = buildSlugInput(section);
 
     const actions = document.createElement('div');
     actions.className = 'actions';
@@ -134,7 +239,7 @@ function render() {
     const saveBtn = document.createElement('button');
     saveBtn.textContent = 'Save';
     saveBtn.addEventListener('click', async () => {
-      await updateSection(section.id, editInput.value.trim());
+      await updateSection(section, editInput.value.trim(), changedSlug(section, slugInput));
     });
 
     const deleteBtn = document.createElement('button');
@@ -149,7 +254,7 @@ function render() {
     upBtn.textContent = 'Move up';
     upBtn.disabled = index === 0;
     upBtn.addEventListener('click', async () => {
-      await moveSection(section.id, sections[index - 1].id);
+      await moveSection(section, sections[index - 1]);
     });
 
     const downBtn = document.createElement('button');
@@ -157,14 +262,16 @@ function render() {
     downBtn.textContent = 'Move down';
     downBtn.disabled = index === sections.length - 1;
     downBtn.addEventListener('click', async () => {
-      await moveSection(section.id, sections[index + 1].id);
+      await moveSection(section, sections[index + 1]);
     });
 
     const toggleBtn = document.createElement('button');
     toggleBtn.className = 'toggle-btn';
     toggleBtn.textContent = 'Collapse';
 
-    actions.append(toggleBtn, saveBtn, deleteBtn, upBtn, downBtn);
+    const statusSelect = buildStatusSelect(section, `/sections/${section.id}`);
+
+    actions.append(toggleBtn, saveBtn, deleteBtn, upBtn, downBtn, statusSelect);
 
     const subsectionList = document.createElement('div');
     subsectionList.className = 'list';
@@ -176,10 +283,11 @@ function render() {
 
       const subTitle = document.createElement('div');
       subTitle.className = 'item-title';
-      subTitle.textContent = `${subsection.title} (pos ${subsection.position})`;
+      subTitle.textContent = `${subsection.title} (pos ${subsection.position})${publicationLabel(subsection)}`;
 
       const subEdit = document.createElement('input');
       subEdit.value = subsection.title;
+      const subSlug = buildSlugInput(subsection);
 
       const subActions = document.createElement('div');
       subActions.className = 'actions';
@@ -187,7 +295,7 @@ function render() {
       const subSave = document.createElement('button');
       subSave.textContent = 'Save';
       subSave.addEventListener('click', async () => {
-        await updateSubsection(subsection.id, subEdit.value.trim());
+        await updateSubsection(subsection, subEdit.value.trim(), changedSlug(subsection, subSlug));
       });
 
       const subDelete = document.createElement('button');
@@ -202,7 +310,7 @@ function render() {
       subUp.textContent = 'Move up';
       subUp.disabled = subIndex === 0;
       subUp.addEventListener('click', async () => {
-        await moveSubsection(subsection.id, subsections[subIndex - 1].id);
+        await moveSubsection(subsection, subsections[subIndex - 1]);
       });
 
       const subDown = document.createElement('button');
@@ -210,14 +318,16 @@ function render() {
       subDown.textContent = 'Move down';
       subDown.disabled = subIndex === subsections.length - 1;
       subDown.addEventListener('click', async () => {
-        await moveSubsection(subsection.id, subsections[subIndex + 1].id);
+        await moveSubsection(subsection, subsections[subIndex + 1]);
       });
 
       const subToggle = document.createElement('button');
       subToggle.className = 'toggle-btn';
       subToggle.textContent = 'Collapse';
 
-      subActions.append(subToggle, subSave, subDelete, subUp, subDown);
+      const subStatus = buildStatusSelect(subsection, `/subsections/${subsection.id}`);
+
+      subActions.append(subToggle, subSave, subDelete, subUp, subDown, subStatus);
 
       const notesList = document.createElement('div');
       notesList.className = 'list';
@@ -232,7 +342,7 @@ function render() {
         subToggle.textContent = isCollapsed ? 'Expand' : 'Collapse';
       });
 
-      subItem.append(subTitle, subEdit, subActions, notesList);
+      subItem.append(subTitle, subEdit, subSlug, subActions, notesList);
       subsectionList.appendChild(subItem);
     });
 
@@ -246,7 +356,7 @@ function render() {
     const sectionBody = document.createElement('div');
     sectionBody.className = 'section-body';
 
-    card.append(title, editInput, actions);
+    card.append(title, editInput, slugInput, actions);
     if (subsections.length) {
       const subHeader = document.createElement('div');
       subHeader.className = 'tag';
@@ -289,7 +399,7 @@ function buildNoteItem(note, noteIndex, list) {
 
   const noteTitle = document.createElement('div');
   noteTitle.className = 'item-title';
-  noteTitle.textContent = `${note.name} (pos ${note.position})`;
+  noteTitle.textContent = `${note.name} (pos ${note.position})${publicationLabel(note)}`;
 
   noteSummary.append(noteTitle);
 
@@ -335,7 +445,17 @@ function buildNoteItem(note, noteIndex, list) {
   descriptionInput.placeholder = 'Short explanation shown with the note';
   descriptionField.append(descriptionLabel, descriptionInput);
 
-  noteInputs.append(nameField, urlField, descriptionField);
+  const slugField = document.createElement('label');
+  slugField.className = 'field-stack';
+
+  const slugLabel = document.createElement('span');
+  slugLabel.className = 'field-label';
+  slugLabel.textContent = 'Slug';
+
+  const slugInput = buildSlugInput(note);
+  slugField.append(slugLabel, slugInput);
+
+  noteInputs.append(nameField, urlField, slugField, descriptionField);
 
   const noteActions = document.createElement('div');
   noteActions.className = 'actions';
@@ -344,10 +464,11 @@ function buildNoteItem(note, noteIndex, list) {
   noteSave.textContent = 'Save';
   noteSave.addEventListener('click', async () => {
     await updateNote(
-      note.id,
+      note,
       nameInput.value.trim(),
       descriptionInput.value.trim(),
-      urlInput.value.trim()
+      urlInput.value.trim(),
+      changedSlug(note, slugInput)
     );
   });
 
@@ -363,7 +484,7 @@ function buildNoteItem(note, noteIndex, list) {
   noteUp.textContent = 'Move up';
   noteUp.disabled = noteIndex === 0;
   noteUp.addEventListener('click', async () => {
-    await moveNote(note.id, list[noteIndex - 1].id);
+    await moveNote(note, list[noteIndex - 1]);
   });
 
   const noteDown = document.createElement('button');
@@ -371,7 +492,7 @@ function buildNoteItem(note, noteIndex, list) {
   noteDown.textContent = 'Move down';
   noteDown.disabled = noteIndex === list.length - 1;
   noteDown.addEventListener('click', async () => {
-    await moveNote(note.id, list[noteIndex + 1].id);
+    await moveNote(note, list[noteIndex + 1]);
   });
 
   const link = document.createElement('a');
@@ -381,11 +502,137 @@ function buildNoteItem(note, noteIndex, list) {
   link.rel = 'noopener';
   link.textContent = 'Open';
 
-  noteActions.append(noteSave, noteDelete, noteUp, noteDown, link);
-  noteItem.append(noteSummary, noteInputs, noteActions);
+  const noteStatus = buildStatusSelect(note, `/notes/${note.id}`);
+
+  noteActions.append(noteSave, noteDelete, noteUp, noteDown, noteStatus, link);
+  noteItem.append(noteSummary, noteInputs, noteActions, buildAttachmentsPanel(note));
   return noteItem;
 }
 
+function buildAttachmentInputs(attachment) {
+  const labelInput = document.createElement('input');
+  labelInput.value = attachment ? attachment.label : '';
+  labelInput.placeholder = 'Label, e.g. Slides';
+
+  const urlInput = document.createElement('input');
+  urlInput.type = 'url';
+  urlInput.value = attachment ? attachment.url : '';
+  urlInput.placeholder = 'https://example.com/slides.pdf';
+  urlInput.className = 'url-input';
+
+  const mimeInput = document.createElement('input');
+  mimeInput.value = attachment && attachment.mime_type ? attachment.mime_type : '';
+  mimeInput.placeholder = 'MIME type, e.g. application/pdf';
+
+  return { labelInput, urlInput, mimeInput };
+}
+
+function buildAttachmentsPanel(note) {
+  const panel = document.createElement('div');
+  panel.className = 'attachments';
+
+  const panelTitle = document.createElement('div');
+  panelTitle.className = 'field-label';
+  panelTitle.textContent = 'Attachments';
+  panel.append(panelTitle);
+
+  const attachments = note.attachments || [];
+  attachments.forEach((attachment, index) => {
+    const row = document.createElement('div');
+    row.className = 'inline-inputs attachment-row';
+    const { labelInput, urlInput, mimeInput } = buildAttachmentInputs(attachment);
+
+    const saveBtn = document.createElement('button');
+    saveBtn.textContent = 'Save';
+    saveBtn.addEventListener('click', async () => {
+      await updateAttachment(attachment.id, {
+        label: labelInput.value.trim(),
+        url: urlInput.value.trim(),
+        mime_type: mimeInput.value.trim(),
+      });
+    });
+
+    const deleteBtn = document.createElement('button');
+    deleteBtn.className = 'secondary';
+    deleteBtn.textContent = 'Delete';
+    deleteBtn.addEventListener('click', async () => {
+      await deleteAttachment(attachment.id);
+    });
+
+    const upBtn = document.createElement('button');
+    upBtn.className = 'ghost';
+    upBtn.textContent = 'Up';
+    upBtn.disabled = index === 0;
+    upBtn.addEventListener('click', async () => {
+      await moveAttachment(attachment.id, attachments[index - 1].id);
+    });
+
+    const downBtn = document.createElement('button');
+    downBtn.className = 'ghost';
+    downBtn.textContent = 'Down';
+    downBtn.disabled = index === attachments.length - 1;
+    downBtn.addEventListener('click', async () => {
+      await moveAttachment(attachment.id, attachments[index + 1].id);
+    });
+
+    row.append(labelInput, urlInput, mimeInput, saveBtn, deleteBtn, upBtn, downBtn);
+    panel.append(row);
+  });
+
+  const addRow = document.createElement('div');
+  addRow.className = 'inline-inputs attachment-row';
+  const { labelInput, urlInput, mimeInput } = buildAttachmentInputs(null);
+
+  const addBtn = document.createElement('button');
+  addBtn.textContent = 'Add attachment';
+  addBtn.addEventListener('click', async () => {
+    const payload = { label: labelInput.value.trim(), url: urlInput.value.trim() };
+    if (mimeInput.value.trim()) {
+      payload.mime_type = mimeInput.value.trim();
+    }
+    try {
+      await createAttachment(note.id, payload);
+      await loadAll();
+    } catch (err) {
+      setStatus(err.message || 'Failed to add attachment');
+    }
+  });
+
+  addRow.append(labelInput, urlInput, mimeInput, addBtn);
+
+  const uploadRow = document.createElement('div');
+  uploadRow.className = 'inline-inputs attachment-row';
+
+  const uploadLabelInput = document.createElement('input');
+  uploadLabelInput.placeholder = 'Label (defaults to the file name)';
+
+  const fileInput = document.createElement('input');
+  fileInput.type = 'file';
+
+  const uploadBtn = document.createElement('button');
+  uploadBtn.textContent = 'Upload file';
+  uploadBtn.addEventListener('click', async () => {
+    if (!fileInput.files.length) {
+      setStatus('Choose a file to upload');
+      return;
+    }
+    try {
+      const file = await uploadFile(fileInput.files[0]);
+      await createAttachment(note.id, {
+        label: uploadLabelInput.value.trim(),
+        file_id: file.id,
+      });
+      await loadAll();
+    } catch (err) {
+      setStatus(err.message || 'Failed to upload file');
+    }
+  });
+
+  uploadRow.append(uploadLabelInput, fileInput, uploadBtn);
+  panel.append(addRow, uploadRow);
+  return panel;
+}
+
 async function loadAll() {
   clearStatus();
   try {
@@ -394,9 +641,9 @@ async function loadAll() {
       apiFetch('/subsections', { method: 'GET' }),
       apiFetch('/notes', { method: 'GET' }),
     ]);
-    state.sections = sections;
-    state.subsections = subsections;
-    state.notes = notes;
+    state.sections = sections.items;
+    state.subsections = subsections.items;
+    state.notes = notes.items;
     render();
   } catch (err) {
     setStatus(err.message || 'Failed to load data');
@@ -410,12 +657,11 @@ async function createSection(title) {
   });
 }
 
-async function updateSection(id, title) {
-  await apiFetch(`/sections/${id}`, {
+async function updateSection(item, title, slug) {
+  await versionedUpdate(`/sections/${item.id}`, {
     method: 'PUT',
-    body: JSON.stringify({ title }),
+    body: JSON.stringify({ title, slug, version: item.version }),
   });
-  await loadAll();
 }
 
 async function deleteSection(id) {
@@ -423,12 +669,16 @@ async function deleteSection(id) {
   await loadAll();
 }
 
-async function moveSection(firstId, secondId) {
-  await apiFetch('/sections/move', {
+async function moveSection(first, second) {
+  await versionedUpdate('/sections/move', {
     method: 'POST',
-    body: JSON.stringify({ first_id: firstId, second_id: secondId }),
+    body: JSON.stringify({
+      first_id: first.id,
+      second_id: second.id,
+      first_version: first.version,
+      second_version: second.version,
+    }),
   });
-  await loadAll();
 }
 
 async function createSubsection(title, sectionId) {
@@ -438,12 +688,11 @@ async function createSubsection(title, sectionId) {
   });
 }
 
-async function updateSubsection(id, title) {
-  await apiFetch(`/subsections/${id}`, {
+async function updateSubsection(item, title, slug) {
+  await versionedUpdate(`/subsections/${item.id}`, {
     method: 'PUT',
-    body: JSON.stringify({ title }),
+    body: JSON.stringify({ title, slug, version: item.version }),
   });
-  await loadAll();
 }
 
 async function deleteSubsection(id) {
@@ -451,12 +700,16 @@ async function deleteSubsection(id) {
   await loadAll();
 }
 
-async function moveSubsection(firstId, secondId) {
-  await apiFetch('/subsections/move', {
+async function moveSubsection(first, second) {
+  await versionedUpdate('/subsections/move', {
     method: 'POST',
-    body: JSON.stringify({ first_id: firstId, second_id: secondId }),
+    body: JSON.stringify({
+      first_id: first.id,
+      second_id: second.id,
+      first_version: first.version,
+      second_version: second.version,
+    }),
   });
-  await loadAll();
 }
 
 async function createNote(payload) {
@@ -466,12 +719,11 @@ async function createNote(payload) {
   });
 }
 
-async function updateNote(id, name, description, url) {
-  await apiFetch(`/notes/${id}`, {
+async function updateNote(item, name, description, url, slug) {
+  await versionedUpdate(`/notes/${item.id}`, {
     method: 'PUT',
-    body: JSON.stringify({ name, description, url }),
+    body: JSON.stringify({ name, description, url, slug, version: item.version }),
   });
-  await loadAll();
 }
 
 async function deleteNote(id) {
@@ -479,14 +731,62 @@ async function deleteNote(id) {
   await loadAll();
 }
 
-async function moveNote(firstId, secondId) {
-  await apiFetch('/notes/move', {
+async function moveNote(first, second) {
+  await versionedUpdate('/notes/move', {
+    method: 'POST',
+    body: JSON.stringify({
+      first_id: first.id,
+      second_id: second.id,
+      first_version: first.version,
+      second_version: second.version,
+    }),
+  });
+}
+
+async function uploadFile(file) {
+  const body = new FormData();
+  body.append('file', file);
+  return apiFetch('/files', { method: 'POST', body });
+}
+
+async function createAttachment(noteId, payload) {
+  await apiFetch(`/notes/${noteId}/attachments`, {
+    method: 'POST',
+    body: JSON.stringify(payload),
+  });
+}
+
+async function updateAttachment(id, payload) {
+  await apiFetch(`/attachments/${id}`, {
+    method: 'PUT',
+    body: JSON.stringify(payload),
+  });
+  await loadAll();
+}
+
+async function deleteAttachment(id) {
+  await apiFetch(`/attachments/${id}`, { method: 'DELETE' });
+  await loadAll();
+}
+
+async function moveAttachment(firstId, secondId) {
+  await apiFetch('/attachments/move', {
     method: 'POST',
     body: JSON.stringify({ first_id: firstId, second_id: secondId }),
   });
   await loadAll();
 }
 
+// Rows name the section, optional subsection, name, description and url of a note.
+async function importNotes(file) {
+  const format = file.name.toLowerCase().endsWith('.csv') ? 'csv' : 'yaml';
+  return apiFetch(`/notes/import?format=${format}`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'text/plain' },
+    body: await file.text(),
+  });
+}
+
 async function generatePages() {
   const result = await apiFetch('/pages/generate', {
     method: 'POST',
@@ -496,17 +796,20 @@ async function generatePages() {
 
 sectionForm.addEventListener('submit', async (event) => {
   event.preventDefault();
+  clearFieldErrors(sectionForm);
   try {
     await createSection(sectionForm.sectionTitle.value.trim());
     sectionForm.reset();
     await loadAll();
   } catch (err) {
+    if (showFieldErrors(sectionForm, err, { title: 'sectionTitle' })) return;
     setStatus(err.message || 'Failed to create section');
   }
 });
 
 subsectionForm.addEventListener('submit', async (event) => {
   event.preventDefault();
+  clearFieldErrors(subsectionForm);
   try {
     await createSubsection(
       subsectionForm.subsectionTitle.value.trim(),
@@ -515,6 +818,10 @@ subsectionForm.addEventListener('submit', async (event) => {
     subsectionForm.reset();
     await loadAll();
   } catch (err) {
+    if (showFieldErrors(subsectionForm, err, {
+      title: 'subsectionTitle',
+      section_id: 'subsectionSection',
+    })) return;
     setStatus(err.message || 'Failed to create subsection');
   }
 });
@@ -530,6 +837,7 @@ noteParent.addEventListener('change', () => {
 
 noteForm.addEventListener('submit', async (event) => {
   event.preventDefault();
+  clearFieldErrors(noteForm);
   try {
     const parentId = Number(noteForm.noteParentSelect.value);
     const payload = {
@@ -543,10 +851,35 @@ noteForm.addEventListener('submit', async (event) => {
     noteForm.reset();
     await loadAll();
   } catch (err) {
+    if (showFieldErrors(noteForm, err, {
+      name: 'noteName',
+      url: 'noteUrl',
+      description: 'noteDescription',
+      section_id: 'noteParentSelect',
+      subsection_id: 'noteParentSelect',
+    })) return;
     setStatus(err.message || 'Failed to create note');
   }
 });
 
+importForm.addEventListener('submit', async (event) => {
+  event.preventDefault();
+  try {
+    const report = await importNotes(importForm.importFile.files[0]);
+    importForm.reset();
+    await loadAll();
+    const failed = report.errors.map((error) => `line ${error.line}: ${error.message}`);
+    setStatus(
+      [
+        `${report.created_notes} notes created, ${report.updated_notes} updated`,
+        ...failed,
+      ].join('\n')
+    );
+  } catch (err) {
+    setStatus(err.message || 'Failed to import notes');
+  }
+});
+
 refreshBtn.addEventListener('click', () => loadAll());
 generatePagesBtn.addEventListener('click', async () => {
   try {
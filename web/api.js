@@ -0,0 +1,3 @@
+// The server serves the JSON API under this path. Loaded before the script of
+// each page, which builds its request URLs from it.
+const API_PATH = '/api/v1';
@@ -14,9 +14,23 @@ goAdmin.addEventListener('click', () => {
   window.location.href = '/admin';
 });
 
+// Show the field errors of a refused request under the inputs they concern.
+function showFieldErrors(details) {
+  form.querySelectorAll('.field-error').forEach((el) => el.remove());
+  details.forEach((detail) => {
+    const input = document.getElementById(detail.field);
+    if (!input) return;
+    const message = document.createElement('span');
+    message.className = 'field-error';
+    message.textContent = detail.message;
+    input.insertAdjacentElement('afterend', message);
+  });
+}
+
 form.addEventListener('submit', async (event) => {
   event.preventDefault();
   statusEl.hidden = true;
+  showFieldErrors([]);
 
   const apiBase = apiBaseInput.value.trim() || 'http://127.0.0.1:3000';
   localStorage.setItem('apiBase', apiBase);
@@ -35,7 +49,7 @@ form.addEventListener('submit', async (event) => {
   };
 
   try {
-    const res = await fetch(`${apiBase}/users/register`, {
+    const res = await fetch(`${apiBase}${API_PATH}/users/register`, {
       method: 'POST',
       headers: {
         'Content-Type': 'application/json',
@@ -46,6 +60,11 @@ form.addEventListener('submit', async (event) => {
 
     if (!res.ok) {
       const message = await res.text();
+      if (res.status === 422) {
+        const body = JSON.parse(message);
+        showFieldErrors(body.details || []);
+        throw new Error(body.error);
+      }
       throw new Error(message || 'User creation failed');
     }
 
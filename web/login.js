@@ -23,7 +23,7 @@ form.addEventListener('submit', async (event) => {
   };
 
   try {
-    const res = await fetch(`${apiBase}/users/login`, {
+    const res = await fetch(`${apiBase}${API_PATH}/users/login`, {
       method: 'POST',
       headers: { 'Content-Type': 'application/json' },
       body: JSON.stringify(payload),
@@ -30,7 +30,7 @@ form.addEventListener('submit', async (event) => {
       headers.Authorization = `Bearer ${token}`;
     }
 
-    const res = await fetch(`${apiBase}/users/register`, {
+    const res = await fetch(`${apiBase}${API_PATH}/users/register`, {
       method: 'POST',
       headers,
       body: JSON.stringify(payload),